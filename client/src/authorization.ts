@@ -0,0 +1,217 @@
+/**
+ * Spend authorization for transfers and withdrawals.
+ *
+ * A prover gets each input's proving key, not its spending key, and the
+ * circuit only accepts the spend if every input's owner signed the public
+ * values it commits. These functions compute those public values before
+ * proving so the owners can sign them on their own device.
+ * All functions must produce identical outputs to lib/src/lib.rs.
+ */
+
+import {
+  computeCommitment,
+  computeCancellableNullifier,
+  computeMultisigNullifier,
+  nullifierFromKey,
+  deriveSpenderKey,
+  spenderNullifierKey,
+  derivePubkey,
+  legacyPubkey,
+  spendAuthMessage,
+  signSpend,
+  keccak256,
+  hexToBytes,
+  bytesToHex,
+} from "./crypto.js";
+import { encryptNoteWith } from "./encryption.js";
+import type {
+  CancellableLock,
+  MultisigSpend,
+  Note,
+  SpenderKey,
+  SpendSignature,
+  TransferRequest,
+  WithdrawRequest,
+} from "./types.js";
+
+/** How a transfer or withdrawal input is spent. */
+export interface InputAuthorization {
+  /** Key the input is spent with (see deriveSpenderKey) */
+  spender: SpenderKey;
+  /** Owner 1's key for a multisig input */
+  cosigner?: SpenderKey;
+  nullifier: Uint8Array;
+  /** The lock's deadline when the sender reclaims, else 0 */
+  reclaimDeadline: bigint;
+}
+
+/**
+ * Spend `note` with `spendingKey`: its owner's key, the recipient's or
+ * sender's of a cancellable lock, or owner 0's of a multisig. The circuit
+ * checks the rest.
+ *
+ * Must match lib.rs multisig::authorize_input().
+ */
+export function authorizeInput(
+  note: Note,
+  spendingKey: Uint8Array,
+  lock?: CancellableLock,
+  multisig?: MultisigSpend
+): InputAuthorization {
+  const commitment = computeCommitment(note.amount, note.pubkey, note.blinding);
+  if (multisig) {
+    const spender = deriveSpenderKey(spendingKey, multisig.ownerPubkeys[0]);
+    const cosigner = deriveSpenderKey(multisig.cosignerKey, multisig.ownerPubkeys[1]);
+    const nullifierKeys = [spenderNullifierKey(spender), spenderNullifierKey(cosigner)];
+    const nullifier = computeMultisigNullifier(commitment, nullifierKeys);
+    return { spender, cosigner, nullifier, reclaimDeadline: 0n };
+  }
+  if (lock) {
+    const mine = [derivePubkey(spendingKey), legacyPubkey(spendingKey)].map(bytesToHex);
+    const claim = mine.includes(bytesToHex(lock.recipientPubkey));
+    return {
+      spender: deriveSpenderKey(spendingKey, claim ? lock.recipientPubkey : lock.senderPubkey),
+      nullifier: computeCancellableNullifier(commitment, note.blinding),
+      reclaimDeadline: claim ? 0n : lock.reclaimDeadline,
+    };
+  }
+  const spender = deriveSpenderKey(spendingKey, note.pubkey);
+  return { spender, nullifier: nullifierFromKey(commitment, spenderNullifierKey(spender)), reclaimDeadline: 0n };
+}
+
+/** Authorizations of a transfer's two inputs. */
+export function authorizeTransferInputs(request: TransferRequest): InputAuthorization[] {
+  return [0, 1].map((i) =>
+    authorizeInput(
+      request.inputNotes[i],
+      request.inputSpendingKeys[i],
+      request.inputLocks?.[i],
+      request.inputMultisig?.[i]
+    )
+  );
+}
+
+/** Authorization of a withdrawal's input. */
+export function authorizeWithdrawInput(request: WithdrawRequest): InputAuthorization {
+  return authorizeInput(request.inputNote, request.spendingKey, request.lock, request.multisig);
+}
+
+// ============================================================================
+//                          PUBLIC VALUES
+// ============================================================================
+
+function setU64(out: Uint8Array, end: number, value: bigint): void {
+  new DataView(out.buffer, out.byteOffset).setBigUint64(end - 8, value, false);
+}
+
+/**
+ * The public values a transfer proof commits (320 bytes), without
+ * association sets.
+ *
+ * Must match lib.rs TransferPublicValues::encode().
+ */
+export function transferPublicValues(request: TransferRequest): Uint8Array {
+  const auths = authorizeTransferInputs(request);
+  const deadlines = auths.map((a) => a.reclaimDeadline).filter((d) => d !== 0n);
+  // Zero-amount inputs need no leaf, so only the others' proofs set the depth
+  const spent = [0, 1].filter((i) => request.inputNotes[i].amount !== 0n);
+  const levels = spent.length ? request.inputMerkleProofs[spent[0]].length : 0;
+  const out = new Uint8Array(320);
+  out.set(request.root, 0);
+  out.set(auths[0].nullifier, 32);
+  out.set(auths[1].nullifier, 64);
+  request.outputNotes.forEach((n, i) => out.set(computeCommitment(n.amount, n.pubkey, n.blinding), 96 + 32 * i));
+  setU64(out, 192, deadlines.reduce((min, d) => (d < min ? d : min), deadlines[0] ?? 0n));
+  out.set(request.poolBinding, 224);
+  if (request.outputEncryption) {
+    const hashes = request.outputEncryption.map((e, i) => keccak256(encryptNoteWith(request.outputNotes[i], e)));
+    const preimage = new Uint8Array(64);
+    preimage.set(hashes[0], 0);
+    preimage.set(hashes[1], 32);
+    out.set(keccak256(preimage), 256);
+  }
+  setU64(out, 320, BigInt(levels));
+  return out;
+}
+
+/**
+ * The public values a withdrawal proof commits (416 bytes), without
+ * association sets.
+ *
+ * Must match lib.rs WithdrawPublicValues::encode().
+ */
+export function withdrawPublicValues(request: WithdrawRequest): Uint8Array {
+  const auth = authorizeWithdrawInput(request);
+  const out = new Uint8Array(416);
+  out.set(request.root, 0);
+  out.set(auth.nullifier, 32);
+  out.set(hexToBytes(request.recipient), 76);
+  setU64(out, 128, request.withdrawAmount);
+  if (request.changeNote) {
+    const change = request.changeNote;
+    out.set(computeCommitment(change.amount, change.pubkey, change.blinding), 128);
+  }
+  setU64(out, 192, auth.reclaimDeadline);
+  if (request.relayer) out.set(hexToBytes(request.relayer), 204);
+  setU64(out, 256, request.fee ?? 0n);
+  out.set(request.poolBinding, 288);
+  setU64(out, 352, request.deadline ?? 0n);
+  if (request.call) {
+    // call_binding = keccak256(abi.encode(target, keccak256(data)))
+    const preimage = new Uint8Array(64);
+    preimage.set(hexToBytes(request.call.target), 12);
+    preimage.set(keccak256(request.call.data), 32);
+    out.set(keccak256(preimage), 352);
+  }
+  setU64(out, 416, BigInt(request.merkleProof.length));
+  return out;
+}
+
+// ============================================================================
+//                          SIGNING
+// ============================================================================
+
+/**
+ * Sign `message` with each spending key whose input is spent with a proving
+ * key (legacy keys sign nothing), once per key.
+ */
+function signWith(keys: [Uint8Array, SpenderKey | undefined][], message: Uint8Array): SpendSignature[] {
+  const signatures = new Map<string, SpendSignature>();
+  for (const [spendingKey, spender] of keys) {
+    if (!spender || "legacy" in spender) continue;
+    signatures.set(bytesToHex(spender.authKey), signSpend(spendingKey, message));
+  }
+  return Array.from(signatures.values());
+}
+
+/**
+ * The owners' signatures of a transfer: every non-dummy input's owner and
+ * each multisig input's cosigner sign its public values.
+ *
+ * Must match lib.rs TransferPrivateInputs::sign().
+ */
+export function signTransfer(request: TransferRequest): SpendSignature[] {
+  const auths = authorizeTransferInputs(request);
+  const keys = [0, 1]
+    .filter((i) => request.inputNotes[i].amount !== 0n)
+    .flatMap((i): [Uint8Array, SpenderKey | undefined][] => [
+      [request.inputSpendingKeys[i], auths[i].spender],
+      [request.inputMultisig?.[i]?.cosignerKey ?? new Uint8Array(32), auths[i].cosigner],
+    ]);
+  return signWith(keys, spendAuthMessage("transfer", transferPublicValues(request)));
+}
+
+/**
+ * The owners' signatures of a withdrawal: the input's owner and, for a
+ * multisig input, the cosigner sign its public values.
+ *
+ * Must match lib.rs WithdrawPrivateInputs::sign().
+ */
+export function signWithdraw(request: WithdrawRequest): SpendSignature[] {
+  const auth = authorizeWithdrawInput(request);
+  const keys: [Uint8Array, SpenderKey | undefined][] = [
+    [request.spendingKey, auth.spender],
+    [request.multisig?.cosignerKey ?? new Uint8Array(32), auth.cosigner],
+  ];
+  return signWith(keys, spendAuthMessage("withdraw", withdrawPublicValues(request)));
+}
@@ -4,6 +4,8 @@
  */
 
 import { keccak256 as ethersKeccak256 } from "ethers";
+import nacl from "tweetnacl";
+import type { FeeQuote, Note, SpenderKey, SpendSignature } from "./types.js";
 
 // ============================================================================
 //                          HELPERS
@@ -68,8 +70,8 @@ export function computeCommitment(
 // ============================================================================
 
 /**
- * Compute nullifier for a note.
- * nullifier = keccak256(commitment_32bytes || spending_key_32bytes)
+ * Compute nullifier for a note from its owner's spending key.
+ * nullifier = keccak256(commitment_32bytes || nullifier_key_32bytes)
  * Total preimage: 64 bytes.
  *
  * Must match lib.rs compute_nullifier().
@@ -77,10 +79,22 @@ export function computeCommitment(
 export function computeNullifier(
   commitment: Uint8Array,
   spendingKey: Uint8Array
+): Uint8Array {
+  return nullifierFromKey(commitment, deriveNullifierKey(spendingKey));
+}
+
+/**
+ * Compute nullifier for a note from its owner's nullifier key.
+ *
+ * Must match lib.rs nullifier_from_key().
+ */
+export function nullifierFromKey(
+  commitment: Uint8Array,
+  nullifierKey: Uint8Array
 ): Uint8Array {
   const preimage = new Uint8Array(64);
   preimage.set(commitment, 0);
-  preimage.set(spendingKey, 32);
+  preimage.set(nullifierKey, 32);
   return keccak256(preimage);
 }
 
@@ -88,16 +102,253 @@ export function computeNullifier(
 //                      KEY DERIVATION
 // ============================================================================
 
+/** A 32-byte domain: `tag` zero-padded, as the Rust constants are. */
+function domain32(tag: string): Uint8Array {
+  const domain = new Uint8Array(32);
+  domain.set(new TextEncoder().encode(tag));
+  return domain;
+}
+
+const NULLIFIER_KEY_DOMAIN = domain32("shielded-pool/nullifier-key/1");
+const SPEND_AUTH_KEY_DOMAIN = domain32("shielded-pool/spend-auth-key/1");
+const PUBKEY_DOMAIN = domain32("shielded-pool/note-pubkey/2");
+
+/**
+ * Derive the nullifier key from a spending key. A delegated prover gets this
+ * key and the spend-authorization public key (see deriveSpenderKey), never
+ * the spending key.
+ * nullifier_key = keccak256(domain || spending_key)
+ *
+ * Must match lib.rs derive_nullifier_key().
+ */
+export function deriveNullifierKey(spendingKey: Uint8Array): Uint8Array {
+  const preimage = new Uint8Array(64);
+  preimage.set(NULLIFIER_KEY_DOMAIN, 0);
+  preimage.set(spendingKey, 32);
+  return keccak256(preimage);
+}
+
+/**
+ * Derive the spend-authorization key from a spending key: the Ed25519 seed
+ * the owner signs spends with.
+ * spend_auth_key = keccak256(domain || spending_key)
+ *
+ * Must match lib.rs derive_spend_auth_key().
+ */
+export function deriveSpendAuthKey(spendingKey: Uint8Array): Uint8Array {
+  const preimage = new Uint8Array(64);
+  preimage.set(SPEND_AUTH_KEY_DOMAIN, 0);
+  preimage.set(spendingKey, 32);
+  return keccak256(preimage);
+}
+
+/**
+ * The Ed25519 public key of a spend-authorization key.
+ *
+ * Must match lib.rs spend_auth_pubkey().
+ */
+export function spendAuthPubkey(spendAuthKey: Uint8Array): Uint8Array {
+  return nacl.sign.keyPair.fromSeed(spendAuthKey).publicKey;
+}
+
+/**
+ * Compute a note pubkey from a spend-authorization public key and a
+ * nullifier key.
+ * pubkey = keccak256(domain || auth_key || nullifier_key)
+ *
+ * Must match lib.rs note_pubkey().
+ */
+export function notePubkey(authKey: Uint8Array, nullifierKey: Uint8Array): Uint8Array {
+  const preimage = new Uint8Array(96);
+  preimage.set(PUBKEY_DOMAIN, 0);
+  preimage.set(authKey, 32);
+  preimage.set(nullifierKey, 64);
+  return keccak256(preimage);
+}
+
 /**
  * Derive public key from spending key.
- * pubkey = keccak256(spending_key)
  *
  * Must match lib.rs derive_pubkey().
  */
 export function derivePubkey(spendingKey: Uint8Array): Uint8Array {
+  return notePubkey(spendAuthPubkey(deriveSpendAuthKey(spendingKey)), deriveNullifierKey(spendingKey));
+}
+
+/**
+ * The pubkey notes had before spend-authorization keys.
+ * pubkey = keccak256(spending_key)
+ * Their nullifier is nullifierFromKey() with the spending key as the
+ * nullifier key.
+ *
+ * Must match lib.rs legacy_pubkey().
+ */
+export function legacyPubkey(spendingKey: Uint8Array): Uint8Array {
   return keccak256(spendingKey);
 }
 
+/**
+ * The key a transfer or withdrawal spends an input with: the proving key,
+ * or the legacy key if `ownedPubkey` is the spending key's legacy pubkey.
+ * A legacy key is the spending key itself; never send it to a remote prover.
+ *
+ * Must match lib.rs authorize_spender().
+ */
+export function deriveSpenderKey(spendingKey: Uint8Array, ownedPubkey?: Uint8Array): SpenderKey {
+  if (ownedPubkey && bytesToHex(ownedPubkey) === bytesToHex(legacyPubkey(spendingKey))) {
+    return { legacy: spendingKey };
+  }
+  return {
+    authKey: spendAuthPubkey(deriveSpendAuthKey(spendingKey)),
+    nullifierKey: deriveNullifierKey(spendingKey),
+  };
+}
+
+/** The key a spender key derives nullifiers with. */
+export function spenderNullifierKey(key: SpenderKey): Uint8Array {
+  return "legacy" in key ? key.legacy : key.nullifierKey;
+}
+
+// ============================================================================
+//                      SPEND AUTHORIZATION
+// ============================================================================
+
+const SPEND_AUTH_MESSAGE_DOMAIN = domain32("shielded-pool/spend-auth-msg/1");
+
+/** lib.rs CircuitKind discriminants of the circuits that take signatures. */
+const SPEND_CIRCUITS = { transfer: 0, withdraw: 1 } as const;
+
+/**
+ * What the owners of a transfer's or withdrawal's inputs sign: every public
+ * value the proof commits.
+ * message = keccak256(domain || circuit (1 byte) || public_values)
+ *
+ * Must match lib.rs spend_auth_message().
+ */
+export function spendAuthMessage(
+  circuit: keyof typeof SPEND_CIRCUITS,
+  publicValues: Uint8Array
+): Uint8Array {
+  const preimage = new Uint8Array(33 + publicValues.length);
+  preimage.set(SPEND_AUTH_MESSAGE_DOMAIN, 0);
+  preimage[32] = SPEND_CIRCUITS[circuit];
+  preimage.set(publicValues, 33);
+  return keccak256(preimage);
+}
+
+/**
+ * Sign a spend message with the spending key's spend-authorization key
+ * (Ed25519, RFC 8032).
+ *
+ * Must match lib.rs SpendSignature::sign().
+ */
+export function signSpend(spendingKey: Uint8Array, message: Uint8Array): SpendSignature {
+  const keypair = nacl.sign.keyPair.fromSeed(deriveSpendAuthKey(spendingKey));
+  const signature = nacl.sign.detached(message, keypair.secretKey);
+  return { authKey: keypair.publicKey, r: signature.slice(0, 32), s: signature.slice(32) };
+}
+
+/**
+ * Spending key of dummy inputs. Public: a dummy holds no value.
+ *
+ * Must match lib.rs DUMMY_SPENDING_KEY.
+ */
+export const DUMMY_SPENDING_KEY = new TextEncoder().encode("shielded-pool/dummy-spending-key");
+
+/**
+ * Zero-amount note padding a transfer's second input slot. It is in no tree;
+ * the circuit skips the Merkle check for zero-amount inputs. `blinding` must
+ * be fresh randomness, or the dummy's nullifier repeats.
+ *
+ * Must match lib.rs Note::dummy().
+ */
+export function dummyNote(blinding: Uint8Array): Note {
+  return { amount: 0n, pubkey: derivePubkey(DUMMY_SPENDING_KEY), blinding };
+}
+
+// ============================================================================
+//                      CANCELLABLE NOTES
+// ============================================================================
+
+const LOCK_DOMAIN = new TextEncoder().encode("shielded-pool/cancellable-lock/1");
+const CANCEL_NULLIFIER_DOMAIN = new TextEncoder().encode("shielded-pool/cancel-nullifier/1");
+
+/**
+ * Compute the pubkey of a cancellable note.
+ * lock_pubkey = keccak256(domain || recipient_pubkey || sender_pubkey || deadline_be_8bytes)
+ *
+ * Must match lib.rs CancellableLock::lock_pubkey().
+ */
+export function computeLockPubkey(
+  recipientPubkey: Uint8Array,
+  senderPubkey: Uint8Array,
+  reclaimDeadline: bigint
+): Uint8Array {
+  const preimage = new Uint8Array(104);
+  preimage.set(LOCK_DOMAIN, 0);
+  preimage.set(recipientPubkey, 32);
+  preimage.set(senderPubkey, 64);
+  new DataView(preimage.buffer).setBigUint64(96, reclaimDeadline, false);
+  return keccak256(preimage);
+}
+
+/**
+ * Compute the nullifier of a cancellable note (same for claim and reclaim).
+ * nullifier = keccak256(domain || commitment || blinding)
+ *
+ * Must match lib.rs cancellable_nullifier().
+ */
+export function computeCancellableNullifier(
+  commitment: Uint8Array,
+  blinding: Uint8Array
+): Uint8Array {
+  const preimage = new Uint8Array(96);
+  preimage.set(CANCEL_NULLIFIER_DOMAIN, 0);
+  preimage.set(commitment, 32);
+  preimage.set(blinding, 64);
+  return keccak256(preimage);
+}
+
+// ============================================================================
+//                      MULTISIG NOTES
+// ============================================================================
+
+const MULTISIG_DOMAIN = new TextEncoder().encode("shielded-pool/multisig-pubkeys/1");
+const MULTISIG_NULLIFIER_DOMAIN = new TextEncoder().encode("shielded-pool/multisig-nullify/1");
+
+/**
+ * Compute the pubkey of a 2-of-2 multisig note.
+ * multisig_pubkey = keccak256(domain || owner_pubkey_0 || owner_pubkey_1)
+ *
+ * Must match lib.rs MultisigLock::multisig_pubkey().
+ */
+export function computeMultisigPubkey(ownerPubkeys: Uint8Array[]): Uint8Array {
+  const preimage = new Uint8Array(96);
+  preimage.set(MULTISIG_DOMAIN, 0);
+  preimage.set(ownerPubkeys[0], 32);
+  preimage.set(ownerPubkeys[1], 64);
+  return keccak256(preimage);
+}
+
+/**
+ * Compute the nullifier of a multisig note from both owners' nullifier keys,
+ * in lock order.
+ * nullifier = keccak256(domain || commitment || nullifier_key_0 || nullifier_key_1)
+ *
+ * Must match lib.rs multisig_nullifier().
+ */
+export function computeMultisigNullifier(
+  commitment: Uint8Array,
+  nullifierKeys: Uint8Array[]
+): Uint8Array {
+  const preimage = new Uint8Array(128);
+  preimage.set(MULTISIG_NULLIFIER_DOMAIN, 0);
+  preimage.set(commitment, 32);
+  preimage.set(nullifierKeys[0], 64);
+  preimage.set(nullifierKeys[1], 96);
+  return keccak256(preimage);
+}
+
 /**
  * Hash a pair of 32-byte nodes.
  * Matches Solidity: keccak256(abi.encodePacked(left, right))
@@ -108,3 +359,27 @@ export function hashPair(left: Uint8Array, right: Uint8Array): Uint8Array {
   data.set(right, 32);
   return keccak256(data);
 }
+
+// ============================================================================
+//                      PROVER FEES
+// ============================================================================
+
+const FEE_BLINDING_DOMAIN = new TextEncoder().encode("prover-fee");
+
+/**
+ * Note paying `amount` (at least quote.fee) to a proving relayer.
+ * blinding = keccak256("prover-fee" || quoteId)
+ *
+ * Must match lib.rs prover_fee::FeeQuote::fee_note().
+ */
+export function feeNote(quote: FeeQuote, amount: bigint): Note {
+  const quoteId = hexToBytes(quote.quoteId);
+  const preimage = new Uint8Array(FEE_BLINDING_DOMAIN.length + 32);
+  preimage.set(FEE_BLINDING_DOMAIN, 0);
+  preimage.set(quoteId, FEE_BLINDING_DOMAIN.length);
+  return {
+    amount,
+    pubkey: hexToBytes(quote.proverPubkey),
+    blinding: keccak256(preimage),
+  };
+}
@@ -5,11 +5,14 @@
  * The viewing key is derived from the spending key using a domain separator.
  *
  * Encrypted note format: [ephemeral_pubkey(32) || nonce(24) || ciphertext]
+ *
+ * A tagged note's nonce starts with an 8-byte detection tag so light wallets
+ * can find it through per-epoch bloom filters (see lib detection.rs).
  */
 
 import nacl from "tweetnacl";
 import { keccak256, hexToBytes, bytesToHex } from "./crypto.js";
-import type { Note } from "./types.js";
+import type { Note, OutputEncryption } from "./types.js";
 
 /**
  * Derive a viewing keypair from a spending key.
@@ -29,15 +32,88 @@ export function deriveViewingKeypair(spendingKey: Uint8Array): {
   return { secretKey, publicKey: keypair.publicKey };
 }
 
+const TAG_DOMAIN = new Uint8Array(32);
+TAG_DOMAIN.set(new TextEncoder().encode("shielded-pool/detection-tag/v1"));
+const TAG_LEN = 8;
+const TAG_SLOTS = 4;
+const EPOCH_SECONDS = 86_400;
+
+/**
+ * Detection tag of a note for `viewingPubkey` sent in `epoch`.
+ * tag = keccak256(domain || viewing_pubkey || epoch_be_8bytes || slot)[0..8]
+ *
+ * Must match lib detection.rs detection_tag().
+ */
+export function computeDetectionTag(
+  viewingPubkey: Uint8Array,
+  epoch: bigint,
+  slot: number
+): Uint8Array {
+  if (!Number.isInteger(slot) || slot < 0 || slot >= TAG_SLOTS) {
+    throw new Error(`detection tag slot must be in 0..${TAG_SLOTS}`);
+  }
+  const preimage = new Uint8Array(73);
+  preimage.set(TAG_DOMAIN, 0);
+  preimage.set(viewingPubkey, 32);
+  new DataView(preimage.buffer).setBigUint64(64, epoch, false);
+  preimage[72] = slot;
+  return keccak256(preimage).slice(0, TAG_LEN);
+}
+
+/** Options for encryptNote(). */
+export interface EncryptOptions {
+  /**
+   * Prefix the nonce with a detection tag. Anyone who knows the recipient's
+   * viewing pubkey can then recognise the note, so only tag for recipients
+   * that scan through bloom filters.
+   */
+  detectionTag?: boolean;
+  /** Send time used for the tag's epoch (unix seconds; default: local clock) */
+  now?: number;
+}
+
 /**
  * Encrypt note data for a recipient's viewing key.
  * Returns: ephemeral_pubkey(32) || nonce(24) || ciphertext
  */
 export function encryptNote(
   note: Note,
-  recipientViewingPubkey: Uint8Array
+  recipientViewingPubkey: Uint8Array,
+  options: EncryptOptions = {}
 ): Uint8Array {
-  // Serialize note to JSON bytes
+  return encryptNoteWith(note, newOutputEncryption(recipientViewingPubkey, options));
+}
+
+/**
+ * Fresh randomness for encrypting a note to a recipient's viewing key. Pass
+ * it as a transfer's outputEncryption to prove the payload, then post
+ * encryptNoteWith() of the same note.
+ */
+export function newOutputEncryption(
+  recipientViewingPubkey: Uint8Array,
+  options: EncryptOptions = {}
+): OutputEncryption {
+  const nonce = nacl.randomBytes(nacl.box.nonceLength);
+  if (options.detectionTag) {
+    const now = options.now ?? Math.floor(Date.now() / 1000);
+    const epoch = BigInt(Math.floor(now / EPOCH_SECONDS));
+    const slot = nacl.randomBytes(1)[0] % TAG_SLOTS;
+    nonce.set(computeDetectionTag(recipientViewingPubkey, epoch, slot), 0);
+  }
+  return {
+    viewingPubkey: recipientViewingPubkey,
+    ephemeralSecret: nacl.randomBytes(32),
+    nonce,
+  };
+}
+
+/**
+ * Encrypt a note with the given randomness: the same payload every time,
+ * byte for byte what the transfer circuit builds (lib note_encryption.rs).
+ * Returns: ephemeral_pubkey(32) || nonce(24) || ciphertext
+ */
+export function encryptNoteWith(note: Note, encryption: OutputEncryption): Uint8Array {
+  // Serialize note to JSON bytes (lib note_plaintext())
   const noteData = JSON.stringify({
     amount: note.amount.toString(),
     pubkey: bytesToHex(note.pubkey),
@@ -45,15 +121,13 @@ export function encryptNote(
   });
   const plaintext = new TextEncoder().encode(noteData);
 
-  // Generate ephemeral keypair for this encryption
-  const ephemeral = nacl.box.keyPair();
-  const nonce = nacl.randomBytes(nacl.box.nonceLength);
-
-  // Encrypt
+  // Encrypt under the one-time ephemeral key
+  const ephemeral = nacl.box.keyPair.fromSecretKey(encryption.ephemeralSecret);
+  const nonce = encryption.nonce;
   const ciphertext = nacl.box(
     plaintext,
     nonce,
-    recipientViewingPubkey,
+    encryption.viewingPubkey,
     ephemeral.secretKey
   );
 
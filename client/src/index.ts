@@ -12,6 +12,16 @@ export type {
   TransferRequest,
   WithdrawRequest,
   ProofResult,
+  JsonValue,
+  NoteMetadata,
+  CancellableLock,
+  MultisigSpend,
+  SpenderKey,
+  SpendSignature,
+  SpendPath,
+  FeeQuote,
+  OutputEncryption,
+  WithdrawCall,
 } from "./types.js";
 
 // Crypto primitives
@@ -19,12 +29,35 @@ export {
   keccak256,
   computeCommitment,
   computeNullifier,
+  nullifierFromKey,
+  computeLockPubkey,
+  computeCancellableNullifier,
+  computeMultisigPubkey,
+  computeMultisigNullifier,
+  feeNote,
+  deriveNullifierKey,
+  deriveSpendAuthKey,
+  spendAuthPubkey,
+  notePubkey,
   derivePubkey,
+  legacyPubkey,
+  deriveSpenderKey,
+  spenderNullifierKey,
+  spendAuthMessage,
+  signSpend,
   hashPair,
   hexToBytes,
   bytesToHex,
 } from "./crypto.js";
 
+// Spend authorization
+export {
+  transferPublicValues,
+  withdrawPublicValues,
+  signTransfer,
+  signWithdraw,
+} from "./authorization.js";
+
 // Merkle tree
 export {
   ClientMerkleTree,
@@ -33,15 +66,36 @@ export {
 } from "./merkle.js";
 
 // Wallet / key management
-export { ShieldedWallet } from "./wallet.js";
+export { ShieldedWallet, assertMetadataKey } from "./wallet.js";
+export type { MetadataFilter } from "./wallet.js";
 
 // Encryption
-export { encryptNote, decryptNote, deriveViewingKeypair } from "./encryption.js";
+export {
+  encryptNote,
+  encryptNoteWith,
+  newOutputEncryption,
+  decryptNote,
+  deriveViewingKeypair,
+  computeDetectionTag,
+} from "./encryption.js";
+export type { EncryptOptions } from "./encryption.js";
 
 // Prover
 export { Prover } from "./prover.js";
 export type { ProverOptions } from "./prover.js";
 
+// Submission middleware
+export { SubmissionPipeline } from "./middleware.js";
+export type {
+  SubmissionContext,
+  SubmissionMethod,
+  SubmissionMiddleware,
+} from "./middleware.js";
+
+// RPC throttling
+export { RpcThrottle, ThrottledJsonRpcProvider, sharedThrottle, isRateLimitError } from "./rpc.js";
+export type { RateLimitOptions, RetryInfo } from "./rpc.js";
+
 // Pool client
 export { ShieldedPoolClient } from "./pool.js";
-export type { PoolClientOptions } from "./pool.js";
+export type { PoolClientOptions, TransferOptions } from "./pool.js";
@@ -31,6 +31,11 @@ export class ClientMerkleTree {
   roots: Uint8Array[];
   currentRootIndex: number;
   leaves: Uint8Array[];
+  /**
+   * Interior nodes with an inserted leaf below them: nodes[h - 1][i] is node
+   * i at height h. Absent nodes are zeros[h]. Keeps getProof O(levels).
+   */
+  private nodes: Uint8Array[][];
 
   constructor(levels: number) {
     this.levels = levels;
@@ -48,6 +53,7 @@ export class ClientMerkleTree {
     );
     this.currentRootIndex = 0;
     this.leaves = [];
+    this.nodes = Array.from({ length: Math.max(levels - 1, 0) }, () => []);
   }
 
   /**
@@ -80,6 +86,9 @@ export class ClientMerkleTree {
 
       currentHash = new Uint8Array(hashPair(left, right));
       currentIndex = Math.floor(currentIndex / 2);
+      if (i + 1 < this.levels) {
+        this.nodes[i][currentIndex] = currentHash;
+      }
     }
 
     // Update root in circular buffer
@@ -114,46 +123,32 @@ export class ClientMerkleTree {
 
   /**
    * Generate a Merkle proof for the leaf at the given index.
-   * Rebuilds the full tree to compute sibling hashes.
+   * Siblings come from the nodes cached by insert(), so this is O(levels).
    */
   getProof(leafIndex: number): MerkleProofStep[] {
     if (leafIndex >= this.leaves.length) {
       throw new Error("Leaf index out of range");
     }
 
-    const numLeaves = 2 ** this.levels;
-    let currentLevel: Uint8Array[] = [];
-
-    // Fill leaves, pad rest with zeros[0]
-    for (let i = 0; i < numLeaves; i++) {
-      if (i < this.leaves.length) {
-        currentLevel.push(new Uint8Array(this.leaves[i]));
-      } else {
-        currentLevel.push(new Uint8Array(this.zeros[0]));
-      }
-    }
-
     const proof: MerkleProofStep[] = [];
     let idx = leafIndex;
 
     for (let level = 0; level < this.levels; level++) {
-      const siblingIdx = idx ^ 1;
-      const sibling = currentLevel[siblingIdx];
-      const isLeft = idx % 2 === 0;
-
-      proof.push({ is_left: isLeft, sibling: new Uint8Array(sibling) });
-
-      // Compute next level
-      const nextLevel: Uint8Array[] = [];
-      for (let j = 0; j < currentLevel.length / 2; j++) {
-        nextLevel.push(hashPair(currentLevel[2 * j], currentLevel[2 * j + 1]));
-      }
-      currentLevel = nextLevel;
+      proof.push({
+        is_left: idx % 2 === 0,
+        sibling: new Uint8Array(this.node(level, idx ^ 1)),
+      });
       idx = Math.floor(idx / 2);
     }
 
     return proof;
   }
+
+  /** Node `index` at `height` (0 = leaves), or the zero value if empty below. */
+  private node(height: number, index: number): Uint8Array {
+    const stored = height === 0 ? this.leaves : this.nodes[height - 1];
+    return stored[index] ?? this.zeros[height];
+  }
 }
 
 /** Verify a Merkle proof against an expected root. */
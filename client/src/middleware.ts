@@ -0,0 +1,107 @@
+/**
+ * Submission pipeline: composable middleware around every on-chain call.
+ *
+ * Gas policy, simulation, screening, nonce management and receipt handling
+ * all hook the same three points of a transaction's life:
+ *
+ *   preSend   — before the contract method is called (may rewrite args/overrides, or throw to abort)
+ *   postSend  — after the transaction is broadcast, before it is mined
+ *   onReceipt — once the receipt is available
+ *
+ * Middleware runs in the order it was registered.
+ */
+
+import type {
+  Contract,
+  ContractTransactionResponse,
+  TransactionReceipt,
+} from "ethers";
+
+/** Which contract call is being submitted. */
+export type SubmissionMethod = "approve" | "deposit" | "privateTransfer" | "withdraw";
+
+/** State shared by every middleware for a single submission. */
+export interface SubmissionContext {
+  /** Contract method being called. */
+  method: SubmissionMethod;
+  /** Contract the method is called on. */
+  contract: Contract;
+  /** Method arguments in ABI order. preSend hooks may rewrite these. */
+  args: unknown[];
+  /** Transaction overrides (gasLimit, nonce, maxFeePerGas, ...). */
+  overrides: Record<string, unknown>;
+  /** Scratch space for middleware to pass data between hooks. */
+  meta: Record<string, unknown>;
+}
+
+export interface SubmissionMiddleware {
+  /** Name used in error messages. */
+  name?: string;
+  preSend?(ctx: SubmissionContext): void | Promise<void>;
+  postSend?(ctx: SubmissionContext, tx: ContractTransactionResponse): void | Promise<void>;
+  onReceipt?(ctx: SubmissionContext, receipt: TransactionReceipt): void | Promise<void>;
+}
+
+export class SubmissionPipeline {
+  private middleware: SubmissionMiddleware[];
+
+  constructor(middleware: SubmissionMiddleware[] = []) {
+    this.middleware = [...middleware];
+  }
+
+  /** Append a middleware to the end of the chain. */
+  use(middleware: SubmissionMiddleware): this {
+    this.middleware.push(middleware);
+    return this;
+  }
+
+  /**
+   * Run `contract[method](...args, overrides)` through the middleware chain
+   * and wait for the receipt.
+   */
+  async submit(
+    contract: Contract,
+    method: SubmissionMethod,
+    args: unknown[]
+  ): Promise<TransactionReceipt> {
+    const ctx: SubmissionContext = { method, contract, args, overrides: {}, meta: {} };
+
+    for (const mw of this.middleware) {
+      if (mw.preSend) await this.run(mw, "preSend", () => mw.preSend!(ctx));
+    }
+
+    const tx: ContractTransactionResponse = await ctx.contract[ctx.method](
+      ...ctx.args,
+      ctx.overrides
+    );
+
+    for (const mw of this.middleware) {
+      if (mw.postSend) await this.run(mw, "postSend", () => mw.postSend!(ctx, tx));
+    }
+
+    const receipt = await tx.wait();
+    if (!receipt) {
+      throw new Error(`${method}: transaction ${tx.hash} returned no receipt`);
+    }
+
+    for (const mw of this.middleware) {
+      if (mw.onReceipt) await this.run(mw, "onReceipt", () => mw.onReceipt!(ctx, receipt));
+    }
+
+    return receipt;
+  }
+
+  private async run(
+    mw: SubmissionMiddleware,
+    hook: string,
+    fn: () => void | Promise<void>
+  ): Promise<void> {
+    try {
+      await fn();
+    } catch (err) {
+      const name = mw.name ?? "anonymous middleware";
+      const msg = err instanceof Error ? err.message : String(err);
+      throw new Error(`${name} ${hook} failed: ${msg}`);
+    }
+  }
+}
@@ -7,19 +7,23 @@
 
 import { Contract, type Signer, type TransactionReceipt } from "ethers";
 import { randomBytes } from "crypto";
-import { computeCommitment, bytesToHex, hexToBytes } from "./crypto.js";
+import { computeCommitment, bytesToHex, hexToBytes, dummyNote, DUMMY_SPENDING_KEY } from "./crypto.js";
 import { ClientMerkleTree } from "./merkle.js";
 import { ShieldedWallet } from "./wallet.js";
 import { Prover, type ProverOptions } from "./prover.js";
-import { encryptNote, deriveViewingKeypair } from "./encryption.js";
-import type { Note, NoteWithIndex } from "./types.js";
+import { encryptNote, encryptNoteWith, newOutputEncryption, deriveViewingKeypair } from "./encryption.js";
+import { SubmissionPipeline, type SubmissionMiddleware } from "./middleware.js";
+import type { RpcThrottle } from "./rpc.js";
+import type { CancellableLock, Note, NoteWithIndex, WithdrawCall } from "./types.js";
 
 const SHIELDED_POOL_ABI = [
   "function deposit(bytes32 commitment, uint256 amount, bytes encryptedData) external",
   "function privateTransfer(bytes proof, bytes publicValues, bytes encryptedOutput1, bytes encryptedOutput2) external",
   "function withdraw(bytes proof, bytes publicValues, bytes encryptedChange) external",
+  "function withdrawAndCall(bytes proof, bytes publicValues, bytes encryptedChange, address target, bytes data) external",
   "function getLastRoot() view returns (bytes32)",
   "function isKnownRoot(bytes32 root) view returns (bool)",
+  "function poolBinding() view returns (bytes32)",
   "function isSpent(bytes32 nullifier) view returns (bool)",
   "function getEncryptedNote(uint256 leafIndex) view returns (bytes)",
   "function nextIndex() view returns (uint32)",
@@ -35,12 +39,31 @@ const ERC20_ABI = [
   "function allowance(address owner, address spender) view returns (uint256)",
 ];
 
+/** Options for privateTransfer(). */
+export interface TransferOptions {
+  /**
+   * Make the recipient's note cancellable: this wallet can reclaim it until
+   * this unix timestamp (seconds) unless the recipient claims it first.
+   */
+  reclaimDeadline?: bigint;
+  /** Tag both outputs for bloom-filter scanning (see EncryptOptions) */
+  detectionTag?: boolean;
+}
+
 export interface PoolClientOptions {
   poolAddress: string;
   tokenAddress: string;
   signer: Signer;
   treeLevels?: number;
   proverOptions?: ProverOptions;
+  /** Middleware applied to every transaction the client submits. */
+  middleware?: SubmissionMiddleware[];
+  /**
+   * Throttle for the client's reads (event sync, spent checks). Not needed
+   * when the signer's provider is a ThrottledJsonRpcProvider, which already
+   * throttles every request.
+   */
+  rpc?: RpcThrottle;
 }
 
 export class ShieldedPoolClient {
@@ -50,8 +73,10 @@ export class ShieldedPoolClient {
   private wallet: ShieldedWallet;
   private prover: Prover;
   private treeLevels: number;
-  /** Dummy zero-value note for 2-in-2-out padding */
-  private dummyNote?: NoteWithIndex;
+  private pipeline: SubmissionPipeline;
+  private rpc?: RpcThrottle;
+  /** The pool's poolBinding(), fetched once */
+  private poolBinding?: Uint8Array;
 
   constructor(
     wallet: ShieldedWallet,
@@ -63,6 +88,14 @@ export class ShieldedPoolClient {
     this.tree = new ClientMerkleTree(this.treeLevels);
     this.wallet = wallet;
     this.prover = new Prover(options.proverOptions);
+    this.pipeline = new SubmissionPipeline(options.middleware);
+    this.rpc = options.rpc;
+  }
+
+  /** Add a submission middleware (runs after any already registered). */
+  use(middleware: SubmissionMiddleware): this {
+    this.pipeline.use(middleware);
+    return this;
   }
 
   /** Get the local Merkle tree (for testing/debugging). */
@@ -90,16 +123,14 @@ export class ShieldedPoolClient {
 
     // 3. Approve token spending
     const poolAddress = await this.pool.getAddress();
-    const tx1 = await this.token.approve(poolAddress, amount);
-    await tx1.wait();
+    await this.pipeline.submit(this.token, "approve", [poolAddress, amount]);
 
     // 4. Call ShieldedPool.deposit(commitment, amount, encryptedData)
-    const tx2 = await this.pool.deposit(
+    const receipt = await this.pipeline.submit(this.pool, "deposit", [
       bytesToHex(commitment),
       amount,
-      bytesToHex(encryptedData)
-    );
-    const receipt = await tx2.wait();
+      bytesToHex(encryptedData),
+    ]);
 
     // 5. Track in local tree and wallet
     const leafIndex = this.tree.insert(commitment);
@@ -111,57 +142,86 @@ export class ShieldedPoolClient {
   /**
    * Execute a private transfer within the pool.
    * 2-in-2-out: consumes up to 2 of your notes, creates 1 for recipient + 1 change.
+   * A single note is paired with a dummy input (see crypto.ts dummyNote).
+   *
+   * With `options.reclaimDeadline` the recipient's note is cancellable; the
+   * wallet keeps tracking it so it can be reclaimed (see reclaim()). Hand the
+   * lock to the recipient so they can claim it.
    */
   async privateTransfer(
     recipientPubkey: Uint8Array,
     amount: bigint,
-    recipientViewingPubkey?: Uint8Array
+    recipientViewingPubkey?: Uint8Array,
+    options: TransferOptions = {}
   ): Promise<TransactionReceipt> {
     // 1. Select input notes
-    const { inputs, change } = this.wallet.selectNotes(amount, this.dummyNote);
+    const { inputs, change } = this.wallet.selectNotes(amount);
 
     // 2. Create output notes
-    const recipientNote: Note = {
-      amount,
-      pubkey: recipientPubkey,
-      blinding: new Uint8Array(randomBytes(32)),
-    };
+    let recipientNote: Note;
+    let recipientLock: CancellableLock | undefined;
+    if (options.reclaimDeadline !== undefined) {
+      ({ note: recipientNote, lock: recipientLock } = this.wallet.createCancellableNote(
+        amount,
+        recipientPubkey,
+        options.reclaimDeadline
+      ));
+    } else {
+      recipientNote = {
+        amount,
+        pubkey: recipientPubkey,
+        blinding: new Uint8Array(randomBytes(32)),
+      };
+    }
     const changeNote = this.wallet.createNote(change);
 
-    // 3. Get Merkle proofs
+    // 3. Get Merkle proofs; a dummy second input needs none
     const root = this.tree.getRoot();
-    const proof0 = this.tree.getProof(inputs[0].leafIndex);
-    const proof1 = this.tree.getProof(inputs[1].leafIndex);
+    const proofs = inputs.map((n) => this.tree.getProof(n.leafIndex));
+    const keys = inputs.map(() => this.wallet.getSpendingKey());
+    const inputNotes: Note[] = [...inputs];
+    if (inputs.length === 1) {
+      inputNotes.push(dummyNote(new Uint8Array(randomBytes(32))));
+      keys.push(DUMMY_SPENDING_KEY);
+      proofs.push([]);
+    }
 
-    // 4. Generate proof
+    // 4. Pick the outputs' encryption randomness. With the recipient's
+    //    viewing key both payloads are proven, so the pool only takes them.
+    const viewingKeypair = deriveViewingKeypair(this.wallet.getSpendingKey());
+    const encryptOptions = { detectionTag: options.detectionTag };
+    const changeEncryption = newOutputEncryption(viewingKeypair.publicKey, encryptOptions);
+    const recipientEncryption = recipientViewingPubkey
+      ? newOutputEncryption(recipientViewingPubkey, encryptOptions)
+      : undefined;
+
+    // 5. Generate proof
     const proofResult = await this.prover.proveTransfer({
-      inputNotes: inputs,
-      inputSpendingKeys: [
-        this.wallet.getSpendingKey(),
-        this.wallet.getSpendingKey(),
-      ],
-      inputMerkleProofs: [proof0, proof1],
+      inputNotes,
+      inputSpendingKeys: keys,
+      inputMerkleProofs: proofs,
       outputNotes: [recipientNote, changeNote],
       root,
+      inputLocks: inputs.map((n) => n.lock),
+      poolBinding: await this.getPoolBinding(),
+      outputEncryption: recipientEncryption ? [recipientEncryption, changeEncryption] : undefined,
     });
 
-    // 5. Encrypt output notes
-    const viewingKeypair = deriveViewingKeypair(this.wallet.getSpendingKey());
-    const enc1 = recipientViewingPubkey
-      ? encryptNote(recipientNote, recipientViewingPubkey)
+    // 6. Encrypt output notes with that randomness
+    const enc1 = recipientEncryption
+      ? encryptNoteWith(recipientNote, recipientEncryption)
       : new Uint8Array(0);
-    const enc2 = encryptNote(changeNote, viewingKeypair.publicKey);
+    const enc2 = encryptNoteWith(changeNote, changeEncryption);
 
-    // 6. Submit on-chain
-    const tx = await this.pool.privateTransfer(
+    // 7. Submit on-chain
+    const receipt = await this.pipeline.submit(this.pool, "privateTransfer", [
       bytesToHex(proofResult.proof),
       bytesToHex(proofResult.publicValues),
       bytesToHex(enc1),
-      bytesToHex(enc2)
-    );
-    const receipt = await tx.wait();
+      bytesToHex(enc2),
+    ]);
 
-    // 7. Update local state
+    // 8. Update local state
     for (const input of inputs) {
       if (input.nullifier) this.wallet.markSpent(input.nullifier);
     }
@@ -176,9 +236,12 @@ export class ShieldedPoolClient {
       changeNote.pubkey,
       changeNote.blinding
     );
-    this.tree.insert(outComm1);
+    const recipientIndex = this.tree.insert(outComm1);
     const changeIndex = this.tree.insert(outComm2);
     this.wallet.addNote(changeNote, changeIndex);
+    if (recipientLock) {
+      this.wallet.addCancellableNote(recipientNote, recipientLock, recipientIndex);
+    }
 
     return receipt;
   }
@@ -196,6 +259,73 @@ export class ShieldedPoolClient {
     if (!inputNote) {
       throw new Error(`No single note covers ${amount}. Use transfer to consolidate first.`);
     }
+    return this.withdrawNote(inputNote, amount, recipient);
+  }
+
+  /**
+   * Withdraw `amount` to the contract `target` and have the pool call it
+   * with `data` in the same transaction (e.g. a DEX or bridge adapter that
+   * acts on the tokens it just received). Reverts entirely if the call does.
+   */
+  async withdrawAndCall(
+    amount: bigint,
+    target: string,
+    data: Uint8Array
+  ): Promise<TransactionReceipt> {
+    const spendable = this.wallet.getSpendableNotes();
+    const inputNote = spendable.find((n) => n.amount >= amount);
+    if (!inputNote) {
+      throw new Error(`No single note covers ${amount}. Use transfer to consolidate first.`);
+    }
+    return this.withdrawNote(inputNote, amount, target, { target, data });
+  }
+
+  /**
+   * Claim a cancellable note sent to this wallet by withdrawing it to
+   * `recipient` (default: the full amount; the rest stays as change).
+   */
+  async claim(
+    commitment: Uint8Array,
+    recipient: string,
+    amount?: bigint
+  ): Promise<TransactionReceipt> {
+    const note = this.wallet
+      .getClaimableNotes()
+      .find((n) => bytesToHex(n.commitment) === bytesToHex(commitment));
+    if (!note) throw new Error(`no claimable note ${bytesToHex(commitment)}`);
+    return this.withdrawNote(note, amount ?? note.amount, recipient);
+  }
+
+  /**
+   * Reclaim a cancellable note this wallet sent, withdrawing it to
+   * `recipient`. Fails once the reclaim deadline has passed or the recipient
+   * has claimed the note.
+   */
+  async reclaim(
+    commitment: Uint8Array,
+    recipient: string,
+    amount?: bigint
+  ): Promise<TransactionReceipt> {
+    const note = this.wallet
+      .getReclaimableNotes()
+      .find((n) => bytesToHex(n.commitment) === bytesToHex(commitment));
+    if (!note) throw new Error(`no reclaimable note ${bytesToHex(commitment)} (deadline passed?)`);
+    if (await this.read("isSpent", () => this.pool.isSpent(bytesToHex(note.nullifier!)))) {
+      this.wallet.markSpent(note.nullifier!);
+      throw new Error(`note ${bytesToHex(commitment)} was already claimed`);
+    }
+    return this.withdrawNote(note, amount ?? note.amount, recipient);
+  }
+
+  private async withdrawNote(
+    inputNote: NoteWithIndex,
+    amount: bigint,
+    recipient: string,
+    call?: WithdrawCall
+  ): Promise<TransactionReceipt> {
+    if (amount > inputNote.amount) {
+      throw new Error(`note holds ${inputNote.amount}, cannot withdraw ${amount}`);
+    }
 
     // 2. Create change note if partial withdrawal
     const changeAmount = inputNote.amount - amount;
@@ -214,6 +344,9 @@ export class ShieldedPoolClient {
       recipient,
       withdrawAmount: amount,
       changeNote,
+      lock: inputNote.lock,
+      poolBinding: await this.getPoolBinding(),
+      call,
     });
 
     // 5. Encrypt change note
@@ -222,13 +355,11 @@ export class ShieldedPoolClient {
       ? encryptNote(changeNote, viewingKeypair.publicKey)
       : new Uint8Array(0);
 
-    // 6. Submit on-chain
-    const tx = await this.pool.withdraw(
-      bytesToHex(proofResult.proof),
-      bytesToHex(proofResult.publicValues),
-      bytesToHex(encChange)
-    );
-    const receipt = await tx.wait();
+    // 6. Submit on-chain (a withdraw-and-call proof only through withdrawAndCall)
+    const args = [bytesToHex(proofResult.proof), bytesToHex(proofResult.publicValues), bytesToHex(encChange)];
+    const receipt = call
+      ? await this.pipeline.submit(this.pool, "withdrawAndCall", [...args, call.target, bytesToHex(call.data)])
+      : await this.pipeline.submit(this.pool, "withdraw", args);
 
     // 7. Update local state
     if (inputNote.nullifier) this.wallet.markSpent(inputNote.nullifier);
@@ -245,6 +376,24 @@ export class ShieldedPoolClient {
     return receipt;
   }
 
+  /**
+   * The binding proofs for this pool must commit (chain id and pool
+   * address; see shielded_pool_lib::pool_binding). Read from the pool so it
+   * always matches what the contract checks.
+   */
+  private async getPoolBinding(): Promise<Uint8Array> {
+    if (!this.poolBinding) {
+      const binding: string = await this.read("poolBinding", () => this.pool.poolBinding());
+      this.poolBinding = hexToBytes(binding);
+    }
+    return this.poolBinding;
+  }
+
+  /** Run an RPC read through the throttle, if one was given. */
+  private read<T>(label: string, fn: () => Promise<T>): Promise<T> {
+    return this.rpc ? this.rpc.call(fn, label) : fn();
+  }
+
   /**
    * Sync local Merkle tree by replaying on-chain events.
    * Call this on startup to catch up with any deposits/transfers that happened.
@@ -252,11 +401,11 @@ export class ShieldedPoolClient {
   async sync(fromBlock: number = 0): Promise<void> {
     // Get Deposit events
     const depositFilter = this.pool.filters.Deposit();
-    const depositEvents = await this.pool.queryFilter(depositFilter, fromBlock);
+    const depositEvents = await this.read("queryFilter", () => this.pool.queryFilter(depositFilter, fromBlock));
 
     // Get PrivateTransfer events
     const transferFilter = this.pool.filters.PrivateTransfer();
-    const transferEvents = await this.pool.queryFilter(transferFilter, fromBlock);
+    const transferEvents = await this.read("queryFilter", () => this.pool.queryFilter(transferFilter, fromBlock));
 
     // Sort all events by block number and log index
     const allEvents = [...depositEvents, ...transferEvents].sort((a, b) => {
@@ -1,7 +1,9 @@
 /**
  * Wraps the Rust SP1 proof generation binary.
  *
- * Invokes the shielded-pool-script binary via subprocess.
+ * Invokes the shielded-pool-script binary via subprocess. The script gets
+ * the inputs' proving keys and the owners' spend signatures, made here from
+ * the request's spending keys (see authorization.ts).
  * For production, set SP1_PROVER=network to use the Succinct Prover Network.
  */
 
@@ -10,12 +12,23 @@ import { writeFileSync, readFileSync, mkdtempSync, rmSync } from "fs";
 import { join } from "path";
 import { tmpdir } from "os";
 import { promisify } from "util";
-import { hexToBytes, bytesToHex } from "./crypto.js";
+import { hexToBytes, bytesToHex, keccak256 } from "./crypto.js";
+import {
+  authorizeTransferInputs,
+  authorizeWithdrawInput,
+  signTransfer,
+  signWithdraw,
+  type InputAuthorization,
+} from "./authorization.js";
 import type {
   TransferRequest,
   WithdrawRequest,
   ProofResult,
   MerkleProofStep,
+  CancellableLock,
+  MultisigSpend,
+  SpenderKey,
+  SpendSignature,
 } from "./types.js";
 
 const execFileAsync = promisify(execFile);
@@ -118,14 +131,48 @@ function serializeMerkleProof(
   }));
 }
 
+function serializeLock(lock?: CancellableLock) {
+  return lock
+    ? {
+        recipient_pubkey: Array.from(lock.recipientPubkey),
+        sender_pubkey: Array.from(lock.senderPubkey),
+        reclaim_deadline: Number(lock.reclaimDeadline),
+      }
+    : null;
+}
+
+function serializeSpenderKey(key: SpenderKey) {
+  return "legacy" in key
+    ? { Legacy: Array.from(key.legacy) }
+    : { Proving: { auth_key: Array.from(key.authKey), nullifier_key: Array.from(key.nullifierKey) } };
+}
+
+function serializeMultisig(auth: InputAuthorization, multisig?: MultisigSpend) {
+  return multisig && auth.cosigner
+    ? {
+        lock: { owner_pubkeys: multisig.ownerPubkeys.map((pk) => Array.from(pk)) },
+        cosigner: serializeSpenderKey(auth.cosigner),
+      }
+    : null;
+}
+
+function serializeSignatures(signatures: SpendSignature[]) {
+  return signatures.map((sig) => ({
+    auth_key: Array.from(sig.authKey),
+    r: Array.from(sig.r),
+    s: Array.from(sig.s),
+  }));
+}
+
 function serializeTransferInputs(request: TransferRequest): string {
+  const auths = authorizeTransferInputs(request);
   return JSON.stringify({
     input_notes: request.inputNotes.map((n) => ({
       amount: Number(n.amount),
       pubkey: Array.from(n.pubkey),
       blinding: Array.from(n.blinding),
     })),
-    spending_keys: request.inputSpendingKeys.map((k) => Array.from(k)),
+    spender_keys: auths.map((a) => serializeSpenderKey(a.spender)),
     merkle_proofs: request.inputMerkleProofs.map(serializeMerkleProof),
     output_notes: request.outputNotes.map((n) => ({
       amount: Number(n.amount),
@@ -133,17 +180,29 @@ function serializeTransferInputs(request: TransferRequest): string {
       blinding: Array.from(n.blinding),
     })),
     root: Array.from(request.root),
+    locks: [0, 1].map((i) => serializeLock(request.inputLocks?.[i])),
+    pool_binding: Array.from(request.poolBinding),
+    output_encryption: request.outputEncryption
+      ? request.outputEncryption.map((e) => ({
+          viewing_pubkey: Array.from(e.viewingPubkey),
+          ephemeral_secret: Array.from(e.ephemeralSecret),
+          nonce: Array.from(e.nonce),
+        }))
+      : null,
+    multisig: [0, 1].map((i) => serializeMultisig(auths[i], request.inputMultisig?.[i])),
+    spend_signatures: serializeSignatures(signTransfer(request)),
   });
 }
 
 function serializeWithdrawInputs(request: WithdrawRequest): string {
+  const auth = authorizeWithdrawInput(request);
   return JSON.stringify({
     input_note: {
       amount: Number(request.inputNote.amount),
       pubkey: Array.from(request.inputNote.pubkey),
       blinding: Array.from(request.inputNote.blinding),
     },
-    spending_key: Array.from(request.spendingKey),
+    spender_key: serializeSpenderKey(auth.spender),
     merkle_proof: serializeMerkleProof(request.merkleProof),
     root: Array.from(request.root),
     recipient: Array.from(hexToBytes(request.recipient)),
@@ -155,5 +214,14 @@ function serializeWithdrawInputs(request: WithdrawRequest): string {
           blinding: Array.from(request.changeNote.blinding),
         }
       : null,
+    lock: serializeLock(request.lock),
+    fee: Number(request.fee ?? 0n),
+    relayer: Array.from(hexToBytes(request.relayer ?? "0x" + "00".repeat(20))),
+    pool_binding: Array.from(request.poolBinding),
+    deadline: Number(request.deadline ?? 0n),
+    multisig: serializeMultisig(auth, request.multisig),
+    call_target: Array.from(hexToBytes(request.call?.target ?? "0x" + "00".repeat(20))),
+    calldata_hash: Array.from(request.call ? keccak256(request.call.data) : new Uint8Array(32)),
+    spend_signatures: serializeSignatures(signWithdraw(request)),
   });
 }
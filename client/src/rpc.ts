@@ -0,0 +1,227 @@
+/**
+ * Shared RPC throttling: one rate limit and backoff policy per endpoint.
+ *
+ * Sync, balance checks, watchers, relayers and exits each query the RPC in
+ * their own pattern. Run side by side they trip the provider's limits, and
+ * each one retrying on its own only makes it worse. An `RpcThrottle` is the
+ * single gate for an endpoint:
+ *
+ *   - a token bucket caps the request rate (`requestsPerSecond`, `burst`)
+ *   - at most `maxConcurrent` requests are in flight
+ *   - a rate-limited or timed-out request is retried with exponential
+ *     backoff and jitter, and the whole throttle pauses for that delay, so
+ *     every consumer backs off together rather than hammering the endpoint
+ *
+ * Route calls through it with `throttle.call(fn)`, or give every component a
+ * `ThrottledJsonRpcProvider`, which sends each JSON-RPC request (reads,
+ * log queries and submissions alike) through its throttle. Components
+ * created from the same URL share one throttle via `sharedThrottle`.
+ */
+
+import {
+  JsonRpcProvider,
+  type JsonRpcApiProviderOptions,
+  type JsonRpcPayload,
+  type JsonRpcResult,
+  type Networkish,
+} from "ethers";
+
+export interface RateLimitOptions {
+  /** Sustained request rate. Default 10. */
+  requestsPerSecond?: number;
+  /** Requests that may go out at once after an idle period. Default: requestsPerSecond. */
+  burst?: number;
+  /** Requests in flight at most. Default 4. */
+  maxConcurrent?: number;
+  /** Retries after a retryable error. Default 5. */
+  retries?: number;
+  /** Delay before the first retry, doubled for each further one. Default 250 ms. */
+  baseDelayMs?: number;
+  /** Cap on a single retry delay. Default 10 s. */
+  maxDelayMs?: number;
+  /** Which errors to retry. Default: isRateLimitError. */
+  isRetryable?: (err: unknown) => boolean;
+  /** Called before each retry (for logging). */
+  onRetry?: (info: RetryInfo) => void;
+}
+
+export interface RetryInfo {
+  /** Label passed to call(), e.g. the JSON-RPC method. */
+  label?: string;
+  /** 1 for the first retry. */
+  attempt: number;
+  delayMs: number;
+  error: unknown;
+}
+
+/** JSON-RPC error codes providers use for "slow down". */
+const RATE_LIMIT_CODES = new Set<unknown>([429, -32005, -32029, -32090]);
+
+/**
+ * Whether `err` says the endpoint is overloaded or limiting us: HTTP 429,
+ * the usual JSON-RPC limit codes and messages, or a timeout. A timed-out
+ * request may still have been processed; sending it again is safe anyway,
+ * as every request is a read or a signed transaction with a fixed hash.
+ */
+export function isRateLimitError(err: unknown): boolean {
+  if (err === null || err === undefined) return false;
+  const e = err as {
+    code?: unknown;
+    status?: unknown;
+    error?: { code?: unknown; message?: unknown };
+    message?: unknown;
+  };
+  if (e.code === "TIMEOUT") return true;
+  if (RATE_LIMIT_CODES.has(e.code) || RATE_LIMIT_CODES.has(e.status) || RATE_LIMIT_CODES.has(e.error?.code)) {
+    return true;
+  }
+  const message = (typeof err === "string" ? err : `${e.message ?? ""} ${e.error?.message ?? ""}`).toLowerCase();
+  return /\b429\b|rate.?limit|too many requests|limit exceeded|throttl|capacity exceeded/.test(message);
+}
+
+const sleep = (ms: number) => new Promise<void>((resolve) => setTimeout(resolve, ms));
+
+export class RpcThrottle {
+  private readonly rate: number;
+  private readonly burst: number;
+  private readonly maxConcurrent: number;
+  private readonly retries: number;
+  private readonly baseDelayMs: number;
+  private readonly maxDelayMs: number;
+  private readonly isRetryable: (err: unknown) => boolean;
+  private readonly onRetry?: (info: RetryInfo) => void;
+
+  private tokens: number;
+  private refilledAt = Date.now();
+  private pausedUntil = 0;
+  private inFlight = 0;
+  /** Callers waiting for a slot, first come first served */
+  private waiters: Array<() => void> = [];
+  /** Serializes token acquisition so waiters keep their order */
+  private gate: Promise<void> = Promise.resolve();
+
+  constructor(options: RateLimitOptions = {}) {
+    this.rate = options.requestsPerSecond ?? 10;
+    this.burst = options.burst ?? this.rate;
+    this.maxConcurrent = options.maxConcurrent ?? 4;
+    this.retries = options.retries ?? 5;
+    this.baseDelayMs = options.baseDelayMs ?? 250;
+    this.maxDelayMs = options.maxDelayMs ?? 10_000;
+    this.isRetryable = options.isRetryable ?? isRateLimitError;
+    this.onRetry = options.onRetry;
+    if (!(this.rate > 0) || !(this.burst >= 1) || !(this.maxConcurrent >= 1)) {
+      throw new Error("rate limit needs requestsPerSecond > 0, burst >= 1 and maxConcurrent >= 1");
+    }
+    this.tokens = this.burst;
+  }
+
+  /**
+   * Run `fn` (one RPC request) under the rate limit, retrying it with
+   * backoff while it fails with a retryable error.
+   */
+  async call<T>(fn: () => Promise<T>, label?: string): Promise<T> {
+    for (let attempt = 0; ; attempt++) {
+      await this.acquire();
+      try {
+        return await fn();
+      } catch (err) {
+        if (attempt >= this.retries || !this.isRetryable(err)) throw err;
+        const delayMs = this.backoff(attempt);
+        // Everyone sharing the endpoint waits, not just this caller
+        this.pausedUntil = Math.max(this.pausedUntil, Date.now() + delayMs);
+        this.onRetry?.({ label, attempt: attempt + 1, delayMs, error: err });
+      } finally {
+        this.release();
+      }
+    }
+  }
+
+  /** Requests currently in flight. */
+  pending(): number {
+    return this.inFlight;
+  }
+
+  private backoff(attempt: number): number {
+    const delay = Math.min(this.maxDelayMs, this.baseDelayMs * 2 ** attempt);
+    return Math.round(delay * (0.5 + Math.random() / 2));
+  }
+
+  private async acquire(): Promise<void> {
+    if (this.inFlight >= this.maxConcurrent) {
+      // release() hands its slot straight to us
+      await new Promise<void>((resolve) => this.waiters.push(resolve));
+    } else {
+      this.inFlight++;
+    }
+    const turn = this.gate.then(() => this.takeToken());
+    this.gate = turn;
+    await turn;
+  }
+
+  private release(): void {
+    const next = this.waiters.shift();
+    if (next) next();
+    else this.inFlight--;
+  }
+
+  private async takeToken(): Promise<void> {
+    for (;;) {
+      const now = Date.now();
+      if (now < this.pausedUntil) {
+        await sleep(this.pausedUntil - now);
+        continue;
+      }
+      this.tokens = Math.min(this.burst, this.tokens + ((now - this.refilledAt) / 1000) * this.rate);
+      this.refilledAt = now;
+      if (this.tokens >= 1) {
+        this.tokens -= 1;
+        return;
+      }
+      await sleep(((1 - this.tokens) / this.rate) * 1000);
+    }
+  }
+}
+
+const shared = new Map<string, RpcThrottle>();
+
+/**
+ * The throttle for endpoint `key` (usually the RPC URL), created with
+ * `options` on first use. Later options for the same key are ignored.
+ */
+export function sharedThrottle(key: string, options?: RateLimitOptions): RpcThrottle {
+  let throttle = shared.get(key);
+  if (!throttle) {
+    throttle = new RpcThrottle(options);
+    shared.set(key, throttle);
+  }
+  return throttle;
+}
+
+/**
+ * JsonRpcProvider that sends every request through an RpcThrottle (by
+ * default the shared one for `url`). Batches count as one request.
+ */
+export class ThrottledJsonRpcProvider extends JsonRpcProvider {
+  readonly throttle: RpcThrottle;
+
+  constructor(
+    url: string,
+    network?: Networkish,
+    throttle?: RpcThrottle | RateLimitOptions,
+    options?: JsonRpcApiProviderOptions
+  ) {
+    super(url, network, options);
+    this.throttle = throttle instanceof RpcThrottle ? throttle : sharedThrottle(url, throttle);
+  }
+
+  override _send(payload: JsonRpcPayload | Array<JsonRpcPayload>): Promise<Array<JsonRpcResult>> {
+    const label = Array.isArray(payload) ? `batch of ${payload.length}` : payload.method;
+    return this.throttle.call(async () => {
+      const results = await super._send(payload);
+      // A limit may come back per request rather than as an HTTP error
+      const limited = results.find((r) => isRateLimitError((r as { error?: unknown }).error));
+      if (limited) throw (limited as { error?: unknown }).error;
+      return results;
+    }, label);
+  }
+}
@@ -9,11 +9,68 @@ export interface Note {
   blinding: Uint8Array; // 32 bytes
 }
 
+/** Any JSON-serializable value. */
+export type JsonValue =
+  | string
+  | number
+  | boolean
+  | null
+  | JsonValue[]
+  | { [key: string]: JsonValue };
+
+/**
+ * Integrator metadata attached to a note (order ids, ticket refs, ...).
+ * Keys are namespaced as `namespace:name`; values are plain JSON.
+ */
+export type NoteMetadata = Record<string, JsonValue>;
+
+/**
+ * Lock of a cancellable note: the recipient can claim it at any time, the
+ * sender can reclaim it until `reclaimDeadline`. The note's pubkey is the
+ * lock hash (see computeLockPubkey).
+ */
+export interface CancellableLock {
+  recipientPubkey: Uint8Array; // 32 bytes
+  senderPubkey: Uint8Array; // 32 bytes
+  reclaimDeadline: bigint; // unix seconds
+}
+
+/**
+ * Second half of a 2-of-2 multisig spend. The note's pubkey commits to both
+ * owners (see computeMultisigPubkey); the request's spending key is owner
+ * 0's and `cosignerKey` is owner 1's. Both owners sign the spend.
+ */
+export interface MultisigSpend {
+  ownerPubkeys: Uint8Array[]; // two 32-byte pubkeys, in order
+  cosignerKey: Uint8Array; // owner 1's spending key (the prover gets its proving key)
+}
+
+/**
+ * The key a transfer or withdrawal input is spent with (lib.rs SpenderKey):
+ * the owner's proving key, whose spends the owner signs under `authKey`, or
+ * the spending key of a legacy note (see deriveSpenderKey).
+ */
+export type SpenderKey =
+  | { authKey: Uint8Array; nullifierKey: Uint8Array } // 32 bytes each
+  | { legacy: Uint8Array }; // 32-byte spending key
+
+/** An owner's Ed25519 signature of a spend (see spendAuthMessage). */
+export interface SpendSignature {
+  authKey: Uint8Array; // 32 bytes
+  r: Uint8Array; // 32 bytes
+  s: Uint8Array; // 32 bytes
+}
+
+/** How this wallet can spend a note. */
+export type SpendPath = "owner" | "claim" | "reclaim";
+
 /** A note with its position in the Merkle tree. */
 export interface NoteWithIndex extends Note {
   commitment: Uint8Array; // 32 bytes
   leafIndex: number;
   nullifier?: Uint8Array; // computed when spending key is known
+  metadata?: NoteMetadata;
+  lock?: CancellableLock; // set for cancellable notes
 }
 
 /** A single step in a Merkle proof. */
@@ -22,13 +79,41 @@ export interface MerkleProofStep {
   sibling: Uint8Array; // 32 bytes
 }
 
+/**
+ * What a transfer output's payload is encrypted with, when the transfer
+ * proves its payloads open to its output notes (see lib note_encryption.rs).
+ */
+export interface OutputEncryption {
+  viewingPubkey: Uint8Array; // recipient's viewing pubkey (x25519)
+  ephemeralSecret: Uint8Array; // 32 bytes, fresh per payload
+  nonce: Uint8Array; // 24 bytes, fresh per payload
+}
+
 /** Inputs for generating a transfer proof. */
 export interface TransferRequest {
-  inputNotes: NoteWithIndex[];
+  /** Two notes; a dummy (see crypto.ts dummyNote) fills an unused slot */
+  inputNotes: Note[];
   inputSpendingKeys: Uint8Array[];
   inputMerkleProofs: MerkleProofStep[][];
   outputNotes: Note[];
   root: Uint8Array;
+  /** Lock per input (undefined for plain notes) */
+  inputLocks?: (CancellableLock | undefined)[];
+  /** The pool's poolBinding(); the pool rejects proofs bound to another */
+  poolBinding: Uint8Array;
+  /** One per output to prove the posted payloads (see encryptNoteWith) */
+  outputEncryption?: OutputEncryption[];
+  /** Cosigner per input (undefined unless the input is a multisig note) */
+  inputMultisig?: (MultisigSpend | undefined)[];
+}
+
+/**
+ * The call a withdraw-and-call makes after paying out: the pool calls
+ * `target` with `data`, and the proof commits both (see lib call_binding).
+ */
+export interface WithdrawCall {
+  target: string; // 0x-prefixed contract address
+  data: Uint8Array; // calldata
 }
 
 /** Inputs for generating a withdraw proof. */
@@ -40,6 +125,30 @@ export interface WithdrawRequest {
   recipient: string; // 0x-prefixed Ethereum address
   withdrawAmount: bigint;
   changeNote?: Note;
+  /** Lock if the input is a cancellable note */
+  lock?: CancellableLock;
+  /** Relayer submitting the withdrawal; paid `fee` out of the note */
+  relayer?: string; // 0x-prefixed address
+  fee?: bigint;
+  /** The pool's poolBinding(); the pool rejects proofs bound to another */
+  poolBinding: Uint8Array;
+  /** Unix time after which the pool rejects the proof (default 0: none) */
+  deadline?: bigint;
+  /** Cosigner if the input is a multisig note; change must go back to it */
+  multisig?: MultisigSpend;
+  /** Set for a withdraw-and-call; the proof must be submitted with withdrawAndCall */
+  call?: WithdrawCall;
+}
+
+/**
+ * A proving relayer's price for one transfer, from its GET /quote endpoint.
+ * The transfer pays it with a note in its second output (see feeNote).
+ */
+export interface FeeQuote {
+  quoteId: string; // 0x-prefixed 32 bytes
+  proverPubkey: string; // 0x-prefixed 32 bytes
+  fee: string; // minimum fee in raw units, decimal string
+  expiresAt: number; // unix seconds
 }
 
 /** Result of proof generation. */
@@ -6,14 +6,44 @@ import { randomBytes } from "crypto";
 import {
   computeCommitment,
   computeNullifier,
+  nullifierFromKey,
+  computeLockPubkey,
+  computeCancellableNullifier,
   derivePubkey,
+  legacyPubkey,
   bytesToHex,
 } from "./crypto.js";
-import type { Note, NoteWithIndex } from "./types.js";
+import type {
+  CancellableLock,
+  JsonValue,
+  Note,
+  NoteMetadata,
+  NoteWithIndex,
+  SpendPath,
+} from "./types.js";
+
+const METADATA_KEY = /^[a-z0-9_.-]+:\S+$/;
+
+/** Throw unless `key` has the form `namespace:name` (namespace lowercase [a-z0-9_.-]). */
+export function assertMetadataKey(key: string): void {
+  if (!METADATA_KEY.test(key)) {
+    throw new Error(
+      `metadata key '${key}' must be namespaced as 'namespace:name' (namespace lowercase [a-z0-9_.-])`
+    );
+  }
+}
+
+/**
+ * Metadata filter: every key must be present; a non-undefined value must
+ * also match (compared as JSON).
+ */
+export type MetadataFilter = Record<string, JsonValue | undefined>;
 
 export class ShieldedWallet {
   private spendingKey: Uint8Array;
   public pubkey: Uint8Array;
+  /** Pubkey of this key's notes from before spend-authorization keys */
+  private legacyPubkey: Uint8Array;
   private notes: Map<string, NoteWithIndex>; // commitment hex -> note
   private spentNullifiers: Set<string>;
 
@@ -21,6 +51,7 @@ export class ShieldedWallet {
     this.spendingKey =
       spendingKey ?? new Uint8Array(randomBytes(32));
     this.pubkey = derivePubkey(this.spendingKey);
+    this.legacyPubkey = legacyPubkey(this.spendingKey);
     this.notes = new Map();
     this.spentNullifiers = new Set();
   }
@@ -40,37 +71,166 @@ export class ShieldedWallet {
     };
   }
 
-  /** Register a note in the wallet's local state. */
-  addNote(note: Note, leafIndex: number): NoteWithIndex {
+  /** Register a note in the wallet's local state, optionally with metadata. */
+  addNote(note: Note, leafIndex: number, metadata?: NoteMetadata): NoteWithIndex {
     const commitment = computeCommitment(
       note.amount,
       note.pubkey,
       note.blinding
     );
-    const nullifier = computeNullifier(commitment, this.spendingKey);
+    const nullifier = this.isLegacy(note.pubkey)
+      ? nullifierFromKey(commitment, this.spendingKey)
+      : computeNullifier(commitment, this.spendingKey);
     const noteWithIndex: NoteWithIndex = {
       ...note,
       commitment,
       leafIndex,
       nullifier,
     };
+    if (metadata) {
+      Object.keys(metadata).forEach(assertMetadataKey);
+      noteWithIndex.metadata = { ...metadata };
+    }
     this.notes.set(bytesToHex(commitment), noteWithIndex);
     return noteWithIndex;
   }
 
+  /**
+   * Create a cancellable payment note: `recipientPubkey` can claim it at any
+   * time, this wallet can reclaim it until `reclaimDeadline` (unix seconds).
+   */
+  createCancellableNote(
+    amount: bigint,
+    recipientPubkey: Uint8Array,
+    reclaimDeadline: bigint
+  ): { note: Note; lock: CancellableLock } {
+    const lock: CancellableLock = {
+      recipientPubkey: new Uint8Array(recipientPubkey),
+      senderPubkey: new Uint8Array(this.pubkey),
+      reclaimDeadline,
+    };
+    const note: Note = {
+      amount,
+      pubkey: computeLockPubkey(lock.recipientPubkey, lock.senderPubkey, reclaimDeadline),
+      blinding: new Uint8Array(randomBytes(32)),
+    };
+    return { note, lock };
+  }
+
+  /**
+   * Register a cancellable note this wallet can claim (as recipient) or
+   * reclaim (as sender).
+   */
+  addCancellableNote(
+    note: Note,
+    lock: CancellableLock,
+    leafIndex: number,
+    metadata?: NoteMetadata
+  ): NoteWithIndex {
+    const lockPubkey = computeLockPubkey(lock.recipientPubkey, lock.senderPubkey, lock.reclaimDeadline);
+    if (bytesToHex(lockPubkey) !== bytesToHex(note.pubkey)) {
+      throw new Error("cancellable lock does not match the note's pubkey");
+    }
+    if (!this.owns(lock.recipientPubkey) && !this.owns(lock.senderPubkey)) {
+      throw new Error("this wallet is neither the recipient nor the sender of the note");
+    }
+    const tracked = this.addNote(note, leafIndex, metadata);
+    tracked.lock = lock;
+    tracked.nullifier = computeCancellableNullifier(tracked.commitment, note.blinding);
+    return tracked;
+  }
+
+  /** How this wallet would spend `note` (undefined if it cannot). */
+  spendPath(note: NoteWithIndex): SpendPath | undefined {
+    if (!note.lock) {
+      return this.owns(note.pubkey) ? "owner" : undefined;
+    }
+    if (this.owns(note.lock.recipientPubkey)) return "claim";
+    if (this.owns(note.lock.senderPubkey)) return "reclaim";
+    return undefined;
+  }
+
+  /** Whether `pubkey` is this wallet's, current or legacy. */
+  private owns(pubkey: Uint8Array): boolean {
+    return bytesToHex(pubkey) === bytesToHex(this.pubkey) || this.isLegacy(pubkey);
+  }
+
+  /**
+   * Whether `pubkey` is this wallet's legacy pubkey. Such notes are spent
+   * with the spending key itself, so prove them locally (see
+   * deriveSpenderKey); their change comes back under the current pubkey.
+   */
+  private isLegacy(pubkey: Uint8Array): boolean {
+    return bytesToHex(pubkey) === bytesToHex(this.legacyPubkey);
+  }
+
+  /** Unspent cancellable notes this wallet can claim as recipient. */
+  getClaimableNotes(): NoteWithIndex[] {
+    return this.getUnspentNotes().filter((note) => note.lock && this.spendPath(note) === "claim");
+  }
+
+  /**
+   * Unspent cancellable notes this wallet sent and can still reclaim at
+   * `now` (unix seconds; default: local clock).
+   */
+  getReclaimableNotes(now: bigint = BigInt(Math.floor(Date.now() / 1000))): NoteWithIndex[] {
+    return this.getUnspentNotes().filter(
+      (note) => this.spendPath(note) === "reclaim" && now <= note.lock!.reclaimDeadline
+    );
+  }
+
+  /** Set a metadata entry on a tracked note. */
+  setNoteMetadata(commitment: Uint8Array, key: string, value: JsonValue): void {
+    assertMetadataKey(key);
+    const note = this.requireNote(commitment);
+    note.metadata = { ...note.metadata, [key]: value };
+  }
+
+  /** Remove a metadata entry from a tracked note. */
+  removeNoteMetadata(commitment: Uint8Array, key: string): void {
+    const note = this.requireNote(commitment);
+    if (!note.metadata) return;
+    delete note.metadata[key];
+    if (Object.keys(note.metadata).length === 0) delete note.metadata;
+  }
+
+  /** All tracked notes (spent or not) matching a metadata filter. */
+  findNotes(filter: MetadataFilter = {}): NoteWithIndex[] {
+    return Array.from(this.notes.values()).filter((note) =>
+      Object.entries(filter).every(([key, want]) => {
+        if (!note.metadata || !(key in note.metadata)) return false;
+        return want === undefined || JSON.stringify(note.metadata[key]) === JSON.stringify(want);
+      })
+    );
+  }
+
+  private requireNote(commitment: Uint8Array): NoteWithIndex {
+    const note = this.notes.get(bytesToHex(commitment));
+    if (!note) throw new Error(`unknown note ${bytesToHex(commitment)}`);
+    return note;
+  }
+
   /** Mark a nullifier as spent (after a successful transfer/withdraw). */
   markSpent(nullifier: Uint8Array): void {
     this.spentNullifiers.add(bytesToHex(nullifier));
   }
 
-  /** Get all unspent notes. */
-  getSpendableNotes(): NoteWithIndex[] {
+  private getUnspentNotes(): NoteWithIndex[] {
     return Array.from(this.notes.values()).filter((note) => {
       if (!note.nullifier) return false;
       return !this.spentNullifiers.has(bytesToHex(note.nullifier));
     });
   }
 
+  /**
+   * Get all unspent notes this wallet can spend as its own: plain notes and
+   * cancellable notes it can claim. Notes it could only reclaim are left out;
+   * use getReclaimableNotes() for those.
+   */
+  getSpendableNotes(): NoteWithIndex[] {
+    return this.getUnspentNotes().filter((note) => this.spendPath(note) !== "reclaim");
+  }
+
   /** Get total spendable balance. */
   getBalance(): bigint {
     return this.getSpendableNotes().reduce(
@@ -81,13 +241,10 @@ export class ShieldedWallet {
 
   /**
    * Select notes to cover the requested amount.
-   * Returns { inputs: NoteWithIndex[], change: bigint }.
-   * For 2-in-2-out, always returns exactly 2 inputs (padding with dummy if needed).
+   * Returns { inputs: NoteWithIndex[], change: bigint } with 1 or 2 inputs;
+   * the caller pads a single input with a dummy (see crypto.ts dummyNote).
    */
-  selectNotes(
-    amount: bigint,
-    dummyNote?: NoteWithIndex
-  ): { inputs: NoteWithIndex[]; change: bigint } {
+  selectNotes(amount: bigint): { inputs: NoteWithIndex[]; change: bigint } {
     const spendable = this.getSpendableNotes().sort(
       (a, b) => Number(b.amount - a.amount) // largest first
     );
@@ -108,16 +265,6 @@ export class ShieldedWallet {
       );
     }
 
-    // Pad to exactly 2 inputs
-    while (selected.length < 2) {
-      if (!dummyNote) {
-        throw new Error(
-          "Need a dummy zero-value note for 2-in-2-out padding"
-        );
-      }
-      selected.push(dummyNote);
-    }
-
     return { inputs: selected, change: total - amount };
   }
 
@@ -133,6 +280,16 @@ export class ShieldedWallet {
         commitment: bytesToHex(note.commitment),
         leafIndex: note.leafIndex,
         nullifier: note.nullifier ? bytesToHex(note.nullifier) : null,
+        ...(note.metadata ? { metadata: note.metadata } : {}),
+        ...(note.lock
+          ? {
+              lock: {
+                recipientPubkey: bytesToHex(note.lock.recipientPubkey),
+                senderPubkey: bytesToHex(note.lock.senderPubkey),
+                reclaimDeadline: note.lock.reclaimDeadline.toString(),
+              },
+            }
+          : {}),
       })),
       spentNullifiers: Array.from(this.spentNullifiers),
     });
@@ -0,0 +1,27 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {MerkleTree} from "./MerkleTree.sol";
+
+/// @title CounterTree
+/// @notice Merkle tree of policy spend counters, kept apart from the note
+///         tree so a counter cannot be forged with a deposit. Only the pool
+///         that deployed it appends, one counter per verified policy spend.
+
+contract CounterTree is MerkleTree {
+    /// @notice The pool allowed to append counters
+    address public immutable POOL;
+
+    error NotPool();
+
+    constructor(uint32 _levels) MerkleTree(_levels) {
+        POOL = msg.sender;
+    }
+
+    /// @notice Append `counter`
+    /// @return index The leaf index of the counter
+    function insert(bytes32 counter) external returns (uint32 index) {
+        if (msg.sender != POOL) revert NotPool();
+        return _insert(counter);
+    }
+}
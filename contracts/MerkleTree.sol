@@ -82,12 +82,45 @@ contract MerkleTree {
         uint32 _nextIndex = nextIndex;
         if (_nextIndex >= uint32(2) ** levels) revert MerkleTreeFull();
 
-        uint32 currentIndex = _nextIndex;
-        bytes32 currentLevelHash = leaf;
+        _pushRoot(_hashUp(0, _nextIndex, leaf));
+
+        // Advance leaf pointer
+        nextIndex = _nextIndex + 1;
+        return _nextIndex;
+    }
+
+    /// @notice Insert two leaves as one batch, recording a single new root.
+    ///         When the left leaf lands on an even index the pair is hashed
+    ///         together at level 0 first and only one path is walked.
+    /// @return index The leaf index of `leftLeaf`; `rightLeaf` is at index + 1
+    function _insertPair(bytes32 leftLeaf, bytes32 rightLeaf) internal returns (uint32 index) {
+        uint32 _nextIndex = nextIndex;
+        if (uint256(_nextIndex) + 2 > uint256(2) ** levels) revert MerkleTreeFull();
+
+        bytes32 root;
+        if (_nextIndex % 2 == 0) {
+            filledSubtrees[0] = leftLeaf;
+            root = _hashUp(1, _nextIndex / 2, _hashPair(leftLeaf, rightLeaf));
+        } else {
+            // Pair straddles two level-1 nodes: walk each leaf separately
+            _hashUp(0, _nextIndex, leftLeaf);
+            root = _hashUp(0, _nextIndex + 1, rightLeaf);
+        }
+        _pushRoot(root);
+
+        nextIndex = _nextIndex + 2;
+        return _nextIndex;
+    }
+
+    /// @notice Hash `node` (at position `index` of `level`) up to the root,
+    ///         updating filled subtrees along the way
+    function _hashUp(uint32 level, uint32 index, bytes32 node) internal returns (bytes32) {
+        uint32 currentIndex = index;
+        bytes32 currentLevelHash = node;
         bytes32 left;
         bytes32 right;
 
-        for (uint32 i = 0; i < levels; i++) {
+        for (uint32 i = level; i < levels; i++) {
             if (currentIndex % 2 == 0) {
                 // We're the left child — pair with the zero value on the right
                 left = currentLevelHash;
@@ -103,15 +136,14 @@ contract MerkleTree {
             currentLevelHash = _hashPair(left, right);
             currentIndex /= 2;
         }
+        return currentLevelHash;
+    }
 
-        // Update root in circular buffer
+    /// @notice Update root in circular buffer
+    function _pushRoot(bytes32 root) internal {
         uint32 newRootIndex = (currentRootIndex + 1) % ROOT_HISTORY_SIZE;
         currentRootIndex = newRootIndex;
-        roots[newRootIndex] = currentLevelHash;
-
-        // Advance leaf pointer
-        nextIndex = _nextIndex + 1;
-        return _nextIndex;
+        roots[newRootIndex] = root;
     }
 
     /// @notice Hash two children to get parent node
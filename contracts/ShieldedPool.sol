@@ -4,6 +4,7 @@ pragma solidity ^0.8.20;
 import {IERC20} from "./interfaces/IERC20.sol";
 import {ISP1Verifier} from "./interfaces/ISP1Verifier.sol";
 import {MerkleTree} from "./MerkleTree.sol";
+import {CounterTree} from "./CounterTree.sol";
 
 /// @title ShieldedPool
 /// @notice Private USDT payment pool on Plasma using SP1 ZK proofs.
@@ -14,15 +15,44 @@ import {MerkleTree} from "./MerkleTree.sol";
 /// @dev Note structure (off-chain):
 ///      note = {amount, pubkey, blinding}
 ///      commitment = keccak256(amount, pubkey, blinding)
-///      nullifier  = keccak256(commitment, spending_key)
+///      nullifier  = keccak256(commitment, nullifier_key)
 ///
 ///      Three operations, each verified by a separate SP1 program:
 ///      1. Deposit:  public amount in, commitment inserted into Merkle tree
+///                   (optionally with a proof the commitment opens to the amount)
 ///      2. Transfer: consume input note(s), create output note(s), prove in ZK
 ///      3. Withdraw: consume note, release public tokens
 ///
 ///      Selective disclosure: each note optionally stores an encrypted blob
 ///      on-chain, decryptable by the recipient (and anyone with their viewing key).
+///
+///      Cancellable payments: a note can be locked to a recipient and a sender.
+///      The sender may reclaim it until a deadline; the circuits commit that
+///      deadline (0 when no input is reclaimed) and the pool enforces it.
+///
+///      Association sets: a transfer or withdrawal may prove its inputs
+///      descend from deposits an association-set provider approved, and
+///      commits that set's root (0 when it did not). The pool accepts both
+///      and emits AssociatedSpend so providers can track the outputs.
+///
+///      Pool binding: every spend proof commits poolBinding(), a hash of
+///      the chain id and this pool's address, so a proof made for one
+///      deployment is rejected by every other one sharing its tree state.
+///
+///      Proven payloads: a transfer may prove its encrypted outputs open to
+///      its output notes, committing a hash of both (0 when it did not). The
+///      pool then only accepts the transfer with exactly those payloads.
+///
+///      Withdraw-and-call: a withdrawal may commit a call target and the hash
+///      of its calldata (0 when it did not). Only withdrawAndCall() accepts
+///      it, and makes exactly that call after paying out, in the same
+///      transaction.
+///
+///      Wider spends: join-split (4-in-4-out), batch withdraw (up to 8 notes
+///      to one address), consolidate (up to 8 notes of one key into one),
+///      payout (2 notes to up to 8 recipients) and policy spend (a payment
+///      under a daily limit) each have their own circuit and entry point.
+///      A zero verification key disables that entry point.
 
 contract ShieldedPool is MerkleTree {
     // =========================================================================
@@ -33,12 +63,42 @@ contract ShieldedPool is MerkleTree {
     bytes32 public immutable TRANSFER_VKEY;
     bytes32 public immutable WITHDRAW_VKEY;
 
+    /// @notice SP1 verification key for the deposit circuit. Zero: deposits
+    ///         take no proof. Otherwise every deposit must prove its
+    ///         commitment opens to the deposited amount.
+    bytes32 public immutable DEPOSIT_VKEY;
+
+    /// @notice SP1 verification keys for the wider spend circuits. Zero:
+    ///         that entry point is disabled.
+    bytes32 public immutable JOIN_SPLIT_VKEY;
+    bytes32 public immutable BATCH_WITHDRAW_VKEY;
+    bytes32 public immutable CONSOLIDATE_VKEY;
+    bytes32 public immutable PAYOUT_VKEY;
+    bytes32 public immutable POLICY_SPEND_VKEY;
+
+    /// @notice Counters of policy spends, appended only by this pool
+    CounterTree public immutable COUNTER_TREE;
+
     /// @notice The token used in this pool (USDT on Plasma)
     IERC20 public immutable TOKEN;
 
     /// @notice SP1 Groth16 verifier contract (deployed by Succinct)
     ISP1Verifier public immutable VERIFIER;
 
+    // =========================================================================
+    //                               TYPES
+    // =========================================================================
+
+    /// @notice Verification keys of the wider spend circuits, passed as one
+    ///         constructor argument
+    struct SpendVkeys {
+        bytes32 joinSplit;
+        bytes32 batchWithdraw;
+        bytes32 consolidate;
+        bytes32 payout;
+        bytes32 policySpend;
+    }
+
     // =========================================================================
     //                               STATE
     // =========================================================================
@@ -82,6 +142,57 @@ contract ShieldedPool is MerkleTree {
         uint256 timestamp
     );
 
+    /// @notice Emitted on a join-split. Dummy inputs and outputs are
+    ///         included and look like real ones.
+    event JoinSplit(
+        bytes32[4] nullifiers,
+        bytes32[4] newCommitments,
+        uint256 timestamp
+    );
+
+    /// @notice Emitted on a batch withdrawal. Unused nullifier slots are zero.
+    event BatchWithdrawal(
+        bytes32[8] nullifiers,
+        address indexed recipient,
+        uint256 amount,
+        uint256 timestamp
+    );
+
+    /// @notice Emitted on a consolidation. Unused nullifier slots are zero.
+    event Consolidation(
+        bytes32[8] nullifiers,
+        bytes32 indexed newCommitment,
+        uint256 timestamp
+    );
+
+    /// @notice Emitted on a payout. Dummy inputs and outputs are included.
+    event Payout(
+        bytes32[2] nullifiers,
+        bytes32[8] newCommitments,
+        uint256 timestamp
+    );
+
+    /// @notice Emitted on a policy spend. `newCounter` was appended to the
+    ///         counter tree at `counterIndex`.
+    event PolicySpend(
+        bytes32 indexed nullifier,
+        bytes32 indexed counterNullifier,
+        bytes32 paymentCommitment,
+        bytes32 changeCommitment,
+        bytes32 newCounter,
+        uint32 counterIndex,
+        uint256 timestamp
+    );
+
+    /// @notice Emitted for a transfer or withdrawal proven against an
+    ///         association set. Its provider adds the new commitments (zero
+    ///         when absent) to the set if the root is one of its own.
+    event AssociatedSpend(
+        bytes32 indexed associationRoot,
+        bytes32 commitment1,
+        bytes32 commitment2
+    );
+
     /// @notice Emitted when encrypted note data is stored (for viewing key holders)
     event EncryptedNote(
         bytes32 indexed commitment,
@@ -98,6 +209,17 @@ contract ShieldedPool is MerkleTree {
     error InvalidDepositAmount();
     error TransferFailed();
     error ZeroAddress();
+    error ReclaimWindowClosed();
+    error DepositProofRequired();
+    error DepositProofsDisabled();
+    error WrongPool();
+    error WithdrawalExpired();
+    error EncryptedOutputMismatch();
+    error WrongCall();
+    error CallFailed();
+    error WrongTreeDepth();
+    error CircuitDisabled();
+    error WrongEpoch();
 
     // =========================================================================
     //                            CONSTRUCTOR
@@ -107,13 +229,20 @@ contract ShieldedPool is MerkleTree {
     /// @param _verifier     SP1 Groth16 verifier contract address
     /// @param _transferVkey SP1 verification key for the transfer circuit
     /// @param _withdrawVkey SP1 verification key for the withdraw circuit
-    /// @param _treeLevels   Merkle tree depth (e.g., 20 → supports ~1M notes)
+    /// @param _depositVkey  SP1 verification key for the deposit circuit, or
+    ///                      zero to take deposits without proofs
+    /// @param _treeLevels   Merkle tree depth (e.g., 20 → supports ~1M notes),
+    ///                      also used for the counter tree
+    /// @param _spendVkeys   SP1 verification keys for the wider spend
+    ///                      circuits; zero disables a circuit
     constructor(
         address _token,
         address _verifier,
         bytes32 _transferVkey,
         bytes32 _withdrawVkey,
-        uint32 _treeLevels
+        bytes32 _depositVkey,
+        uint32 _treeLevels,
+        SpendVkeys memory _spendVkeys
     ) MerkleTree(_treeLevels) {
         if (_token == address(0) || _verifier == address(0)) revert ZeroAddress();
 
@@ -121,7 +250,13 @@ contract ShieldedPool is MerkleTree {
         VERIFIER = ISP1Verifier(_verifier);
         TRANSFER_VKEY = _transferVkey;
         WITHDRAW_VKEY = _withdrawVkey;
-
+        DEPOSIT_VKEY = _depositVkey;
+        JOIN_SPLIT_VKEY = _spendVkeys.joinSplit;
+        BATCH_WITHDRAW_VKEY = _spendVkeys.batchWithdraw;
+        CONSOLIDATE_VKEY = _spendVkeys.consolidate;
+        PAYOUT_VKEY = _spendVkeys.payout;
+        POLICY_SPEND_VKEY = _spendVkeys.policySpend;
+        COUNTER_TREE = new CounterTree(_treeLevels);
     }
 
     // =========================================================================
@@ -131,6 +266,7 @@ contract ShieldedPool is MerkleTree {
     /// @notice Deposit tokens into the shielded pool.
     ///         No ZK proof needed — this is a public action.
     ///         The commitment hides the note details.
+    ///         Reverts when the pool requires deposit proofs (DEPOSIT_VKEY set).
     ///
     /// @param commitment     keccak256 hash of (amount, pubkey, blinding)
     /// @param amount         Token amount to deposit (public)
@@ -141,6 +277,36 @@ contract ShieldedPool is MerkleTree {
         uint256 amount,
         bytes calldata encryptedData
     ) external {
+        if (DEPOSIT_VKEY != bytes32(0)) revert DepositProofRequired();
+        _deposit(commitment, amount, encryptedData);
+    }
+
+    /// @notice Deposit tokens with a proof that the commitment opens to the
+    ///         deposited amount, so no note can claim more than was paid in.
+    ///         The proof reveals nothing else about the note.
+    ///
+    /// @param proof          SP1 Groth16 proof bytes
+    /// @param publicValues   ABI-encoded public inputs:
+    ///                       (bytes32 commitment, uint256 amount)
+    /// @param encryptedData  Optional: note details encrypted to recipient's
+    ///                       viewing key. Pass empty bytes if not using.
+    function depositWithProof(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes calldata encryptedData
+    ) external {
+        if (DEPOSIT_VKEY == bytes32(0)) revert DepositProofsDisabled();
+        (bytes32 commitment, uint256 amount) = abi.decode(publicValues, (bytes32, uint256));
+        VERIFIER.verifyProof(DEPOSIT_VKEY, publicValues, proof);
+        _deposit(commitment, amount, encryptedData);
+    }
+
+    /// @dev Pull `amount` from the sender and insert `commitment`
+    function _deposit(
+        bytes32 commitment,
+        uint256 amount,
+        bytes calldata encryptedData
+    ) internal {
         if (amount == 0) revert InvalidDepositAmount();
 
         // Transfer tokens from sender to this contract
@@ -176,13 +342,28 @@ contract ShieldedPool is MerkleTree {
     ///         - Nullifiers are correctly derived
     ///         - sum(input amounts) == sum(output amounts)   [no inflation]
     ///         - Output commitments are well-formed
+    ///         - reclaimDeadline is the earliest deadline of any reclaimed input
+    ///         - associationRoot, if non-zero, is an association set both
+    ///           inputs descend from
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///         - treeLevels is the depth of the inputs' Merkle proofs; it must
+    ///           be this tree's, or a shorter proof could end at a known root
+    ///         - ciphertextsHash, if non-zero, is
+    ///           keccak256(abi.encode(keccak256(payload1), keccak256(payload2)))
+    ///           of payloads that open to the output notes; the posted
+    ///           encrypted outputs must be those payloads
     ///
     /// @param proof          SP1 Groth16 proof bytes
     /// @param publicValues   ABI-encoded public inputs:
     ///                       (bytes32 root, bytes32 nullifier1, bytes32 nullifier2,
-    ///                        bytes32 outCommitment1, bytes32 outCommitment2)
-    /// @param encryptedOutput1 Encrypted note data for first output (optional)
-    /// @param encryptedOutput2 Encrypted note data for second output (optional)
+    ///                        bytes32 outCommitment1, bytes32 outCommitment2,
+    ///                        uint256 reclaimDeadline, bytes32 associationRoot,
+    ///                        bytes32 poolBinding, bytes32 ciphertextsHash,
+    ///                        uint256 treeLevels)
+    /// @param encryptedOutput1 Encrypted note data for first output (optional
+    ///                         unless proven)
+    /// @param encryptedOutput2 Encrypted note data for second output (optional
+    ///                         unless proven)
     function privateTransfer(
         bytes calldata proof,
         bytes calldata publicValues,
@@ -190,29 +371,57 @@ contract ShieldedPool is MerkleTree {
         bytes calldata encryptedOutput2
     ) external {
         // Decode into memory struct to avoid stack-too-deep
-        bytes32[5] memory v = abi.decode(publicValues, (bytes32[5]));
+        bytes32[10] memory v = abi.decode(publicValues, (bytes32[10]));
         // v[0] = root, v[1] = nullifier1, v[2] = nullifier2,
-        // v[3] = outCommitment1, v[4] = outCommitment2
-
-        // 1. Check the Merkle root is known
+        // v[3] = outCommitment1, v[4] = outCommitment2, v[5] = reclaimDeadline,
+        // v[6] = associationRoot, v[7] = poolBinding, v[8] = ciphertextsHash,
+        // v[9] = treeLevels
+
+        // 1. Check the proof is for this pool, the Merkle root is known at
+        //    this tree's depth and any reclaim is still allowed
+        if (v[7] != poolBinding()) revert WrongPool();
+        if (uint256(v[9]) != levels) revert WrongTreeDepth();
         if (!isKnownRoot(v[0])) revert InvalidMerkleRoot();
+        _checkReclaimWindow(uint256(v[5]));
 
         // 2. Check nullifiers haven't been spent
         if (nullifiers[v[1]]) revert NullifierAlreadySpent();
-        if (nullifiers[v[2]]) revert NullifierAlreadySpent();
+        if (nullifiers[v[2]] || v[2] == v[1]) revert NullifierAlreadySpent();
 
-        // 3. Verify the SP1 proof
+        // 3. If the payloads are proven, the posted ones must be them
+        if (
+            v[8] != bytes32(0)
+                && v[8] != keccak256(abi.encode(keccak256(encryptedOutput1), keccak256(encryptedOutput2)))
+        ) revert EncryptedOutputMismatch();
+
+        // 4. Verify the SP1 proof
         VERIFIER.verifyProof(TRANSFER_VKEY, publicValues, proof);
 
-        // 4. Mark nullifiers as spent
+        // 5. Mark nullifiers as spent
         nullifiers[v[1]] = true;
         nullifiers[v[2]] = true;
 
-        // 5. Insert new commitments into the Merkle tree
+        // 6. Insert new commitments into the Merkle tree
         _insertAndStoreEncrypted(v[3], encryptedOutput1);
         _insertAndStoreEncrypted(v[4], encryptedOutput2);
 
         emit PrivateTransfer(v[1], v[2], v[3], v[4], block.timestamp);
+        _emitAssociatedSpend(v[6], v[3], v[4]);
+    }
+
+    /// @dev Reject reclaims of cancellable notes after their deadline (0 = no reclaim)
+    function _checkReclaimWindow(uint256 reclaimDeadline) internal view {
+        if (reclaimDeadline != 0 && block.timestamp > reclaimDeadline) revert ReclaimWindowClosed();
+    }
+
+    /// @dev Reject withdrawals submitted after the owner's deadline (0 = none)
+    function _checkWithdrawDeadline(uint256 deadline) internal view {
+        if (deadline != 0 && block.timestamp > deadline) revert WithdrawalExpired();
+    }
+
+    /// @dev Announce the outputs of a spend proven against an association set (0 = none)
+    function _emitAssociatedSpend(bytes32 associationRoot, bytes32 commitment1, bytes32 commitment2) internal {
+        if (associationRoot != bytes32(0)) emit AssociatedSpend(associationRoot, commitment1, commitment2);
     }
 
     /// @dev Helper to insert commitment and optionally store encrypted data
@@ -235,22 +444,74 @@ contract ShieldedPool is MerkleTree {
     ///         Consumes a note and sends tokens to the recipient.
     ///
     ///         The SP1 proof guarantees:
-    ///         - The input note exists in the Merkle tree
+    ///         - The input note exists in the Merkle tree, by a proof of
+    ///           treeLevels steps; it must be this tree's depth
     ///         - The caller knows the secret key for the note
     ///         - Nullifier is correctly derived
     ///         - The claimed amount matches the note's amount
     ///         - The recipient address is committed in the proof (prevents front-running)
+    ///         - reclaimDeadline is the note's deadline if the sender is reclaiming it
+    ///         - amount + change + fee equals the note's amount, and the relayer
+    ///           is committed, so anyone may submit it and the fee still goes
+    ///           to the relayer the owner chose
+    ///         - associationRoot, if non-zero, is an association set the note
+    ///           descends from
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///         - deadline is the one the owner chose; once it has passed the
+    ///           proof can no longer be submitted (0 = no deadline)
+    ///         - callBinding is zero; a withdraw-and-call proof must go
+    ///           through withdrawAndCall() so its call cannot be skipped
     ///
     /// @param proof          SP1 Groth16 proof bytes
     /// @param publicValues   ABI-encoded public inputs:
     ///                       (bytes32 root, bytes32 nullifier, address recipient,
-    ///                        uint256 amount, bytes32 changeCommitment)
+    ///                        uint256 amount, bytes32 changeCommitment,
+    ///                        uint256 reclaimDeadline, address relayer, uint256 fee,
+    ///                        bytes32 associationRoot, bytes32 poolBinding,
+    ///                        uint256 deadline, bytes32 callBinding,
+    ///                        uint256 treeLevels)
     /// @param encryptedChange Encrypted note data for change output (optional)
     function withdraw(
         bytes calldata proof,
         bytes calldata publicValues,
         bytes calldata encryptedChange
     ) external {
+        if (bytes32(publicValues[352:384]) != bytes32(0)) revert WrongCall();
+        _withdraw(proof, publicValues, encryptedChange);
+    }
+
+    /// @notice Withdraw as in withdraw(), then call `target` with `data`, the
+    ///         call the proof commits:
+    ///         callBinding = keccak256(abi.encode(target, keccak256(data))).
+    ///         The amount goes to the committed recipient first (usually the
+    ///         target itself, e.g. a DEX or bridge adapter), so the call acts
+    ///         on funds it already holds; it gets no allowance from the pool.
+    ///         If the call reverts, the whole withdrawal reverts.
+    ///
+    ///         The pool is msg.sender of the call, so it may not target the
+    ///         token or the pool itself.
+    function withdrawAndCall(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes calldata encryptedChange,
+        address target,
+        bytes calldata data
+    ) external {
+        if (target == address(TOKEN) || target == address(this)) revert WrongCall();
+        if (bytes32(publicValues[352:384]) != keccak256(abi.encode(target, keccak256(data)))) revert WrongCall();
+        _withdraw(proof, publicValues, encryptedChange);
+
+        (bool success, ) = target.call(data);
+        if (!success) revert CallFailed();
+    }
+
+    /// @dev Checks, proof verification and payout shared by withdraw() and
+    ///      withdrawAndCall(); the caller has checked the call binding
+    function _withdraw(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes calldata encryptedChange
+    ) internal {
         // Decode public values
         (
             bytes32 root,
@@ -260,10 +521,15 @@ contract ShieldedPool is MerkleTree {
             bytes32 changeCommitment
         ) = abi.decode(publicValues, (bytes32, bytes32, address, uint256, bytes32));
 
-        // 1. Validate
+        // 1. Validate (the deadlines and pool binding are read on their own to
+        //    avoid stack-too-deep)
+        if (bytes32(publicValues[288:320]) != poolBinding()) revert WrongPool();
+        _checkWithdrawDeadline(abi.decode(publicValues[320:352], (uint256)));
+        if (abi.decode(publicValues[384:416], (uint256)) != levels) revert WrongTreeDepth();
         if (!isKnownRoot(root)) revert InvalidMerkleRoot();
         if (nullifiers[nullifier]) revert NullifierAlreadySpent();
         if (recipient == address(0)) revert ZeroAddress();
+        _checkReclaimWindow(abi.decode(publicValues[160:], (uint256)));
 
         // 2. Verify the SP1 proof
         VERIFIER.verifyProof(WITHDRAW_VKEY, publicValues, proof);
@@ -282,17 +548,336 @@ contract ShieldedPool is MerkleTree {
             }
         }
 
-        // 5. Transfer tokens to recipient
+        // 5. Transfer tokens to recipient, and the fee to the relayer
         bool success = TOKEN.transfer(recipient, amount);
         if (!success) revert TransferFailed();
+        _payRelayer(publicValues[192:256]);
 
         emit Withdrawal(nullifier, recipient, amount, block.timestamp);
+        _emitAssociatedSpend(bytes32(publicValues[256:288]), changeCommitment, bytes32(0));
+    }
+
+    /// @dev Pay the relayer fee committed in a withdrawal's relayer slots
+    ///      (address relayer, uint256 fee); nothing when the fee is 0
+    function _payRelayer(bytes calldata relayerSlots) internal {
+        (address relayer, uint256 fee) = abi.decode(relayerSlots, (address, uint256));
+        if (fee == 0) return;
+        if (relayer == address(0)) revert ZeroAddress();
+        if (!TOKEN.transfer(relayer, fee)) revert TransferFailed();
+    }
+
+    // =========================================================================
+    //                             JOIN-SPLIT
+    // =========================================================================
+
+    /// @notice Execute a 4-in-4-out private transfer. Unused slots hold
+    ///         zero-amount dummy notes, whose nullifiers and commitments are
+    ///         recorded like real ones.
+    ///
+    ///         The SP1 proof guarantees:
+    ///         - Every input with a non-zero amount exists in the Merkle tree,
+    ///           by a proof of treeLevels steps; it must be this tree's depth
+    ///         - The sender may spend every input, and the nullifiers are
+    ///           correctly derived
+    ///         - sum(input amounts) == sum(output amounts)   [no inflation]
+    ///         - reclaimDeadline is the earliest deadline of any reclaimed input
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///
+    /// @param proof            SP1 Groth16 proof bytes
+    /// @param publicValues     ABI-encoded public inputs:
+    ///                         (bytes32 root, bytes32[4] nullifiers,
+    ///                          bytes32[4] outCommitments, uint256 reclaimDeadline,
+    ///                          uint256 treeLevels, bytes32 poolBinding)
+    /// @param encryptedOutputs Encrypted note data for each output (optional)
+    function joinSplit(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes[4] calldata encryptedOutputs
+    ) external {
+        if (JOIN_SPLIT_VKEY == bytes32(0)) revert CircuitDisabled();
+        bytes32[12] memory v = abi.decode(publicValues, (bytes32[12]));
+        // v[0] = root, v[1..4] = nullifiers, v[5..8] = outCommitments,
+        // v[9] = reclaimDeadline, v[10] = treeLevels, v[11] = poolBinding
+
+        // 1. Check the proof is for this pool, the Merkle root is known at
+        //    this tree's depth and any reclaim is still allowed
+        _checkSpendTree(v[11], v[0], uint256(v[10]));
+        _checkReclaimWindow(uint256(v[9]));
+
+        // 2. Verify the SP1 proof
+        VERIFIER.verifyProof(JOIN_SPLIT_VKEY, publicValues, proof);
+
+        // 3. Mark nullifiers as spent
+        _spendNullifiers(publicValues[32:160]);
+
+        // 4. Insert new commitments into the Merkle tree
+        for (uint256 i = 0; i < 4; i++) {
+            _insertAndStoreEncrypted(v[5 + i], encryptedOutputs[i]);
+        }
+
+        emit JoinSplit(
+            abi.decode(publicValues[32:160], (bytes32[4])),
+            abi.decode(publicValues[160:288], (bytes32[4])),
+            block.timestamp
+        );
+    }
+
+    // =========================================================================
+    //                           BATCH WITHDRAW
+    // =========================================================================
+
+    /// @notice Withdraw up to 8 notes to one public address in one proof.
+    ///         There is no change note, relayer fee or call.
+    ///
+    ///         The SP1 proof guarantees:
+    ///         - Every input with a non-zero amount exists in the Merkle tree,
+    ///           by a proof of treeLevels steps; it must be this tree's depth
+    ///         - The sender may spend every input, and the nullifiers are
+    ///           correctly derived (zero for an unused slot)
+    ///         - amount is the sum of the inputs
+    ///         - The recipient address is committed in the proof (prevents front-running)
+    ///         - reclaimDeadline is the earliest deadline of any reclaimed input
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///
+    /// @param proof          SP1 Groth16 proof bytes
+    /// @param publicValues   ABI-encoded public inputs:
+    ///                       (bytes32 root, bytes32[8] nullifiers, address recipient,
+    ///                        uint256 amount, uint256 reclaimDeadline,
+    ///                        uint256 treeLevels, bytes32 poolBinding)
+    function batchWithdraw(bytes calldata proof, bytes calldata publicValues) external {
+        if (BATCH_WITHDRAW_VKEY == bytes32(0)) revert CircuitDisabled();
+        bytes32[14] memory v = abi.decode(publicValues, (bytes32[14]));
+        // v[0] = root, v[1..8] = nullifiers, v[9] = recipient, v[10] = amount,
+        // v[11] = reclaimDeadline, v[12] = treeLevels, v[13] = poolBinding
+        address recipient = address(uint160(uint256(v[9])));
+
+        // 1. Validate
+        _checkSpendTree(v[13], v[0], uint256(v[12]));
+        if (recipient == address(0)) revert ZeroAddress();
+        _checkReclaimWindow(uint256(v[11]));
+
+        // 2. Verify the SP1 proof
+        VERIFIER.verifyProof(BATCH_WITHDRAW_VKEY, publicValues, proof);
+
+        // 3. Mark nullifiers as spent
+        _spendNullifiers(publicValues[32:288]);
+
+        // 4. Transfer tokens to recipient
+        if (!TOKEN.transfer(recipient, uint256(v[10]))) revert TransferFailed();
+
+        emit BatchWithdrawal(
+            abi.decode(publicValues[32:288], (bytes32[8])),
+            recipient,
+            uint256(v[10]),
+            block.timestamp
+        );
+    }
+
+    // =========================================================================
+    //                            CONSOLIDATE
+    // =========================================================================
+
+    /// @notice Merge up to 8 notes of one key into one note of the same key.
+    ///
+    ///         The SP1 proof guarantees:
+    ///         - Every input exists in the Merkle tree, by a proof of
+    ///           treeLevels steps; it must be this tree's depth
+    ///         - One spending key owns every input and the output, and the
+    ///           nullifiers are correctly derived (zero for an unused slot)
+    ///         - The output's amount is the sum of the inputs
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///
+    /// @param proof           SP1 Groth16 proof bytes
+    /// @param publicValues    ABI-encoded public inputs:
+    ///                        (bytes32 root, bytes32[8] nullifiers,
+    ///                         bytes32 outCommitment, uint256 treeLevels,
+    ///                         bytes32 poolBinding)
+    /// @param encryptedOutput Encrypted note data for the output (optional)
+    function consolidate(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes calldata encryptedOutput
+    ) external {
+        if (CONSOLIDATE_VKEY == bytes32(0)) revert CircuitDisabled();
+        bytes32[12] memory v = abi.decode(publicValues, (bytes32[12]));
+        // v[0] = root, v[1..8] = nullifiers, v[9] = outCommitment,
+        // v[10] = treeLevels, v[11] = poolBinding
+
+        // 1. Check the proof is for this pool and the Merkle root is known at
+        //    this tree's depth
+        _checkSpendTree(v[11], v[0], uint256(v[10]));
+
+        // 2. Verify the SP1 proof
+        VERIFIER.verifyProof(CONSOLIDATE_VKEY, publicValues, proof);
+
+        // 3. Mark nullifiers as spent
+        _spendNullifiers(publicValues[32:288]);
+
+        // 4. Insert the merged note into the Merkle tree
+        _insertAndStoreEncrypted(v[9], encryptedOutput);
+
+        emit Consolidation(abi.decode(publicValues[32:288], (bytes32[8])), v[9], block.timestamp);
+    }
+
+    // =========================================================================
+    //                               PAYOUT
+    // =========================================================================
+
+    /// @notice Pay up to 8 recipients out of up to 2 notes. Unused slots hold
+    ///         zero-amount dummy notes, recorded like real ones.
+    ///
+    ///         The SP1 proof guarantees:
+    ///         - Every input with a non-zero amount exists in the Merkle tree,
+    ///           by a proof of treeLevels steps; it must be this tree's depth
+    ///         - The sender may spend every input, and the nullifiers are
+    ///           correctly derived
+    ///         - sum(input amounts) == sum(output amounts)   [no inflation]
+    ///         - No two outputs share a pubkey
+    ///         - reclaimDeadline is the earliest deadline of any reclaimed input
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///
+    /// @param proof            SP1 Groth16 proof bytes
+    /// @param publicValues     ABI-encoded public inputs:
+    ///                         (bytes32 root, bytes32[2] nullifiers,
+    ///                          bytes32[8] outCommitments, uint256 reclaimDeadline,
+    ///                          uint256 treeLevels, bytes32 poolBinding)
+    /// @param encryptedOutputs Encrypted note data for each output (optional)
+    function payout(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes[8] calldata encryptedOutputs
+    ) external {
+        if (PAYOUT_VKEY == bytes32(0)) revert CircuitDisabled();
+        bytes32[14] memory v = abi.decode(publicValues, (bytes32[14]));
+        // v[0] = root, v[1..2] = nullifiers, v[3..10] = outCommitments,
+        // v[11] = reclaimDeadline, v[12] = treeLevels, v[13] = poolBinding
+
+        // 1. Check the proof is for this pool, the Merkle root is known at
+        //    this tree's depth and any reclaim is still allowed
+        _checkSpendTree(v[13], v[0], uint256(v[12]));
+        _checkReclaimWindow(uint256(v[11]));
+
+        // 2. Verify the SP1 proof
+        VERIFIER.verifyProof(PAYOUT_VKEY, publicValues, proof);
+
+        // 3. Mark nullifiers as spent
+        _spendNullifiers(publicValues[32:96]);
+
+        // 4. Insert new commitments into the Merkle tree
+        for (uint256 i = 0; i < 8; i++) {
+            _insertAndStoreEncrypted(v[3 + i], encryptedOutputs[i]);
+        }
+
+        emit Payout(
+            abi.decode(publicValues[32:96], (bytes32[2])),
+            abi.decode(publicValues[96:352], (bytes32[8])),
+            block.timestamp
+        );
+    }
+
+    // =========================================================================
+    //                            POLICY SPEND
+    // =========================================================================
+
+    /// @notice Pay out of a note locked to a spending-limit policy. The change
+    ///         stays under the policy, and the epoch's counter is replaced by
+    ///         one that adds the payment, appended to COUNTER_TREE.
+    ///
+    ///         The SP1 proof guarantees:
+    ///         - The note exists in the Merkle tree, by a proof of treeLevels
+    ///           steps; it must be this tree's depth
+    ///         - The spender's key and the policy open the note, and the
+    ///           nullifier is correctly derived
+    ///         - payment + change == note amount, and the change keeps the policy
+    ///         - counterNullifier is the nullifier of the epoch's counter, found
+    ///           under counterRoot by a proof of counterLevels steps, or of the
+    ///           epoch itself on its first spend (counterLevels = 0)
+    ///         - newCounter adds the payment and stays within the limit
+    ///         - epoch is the one the spend counts against; it must be today's
+    ///         - poolBinding is the one the prover chose; it must be this pool's
+    ///
+    /// @param proof            SP1 Groth16 proof bytes
+    /// @param publicValues     ABI-encoded public inputs:
+    ///                         (bytes32 root, bytes32 nullifier,
+    ///                          bytes32 paymentCommitment, bytes32 changeCommitment,
+    ///                          bytes32 counterRoot, bytes32 counterNullifier,
+    ///                          bytes32 newCounter, uint256 epoch,
+    ///                          uint256 treeLevels, uint256 counterLevels,
+    ///                          bytes32 poolBinding)
+    /// @param encryptedPayment Encrypted note data for the payment (optional)
+    /// @param encryptedChange  Encrypted note data for the change (optional)
+    function policySpend(
+        bytes calldata proof,
+        bytes calldata publicValues,
+        bytes calldata encryptedPayment,
+        bytes calldata encryptedChange
+    ) external {
+        if (POLICY_SPEND_VKEY == bytes32(0)) revert CircuitDisabled();
+        bytes32[11] memory v = abi.decode(publicValues, (bytes32[11]));
+        // v[0] = root, v[1] = nullifier, v[2] = paymentCommitment,
+        // v[3] = changeCommitment, v[4] = counterRoot, v[5] = counterNullifier,
+        // v[6] = newCounter, v[7] = epoch, v[8] = treeLevels,
+        // v[9] = counterLevels, v[10] = poolBinding
+
+        // 1. Check the proof is for this pool and today's epoch, and the
+        //    Merkle root is known at this tree's depth
+        _checkSpendTree(v[10], v[0], uint256(v[8]));
+        if (uint256(v[7]) != block.timestamp / 1 days) revert WrongEpoch();
+
+        // 2. Unless this is the epoch's first spend, the counter's root must
+        //    be known at the counter tree's depth
+        if (v[9] != bytes32(0)) {
+            if (uint256(v[9]) != COUNTER_TREE.levels()) revert WrongTreeDepth();
+            if (!COUNTER_TREE.isKnownRoot(v[4])) revert InvalidMerkleRoot();
+        }
+
+        // 3. Verify the SP1 proof
+        VERIFIER.verifyProof(POLICY_SPEND_VKEY, publicValues, proof);
+
+        // 4. Mark the note's and the counter's nullifiers as spent
+        _spendNullifiers(publicValues[32:64]);
+        _spendNullifiers(publicValues[160:192]);
+
+        // 5. Insert both notes, and append the new counter
+        _insertAndStoreEncrypted(v[2], encryptedPayment);
+        _insertAndStoreEncrypted(v[3], encryptedChange);
+        uint32 counterIndex = COUNTER_TREE.insert(v[6]);
+
+        emit PolicySpend(v[1], v[5], v[2], v[3], v[6], counterIndex, block.timestamp);
+    }
+
+    /// @dev Reject a spend proven for another pool, or against a root this
+    ///      tree does not know or at another depth than this tree's
+    function _checkSpendTree(bytes32 binding, bytes32 root, uint256 treeLevels) internal view {
+        if (binding != poolBinding()) revert WrongPool();
+        if (treeLevels != levels) revert WrongTreeDepth();
+        if (!isKnownRoot(root)) revert InvalidMerkleRoot();
+    }
+
+    /// @dev Mark the nullifiers in `slots` (consecutive bytes32 words, zero
+    ///      for an unused input) as spent. Reverts if one already is, which
+    ///      also rejects a nullifier repeated within the spend.
+    function _spendNullifiers(bytes calldata slots) internal {
+        for (uint256 i = 0; i < slots.length; i += 32) {
+            bytes32 nullifier = bytes32(slots[i:i + 32]);
+            if (nullifier == bytes32(0)) continue;
+            if (nullifiers[nullifier]) revert NullifierAlreadySpent();
+            nullifiers[nullifier] = true;
+        }
     }
 
     // =========================================================================
     //                          VIEW FUNCTIONS
     // =========================================================================
 
+    /// @notice The binding every spend proof must commit to be accepted
+    ///         here: keccak256(abi.encode(chainid, address(this))).
+    ///         Computed per call, so after a chain split the fork's pool
+    ///         rejects proofs made for the original.
+    function poolBinding() public view returns (bytes32) {
+        return keccak256(abi.encode(block.chainid, address(this)));
+    }
+
     /// @notice Check if a nullifier has been spent
     function isSpent(bytes32 nullifier) external view returns (bool) {
         return nullifiers[nullifier];
@@ -19,6 +19,9 @@ import {ShieldedPool} from "../contracts/ShieldedPool.sol";
 ///
 /// Optional:
 ///   TREE_LEVELS (default 20)
+///   DEPOSIT_VKEY (default zero: deposits take no proof)
+///   JOIN_SPLIT_VKEY, BATCH_WITHDRAW_VKEY, CONSOLIDATE_VKEY, PAYOUT_VKEY,
+///   POLICY_SPEND_VKEY (default zero: that entry point is disabled)
 contract DeployShieldedPool is Script {
     function run() external {
         uint256 deployerKey = vm.envUint("PRIVATE_KEY");
@@ -26,7 +29,15 @@ contract DeployShieldedPool is Script {
         address verifier = vm.envAddress("VERIFIER_ADDRESS");
         bytes32 transferVkey = vm.envBytes32("TRANSFER_VKEY");
         bytes32 withdrawVkey = vm.envBytes32("WITHDRAW_VKEY");
+        bytes32 depositVkey = vm.envOr("DEPOSIT_VKEY", bytes32(0));
         uint32 treeLevels = uint32(vm.envOr("TREE_LEVELS", uint256(20)));
+        ShieldedPool.SpendVkeys memory spendVkeys = ShieldedPool.SpendVkeys({
+            joinSplit: vm.envOr("JOIN_SPLIT_VKEY", bytes32(0)),
+            batchWithdraw: vm.envOr("BATCH_WITHDRAW_VKEY", bytes32(0)),
+            consolidate: vm.envOr("CONSOLIDATE_VKEY", bytes32(0)),
+            payout: vm.envOr("PAYOUT_VKEY", bytes32(0)),
+            policySpend: vm.envOr("POLICY_SPEND_VKEY", bytes32(0))
+        });
 
         console.log("Deploying ShieldedPool...");
         console.log("  Token:         ", token);
@@ -35,7 +46,15 @@ contract DeployShieldedPool is Script {
         console.logBytes32(transferVkey);
         console.log("  Withdraw VKey: ");
         console.logBytes32(withdrawVkey);
+        console.log("  Deposit VKey:  ");
+        console.logBytes32(depositVkey);
         console.log("  Tree Levels:   ", treeLevels);
+        console.log("  Join-Split / Batch-Withdraw / Consolidate / Payout / Policy-Spend VKeys:");
+        console.logBytes32(spendVkeys.joinSplit);
+        console.logBytes32(spendVkeys.batchWithdraw);
+        console.logBytes32(spendVkeys.consolidate);
+        console.logBytes32(spendVkeys.payout);
+        console.logBytes32(spendVkeys.policySpend);
 
         vm.startBroadcast(deployerKey);
 
@@ -44,7 +63,9 @@ contract DeployShieldedPool is Script {
             verifier,
             transferVkey,
             withdrawVkey,
-            treeLevels
+            depositVkey,
+            treeLevels,
+            spendVkeys
         );
 
         vm.stopBroadcast();
@@ -11,7 +11,13 @@ export {
   keccak256,
   computeCommitment,
   computeNullifier,
+  nullifierFromKey,
+  feeNote,
+  deriveNullifierKey,
   derivePubkey,
+  legacyPubkey,
+  dummyNote,
+  DUMMY_SPENDING_KEY,
   hashPair,
   hexToBytes,
   bytesToHex,
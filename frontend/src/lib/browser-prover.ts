@@ -2,13 +2,27 @@
  * Browser-compatible prover that calls the proxy server.
  *
  * Replaces the Node.js subprocess-based Prover with HTTP calls
- * to the proof generation proxy.
+ * to the proof generation proxy. The proxy gets the inputs' proving keys
+ * and the spend signatures made here, never a spending key, so legacy notes
+ * (whose key is the spending key) are refused.
  */
 
 // import { config } from '../config'; // config is no longer used directly for proxyUrl
 import { getProxyUrl } from './settings';
 import { bytesToHex } from './browser-crypto';
-import type { MerkleProofStep } from '../../../client/src/types.js';
+import {
+  authorizeTransferInputs,
+  authorizeWithdrawInput,
+  signTransfer,
+  signWithdraw,
+} from '../../../client/src/authorization.js';
+import type {
+  FeeQuote,
+  MerkleProofStep,
+  OutputEncryption,
+  SpenderKey,
+  SpendSignature,
+} from '../../../client/src/types.js';
 
 export interface ProofResult {
   proof: string;   // hex-encoded
@@ -25,11 +39,18 @@ interface TransferInputNote {
 }
 
 export interface BrowserTransferRequest {
-  inputNotes: TransferInputNote[];
+  /** Two notes; a dummy (see browser-crypto dummyNote) fills an unused slot */
+  inputNotes: { amount: bigint; pubkey: Uint8Array; blinding: Uint8Array }[];
   inputSpendingKeys: Uint8Array[];
   inputMerkleProofs: MerkleProofStep[][];
   outputNotes: { amount: bigint; pubkey: Uint8Array; blinding: Uint8Array }[];
   root: Uint8Array;
+  /** The pool's poolBinding(); the pool rejects proofs bound to another */
+  poolBinding: Uint8Array;
+  /** One per output to prove the posted payloads (see encryptNoteWith) */
+  outputEncryption?: OutputEncryption[];
+  /** Quote the second output pays (required by proxies that charge fees) */
+  feeQuote?: FeeQuote;
 }
 
 export interface BrowserWithdrawRequest {
@@ -40,6 +61,8 @@ export interface BrowserWithdrawRequest {
   recipient: string;
   withdrawAmount: bigint;
   changeNote?: { amount: bigint; pubkey: Uint8Array; blinding: Uint8Array } | null;
+  /** The pool's poolBinding(); the pool rejects proofs bound to another */
+  poolBinding: Uint8Array;
 }
 
 function serializeMerkleProof(proof: MerkleProofStep[]): Array<{ is_left: boolean; sibling: number[] }> {
@@ -49,6 +72,22 @@ function serializeMerkleProof(proof: MerkleProofStep[]): Array<{ is_left: boolea
   }));
 }
 
+/** A proving key as the proxy takes it; a legacy key must stay on this device. */
+function serializeProvingKey(key: SpenderKey) {
+  if ('legacy' in key) {
+    throw new Error('This note predates spend-authorization keys: spend it with the local prover');
+  }
+  return { Proving: { auth_key: Array.from(key.authKey), nullifier_key: Array.from(key.nullifierKey) } };
+}
+
+function serializeSignatures(signatures: SpendSignature[]) {
+  return signatures.map((sig) => ({
+    auth_key: Array.from(sig.authKey),
+    r: Array.from(sig.r),
+    s: Array.from(sig.s),
+  }));
+}
+
 function hexToBytes(hex: string): Uint8Array {
   const clean = hex.startsWith('0x') ? hex.slice(2) : hex;
   const bytes = new Uint8Array(clean.length / 2);
@@ -58,17 +97,31 @@ function hexToBytes(hex: string): Uint8Array {
   return bytes;
 }
 
+/**
+ * Ask the proxy for a fee quote. Returns null if the proxy does not charge fees.
+ */
+export async function fetchFeeQuote(): Promise<FeeQuote | null> {
+  const res = await fetch(`${getProxyUrl()}/quote`);
+  if (res.status === 404) return null;
+  if (!res.ok) {
+    const err = await res.json().catch(() => ({ error: res.statusText }));
+    throw new Error(`Fee quote failed: ${err.error || res.statusText}`);
+  }
+  return res.json();
+}
+
 /**
  * Generate a transfer proof via the proxy server.
  */
 export async function proveTransfer(request: BrowserTransferRequest): Promise<ProofResult> {
+  const auths = authorizeTransferInputs(request);
   const body = {
     input_notes: request.inputNotes.map((n) => ({
       amount: Number(n.amount),
       pubkey: Array.from(n.pubkey),
       blinding: Array.from(n.blinding),
     })),
-    spending_keys: request.inputSpendingKeys.map((k) => Array.from(k)),
+    spender_keys: auths.map((a) => serializeProvingKey(a.spender)),
     merkle_proofs: request.inputMerkleProofs.map(serializeMerkleProof),
     output_notes: request.outputNotes.map((n) => ({
       amount: Number(n.amount),
@@ -76,12 +129,23 @@ export async function proveTransfer(request: BrowserTransferRequest): Promise<Pr
       blinding: Array.from(n.blinding),
     })),
     root: Array.from(request.root),
+    pool_binding: Array.from(request.poolBinding),
+    output_encryption: request.outputEncryption
+      ? request.outputEncryption.map((e) => ({
+        viewing_pubkey: Array.from(e.viewingPubkey),
+        ephemeral_secret: Array.from(e.ephemeralSecret),
+        nonce: Array.from(e.nonce),
+      }))
+      : null,
+    spend_signatures: serializeSignatures(signTransfer(request)),
   };
 
   const proxyUrl = getProxyUrl();
+  const headers: Record<string, string> = { 'Content-Type': 'application/json' };
+  if (request.feeQuote) headers['X-Fee-Quote'] = request.feeQuote.quoteId;
   const res = await fetch(`${proxyUrl}/prove/transfer`, {
     method: 'POST',
-    headers: { 'Content-Type': 'application/json' },
+    headers,
     body: JSON.stringify(body),
   });
 
@@ -97,13 +161,14 @@ export async function proveTransfer(request: BrowserTransferRequest): Promise<Pr
  * Generate a withdraw proof via the proxy server.
  */
 export async function proveWithdraw(request: BrowserWithdrawRequest): Promise<ProofResult> {
+  const withdrawal = { ...request, changeNote: request.changeNote ?? undefined };
   const body = {
     input_note: {
       amount: Number(request.inputNote.amount),
       pubkey: Array.from(request.inputNote.pubkey),
       blinding: Array.from(request.inputNote.blinding),
     },
-    spending_key: Array.from(request.spendingKey),
+    spender_key: serializeProvingKey(authorizeWithdrawInput(withdrawal).spender),
     merkle_proof: serializeMerkleProof(request.merkleProof),
     root: Array.from(request.root),
     recipient: Array.from(hexToBytes(request.recipient)),
@@ -115,6 +180,8 @@ export async function proveWithdraw(request: BrowserWithdrawRequest): Promise<Pr
         blinding: Array.from(request.changeNote.blinding),
       }
       : null,
+    pool_binding: Array.from(request.poolBinding),
+    spend_signatures: serializeSignatures(signWithdraw(withdrawal)),
   };
 
   const proxyUrl = getProxyUrl();
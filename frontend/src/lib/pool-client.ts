@@ -12,13 +12,23 @@ import { Contract, type Signer, type TransactionReceipt, type BrowserProvider }
 import {
   computeCommitment,
   computeNullifier,
+  nullifierFromKey,
   derivePubkey,
+  legacyPubkey,
+  dummyNote,
+  DUMMY_SPENDING_KEY,
   bytesToHex,
   hexToBytes,
   randomBytes,
 } from './browser-crypto';
 import { ClientMerkleTree, verifyMerkleProof } from '../../../client/src/merkle.js';
-import { encryptNote, decryptNote, deriveViewingKeypair } from '../../../client/src/encryption.js';
+import {
+  encryptNote,
+  encryptNoteWith,
+  newOutputEncryption,
+  decryptNote,
+  deriveViewingKeypair,
+} from '../../../client/src/encryption.js';
 import { proveTransfer, proveWithdraw } from './browser-prover';
 import { config } from '../config';
 import type { Note, NoteWithIndex, MerkleProofStep } from '../../../client/src/types.js';
@@ -31,6 +41,7 @@ const SHIELDED_POOL_ABI = [
   'function withdraw(bytes proof, bytes publicValues, bytes encryptedChange) external',
   'function getLastRoot() view returns (bytes32)',
   'function isKnownRoot(bytes32 root) view returns (bool)',
+  'function poolBinding() view returns (bytes32)',
   'function isSpent(bytes32 nullifier) view returns (bool)',
   'function getLeafCount() view returns (uint32)',
   'event Deposit(bytes32 indexed commitment, uint256 amount, uint32 leafIndex, uint256 timestamp)',
@@ -87,7 +98,12 @@ export class BrowserShieldedWallet {
 
   addNote(note: Note, leafIndex: number): NoteWithIndex {
     const commitment = computeCommitment(note.amount, note.pubkey, note.blinding);
-    const nullifier = computeNullifier(commitment, this.spendingKey);
+    // A note under the legacy pubkey has the legacy nullifier; the proxy
+    // cannot spend it (see browser-prover), but the wallet tracks it
+    const legacy = bytesToHex(note.pubkey) === bytesToHex(legacyPubkey(this.spendingKey));
+    const nullifier = legacy
+      ? nullifierFromKey(commitment, this.spendingKey)
+      : computeNullifier(commitment, this.spendingKey);
     const noteWithIndex: NoteWithIndex = {
       ...note,
       commitment,
@@ -214,6 +230,8 @@ export class BrowserPoolClient {
   tree: ClientMerkleTree;
   wallet: BrowserShieldedWallet;
   private signer: Signer;
+  /** The pool's poolBinding(), fetched once */
+  private poolBinding?: Uint8Array;
 
   constructor(wallet: BrowserShieldedWallet, signer: Signer) {
     this.pool = new Contract(config.poolAddress, SHIELDED_POOL_ABI, signer);
@@ -223,6 +241,14 @@ export class BrowserPoolClient {
     this.signer = signer;
   }
 
+  /** The binding proofs for this pool must commit (chain id and pool address). */
+  private async getPoolBinding(): Promise<Uint8Array> {
+    if (!this.poolBinding) {
+      this.poolBinding = hexToBytes(await this.pool.poolBinding());
+    }
+    return this.poolBinding;
+  }
+
   /**
    * Sync local Merkle tree from on-chain events AND scan for incoming notes.
    *
@@ -487,7 +513,7 @@ export class BrowserPoolClient {
   /**
    * Private transfer within the pool.
    *
-   * 2-in-2-out circuit: requires exactly 2 input notes.
+   * 2-in-2-out circuit: spends 1 or 2 notes, padding a single one with a dummy input.
    * The recipient must provide their viewing public key so we can encrypt the note for them.
    */
   async privateTransfer(
@@ -501,14 +527,8 @@ export class BrowserPoolClient {
       onProgress?.({ stage: 'approving', message: 'Syncing Merkle tree...' });
       await this.sync();
 
-      // 1. Select input notes (need exactly 2 for 2-in-2-out circuit)
+      // 1. Select input notes (1 or 2; a dummy fills the second slot)
       const { inputs, change } = this.wallet.selectNotes(amount);
-      if (inputs.length < 2) {
-        throw new Error(
-          'Private transfer requires at least 2 shielded notes. ' +
-          'You can: (1) make two separate deposits, or (2) use Withdraw to move funds out with a single note.'
-        );
-      }
 
       // 2. Create output notes
       const recipientNote: Note = {
@@ -520,8 +540,7 @@ export class BrowserPoolClient {
 
       // 3. Get Merkle proofs + local verification
       const root = this.tree.getRoot();
-      const proof0 = this.tree.getProof(inputs[0].leafIndex);
-      const proof1 = this.tree.getProof(inputs[1].leafIndex);
+      const proofs = inputs.map((n) => this.tree.getProof(n.leafIndex));
 
       // Verify locally before sending to prover
       for (let i = 0; i < inputs.length; i++) {
@@ -536,19 +555,35 @@ export class BrowserPoolClient {
 
       // 4. Generate proof via proxy
       onProgress?.({ stage: 'proving', message: 'Generating ZK proof... (this may take a few minutes)' });
+      // A dummy second input is in no tree, so it has no Merkle proof
+      const inputNotes: Note[] = [...inputs];
+      const keys = inputs.map(() => this.wallet.getSpendingKey());
+      if (inputs.length === 1) {
+        inputNotes.push(dummyNote(randomBytes(32)));
+        keys.push(DUMMY_SPENDING_KEY);
+        proofs.push([]);
+      }
+      //    The proof commits both encrypted outputs, so the pool only accepts
+      //    payloads the recipient can open:
+      //    - enc1: recipient's note encrypted with their viewing pubkey (so they can scan and find it)
+      //    - enc2: our change note encrypted with our viewing pubkey
+      const encryptions = [
+        newOutputEncryption(recipientViewingPubkey),
+        newOutputEncryption(this.wallet.getViewingPublicKey()),
+      ];
       const proofResult = await proveTransfer({
-        inputNotes: inputs,
-        inputSpendingKeys: [this.wallet.getSpendingKey(), this.wallet.getSpendingKey()],
-        inputMerkleProofs: [proof0, proof1],
+        inputNotes,
+        inputSpendingKeys: keys,
+        inputMerkleProofs: proofs,
         outputNotes: [recipientNote, changeNote],
         root,
+        poolBinding: await this.getPoolBinding(),
+        outputEncryption: encryptions,
       });
 
-      // 5. Encrypt output notes
-      //    - enc1: recipient's note encrypted with their viewing pubkey (so they can scan and find it)
-      //    - enc2: our change note encrypted with our viewing pubkey
-      const enc1 = encryptNote(recipientNote, recipientViewingPubkey);
-      const enc2 = encryptNote(changeNote, this.wallet.getViewingPublicKey());
+      // 5. Encrypt output notes with the proven randomness
+      const enc1 = encryptNoteWith(recipientNote, encryptions[0]);
+      const enc2 = encryptNoteWith(changeNote, encryptions[1]);
 
       // 6. Submit on-chain
       onProgress?.({ stage: 'submitting', message: 'Submitting transaction...' });
@@ -637,6 +672,7 @@ export class BrowserPoolClient {
         recipient,
         withdrawAmount: amount,
         changeNote: changeNote || null,
+        poolBinding: await this.getPoolBinding(),
       });
 
       // 5. Encrypt change note for self
@@ -0,0 +1,480 @@
+//! End-to-end regression tests: the script binaries against a local chain.
+//!
+//! Each test starts its own `anvil`, deploys `MockERC20`, `MockSP1Verifier`
+//! and `ShieldedPool` with `forge create`, and runs the binaries built from
+//! `shielded-pool-script` as subprocesses with `SP1_PROVER=mock`. The guests
+//! still execute, so an input they reject fails the run, but the proofs are
+//! mocks that only the mock verifier accepts. The tests then check the wallet
+//! file, token balances and the reports the binaries wrote.
+//!
+//! Binaries run in a temporary directory and every file they write (wallet,
+//! audit log, exit report, tree store) goes there, so the checkout's `.env`
+//! and `fixtures/` are never read or modified.
+//!
+//! The tests need `anvil` and `forge` on PATH and the release binaries, and
+//! fail without them; `make test-e2e-local` builds the binaries first. Set
+//! `SKIP_LOCAL_CHAIN=1` to have `Session::start` return `None` instead, so
+//! the tests pass without running. `SHIELDED_POOL_BIN_DIR` points the tests
+//! at binaries built elsewhere.
+
+use alloy::{
+    primitives::{Address, FixedBytes, U256},
+    providers::{Provider, ProviderBuilder},
+    signers::local::PrivateKeySigner,
+    sol,
+};
+use anyhow::{bail, ensure, Context, Result};
+use shielded_pool_lib::Note;
+use shielded_pool_script::wallet::WalletState;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Anvil's first dev account: deploys the contracts, runs the e2e flow and
+/// receives every withdrawal.
+pub const DEPLOYER_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+/// Anvil's second dev account: the relayer's key.
+pub const RELAYER_KEY: &str = "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+/// Tokens minted to the deployer (1000 USDT).
+pub const MINTED: u64 = 1_000_000_000;
+
+/// Merkle tree depth of the test pool.
+pub const TREE_LEVELS: usize = 10;
+
+/// Passphrase the binaries encrypt and unlock wallet keys with.
+pub const PASSPHRASE: &str = "integration-tests";
+
+/// The CLI binary (`src/main.rs` of the script crate).
+pub const CLI: &str = "shielded-pool-script";
+
+/// Bearer token walletd is started with.
+pub const WALLETD_TOKEN: &str = "integration-tests-token";
+
+sol! {
+    #[sol(rpc)]
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+        function approve(address spender, uint256 amount) external returns (bool);
+        function mint(address to, uint256 amount) external;
+    }
+
+    #[sol(rpc)]
+    interface IShieldedPool {
+        function deposit(bytes32 commitment, uint256 amount, bytes calldata encryptedData) external;
+        function getLastRoot() external view returns (bytes32);
+        function getLeafCount() external view returns (uint32);
+        function isSpent(bytes32 nullifier) external view returns (bool);
+    }
+}
+
+/// The repository root (contracts, foundry.toml).
+pub fn repo_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().to_path_buf()
+}
+
+/// `SHIELDED_POOL_BIN_DIR` if set, otherwise the workspace's release target
+/// directory.
+pub fn bin_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("SHIELDED_POOL_BIN_DIR") {
+        return dir.into();
+    }
+    std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| repo_root().join("target"))
+        .join("release")
+}
+
+pub fn deployer() -> Address {
+    DEPLOYER_KEY.parse::<PrivateKeySigner>().unwrap().address()
+}
+
+/// Why the tests cannot run here, if they cannot: Foundry or the CLI binary
+/// is missing.
+fn missing_prerequisite() -> Option<String> {
+    for tool in ["anvil", "forge"] {
+        if Command::new(tool).arg("--version").output().is_err() {
+            return Some(format!("{tool} is not on PATH (is Foundry installed?)"));
+        }
+    }
+    let cli = bin_dir().join(CLI);
+    (!cli.exists()).then(|| format!("{} not found", cli.display()))
+}
+
+/// The transfer and withdraw vkeys of the built guests, as `vkeys` prints
+/// them. The CLI refuses to send proofs to a pool with other vkeys, so the
+/// test pool is deployed with these. Computed once per test binary.
+fn guest_vkeys() -> Result<(FixedBytes<32>, FixedBytes<32>)> {
+    static VKEYS: OnceLock<(FixedBytes<32>, FixedBytes<32>)> = OnceLock::new();
+    if let Some(vkeys) = VKEYS.get() {
+        return Ok(*vkeys);
+    }
+    let mut cmd = Command::new(bin_dir().join(CLI));
+    cmd.arg("vkeys").env_clear().env("SP1_PROVER", "mock");
+    for key in ["PATH", "HOME"] {
+        if let Some(value) = std::env::var_os(key) {
+            cmd.env(key, value);
+        }
+    }
+    let stdout = check_output("vkeys", cmd.output().context("Failed to run vkeys")?)?;
+    let vkey = |name: &str| -> Result<FixedBytes<32>> {
+        let line = stdout.lines().find_map(|line| line.strip_prefix(name)).with_context(|| {
+            format!("vkeys printed no {name}:\n{stdout}")
+        })?;
+        Ok(line.trim_start_matches(':').trim().parse()?)
+    };
+    let vkeys = (vkey("TRANSFER_VKEY")?, vkey("WITHDRAW_VKEY")?);
+    Ok(*VKEYS.get_or_init(|| vkeys))
+}
+
+/// A local `anvil` node, killed on drop.
+pub struct Anvil {
+    child: Child,
+    pub rpc_url: String,
+}
+
+impl Anvil {
+    pub fn spawn() -> Result<Self> {
+        let port = free_port()?;
+        let child = Command::new("anvil")
+            .args(["--port", &port.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Failed to start anvil (is Foundry installed?)")?;
+        let anvil = Anvil { child, rpc_url: format!("http://127.0.0.1:{port}") };
+        wait_for_port(port, "anvil", Duration::from_secs(30))?;
+        Ok(anvil)
+    }
+}
+
+impl Drop for Anvil {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Contracts deployed by `Deployment::deploy`.
+#[derive(Clone, Copy, Debug)]
+pub struct Deployment {
+    pub token: Address,
+    pub pool: Address,
+    /// Block before the first deployment; sync replays from here
+    pub deploy_block: u64,
+}
+
+impl Deployment {
+    /// Deploy the mock token and verifier and a pool using them, and mint
+    /// `MINTED` to the deployer.
+    pub async fn deploy(anvil: &Anvil) -> Result<Self> {
+        let provider = ProviderBuilder::new().connect_http(anvil.rpc_url.parse()?);
+        let deploy_block = provider.get_block_number().await?;
+
+        let token = forge_create(anvil, "test/MockERC20.sol:MockERC20", &[])?;
+        let verifier = forge_create(anvil, "test/MockSP1Verifier.sol:MockSP1Verifier", &[])?;
+        // The mock verifier ignores the vkeys, but `send` and `withdraw
+        // --submit` check the transfer and withdraw ones against their build
+        let (transfer_vkey, withdraw_vkey) = guest_vkeys()?;
+        let spend_vkeys: Vec<String> = (3..=7).map(|n| FixedBytes::<32>::with_last_byte(n).to_string()).collect();
+        let spend_vkeys = format!("({})", spend_vkeys.join(","));
+        let pool = forge_create(
+            anvil,
+            "contracts/ShieldedPool.sol:ShieldedPool",
+            &[
+                &token.to_string(),
+                &verifier.to_string(),
+                &transfer_vkey.to_string(),
+                &withdraw_vkey.to_string(),
+                // Zero deposit vkey: the scripts deposit without proofs
+                &FixedBytes::<32>::ZERO.to_string(),
+                &TREE_LEVELS.to_string(),
+                // Join-split, batch-withdraw, consolidate, payout and
+                // policy-spend vkeys, all enabled
+                &spend_vkeys,
+            ],
+        )?;
+
+        let signer: PrivateKeySigner = DEPLOYER_KEY.parse()?;
+        let provider = ProviderBuilder::new().wallet(signer).connect_http(anvil.rpc_url.parse()?);
+        IERC20::new(token, &provider).mint(deployer(), U256::from(MINTED)).send().await?.get_receipt().await?;
+
+        Ok(Deployment { token, pool, deploy_block })
+    }
+}
+
+/// Deploy `contract` (path:Name) with `forge create` and return its address.
+fn forge_create(anvil: &Anvil, contract: &str, args: &[&str]) -> Result<Address> {
+    let mut cmd = Command::new("forge");
+    cmd.current_dir(repo_root()).args([
+        "create",
+        contract,
+        "--rpc-url",
+        &anvil.rpc_url,
+        "--private-key",
+        DEPLOYER_KEY,
+        "--broadcast",
+        "--json",
+    ]);
+    if !args.is_empty() {
+        cmd.arg("--constructor-args").args(args);
+    }
+    let output = cmd.output().context("Failed to run forge (is Foundry installed?)")?;
+    let stdout = check_output(&format!("forge create {contract}"), output)?;
+    // Compiler progress may come first; the result is the JSON line
+    let result: serde_json::Value = stdout
+        .lines()
+        .rev()
+        .find_map(|line| serde_json::from_str(line).ok())
+        .with_context(|| format!("no JSON result from forge create {contract}:\n{stdout}"))?;
+    let address = result["deployedTo"].as_str().context("forge create result has no deployedTo")?;
+    Ok(address.parse()?)
+}
+
+/// One test's chain, contracts and working directory.
+pub struct Session {
+    pub anvil: Anvil,
+    pub deployment: Deployment,
+    dir: tempfile::TempDir,
+}
+
+impl Session {
+    /// Start anvil and deploy a fresh pool. Fails when Foundry or the
+    /// binaries are missing, unless `SKIP_LOCAL_CHAIN` is set, in which case
+    /// it returns `None`.
+    pub async fn start() -> Result<Option<Self>> {
+        if let Some(missing) = missing_prerequisite() {
+            if std::env::var_os("SKIP_LOCAL_CHAIN").is_some() {
+                eprintln!("skipping: {missing} (SKIP_LOCAL_CHAIN is set)");
+                return Ok(None);
+            }
+            bail!("{missing}; see make test-e2e-local, or set SKIP_LOCAL_CHAIN=1 to skip these tests");
+        }
+        let anvil = Anvil::spawn()?;
+        let deployment = Deployment::deploy(&anvil).await?;
+        let dir = tempfile::tempdir()?;
+        Ok(Some(Session { anvil, deployment, dir }))
+    }
+
+    /// `name` inside the session's working directory.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    pub fn wallet_path(&self) -> PathBuf {
+        self.path("wallet.db")
+    }
+
+    pub fn exit_report_path(&self) -> PathBuf {
+        self.path("exit-report.json")
+    }
+
+    /// A command running the script binary `bin` against this session's
+    /// pool, with nothing inherited from the caller's environment but PATH,
+    /// HOME and RUST_LOG.
+    pub fn command(&self, bin: &str) -> Result<Command> {
+        let path = bin_dir().join(bin);
+        ensure!(
+            path.exists(),
+            "{} not found; build it with `cargo build --release -p shielded-pool-script --bins`",
+            path.display()
+        );
+        let mut cmd = Command::new(path);
+        cmd.current_dir(self.dir.path()).env_clear().stdin(Stdio::null());
+        for key in ["PATH", "HOME", "RUST_LOG"] {
+            if let Some(value) = std::env::var_os(key) {
+                cmd.env(key, value);
+            }
+        }
+        cmd.envs([
+            ("SP1_PROVER", "mock".to_string()),
+            ("RPC_URL", self.anvil.rpc_url.clone()),
+            ("PRIVATE_KEY", DEPLOYER_KEY.to_string()),
+            ("TOKEN_ADDRESS", self.deployment.token.to_string()),
+            ("POOL_ADDRESS", self.deployment.pool.to_string()),
+            ("DEPLOY_BLOCK", self.deployment.deploy_block.to_string()),
+            ("TREE_LEVELS", TREE_LEVELS.to_string()),
+            ("WALLET_PASSPHRASE", PASSPHRASE.to_string()),
+            ("WALLET_FILE", self.wallet_path().display().to_string()),
+            ("AUDIT_LOG", self.path("audit-log.jsonl").display().to_string()),
+            ("EXIT_REPORT", self.exit_report_path().display().to_string()),
+            ("TREE_STORE", self.path("tree-store").display().to_string()),
+            ("STRICT", "1".to_string()),
+        ]);
+        Ok(cmd)
+    }
+
+    /// Run `bin` with `args` to completion and return its stdout. Fails with
+    /// the binary's output unless it exits successfully.
+    pub fn run(&self, bin: &str, args: &[&str]) -> Result<String> {
+        let output = self.command(bin)?.args(args).output().with_context(|| format!("Failed to run {bin}"))?;
+        check_output(bin, output)
+    }
+
+    /// The wallet the binaries wrote.
+    pub fn wallet(&self) -> Result<WalletState> {
+        WalletState::load(&self.wallet_path())
+    }
+
+    pub async fn token_balance(&self, owner: Address) -> Result<u64> {
+        let provider = ProviderBuilder::new().connect_http(self.anvil.rpc_url.parse()?);
+        let balance = IERC20::new(self.deployment.token, &provider).balanceOf(owner).call().await?;
+        Ok(balance.try_into()?)
+    }
+
+    pub async fn leaf_count(&self) -> Result<u32> {
+        let provider = ProviderBuilder::new().connect_http(self.anvil.rpc_url.parse()?);
+        Ok(IShieldedPool::new(self.deployment.pool, &provider).getLeafCount().call().await?)
+    }
+
+    pub async fn last_root(&self) -> Result<[u8; 32]> {
+        let provider = ProviderBuilder::new().connect_http(self.anvil.rpc_url.parse()?);
+        Ok(IShieldedPool::new(self.deployment.pool, &provider).getLastRoot().call().await?.0)
+    }
+
+    pub async fn is_spent(&self, nullifier: [u8; 32]) -> Result<bool> {
+        let provider = ProviderBuilder::new().connect_http(self.anvil.rpc_url.parse()?);
+        Ok(IShieldedPool::new(self.deployment.pool, &provider).isSpent(FixedBytes(nullifier)).call().await?)
+    }
+
+    pub async fn chain_id(&self) -> Result<u64> {
+        let provider = ProviderBuilder::new().connect_http(self.anvil.rpc_url.parse()?);
+        Ok(provider.get_chain_id().await?)
+    }
+
+    /// Approve and deposit `note` from the deployer, posted without a payload.
+    pub async fn deposit(&self, note: &Note) -> Result<()> {
+        let signer: PrivateKeySigner = DEPLOYER_KEY.parse()?;
+        let provider = ProviderBuilder::new().wallet(signer).connect_http(self.anvil.rpc_url.parse()?);
+        let amount = U256::from(note.amount);
+        let token = IERC20::new(self.deployment.token, &provider);
+        token.approve(self.deployment.pool, amount).send().await?.get_receipt().await?;
+        let pool = IShieldedPool::new(self.deployment.pool, &provider);
+        let pending = pool.deposit(FixedBytes(note.commitment()), amount, Default::default()).send().await?;
+        ensure!(pending.get_receipt().await?.status(), "deposit reverted");
+        Ok(())
+    }
+
+    /// Start the explorer on a free port and wait until it serves.
+    pub fn explorer(&self) -> Result<Service> {
+        let port = free_port()?;
+        let mut cmd = self.command("explorer")?;
+        cmd.env("EXPLORER_PORT", port.to_string());
+        Service::spawn("explorer", &mut cmd, port, None)
+    }
+
+    /// Start the relayer on a free port, paying gas from `RELAYER_KEY`.
+    pub fn relayer(&self) -> Result<Service> {
+        let port = free_port()?;
+        let mut cmd = self.command("relayer")?;
+        cmd.env("RELAYER_PORT", port.to_string()).env("RELAYER_PRIVATE_KEY", RELAYER_KEY);
+        Service::spawn("relayer", &mut cmd, port, None)
+    }
+
+    /// Start walletd over the session's wallet on a free port, with
+    /// `WALLETD_TOKEN`.
+    pub fn walletd(&self) -> Result<Service> {
+        let port = free_port()?;
+        let mut cmd = self.command(CLI)?;
+        cmd.args(["walletd", "--port", &port.to_string()]).env("WALLETD_TOKEN", WALLETD_TOKEN);
+        Service::spawn("walletd", &mut cmd, port, Some(WALLETD_TOKEN))
+    }
+}
+
+/// A running server (explorer, relayer or walletd), killed on drop.
+pub struct Service {
+    child: Child,
+    port: u16,
+    /// Bearer token sent with every request
+    token: Option<&'static str>,
+}
+
+impl Service {
+    fn spawn(what: &str, cmd: &mut Command, port: u16, token: Option<&'static str>) -> Result<Self> {
+        let child = cmd
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start the {what}"))?;
+        let service = Service { child, port, token };
+        wait_for_port(port, what, Duration::from_secs(120))?;
+        Ok(service)
+    }
+
+    /// GET `path` and parse the JSON body. Fails unless the status is 200.
+    pub fn get(&self, path: &str) -> Result<serde_json::Value> {
+        let (status, body) = self.request("GET", path, None)?;
+        ensure!(status == 200, "GET {path}: {status} {body}");
+        Ok(body)
+    }
+
+    /// POST `body` as JSON to `path` and return the status and the body,
+    /// parsed as JSON if it is.
+    pub fn post(&self, path: &str, body: &serde_json::Value) -> Result<(u16, serde_json::Value)> {
+        self.request("POST", path, Some(body))
+    }
+
+    /// Call a JSON-RPC `method` and return its result. Fails on an error.
+    pub fn rpc(&self, method: &str, params: serde_json::Value) -> Result<serde_json::Value> {
+        let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let (status, mut response) = self.post("/", &request)?;
+        ensure!(status == 200, "{method}: {status} {response}");
+        ensure!(response["error"].is_null(), "{method} failed: {}", response["error"]);
+        Ok(response["result"].take())
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<&serde_json::Value>) -> Result<(u16, serde_json::Value)> {
+        let body = body.map(serde_json::Value::to_string).unwrap_or_default();
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port))?;
+        write!(stream, "{method} {path} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n", self.port)?;
+        if let Some(token) = self.token {
+            write!(stream, "Authorization: Bearer {token}\r\n")?;
+        }
+        if method == "POST" {
+            write!(stream, "Content-Type: application/json\r\nContent-Length: {}\r\n", body.len())?;
+        }
+        write!(stream, "\r\n{body}")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        let (head, body) = response.split_once("\r\n\r\n").context("malformed HTTP response")?;
+        let status = head.split_whitespace().nth(1).and_then(|s| s.parse().ok()).context("malformed HTTP status")?;
+        let body = serde_json::from_str(body).unwrap_or_else(|_| serde_json::Value::String(body.to_string()));
+        Ok((status, body))
+    }
+}
+
+impl Drop for Service {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn check_output(what: &str, output: Output) -> Result<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() {
+        bail!(
+            "{what} failed ({}):\n--- stdout\n{stdout}\n--- stderr\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(stdout)
+}
+
+fn free_port() -> Result<u16> {
+    Ok(TcpListener::bind(("127.0.0.1", 0))?.local_addr()?.port())
+}
+
+fn wait_for_port(port: u16, what: &str, timeout: Duration) -> Result<()> {
+    let start = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        ensure!(start.elapsed() < timeout, "{what} did not listen on port {port} within {timeout:?}");
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
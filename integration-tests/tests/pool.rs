@@ -0,0 +1,149 @@
+//! The binaries against a fresh pool on anvil; see the crate docs.
+//!
+//! Run with `make test-e2e-local`.
+
+use anyhow::Result;
+use shielded_pool_integration_tests::{deployer, Session, CLI, MINTED};
+use shielded_pool_lib::note_bundle::NoteBundle;
+use shielded_pool_script::{audit::AuditReport, exit_report::ExitReport, wallet::WalletState};
+
+/// Labels and amounts of the notes the default e2e run leaves unspent: the
+/// transfer's change to the sender and the withdrawal's change.
+const UNSPENT_AFTER_E2E: [(&str, u64); 2] = [("transfer_change", 500_000), ("withdraw_change", 200_000)];
+
+/// Run the default e2e flow and check what it left behind: 1.0 USDT
+/// deposited, 0.3 withdrawn back, five leaves.
+async fn e2e(session: &Session) -> Result<()> {
+    let out = session.run("e2e", &[])?;
+    assert!(out.contains("E2E Test Passed"), "{out}");
+
+    let wallet = session.wallet()?;
+    let labels: Vec<&str> = wallet.notes.iter().map(|n| n.label.as_str()).collect();
+    assert_eq!(labels, ["deposit_a", "deposit_b", "transfer_to_recipient", "transfer_change", "withdraw_change"]);
+    assert!(wallet.spending_keys.iter().all(|k| k.keystore.is_some() && k.spending_key.is_empty()));
+
+    assert_eq!(session.leaf_count().await?, 5);
+    assert_eq!(session.token_balance(deployer()).await?, MINTED - 700_000);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 700_000);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_e2e_then_exit_recovers_every_note() -> Result<()> {
+    let Some(session) = Session::start().await? else { return Ok(()) };
+    e2e(&session).await?;
+
+    session.run("exit", &[])?;
+    let report = ExitReport::load(&session.exit_report_path())?;
+    let mut withdrawn: Vec<(&str, u64)> = report.withdrawals.iter().map(|w| (w.label.as_str(), w.amount)).collect();
+    withdrawn.sort();
+    assert_eq!(withdrawn, UNSPENT_AFTER_E2E);
+    assert!(report.skipped.is_empty());
+    assert_eq!(report.balance_delta()?, 700_000);
+    report.check_offline()?;
+    assert_eq!(session.token_balance(deployer()).await?, MINTED);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 0);
+
+    // The bundle checks out against the chain, and a second exit has nothing to do
+    let report_path = session.exit_report_path().display().to_string();
+    session.run("verify-exit-report", &[&report_path])?;
+    assert!(session.run("exit", &[])?.contains("No unspent notes found"));
+
+    // Every pool transaction of both runs is in the audit log, and the
+    // exported report verifies against the chain
+    let audit_path = session.path("audit-report.json").display().to_string();
+    session.run(CLI, &["audit", "export", "--out", &audit_path])?;
+    let audit: AuditReport = serde_json::from_str(&std::fs::read_to_string(&audit_path)?)?;
+    assert_eq!(audit.entries.len(), 2 + 1 + 1 + UNSPENT_AFTER_E2E.len());
+    session.run("verify-audit-report", &[&audit_path])?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sweep_idle_exits_idle_notes() -> Result<()> {
+    let Some(session) = Session::start().await? else { return Ok(()) };
+    e2e(&session).await?;
+
+    // Nothing is idle yet under the default policy
+    assert!(session.run("sweep-idle", &[])?.contains("No idle notes"));
+
+    session.run(CLI, &["note", "idle", "--remind-after-days", "0", "--sweep", "exit"])?;
+    session.run("sweep-idle", &[])?;
+    let wallet = session.wallet()?;
+    for (label, _) in UNSPENT_AFTER_E2E {
+        assert!(!wallet.notes.iter().any(|n| n.label == label), "{label} still in the wallet");
+    }
+    assert_eq!(session.token_balance(deployer()).await?, MINTED);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_explorer_serves_pool_state() -> Result<()> {
+    let Some(session) = Session::start().await? else { return Ok(()) };
+    e2e(&session).await?;
+
+    let explorer = session.explorer()?;
+    let status = explorer.get("/status")?;
+    assert_eq!(status["leaf_count"], 5);
+    assert_eq!(status["root"], format!("0x{}", hex::encode(session.last_root().await?)));
+    // Two notes spent by the transfer, one by the withdrawal
+    assert_eq!(status["nullifiers"], 3);
+
+    // Every wallet note is indexed at its leaf; a note bundle from the CLI
+    // names the same leaf
+    let wallet = session.wallet()?;
+    for note in &wallet.notes {
+        let entry = explorer.get(&format!("/commitments/0x{}", note.commitment))?;
+        assert_eq!(entry["leaf_index"], note.leaf_index, "{}", note.label);
+    }
+    let bundle = session.run(CLI, &["note", "export", "withdraw_change"])?;
+    let bundle = NoteBundle::decode(bundle.trim()).map_err(anyhow::Error::msg)?;
+    assert_eq!(bundle.leaf_index, wallet.notes[4].leaf_index);
+    assert_eq!(hex::encode(bundle.note.commitment()), wallet.notes[4].commitment);
+    Ok(())
+}
+
+/// `(label, amount, spent)` of each note in a wallet.
+fn notes(wallet: &WalletState) -> Vec<(&str, u64, bool)> {
+    wallet.notes.iter().map(|n| (n.label.as_str(), n.amount, n.spent)).collect()
+}
+
+#[tokio::test]
+async fn test_cli_deposit_send_scan_withdraw() -> Result<()> {
+    let Some(session) = Session::start().await? else { return Ok(()) };
+    let bob_wallet = session.path("bob.db");
+    let bob_wallet_arg = bob_wallet.display().to_string();
+    session.run(CLI, &["keygen", "--label", "alice"])?;
+    session.run(CLI, &["keygen", "--label", "bob", "--wallet", &bob_wallet_arg])?;
+    let bob = WalletState::load(&bob_wallet)?.spending_keys.remove(0);
+
+    session.run(CLI, &["deposit", "--amount", "1", "--to", "alice"])?;
+    assert_eq!(notes(&session.wallet()?), [("deposit_0", 1_000_000, false)]);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 1_000_000);
+
+    // Bob's key is not in Alice's wallet, so only the change is recorded there
+    let (to, viewing_pubkey) = (format!("0x{}", bob.pubkey), format!("0x{}", bob.viewing_pubkey));
+    session.run(CLI, &["send", "--to", &to, "--viewing-pubkey", &viewing_pubkey, "--amount", "0.4"])?;
+    assert_eq!(notes(&session.wallet()?), [("deposit_0", 1_000_000, true), ("change_2", 600_000, false)]);
+    assert_eq!(session.leaf_count().await?, 3);
+
+    // Bob finds the payment by trial decryption
+    let from_block = session.deployment.deploy_block.to_string();
+    let scan = ["scan", "--from-block", &from_block, "--wallet", &bob_wallet_arg];
+    session.run(CLI, &scan)?;
+    assert_eq!(notes(&WalletState::load(&bob_wallet)?), [("received_1", 400_000, false)]);
+
+    session.run(CLI, &["withdraw", "--submit", "--amount", "0.1", "--wallet", &bob_wallet_arg])?;
+    let bob_notes = [("received_1", 400_000, true), ("change_3", 300_000, false)];
+    assert_eq!(notes(&WalletState::load(&bob_wallet)?), bob_notes);
+    assert_eq!(session.token_balance(deployer()).await?, MINTED - 900_000);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 900_000);
+
+    let balance = session.run(CLI, &["balance", "--wallet", &bob_wallet_arg])?;
+    assert!(balance.contains("Unspent: 0.3 USDT in 1 of 2 notes"), "{balance}");
+    // A rescan finds nothing new
+    let rescan = session.run(CLI, &["scan", "--wallet", &bob_wallet_arg])?;
+    assert!(rescan.contains("0 new note(s)"), "{rescan}");
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! The relayer and walletd against a fresh pool on anvil; see the crate docs.
+//!
+//! Run with `make test-e2e-local`.
+
+use anyhow::Result;
+use shielded_pool_integration_tests::{Session, CLI, TREE_LEVELS};
+use shielded_pool_lib::{
+    derive_pubkey, pool_binding, IncrementalMerkleTree, Note, SpenderKey, WithdrawPrivateInputs, WithdrawPublicValues,
+};
+use shielded_pool_script::{decode_hex, keygen};
+
+#[tokio::test]
+async fn test_relayer_submits_a_withdrawal_until_mined() -> Result<()> {
+    let Some(session) = Session::start().await? else { return Ok(()) };
+    let spending_key = *keygen::spending_key_from_mnemonic(&keygen::generate_mnemonic()?).as_bytes();
+    let note = Note { amount: 1_000_000, pubkey: derive_pubkey(&spending_key), blinding: [9u8; 32] };
+    session.deposit(&note).await?;
+    let mut tree = IncrementalMerkleTree::new(TREE_LEVELS);
+    tree.insert(note.commitment());
+    assert_eq!(tree.get_root(), session.last_root().await?);
+
+    // Prove a withdrawal with the CLI, and post it instead of sending it
+    let recipient = [0x42; 20];
+    let change = Note { amount: 400_000, pubkey: note.pubkey, blinding: [10u8; 32] };
+    let mut inputs = WithdrawPrivateInputs {
+        input_note: note.clone(),
+        spender_key: SpenderKey::derive(&spending_key),
+        merkle_proof: tree.get_proof(0),
+        root: tree.get_root(),
+        recipient,
+        withdraw_amount: 600_000,
+        change_note: Some(change.clone()),
+        lock: None,
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: pool_binding(session.chain_id().await?, &session.deployment.pool.0 .0),
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: None,
+        spend_signatures: Vec::new(),
+    };
+    inputs.sign(&spending_key).map_err(anyhow::Error::msg)?;
+    let (input, output) = (session.path("withdraw-input.json"), session.path("withdraw-proof.json"));
+    std::fs::write(&input, serde_json::to_string(&inputs)?)?;
+    let (input, output) = (input.display().to_string(), output.display().to_string());
+    session.run(CLI, &["withdraw", "--input", &input, "--output", &output])?;
+    let proof: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&output)?)?;
+    let submission = serde_json::json!({ "proof": proof["proof"], "publicValues": proof["public_values"] });
+    let public_values = decode_hex(proof["public_values"].as_str().unwrap())?;
+    let values = WithdrawPublicValues::decode(&public_values).unwrap();
+
+    let relayer = session.relayer()?;
+    let info = relayer.get("/info")?;
+    assert_eq!(info["treeLevels"], TREE_LEVELS);
+    let (status, relayed) = relayer.post("/relay", &submission)?;
+    assert_eq!(status, 200, "{relayed}");
+    assert!(relayed["txHash"].is_string() && relayed["block"].is_u64(), "{relayed}");
+
+    // Mined: the note is spent, the recipient paid and the change inserted
+    assert!(session.is_spent(values.nullifier).await?);
+    assert_eq!(session.token_balance(recipient.into()).await?, 600_000);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 400_000);
+    assert_eq!(session.leaf_count().await?, 2);
+
+    // The same submission again spends a spent note, and is refused
+    let (status, refused) = relayer.post("/relay", &submission)?;
+    assert_eq!(status, 409, "{refused}");
+    assert!(refused["error"].as_str().unwrap().contains("already spent"), "{refused}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_walletd_transfer_shows_in_balance() -> Result<()> {
+    let Some(session) = Session::start().await? else { return Ok(()) };
+    session.run(CLI, &["keygen", "--label", "alice"])?;
+    session.run(CLI, &["keygen", "--label", "bob"])?;
+    session.run(CLI, &["deposit", "--amount", "1", "--to", "alice"])?;
+
+    let walletd = session.walletd()?;
+    let transfer = serde_json::json!({ "to": "bob", "amount": "0.4" });
+    let sent = walletd.rpc("createTransfer", transfer)?;
+    assert_eq!((&sent["amount"], &sent["change"]), (&400_000.into(), &600_000.into()), "{sent}");
+    assert_eq!((&sent["paymentLeaf"], &sent["changeLeaf"]), (&1.into(), &2.into()), "{sent}");
+
+    // Both outputs are the wallet's: Bob's payment and Alice's change
+    let balance = walletd.rpc("getBalance", serde_json::Value::Null)?;
+    let notes: Vec<(&str, u64, &str)> = balance["notes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|n| (n["label"].as_str().unwrap(), n["amount"].as_u64().unwrap(), n["status"].as_str().unwrap()))
+        .collect();
+    let expected = [
+        ("deposit_0", 1_000_000, "spent"),
+        ("received_1", 400_000, "unspent"),
+        ("change_2", 600_000, "unspent"),
+    ];
+    assert_eq!(notes, expected);
+    assert_eq!((&balance["unspent"], &balance["unspentNotes"]), (&1_000_000.into(), &2.into()), "{balance}");
+    assert_eq!(session.leaf_count().await?, 3);
+    assert_eq!(session.token_balance(session.deployment.pool).await?, 1_000_000);
+    Ok(())
+}
@@ -0,0 +1,193 @@
+//! Stable abort codes of the guests.
+//!
+//! A guest that rejects its inputs panics, and the host only sees the
+//! executor's "exit code 1". Each guest installs `install_abort_hook!` first
+//! thing, which classifies the panic message and halts with that class's
+//! code instead, so the CLI can say why without the guest's output.
+//!
+//! Codes are part of the interface: never renumber one, only add. A panic
+//! that matches no class (malformed stdin, say) keeps exit code 1.
+
+/// Why a guest rejected its inputs. The discriminant is the exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AbortCode {
+    /// A panic no class matches (also SP1's own exit code for a panic)
+    Unclassified = 1,
+    Header = 10,
+    EndMarker = 11,
+    Shape = 12,
+    Ownership = 20,
+    Merkle = 21,
+    DuplicateInput = 22,
+    Spent = 23,
+    SpendAuthorization = 24,
+    OutputRange = 30,
+    Conservation = 31,
+    OutputOwner = 32,
+    Recipients = 33,
+    Relayer = 34,
+    Call = 35,
+    Association = 40,
+    Threshold = 41,
+    Disclosure = 42,
+    Policy = 44,
+}
+
+/// (message fragment, class), first match wins: the specific fragments
+/// come before the ones they contain.
+const MESSAGES: &[(&str, AbortCode)] = &[
+    ("input header", AbortCode::Header),
+    ("missing end marker", AbortCode::EndMarker),
+    ("after the end marker", AbortCode::EndMarker),
+    ("pad with dummies", AbortCode::Shape),
+    ("spends 1 to", AbortCode::Shape),
+    ("creates 1 to", AbortCode::Shape),
+    ("merges 2 to 8", AbortCode::Shape),
+    ("counts 1 to 8", AbortCode::Shape),
+    ("opens 1 to 8", AbortCode::Shape),
+    ("counter ", AbortCode::Policy),
+    ("epoch limit", AbortCode::Policy),
+    ("leaves the policy", AbortCode::Policy),
+    ("match note pubkey", AbortCode::Ownership),
+    ("neither the recipient nor the sender", AbortCode::Ownership),
+    ("no reclaim deadline", AbortCode::Ownership),
+    ("multisig owners", AbortCode::Ownership),
+    ("both cancellable and multisig", AbortCode::Ownership),
+    ("spend authorization signature", AbortCode::SpendAuthorization),
+    ("Merkle proof invalid", AbortCode::Merkle),
+    ("differ in depth", AbortCode::Merkle),
+    ("duplicate payload", AbortCode::Disclosure),
+    ("duplicate input note", AbortCode::DuplicateInput),
+    ("spent at the snapshot", AbortCode::Spent),
+    ("nullifier root", AbortCode::Spent),
+    ("MAX_AMOUNT", AbortCode::OutputRange),
+    ("amount is zero", AbortCode::OutputRange),
+    ("amount overflows", AbortCode::OutputRange),
+    ("don't balance", AbortCode::Conservation),
+    ("amount mismatch", AbortCode::Conservation),
+    ("not owned by the spender", AbortCode::OutputOwner),
+    ("recipients must be distinct", AbortCode::Recipients),
+    ("relayer fee", AbortCode::Relayer),
+    ("call target", AbortCode::Call),
+    ("association set", AbortCode::Association),
+    ("label", AbortCode::Association),
+    ("threshold", AbortCode::Threshold),
+    ("payload", AbortCode::Disclosure),
+];
+
+impl AbortCode {
+    pub const ALL: [AbortCode; 19] = [
+        AbortCode::Unclassified,
+        AbortCode::Header,
+        AbortCode::EndMarker,
+        AbortCode::Shape,
+        AbortCode::Ownership,
+        AbortCode::Merkle,
+        AbortCode::DuplicateInput,
+        AbortCode::Spent,
+        AbortCode::SpendAuthorization,
+        AbortCode::OutputRange,
+        AbortCode::Conservation,
+        AbortCode::OutputOwner,
+        AbortCode::Recipients,
+        AbortCode::Relayer,
+        AbortCode::Call,
+        AbortCode::Association,
+        AbortCode::Threshold,
+        AbortCode::Disclosure,
+        AbortCode::Policy,
+    ];
+
+    /// The class of a guest's panic message.
+    pub fn classify(message: &str) -> AbortCode {
+        MESSAGES.iter().find(|(fragment, _)| message.contains(fragment)).map_or(AbortCode::Unclassified, |&(_, c)| c)
+    }
+
+    /// The exit code a guest halts with.
+    pub fn exit_code(self) -> u8 {
+        self as u8
+    }
+
+    /// The class a guest's exit code stands for; `None` for codes no guest
+    /// halts with.
+    pub fn from_exit_code(code: u32) -> Option<AbortCode> {
+        AbortCode::ALL.into_iter().find(|c| c.exit_code() as u32 == code)
+    }
+
+    /// What the rejection means, for people.
+    pub fn explanation(self) -> &'static str {
+        match self {
+            AbortCode::Unclassified => "the guest panicked (malformed inputs?); its output has the message",
+            AbortCode::Header => "the inputs are for another circuit or another input version; rebuild them",
+            AbortCode::EndMarker => "the inputs were not framed with framed_stdin, or carry trailing data",
+            AbortCode::Shape => "wrong number of inputs or outputs for this circuit (pad with dummies)",
+            AbortCode::Ownership => "a key does not open an input note (wrong key, lock or cosigner)",
+            AbortCode::Merkle => "a Merkle proof does not lead to the root, or has another depth: resync the tree",
+            AbortCode::DuplicateInput => "the same note is spent twice in one proof",
+            AbortCode::Spent => "a note was spent at the nullifier snapshot, or the proof is against another root",
+            AbortCode::SpendAuthorization => {
+                "an input's owner did not sign these public values (missing, forged or stale signature)"
+            }
+            AbortCode::OutputRange => "an amount is zero, above MAX_AMOUNT, or overflows",
+            AbortCode::Conservation => "input amounts do not equal output amounts plus what is withdrawn",
+            AbortCode::OutputOwner => "an output that must stay with the spender goes to another key",
+            AbortCode::Recipients => "two outputs pay the same pubkey",
+            AbortCode::Relayer => "a relayer fee is paid to the zero address",
+            AbortCode::Call => "a calldata hash is set without a call target",
+            AbortCode::Association => "a note or label is not in the association set",
+            AbortCode::Threshold => "the notes hold less than the threshold",
+            AbortCode::Disclosure => "a payload does not open under the viewing key, or is disclosed twice",
+            AbortCode::Policy => "the spend breaks the note's policy (limit, counter or change)",
+        }
+    }
+}
+
+/// Installs a panic hook that prints the panic and the abort code, then
+/// halts with the code (see the module docs). Call it first in a guest's
+/// `main`; the guest must depend on `sp1-zkvm`.
+#[macro_export]
+macro_rules! install_abort_hook {
+    () => {
+        ::std::panic::set_hook(::std::boxed::Box::new(|info| {
+            let code = $crate::abort::AbortCode::classify(&::std::string::ToString::to_string(info));
+            ::std::eprintln!("{}\nabort code {}: {}", info, code.exit_code(), code.explanation());
+            ::sp1_zkvm::syscalls::syscall_halt(code.exit_code());
+        }))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_abort_codes_are_stable() {
+        let codes = AbortCode::ALL.map(AbortCode::exit_code);
+        assert_eq!(codes, [1, 10, 11, 12, 20, 21, 22, 23, 24, 30, 31, 32, 33, 34, 35, 40, 41, 42, 44]);
+        for code in AbortCode::ALL {
+            assert_eq!(AbortCode::from_exit_code(code.exit_code() as u32), Some(code));
+        }
+        assert_eq!(AbortCode::from_exit_code(0), None);
+        assert_eq!(AbortCode::from_exit_code(256 + 21), None);
+    }
+
+    #[test]
+    fn test_classify_guest_messages() {
+        let cases = [
+            ("input note 1: spending key does not match note pubkey", AbortCode::Ownership),
+            ("spending key and policy do not match note pubkey", AbortCode::Ownership),
+            ("Merkle proof invalid for input note 0", AbortCode::Merkle),
+            ("counter Merkle proof invalid", AbortCode::Policy),
+            ("assertion `left != right` failed: duplicate input note", AbortCode::DuplicateInput),
+            ("duplicate payload", AbortCode::Disclosure),
+            ("missing spend authorization signature", AbortCode::SpendAuthorization),
+            ("partial withdrawal amounts don't balance", AbortCode::Conservation),
+            ("output label names no input", AbortCode::Association),
+            ("failed to deserialize", AbortCode::Unclassified),
+        ];
+        for (message, code) in cases {
+            assert_eq!(AbortCode::classify(message), code, "{message}");
+        }
+    }
+}
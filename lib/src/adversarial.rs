@@ -0,0 +1,639 @@
+//! Adversarial circuit inputs (feature `adversarial`).
+//!
+//! A fixed corpus of inputs that each break exactly one rule the guests are
+//! meant to enforce: keys that do not own the note, Merkle proofs that are
+//! cut short or point at a tampered root, amounts that only balance when
+//! they wrap around `u64` or reach `MAX_AMOUNT`, the same note spent twice in
+//! one transfer, a spend the owner never signed, and so on. The
+//! `adversarial` binary executes every case in the zkVM and fails unless the
+//! guest rejects it, so a constraint lost while the circuits change shows up
+//! as a failing run rather than as an exploitable proof.
+//!
+//! `controls` are the untouched inputs each case was derived from. They must
+//! execute successfully; otherwise a broken harness would make every case
+//! look rejected.
+//!
+//! Everything is derived from fixed keys and blindings, so the corpus is
+//! identical on every run.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::cancellable::CancellableLock;
+use crate::join_split::JoinSplitInput;
+use crate::multisig::{MultisigLock, MultisigSpend};
+use crate::note_encryption::OutputEncryption;
+use crate::{
+    derive_pubkey, keccak256, CircuitKind, IncrementalMerkleTree, MerkleProofStep, Note, SpenderKey,
+    TransferPrivateInputs, WithdrawPrivateInputs, MAX_AMOUNT,
+};
+
+/// Depth of the corpus trees.
+pub const CORPUS_LEVELS: usize = 8;
+
+/// Filler leaves inserted before the corpus notes, so proofs have real siblings.
+const FILLER_LEAVES: u32 = 5;
+
+const OWNER_KEY: [u8; 32] = [0xA1; 32];
+const OTHER_KEY: [u8; 32] = [0xB2; 32];
+const SENDER_KEY: [u8; 32] = [0xC3; 32];
+const RECIPIENT: [u8; 20] = [0x42; 20];
+const RELAYER: [u8; 20] = [0x52; 20];
+const RECLAIM_DEADLINE: u64 = 1_900_000_000;
+
+/// Private inputs for one of the two circuits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "circuit", content = "inputs", rename_all = "lowercase")]
+pub enum CaseInputs {
+    Transfer(Box<TransferPrivateInputs>),
+    Withdraw(Box<WithdrawPrivateInputs>),
+}
+
+impl CaseInputs {
+    pub fn kind(&self) -> CircuitKind {
+        match self {
+            CaseInputs::Transfer(_) => CircuitKind::Transfer,
+            CaseInputs::Withdraw(_) => CircuitKind::Withdraw,
+        }
+    }
+}
+
+/// One corpus entry.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdversarialCase {
+    /// `circuit/attack/variant`, unique within the corpus
+    pub name: String,
+    /// The guest check expected to reject it (for reports only)
+    pub rejected_by: String,
+    pub inputs: CaseInputs,
+}
+
+impl AdversarialCase {
+    fn new(name: &str, rejected_by: &str, inputs: CaseInputs) -> Self {
+        AdversarialCase { name: name.to_string(), rejected_by: rejected_by.to_string(), inputs }
+    }
+}
+
+fn blinding(tag: u8) -> [u8; 32] {
+    let mut b = [tag; 32];
+    b[0] = 0xB1;
+    b
+}
+
+/// Tree holding filler leaves followed by `notes`; returns the root and one
+/// proof per note.
+fn place(notes: &[&Note]) -> ([u8; 32], Vec<Vec<MerkleProofStep>>) {
+    let mut tree = IncrementalMerkleTree::new(CORPUS_LEVELS);
+    for i in 0..FILLER_LEAVES {
+        tree.insert(keccak256(&i.to_be_bytes()));
+    }
+    let indices: Vec<u32> = notes.iter().map(|n| tree.insert(n.commitment())).collect();
+    let proofs = indices.iter().map(|&i| tree.get_proof(i)).collect();
+    (tree.get_root(), proofs)
+}
+
+/// A transfer of two notes owned by `OWNER_KEY` with the given amounts.
+fn transfer(amounts_in: [u64; 2], amounts_out: [u64; 2]) -> TransferPrivateInputs {
+    let owner = derive_pubkey(&OWNER_KEY);
+    let input_notes = [
+        Note { amount: amounts_in[0], pubkey: owner, blinding: blinding(1) },
+        Note { amount: amounts_in[1], pubkey: owner, blinding: blinding(2) },
+    ];
+    let (root, proofs) = place(&[&input_notes[0], &input_notes[1]]);
+    let [proof0, proof1]: [Vec<MerkleProofStep>; 2] = proofs.try_into().unwrap();
+    let inputs = TransferPrivateInputs {
+        input_notes,
+        spender_keys: [SpenderKey::derive(&OWNER_KEY); 2],
+        merkle_proofs: [proof0, proof1],
+        output_notes: [
+            Note { amount: amounts_out[0], pubkey: derive_pubkey(&OTHER_KEY), blinding: blinding(3) },
+            Note { amount: amounts_out[1], pubkey: owner, blinding: blinding(4) },
+        ],
+        root,
+        locks: [None, None],
+        association: None,
+        pool_binding: [0u8; 32],
+        output_encryption: None,
+        multisig: [None, None],
+        spend_signatures: Vec::new(),
+    };
+    signed_transfer(inputs, &[OWNER_KEY])
+}
+
+/// A transfer of one `OWNER_KEY` note, padded with a dummy input.
+fn dummy_transfer(amount_in: u64, amounts_out: [u64; 2]) -> TransferPrivateInputs {
+    let owner = derive_pubkey(&OWNER_KEY);
+    let note = Note { amount: amount_in, pubkey: owner, blinding: blinding(1) };
+    let (root, mut proofs) = place(&[&note]);
+    let input = JoinSplitInput { note, spending_key: OWNER_KEY, merkle_proof: proofs.remove(0), lock: None };
+    let output_notes = [
+        Note { amount: amounts_out[0], pubkey: derive_pubkey(&OTHER_KEY), blinding: blinding(3) },
+        Note { amount: amounts_out[1], pubkey: owner, blinding: blinding(4) },
+    ];
+    signed_transfer(TransferPrivateInputs::single_input(input, output_notes, root, blinding(7)), &[OWNER_KEY])
+}
+
+/// A withdrawal of one `OWNER_KEY` note worth `amount`.
+fn withdraw(amount: u64, withdraw_amount: u64, change: Option<u64>) -> WithdrawPrivateInputs {
+    let owner = derive_pubkey(&OWNER_KEY);
+    let input_note = Note { amount, pubkey: owner, blinding: blinding(5) };
+    let (root, mut proofs) = place(&[&input_note]);
+    let inputs = WithdrawPrivateInputs {
+        input_note,
+        spender_key: SpenderKey::derive(&OWNER_KEY),
+        merkle_proof: proofs.remove(0),
+        root,
+        recipient: RECIPIENT,
+        withdraw_amount,
+        change_note: change.map(|amount| Note { amount, pubkey: owner, blinding: blinding(6) }),
+        lock: None,
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: [0u8; 32],
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: None,
+        spend_signatures: Vec::new(),
+    };
+    signed_withdraw(inputs, &[OWNER_KEY])
+}
+
+/// A cancellable note from `SENDER_KEY` to `OWNER_KEY`, withdrawn in full.
+fn cancellable_withdraw(spending_key: [u8; 32], reclaim_deadline: u64) -> WithdrawPrivateInputs {
+    let lock = CancellableLock {
+        recipient_pubkey: derive_pubkey(&OWNER_KEY),
+        sender_pubkey: derive_pubkey(&SENDER_KEY),
+        reclaim_deadline,
+    };
+    let input_note = lock.note(250_000, blinding(7));
+    let (root, mut proofs) = place(&[&input_note]);
+    let inputs = WithdrawPrivateInputs {
+        input_note,
+        spender_key: SpenderKey::derive(&spending_key),
+        merkle_proof: proofs.remove(0),
+        root,
+        recipient: RECIPIENT,
+        withdraw_amount: 250_000,
+        change_note: None,
+        lock: Some(lock),
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: [0u8; 32],
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: None,
+        spend_signatures: Vec::new(),
+    };
+    signed_withdraw(inputs, &[spending_key])
+}
+
+/// A multisig note of `OWNER_KEY` and `OTHER_KEY`, half withdrawn with the
+/// change back to the multisig, spent and signed with `cosigner_key` as the second key.
+fn multisig_withdraw(cosigner_key: [u8; 32]) -> WithdrawPrivateInputs {
+    let lock = MultisigLock { owner_pubkeys: [derive_pubkey(&OWNER_KEY), derive_pubkey(&OTHER_KEY)] };
+    let input_note = lock.note(500_000, blinding(8));
+    let (root, mut proofs) = place(&[&input_note]);
+    let inputs = WithdrawPrivateInputs {
+        input_note,
+        spender_key: SpenderKey::derive(&OWNER_KEY),
+        merkle_proof: proofs.remove(0),
+        root,
+        recipient: RECIPIENT,
+        withdraw_amount: 250_000,
+        change_note: Some(lock.note(250_000, blinding(9))),
+        lock: None,
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: [0u8; 32],
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: Some(MultisigSpend { lock, cosigner: SpenderKey::derive(&cosigner_key) }),
+        spend_signatures: Vec::new(),
+    };
+    signed_withdraw(inputs, &[OWNER_KEY, cosigner_key])
+}
+
+/// `inputs` signed by each of `keys`. Inputs the guests reject before
+/// checking signatures stay unsigned.
+fn signed_transfer(mut inputs: TransferPrivateInputs, keys: &[[u8; 32]]) -> TransferPrivateInputs {
+    for key in keys {
+        if inputs.sign(key).is_err() {
+            break;
+        }
+    }
+    inputs
+}
+
+/// `inputs` signed by each of `keys`, as `signed_transfer`.
+fn signed_withdraw(mut inputs: WithdrawPrivateInputs, keys: &[[u8; 32]]) -> WithdrawPrivateInputs {
+    for key in keys {
+        if inputs.sign(key).is_err() {
+            break;
+        }
+    }
+    inputs
+}
+
+fn flip(bytes: &mut [u8; 32]) {
+    bytes[31] ^= 0x01;
+}
+
+/// Valid inputs the guests must accept: the bases every case is derived from.
+pub fn controls() -> Vec<AdversarialCase> {
+    vec![
+        AdversarialCase::new(
+            "transfer/control",
+            "nothing",
+            CaseInputs::Transfer(Box::new(transfer([700_000, 300_000], [600_000, 400_000]))),
+        ),
+        AdversarialCase::new(
+            "transfer/control/below_max_amount",
+            "nothing",
+            CaseInputs::Transfer(Box::new(transfer([MAX_AMOUNT - 1, 0], [MAX_AMOUNT - 1, 0]))),
+        ),
+        AdversarialCase::new(
+            "transfer/control/dummy_input",
+            "nothing",
+            CaseInputs::Transfer(Box::new(dummy_transfer(1_000_000, [600_000, 400_000]))),
+        ),
+        AdversarialCase::new(
+            "transfer/control/proven_encryption",
+            "nothing",
+            CaseInputs::Transfer(Box::new(signed_transfer(
+                TransferPrivateInputs {
+                    output_encryption: Some([0x10, 0x20].map(|seed| OutputEncryption {
+                        viewing_pubkey: [seed; 32],
+                        ephemeral_secret: [seed + 1; 32],
+                        nonce: [seed + 2; 24],
+                    })),
+                    ..transfer([700_000, 300_000], [600_000, 400_000])
+                },
+                &[OWNER_KEY],
+            ))),
+        ),
+        AdversarialCase::new(
+            "withdraw/control/partial",
+            "nothing",
+            CaseInputs::Withdraw(Box::new(withdraw(1_000_000, 400_000, Some(600_000)))),
+        ),
+        AdversarialCase::new(
+            "withdraw/control/claim",
+            "nothing",
+            CaseInputs::Withdraw(Box::new(cancellable_withdraw(OWNER_KEY, RECLAIM_DEADLINE))),
+        ),
+        AdversarialCase::new(
+            "withdraw/control/multisig",
+            "nothing",
+            CaseInputs::Withdraw(Box::new(multisig_withdraw(OTHER_KEY))),
+        ),
+        AdversarialCase::new(
+            "withdraw/control/relayed",
+            "nothing",
+            CaseInputs::Withdraw(Box::new(signed_withdraw(
+                WithdrawPrivateInputs { fee: 20_000, relayer: RELAYER, ..withdraw(1_000_000, 380_000, Some(600_000)) },
+                &[OWNER_KEY],
+            ))),
+        ),
+        AdversarialCase::new(
+            "withdraw/control/call",
+            "nothing",
+            CaseInputs::Withdraw(Box::new(signed_withdraw(
+                WithdrawPrivateInputs {
+                    call_target: RECIPIENT,
+                    calldata_hash: keccak256(b"bridge(uint256)"),
+                    ..withdraw(1_000_000, 400_000, Some(600_000))
+                },
+                &[OWNER_KEY],
+            ))),
+        ),
+        AdversarialCase::new(
+            "withdraw/control/max_amount",
+            "nothing",
+            CaseInputs::Withdraw(Box::new(withdraw(u64::MAX, u64::MAX, None))),
+        ),
+    ]
+}
+
+/// Every adversarial case, transfer cases first.
+pub fn corpus() -> Vec<AdversarialCase> {
+    let mut cases = transfer_cases();
+    cases.extend(withdraw_cases());
+    cases
+}
+
+fn transfer_cases() -> Vec<AdversarialCase> {
+    let base = transfer([700_000, 300_000], [600_000, 400_000]);
+    let mut cases = Vec::new();
+    let mut add = |name: &str, rejected_by: &str, edit: &dyn Fn(&mut TransferPrivateInputs)| {
+        let mut inputs = base.clone();
+        edit(&mut inputs);
+        cases.push(AdversarialCase::new(
+            &format!("transfer/{name}"),
+            rejected_by,
+            CaseInputs::Transfer(Box::new(inputs)),
+        ));
+    };
+
+    for i in 0..2 {
+        add(&format!("mismatched_key/input{i}"), "ownership", &|t| {
+            t.spender_keys[i] = SpenderKey::derive(&OTHER_KEY)
+        });
+        add(&format!("truncated_proof/input{i}"), "merkle proof", &|t| {
+            t.merkle_proofs[i].truncate(CORPUS_LEVELS / 2)
+        });
+        add(&format!("depth_shortened_proof/input{i}"), "merkle proof", &|t| {
+            t.merkle_proofs[i].pop();
+        });
+        add(&format!("tampered_sibling/input{i}"), "merkle proof", &|t| {
+            flip(&mut t.merkle_proofs[i][0].sibling)
+        });
+        add(&format!("flipped_direction/input{i}"), "merkle proof", &|t| {
+            t.merkle_proofs[i][0].is_left ^= true
+        });
+    }
+    add("mismatched_key/swapped_pubkey", "ownership", &|t| {
+        t.input_notes[0].pubkey = derive_pubkey(&OTHER_KEY)
+    });
+    add("empty_proof", "merkle proof", &|t| t.merkle_proofs[1].clear());
+    add("tampered_root", "merkle proof", &|t| flip(&mut t.root));
+    add("zero_outputs", "conservation", &|t| {
+        t.output_notes[0].amount = 0;
+        t.output_notes[1].amount = 0;
+    });
+    add("inflated_output", "conservation", &|t| t.output_notes[0].amount += 1);
+    add("unexpected_lock", "ownership", &|t| {
+        t.locks[0] = Some(CancellableLock {
+            recipient_pubkey: derive_pubkey(&OWNER_KEY),
+            sender_pubkey: derive_pubkey(&SENDER_KEY),
+            reclaim_deadline: RECLAIM_DEADLINE,
+        })
+    });
+    add("duplicate_inputs", "distinct inputs", &|t| {
+        // Spend note 0 twice and mint twice its value
+        t.input_notes[1] = t.input_notes[0].clone();
+        t.merkle_proofs[1] = t.merkle_proofs[0].clone();
+        t.output_notes[0].amount = 1_000_000;
+        t.output_notes[1].amount = 400_000;
+    });
+    add("spend_auth/missing", "spend authorization", &|t| t.spend_signatures.clear());
+    add("spend_auth/foreign_key", "spend authorization", &|t| {
+        // Signed by a key that owns neither input
+        t.spend_signatures.clear();
+        t.sign(&OTHER_KEY).unwrap();
+    });
+    add("spend_auth/stale", "spend authorization", &|t| {
+        // A prover redirecting an output after the owner signed
+        t.output_notes[0].pubkey = derive_pubkey(&SENDER_KEY);
+    });
+
+    // Amounts that balance only modulo 2^64
+    cases.push(AdversarialCase::new(
+        "transfer/max_amount/inputs_wrap_to_zero",
+        "conservation",
+        CaseInputs::Transfer(Box::new(transfer([u64::MAX, 1], [0, 0]))),
+    ));
+    cases.push(AdversarialCase::new(
+        "transfer/max_amount/outputs_wrap",
+        "output range",
+        CaseInputs::Transfer(Box::new(transfer([1_000_000, 0], [u64::MAX, 1_000_001]))),
+    ));
+    // Balanced, but an output reaches the bound
+    cases.push(AdversarialCase::new(
+        "transfer/max_amount/output_at_bound",
+        "output range",
+        CaseInputs::Transfer(Box::new(transfer([MAX_AMOUNT, 0], [MAX_AMOUNT, 0]))),
+    ));
+    // A dummy skips the Merkle check only while it holds nothing
+    let mut valued_dummy = dummy_transfer(1_000_000, [700_000, 400_000]);
+    valued_dummy.input_notes[1].amount = 100_000;
+    cases.push(AdversarialCase::new(
+        "transfer/dummy/valued_dummy",
+        "merkle proof",
+        CaseInputs::Transfer(Box::new(valued_dummy)),
+    ));
+    cases
+}
+
+fn withdraw_cases() -> Vec<AdversarialCase> {
+    let base = withdraw(1_000_000, 400_000, Some(600_000));
+    let mut cases = Vec::new();
+    let mut add = |name: &str, rejected_by: &str, edit: &dyn Fn(&mut WithdrawPrivateInputs)| {
+        let mut inputs = base.clone();
+        edit(&mut inputs);
+        cases.push(AdversarialCase::new(
+            &format!("withdraw/{name}"),
+            rejected_by,
+            CaseInputs::Withdraw(Box::new(inputs)),
+        ));
+    };
+
+    add("mismatched_key", "ownership", &|w| w.spender_key = SpenderKey::derive(&OTHER_KEY));
+    add("truncated_proof", "merkle proof", &|w| w.merkle_proof.truncate(CORPUS_LEVELS / 2));
+    add("depth_shortened_proof", "merkle proof", &|w| {
+        w.merkle_proof.pop();
+    });
+    add("empty_proof", "merkle proof", &|w| w.merkle_proof.clear());
+    add("tampered_sibling", "merkle proof", &|w| flip(&mut w.merkle_proof[0].sibling));
+    add("tampered_root", "merkle proof", &|w| flip(&mut w.root));
+    add("zero_outputs", "conservation", &|w| {
+        w.withdraw_amount = 0;
+        w.change_note.as_mut().unwrap().amount = 0;
+    });
+    add("over_withdraw/full", "conservation", &|w| {
+        w.withdraw_amount = 1_000_001;
+        w.change_note = None;
+    });
+    add("over_withdraw/partial", "conservation", &|w| w.withdraw_amount += 1);
+    add("max_amount/change_wraps", "conservation", &|w| {
+        // u64::MAX + 1_000_001 wraps to 1_000_000, the note's amount
+        w.withdraw_amount = u64::MAX;
+        w.change_note.as_mut().unwrap().amount = 1_000_001;
+    });
+    add("change/foreign_pubkey", "change owner", &|w| {
+        w.change_note.as_mut().unwrap().pubkey = derive_pubkey(&OTHER_KEY);
+    });
+    add("fee/zero_relayer", "relayer", &|w| {
+        w.withdraw_amount -= 20_000;
+        w.fee = 20_000;
+    });
+    add("fee/unbalanced", "conservation", &|w| {
+        w.fee = 20_000;
+        w.relayer = RELAYER;
+    });
+    add("call/calldata_without_target", "call", &|w| w.calldata_hash = keccak256(b"bridge(uint256)"));
+    add("fee/wraps", "conservation", &|w| {
+        // 400_000 + 600_000 + (u64::MAX - 999_999) wraps to 1_000_000
+        w.fee = u64::MAX - 999_999;
+        w.relayer = RELAYER;
+    });
+    add("spend_auth/missing", "spend authorization", &|w| w.spend_signatures.clear());
+    add("spend_auth/stale", "spend authorization", &|w| {
+        // A prover redirecting the withdrawal after the owner signed
+        w.recipient = [0x66; 20];
+    });
+    add("spend_auth/forged", "spend authorization", &|w| w.spend_signatures[0].s[0] ^= 0x01);
+
+    cases.push(AdversarialCase::new(
+        "withdraw/max_amount/change_at_bound",
+        "output range",
+        CaseInputs::Withdraw(Box::new(withdraw(MAX_AMOUNT, 0, Some(MAX_AMOUNT)))),
+    ));
+
+    let mut add_lock = |name: &str, rejected_by: &str, inputs: WithdrawPrivateInputs| {
+        cases.push(AdversarialCase::new(
+            &format!("withdraw/cancellable/{name}"),
+            rejected_by,
+            CaseInputs::Withdraw(Box::new(inputs)),
+        ));
+    };
+    add_lock(
+        "third_party_key",
+        "ownership",
+        cancellable_withdraw(OTHER_KEY, RECLAIM_DEADLINE),
+    );
+    add_lock("reclaim_without_deadline", "ownership", cancellable_withdraw(SENDER_KEY, 0));
+    let mut missing_lock = cancellable_withdraw(OWNER_KEY, RECLAIM_DEADLINE);
+    missing_lock.lock = None;
+    add_lock("missing_lock", "ownership", missing_lock);
+    let mut wrong_lock = cancellable_withdraw(OWNER_KEY, RECLAIM_DEADLINE);
+    if let Some(lock) = wrong_lock.lock.as_mut() {
+        lock.reclaim_deadline += 1;
+    }
+    add_lock("lock_mismatch", "ownership", wrong_lock);
+
+    let mut add_multisig = |name: &str, rejected_by: &str, inputs: WithdrawPrivateInputs| {
+        cases.push(AdversarialCase::new(
+            &format!("withdraw/multisig/{name}"),
+            rejected_by,
+            CaseInputs::Withdraw(Box::new(inputs)),
+        ));
+    };
+    add_multisig("one_key_twice", "ownership", multisig_withdraw(OWNER_KEY));
+    let mut missing_cosigner = multisig_withdraw(OTHER_KEY);
+    missing_cosigner.multisig = None;
+    add_multisig("missing_cosigner", "ownership", missing_cosigner);
+    let mut change_to_one_owner = multisig_withdraw(OTHER_KEY);
+    change_to_one_owner.change_note.as_mut().unwrap().pubkey = derive_pubkey(&OWNER_KEY);
+    add_multisig("change_to_one_owner", "change owner", change_to_one_owner);
+    let mut one_signature = multisig_withdraw(OTHER_KEY);
+    one_signature.spend_signatures.truncate(1);
+    add_multisig("missing_cosigner_signature", "spend authorization", one_signature);
+    cases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multisig::{authorize_input, owns_change};
+    use crate::simulate::{simulate_transfer, simulate_withdraw};
+    use crate::{
+        call_binding, check_output_amount, check_spend_signatures, validate_transfer_inputs, verify_merkle_proof,
+    };
+    use alloc::collections::BTreeSet;
+
+    /// What the guests check, on the host. Returns the first failed rule.
+    fn guest_rules(inputs: &CaseInputs) -> Result<(), &'static str> {
+        match inputs {
+            CaseInputs::Transfer(t) => {
+                let mut nullifiers = Vec::new();
+                for i in 0..2 {
+                    let auth = authorize_input(
+                        &t.input_notes[i],
+                        &t.spender_keys[i],
+                        t.locks[i].as_ref(),
+                        t.multisig[i].as_ref(),
+                    )?;
+                    let commitment = t.input_notes[i].commitment();
+                    if t.input_notes[i].amount != 0 && !verify_merkle_proof(commitment, &t.merkle_proofs[i], t.root) {
+                        return Err("merkle proof");
+                    }
+                    nullifiers.push(auth.nullifier);
+                }
+                if nullifiers[0] == nullifiers[1] {
+                    return Err("distinct inputs");
+                }
+                if validate_transfer_inputs(t).is_err() {
+                    return Err("output range");
+                }
+                let sum = |notes: &[Note; 2]| notes.iter().map(|n| n.amount as u128).sum::<u128>();
+                if sum(&t.input_notes) != sum(&t.output_notes) {
+                    return Err("conservation");
+                }
+                if check_spend_signatures(t.signers(), &t.spend_signatures, &t.spend_message()?).is_err() {
+                    return Err("spend authorization");
+                }
+            }
+            CaseInputs::Withdraw(w) => {
+                authorize_input(&w.input_note, &w.spender_key, w.lock.as_ref(), w.multisig.as_ref())?;
+                if !verify_merkle_proof(w.input_note.commitment(), &w.merkle_proof, w.root) {
+                    return Err("merkle proof");
+                }
+                if w.fee != 0 && w.relayer == [0u8; 20] {
+                    return Err("relayer");
+                }
+                let multisig = w.multisig.as_ref();
+                if w.change_note.as_ref().is_some_and(|n| !owns_change(&w.spender_key, multisig, &n.pubkey)) {
+                    return Err("change owner");
+                }
+                if w.change_note.as_ref().is_some_and(|n| check_output_amount(n.amount).is_err()) {
+                    return Err("output range");
+                }
+                let change = w.change_note.as_ref().map_or(0, |n| n.amount);
+                if w.withdraw_amount.checked_add(change).and_then(|sum| sum.checked_add(w.fee)) != Some(w.input_note.amount) {
+                    return Err("conservation");
+                }
+                if call_binding(&w.call_target, &w.calldata_hash).is_err() {
+                    return Err("call");
+                }
+                if check_spend_signatures(w.signers(), &w.spend_signatures, &w.spend_message()?).is_err() {
+                    return Err("spend authorization");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_controls_pass_and_corpus_fails_guest_rules() {
+        for case in controls() {
+            assert_eq!(guest_rules(&case.inputs), Ok(()), "{}", case.name);
+        }
+        for case in corpus() {
+            assert!(guest_rules(&case.inputs).is_err(), "{} passes", case.name);
+        }
+    }
+
+    #[test]
+    fn test_simulated_guests_reject_the_corpus() {
+        let simulate = |inputs: &CaseInputs| match inputs {
+            CaseInputs::Transfer(t) => simulate_transfer(t).map(|_| ()),
+            CaseInputs::Withdraw(w) => simulate_withdraw(w).map(|_| ()),
+        };
+        for case in controls() {
+            assert_eq!(simulate(&case.inputs), Ok(()), "{}", case.name);
+        }
+        for case in corpus() {
+            assert!(simulate(&case.inputs).is_err(), "{} passes", case.name);
+        }
+    }
+
+    #[test]
+    fn test_corpus_names_unique_and_deterministic() {
+        let names: BTreeSet<String> = corpus().into_iter().map(|c| c.name).collect();
+        assert_eq!(names.len(), corpus().len());
+        let json = serde_json::to_string(&corpus()).unwrap();
+        assert_eq!(json, serde_json::to_string(&corpus()).unwrap());
+        assert!(json.starts_with("[{\"name\":\"transfer/"));
+        assert!(json.contains("\"circuit\":\"withdraw\""));
+    }
+}
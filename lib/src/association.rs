@@ -0,0 +1,312 @@
+//! Association sets: proving spent notes descend from approved deposits.
+//!
+//! An association-set provider (ASP) publishes the root of a Merkle tree
+//! over deposits it approves. A transfer or withdrawal can opt in by proving
+//! each note it spends descends from one of them, and commits the root it
+//! proved against so anyone can check which set that was. Zero means the
+//! spend did not opt in; the pool accepts both.
+//!
+//! Descent is tracked with labels. An approved deposit's label is its own
+//! commitment. A note created by an associated spend carries the label of
+//! one of the notes it was made from, in its blinding:
+//!
+//!   blinding = keccak256(LABEL_DOMAIN || label || salt)
+//!
+//! Anyone can pick such a blinding, so a label alone proves nothing: a note
+//! deposited, or created by a spend that did not opt in, could claim any
+//! label. The set therefore holds two kinds of leaves, both built from
+//! chain data:
+//!
+//!   - `deposit_leaf(commitment)` for each approved deposit
+//!   - `labelled_note_leaf(commitment)` for each output of an associated
+//!     spend made against one of the ASP's own roots
+//!
+//! A spent note proves either that it is an approved deposit, or that it is
+//! a labelled note and its label is an approved deposit. Dropping a deposit
+//! from the set excludes every note labelled with it. A note made from two
+//! notes keeps only one of their labels, so value merged in from the other
+//! one is not traced further.
+//!
+//! The tree is binary with the pool's hashing, and its depth is the ASP's
+//! choice.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{keccak256, verify_merkle_proof, IncrementalMerkleTree, MerkleProofStep, Note};
+
+/// Domain separator of labelled blindings.
+pub const LABEL_DOMAIN: &[u8] = b"shielded-pool/association-label/1";
+
+/// Domain separator of association-set leaves.
+pub const LEAF_DOMAIN: &[u8] = b"shielded-pool/association-leaf/1";
+
+fn leaf(tag: u8, commitment: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(LEAF_DOMAIN.len() + 33);
+    preimage.extend_from_slice(LEAF_DOMAIN);
+    preimage.push(tag);
+    preimage.extend_from_slice(commitment);
+    keccak256(&preimage)
+}
+
+/// Leaf of an approved deposit.
+pub fn deposit_leaf(commitment: &[u8; 32]) -> [u8; 32] {
+    leaf(0, commitment)
+}
+
+/// Leaf of a note created by an associated spend.
+pub fn labelled_note_leaf(commitment: &[u8; 32]) -> [u8; 32] {
+    leaf(1, commitment)
+}
+
+/// Blinding of a note carrying `label`; `salt` must be fresh randomness.
+pub fn labelled_blinding(label: &[u8; 32], salt: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(LABEL_DOMAIN.len() + 64);
+    preimage.extend_from_slice(LABEL_DOMAIN);
+    preimage.extend_from_slice(label);
+    preimage.extend_from_slice(salt);
+    keccak256(&preimage)
+}
+
+/// Where a spent note comes from, with its proofs under the set's root.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Lineage {
+    /// The note is an approved deposit; its label is its commitment
+    Deposit { proof: Vec<MerkleProofStep> },
+    /// The note was created by an associated spend and carries `label`
+    Labelled {
+        label: [u8; 32],
+        salt: [u8; 32],
+        /// Membership of `labelled_note_leaf(commitment)`
+        note_proof: Vec<MerkleProofStep>,
+        /// Membership of `deposit_leaf(label)`
+        label_proof: Vec<MerkleProofStep>,
+    },
+}
+
+impl Lineage {
+    /// The label of `note`, if it descends from an approved deposit under
+    /// `root`.
+    pub fn verify(&self, note: &Note, root: [u8; 32]) -> Result<[u8; 32], &'static str> {
+        let commitment = note.commitment();
+        match self {
+            Lineage::Deposit { proof } => {
+                if !verify_merkle_proof(deposit_leaf(&commitment), proof, root) {
+                    return Err("deposit is not in the association set");
+                }
+                Ok(commitment)
+            }
+            Lineage::Labelled { label, salt, note_proof, label_proof } => {
+                if note.blinding != labelled_blinding(label, salt) {
+                    return Err("note does not carry the claimed label");
+                }
+                if !verify_merkle_proof(labelled_note_leaf(&commitment), note_proof, root) {
+                    return Err("note is not in the association set");
+                }
+                if !verify_merkle_proof(deposit_leaf(label), label_proof, root) {
+                    return Err("label is not in the association set");
+                }
+                Ok(*label)
+            }
+        }
+    }
+}
+
+/// How an output of an associated spend inherits a label.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputLabel {
+    /// Input slot whose label the output carries
+    pub from_input: u8,
+    pub salt: [u8; 32],
+}
+
+/// Association witness of a transfer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferAssociation {
+    /// The ASP's root, committed in the public values
+    pub root: [u8; 32],
+    pub inputs: [Lineage; 2],
+    pub outputs: [OutputLabel; 2],
+}
+
+/// Association witness of a withdrawal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawAssociation {
+    /// The ASP's root, committed in the public values
+    pub root: [u8; 32],
+    pub input: Lineage,
+    /// Salt of the change note's label (unused for full withdrawals)
+    #[serde(default)]
+    pub change_salt: [u8; 32],
+}
+
+impl TransferAssociation {
+    /// Check both inputs descend from approved deposits and both outputs
+    /// carry one of their labels.
+    pub fn check(&self, inputs: &[Note; 2], outputs: &[Note; 2]) -> Result<(), &'static str> {
+        let labels = [self.inputs[0].verify(&inputs[0], self.root)?, self.inputs[1].verify(&inputs[1], self.root)?];
+        for (note, output) in outputs.iter().zip(&self.outputs) {
+            let label = labels.get(output.from_input as usize).ok_or("output label names no input")?;
+            if note.blinding != labelled_blinding(label, &output.salt) {
+                return Err("output note does not inherit an input's label");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WithdrawAssociation {
+    /// Check the input descends from an approved deposit and the change note,
+    /// if any, carries its label.
+    pub fn check(&self, input: &Note, change: Option<&Note>) -> Result<(), &'static str> {
+        let label = self.input.verify(input, self.root)?;
+        if change.is_some_and(|change| change.blinding != labelled_blinding(&label, &self.change_salt)) {
+            return Err("output note does not inherit an input's label");
+        }
+        Ok(())
+    }
+}
+
+/// An ASP's association set.
+#[derive(Clone, Debug)]
+pub struct AssociationSet {
+    tree: IncrementalMerkleTree,
+}
+
+impl AssociationSet {
+    pub fn new(levels: usize) -> Self {
+        AssociationSet { tree: IncrementalMerkleTree::new(levels) }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.get_root()
+    }
+
+    /// Approve the deposit of `commitment`.
+    pub fn approve_deposit(&mut self, commitment: &[u8; 32]) -> Result<u32, &'static str> {
+        self.tree.try_insert(deposit_leaf(commitment))
+    }
+
+    /// Add an output of an associated spend made against this set.
+    pub fn add_labelled_note(&mut self, commitment: &[u8; 32]) -> Result<u32, &'static str> {
+        self.tree.try_insert(labelled_note_leaf(commitment))
+    }
+
+    fn prove(&self, leaf: [u8; 32]) -> Option<Vec<MerkleProofStep>> {
+        let index = self.tree.leaves.iter().position(|l| *l == leaf)?;
+        self.tree.try_get_proof(index as u32).ok()
+    }
+
+    /// Lineage of an approved deposit, or `None` if it is not approved.
+    pub fn deposit_lineage(&self, commitment: &[u8; 32]) -> Option<Lineage> {
+        Some(Lineage::Deposit { proof: self.prove(deposit_leaf(commitment))? })
+    }
+
+    /// Lineage of a labelled note, or `None` if the note or its label is not
+    /// in the set.
+    pub fn labelled_lineage(&self, commitment: &[u8; 32], label: [u8; 32], salt: [u8; 32]) -> Option<Lineage> {
+        Some(Lineage::Labelled {
+            label,
+            salt,
+            note_proof: self.prove(labelled_note_leaf(commitment))?,
+            label_proof: self.prove(deposit_leaf(&label))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derive_pubkey;
+
+    fn note(amount: u64, blinding: [u8; 32]) -> Note {
+        Note { amount, pubkey: derive_pubkey(&[1u8; 32]), blinding }
+    }
+
+    #[test]
+    fn test_lineage_follows_labels() {
+        let (approved, other) = (note(100, [1u8; 32]), note(50, [2u8; 32]));
+        let mut set = AssociationSet::new(6);
+        set.approve_deposit(&approved.commitment()).unwrap();
+        set.approve_deposit(&other.commitment()).unwrap();
+
+        // A deposit is its own label
+        let label = approved.commitment();
+        let lineage = set.deposit_lineage(&label).unwrap();
+        assert_eq!(lineage.verify(&approved, set.root()), Ok(label));
+
+        // Its descendants carry the label once the ASP records them
+        let child = note(60, labelled_blinding(&label, &[9u8; 32]));
+        assert!(set.labelled_lineage(&child.commitment(), label, [9u8; 32]).is_none());
+        set.add_labelled_note(&child.commitment()).unwrap();
+        let child_lineage = set.labelled_lineage(&child.commitment(), label, [9u8; 32]).unwrap();
+        assert_eq!(child_lineage.verify(&child, set.root()), Ok(label));
+
+        let spend = TransferAssociation {
+            root: set.root(),
+            inputs: [set.deposit_lineage(&other.commitment()).unwrap(), child_lineage.clone()],
+            outputs: [OutputLabel { from_input: 1, salt: [3u8; 32] }, OutputLabel { from_input: 0, salt: [4u8; 32] }],
+        };
+        let outputs = [
+            note(90, labelled_blinding(&label, &[3u8; 32])),
+            note(20, labelled_blinding(&other.commitment(), &[4u8; 32])),
+        ];
+        assert_eq!(spend.check(&[other.clone(), child.clone()], &outputs), Ok(()));
+
+        let withdraw = WithdrawAssociation { root: set.root(), input: child_lineage, change_salt: [5u8; 32] };
+        assert_eq!(withdraw.check(&child, None), Ok(()));
+        assert_eq!(withdraw.check(&child, Some(&note(10, labelled_blinding(&label, &[5u8; 32])))), Ok(()));
+        assert_eq!(
+            withdraw.check(&child, Some(&note(10, [5u8; 32]))),
+            Err("output note does not inherit an input's label")
+        );
+    }
+
+    #[test]
+    fn test_lineage_rejects_forgeries() {
+        let approved = note(100, [1u8; 32]);
+        let mut set = AssociationSet::new(6);
+        set.approve_deposit(&approved.commitment()).unwrap();
+        let label = approved.commitment();
+
+        // An unapproved deposit
+        let stranger = note(100, [2u8; 32]);
+        let approved_lineage = set.deposit_lineage(&label).unwrap();
+        assert_eq!(approved_lineage.verify(&stranger, set.root()), Err("deposit is not in the association set"));
+
+        // A note that picked an approved label but was not made by an
+        // associated spend (so the ASP never recorded it)
+        let mut elsewhere = AssociationSet::new(6);
+        elsewhere.approve_deposit(&label).unwrap();
+        let squatter = note(100, labelled_blinding(&label, &[7u8; 32]));
+        elsewhere.add_labelled_note(&squatter.commitment()).unwrap();
+        let lineage = elsewhere.labelled_lineage(&squatter.commitment(), label, [7u8; 32]).unwrap();
+        assert_eq!(lineage.verify(&squatter, set.root()), Err("note is not in the association set"));
+
+        // A recorded note claiming another label
+        let child = note(60, labelled_blinding(&label, &[9u8; 32]));
+        set.add_labelled_note(&child.commitment()).unwrap();
+        let Some(Lineage::Labelled { note_proof, label_proof, .. }) =
+            set.labelled_lineage(&child.commitment(), label, [9u8; 32])
+        else {
+            panic!("expected a labelled lineage");
+        };
+        let relabelled = Lineage::Labelled { label: [3u8; 32], salt: [9u8; 32], note_proof, label_proof };
+        assert_eq!(relabelled.verify(&child, set.root()), Err("note does not carry the claimed label"));
+
+        // A dropped deposit takes its descendants with it
+        let mut without = AssociationSet::new(6);
+        without.approve_deposit(&[0xaa; 32]).unwrap();
+        without.add_labelled_note(&child.commitment()).unwrap();
+        assert!(without.labelled_lineage(&child.commitment(), label, [9u8; 32]).is_none());
+
+        // An output must name an input and carry its label
+        let spend = TransferAssociation {
+            root: set.root(),
+            inputs: [set.deposit_lineage(&label).unwrap(), set.deposit_lineage(&label).unwrap()],
+            outputs: [OutputLabel { from_input: 2, salt: [0u8; 32] }, OutputLabel { from_input: 0, salt: [0u8; 32] }],
+        };
+        let outputs = [note(1, labelled_blinding(&label, &[0u8; 32])), note(1, labelled_blinding(&label, &[0u8; 32]))];
+        assert_eq!(spend.check(&[approved.clone(), approved.clone()], &outputs), Err("output label names no input"));
+    }
+}
@@ -0,0 +1,289 @@
+//! Proof of funds: unspent notes worth at least a threshold, nothing moved.
+//!
+//! A holder shows a counterparty (a lender taking collateral, an exchange
+//! checking solvency) that they hold at least `threshold` in the pool,
+//! without spending or revealing the notes. The attest circuit proves, for
+//! 1 to `ATTEST_NOTES` notes, that
+//!
+//!   - each note's commitment is in the tree under `root`,
+//!   - its spending key owns it,
+//!   - its pool nullifier is not in the `NullifierSet` under
+//!     `nullifier_root`, so it was unspent when that set was taken,
+//!   - no note is counted twice,
+//!   - their amounts add up to at least `threshold`,
+//!
+//! and commits only the two roots, the threshold and the counterparty's
+//! audit nonce. Nullifiers, commitments and the exact balance stay private,
+//! and nothing on chain changes.
+//!
+//! The counterparty picks a fresh nonce for each request, so an old proof
+//! cannot be replayed as a new one, and checks that `root` is a pool root and
+//! `nullifier_root` is the root of every nullifier spent when `root` was
+//! current (as for a claim snapshot). The proof says nothing about spends
+//! after that, and the same notes can back attestations to several parties
+//! at once; a counterparty that needs the funds held must take them (e.g. a
+//! transfer to a note it can reclaim), not an attestation. Cancellable and
+//! multisig notes cannot be attested: claim them into a plain note first.
+//!
+//! Public values committed (192 bytes = 6 × 32-byte slots):
+//!   [root, nullifierRoot, threshold (uint256 BE), nonce, treeLevels (uint256 BE),
+//!    poolBinding]
+//! for a verifier contract to read with
+//!   abi.decode(publicValues, (bytes32, bytes32, uint256, bytes32, uint256, bytes32))
+//! `treeLevels` is the common depth of the notes' Merkle proofs and
+//! `poolBinding` identifies the pool (see `crate::pool_binding`); the verifier
+//! must require the pool tree's depth and that pool's binding.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::nullifier_set::NullifierProof;
+use crate::{compute_nullifier, probe, proof_levels, verify_merkle_proof, CheckError, MerkleProofStep, Note};
+
+/// Most notes one attestation can count.
+pub const ATTEST_NOTES: usize = 8;
+
+/// One attested note with its proofs.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AttestNote {
+    pub note: Note,
+    /// Spending key owning the note
+    pub spending_key: [u8; 32],
+    /// Merkle proof of the note under `root`
+    pub merkle_proof: Vec<MerkleProofStep>,
+    /// Non-membership of the note's pool nullifier under `nullifier_root`
+    pub nullifier_proof: NullifierProof,
+}
+
+/// Private inputs for the attest circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AttestPrivateInputs {
+    /// 1 to `ATTEST_NOTES` notes
+    pub notes: Vec<AttestNote>,
+    /// The tree root every note's proof verifies against
+    pub root: [u8; 32],
+    /// Root of the nullifiers spent as of `root`
+    pub nullifier_root: [u8; 32],
+    /// The notes must hold at least this much
+    pub threshold: u64,
+    /// Fresh value chosen by the counterparty
+    pub nonce: [u8; 32],
+    /// The pool the proof is for (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the attest circuit (192 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttestPublicValues {
+    pub root: [u8; 32],
+    pub nullifier_root: [u8; 32],
+    pub threshold: u64,
+    pub nonce: [u8; 32],
+    /// Depth of the notes' Merkle proofs (see `proof_levels`)
+    pub tree_levels: u64,
+    /// The pool the proof is for (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+impl AttestNote {
+    /// Check ownership, membership under `root` and non-membership of the
+    /// note's nullifier under `nullifier_root`, returning its commitment.
+    pub fn check(&self, root: [u8; 32], nullifier_root: [u8; 32]) -> Result<[u8; 32], &'static str> {
+        // Cancellable and multisig notes are rejected: no key derives their
+        // pubkey
+        probe!(start "attest/ownership");
+        if !self.note.is_owned_by(&self.spending_key) {
+            return Err("spending key does not match note pubkey");
+        }
+        probe!(end "attest/ownership");
+        let commitment = self.note.commitment();
+        probe!(start "attest/merkle");
+        if !verify_merkle_proof(commitment, &self.merkle_proof, root) {
+            return Err("Merkle proof invalid");
+        }
+        probe!(end "attest/merkle");
+        probe!(start "attest/unspent");
+        let nullifier = compute_nullifier(&commitment, &self.spending_key);
+        match self.nullifier_proof.verify(nullifier_root, &nullifier) {
+            Ok(false) => {}
+            Ok(true) => return Err("note was spent at the snapshot"),
+            Err(_) => return Err("nullifier proof does not match the nullifier root"),
+        }
+        probe!(end "attest/unspent");
+        Ok(commitment)
+    }
+}
+
+impl AttestPrivateInputs {
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks
+    /// (`guest_check`).
+    pub fn check(&self) -> Result<AttestPublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The attest guest's checks, in its order. A rejected note is named.
+    pub fn guest_check(&self) -> Result<AttestPublicValues, CheckError> {
+        probe!(start "attest/shape");
+        if self.notes.is_empty() || self.notes.len() > ATTEST_NOTES {
+            return Err("attestation counts 1 to 8 notes".into());
+        }
+        probe!(end "attest/shape");
+        let mut commitments = Vec::with_capacity(self.notes.len());
+        for (i, attested) in self.notes.iter().enumerate() {
+            let commitment =
+                attested.check(self.root, self.nullifier_root).map_err(|msg| CheckError::at("note", i, msg))?;
+            commitments.push(commitment);
+        }
+        // Counting one note twice would attest its value twice
+        probe!(start "attest/distinct_inputs");
+        if (1..commitments.len()).any(|i| commitments[..i].contains(&commitments[i])) {
+            return Err("duplicate input note".into());
+        }
+        probe!(end "attest/distinct_inputs");
+        // A shorter proof can reach a known root from an inner node
+        probe!(start "attest/proof_depth");
+        let tree_levels = proof_levels(self.notes.iter().map(|n| &n.merkle_proof))?;
+        probe!(end "attest/proof_depth");
+        // The notes cover the threshold; how far above it stays private
+        probe!(start "attest/threshold");
+        let total: u128 = self.notes.iter().map(|n| n.note.amount as u128).sum();
+        if total < self.threshold as u128 {
+            return Err("notes hold less than the threshold".into());
+        }
+        probe!(end "attest/threshold");
+        Ok(AttestPublicValues {
+            root: self.root,
+            nullifier_root: self.nullifier_root,
+            threshold: self.threshold,
+            nonce: self.nonce,
+            tree_levels,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl AttestPublicValues {
+    pub const LEN: usize = 192;
+
+    /// ABI-encode exactly as the attest guest commits it.
+    pub fn encode(&self) -> [u8; 192] {
+        let mut out = [0u8; 192];
+        out[0..32].copy_from_slice(&self.root);
+        out[32..64].copy_from_slice(&self.nullifier_root);
+        out[88..96].copy_from_slice(&self.threshold.to_be_bytes());
+        out[96..128].copy_from_slice(&self.nonce);
+        out[152..160].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[160..192].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the threshold or the tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN || bytes[64..88].iter().any(|&b| b != 0) || bytes[128..152].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(AttestPublicValues {
+            root: word(0),
+            nullifier_root: word(1),
+            threshold: u64::from_be_bytes(bytes[88..96].try_into().unwrap()),
+            nonce: word(3),
+            tree_levels: u64::from_be_bytes(bytes[152..160].try_into().unwrap()),
+            pool_binding: word(5),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nullifier_set::NullifierSet;
+    use crate::{derive_pubkey, keccak256, IncrementalMerkleTree};
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    /// An attestation of notes of `amounts` to `threshold`, with `spent`
+    /// (indices into the notes) in the nullifier set.
+    fn attest(amounts: &[u64], spent: &[usize], threshold: u64) -> AttestPrivateInputs {
+        let notes: Vec<Note> = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note { amount, pubkey: derive_pubkey(&KEY), blinding: [i as u8 + 1; 32] })
+            .collect();
+        let mut tree = IncrementalMerkleTree::new(8);
+        let mut set = NullifierSet::new();
+        set.insert(keccak256(b"someone else's spend"));
+        for (i, n) in notes.iter().enumerate() {
+            tree.insert(n.commitment());
+            if spent.contains(&i) {
+                set.insert(compute_nullifier(&n.commitment(), &KEY));
+            }
+        }
+        let notes = notes
+            .into_iter()
+            .enumerate()
+            .map(|(i, note)| AttestNote {
+                nullifier_proof: set.prove(&compute_nullifier(&note.commitment(), &KEY)),
+                note,
+                spending_key: KEY,
+                merkle_proof: tree.get_proof(i as u32),
+            })
+            .collect();
+        AttestPrivateInputs {
+            notes,
+            root: tree.get_root(),
+            nullifier_root: set.root(),
+            threshold,
+            nonce: [0xa5; 32],
+            pool_binding: [0x5b; 32],
+        }
+    }
+
+    #[test]
+    fn test_attest_proves_threshold_only() {
+        let inputs = attest(&[100, 250, 50], &[], 400);
+        let pv = inputs.check().unwrap();
+        assert_eq!((pv.threshold, pv.nonce, pv.tree_levels), (400, [0xa5; 32], 8));
+        assert_eq!(pv.pool_binding, [0x5b; 32]);
+        assert_eq!(AttestPublicValues::LEN, 192);
+        assert_eq!(AttestPublicValues::decode(&pv.encode()), Some(pv.clone()));
+        // The balance above the threshold stays private
+        assert_eq!(attest(&[100, 250, 50], &[], 1).check().unwrap().threshold, 1);
+        assert!(attest(&[1; ATTEST_NOTES], &[], 8).check().is_ok());
+
+        let mut bytes = pv.encode();
+        bytes[70] = 1;
+        assert_eq!(AttestPublicValues::decode(&bytes), None);
+    }
+
+    #[test]
+    fn test_attest_rejects_spent_short_or_foreign_notes() {
+        assert_eq!(attest(&[], &[], 0).check(), Err("attestation counts 1 to 8 notes"));
+        assert_eq!(attest(&[1; ATTEST_NOTES + 1], &[], 1).check(), Err("attestation counts 1 to 8 notes"));
+        assert_eq!(attest(&[100, 250], &[], 351).check(), Err("notes hold less than the threshold"));
+        assert_eq!(attest(&[100, 250], &[1], 100).check(), Err("note was spent at the snapshot"));
+
+        let base = attest(&[100, 250], &[], 350);
+        let mut duplicate = base.clone();
+        duplicate.notes[1] = duplicate.notes[0].clone();
+        duplicate.threshold = 200;
+        assert_eq!(duplicate.check(), Err("duplicate input note"));
+
+        let mut wrong_key = base.clone();
+        wrong_key.notes[0].spending_key = [8u8; 32];
+        assert_eq!(wrong_key.check(), Err("spending key does not match note pubkey"));
+
+        let mut stale = base.clone();
+        stale.root = [0u8; 32];
+        assert_eq!(stale.check(), Err("Merkle proof invalid"));
+
+        // A non-membership proof against another set does not count
+        let mut forged = base.clone();
+        forged.nullifier_root = attest(&[100, 250], &[0], 350).nullifier_root;
+        assert_eq!(forged.check(), Err("nullifier proof does not match the nullifier root"));
+    }
+}
@@ -0,0 +1,248 @@
+//! Batched withdrawals: up to eight notes out of the pool in one proof.
+//!
+//! Exiting N notes with the withdraw circuit takes N proofs and N
+//! transactions. The batch-withdraw circuit spends 1 to
+//! `BATCH_WITHDRAW_NOTES` notes to one recipient, under the withdraw
+//! circuit's rules for each (authorized, in the tree under `root`, spent
+//! once), and commits their total as one public amount. It has no change
+//! note: every note leaves the pool whole.
+//!
+//! Unused nullifier slots are zero. An exit already reveals the recipient
+//! and the amount, so padding with dummy notes would hide little and cost a
+//! storage write per slot; the pool skips zero nullifiers instead.
+//!
+//! Public values committed (448 bytes = 14 × 32-byte slots):
+//!   [root, nullifier0..7, recipient (left-padded), amount (uint256 BE),
+//!    reclaimDeadline (uint256 BE), treeLevels (uint256 BE), poolBinding]
+//! for `ShieldedPool.batchWithdraw` to read with
+//!   abi.decode(publicValues, (bytes32, bytes32[8], address, uint256, uint256, uint256, bytes32))
+//! It rejects a spent or repeated non-zero nullifier and requires
+//! `treeLevels` to be its tree's depth and `poolBinding` its own, as
+//! `withdraw` does for one.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::cancellable::committed_deadline;
+use crate::join_split::JoinSplitInput;
+use crate::{probe, spent_proof_levels, CheckError};
+
+/// Note slots of the batch-withdraw circuit.
+pub const BATCH_WITHDRAW_NOTES: usize = 8;
+
+/// Private inputs for the batch-withdraw circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BatchWithdrawPrivateInputs {
+    /// 1 to `BATCH_WITHDRAW_NOTES` notes to spend, each checked as a
+    /// join-split input is
+    pub inputs: Vec<JoinSplitInput>,
+    /// The Merkle root every input's proof verifies against
+    pub root: [u8; 32],
+    /// Address receiving the total (20 bytes)
+    pub recipient: [u8; 20],
+    /// The pool the proof is for (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the batch-withdraw circuit (448 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchWithdrawPublicValues {
+    pub root: [u8; 32],
+    /// One per spent note, then zeros
+    pub nullifiers: [[u8; 32]; BATCH_WITHDRAW_NOTES],
+    pub recipient: [u8; 20],
+    /// Sum of the spent notes' amounts
+    pub amount: u64,
+    /// Zero unless an input is a reclaimed cancellable note
+    pub reclaim_deadline: u64,
+    /// Depth of the inputs' Merkle proofs (see `spent_proof_levels`)
+    pub tree_levels: u64,
+    /// The pool the proof is for (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+impl BatchWithdrawPrivateInputs {
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks
+    /// (`guest_check`).
+    pub fn check(&self) -> Result<BatchWithdrawPublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The batch-withdraw guest's checks, in its order. A rejected input is
+    /// named.
+    pub fn guest_check(&self) -> Result<BatchWithdrawPublicValues, CheckError> {
+        // Between one note and one per nullifier slot
+        probe!(start "batch_withdraw/shape");
+        if self.inputs.is_empty() || self.inputs.len() > BATCH_WITHDRAW_NOTES {
+            return Err("batch withdraw spends 1 to 8 notes".into());
+        }
+        probe!(end "batch_withdraw/shape");
+        let mut auths = Vec::with_capacity(self.inputs.len());
+        for (i, input) in self.inputs.iter().enumerate() {
+            probe!(start "batch_withdraw/authorize");
+            let auth = input.authorize().map_err(|msg| CheckError::at("input note", i, msg))?;
+            probe!(end "batch_withdraw/authorize");
+            probe!(arm "batch_withdraw/authorize", crate::coverage::spend_arm(auth.path));
+            probe!(start "batch_withdraw/merkle");
+            if !input.is_member(self.root) {
+                return Err(CheckError::at("input note", i, "Merkle proof invalid"));
+            }
+            probe!(end "batch_withdraw/merkle");
+            probe!(arm "batch_withdraw/merkle", if input.note.amount != 0 { "member" } else { "zero_amount" });
+            auths.push(auth);
+        }
+        // Spending one note twice would repeat its nullifier and pay it out
+        // twice
+        probe!(start "batch_withdraw/distinct_inputs");
+        let mut nullifiers = [[0u8; 32]; BATCH_WITHDRAW_NOTES];
+        for (i, auth) in auths.iter().enumerate() {
+            if nullifiers[..i].contains(&auth.nullifier) {
+                return Err("duplicate input note".into());
+            }
+            nullifiers[i] = auth.nullifier;
+        }
+        probe!(end "batch_withdraw/distinct_inputs");
+        // A shorter proof can reach a known root from an inner node
+        probe!(start "batch_withdraw/proof_depth");
+        let tree_levels = spent_proof_levels(self.inputs.iter().map(|input| (&input.note, &input.merkle_proof)))?;
+        probe!(end "batch_withdraw/proof_depth");
+        // The public amount is the total of the inputs
+        probe!(start "batch_withdraw/amount");
+        let amount = self
+            .inputs
+            .iter()
+            .try_fold(0u64, |sum, input| sum.checked_add(input.note.amount))
+            .ok_or("batch amount overflows")?;
+        probe!(end "batch_withdraw/amount");
+        Ok(BatchWithdrawPublicValues {
+            root: self.root,
+            nullifiers,
+            recipient: self.recipient,
+            amount,
+            reclaim_deadline: committed_deadline(&auths),
+            tree_levels,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl BatchWithdrawPublicValues {
+    pub const LEN: usize = 32 * (6 + BATCH_WITHDRAW_NOTES);
+
+    /// ABI-encode exactly as the batch-withdraw guest commits it.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        let words = core::iter::once(&self.root).chain(&self.nullifiers);
+        for (chunk, word) in out.chunks_mut(32).zip(words) {
+            chunk.copy_from_slice(word);
+        }
+        let tail = 32 * (1 + BATCH_WITHDRAW_NOTES);
+        out[tail + 12..tail + 32].copy_from_slice(&self.recipient);
+        out[tail + 56..tail + 64].copy_from_slice(&self.amount.to_be_bytes());
+        out[tail + 88..tail + 96].copy_from_slice(&self.reclaim_deadline.to_be_bytes());
+        out[tail + 120..tail + 128].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[tail + 128..].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong,
+    /// the address padding is non-zero, or the amount, the deadline or the
+    /// tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let tail = 32 * (1 + BATCH_WITHDRAW_NOTES);
+        if bytes.len() != Self::LEN
+            || bytes[tail..tail + 12].iter().any(|&b| b != 0)
+            || bytes[tail + 32..tail + 56].iter().any(|&b| b != 0)
+            || bytes[tail + 64..tail + 88].iter().any(|&b| b != 0)
+            || bytes[tail + 96..tail + 120].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(BatchWithdrawPublicValues {
+            root: word(0),
+            nullifiers: core::array::from_fn(|i| word(1 + i)),
+            recipient: bytes[tail + 12..tail + 32].try_into().unwrap(),
+            amount: u64::from_be_bytes(bytes[tail + 56..tail + 64].try_into().unwrap()),
+            reclaim_deadline: u64::from_be_bytes(bytes[tail + 88..tail + 96].try_into().unwrap()),
+            tree_levels: u64::from_be_bytes(bytes[tail + 120..tail + 128].try_into().unwrap()),
+            pool_binding: bytes[tail + 128..].try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_nullifier, derive_pubkey, IncrementalMerkleTree, Note};
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    /// Inputs spending notes of `amounts`, all in one tree.
+    fn batch(amounts: &[u64]) -> BatchWithdrawPrivateInputs {
+        let notes: Vec<Note> = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note { amount, pubkey: derive_pubkey(&KEY), blinding: [i as u8 + 1; 32] })
+            .collect();
+        let mut tree = IncrementalMerkleTree::new(8);
+        for n in &notes {
+            tree.insert(n.commitment());
+        }
+        let inputs = notes
+            .into_iter()
+            .enumerate()
+            .map(|(i, note)| JoinSplitInput {
+                note,
+                spending_key: KEY,
+                merkle_proof: tree.get_proof(i as u32),
+                lock: None,
+            })
+            .collect();
+        BatchWithdrawPrivateInputs { inputs, root: tree.get_root(), recipient: [0xab; 20], pool_binding: [0x5b; 32] }
+    }
+
+    #[test]
+    fn test_batch_withdraw_sums_and_pads() {
+        let request = batch(&[100, 250, 50]);
+        let pv = request.check().unwrap();
+        assert_eq!((pv.amount, pv.recipient, pv.reclaim_deadline, pv.tree_levels), (400, [0xab; 20], 0, 8));
+        for (i, input) in request.inputs.iter().enumerate() {
+            assert_eq!(pv.nullifiers[i], compute_nullifier(&input.note.commitment(), &KEY));
+        }
+        assert!(pv.nullifiers[3..].iter().all(|n| n == &[0u8; 32]));
+        assert_eq!(pv.pool_binding, [0x5b; 32]);
+        assert_eq!(BatchWithdrawPublicValues::LEN, 448);
+        assert_eq!(BatchWithdrawPublicValues::decode(&pv.encode()), Some(pv.clone()));
+
+        let full = batch(&[1; BATCH_WITHDRAW_NOTES]).check().unwrap();
+        assert_eq!(full.amount, 8);
+        assert!(full.nullifiers.iter().all(|n| n != &[0u8; 32]));
+
+        let mut bytes = pv.encode();
+        bytes[32 * 9] = 1;
+        assert_eq!(BatchWithdrawPublicValues::decode(&bytes), None);
+    }
+
+    #[test]
+    fn test_batch_withdraw_rejects_bad_spends() {
+        assert_eq!(batch(&[]).check(), Err("batch withdraw spends 1 to 8 notes"));
+        assert_eq!(batch(&[1; BATCH_WITHDRAW_NOTES + 1]).check(), Err("batch withdraw spends 1 to 8 notes"));
+        assert_eq!(batch(&[u64::MAX, 1]).check(), Err("batch amount overflows"));
+
+        let base = batch(&[100, 250]);
+        let mut duplicate = base.clone();
+        duplicate.inputs[1] = duplicate.inputs[0].clone();
+        assert_eq!(duplicate.check(), Err("duplicate input note"));
+
+        let mut wrong_key = base.clone();
+        wrong_key.inputs[1].spending_key = [8u8; 32];
+        assert_eq!(wrong_key.check(), Err("spending key does not match note pubkey"));
+
+        let mut stale = base.clone();
+        stale.root = [0u8; 32];
+        assert_eq!(stale.check(), Err("Merkle proof invalid"));
+    }
+}
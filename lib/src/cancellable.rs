@@ -0,0 +1,264 @@
+//! Cancellable payments.
+//!
+//! A cancellable note can be spent by either of two keys: the recipient can
+//! *claim* it at any time, and the sender can *reclaim* it until a deadline.
+//! This gives the sender a window to pull back a mistaken payment before the
+//! recipient has claimed it.
+//!
+//! The note itself is an ordinary `Note` whose `pubkey` is a lock hash:
+//!
+//!   lock_pubkey = keccak256(LOCK_DOMAIN || recipient_pubkey || sender_pubkey || deadline_be8)
+//!
+//! so commitments, the Merkle tree and the pool contract do not change. The
+//! spender reveals the lock privately to the circuit, which checks it against
+//! the note's pubkey and that the spender's key derives one of the two pubkeys.
+//!
+//! Both paths must produce the same nullifier, otherwise sender and recipient
+//! could each spend the note once. The nullifier therefore comes from the
+//! note's blinding (known to both parties) instead of the spending key:
+//!
+//!   nullifier = keccak256(NULLIFIER_DOMAIN || commitment || blinding)
+//!
+//! A consequence is that the sender can recognise the nullifier when the
+//! recipient claims. A reclaim commits the lock's deadline as a public value
+//! and the pool rejects it once `block.timestamp` has passed the deadline.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{keccak256, nullifier_from_key, Note, SpenderKey};
+
+/// Domain tag for lock pubkeys (exactly 32 bytes).
+pub const LOCK_DOMAIN: [u8; 32] = *b"shielded-pool/cancellable-lock/1";
+
+/// Domain tag for cancellable-note nullifiers (exactly 32 bytes).
+pub const NULLIFIER_DOMAIN: [u8; 32] = *b"shielded-pool/cancel-nullifier/1";
+
+/// The two spenders of a cancellable note and the end of the reclaim window.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct CancellableLock {
+    /// Can claim the note at any time
+    pub recipient_pubkey: [u8; 32],
+    /// Can reclaim the note until `reclaim_deadline`
+    pub sender_pubkey: [u8; 32],
+    /// Unix timestamp (seconds) after which reclaims are rejected on-chain
+    pub reclaim_deadline: u64,
+}
+
+impl CancellableLock {
+    /// The value stored in the note's `pubkey` field.
+    pub fn lock_pubkey(&self) -> [u8; 32] {
+        let mut preimage = [0u8; 104];
+        preimage[0..32].copy_from_slice(&LOCK_DOMAIN);
+        preimage[32..64].copy_from_slice(&self.recipient_pubkey);
+        preimage[64..96].copy_from_slice(&self.sender_pubkey);
+        preimage[96..104].copy_from_slice(&self.reclaim_deadline.to_be_bytes());
+        keccak256(&preimage)
+    }
+
+    /// Build the cancellable note for `amount`.
+    pub fn note(&self, amount: u64, blinding: [u8; 32]) -> Note {
+        Note { amount, pubkey: self.lock_pubkey(), blinding }
+    }
+
+    /// Whether `note` is locked by this lock.
+    pub fn locks(&self, note: &Note) -> bool {
+        self.lock_pubkey() == note.pubkey
+    }
+}
+
+/// Nullifier shared by the claim and reclaim paths.
+pub fn cancellable_nullifier(commitment: &[u8; 32], blinding: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = [0u8; 96];
+    preimage[0..32].copy_from_slice(&NULLIFIER_DOMAIN);
+    preimage[32..64].copy_from_slice(commitment);
+    preimage[64..96].copy_from_slice(blinding);
+    keccak256(&preimage)
+}
+
+/// How a note is being spent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpendPath {
+    /// Plain note, spent by its owner
+    Owner,
+    /// Cancellable note, spent by the recipient
+    Claim,
+    /// Cancellable note, spent back by the sender
+    Reclaim,
+    /// Multisig note, spent with both owners' keys (see `multisig`)
+    Multisig,
+}
+
+/// Result of checking that a spending key may spend a note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpendAuthorization {
+    pub path: SpendPath,
+    pub nullifier: [u8; 32],
+    /// Set for reclaims: the spend must land on-chain by this timestamp
+    pub reclaim_deadline: Option<u64>,
+}
+
+/// Check that `spending_key` may spend `note` and derive its nullifier.
+///
+/// `lock` must be `Some` exactly when the note is cancellable. If the key
+/// matches both pubkeys of a lock, the spend is treated as a claim.
+pub fn authorize_spend(
+    note: &Note,
+    spending_key: &[u8; 32],
+    lock: Option<&CancellableLock>,
+) -> Result<SpendAuthorization, &'static str> {
+    authorize_with_key(note, &SpenderKey::derive(spending_key), lock)
+}
+
+/// `authorize_spend` for the transfer and withdraw circuits, which also
+/// spend notes from before proving keys: returns the key `spending_key`
+/// spends `note` with, its proving key or else its legacy key (see
+/// `legacy_pubkey`), and the authorization.
+pub fn authorize_spender(
+    note: &Note,
+    spending_key: &[u8; 32],
+    lock: Option<&CancellableLock>,
+) -> Result<(SpenderKey, SpendAuthorization), &'static str> {
+    let key = SpenderKey::derive(spending_key);
+    match authorize_with_key(note, &key, lock) {
+        Ok(auth) => Ok((key, auth)),
+        Err(err) => {
+            let legacy = SpenderKey::Legacy(*spending_key);
+            authorize_with_key(note, &legacy, lock).map(|auth| (legacy, auth)).map_err(|_| err)
+        }
+    }
+}
+
+/// `authorize_spend` from the spender's key as the transfer and withdraw
+/// circuits take it. They check the spend signature separately.
+pub fn authorize_with_key(
+    note: &Note,
+    key: &SpenderKey,
+    lock: Option<&CancellableLock>,
+) -> Result<SpendAuthorization, &'static str> {
+    let commitment = note.commitment();
+    let Some(lock) = lock else {
+        if !key.owns(&note.pubkey) {
+            return Err("spending key does not match note pubkey");
+        }
+        return Ok(SpendAuthorization {
+            path: SpendPath::Owner,
+            nullifier: nullifier_from_key(&commitment, key.nullifier_key()),
+            reclaim_deadline: None,
+        });
+    };
+
+    if !lock.locks(note) {
+        return Err("cancellable lock does not match note pubkey");
+    }
+    let nullifier = cancellable_nullifier(&commitment, &note.blinding);
+    if key.owns(&lock.recipient_pubkey) {
+        Ok(SpendAuthorization { path: SpendPath::Claim, nullifier, reclaim_deadline: None })
+    } else if key.owns(&lock.sender_pubkey) {
+        if lock.reclaim_deadline == 0 {
+            // 0 is the "no deadline" public value; never let a reclaim use it
+            return Err("cancellable lock has no reclaim deadline");
+        }
+        Ok(SpendAuthorization {
+            path: SpendPath::Reclaim,
+            nullifier,
+            reclaim_deadline: Some(lock.reclaim_deadline),
+        })
+    } else {
+        Err("spending key is neither the recipient nor the sender of the cancellable note")
+    }
+}
+
+/// The deadline a proof commits: the earliest reclaim deadline among its
+/// inputs, or 0 when no input is a reclaim.
+pub fn committed_deadline(auths: &[SpendAuthorization]) -> u64 {
+    auths.iter().filter_map(|a| a.reclaim_deadline).min().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_nullifier, derive_pubkey};
+
+    const RECIPIENT_SK: [u8; 32] = [0x11; 32];
+    const SENDER_SK: [u8; 32] = [0x22; 32];
+
+    fn lock() -> CancellableLock {
+        CancellableLock {
+            recipient_pubkey: derive_pubkey(&RECIPIENT_SK),
+            sender_pubkey: derive_pubkey(&SENDER_SK),
+            reclaim_deadline: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn test_claim_and_reclaim_share_nullifier() {
+        let lock = lock();
+        let note = lock.note(500, [0x33; 32]);
+        let claim = authorize_spend(&note, &RECIPIENT_SK, Some(&lock)).unwrap();
+        let reclaim = authorize_spend(&note, &SENDER_SK, Some(&lock)).unwrap();
+        assert_eq!(claim.path, SpendPath::Claim);
+        assert_eq!(claim.reclaim_deadline, None);
+        assert_eq!(reclaim.path, SpendPath::Reclaim);
+        assert_eq!(reclaim.reclaim_deadline, Some(1_700_000_000));
+        assert_eq!(claim.nullifier, reclaim.nullifier);
+        assert_ne!(claim.nullifier, compute_nullifier(&note.commitment(), &RECIPIENT_SK));
+    }
+
+    #[test]
+    fn test_rejects_wrong_key_or_lock() {
+        let lock = lock();
+        let note = lock.note(500, [0x33; 32]);
+        assert!(authorize_spend(&note, &[0x44; 32], Some(&lock)).is_err());
+        // Lock pubkey commits to the deadline
+        let extended = CancellableLock { reclaim_deadline: u64::MAX, ..lock };
+        assert!(authorize_spend(&note, &SENDER_SK, Some(&extended)).is_err());
+        // A cancellable note has no plain owner
+        assert!(authorize_spend(&note, &RECIPIENT_SK, None).is_err());
+        // A plain note cannot be spent through a lock
+        let plain = Note { amount: 500, pubkey: derive_pubkey(&RECIPIENT_SK), blinding: [0x33; 32] };
+        assert!(authorize_spend(&plain, &RECIPIENT_SK, Some(&lock)).is_err());
+        assert_eq!(authorize_spend(&plain, &RECIPIENT_SK, None).unwrap().path, SpendPath::Owner);
+    }
+
+    #[test]
+    fn test_authorize_spender_falls_back_to_legacy_key() {
+        let plain = Note { amount: 500, pubkey: derive_pubkey(&RECIPIENT_SK), blinding: [0x33; 32] };
+        let (key, _) = authorize_spender(&plain, &RECIPIENT_SK, None).unwrap();
+        assert_eq!(key, SpenderKey::derive(&RECIPIENT_SK));
+        let legacy = Note { pubkey: crate::legacy_pubkey(&RECIPIENT_SK), ..plain };
+        let (key, auth) = authorize_spender(&legacy, &RECIPIENT_SK, None).unwrap();
+        assert_eq!(key, SpenderKey::Legacy(RECIPIENT_SK));
+        // The nullifier the note had before proving keys
+        assert_eq!(auth.nullifier, keccak256(&[legacy.commitment(), RECIPIENT_SK].concat()));
+        assert!(authorize_spend(&legacy, &RECIPIENT_SK, None).is_err());
+        assert_eq!(
+            authorize_spender(&legacy, &SENDER_SK, None).unwrap_err(),
+            "spending key does not match note pubkey"
+        );
+    }
+
+    #[test]
+    fn test_reclaim_requires_deadline() {
+        let lock = CancellableLock { reclaim_deadline: 0, ..lock() };
+        let note = lock.note(1, [0x55; 32]);
+        assert!(authorize_spend(&note, &SENDER_SK, Some(&lock)).is_err());
+        assert!(authorize_spend(&note, &RECIPIENT_SK, Some(&lock)).is_ok());
+    }
+
+    #[test]
+    fn test_committed_deadline_is_earliest_reclaim() {
+        let a = CancellableLock { reclaim_deadline: 200, ..lock() };
+        let b = CancellableLock { reclaim_deadline: 100, ..lock() };
+        let na = a.note(1, [1; 32]);
+        let nb = b.note(2, [2; 32]);
+        let auths = [
+            authorize_spend(&na, &SENDER_SK, Some(&a)).unwrap(),
+            authorize_spend(&nb, &SENDER_SK, Some(&b)).unwrap(),
+        ];
+        assert_eq!(committed_deadline(&auths), 100);
+        let claim = authorize_spend(&nb, &RECIPIENT_SK, Some(&b)).unwrap();
+        assert_eq!(committed_deadline(&[auths[0], claim]), 200);
+        assert_eq!(committed_deadline(&[claim]), 0);
+    }
+}
@@ -0,0 +1,856 @@
+//! Canonical encoding and hash of a proof request.
+//!
+//! Relayers key proving jobs, archives dedup stored proofs, and audit logs
+//! refer to the request behind a proof, all before or without a proof to
+//! hash. JSON does not serve: key order, whitespace and omitted defaults all
+//! vary between writers. The canonical encoding is instead
+//!
+//!   "shielded-pool/proof-request" || version(1) || circuit(1) || fields
+//!   field = name_len(1) || name || value_len(4, BE) || value
+//!
+//! with fields in name order. A field still at its serde default (no locks)
+//! is left out, so requests written before such a field existed keep their
+//! hash. Integers are big-endian; a note is amount(8) || pubkey || blinding,
+//! a Merkle proof is step_count(4) || (is_left(1) || sibling)*, a nullifier
+//! proof is sibling_count(4) || siblings || terminal (0, or 1 followed by the
+//! leaf), an optional value is 0, or 1 followed by the value. A list is
+//! count(4) || items, and a struct inside one is its fields' values in name
+//! order, without names. An enum is its variant index(1) followed by the
+//! variant's fields the same way.
+//!
+//! `request_hash` is keccak256 of the encoding. It covers the spending keys
+//! (or the proving keys and spend signatures), like the request itself, so
+//! two requests share a hash exactly when they would prove the same statement
+//! with the same witness.
+
+use alloc::vec::Vec;
+
+use crate::association::{Lineage, OutputLabel, TransferAssociation, WithdrawAssociation};
+use crate::attest::{AttestNote, AttestPrivateInputs};
+use crate::batch_withdraw::BatchWithdrawPrivateInputs;
+use crate::claim::ClaimPrivateInputs;
+use crate::consolidate::ConsolidatePrivateInputs;
+use crate::deposit::DepositPrivateInputs;
+use crate::disclosure::DisclosurePrivateInputs;
+use crate::join_split::{JoinSplitInput, JoinSplitPrivateInputs};
+use crate::multisig::MultisigSpend;
+use crate::note_encryption::OutputEncryption;
+use crate::nullifier_set::{NullifierProof, ProofTerminal};
+use crate::payout::PayoutPrivateInputs;
+use crate::policy::{CounterInput, Policy, PolicySpendPrivateInputs};
+use crate::{
+    keccak256, CancellableLock, CircuitKind, MerkleProofStep, Note, SpendSignature, SpenderKey, TransferPrivateInputs,
+    WithdrawPrivateInputs,
+};
+
+/// Domain separator leading every canonical request.
+pub const REQUEST_DOMAIN: &[u8] = b"shielded-pool/proof-request";
+
+/// Version of the canonical encoding. Bump whenever it changes.
+pub const REQUEST_ENCODING_VERSION: u8 = 3;
+
+/// Writes fields, checking they arrive in name order.
+struct Encoder {
+    out: Vec<u8>,
+    last: &'static str,
+}
+
+impl Encoder {
+    fn new(kind: CircuitKind) -> Self {
+        let mut out = Vec::with_capacity(1024);
+        out.extend_from_slice(REQUEST_DOMAIN);
+        out.push(REQUEST_ENCODING_VERSION);
+        out.push(match kind {
+            CircuitKind::Transfer => 0,
+            CircuitKind::Withdraw => 1,
+            CircuitKind::Claim => 2,
+            CircuitKind::JoinSplit => 3,
+            CircuitKind::Deposit => 4,
+            CircuitKind::BatchWithdraw => 5,
+            CircuitKind::Consolidate => 6,
+            CircuitKind::Attest => 7,
+            CircuitKind::Disclose => 8,
+            CircuitKind::PolicySpend => 10,
+            CircuitKind::Payout => 11,
+        });
+        Encoder { out, last: "" }
+    }
+
+    fn field(&mut self, name: &'static str, value: impl FnOnce(&mut Vec<u8>)) -> &mut Self {
+        debug_assert!(name > self.last, "canonical fields must be written in name order");
+        self.last = name;
+        self.out.push(name.len() as u8);
+        self.out.extend_from_slice(name.as_bytes());
+        let len_at = self.out.len();
+        self.out.extend_from_slice(&[0u8; 4]);
+        value(&mut self.out);
+        let len = (self.out.len() - len_at - 4) as u32;
+        self.out[len_at..len_at + 4].copy_from_slice(&len.to_be_bytes());
+        self
+    }
+
+    /// The `pool_binding` field, left out while zero like other defaults.
+    fn pool_binding(&mut self, binding: &[u8; 32]) -> &mut Self {
+        if *binding != [0u8; 32] {
+            self.field("pool_binding", |out| out.extend_from_slice(binding));
+        }
+        self
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.out)
+    }
+}
+
+fn note(out: &mut Vec<u8>, note: &Note) {
+    out.extend_from_slice(&note.amount.to_be_bytes());
+    out.extend_from_slice(&note.pubkey);
+    out.extend_from_slice(&note.blinding);
+}
+
+fn proof(out: &mut Vec<u8>, proof: &[MerkleProofStep]) {
+    out.extend_from_slice(&(proof.len() as u32).to_be_bytes());
+    for step in proof {
+        out.push(step.is_left as u8);
+        out.extend_from_slice(&step.sibling);
+    }
+}
+
+fn nullifier_proof(out: &mut Vec<u8>, proof: &NullifierProof) {
+    out.extend_from_slice(&(proof.siblings.len() as u32).to_be_bytes());
+    proof.siblings.iter().for_each(|s| out.extend_from_slice(s));
+    let leaf = match &proof.terminal {
+        ProofTerminal::Empty => None,
+        ProofTerminal::Leaf(key) => Some(key),
+    };
+    option(out, leaf, |out, key| out.extend_from_slice(key));
+}
+
+fn lock(out: &mut Vec<u8>, lock: &CancellableLock) {
+    out.extend_from_slice(&lock.recipient_pubkey);
+    out.extend_from_slice(&lock.sender_pubkey);
+    out.extend_from_slice(&lock.reclaim_deadline.to_be_bytes());
+}
+
+fn spender_key(out: &mut Vec<u8>, key: &SpenderKey) {
+    match key {
+        SpenderKey::Proving(key) => {
+            out.push(0);
+            out.extend_from_slice(&key.auth_key);
+            out.extend_from_slice(&key.nullifier_key);
+        }
+        SpenderKey::Legacy(spending_key) => {
+            out.push(1);
+            out.extend_from_slice(spending_key);
+        }
+    }
+}
+
+fn spend_signature(out: &mut Vec<u8>, signature: &SpendSignature) {
+    out.extend_from_slice(&signature.auth_key);
+    out.extend_from_slice(&signature.r);
+    out.extend_from_slice(&signature.s);
+}
+
+fn multisig_spend(out: &mut Vec<u8>, spend: &MultisigSpend) {
+    spender_key(out, &spend.cosigner);
+    spend.lock.owner_pubkeys.iter().for_each(|pk| out.extend_from_slice(pk));
+}
+
+fn list<T>(out: &mut Vec<u8>, items: &[T], write: impl Fn(&mut Vec<u8>, &T)) {
+    out.extend_from_slice(&(items.len() as u32).to_be_bytes());
+    items.iter().for_each(|item| write(out, item));
+}
+
+fn join_split_input(out: &mut Vec<u8>, input: &JoinSplitInput) {
+    option(out, input.lock.as_ref(), lock);
+    proof(out, &input.merkle_proof);
+    note(out, &input.note);
+    out.extend_from_slice(&input.spending_key);
+}
+
+fn attest_note(out: &mut Vec<u8>, attested: &AttestNote) {
+    proof(out, &attested.merkle_proof);
+    note(out, &attested.note);
+    nullifier_proof(out, &attested.nullifier_proof);
+    out.extend_from_slice(&attested.spending_key);
+}
+
+fn policy(out: &mut Vec<u8>, policy: &Policy) {
+    out.extend_from_slice(&policy.limit.to_be_bytes());
+    out.extend_from_slice(&policy.secret);
+}
+
+fn counter_input(out: &mut Vec<u8>, input: &CounterInput) {
+    let counter = &input.counter;
+    out.extend_from_slice(&counter.blinding);
+    out.extend_from_slice(&counter.epoch.to_be_bytes());
+    out.extend_from_slice(&counter.policy_key);
+    out.extend_from_slice(&counter.spent.to_be_bytes());
+    proof(out, &input.merkle_proof);
+}
+
+fn lineage(out: &mut Vec<u8>, lineage: &Lineage) {
+    match lineage {
+        Lineage::Deposit { proof: p } => {
+            out.push(0);
+            proof(out, p);
+        }
+        Lineage::Labelled { label, salt, note_proof, label_proof } => {
+            out.push(1);
+            out.extend_from_slice(label);
+            proof(out, label_proof);
+            proof(out, note_proof);
+            out.extend_from_slice(salt);
+        }
+    }
+}
+
+fn output_label(out: &mut Vec<u8>, label: &OutputLabel) {
+    out.push(label.from_input);
+    out.extend_from_slice(&label.salt);
+}
+
+fn transfer_association(out: &mut Vec<u8>, association: &TransferAssociation) {
+    association.inputs.iter().for_each(|l| lineage(out, l));
+    association.outputs.iter().for_each(|l| output_label(out, l));
+    out.extend_from_slice(&association.root);
+}
+
+fn output_encryption(out: &mut Vec<u8>, e: &OutputEncryption) {
+    out.extend_from_slice(&e.ephemeral_secret);
+    out.extend_from_slice(&e.nonce);
+    out.extend_from_slice(&e.viewing_pubkey);
+}
+
+fn withdraw_association(out: &mut Vec<u8>, association: &WithdrawAssociation) {
+    out.extend_from_slice(&association.change_salt);
+    lineage(out, &association.input);
+    out.extend_from_slice(&association.root);
+}
+
+fn option<T>(out: &mut Vec<u8>, value: Option<&T>, write: impl FnOnce(&mut Vec<u8>, &T)) {
+    match value {
+        None => out.push(0),
+        Some(v) => {
+            out.push(1);
+            write(out, v);
+        }
+    }
+}
+
+impl TransferPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut enc = Encoder::new(CircuitKind::Transfer);
+        if let Some(a) = &self.association {
+            enc.field("association", |out| transfer_association(out, a));
+        }
+        enc.field("input_notes", |out| self.input_notes.iter().for_each(|n| note(out, n)));
+        if self.locks.iter().any(Option::is_some) {
+            enc.field("locks", |out| self.locks.iter().for_each(|l| option(out, l.as_ref(), lock)));
+        }
+        enc.field("merkle_proofs", |out| self.merkle_proofs.iter().for_each(|p| proof(out, p)));
+        if self.multisig.iter().any(Option::is_some) {
+            enc.field("multisig", |out| self.multisig.iter().for_each(|m| option(out, m.as_ref(), multisig_spend)));
+        }
+        if let Some(e) = &self.output_encryption {
+            enc.field("output_encryption", |out| e.iter().for_each(|e| output_encryption(out, e)));
+        }
+        enc.field("output_notes", |out| self.output_notes.iter().for_each(|n| note(out, n)));
+        if self.pool_binding != [0u8; 32] {
+            enc.field("pool_binding", |out| out.extend_from_slice(&self.pool_binding));
+        }
+        enc.field("root", |out| out.extend_from_slice(&self.root));
+        if !self.spend_signatures.is_empty() {
+            enc.field("spend_signatures", |out| list(out, &self.spend_signatures, spend_signature));
+        }
+        enc.field("spender_keys", |out| self.spender_keys.iter().for_each(|k| spender_key(out, k))).finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl WithdrawPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut enc = Encoder::new(CircuitKind::Withdraw);
+        // Like the lock, the relayer and call fields are left out when unused, so
+        // requests from before they existed keep their hash
+        let relayed = self.fee != 0 || self.relayer != [0u8; 20];
+        if let Some(a) = &self.association {
+            enc.field("association", |out| withdraw_association(out, a));
+        }
+        if self.call_target != [0u8; 20] || self.calldata_hash != [0u8; 32] {
+            enc.field("call_target", |out| out.extend_from_slice(&self.call_target));
+            enc.field("calldata_hash", |out| out.extend_from_slice(&self.calldata_hash));
+        }
+        enc.field("change_note", |out| option(out, self.change_note.as_ref(), note));
+        if self.deadline != 0 {
+            enc.field("deadline", |out| out.extend_from_slice(&self.deadline.to_be_bytes()));
+        }
+        if relayed {
+            enc.field("fee", |out| out.extend_from_slice(&self.fee.to_be_bytes()));
+        }
+        enc.field("input_note", |out| note(out, &self.input_note));
+        if let Some(l) = &self.lock {
+            enc.field("lock", |out| lock(out, l));
+        }
+        enc.field("merkle_proof", |out| proof(out, &self.merkle_proof));
+        if let Some(m) = &self.multisig {
+            enc.field("multisig", |out| multisig_spend(out, m));
+        }
+        if self.pool_binding != [0u8; 32] {
+            enc.field("pool_binding", |out| out.extend_from_slice(&self.pool_binding));
+        }
+        enc.field("recipient", |out| out.extend_from_slice(&self.recipient));
+        if relayed {
+            enc.field("relayer", |out| out.extend_from_slice(&self.relayer));
+        }
+        enc.field("root", |out| out.extend_from_slice(&self.root));
+        if !self.spend_signatures.is_empty() {
+            enc.field("spend_signatures", |out| list(out, &self.spend_signatures, spend_signature));
+        }
+        enc.field("spender_key", |out| spender_key(out, &self.spender_key))
+            .field("withdraw_amount", |out| out.extend_from_slice(&self.withdraw_amount.to_be_bytes()))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl ClaimPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::Claim)
+            .field("airdrop_id", |out| out.extend_from_slice(&self.airdrop_id))
+            .field("merkle_proof", |out| proof(out, &self.merkle_proof))
+            .field("note", |out| note(out, &self.note))
+            .field("nullifier_proof", |out| nullifier_proof(out, &self.nullifier_proof))
+            .field("nullifier_root", |out| out.extend_from_slice(&self.nullifier_root))
+            .pool_binding(&self.pool_binding)
+            .field("recipient", |out| out.extend_from_slice(&self.recipient))
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .field("spending_key", |out| out.extend_from_slice(&self.spending_key))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl BatchWithdrawPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::BatchWithdraw)
+            .field("inputs", |out| list(out, &self.inputs, join_split_input))
+            .pool_binding(&self.pool_binding)
+            .field("recipient", |out| out.extend_from_slice(&self.recipient))
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl ConsolidatePrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::Consolidate)
+            .field("input_notes", |out| list(out, &self.input_notes, note))
+            .field("merkle_proofs", |out| list(out, &self.merkle_proofs, |out, p| proof(out, p)))
+            .field("output_note", |out| note(out, &self.output_note))
+            .pool_binding(&self.pool_binding)
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .field("spending_key", |out| out.extend_from_slice(&self.spending_key))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl AttestPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::Attest)
+            .field("nonce", |out| out.extend_from_slice(&self.nonce))
+            .field("notes", |out| list(out, &self.notes, attest_note))
+            .field("nullifier_root", |out| out.extend_from_slice(&self.nullifier_root))
+            .pool_binding(&self.pool_binding)
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .field("threshold", |out| out.extend_from_slice(&self.threshold.to_be_bytes()))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl DepositPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::Deposit).field("note", |out| note(out, &self.note)).finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl DisclosurePrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::Disclose)
+            .field("audit_id", |out| out.extend_from_slice(&self.audit_id))
+            .field("payloads", |out| {
+                list(out, &self.payloads, |out, p| {
+                    out.extend_from_slice(&(p.len() as u32).to_be_bytes());
+                    out.extend_from_slice(p);
+                })
+            })
+            .pool_binding(&self.pool_binding)
+            .field("viewing_secret", |out| out.extend_from_slice(&self.viewing_secret))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl JoinSplitPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::JoinSplit)
+            .field("inputs", |out| list(out, &self.inputs, join_split_input))
+            .field("output_notes", |out| list(out, &self.output_notes, note))
+            .pool_binding(&self.pool_binding)
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl PayoutPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::Payout)
+            .field("inputs", |out| list(out, &self.inputs, join_split_input))
+            .field("output_notes", |out| list(out, &self.output_notes, note))
+            .pool_binding(&self.pool_binding)
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+impl PolicySpendPrivateInputs {
+    /// Canonical encoding of this request (see the module docs).
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        Encoder::new(CircuitKind::PolicySpend)
+            .field("change_note", |out| note(out, &self.change_note))
+            .field("counter", |out| option(out, self.counter.as_ref(), counter_input))
+            .field("counter_blinding", |out| out.extend_from_slice(&self.counter_blinding))
+            .field("counter_root", |out| out.extend_from_slice(&self.counter_root))
+            .field("epoch", |out| out.extend_from_slice(&self.epoch.to_be_bytes()))
+            .field("merkle_proof", |out| proof(out, &self.merkle_proof))
+            .field("note", |out| note(out, &self.note))
+            .field("payment_note", |out| note(out, &self.payment_note))
+            .field("policy", |out| policy(out, &self.policy))
+            .pool_binding(&self.pool_binding)
+            .field("root", |out| out.extend_from_slice(&self.root))
+            .field("spending_key", |out| out.extend_from_slice(&self.spending_key))
+            .finish()
+    }
+
+    /// keccak256 of `canonical_bytes`: a stable id for this proof request.
+    pub fn request_hash(&self) -> [u8; 32] {
+        keccak256(&self.canonical_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multisig::MultisigLock;
+    use crate::policy::PolicyCounter;
+    use crate::tests::hex_to_bytes32;
+    use crate::ProvingKey;
+    use alloc::vec;
+
+    const KEY: SpenderKey = SpenderKey::Proving(ProvingKey { auth_key: [3u8; 32], nullifier_key: [8u8; 32] });
+
+    fn transfer() -> TransferPrivateInputs {
+        let n = |amount, b| Note { amount, pubkey: [1u8; 32], blinding: [b; 32] };
+        let step = MerkleProofStep { is_left: true, sibling: [9u8; 32] };
+        TransferPrivateInputs {
+            input_notes: [n(5, 1), n(7, 2)],
+            spender_keys: [KEY; 2],
+            merkle_proofs: [vec![step.clone()], vec![step]],
+            output_notes: [n(10, 4), n(2, 5)],
+            root: [6u8; 32],
+            locks: [None, None],
+            association: None,
+            pool_binding: [0u8; 32],
+            output_encryption: None,
+            multisig: [None, None],
+            spend_signatures: vec![],
+        }
+    }
+
+    fn withdraw() -> WithdrawPrivateInputs {
+        WithdrawPrivateInputs {
+            input_note: Note { amount: 5, pubkey: [1u8; 32], blinding: [2u8; 32] },
+            spender_key: KEY,
+            merkle_proof: vec![MerkleProofStep { is_left: false, sibling: [9u8; 32] }],
+            root: [6u8; 32],
+            recipient: [7u8; 20],
+            withdraw_amount: 5,
+            change_note: None,
+            lock: None,
+            fee: 0,
+            relayer: [0u8; 20],
+            association: None,
+            pool_binding: [0u8; 32],
+            deadline: 0,
+            call_target: [0u8; 20],
+            calldata_hash: [0u8; 32],
+            multisig: None,
+            spend_signatures: vec![],
+        }
+    }
+
+    #[test]
+    fn test_request_hash_ignores_json_layout() {
+        let t = transfer();
+        let json = serde_json::to_value(&t).unwrap();
+        // Keys in another order, and `locks` left to its default
+        let mut reordered = serde_json::Map::new();
+        for key in ["root", "spender_keys", "output_notes", "merkle_proofs", "input_notes"] {
+            reordered.insert(key.into(), json[key].clone());
+        }
+        let parsed: TransferPrivateInputs = serde_json::from_value(reordered.into()).unwrap();
+        assert_eq!(parsed.request_hash(), t.request_hash());
+
+        let w = withdraw();
+        let parsed: WithdrawPrivateInputs = serde_json::from_str(&serde_json::to_string_pretty(&w).unwrap()).unwrap();
+        assert_eq!(parsed.request_hash(), w.request_hash());
+    }
+
+    #[test]
+    fn test_request_hash_covers_every_field() {
+        let base = transfer().request_hash();
+        let associated = |t: &mut TransferPrivateInputs| {
+            let lineage = Lineage::Deposit { proof: vec![] };
+            let output = OutputLabel { from_input: 0, salt: [0u8; 32] };
+            t.association = Some(TransferAssociation {
+                root: [0u8; 32],
+                inputs: [lineage.clone(), lineage],
+                outputs: [output.clone(), output],
+            });
+        };
+        let changes: [fn(&mut TransferPrivateInputs); 11] = [
+            |t| t.input_notes[1].amount += 1,
+            |t| t.spender_keys[0] = SpenderKey::Legacy([3u8; 32]),
+            |t| t.spend_signatures.push(SpendSignature { auth_key: [3u8; 32], r: [0; 32], s: [0; 32] }),
+            |t| t.merkle_proofs[0][0].is_left = false,
+            |t| t.output_notes.swap(0, 1),
+            |t| t.root[31] ^= 1,
+            |t| t.locks[1] = Some(CancellableLock { recipient_pubkey: [0; 32], sender_pubkey: [0; 32], reclaim_deadline: 0 }),
+            associated,
+            |t| t.pool_binding[0] = 1,
+            |t| {
+                let e = OutputEncryption { viewing_pubkey: [0; 32], ephemeral_secret: [0; 32], nonce: [0; 24] };
+                t.output_encryption = Some([e.clone(), e])
+            },
+            |t| {
+                t.multisig[0] = Some(MultisigSpend {
+                    lock: MultisigLock { owner_pubkeys: [[0; 32]; 2] },
+                    cosigner: SpenderKey::Legacy([0; 32]),
+                })
+            },
+        ];
+        for change in changes {
+            let mut t = transfer();
+            change(&mut t);
+            assert_ne!(t.request_hash(), base);
+        }
+
+        let base = withdraw().request_hash();
+        let changes: [fn(&mut WithdrawPrivateInputs); 15] = [
+            |w| w.recipient[0] ^= 1,
+            |w| w.spender_key = SpenderKey::Proving(ProvingKey { auth_key: [3u8; 32], nullifier_key: [9u8; 32] }),
+            |w| w.spend_signatures.push(SpendSignature { auth_key: [3u8; 32], r: [0; 32], s: [1; 32] }),
+            |w| w.withdraw_amount -= 1,
+            |w| w.change_note = Some(Note { amount: 0, pubkey: [0; 32], blinding: [0; 32] }),
+            |w| w.merkle_proof.push(MerkleProofStep { is_left: true, sibling: [0; 32] }),
+            |w| w.lock = Some(CancellableLock { recipient_pubkey: [0; 32], sender_pubkey: [0; 32], reclaim_deadline: 0 }),
+            |w| w.fee = 1,
+            |w| w.relayer[19] = 1,
+            |w| {
+                w.association = Some(WithdrawAssociation {
+                    root: [0u8; 32],
+                    input: Lineage::Deposit { proof: vec![] },
+                    change_salt: [0u8; 32],
+                })
+            },
+            |w| w.pool_binding[0] = 1,
+            |w| w.deadline = 1,
+            |w| w.call_target[0] = 1,
+            |w| w.calldata_hash[0] = 1,
+            |w| {
+                w.multisig = Some(MultisigSpend {
+                    lock: MultisigLock { owner_pubkeys: [[0; 32]; 2] },
+                    cosigner: SpenderKey::Legacy([0; 32]),
+                })
+            },
+        ];
+        for change in changes {
+            let mut w = withdraw();
+            change(&mut w);
+            assert_ne!(w.request_hash(), base);
+        }
+
+        let claim = || ClaimPrivateInputs {
+            note: Note { amount: 5, pubkey: [1u8; 32], blinding: [2u8; 32] },
+            spending_key: [3u8; 32],
+            merkle_proof: vec![MerkleProofStep { is_left: false, sibling: [9u8; 32] }],
+            root: [6u8; 32],
+            nullifier_root: [4u8; 32],
+            nullifier_proof: NullifierProof { siblings: vec![[5u8; 32]], terminal: ProofTerminal::Empty },
+            airdrop_id: [8u8; 32],
+            recipient: [7u8; 20],
+            pool_binding: [0u8; 32],
+        };
+        let base = claim().request_hash();
+        assert_ne!(base, withdraw().request_hash());
+        let changes: [fn(&mut ClaimPrivateInputs); 5] = [
+            |c| c.airdrop_id[0] ^= 1,
+            |c| c.nullifier_root[0] ^= 1,
+            |c| c.nullifier_proof.terminal = ProofTerminal::Leaf([5u8; 32]),
+            |c| c.pool_binding[0] = 1,
+            |c| c.recipient[0] ^= 1,
+        ];
+        for change in changes {
+            let mut c = claim();
+            change(&mut c);
+            assert_ne!(c.request_hash(), base);
+        }
+
+        let join_split = || {
+            let t = transfer();
+            let inputs = (0..2)
+                .map(|i| JoinSplitInput {
+                    note: t.input_notes[i].clone(),
+                    spending_key: [3u8; 32],
+                    merkle_proof: t.merkle_proofs[i].clone(),
+                    lock: None,
+                })
+                .collect();
+            JoinSplitPrivateInputs::padded(t.root, inputs, t.output_notes.to_vec(), [8u8; 32]).unwrap()
+        };
+        let base = join_split().request_hash();
+        assert_ne!(base, transfer().request_hash());
+        let changes: [fn(&mut JoinSplitPrivateInputs); 5] = [
+            |j| j.inputs.swap(0, 1),
+            |j| j.pool_binding[0] = 1,
+            |j| j.inputs[3].spending_key[0] ^= 1,
+            |j| j.inputs[0].lock = Some(CancellableLock { recipient_pubkey: [0; 32], sender_pubkey: [0; 32], reclaim_deadline: 0 }),
+            |j| j.output_notes[2].blinding[0] ^= 1,
+        ];
+        for change in changes {
+            let mut j = join_split();
+            change(&mut j);
+            assert_ne!(j.request_hash(), base);
+        }
+
+        let batch = || BatchWithdrawPrivateInputs {
+            inputs: join_split().inputs[..2].to_vec(),
+            root: [6u8; 32],
+            recipient: [7u8; 20],
+            pool_binding: [0u8; 32],
+        };
+        let base = batch().request_hash();
+        let changes: [fn(&mut BatchWithdrawPrivateInputs); 4] = [
+            |b| b.inputs.swap(0, 1),
+            |b| b.pool_binding[0] = 1,
+            |b| b.recipient[0] ^= 1,
+            |b| b.root[0] ^= 1,
+        ];
+        for change in changes {
+            let mut b = batch();
+            change(&mut b);
+            assert_ne!(b.request_hash(), base);
+        }
+
+        let consolidate = || {
+            let t = transfer();
+            let inputs = t.input_notes.iter().cloned().zip(t.merkle_proofs.iter().cloned()).collect();
+            ConsolidatePrivateInputs::new(t.root, inputs, [3u8; 32], [8u8; 32]).unwrap()
+        };
+        let base = consolidate().request_hash();
+        let changes: [fn(&mut ConsolidatePrivateInputs); 4] = [
+            |c| c.input_notes.swap(0, 1),
+            |c| c.pool_binding[0] = 1,
+            |c| c.merkle_proofs[1].clear(),
+            |c| c.output_note.blinding[0] ^= 1,
+        ];
+        for change in changes {
+            let mut c = consolidate();
+            change(&mut c);
+            assert_ne!(c.request_hash(), base);
+        }
+
+        let attest = || {
+            let c = claim();
+            AttestPrivateInputs {
+                notes: vec![AttestNote {
+                    note: c.note,
+                    spending_key: c.spending_key,
+                    merkle_proof: c.merkle_proof,
+                    nullifier_proof: c.nullifier_proof,
+                }],
+                root: c.root,
+                nullifier_root: c.nullifier_root,
+                threshold: 5,
+                nonce: [0xa5; 32],
+                pool_binding: [0u8; 32],
+            }
+        };
+        let base = attest().request_hash();
+        let changes: [fn(&mut AttestPrivateInputs); 5] = [
+            |a| a.nonce[0] ^= 1,
+            |a| a.pool_binding[0] = 1,
+            |a| a.notes[0].nullifier_proof.terminal = ProofTerminal::Leaf([5u8; 32]),
+            |a| a.notes.push(a.notes[0].clone()),
+            |a| a.threshold = 4,
+        ];
+        for change in changes {
+            let mut a = attest();
+            change(&mut a);
+            assert_ne!(a.request_hash(), base);
+        }
+
+        let disclosure = || DisclosurePrivateInputs {
+            viewing_secret: [3u8; 32],
+            payloads: vec![vec![1, 2, 3], vec![4]],
+            audit_id: [7u8; 32],
+            pool_binding: [0u8; 32],
+        };
+        let base = disclosure().request_hash();
+        let changes: [fn(&mut DisclosurePrivateInputs); 5] = [
+            |d| d.audit_id[0] ^= 1,
+            |d| d.pool_binding[0] = 1,
+            |d| d.payloads.swap(0, 1),
+            |d| d.payloads = vec![vec![1, 2], vec![3, 4]],
+            |d| d.viewing_secret[0] ^= 1,
+        ];
+        for change in changes {
+            let mut d = disclosure();
+            change(&mut d);
+            assert_ne!(d.request_hash(), base);
+        }
+
+        let policy_spend = || PolicySpendPrivateInputs {
+            note: withdraw().input_note,
+            spending_key: [3u8; 32],
+            policy: Policy { limit: 10, secret: [4u8; 32] },
+            merkle_proof: withdraw().merkle_proof,
+            root: withdraw().root,
+            payment_note: Note { amount: 3, ..withdraw().input_note },
+            change_note: Note { amount: 2, ..withdraw().input_note },
+            counter: Some(CounterInput {
+                counter: PolicyCounter { policy_key: [5u8; 32], epoch: 1, spent: 4, blinding: [6u8; 32] },
+                merkle_proof: withdraw().merkle_proof,
+            }),
+            counter_root: [7u8; 32],
+            epoch: 1,
+            counter_blinding: [8u8; 32],
+            pool_binding: [0u8; 32],
+        };
+        let base = policy_spend().request_hash();
+        let changes: [fn(&mut PolicySpendPrivateInputs); 7] = [
+            |p| p.counter = None,
+            |p| p.pool_binding[0] = 1,
+            |p| p.counter.as_mut().unwrap().counter.spent += 1,
+            |p| p.counter_blinding[0] ^= 1,
+            |p| p.epoch += 1,
+            |p| p.policy.limit += 1,
+            |p| core::mem::swap(&mut p.payment_note, &mut p.change_note),
+        ];
+        for change in changes {
+            let mut p = policy_spend();
+            change(&mut p);
+            assert_ne!(p.request_hash(), base);
+        }
+
+        let payout = || {
+            let j = join_split();
+            let (inputs, outputs) = (j.inputs[..2].to_vec(), j.output_notes[..2].to_vec());
+            PayoutPrivateInputs::padded(j.root, inputs, outputs, [8u8; 32]).unwrap()
+        };
+        let base = payout().request_hash();
+        let mut same_fields = join_split();
+        same_fields.inputs.truncate(2);
+        same_fields.output_notes = payout().output_notes;
+        assert_ne!(base, same_fields.request_hash());
+        let changes: [fn(&mut PayoutPrivateInputs); 4] = [
+            |p| p.inputs.swap(0, 1),
+            |p| p.pool_binding[0] = 1,
+            |p| p.output_notes.swap(0, 7),
+            |p| p.output_notes[5].pubkey[0] ^= 1,
+        ];
+        for change in changes {
+            let mut p = payout();
+            change(&mut p);
+            assert_ne!(p.request_hash(), base);
+        }
+
+        let deposit = DepositPrivateInputs { note: withdraw().input_note };
+        let base = deposit.request_hash();
+        assert_ne!(base, withdraw().request_hash());
+        let mut other = deposit.clone();
+        other.note.blinding[0] ^= 1;
+        assert_ne!(other.request_hash(), base);
+    }
+
+    #[test]
+    fn test_request_hash_is_pinned() {
+        // Changing these means every stored job key and audit reference
+        // changes too: bump REQUEST_ENCODING_VERSION.
+        assert_eq!(
+            transfer().request_hash(),
+            hex_to_bytes32("74a837c6ae6c215e6697856b328d645e690730eea0767c818e60dfbd1b132e22")
+        );
+        assert_eq!(
+            withdraw().request_hash(),
+            hex_to_bytes32("33e81b0b3c6131ea63283c40d321ac06a9633170e63b0fd1d773f50ab843d1c3")
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Historical ownership claims (airdrops to past shielded balances).
+//!
+//! An airdrop picks a snapshot of the pool: a tree root `R` the pool had,
+//! and the root of the `NullifierSet` of everything spent by then. The claim
+//! circuit proves, for one note, that
+//!
+//!   - the note's commitment is in the tree under `R`,
+//!   - the spending key owns the note,
+//!   - the note's pool nullifier is not in the snapshot's nullifier set, so
+//!     the note was still unspent at the snapshot,
+//!
+//! and commits a claim nullifier the airdrop records to refuse a second
+//! claim of the same note:
+//!
+//!   claim_nullifier = keccak256(CLAIM_DOMAIN || airdrop_id || commitment || spending_key)
+//!
+//! It is bound to the airdrop, so one note can be claimed once per airdrop,
+//! and it cannot be linked to the note's pool nullifier, so a claim does not
+//! reveal which note is claimed or whether it has been spent since. The
+//! note's amount is public (airdrops are usually weighted by balance).
+//!
+//! Without the nullifier check, a holder could spend a note into a new one
+//! before the snapshot and claim with both. Cancellable notes cannot claim:
+//! no spending key owns their lock pubkey. Claim them into a plain note
+//! before the snapshot.
+//!
+//! Public values committed (256 bytes = 8 × 32-byte slots):
+//!   [root, nullifierRoot, airdropId, claimNullifier, recipient (left-padded),
+//!    amount (uint256 BE), treeLevels (uint256 BE), poolBinding]
+//! for an airdrop contract to read with
+//!   abi.decode(publicValues, (bytes32, bytes32, bytes32, bytes32, address, uint256, uint256, bytes32))
+//! `treeLevels` is the depth of the Merkle proof and `poolBinding` the
+//! snapshot pool's (see `crate::pool_binding`); the airdrop must require the
+//! snapshot tree's depth and its pool's binding, as the pool does for spends.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::nullifier_set::NullifierProof;
+use crate::{keccak256, probe, MerkleProofStep, Note};
+
+/// Domain tag for claim nullifiers (exactly 32 bytes).
+pub const CLAIM_DOMAIN: [u8; 32] = *b"shielded-pool/claim-nullifiers/1";
+
+/// Nullifier an airdrop records for a claim of the note with `commitment`.
+pub fn compute_claim_nullifier(airdrop_id: &[u8; 32], commitment: &[u8; 32], spending_key: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = [0u8; 128];
+    preimage[0..32].copy_from_slice(&CLAIM_DOMAIN);
+    preimage[32..64].copy_from_slice(airdrop_id);
+    preimage[64..96].copy_from_slice(commitment);
+    preimage[96..128].copy_from_slice(spending_key);
+    keccak256(&preimage)
+}
+
+/// Private inputs for the claim circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClaimPrivateInputs {
+    /// The note held at the snapshot
+    pub note: Note,
+    /// Spending key owning the note
+    pub spending_key: [u8; 32],
+    /// Merkle proof of the note under `root`
+    pub merkle_proof: Vec<MerkleProofStep>,
+    /// The snapshot's tree root
+    pub root: [u8; 32],
+    /// Root of the nullifiers spent by the snapshot
+    pub nullifier_root: [u8; 32],
+    /// Non-membership of the note's pool nullifier under `nullifier_root`
+    pub nullifier_proof: NullifierProof,
+    /// Identifies the airdrop; claim nullifiers are scoped to it
+    pub airdrop_id: [u8; 32],
+    /// Address the airdrop pays (20 bytes)
+    pub recipient: [u8; 20],
+    /// The snapshot's pool (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the claim circuit (256 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClaimPublicValues {
+    pub root: [u8; 32],
+    pub nullifier_root: [u8; 32],
+    pub airdrop_id: [u8; 32],
+    pub claim_nullifier: [u8; 32],
+    pub recipient: [u8; 20],
+    pub amount: u64,
+    /// Depth of the Merkle proof
+    pub tree_levels: u64,
+    /// The snapshot's pool (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+impl ClaimPublicValues {
+    pub const LEN: usize = 256;
+
+    /// ABI-encode exactly as the claim guest commits it.
+    pub fn encode(&self) -> [u8; 256] {
+        let mut out = [0u8; 256];
+        out[0..32].copy_from_slice(&self.root);
+        out[32..64].copy_from_slice(&self.nullifier_root);
+        out[64..96].copy_from_slice(&self.airdrop_id);
+        out[96..128].copy_from_slice(&self.claim_nullifier);
+        out[140..160].copy_from_slice(&self.recipient);
+        out[184..192].copy_from_slice(&self.amount.to_be_bytes());
+        out[216..224].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[224..256].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong,
+    /// the address padding is non-zero, or the amount or the tree depth does
+    /// not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN
+            || bytes[128..140].iter().any(|&b| b != 0)
+            || bytes[160..184].iter().any(|&b| b != 0)
+            || bytes[192..216].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(ClaimPublicValues {
+            root: word(0),
+            nullifier_root: word(1),
+            airdrop_id: word(2),
+            claim_nullifier: word(3),
+            recipient: bytes[140..160].try_into().unwrap(),
+            amount: u64::from_be_bytes(bytes[184..192].try_into().unwrap()),
+            tree_levels: u64::from_be_bytes(bytes[216..224].try_into().unwrap()),
+            pool_binding: word(7),
+        })
+    }
+}
+
+impl ClaimPrivateInputs {
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks.
+    pub fn check(&self) -> Result<ClaimPublicValues, &'static str> {
+        // A cancellable note's pubkey is a lock hash no key derives, so it
+        // is rejected here
+        probe!(start "claim/ownership");
+        if !self.note.is_owned_by(&self.spending_key) {
+            return Err("spending key does not match note pubkey");
+        }
+        probe!(end "claim/ownership");
+        let commitment = self.note.commitment();
+        probe!(start "claim/merkle");
+        if !crate::verify_merkle_proof(commitment, &self.merkle_proof, self.root) {
+            return Err("Merkle proof invalid");
+        }
+        probe!(end "claim/merkle");
+        // Unspent at the snapshot: its pool nullifier is not in the
+        // snapshot's nullifier set
+        probe!(start "claim/unspent");
+        let nullifier = crate::compute_nullifier(&commitment, &self.spending_key);
+        match self.nullifier_proof.verify(self.nullifier_root, &nullifier) {
+            Ok(false) => {}
+            Ok(true) => return Err("note was spent at the snapshot"),
+            Err(_) => return Err("nullifier proof does not match the nullifier root"),
+        }
+        probe!(end "claim/unspent");
+        Ok(ClaimPublicValues {
+            root: self.root,
+            nullifier_root: self.nullifier_root,
+            airdrop_id: self.airdrop_id,
+            claim_nullifier: compute_claim_nullifier(&self.airdrop_id, &commitment, &self.spending_key),
+            recipient: self.recipient,
+            amount: self.note.amount,
+            tree_levels: self.merkle_proof.len() as u64,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nullifier_set::NullifierSet;
+    use crate::{compute_nullifier, derive_pubkey, IncrementalMerkleTree};
+
+    fn claim(key: [u8; 32], spent: &[[u8; 32]]) -> ClaimPrivateInputs {
+        let note = Note { amount: 5_000_000, pubkey: derive_pubkey(&key), blinding: [9u8; 32] };
+        let mut tree = IncrementalMerkleTree::new(5);
+        tree.insert(keccak256(b"other"));
+        tree.insert(note.commitment());
+        let set: NullifierSet = spent.iter().copied().collect();
+        let nullifier = compute_nullifier(&note.commitment(), &key);
+        ClaimPrivateInputs {
+            note,
+            spending_key: key,
+            merkle_proof: tree.get_proof(1),
+            root: tree.get_root(),
+            nullifier_root: set.root(),
+            nullifier_proof: set.prove(&nullifier),
+            airdrop_id: [7u8; 32],
+            recipient: [0xab; 20],
+            pool_binding: [5u8; 32],
+        }
+    }
+
+    #[test]
+    fn test_claim_checks_and_public_values() {
+        let key = [1u8; 32];
+        let inputs = claim(key, &[keccak256(b"someone else's spend")]);
+        let pv = inputs.check().unwrap();
+        assert_eq!(pv.amount, 5_000_000);
+        assert_eq!((pv.tree_levels, pv.pool_binding), (5, [5u8; 32]));
+        assert_eq!(ClaimPublicValues::decode(&pv.encode()), Some(pv.clone()));
+
+        // One claim nullifier per note and airdrop, unrelated to the pool nullifier
+        let commitment = inputs.note.commitment();
+        assert_ne!(pv.claim_nullifier, compute_nullifier(&commitment, &key));
+        assert_ne!(pv.claim_nullifier, compute_claim_nullifier(&[8u8; 32], &commitment, &key));
+        assert_eq!(claim(key, &[]).check().unwrap().claim_nullifier, pv.claim_nullifier);
+
+        let mut wrong_key = inputs.clone();
+        wrong_key.spending_key = [2u8; 32];
+        assert_eq!(wrong_key.check(), Err("spending key does not match note pubkey"));
+        let mut wrong_root = inputs.clone();
+        wrong_root.root = [3u8; 32];
+        assert_eq!(wrong_root.check(), Err("Merkle proof invalid"));
+
+        // Spent by the snapshot, or a nullifier proof against another set
+        let spent = claim(key, &[compute_nullifier(&commitment, &key)]);
+        assert_eq!(spent.check(), Err("note was spent at the snapshot"));
+        let mut forged = spent.clone();
+        forged.nullifier_proof = inputs.nullifier_proof.clone();
+        assert_eq!(forged.check(), Err("nullifier proof does not match the nullifier root"));
+
+        let mut bytes = pv.encode();
+        bytes[130] = 1;
+        assert_eq!(ClaimPublicValues::decode(&bytes), None);
+    }
+}
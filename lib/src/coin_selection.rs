@@ -0,0 +1,494 @@
+//! Coin selection for spends.
+//!
+//! The transfer circuit is strictly 2-in-2-out, so every payment spends two
+//! input notes. Given the wallet's unspent notes and a target amount, the
+//! planner picks those two inputs and the resulting change:
+//!
+//!   1. A pair summing exactly to the target (no change note value).
+//!   2. Otherwise the pair with the smallest change, avoiding change below
+//!      the dust threshold when a non-dust alternative exists.
+//!   3. If no pair covers the target, a multi-hop plan: merge the largest
+//!      notes pairwise into one self-owned note until two inputs suffice.
+//!   4. A wallet holding a single note pairs it with a dummy input
+//!      (`NoteRef::Dummy`, see `Note::dummy`).
+//!
+//! Planning works on indices into the caller's note slice, so it never needs
+//! spending keys or Merkle proofs.
+//!
+//! A large change note stands out: spending it later ties that spend to this
+//! payment. `plan_transfer_split` returns the change as up to three notes of
+//! standard denominations instead, through the join-split circuit (one
+//! output pays the recipient). The plan's `Display` summary lists the
+//! trade-offs of what it chose.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::join_split::JOIN_SPLIT_OUTPUTS;
+use crate::{CircuitKind, Note};
+
+/// Reference to a note consumed by a planned step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteRef {
+    /// Index into the notes passed to the planner.
+    Wallet(usize),
+    /// The merged output of an earlier `Merge` step (index into `SpendPlan::steps`).
+    Merged(usize),
+    /// A zero-amount dummy input (see `TransferPrivateInputs::single_input`).
+    Dummy,
+}
+
+/// One 2-in-2-out transfer in a spend plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlannedStep {
+    /// Combine two notes into one self-owned note of `amount`
+    /// (second output is a zero-value note).
+    Merge { inputs: [NoteRef; 2], amount: u64 },
+    /// The payment itself: `amount` to the recipient, `change` back to self.
+    Pay { inputs: [NoteRef; 2], amount: u64, change: u64 },
+}
+
+/// Ordered transfers that together pay the target amount.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpendPlan {
+    /// Merges first (if any), then exactly one `Pay` step last.
+    pub steps: Vec<PlannedStep>,
+    /// Amounts of the notes the payment's change is split into, summing to
+    /// `change()`; empty when the change is one note (see `split_change`).
+    pub change_split: Vec<u64>,
+}
+
+impl SpendPlan {
+    /// The final payment step's inputs, amount and change.
+    pub fn payment(&self) -> ([NoteRef; 2], u64, u64) {
+        match self.steps.last() {
+            Some(PlannedStep::Pay { inputs, amount, change }) => (*inputs, *amount, *change),
+            _ => unreachable!("spend plan always ends with a Pay step"),
+        }
+    }
+
+    /// Change returned to the sender by the payment.
+    pub fn change(&self) -> u64 {
+        self.payment().2
+    }
+
+    /// Number of proofs the plan needs.
+    pub fn hops(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether the payment can be made with a single transfer.
+    pub fn is_single_hop(&self) -> bool {
+        self.steps.len() == 1
+    }
+
+    /// Amounts of the change notes the payment creates (none without change).
+    pub fn change_notes(&self) -> Vec<u64> {
+        match (self.change(), self.change_split.is_empty()) {
+            (0, _) => Vec::new(),
+            (change, true) => alloc::vec![change],
+            (_, false) => self.change_split.clone(),
+        }
+    }
+
+    /// Circuit the payment step is proven with: the join-split when its
+    /// change is split, the transfer otherwise. Merges always use the transfer.
+    pub fn payment_circuit(&self) -> CircuitKind {
+        if self.change_split.is_empty() {
+            CircuitKind::Transfer
+        } else {
+            CircuitKind::JoinSplit
+        }
+    }
+}
+
+/// Summary of the plan and its privacy trade-offs, for showing before
+/// anything is proven.
+impl fmt::Display for SpendPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, amount, change) = self.payment();
+        writeln!(f, "{} proof(s): {} merge(s), then pay {amount}", self.hops(), self.hops() - 1)?;
+        if self.hops() > 1 {
+            writeln!(
+                f,
+                "  - merges are separate transactions; sent close together, their timing links them to the payment"
+            )?;
+        }
+        let notes = self.change_notes();
+        match notes.len() {
+            0 => writeln!(f, "  - no change note: nothing of this payment comes back to be linked later")?,
+            1 => writeln!(
+                f,
+                "  - change {change} comes back as one note; spending it later ties that spend to this payment"
+            )?,
+            n => {
+                writeln!(f, "  - change {change} is split into {n} notes: {notes:?}")?;
+                writeln!(
+                    f,
+                    "  - proven with the join-split circuit (outputs padded to {JOIN_SPLIT_OUTPUTS}); \
+                     spending these notes together again links them"
+                )?;
+                if let Some(remainder) = self.change_split.last().filter(|&&r| r < self.change_split[0]) {
+                    writeln!(f, "  - the last note ({remainder}) is the remainder and may not be a standard size")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Planner knobs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelectionConfig {
+    /// Change strictly below this (and above zero) counts as dust.
+    pub dust_threshold: u64,
+}
+
+impl Default for SelectionConfig {
+    /// 0.01 USDT (6 decimals).
+    fn default() -> Self {
+        SelectionConfig { dust_threshold: 10_000 }
+    }
+}
+
+/// How `plan_transfer_split` splits change.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangeSplit {
+    /// Note sizes to split into, in any order
+    pub denominations: Vec<u64>,
+    /// Most change notes, capped at `JOIN_SPLIT_OUTPUTS - 1`
+    pub max_notes: usize,
+    /// Whether payments can use the join-split circuit. Without it a payment
+    /// has a single change output and nothing is split.
+    pub join_split: bool,
+}
+
+impl Default for ChangeSplit {
+    /// 1, 2 and 5 USDT times powers of ten up to 500,000 USDT, into at most
+    /// three notes. The join-split is off: `send` only proves transfers.
+    fn default() -> Self {
+        let denominations = (0..6)
+            .flat_map(|exp| [1u64, 2, 5].map(|d| d * 10u64.pow(exp) * 1_000_000))
+            .collect();
+        ChangeSplit { denominations, max_notes: JOIN_SPLIT_OUTPUTS - 1, join_split: false }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionError {
+    /// Transfers of zero are not useful.
+    ZeroAmount,
+    /// The wallet's notes do not add up to the target.
+    InsufficientFunds { available: u64, required: u64 },
+    /// Paying a prover fee takes two notes, but the wallet only has one
+    /// (see `prover_fee::plan_fee_transfer`).
+    NeedsTwoNotes,
+    /// No single note covers a withdrawal of this size.
+    NoSingleNoteCovers { largest: u64, required: u64 },
+    /// Every covering pair would pay the prover more than `max_fee`
+    /// (see `prover_fee::plan_fee_transfer`).
+    NoPairWithinFee { fee: u64, max_fee: u64 },
+}
+
+impl core::fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SelectionError::ZeroAmount => write!(f, "amount must be greater than zero"),
+            SelectionError::InsufficientFunds { available, required } => {
+                write!(f, "insufficient funds: have {available}, need {required}")
+            }
+            SelectionError::NeedsTwoNotes => {
+                write!(f, "a fee-paying transfer needs two input notes but only one is available")
+            }
+            SelectionError::NoSingleNoteCovers { largest, required } => write!(
+                f,
+                "no single note covers {required} (largest is {largest}); merge notes first"
+            ),
+            SelectionError::NoPairWithinFee { fee, max_fee } => write!(
+                f,
+                "no pair of notes pays a fee between {fee} and {max_fee}; merge or split notes first"
+            ),
+        }
+    }
+}
+
+/// Plan a transfer of `amount` from `notes`.
+pub fn plan_transfer(
+    notes: &[Note],
+    amount: u64,
+    config: SelectionConfig,
+) -> Result<SpendPlan, SelectionError> {
+    if amount == 0 {
+        return Err(SelectionError::ZeroAmount);
+    }
+    let available: u64 = notes.iter().map(|n| n.amount).fold(0u64, u64::saturating_add);
+    if available < amount {
+        return Err(SelectionError::InsufficientFunds { available, required: amount });
+    }
+    if notes.len() == 1 {
+        return Ok(SpendPlan {
+            steps: alloc::vec![PlannedStep::Pay {
+                inputs: [NoteRef::Wallet(0), NoteRef::Dummy],
+                amount,
+                change: notes[0].amount - amount,
+            }],
+            change_split: Vec::new(),
+        });
+    }
+
+    if let Some((a, b)) = best_pair(notes, amount, config) {
+        let change = notes[a].amount + notes[b].amount - amount;
+        return Ok(SpendPlan {
+            steps: alloc::vec![PlannedStep::Pay {
+                inputs: [NoteRef::Wallet(a), NoteRef::Wallet(b)],
+                amount,
+                change,
+            }],
+            change_split: Vec::new(),
+        });
+    }
+
+    // No pair covers the target: take the largest notes until they do,
+    // then fold them pairwise into one merged note.
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by(|&x, &y| notes[y].amount.cmp(&notes[x].amount).then(x.cmp(&y)));
+
+    let mut used = 0usize;
+    let mut sum = 0u64;
+    while sum < amount {
+        sum += notes[order[used]].amount;
+        used += 1;
+    }
+
+    let mut steps = Vec::with_capacity(used - 1);
+    let mut carry = NoteRef::Wallet(order[0]);
+    let mut carry_amount = notes[order[0]].amount;
+    for &idx in &order[1..used - 1] {
+        carry_amount += notes[idx].amount;
+        steps.push(PlannedStep::Merge {
+            inputs: [carry, NoteRef::Wallet(idx)],
+            amount: carry_amount,
+        });
+        carry = NoteRef::Merged(steps.len() - 1);
+    }
+    let last = order[used - 1];
+    steps.push(PlannedStep::Pay {
+        inputs: [carry, NoteRef::Wallet(last)],
+        amount,
+        change: carry_amount + notes[last].amount - amount,
+    });
+    Ok(SpendPlan { steps, change_split: Vec::new() })
+}
+
+/// Plan a transfer like `plan_transfer`, then split the payment's change
+/// with `split_change`.
+pub fn plan_transfer_split(
+    notes: &[Note],
+    amount: u64,
+    config: SelectionConfig,
+    split: &ChangeSplit,
+) -> Result<SpendPlan, SelectionError> {
+    let mut plan = plan_transfer(notes, amount, config)?;
+    plan.change_split = split_change(plan.change(), split);
+    Ok(plan)
+}
+
+/// Split `change` into standard denominations, largest first: each note but
+/// the last is the largest denomination that fits, the last takes what is
+/// left. Empty when the change stays one note (no join-split, a single note
+/// allowed, or no denomination fits).
+pub fn split_change(change: u64, split: &ChangeSplit) -> Vec<u64> {
+    let max_notes = split.max_notes.min(JOIN_SPLIT_OUTPUTS - 1);
+    if !split.join_split || max_notes < 2 {
+        return Vec::new();
+    }
+    let mut denominations: Vec<u64> = split.denominations.iter().copied().filter(|&d| d > 0).collect();
+    denominations.sort_unstable_by(|a, b| b.cmp(a));
+
+    let mut notes = Vec::with_capacity(max_notes);
+    let mut remaining = change;
+    while remaining > 0 && notes.len() + 1 < max_notes {
+        let Some(&d) = denominations.iter().find(|&&d| d <= remaining) else {
+            break;
+        };
+        notes.push(d);
+        remaining -= d;
+    }
+    if remaining > 0 {
+        notes.push(remaining);
+    }
+    if notes.len() < 2 {
+        return Vec::new();
+    }
+    notes
+}
+
+/// Pick the single note to spend for a withdrawal of `amount`.
+///
+/// Prefers an exact match (no change note), then the smallest note that
+/// covers the amount without leaving dust, then the smallest that covers it.
+pub fn select_withdraw_note(
+    notes: &[Note],
+    amount: u64,
+    config: SelectionConfig,
+) -> Result<usize, SelectionError> {
+    if amount == 0 {
+        return Err(SelectionError::ZeroAmount);
+    }
+    let mut best: Option<(u8, u64, usize)> = None;
+    for (i, n) in notes.iter().enumerate() {
+        if n.amount < amount {
+            continue;
+        }
+        let key = (change_rank(n.amount - amount, config), n.amount - amount, i);
+        if best.is_none_or(|b| key < b) {
+            best = Some(key);
+        }
+    }
+    match best {
+        Some((_, _, i)) => Ok(i),
+        None => {
+            let largest = notes.iter().map(|n| n.amount).max().unwrap_or(0);
+            let available: u64 = notes.iter().map(|n| n.amount).fold(0u64, u64::saturating_add);
+            if available < amount {
+                Err(SelectionError::InsufficientFunds { available, required: amount })
+            } else {
+                Err(SelectionError::NoSingleNoteCovers { largest, required: amount })
+            }
+        }
+    }
+}
+
+/// 0 = exact, 1 = clean change, 2 = dust change.
+fn change_rank(change: u64, config: SelectionConfig) -> u8 {
+    if change == 0 {
+        0
+    } else if change >= config.dust_threshold {
+        1
+    } else {
+        2
+    }
+}
+
+/// Best covering pair by (change rank, change, indices). O(n²) — wallets are small.
+fn best_pair(notes: &[Note], amount: u64, config: SelectionConfig) -> Option<(usize, usize)> {
+    let mut best: Option<(u8, u64, usize, usize)> = None;
+    for a in 0..notes.len() {
+        for b in a + 1..notes.len() {
+            let sum = notes[a].amount as u128 + notes[b].amount as u128;
+            if sum < amount as u128 {
+                continue;
+            }
+            let change = (sum - amount as u128) as u64;
+            let key = (change_rank(change, config), change, a, b);
+            if best.is_none_or(|k| key < k) {
+                best = Some(key);
+            }
+        }
+    }
+    best.map(|(_, _, a, b)| (a, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn notes(amounts: &[u64]) -> Vec<Note> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note { amount, pubkey: [1u8; 32], blinding: [i as u8; 32] })
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_pair_preferred() {
+        let ns = notes(&[700_000, 300_000, 900_000]);
+        let plan = plan_transfer(&ns, 1_000_000, SelectionConfig::default()).unwrap();
+        assert!(plan.is_single_hop());
+        assert_eq!(plan.payment(), ([NoteRef::Wallet(0), NoteRef::Wallet(1)], 1_000_000, 0));
+    }
+
+    #[test]
+    fn test_dust_change_avoided() {
+        // 500_001 + 500_000 leaves 1 unit of dust; 600_000 + 500_000 leaves clean change.
+        let ns = notes(&[500_001, 500_000, 600_000]);
+        let plan = plan_transfer(&ns, 1_000_000, SelectionConfig::default()).unwrap();
+        assert_eq!(plan.change(), 100_000);
+    }
+
+    #[test]
+    fn test_multi_hop_plan() {
+        let ns = notes(&[100, 200, 300, 400]);
+        let plan = plan_transfer(&ns, 850, SelectionConfig { dust_threshold: 0 }).unwrap();
+        // Largest first: 400 + 300 merged, then + 200 pays 850 with 50 change.
+        assert_eq!(
+            plan.steps,
+            vec![
+                PlannedStep::Merge { inputs: [NoteRef::Wallet(3), NoteRef::Wallet(2)], amount: 700 },
+                PlannedStep::Pay {
+                    inputs: [NoteRef::Merged(0), NoteRef::Wallet(1)],
+                    amount: 850,
+                    change: 50
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_selection_errors() {
+        let cfg = SelectionConfig::default();
+        assert_eq!(plan_transfer(&notes(&[5]), 0, cfg), Err(SelectionError::ZeroAmount));
+        assert_eq!(
+            plan_transfer(&notes(&[5, 5]), 11, cfg),
+            Err(SelectionError::InsufficientFunds { available: 10, required: 11 })
+        );
+        // One note pays with a dummy in the second slot
+        let plan = plan_transfer(&notes(&[50]), 10, cfg).unwrap();
+        assert_eq!(plan.payment(), ([NoteRef::Wallet(0), NoteRef::Dummy], 10, 40));
+    }
+
+    #[test]
+    fn test_change_split() {
+        let usdt = 1_000_000u64;
+        let split = ChangeSplit { join_split: true, ..ChangeSplit::default() };
+        assert_eq!(split_change(1_100 * usdt, &split), vec![1_000 * usdt, 100 * usdt]);
+        assert_eq!(split_change(7_300_000, &split), vec![5 * usdt, 2 * usdt, 300_000]);
+        // Up to three notes; the last takes the rest
+        assert_eq!(split_change(880 * usdt, &split), vec![500 * usdt, 200 * usdt, 180 * usdt]);
+        // Nothing to split
+        assert!(split_change(0, &split).is_empty());
+        assert!(split_change(1_000 * usdt, &split).is_empty(), "already a denomination");
+        assert!(split_change(300_000, &split).is_empty());
+        assert!(split_change(1_100 * usdt, &ChangeSplit::default()).is_empty());
+        assert!(split_change(1_100 * usdt, &ChangeSplit { max_notes: 1, ..split.clone() }).is_empty());
+        assert_eq!(split_change(4_900 * usdt, &ChangeSplit { max_notes: 9, ..split.clone() }).len(), 3);
+
+        let ns = notes(&[900 * usdt, 300 * usdt]);
+        let plan = plan_transfer_split(&ns, 100 * usdt, SelectionConfig::default(), &split).unwrap();
+        assert_eq!(plan.change(), 1_100 * usdt);
+        assert_eq!(plan.change_notes(), vec![1_000 * usdt, 100 * usdt]);
+        assert_eq!(plan.change_split.iter().sum::<u64>(), plan.change());
+        assert_eq!(plan.payment_circuit(), CircuitKind::JoinSplit);
+        let summary = alloc::format!("{plan}");
+        assert!(summary.contains("split into 2 notes"), "{summary}");
+
+        let unsplit = plan_transfer(&ns, 100 * usdt, SelectionConfig::default()).unwrap();
+        assert_eq!(unsplit.change_notes(), vec![1_100 * usdt]);
+        assert_eq!(unsplit.payment_circuit(), CircuitKind::Transfer);
+        assert!(alloc::format!("{unsplit}").contains("comes back as one note"));
+    }
+
+    #[test]
+    fn test_select_withdraw_note() {
+        let cfg = SelectionConfig::default();
+        let ns = notes(&[1_000_000, 300_000, 400_000]);
+        assert_eq!(select_withdraw_note(&ns, 300_000, cfg), Ok(1));
+        assert_eq!(select_withdraw_note(&ns, 350_000, cfg), Ok(2));
+        assert_eq!(
+            select_withdraw_note(&ns, 1_200_000, cfg),
+            Err(SelectionError::NoSingleNoteCovers { largest: 1_000_000, required: 1_200_000 })
+        );
+    }
+}
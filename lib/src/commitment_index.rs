@@ -0,0 +1,274 @@
+//! Sorted index of the tree's leaves, for proving a commitment was never
+//! inserted.
+//!
+//! The pool's tree keeps leaves in insertion order, so it can only show that
+//! a commitment is there. `CommitmentIndex` holds the same leaves sorted, in
+//! a binary Merkle tree of the same depth. Two leaves next to each other in
+//! it, one below and one above a commitment, show the commitment is in
+//! neither place it could be sorted into; the first or last leaf does the
+//! same at the ends. That settles a disputed deposit: either the commitment
+//! has a leaf in the pool, or an `AbsenceProof` shows it has none.
+//!
+//! An `IndexRoot` names the pool root the index was built from, the leaf
+//! count and the sorted tree's root. The count pins which leaf is last, so a
+//! proof cannot stop short of the end. The index is not checked against the
+//! pool by the proof itself: whoever holds the pool's leaves (they are all in
+//! its events) rebuilds it with `CommitmentIndex::new` and compares roots.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{compute_merkle_root, keccak256, IncrementalMerkleTree, MerkleProofStep};
+
+/// Domain separator of `IndexRoot::digest`.
+pub const INDEX_ROOT_DOMAIN: &[u8] = b"shielded-pool/commitment-index/1";
+
+/// What absence proofs are checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexRoot {
+    /// Root of the pool's tree the index was built from
+    pub tree_root: [u8; 32],
+    pub leaf_count: u32,
+    /// Root of the sorted leaves
+    pub sorted_root: [u8; 32],
+}
+
+/// A leaf of the sorted tree with its membership proof.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SortedLeaf {
+    /// Position in sorted order
+    pub position: u32,
+    pub commitment: [u8; 32],
+    pub proof: Vec<MerkleProofStep>,
+}
+
+/// Proof that a commitment is not among the index's leaves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbsenceProof {
+    /// The index has no leaves
+    Empty,
+    /// The commitment sorts before the first leaf
+    Below(SortedLeaf),
+    /// The commitment sorts after the last leaf
+    Above(SortedLeaf),
+    /// The commitment sorts between two neighbouring leaves
+    Between(SortedLeaf, SortedLeaf),
+}
+
+/// The tree's leaves in sorted order.
+#[derive(Clone, Debug)]
+pub struct CommitmentIndex {
+    tree_root: [u8; 32],
+    sorted: IncrementalMerkleTree,
+}
+
+impl IndexRoot {
+    /// One hash over the whole root, for publishing or signing.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut data = Vec::with_capacity(INDEX_ROOT_DOMAIN.len() + 68);
+        data.extend_from_slice(INDEX_ROOT_DOMAIN);
+        data.extend_from_slice(&self.tree_root);
+        data.extend_from_slice(&self.leaf_count.to_be_bytes());
+        data.extend_from_slice(&self.sorted_root);
+        keccak256(&data)
+    }
+}
+
+impl CommitmentIndex {
+    /// Index of `tree`'s leaves.
+    pub fn new(tree: &IncrementalMerkleTree) -> Self {
+        let mut leaves = tree.leaves.clone();
+        leaves.sort_unstable();
+        let sorted = IncrementalMerkleTree::from_leaves(tree.levels, leaves).expect("as many leaves as the tree holds");
+        CommitmentIndex { tree_root: tree.get_root(), sorted }
+    }
+
+    pub fn root(&self) -> IndexRoot {
+        IndexRoot { tree_root: self.tree_root, leaf_count: self.sorted.next_index, sorted_root: self.sorted.get_root() }
+    }
+
+    pub fn contains(&self, commitment: &[u8; 32]) -> bool {
+        self.sorted.leaves.binary_search(commitment).is_ok()
+    }
+
+    fn leaf(&self, position: usize) -> Result<SortedLeaf, &'static str> {
+        Ok(SortedLeaf {
+            position: position as u32,
+            commitment: self.sorted.leaves[position],
+            proof: self.sorted.try_get_proof(position as u32)?,
+        })
+    }
+
+    /// Proof that `commitment` is not a leaf, against `root()`.
+    pub fn prove_absence(&self, commitment: &[u8; 32]) -> Result<AbsenceProof, &'static str> {
+        let leaves = &self.sorted.leaves;
+        let above = match leaves.binary_search(commitment) {
+            Ok(_) => return Err("commitment is in the tree"),
+            Err(above) => above,
+        };
+        Ok(match (above, leaves.len()) {
+            (_, 0) => AbsenceProof::Empty,
+            (0, _) => AbsenceProof::Below(self.leaf(0)?),
+            (above, len) if above == len => AbsenceProof::Above(self.leaf(len - 1)?),
+            (above, _) => AbsenceProof::Between(self.leaf(above - 1)?, self.leaf(above)?),
+        })
+    }
+}
+
+impl SortedLeaf {
+    /// Check the leaf sits at `position` under `root`.
+    fn verify(&self, root: &IndexRoot) -> Result<(), &'static str> {
+        if self.position >= root.leaf_count {
+            return Err("position beyond the last leaf");
+        }
+        if self.proof.len() > 32 || (self.proof.len() < 32 && root.leaf_count > 1 << self.proof.len()) {
+            return Err("proof depth does not fit the leaf count");
+        }
+        // The path must be the position's, or a leaf could be proven at another one
+        let on_path =
+            self.proof.iter().enumerate().all(|(level, step)| step.is_left == (self.position >> level & 1 == 0));
+        if !on_path {
+            return Err("proof path does not match the position");
+        }
+        if compute_merkle_root(self.commitment, &self.proof) != root.sorted_root {
+            return Err("proof does not match the sorted root");
+        }
+        Ok(())
+    }
+}
+
+impl AbsenceProof {
+    /// Check the proof shows `commitment` is not under `root`.
+    pub fn verify(&self, root: &IndexRoot, commitment: &[u8; 32]) -> Result<(), &'static str> {
+        match self {
+            AbsenceProof::Empty => {
+                if root.leaf_count != 0 {
+                    return Err("the index is not empty");
+                }
+            }
+            AbsenceProof::Below(first) => {
+                first.verify(root)?;
+                if first.position != 0 {
+                    return Err("not the first leaf");
+                }
+                if commitment >= &first.commitment {
+                    return Err("commitment does not sort before the first leaf");
+                }
+            }
+            AbsenceProof::Above(last) => {
+                last.verify(root)?;
+                if last.position + 1 != root.leaf_count {
+                    return Err("not the last leaf");
+                }
+                if commitment <= &last.commitment {
+                    return Err("commitment does not sort after the last leaf");
+                }
+            }
+            AbsenceProof::Between(low, high) => {
+                low.verify(root)?;
+                high.verify(root)?;
+                if low.position + 1 != high.position {
+                    return Err("leaves are not neighbours");
+                }
+                if !(&low.commitment < commitment && commitment < &high.commitment) {
+                    return Err("commitment does not sort between the leaves");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commitment(i: u32) -> [u8; 32] {
+        keccak256(&i.to_be_bytes())
+    }
+
+    #[test]
+    fn test_absence_proofs() {
+        let empty = CommitmentIndex::new(&IncrementalMerkleTree::new(6));
+        assert_eq!(empty.prove_absence(&commitment(0)), Ok(AbsenceProof::Empty));
+        assert_eq!(AbsenceProof::Empty.verify(&empty.root(), &commitment(0)), Ok(()));
+
+        let mut tree = IncrementalMerkleTree::new(6);
+        for i in 0..40 {
+            tree.insert(commitment(i));
+        }
+        let index = CommitmentIndex::new(&tree);
+        let root = index.root();
+        assert_eq!((root.tree_root, root.leaf_count), (tree.get_root(), 40));
+        assert_eq!(AbsenceProof::Empty.verify(&root, &commitment(0)), Err("the index is not empty"));
+
+        // Order of insertion does not matter
+        let mut shuffled = IncrementalMerkleTree::new(6);
+        for i in (0..40).rev() {
+            shuffled.insert(commitment(i));
+        }
+        assert_eq!(CommitmentIndex::new(&shuffled).root().sorted_root, root.sorted_root);
+
+        for i in 0..40 {
+            assert!(index.contains(&commitment(i)));
+            assert_eq!(index.prove_absence(&commitment(i)), Err("commitment is in the tree"));
+        }
+        for i in 40..200 {
+            let absent = commitment(i);
+            assert!(!index.contains(&absent));
+            let proof = index.prove_absence(&absent).unwrap();
+            assert_eq!(proof.verify(&root, &absent), Ok(()), "commitment {i}");
+        }
+
+        // Both ends
+        let (lowest, highest) = ([0u8; 32], [0xff; 32]);
+        let below = index.prove_absence(&lowest).unwrap();
+        let above = index.prove_absence(&highest).unwrap();
+        assert!(matches!(below, AbsenceProof::Below(_)));
+        assert!(matches!(above, AbsenceProof::Above(_)));
+        assert_eq!(below.verify(&root, &lowest), Ok(()));
+        assert_eq!(above.verify(&root, &highest), Ok(()));
+        assert_ne!(root.digest(), CommitmentIndex::new(&IncrementalMerkleTree::new(6)).root().digest());
+    }
+
+    #[test]
+    fn test_absence_proof_rejects_tampering() {
+        let mut tree = IncrementalMerkleTree::new(5);
+        for i in 0..12 {
+            tree.insert(commitment(i));
+        }
+        let index = CommitmentIndex::new(&tree);
+        let root = index.root();
+        // Just above the sixth smallest leaf
+        let mut sorted = tree.leaves.clone();
+        sorted.sort_unstable();
+        let mut absent = sorted[5];
+        absent[16..].fill(0xff);
+        let AbsenceProof::Between(low, high) = index.prove_absence(&absent).unwrap() else {
+            panic!("expected neighbours");
+        };
+        assert_eq!((low.position, high.position), (5, 6));
+
+        // A proof cannot cover a commitment that is in the tree
+        let present = commitment(3);
+        assert!(AbsenceProof::Between(low.clone(), high.clone()).verify(&root, &present).is_err());
+
+        // Leaves that are not neighbours leave a gap a leaf could hide in
+        let further = index.leaf(4).unwrap();
+        assert_eq!(AbsenceProof::Between(further, high.clone()).verify(&root, &absent), Err("leaves are not neighbours"));
+
+        // A leaf claimed at another position
+        let moved = SortedLeaf { position: low.position ^ 1, ..low.clone() };
+        assert_eq!(moved.verify(&root), Err("proof path does not match the position"));
+
+        // Stopping short of the end
+        assert_eq!(AbsenceProof::Above(high.clone()).verify(&root, &[0xff; 32]), Err("not the last leaf"));
+
+        // Another index (one leaf more) or a stale one
+        let mut grown = tree.clone();
+        grown.insert(absent);
+        let grown_root = CommitmentIndex::new(&grown).root();
+        assert!(AbsenceProof::Between(low, high).verify(&grown_root, &absent).is_err());
+        assert_eq!(CommitmentIndex::new(&grown).prove_absence(&absent), Err("commitment is in the tree"));
+    }
+}
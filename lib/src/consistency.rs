@@ -0,0 +1,190 @@
+//! Checking a local tree against the pool's tree.
+//!
+//! The pool exposes its latest root, its leaf count and the roots after its
+//! last ROOT_HISTORY_SIZE insertions. A local tree relates to the pool's in
+//! one of four ways: the two are in sync, the local tree is missing the
+//! latest leaves (behind), it holds leaves the pool does not (ahead, e.g.
+//! events since reorged out), or the leaves differ somewhere (diverged).
+//! Which one, and up to which insertion they agree, tells a sync whether to
+//! replay forward, roll back, or start over.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+use crate::{IncrementalMerkleTree, ROOT_HISTORY_SIZE};
+
+/// How a local tree relates to the pool's tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consistency {
+    /// Same leaf count and root
+    InSync,
+    /// The pool has `missing` more leaves. `audit` cannot check the local
+    /// leaves in this case; `audit_with_roots` can, while the pool still
+    /// remembers the root they hash to.
+    Behind { missing: u32 },
+    /// The pool's tree is the local tree without its last `extra` leaves
+    Ahead { extra: u32 },
+    /// The leaves differ. The first `agrees_through` insertions match a root
+    /// the pool accepted, when that is known; the next one is where they
+    /// part.
+    Diverged { agrees_through: Option<u32> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsistencyReport {
+    pub local_leaves: u32,
+    pub on_chain_leaves: u32,
+    pub consistency: Consistency,
+}
+
+impl ConsistencyReport {
+    pub fn is_in_sync(&self) -> bool {
+        self.consistency == Consistency::InSync
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (local, chain) = (self.local_leaves, self.on_chain_leaves);
+        match self.consistency {
+            Consistency::InSync => write!(f, "local tree matches the pool ({local} leaves)"),
+            Consistency::Behind { missing } => {
+                write!(f, "local tree is {missing} leaf/leaves behind the pool ({local} of {chain})")
+            }
+            Consistency::Ahead { extra } => write!(
+                f,
+                "local tree has {extra} leaf/leaves the pool does not ({local} vs {chain}); \
+                 roll back to {chain} leaves"
+            ),
+            Consistency::Diverged { agrees_through: Some(k) } => write!(
+                f,
+                "local tree diverges from the pool after {k} leaves (leaf {k} differs; {local} local, {chain} on-chain)"
+            ),
+            Consistency::Diverged { agrees_through: None } => write!(
+                f,
+                "local tree diverges from the pool within its first {} leaves ({local} local, {chain} on-chain)",
+                local.min(chain)
+            ),
+        }
+    }
+}
+
+impl IncrementalMerkleTree {
+    /// Compare with the pool's latest root and leaf count (`getLastRoot`,
+    /// `getLeafCount`, read at the same block).
+    pub fn audit(&self, on_chain_root: [u8; 32], on_chain_leaf_count: u32) -> ConsistencyReport {
+        self.audit_with_roots(on_chain_root, on_chain_leaf_count, &[])
+    }
+
+    /// As `audit`, also checking local prefixes against `recent_roots`, the
+    /// roots the pool still accepts. That locates a divergence, and checks
+    /// the leaves of a tree that is behind.
+    pub fn audit_with_roots(
+        &self,
+        on_chain_root: [u8; 32],
+        on_chain_leaf_count: u32,
+        recent_roots: &[[u8; 32]],
+    ) -> ConsistencyReport {
+        let (local, chain) = (self.next_index, on_chain_leaf_count);
+        let report = |consistency| ConsistencyReport { local_leaves: local, on_chain_leaves: chain, consistency };
+
+        let known = |k: u32, root: &[u8; 32]| {
+            (k == chain && *root == on_chain_root) || (*root != [0u8; 32] && recent_roots.contains(root))
+        };
+        // Only the pool's last ROOT_HISTORY_SIZE prefixes can be in its history
+        let hi = local.min(chain);
+        let lo = if recent_roots.is_empty() { hi } else { chain.saturating_sub(ROOT_HISTORY_SIZE as u32 - 1).min(hi) };
+        let agrees_through =
+            self.prefix_roots(lo, hi).into_iter().rev().find(|(k, root)| known(*k, root)).map(|(k, _)| k);
+
+        report(match (local.cmp(&chain), agrees_through) {
+            (_, Some(k)) if k == chain && local == chain => Consistency::InSync,
+            (Ordering::Greater, Some(k)) if k == chain => Consistency::Ahead { extra: local - chain },
+            (Ordering::Less, Some(k)) if k == local => Consistency::Behind { missing: chain - local },
+            (Ordering::Less, None) if recent_roots.is_empty() => Consistency::Behind { missing: chain - local },
+            (_, agrees_through) => Consistency::Diverged { agrees_through },
+        })
+    }
+
+    /// (k, root after the first k insertions) for k in `lo..=hi`.
+    fn prefix_roots(&self, lo: u32, hi: u32) -> Vec<(u32, [u8; 32])> {
+        let mut roots = Vec::new();
+        // The tree's own history holds the roots from this prefix length on
+        let buffered = self.next_index.saturating_sub(ROOT_HISTORY_SIZE as u32 - 1);
+        if lo < buffered && lo <= hi {
+            let mut prefix = IncrementalMerkleTree::from_leaves(self.levels, self.leaves[..lo as usize].iter().copied())
+                .expect("a prefix fits wherever the whole does");
+            roots.push((lo, prefix.get_root()));
+            for k in lo + 1..=hi.min(buffered - 1) {
+                prefix.insert(self.leaves[k as usize - 1]);
+                roots.push((k, prefix.get_root()));
+            }
+        }
+        for k in lo.max(buffered)..=hi {
+            let age = (self.next_index - k) as usize;
+            roots.push((k, self.roots[(self.current_root_index + ROOT_HISTORY_SIZE - age) % ROOT_HISTORY_SIZE]));
+        }
+        roots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+    use alloc::string::ToString;
+
+    fn tree(leaves: &[u32]) -> IncrementalMerkleTree {
+        let leaves: Vec<_> = leaves.iter().map(|i| keccak256(&i.to_be_bytes())).collect();
+        IncrementalMerkleTree::from_leaves(7, leaves).unwrap()
+    }
+
+    #[test]
+    fn test_audit_against_latest_root() {
+        let chain = tree(&(0..40).collect::<Vec<_>>());
+        let (root, count) = (chain.get_root(), chain.next_index);
+
+        assert!(chain.audit(root, count).is_in_sync());
+        let behind = tree(&(0..35).collect::<Vec<_>>());
+        assert_eq!(behind.audit(root, count).consistency, Consistency::Behind { missing: 5 });
+        let ahead = tree(&(0..43).collect::<Vec<_>>());
+        assert_eq!(ahead.audit(root, count).consistency, Consistency::Ahead { extra: 3 });
+        // Ahead by more than the tree's own root history still resolves
+        let far = tree(&(0..40).chain(100..140).collect::<Vec<_>>());
+        assert_eq!(far.audit(root, count).consistency, Consistency::Ahead { extra: 40 });
+
+        let mut forked: Vec<u32> = (0..40).collect();
+        forked[37] = 999;
+        let report = tree(&forked).audit(root, count);
+        assert_eq!(report.consistency, Consistency::Diverged { agrees_through: None });
+    }
+
+    #[test]
+    fn test_audit_with_roots_locates_divergence() {
+        let chain = tree(&(0..40).collect::<Vec<_>>());
+        let (root, count, history) = (chain.get_root(), chain.next_index, chain.recent_roots());
+
+        let mut forked: Vec<u32> = (0..40).collect();
+        forked[37] = 999;
+        let report = tree(&forked).audit_with_roots(root, count, &history);
+        assert_eq!(report.consistency, Consistency::Diverged { agrees_through: Some(37) });
+        assert!(report.to_string().contains("after 37 leaves"));
+
+        // A tree behind the pool is checked against the root it hashes to
+        let behind = tree(&(0..35).collect::<Vec<_>>());
+        assert_eq!(behind.audit_with_roots(root, count, &history).consistency, Consistency::Behind { missing: 5 });
+        let mut stale: Vec<u32> = (0..35).collect();
+        stale[20] = 999;
+        assert_eq!(
+            tree(&stale).audit_with_roots(root, count, &history).consistency,
+            Consistency::Diverged { agrees_through: Some(20) }
+        );
+        // Agreement older than the pool's history cannot be located
+        stale[5] = 999;
+        assert_eq!(
+            tree(&stale).audit_with_roots(root, count, &history).consistency,
+            Consistency::Diverged { agrees_through: None }
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Note consolidation: up to eight notes of one key merged into one.
+//!
+//! Wallets accumulate small change notes from repeated transfers, and the
+//! transfer circuit only merges two at a time. The consolidation circuit
+//! spends 2 to `CONSOLIDATE_INPUTS` notes owned by one spending key into a
+//! single note owned by the same key, so value never changes hands:
+//!
+//!   - every input is owned by the key and is in the tree under `root`
+//!   - no note is spent twice
+//!   - the output's amount is the inputs' total
+//!
+//! Cancellable notes cannot be consolidated; claim them first. Unused
+//! nullifier slots are zero, as in `batch_withdraw`, so the public values
+//! show how many notes were merged but nothing about their amounts.
+//!
+//! Public values committed (384 bytes = 12 × 32-byte slots):
+//!   [root, nullifier0..7, outCommitment, treeLevels (uint256 BE), poolBinding]
+//! for `ShieldedPool.consolidate` to read with
+//!   abi.decode(publicValues, (bytes32, bytes32[8], bytes32, uint256, bytes32))
+//! It rejects a spent or repeated non-zero nullifier, requires `treeLevels`
+//! to be its tree's depth and `poolBinding` its own, and inserts the output
+//! commitment.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    check_output_amount, compute_nullifier, derive_pubkey, owns_pubkey, probe, proof_levels, verify_merkle_proof,
+    CheckError, MerkleProofStep, Note,
+};
+
+/// Input slots of the consolidation circuit.
+pub const CONSOLIDATE_INPUTS: usize = 8;
+
+/// Private inputs for the consolidation circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConsolidatePrivateInputs {
+    /// 2 to `CONSOLIDATE_INPUTS` notes to merge
+    pub input_notes: Vec<Note>,
+    /// One per input note, under `root`
+    pub merkle_proofs: Vec<Vec<MerkleProofStep>>,
+    /// Owns every input and the output
+    pub spending_key: [u8; 32],
+    /// The merged note
+    pub output_note: Note,
+    /// The Merkle root every input's proof verifies against
+    pub root: [u8; 32],
+    /// The pool the proof is for (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the consolidation circuit (384 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsolidatePublicValues {
+    pub root: [u8; 32],
+    /// One per input note, then zeros
+    pub nullifiers: [[u8; 32]; CONSOLIDATE_INPUTS],
+    pub out_commitment: [u8; 32],
+    /// Depth of the inputs' Merkle proofs (see `proof_levels`)
+    pub tree_levels: u64,
+    /// The pool the proof is for (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+impl ConsolidatePrivateInputs {
+    /// Merge `inputs` (each note with its Merkle proof under `root`) into one
+    /// note of `spending_key` with the given `blinding`, which must be fresh
+    /// randomness. `pool_binding` is left zero; set it for the pool the proof
+    /// is for.
+    pub fn new(
+        root: [u8; 32],
+        inputs: Vec<(Note, Vec<MerkleProofStep>)>,
+        spending_key: [u8; 32],
+        blinding: [u8; 32],
+    ) -> Result<Self, &'static str> {
+        let amount = inputs
+            .iter()
+            .try_fold(0u64, |sum, (note, _)| sum.checked_add(note.amount))
+            .ok_or("consolidated amount overflows")?;
+        let (input_notes, merkle_proofs) = inputs.into_iter().unzip();
+        Ok(ConsolidatePrivateInputs {
+            input_notes,
+            merkle_proofs,
+            spending_key,
+            output_note: Note { amount, pubkey: derive_pubkey(&spending_key), blinding },
+            root,
+            pool_binding: [0u8; 32],
+        })
+    }
+
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks
+    /// (`guest_check`).
+    pub fn check(&self) -> Result<ConsolidatePublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The consolidation guest's checks, in its order. A rejected input is
+    /// named.
+    pub fn guest_check(&self) -> Result<ConsolidatePublicValues, CheckError> {
+        // Between two notes and one per nullifier slot, each with its proof
+        let count = self.input_notes.len();
+        probe!(start "consolidate/shape");
+        if !(2..=CONSOLIDATE_INPUTS).contains(&count) || self.merkle_proofs.len() != count {
+            return Err("consolidation merges 2 to 8 notes, each with a Merkle proof".into());
+        }
+        probe!(end "consolidate/shape");
+        // Cancellable notes are rejected: no key derives their lock pubkey
+        let mut nullifiers = [[0u8; 32]; CONSOLIDATE_INPUTS];
+        for (i, (note, proof)) in self.input_notes.iter().zip(&self.merkle_proofs).enumerate() {
+            probe!(start "consolidate/ownership");
+            if !note.is_owned_by(&self.spending_key) {
+                return Err(CheckError::at("input note", i, "spending key does not match note pubkey"));
+            }
+            probe!(end "consolidate/ownership");
+            let commitment = note.commitment();
+            probe!(start "consolidate/merkle");
+            if !verify_merkle_proof(commitment, proof, self.root) {
+                return Err(CheckError::at("input note", i, "Merkle proof invalid"));
+            }
+            probe!(end "consolidate/merkle");
+            nullifiers[i] = compute_nullifier(&commitment, &self.spending_key);
+        }
+        // Spending one note in two slots would repeat its nullifier and mint
+        // its value twice
+        probe!(start "consolidate/distinct_inputs");
+        if (1..count).any(|i| nullifiers[..i].contains(&nullifiers[i])) {
+            return Err("duplicate input note".into());
+        }
+        probe!(end "consolidate/distinct_inputs");
+        // A shorter proof can reach a known root from an inner node
+        probe!(start "consolidate/proof_depth");
+        let tree_levels = proof_levels(&self.merkle_proofs)?;
+        probe!(end "consolidate/proof_depth");
+        // The merged note stays with the key that spent the inputs
+        probe!(start "consolidate/output_owner");
+        if !owns_pubkey(&self.spending_key, &self.output_note.pubkey) {
+            return Err("output note is not owned by the spender".into());
+        }
+        probe!(end "consolidate/output_owner");
+        probe!(start "consolidate/output_range");
+        check_output_amount(self.output_note.amount)?;
+        probe!(end "consolidate/output_range");
+        probe!(start "consolidate/conservation");
+        let input_sum: u128 = self.input_notes.iter().map(|n| n.amount as u128).sum();
+        if input_sum != self.output_note.amount as u128 {
+            return Err("amounts don't balance".into());
+        }
+        probe!(end "consolidate/conservation");
+        Ok(ConsolidatePublicValues {
+            root: self.root,
+            nullifiers,
+            out_commitment: self.output_note.commitment(),
+            tree_levels,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl ConsolidatePublicValues {
+    pub const LEN: usize = 32 * (4 + CONSOLIDATE_INPUTS);
+
+    /// ABI-encode exactly as the consolidation guest commits it.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        let words = core::iter::once(&self.root).chain(&self.nullifiers).chain(core::iter::once(&self.out_commitment));
+        for (chunk, word) in out.chunks_mut(32).zip(words) {
+            chunk.copy_from_slice(word);
+        }
+        out[Self::LEN - 40..Self::LEN - 32].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[Self::LEN - 32..].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN || bytes[Self::LEN - 64..Self::LEN - 40].iter().any(|&b| b != 0) {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(ConsolidatePublicValues {
+            root: word(0),
+            nullifiers: core::array::from_fn(|i| word(1 + i)),
+            out_commitment: word(1 + CONSOLIDATE_INPUTS),
+            tree_levels: u64::from_be_bytes(bytes[Self::LEN - 40..Self::LEN - 32].try_into().unwrap()),
+            pool_binding: word(3 + CONSOLIDATE_INPUTS),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IncrementalMerkleTree;
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    /// A request merging notes of `amounts`, all in one tree.
+    fn merge(amounts: &[u64]) -> ConsolidatePrivateInputs {
+        let notes: Vec<Note> = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note { amount, pubkey: derive_pubkey(&KEY), blinding: [i as u8 + 1; 32] })
+            .collect();
+        let mut tree = IncrementalMerkleTree::new(8);
+        for n in &notes {
+            tree.insert(n.commitment());
+        }
+        let inputs = notes.into_iter().enumerate().map(|(i, n)| (n, tree.get_proof(i as u32))).collect();
+        ConsolidatePrivateInputs::new(tree.get_root(), inputs, KEY, [0xee; 32]).unwrap()
+    }
+
+    #[test]
+    fn test_consolidate_merges_dust() {
+        let request = merge(&[10_000, 25_000, 5_000]);
+        assert_eq!(request.output_note.amount, 40_000);
+        let pv = request.check().unwrap();
+        assert_eq!(pv.out_commitment, request.output_note.commitment());
+        for (i, n) in request.input_notes.iter().enumerate() {
+            assert_eq!(pv.nullifiers[i], compute_nullifier(&n.commitment(), &KEY));
+        }
+        assert!(pv.nullifiers[3..].iter().all(|n| n == &[0u8; 32]));
+        assert_eq!(pv.tree_levels, 8);
+        assert_eq!(ConsolidatePublicValues::LEN, 384);
+        assert_eq!(ConsolidatePublicValues::decode(&pv.encode()), Some(pv.clone()));
+        assert!(merge(&[1; CONSOLIDATE_INPUTS]).check().is_ok());
+    }
+
+    #[test]
+    fn test_consolidate_rejects_bad_merges() {
+        let shape = Err("consolidation merges 2 to 8 notes, each with a Merkle proof");
+        assert_eq!(merge(&[5]).check(), shape);
+        assert_eq!(merge(&[1; CONSOLIDATE_INPUTS + 1]).check(), shape);
+        let mut missing_proof = merge(&[5, 6]);
+        missing_proof.merkle_proofs.pop();
+        assert_eq!(missing_proof.check(), shape);
+
+        let base = merge(&[100, 250]);
+        let mut duplicate = base.clone();
+        duplicate.input_notes[1] = duplicate.input_notes[0].clone();
+        duplicate.merkle_proofs[1] = duplicate.merkle_proofs[0].clone();
+        duplicate.output_note.amount = 200;
+        assert_eq!(duplicate.check(), Err("duplicate input note"));
+
+        // Value cannot leave the key
+        let mut elsewhere = base.clone();
+        elsewhere.output_note.pubkey = derive_pubkey(&[8u8; 32]);
+        assert_eq!(elsewhere.check(), Err("output note is not owned by the spender"));
+
+        let mut inflated = base.clone();
+        inflated.output_note.amount += 1;
+        assert_eq!(inflated.check(), Err("amounts don't balance"));
+
+        let mut stale = base.clone();
+        stale.root = [0u8; 32];
+        assert_eq!(stale.check(), Err("Merkle proof invalid"));
+
+        let mut foreign = base.clone();
+        foreign.spending_key = [8u8; 32];
+        assert_eq!(foreign.check(), Err("spending key does not match note pubkey"));
+
+        let overflow = ConsolidatePrivateInputs::new(
+            [0u8; 32],
+            alloc::vec![(Note { amount: u64::MAX, pubkey: [0; 32], blinding: [0; 32] }, Vec::new()); 2],
+            KEY,
+            [0u8; 32],
+        );
+        assert_eq!(overflow.err(), Some("consolidated amount overflows"));
+    }
+}
@@ -0,0 +1,785 @@
+//! Branch map of the guests for coverage runs (feature `coverage`).
+//!
+//! The guests built with their own `coverage` feature wrap each check in an
+//! SP1 cycle-tracker region labelled `cov:<check>` and mark the arm a passing
+//! check took (`cov:<check>/<arm>`, e.g. which `SpendPath` authorized a
+//! spend). The regions come from the crate's `probe!` macro, placed in the
+//! circuits' `check`s that the guests run, and an arm is emitted right after
+//! the end of its check. The executor reports the
+//! instructions spent in each region, so an accepted execution shows which
+//! checks it passed, how, and at what cost. The default build compiles the
+//! probes out, so its ELF and vkey are unchanged.
+//!
+//! A rejected execution reports nothing but its panic message. Each
+//! rejection a check can raise is listed here with its message, so a
+//! rejected case is attributed to the branch that rejected it.
+//!
+//! Branch ids are `<check>/<arm>` for passing arms (`<check>/pass` for a
+//! check with a single way through) and `<check>/reject:<reason>`.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::cancellable::SpendPath;
+use crate::CircuitKind;
+
+/// Prefix of every cycle-tracker label the coverage guests emit.
+pub const PROBE_PREFIX: &str = "cov:";
+
+/// One check a guest performs.
+#[derive(Clone, Copy, Debug)]
+pub struct Check {
+    /// `circuit/check`, also the guest's cycle-tracker label
+    pub name: &'static str,
+    /// Arms the guest probes when the check passes; empty for a single way through
+    pub arms: &'static [&'static str],
+    /// Prepended by the guest to the messages in `rejections`
+    pub context: &'static str,
+    /// (reason, panic message) for every way the check rejects
+    pub rejections: &'static [(&'static str, &'static str)],
+}
+
+const SPEND_ARMS: &[&str] = &["owner", "claim", "reclaim"];
+
+/// Transfer and withdraw also spend multisig notes.
+const MULTISIG_SPEND_ARMS: &[&str] = &["owner", "claim", "reclaim", "multisig"];
+
+const AUTHORIZE_REJECTIONS: &[(&str, &str)] = &[
+    ("wrong_key", "spending key does not match note pubkey"),
+    ("lock_mismatch", "cancellable lock does not match note pubkey"),
+    ("no_deadline", "cancellable lock has no reclaim deadline"),
+    ("third_party", "spending key is neither the recipient nor the sender of the cancellable note"),
+];
+
+const MULTISIG_AUTHORIZE_REJECTIONS: &[(&str, &str)] = &[
+    ("wrong_key", "spending key does not match note pubkey"),
+    ("lock_mismatch", "cancellable lock does not match note pubkey"),
+    ("no_deadline", "cancellable lock has no reclaim deadline"),
+    ("third_party", "spending key is neither the recipient nor the sender of the cancellable note"),
+    ("multisig_mismatch", "multisig lock does not match note pubkey"),
+    ("missing_cosigner", "keys do not match the multisig owners"),
+    ("both_locks", "input note cannot be both cancellable and multisig"),
+];
+
+const ASSOCIATION_ARMS: &[&str] = &["none", "associated"];
+
+const ASSOCIATION_REJECTIONS: &[(&str, &str)] = &[
+    ("unapproved_deposit", "deposit is not in the association set"),
+    ("wrong_label", "note does not carry the claimed label"),
+    ("unrecorded_note", "note is not in the association set"),
+    ("unapproved_label", "label is not in the association set"),
+    ("no_input", "output label names no input"),
+    ("unlabelled_output", "output note does not inherit an input's label"),
+];
+
+/// The zero-amount rejection exists only while the circuits enforce it.
+const OUTPUT_RANGE_REJECTIONS: &[(&str, &str)] = if crate::REQUIRE_NONZERO_OUTPUTS {
+    &[("too_large", "output amount exceeds MAX_AMOUNT"), ("zero", "output amount is zero")]
+} else {
+    &[("too_large", "output amount exceeds MAX_AMOUNT")]
+};
+
+const SPEND_AUTH_REJECTIONS: &[(&str, &str)] = &[
+    ("missing", "missing spend authorization signature"),
+    ("invalid", "invalid spend authorization signature"),
+];
+
+const END_MARKER_REJECTIONS: &[(&str, &str)] = &[
+    ("missing", "unexpected data after private inputs (missing end marker)"),
+    ("trailing", "unexpected data after the end marker"),
+];
+
+const TRANSFER_CHECKS: &[Check] = &[
+    Check {
+        name: "transfer/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the transfer circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "transfer/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "transfer/input0/authorize",
+        arms: MULTISIG_SPEND_ARMS,
+        context: "input note 0: ",
+        rejections: MULTISIG_AUTHORIZE_REJECTIONS,
+    },
+    Check {
+        name: "transfer/input0/merkle",
+        arms: &["member", "zero_amount"],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid for input note 0")],
+    },
+    Check {
+        name: "transfer/input1/authorize",
+        arms: MULTISIG_SPEND_ARMS,
+        context: "input note 1: ",
+        rejections: MULTISIG_AUTHORIZE_REJECTIONS,
+    },
+    Check {
+        name: "transfer/input1/merkle",
+        arms: &["member", "zero_amount"],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid for input note 1")],
+    },
+    Check {
+        name: "transfer/distinct_inputs",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate input note")],
+    },
+    Check {
+        name: "transfer/proof_depth",
+        arms: &[],
+        context: "",
+        rejections: &[("mismatch", "Merkle proofs differ in depth")],
+    },
+    Check { name: "transfer/output_range", arms: &[], context: "", rejections: OUTPUT_RANGE_REJECTIONS },
+    Check {
+        name: "transfer/conservation",
+        arms: &[],
+        context: "",
+        rejections: &[("unbalanced", "amounts don't balance")],
+    },
+    Check { name: "transfer/association", arms: ASSOCIATION_ARMS, context: "", rejections: ASSOCIATION_REJECTIONS },
+    Check { name: "transfer/encryption", arms: &["proven", "none"], context: "", rejections: &[] },
+    Check { name: "transfer/spend_auth", arms: &[], context: "", rejections: SPEND_AUTH_REJECTIONS },
+];
+
+const WITHDRAW_CHECKS: &[Check] = &[
+    Check {
+        name: "withdraw/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the withdraw circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "withdraw/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "withdraw/authorize",
+        arms: MULTISIG_SPEND_ARMS,
+        context: "",
+        rejections: MULTISIG_AUTHORIZE_REJECTIONS,
+    },
+    Check {
+        name: "withdraw/merkle",
+        arms: &[],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid")],
+    },
+    Check {
+        name: "withdraw/relayer",
+        arms: &["no_fee", "fee"],
+        context: "",
+        rejections: &[("zero_address", "relayer fee paid to the zero address")],
+    },
+    Check {
+        name: "withdraw/change_owner",
+        arms: &[],
+        context: "",
+        rejections: &[("foreign_key", "change note is not owned by the spender")],
+    },
+    Check { name: "withdraw/change_range", arms: &[], context: "", rejections: OUTPUT_RANGE_REJECTIONS },
+    Check {
+        name: "withdraw/conservation",
+        arms: &["partial", "full"],
+        context: "",
+        rejections: &[
+            ("partial", "partial withdrawal amounts don't balance"),
+            ("full", "full withdrawal amount mismatch"),
+        ],
+    },
+    Check { name: "withdraw/association", arms: ASSOCIATION_ARMS, context: "", rejections: ASSOCIATION_REJECTIONS },
+    Check {
+        name: "withdraw/call",
+        arms: &["none", "call"],
+        context: "",
+        rejections: &[("no_target", "calldata hash without a call target")],
+    },
+    Check { name: "withdraw/spend_auth", arms: &[], context: "", rejections: SPEND_AUTH_REJECTIONS },
+];
+
+const CLAIM_CHECKS: &[Check] = &[
+    Check {
+        name: "claim/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the claim circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "claim/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "claim/ownership",
+        arms: &[],
+        context: "",
+        rejections: &[("wrong_key", "spending key does not match note pubkey")],
+    },
+    Check {
+        name: "claim/merkle",
+        arms: &[],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid")],
+    },
+    Check {
+        name: "claim/unspent",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("spent", "note was spent at the snapshot"),
+            ("wrong_root", "nullifier proof does not match the nullifier root"),
+        ],
+    },
+];
+
+const JOIN_SPLIT_CHECKS: &[Check] = &[
+    Check {
+        name: "join_split/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the join-split circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "join_split/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "join_split/shape",
+        arms: &[],
+        context: "",
+        rejections: &[("unpadded", "join-split needs exactly 4 inputs and 4 outputs (pad with dummies)")],
+    },
+    // Run once per input slot; the guest prefixes messages with "input note <i>: "
+    Check { name: "join_split/authorize", arms: SPEND_ARMS, context: "", rejections: AUTHORIZE_REJECTIONS },
+    Check {
+        name: "join_split/merkle",
+        arms: &["member", "zero_amount"],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid")],
+    },
+    Check {
+        name: "join_split/distinct_inputs",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate input note")],
+    },
+    Check {
+        name: "join_split/proof_depth",
+        arms: &[],
+        context: "",
+        rejections: &[("mismatch", "Merkle proofs differ in depth")],
+    },
+    Check { name: "join_split/output_range", arms: &[], context: "", rejections: OUTPUT_RANGE_REJECTIONS },
+    Check {
+        name: "join_split/conservation",
+        arms: &[],
+        context: "",
+        rejections: &[("unbalanced", "amounts don't balance")],
+    },
+];
+
+const DEPOSIT_CHECKS: &[Check] = &[
+    Check {
+        name: "deposit/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the deposit circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "deposit/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check { name: "deposit/amount", arms: &[], context: "", rejections: &[("zero", "deposit amount is zero")] },
+];
+
+const BATCH_WITHDRAW_CHECKS: &[Check] = &[
+    Check {
+        name: "batch_withdraw/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the batch-withdraw circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "batch_withdraw/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "batch_withdraw/shape",
+        arms: &[],
+        context: "",
+        rejections: &[("empty_or_over", "batch withdraw spends 1 to 8 notes")],
+    },
+    // Run once per input; the guest prefixes messages with "input note <i>: "
+    Check { name: "batch_withdraw/authorize", arms: SPEND_ARMS, context: "", rejections: AUTHORIZE_REJECTIONS },
+    Check {
+        name: "batch_withdraw/merkle",
+        arms: &["member", "zero_amount"],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid")],
+    },
+    Check {
+        name: "batch_withdraw/distinct_inputs",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate input note")],
+    },
+    Check {
+        name: "batch_withdraw/proof_depth",
+        arms: &[],
+        context: "",
+        rejections: &[("mismatch", "Merkle proofs differ in depth")],
+    },
+    Check {
+        name: "batch_withdraw/amount",
+        arms: &[],
+        context: "",
+        rejections: &[("overflow", "batch amount overflows")],
+    },
+];
+
+const CONSOLIDATE_CHECKS: &[Check] = &[
+    Check {
+        name: "consolidate/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the consolidation circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "consolidate/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "consolidate/shape",
+        arms: &[],
+        context: "",
+        rejections: &[("count", "consolidation merges 2 to 8 notes, each with a Merkle proof")],
+    },
+    // Run once per input; the guest prefixes messages with "input note <i>: "
+    Check {
+        name: "consolidate/ownership",
+        arms: &[],
+        context: "",
+        rejections: &[("wrong_key", "spending key does not match note pubkey")],
+    },
+    Check { name: "consolidate/merkle", arms: &[], context: "", rejections: &[("invalid", "Merkle proof invalid")] },
+    Check {
+        name: "consolidate/distinct_inputs",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate input note")],
+    },
+    Check {
+        name: "consolidate/proof_depth",
+        arms: &[],
+        context: "",
+        rejections: &[("mismatch", "Merkle proofs differ in depth")],
+    },
+    Check {
+        name: "consolidate/output_owner",
+        arms: &[],
+        context: "",
+        rejections: &[("foreign_key", "output note is not owned by the spender")],
+    },
+    Check { name: "consolidate/output_range", arms: &[], context: "", rejections: OUTPUT_RANGE_REJECTIONS },
+    Check {
+        name: "consolidate/conservation",
+        arms: &[],
+        context: "",
+        rejections: &[("unbalanced", "amounts don't balance")],
+    },
+];
+
+const ATTEST_CHECKS: &[Check] = &[
+    Check {
+        name: "attest/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the attest circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "attest/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "attest/shape",
+        arms: &[],
+        context: "",
+        rejections: &[("empty_or_over", "attestation counts 1 to 8 notes")],
+    },
+    // Run once per note; the guest prefixes messages with "note <i>: "
+    Check {
+        name: "attest/ownership",
+        arms: &[],
+        context: "",
+        rejections: &[("wrong_key", "spending key does not match note pubkey")],
+    },
+    Check { name: "attest/merkle", arms: &[], context: "", rejections: &[("invalid", "Merkle proof invalid")] },
+    Check {
+        name: "attest/unspent",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("spent", "note was spent at the snapshot"),
+            ("wrong_root", "nullifier proof does not match the nullifier root"),
+        ],
+    },
+    Check {
+        name: "attest/distinct_inputs",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate input note")],
+    },
+    Check {
+        name: "attest/proof_depth",
+        arms: &[],
+        context: "",
+        rejections: &[("mismatch", "Merkle proofs differ in depth")],
+    },
+    Check {
+        name: "attest/threshold",
+        arms: &[],
+        context: "",
+        rejections: &[("short", "notes hold less than the threshold")],
+    },
+];
+
+const DISCLOSE_CHECKS: &[Check] = &[
+    Check {
+        name: "disclose/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the disclose circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "disclose/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "disclose/shape",
+        arms: &[],
+        context: "",
+        rejections: &[("empty_or_over", "disclosure opens 1 to 8 payloads")],
+    },
+    // Run once per payload; the guest prefixes messages with "payload <i>: "
+    Check {
+        name: "disclose/open",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("foreign_key", "payload does not decrypt under the viewing key"),
+            ("not_a_note", "payload does not hold a note"),
+        ],
+    },
+    Check {
+        name: "disclose/distinct_payloads",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate payload")],
+    },
+];
+
+const POLICY_SPEND_CHECKS: &[Check] = &[
+    Check {
+        name: "policy_spend/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the policy-spend circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "policy_spend/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "policy_spend/authorize",
+        arms: &[],
+        context: "",
+        rejections: &[("wrong_policy", "spending key and policy do not match note pubkey")],
+    },
+    Check { name: "policy_spend/merkle", arms: &[], context: "", rejections: &[("invalid", "Merkle proof invalid")] },
+    Check { name: "policy_spend/output_range", arms: &[], context: "", rejections: OUTPUT_RANGE_REJECTIONS },
+    Check {
+        name: "policy_spend/conservation",
+        arms: &[],
+        context: "",
+        rejections: &[("unbalanced", "amounts don't balance")],
+    },
+    Check {
+        name: "policy_spend/change_owner",
+        arms: &[],
+        context: "",
+        rejections: &[("escapes", "change note leaves the policy")],
+    },
+    Check {
+        name: "policy_spend/counter",
+        arms: &["first_of_epoch", "counter"],
+        context: "",
+        rejections: &[
+            ("other_policy", "counter is for another policy"),
+            ("other_epoch", "counter is from another epoch"),
+            ("invalid", "counter Merkle proof invalid"),
+        ],
+    },
+    Check {
+        name: "policy_spend/limit",
+        arms: &[],
+        context: "",
+        rejections: &[("over", "spend exceeds the policy's epoch limit")],
+    },
+];
+
+const PAYOUT_CHECKS: &[Check] = &[
+    Check {
+        name: "payout/header",
+        arms: &[],
+        context: "",
+        rejections: &[
+            ("wrong_circuit", "input header is not for the payout circuit"),
+            ("wrong_version", "input header version does not match this guest"),
+        ],
+    },
+    Check { name: "payout/end_marker", arms: &[], context: "", rejections: END_MARKER_REJECTIONS },
+    Check {
+        name: "payout/shape",
+        arms: &[],
+        context: "",
+        rejections: &[("unpadded", "payout needs exactly 2 inputs and 8 outputs (pad with dummies)")],
+    },
+    // Run once per input slot; the guest prefixes messages with "input note <i>: "
+    Check { name: "payout/authorize", arms: SPEND_ARMS, context: "", rejections: AUTHORIZE_REJECTIONS },
+    Check {
+        name: "payout/merkle",
+        arms: &["member", "zero_amount"],
+        context: "",
+        rejections: &[("invalid", "Merkle proof invalid")],
+    },
+    Check {
+        name: "payout/distinct_inputs",
+        arms: &[],
+        context: "",
+        rejections: &[("duplicate", "duplicate input note")],
+    },
+    Check {
+        name: "payout/proof_depth",
+        arms: &[],
+        context: "",
+        rejections: &[("mismatch", "Merkle proofs differ in depth")],
+    },
+    Check { name: "payout/output_range", arms: &[], context: "", rejections: OUTPUT_RANGE_REJECTIONS },
+    Check {
+        name: "payout/distinct_recipients",
+        arms: &[],
+        context: "",
+        rejections: &[("repeated", "payout recipients must be distinct")],
+    },
+    Check {
+        name: "payout/conservation",
+        arms: &[],
+        context: "",
+        rejections: &[("unbalanced", "amounts don't balance")],
+    },
+];
+
+/// The checks of a guest, in the order it runs them.
+pub fn checks(kind: CircuitKind) -> &'static [Check] {
+    match kind {
+        CircuitKind::Transfer => TRANSFER_CHECKS,
+        CircuitKind::Withdraw => WITHDRAW_CHECKS,
+        CircuitKind::Claim => CLAIM_CHECKS,
+        CircuitKind::JoinSplit => JOIN_SPLIT_CHECKS,
+        CircuitKind::Deposit => DEPOSIT_CHECKS,
+        CircuitKind::BatchWithdraw => BATCH_WITHDRAW_CHECKS,
+        CircuitKind::Consolidate => CONSOLIDATE_CHECKS,
+        CircuitKind::Attest => ATTEST_CHECKS,
+        CircuitKind::Disclose => DISCLOSE_CHECKS,
+        CircuitKind::PolicySpend => POLICY_SPEND_CHECKS,
+        CircuitKind::Payout => PAYOUT_CHECKS,
+    }
+}
+
+impl Check {
+    /// Ids of the branches that pass this check.
+    pub fn pass_branches(&self) -> Vec<String> {
+        if self.arms.is_empty() {
+            return alloc::vec![format!("{}/pass", self.name)];
+        }
+        self.arms.iter().map(|arm| format!("{}/{arm}", self.name)).collect()
+    }
+
+    /// Ids of the branches that reject at this check.
+    pub fn reject_branches(&self) -> Vec<String> {
+        self.rejections.iter().map(|(reason, _)| format!("{}/reject:{reason}", self.name)).collect()
+    }
+}
+
+/// Every branch id of a guest.
+pub fn branches(kind: CircuitKind) -> Vec<String> {
+    checks(kind).iter().flat_map(|check| check.pass_branches().into_iter().chain(check.reject_branches())).collect()
+}
+
+/// Passing branches an accepted execution took, from the labels of its
+/// cycle-tracker report.
+pub fn pass_branches<'a>(kind: CircuitKind, labels: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let labels: Vec<&str> = labels.into_iter().filter_map(|l| l.strip_prefix(PROBE_PREFIX)).collect();
+    let mut taken = Vec::new();
+    for check in checks(kind) {
+        if check.arms.is_empty() {
+            if labels.contains(&check.name) {
+                taken.push(format!("{}/pass", check.name));
+            }
+        } else {
+            for arm in check.arms {
+                let branch = format!("{}/{arm}", check.name);
+                if labels.contains(&branch.as_str()) {
+                    taken.push(branch);
+                }
+            }
+        }
+    }
+    taken
+}
+
+/// The branch that rejected an execution, from the error it failed with.
+pub fn rejection_branch(kind: CircuitKind, error: &str) -> Option<String> {
+    checks(kind).iter().find_map(|check| {
+        check
+            .rejections
+            .iter()
+            .find(|(_, message)| error.contains(&format!("{}{message}", check.context)))
+            .map(|(reason, _)| format!("{}/reject:{reason}", check.name))
+    })
+}
+
+/// Arm label of an authorized spend.
+pub fn spend_arm(path: SpendPath) -> &'static str {
+    match path {
+        SpendPath::Owner => "owner",
+        SpendPath::Claim => "claim",
+        SpendPath::Reclaim => "reclaim",
+        SpendPath::Multisig => "multisig",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::abort::AbortCode;
+    use crate::cancellable::{authorize_spend, CancellableLock};
+    use crate::{
+        check_spend_signatures, derive_pubkey, validate_end_marker, validate_input_consumed, InputHeader, Note,
+        SpenderKey,
+    };
+
+    #[test]
+    fn test_branches_are_unique_and_complete() {
+        for kind in [
+            CircuitKind::Transfer,
+            CircuitKind::Withdraw,
+            CircuitKind::Claim,
+            CircuitKind::JoinSplit,
+            CircuitKind::Deposit,
+            CircuitKind::BatchWithdraw,
+            CircuitKind::Consolidate,
+            CircuitKind::Attest,
+            CircuitKind::Disclose,
+            CircuitKind::PolicySpend,
+            CircuitKind::Payout,
+        ] {
+            let all = branches(kind);
+            let mut sorted = all.clone();
+            sorted.sort();
+            sorted.dedup();
+            assert_eq!(sorted.len(), all.len());
+            // A reworded message must not fall back to the unclassified abort code
+            for check in checks(kind) {
+                for (reason, message) in check.rejections {
+                    let code = AbortCode::classify(message);
+                    assert_ne!(code, AbortCode::Unclassified, "{}/reject:{reason}", check.name);
+                }
+            }
+        }
+        assert_eq!(branches(CircuitKind::Transfer).len(), 3 + 3 + 2 * (11 + 3) + 2 + 2 + 2 + 2 + (2 + 6) + 2 + 3);
+        assert_eq!(
+            pass_branches(
+                CircuitKind::Withdraw,
+                ["cov:withdraw/header", "cov:withdraw/authorize", "cov:withdraw/authorize/claim", "other"]
+            ),
+            ["withdraw/header/pass", "withdraw/authorize/claim"]
+        );
+    }
+
+    #[test]
+    fn test_rejections_match_guest_messages() {
+        // The messages the lib returns, wrapped the way the guests panic with them
+        let header = InputHeader::new(CircuitKind::Withdraw).validate(CircuitKind::Transfer).unwrap_err();
+        assert_eq!(
+            rejection_branch(CircuitKind::Transfer, &format!("panicked at src/main.rs: {header}")).as_deref(),
+            Some("transfer/header/reject:wrong_circuit")
+        );
+        let marker = validate_end_marker(&[0u8; 32]).unwrap_err();
+        assert_eq!(
+            rejection_branch(CircuitKind::Withdraw, marker).as_deref(),
+            Some("withdraw/end_marker/reject:missing")
+        );
+        let trailing = validate_input_consumed(0).unwrap_err();
+        assert_eq!(
+            rejection_branch(CircuitKind::Deposit, trailing).as_deref(),
+            Some("deposit/end_marker/reject:trailing")
+        );
+
+        let key = [0xA1; 32];
+        let note = Note { amount: 1, pubkey: derive_pubkey(&key), blinding: [7; 32] };
+        let wrong = authorize_spend(&note, &[0xB2; 32], None).unwrap_err();
+        assert_eq!(
+            rejection_branch(CircuitKind::Transfer, &format!("input note 1: {wrong}")).as_deref(),
+            Some("transfer/input1/authorize/reject:wrong_key")
+        );
+        let lock = CancellableLock {
+            recipient_pubkey: derive_pubkey(&key),
+            sender_pubkey: derive_pubkey(&[0xC3; 32]),
+            reclaim_deadline: 0,
+        };
+        let locked = lock.note(1, [7; 32]);
+        let errors = [
+            authorize_spend(&note, &key, Some(&lock)).unwrap_err(),
+            authorize_spend(&locked, &[0xC3; 32], Some(&lock)).unwrap_err(),
+            authorize_spend(&locked, &[0xB2; 32], Some(&lock)).unwrap_err(),
+        ];
+        for (error, reason) in errors.iter().zip(["lock_mismatch", "no_deadline", "third_party"]) {
+            assert_eq!(
+                rejection_branch(CircuitKind::Withdraw, error),
+                Some(format!("withdraw/authorize/reject:{reason}"))
+            );
+        }
+        let cosigned = crate::multisig::MultisigSpend {
+            lock: crate::multisig::MultisigLock { owner_pubkeys: [derive_pubkey(&key), derive_pubkey(&[0xC3; 32])] },
+            cosigner: SpenderKey::derive(&[0xB2; 32]),
+        };
+        let note = cosigned.lock.note(1, [7; 32]);
+        let missing = crate::multisig::authorize_multisig_spend(&note, &SpenderKey::derive(&key), &cosigned);
+        assert_eq!(
+            rejection_branch(CircuitKind::Transfer, &format!("input note 0: {}", missing.unwrap_err())).as_deref(),
+            Some("transfer/input0/authorize/reject:missing_cosigner")
+        );
+        let unsigned = check_spend_signatures([[0xA1; 32]], &[], &[0; 32]).unwrap_err();
+        assert_eq!(
+            rejection_branch(CircuitKind::Withdraw, unsigned).as_deref(),
+            Some("withdraw/spend_auth/reject:missing")
+        );
+        assert_eq!(rejection_branch(CircuitKind::Withdraw, "out of gas"), None);
+        assert_eq!(
+            rejection_branch(CircuitKind::Claim, "note was spent at the snapshot").as_deref(),
+            Some("claim/unspent/reject:spent")
+        );
+        let merkle = format!("{}", crate::CheckError::at("input note", 2, "Merkle proof invalid"));
+        assert_eq!(
+            rejection_branch(CircuitKind::JoinSplit, &merkle).as_deref(),
+            Some("join_split/merkle/reject:invalid")
+        );
+    }
+}
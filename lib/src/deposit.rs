@@ -0,0 +1,104 @@
+//! Shielded deposits: proving a deposit's commitment opens to its amount.
+//!
+//! A plain deposit inserts whatever commitment the depositor passes next to
+//! the public amount. The pool cannot tell whether the commitment opens to
+//! that amount, so a depositor could pay 1 USDT for a note worth 1000 and
+//! spend the difference out of everyone else's deposits. The deposit circuit
+//! proves
+//!
+//!   commitment == keccak256(amount || pubkey || blinding)
+//!
+//! for the public amount, and reveals nothing else about the note: the
+//! pubkey and blinding stay private, and the note need not belong to the
+//! depositor. A pool deployed with a deposit vkey only takes deposits that
+//! carry such a proof.
+//!
+//! Public values committed (64 bytes = 2 × 32-byte slots):
+//!   [commitment, amount (uint256 BE)]
+//! for the pool to read with
+//!   abi.decode(publicValues, (bytes32, uint256))
+
+use serde::{Deserialize, Serialize};
+
+use crate::{probe, Note};
+
+/// Private inputs for the deposit circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DepositPrivateInputs {
+    /// The note the deposit creates
+    pub note: Note,
+}
+
+/// Public values committed by the deposit circuit (64 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DepositPublicValues {
+    pub commitment: [u8; 32],
+    pub amount: u64,
+}
+
+impl DepositPublicValues {
+    pub const LEN: usize = 64;
+
+    /// ABI-encode exactly as the deposit guest commits it.
+    pub fn encode(&self) -> [u8; 64] {
+        let mut out = [0u8; 64];
+        out[0..32].copy_from_slice(&self.commitment);
+        out[56..64].copy_from_slice(&self.amount.to_be_bytes());
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the amount does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN || bytes[32..56].iter().any(|&b| b != 0) {
+            return None;
+        }
+        Some(DepositPublicValues {
+            commitment: bytes[0..32].try_into().unwrap(),
+            amount: u64::from_be_bytes(bytes[56..64].try_into().unwrap()),
+        })
+    }
+}
+
+impl DepositPrivateInputs {
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks.
+    pub fn check(&self) -> Result<DepositPublicValues, &'static str> {
+        // The pool refuses empty deposits; a proof of one would never be used
+        probe!(start "deposit/amount");
+        if self.note.amount == 0 {
+            return Err("deposit amount is zero");
+        }
+        probe!(end "deposit/amount");
+        Ok(DepositPublicValues { commitment: self.note.commitment(), amount: self.note.amount })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derive_pubkey;
+
+    #[test]
+    fn test_deposit_public_values() {
+        let note = Note { amount: 1_500_000, pubkey: derive_pubkey(&[1u8; 32]), blinding: [2u8; 32] };
+        let inputs = DepositPrivateInputs { note: note.clone() };
+        let pv = inputs.check().unwrap();
+        assert_eq!(pv, DepositPublicValues { commitment: note.commitment(), amount: 1_500_000 });
+        assert_eq!(DepositPublicValues::decode(&pv.encode()), Some(pv.clone()));
+
+        // Another amount commits to another note
+        let mut more = inputs.clone();
+        more.note.amount = 1_000_000_000;
+        assert_ne!(more.check().unwrap().commitment, pv.commitment);
+
+        let mut empty = inputs.clone();
+        empty.note.amount = 0;
+        assert_eq!(empty.check(), Err("deposit amount is zero"));
+
+        let mut bytes = pv.encode();
+        bytes[40] = 1;
+        assert_eq!(DepositPublicValues::decode(&bytes), None);
+        assert_eq!(DepositPublicValues::decode(&bytes[..63]), None);
+    }
+}
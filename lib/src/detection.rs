@@ -0,0 +1,277 @@
+//! Detection tags and per-epoch bloom filters for light-wallet scanning.
+//!
+//! Trial-decrypting every `EncryptedNote` is linear in the size of the pool.
+//! A sender can instead tag the ciphertext so the recipient finds it cheaply:
+//!
+//!   tag = keccak256(TAG_DOMAIN || viewing_pubkey || epoch_be8 || slot)[..8]
+//!
+//! where `epoch = unix_time / EPOCH_SECONDS` when the note is sent and `slot`
+//! is picked at random from `0..TAG_SLOTS`, so two notes to the same
+//! recipient in one epoch usually carry different tags. The tag is the first
+//! `TAG_LEN` bytes of the NaCl nonce (`ephemeral_pubkey(32) || nonce(24) ||
+//! ciphertext`); the rest of the nonce stays random, so untagged decryptors
+//! are unaffected.
+//!
+//! An indexer groups tags by the epoch of the block that emitted them and
+//! publishes one `EpochFilter` per epoch. A light wallet downloads the
+//! filters, checks its candidate tags against each (`EpochFilter::matches`)
+//! and fetches only ciphertexts whose nonce starts with a matching tag. A
+//! note sent just before an epoch boundary can land in the next epoch, so
+//! candidates cover the filter's epoch and the one before it.
+//!
+//! Tags trade some privacy for speed: anyone who knows a viewing pubkey can
+//! recognise notes tagged for it. Senders already know it, so tagging is
+//! opt-in and only worth it for wallets that cannot afford a full scan.
+//! Untagged notes are not found through filters.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::keccak256;
+
+/// Domain tag for detection tags (exactly 32 bytes).
+pub const TAG_DOMAIN: [u8; 32] = *b"shielded-pool/detection-tag/v1\0\0";
+
+/// Length of a detection tag (prefix of the NaCl nonce).
+pub const TAG_LEN: usize = 8;
+
+/// Tags a recipient can receive per epoch.
+pub const TAG_SLOTS: u8 = 4;
+
+/// Epoch length in seconds (one day).
+pub const EPOCH_SECONDS: u64 = 86_400;
+
+/// Offset of the nonce in an encrypted note.
+const NONCE_OFFSET: usize = 32;
+
+/// Serialized filter format version.
+pub const FILTER_VERSION: u8 = 1;
+
+const FILTER_MAGIC: [u8; 4] = *b"SPBF";
+const FILTER_HEADER_LEN: usize = 4 + 1 + 8 + 1 + 4 + 4;
+
+pub type DetectionTag = [u8; TAG_LEN];
+
+/// Epoch containing `unix_time`.
+pub fn epoch_of(unix_time: u64) -> u64 {
+    unix_time / EPOCH_SECONDS
+}
+
+/// Detection tag for `viewing_pubkey` in `epoch` at `slot` (< TAG_SLOTS).
+pub fn detection_tag(viewing_pubkey: &[u8; 32], epoch: u64, slot: u8) -> DetectionTag {
+    assert!(slot < TAG_SLOTS, "detection tag slot out of range");
+    let mut preimage = [0u8; 73];
+    preimage[0..32].copy_from_slice(&TAG_DOMAIN);
+    preimage[32..64].copy_from_slice(viewing_pubkey);
+    preimage[64..72].copy_from_slice(&epoch.to_be_bytes());
+    preimage[72] = slot;
+    keccak256(&preimage)[..TAG_LEN].try_into().unwrap()
+}
+
+/// Tags a wallet looks for in the filter for `epoch`: every slot of `epoch`
+/// and of the epoch before it.
+pub fn candidate_tags(viewing_pubkey: &[u8; 32], epoch: u64) -> Vec<DetectionTag> {
+    let mut epochs = vec![epoch];
+    if epoch > 0 {
+        epochs.push(epoch - 1);
+    }
+    epochs
+        .into_iter()
+        .flat_map(|e| (0..TAG_SLOTS).map(move |slot| detection_tag(viewing_pubkey, e, slot)))
+        .collect()
+}
+
+/// The tag position of an encrypted note (its nonce prefix), if long enough.
+///
+/// Every note yields a value; for untagged notes it is random bytes.
+pub fn tag_of_encrypted_note(encrypted: &[u8]) -> Option<DetectionTag> {
+    encrypted.get(NONCE_OFFSET..NONCE_OFFSET + TAG_LEN).map(|t| t.try_into().unwrap())
+}
+
+/// Bloom filter over the detection tags seen in one epoch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochFilter {
+    pub epoch: u64,
+    /// Number of bit probes per tag
+    pub hashes: u8,
+    /// Tags inserted
+    pub items: u32,
+    bits: Vec<u8>,
+    num_bits: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    UnsupportedVersion(u8),
+    Malformed(&'static str),
+}
+
+impl core::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FilterError::UnsupportedVersion(v) => write!(f, "unsupported filter version {v}"),
+            FilterError::Malformed(what) => write!(f, "malformed filter: {what}"),
+        }
+    }
+}
+
+impl EpochFilter {
+    /// Empty filter sized for `expected_items` tags at roughly
+    /// `1 / fp_inverse` false positives per lookup.
+    pub fn with_capacity(epoch: u64, expected_items: u32, fp_inverse: u32) -> Self {
+        // Optimal bloom: k = log2(1/p) probes and m/n = k / ln 2 ≈ 1.44 k bits per item
+        let k = fp_inverse.max(2).ilog2() + 1;
+        let num_bits = (expected_items.max(1) as u64 * k as u64 * 144 / 100)
+            .clamp(64, (u32::MAX - 7) as u64)
+            .next_multiple_of(8) as u32;
+        EpochFilter {
+            epoch,
+            hashes: k.min(32) as u8,
+            items: 0,
+            bits: vec![0u8; (num_bits / 8) as usize],
+            num_bits,
+        }
+    }
+
+    /// Filter over `tags`, sized for them.
+    pub fn build(epoch: u64, tags: &[DetectionTag], fp_inverse: u32) -> Self {
+        let mut filter = Self::with_capacity(epoch, tags.len() as u32, fp_inverse);
+        for tag in tags {
+            filter.insert(tag);
+        }
+        filter
+    }
+
+    pub fn insert(&mut self, tag: &DetectionTag) {
+        for bit in probes(self.hashes, self.num_bits, tag) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+        self.items = self.items.saturating_add(1);
+    }
+
+    /// Whether `tag` may have been inserted (no false negatives).
+    pub fn contains(&self, tag: &DetectionTag) -> bool {
+        probes(self.hashes, self.num_bits, tag).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Candidate tags of `viewing_pubkey` that hit this filter. Fetch the
+    /// ciphertexts with these nonce prefixes and trial-decrypt them.
+    pub fn matches(&self, viewing_pubkey: &[u8; 32]) -> Vec<DetectionTag> {
+        candidate_tags(viewing_pubkey, self.epoch)
+            .into_iter()
+            .filter(|tag| self.contains(tag))
+            .collect()
+    }
+
+    /// Compact encoding:
+    ///   magic "SPBF" | version u8 | epoch u64 | hashes u8 | items u32 | num_bits u32 | bits
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(FILTER_HEADER_LEN + self.bits.len());
+        out.extend_from_slice(&FILTER_MAGIC);
+        out.push(FILTER_VERSION);
+        out.extend_from_slice(&self.epoch.to_be_bytes());
+        out.push(self.hashes);
+        out.extend_from_slice(&self.items.to_be_bytes());
+        out.extend_from_slice(&self.num_bits.to_be_bytes());
+        out.extend_from_slice(&self.bits);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FilterError> {
+        if bytes.len() < FILTER_HEADER_LEN || bytes[0..4] != FILTER_MAGIC {
+            return Err(FilterError::Malformed("bad header"));
+        }
+        if bytes[4] != FILTER_VERSION {
+            return Err(FilterError::UnsupportedVersion(bytes[4]));
+        }
+        let epoch = u64::from_be_bytes(bytes[5..13].try_into().unwrap());
+        let hashes = bytes[13];
+        let items = u32::from_be_bytes(bytes[14..18].try_into().unwrap());
+        let num_bits = u32::from_be_bytes(bytes[18..22].try_into().unwrap());
+        let bits = &bytes[FILTER_HEADER_LEN..];
+        if hashes == 0 || num_bits == 0 || !num_bits.is_multiple_of(8) || bits.len() != (num_bits / 8) as usize {
+            return Err(FilterError::Malformed("inconsistent size"));
+        }
+        Ok(EpochFilter { epoch, hashes, items, bits: bits.to_vec(), num_bits })
+    }
+
+}
+
+/// Bit positions for `tag` by double hashing. Tags are keccak output, so
+/// their two halves already serve as independent hashes.
+fn probes(hashes: u8, num_bits: u32, tag: &DetectionTag) -> impl Iterator<Item = usize> {
+    let h1 = u32::from_be_bytes(tag[0..4].try_into().unwrap()) as u64;
+    let h2 = u32::from_be_bytes(tag[4..8].try_into().unwrap()) as u64 | 1;
+    let m = num_bits as u64;
+    (0..hashes as u64).map(move |i| ((h1 + i * h2) % m) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: [u8; 32] = [0xA1; 32];
+    const BOB: [u8; 32] = [0xB0; 32];
+
+    fn noise(n: u32) -> Vec<DetectionTag> {
+        (0..n).map(|i| keccak256(&i.to_be_bytes())[..TAG_LEN].try_into().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_tags_depend_on_key_epoch_and_slot() {
+        let t = detection_tag(&ALICE, 100, 0);
+        assert_ne!(t, detection_tag(&BOB, 100, 0));
+        assert_ne!(t, detection_tag(&ALICE, 101, 0));
+        assert_ne!(t, detection_tag(&ALICE, 100, 1));
+        assert_eq!(candidate_tags(&ALICE, 100).len(), 2 * TAG_SLOTS as usize);
+        assert_eq!(candidate_tags(&ALICE, 0).len(), TAG_SLOTS as usize);
+        assert_eq!(epoch_of(EPOCH_SECONDS * 7 + 5), 7);
+    }
+
+    #[test]
+    fn test_filter_finds_tagged_notes_across_epoch_boundary() {
+        let mut tags = noise(1000);
+        // Sent late in epoch 41, mined in epoch 42
+        tags.push(detection_tag(&ALICE, 41, 3));
+        tags.push(detection_tag(&ALICE, 42, 0));
+        let filter = EpochFilter::build(42, &tags, 1000);
+
+        let hits = filter.matches(&ALICE);
+        assert!(hits.contains(&detection_tag(&ALICE, 41, 3)));
+        assert!(hits.contains(&detection_tag(&ALICE, 42, 0)));
+        // Bob has nothing here; with 8 candidates at ~1/1000 a hit is unlikely
+        assert!(filter.matches(&BOB).is_empty());
+    }
+
+    #[test]
+    fn test_false_positive_rate_near_target() {
+        let filter = EpochFilter::build(1, &noise(2000), 100);
+        let hits = (0..20_000u32)
+            .map(|i| keccak256(&(u32::MAX - i).to_be_bytes())[..TAG_LEN].try_into().unwrap())
+            .filter(|t: &DetectionTag| filter.contains(t))
+            .count();
+        // Target 1%; allow generous slack
+        assert!(hits < 500, "false positives: {hits} / 20000");
+    }
+
+    #[test]
+    fn test_filter_roundtrip_and_rejects_malformed() {
+        let filter = EpochFilter::build(9, &noise(50), 1000);
+        let bytes = filter.to_bytes();
+        assert_eq!(EpochFilter::from_bytes(&bytes).unwrap(), filter);
+
+        assert!(EpochFilter::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        assert_eq!(EpochFilter::from_bytes(&wrong_version), Err(FilterError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn test_tag_is_nonce_prefix() {
+        let mut encrypted = vec![0u8; 32 + 24 + 16];
+        let tag = detection_tag(&ALICE, 5, 2);
+        encrypted[32..40].copy_from_slice(&tag);
+        assert_eq!(tag_of_encrypted_note(&encrypted), Some(tag));
+        assert_eq!(tag_of_encrypted_note(&encrypted[..39]), None);
+    }
+}
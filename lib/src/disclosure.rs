@@ -0,0 +1,271 @@
+//! Selective disclosure: show an auditor chosen notes under a viewing key.
+//!
+//! Every note the pool creates is posted as a payload encrypted to its
+//! recipient's viewing key (`EncryptedNote(commitment, encryptedData)`, and
+//! `getEncryptedNote(leafIndex)`). Handing an auditor the viewing secret
+//! would open every note the key ever received. The disclose circuit opens
+//! only the payloads the holder picks: for 1 to `DISCLOSE_NOTES` of them it
+//! proves that
+//!
+//!   - each payload decrypts under the viewing secret whose public key is
+//!     committed,
+//!   - the plaintext is a note, whose commitment, amount and recipient
+//!     pubkey are committed next to keccak256 of the payload,
+//!   - no payload is disclosed twice,
+//!
+//! and binds all of it to the auditor's `audit_id`. The viewing secret and
+//! the notes' blindings stay private, and notes that are not picked are not
+//! touched at all.
+//!
+//! The auditor matches each commitment and payload hash to the
+//! `EncryptedNote` event (or `getEncryptedNote`) of the transaction it is
+//! asking about, and the viewing pubkey to the one the holder registered
+//! with it. The proof shows what the holder's viewing key received; it says
+//! nothing about notes sent to other keys.
+//!
+//! The proof also commits the binding of the pool the payloads were posted
+//! to (see `crate::pool_binding`), which the auditor checks against the pool
+//! it asks about.
+//!
+//! Public values committed (1152 bytes = 36 × 32-byte slots):
+//!   [viewingPubkey, auditId, count (uint256 BE), commitments[8],
+//!    payloadHashes[8], amounts[8] (uint256 BE), recipients[8], poolBinding]
+//! with zero in unused slots, for a contract to read with
+//!   abi.decode(publicValues,
+//!     (bytes32, bytes32, uint256, bytes32[8], bytes32[8], uint256[8], bytes32[8], bytes32))
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{keccak256, probe, CheckError};
+use crate::note_encryption::{open_payload, parse_note_plaintext, viewing_pubkey};
+
+/// Most notes one disclosure can open.
+pub const DISCLOSE_NOTES: usize = 8;
+
+/// Private inputs for the disclose circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DisclosurePrivateInputs {
+    /// x25519 viewing secret the payloads were encrypted to
+    pub viewing_secret: [u8; 32],
+    /// 1 to `DISCLOSE_NOTES` payloads as posted on chain
+    pub payloads: Vec<Vec<u8>>,
+    /// Identifies the audit request the disclosure answers
+    pub audit_id: [u8; 32],
+    /// The pool the payloads were posted to
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// One disclosed note.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisclosedNote {
+    pub commitment: [u8; 32],
+    /// keccak256 of the posted payload
+    pub payload_hash: [u8; 32],
+    pub amount: u64,
+    /// The note's pubkey: its owner's shielded pubkey (or a lock hash)
+    pub recipient: [u8; 32],
+}
+
+/// Public values committed by the disclose circuit (1152 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisclosurePublicValues {
+    pub viewing_pubkey: [u8; 32],
+    pub audit_id: [u8; 32],
+    /// In payload order
+    pub notes: Vec<DisclosedNote>,
+    /// The pool the payloads were posted to
+    pub pool_binding: [u8; 32],
+}
+
+/// Open one payload under `viewing_secret`.
+pub fn disclose_payload(payload: &[u8], viewing_secret: &[u8; 32]) -> Result<DisclosedNote, &'static str> {
+    let plaintext = open_payload(payload, viewing_secret).ok_or("payload does not decrypt under the viewing key")?;
+    let note = parse_note_plaintext(&plaintext).ok_or("payload does not hold a note")?;
+    Ok(DisclosedNote {
+        commitment: note.commitment(),
+        payload_hash: keccak256(payload),
+        amount: note.amount,
+        recipient: note.pubkey,
+    })
+}
+
+impl DisclosurePrivateInputs {
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks
+    /// (`guest_check`).
+    pub fn check(&self) -> Result<DisclosurePublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The disclose guest's checks, in its order. A payload that does not
+    /// open is named.
+    pub fn guest_check(&self) -> Result<DisclosurePublicValues, CheckError> {
+        probe!(start "disclose/shape");
+        if self.payloads.is_empty() || self.payloads.len() > DISCLOSE_NOTES {
+            return Err("disclosure opens 1 to 8 payloads".into());
+        }
+        probe!(end "disclose/shape");
+        let mut notes: Vec<DisclosedNote> = Vec::with_capacity(self.payloads.len());
+        for (i, payload) in self.payloads.iter().enumerate() {
+            probe!(start "disclose/open");
+            let disclosed =
+                disclose_payload(payload, &self.viewing_secret).map_err(|msg| CheckError::at("payload", i, msg))?;
+            probe!(end "disclose/open");
+            notes.push(disclosed);
+        }
+        // Each payload once, so the count is the number of distinct notes
+        probe!(start "disclose/distinct_payloads");
+        if (1..notes.len()).any(|i| notes[..i].iter().any(|n| n.payload_hash == notes[i].payload_hash)) {
+            return Err("duplicate payload".into());
+        }
+        probe!(end "disclose/distinct_payloads");
+        Ok(DisclosurePublicValues {
+            viewing_pubkey: viewing_pubkey(&self.viewing_secret),
+            audit_id: self.audit_id,
+            notes,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl DisclosurePublicValues {
+    pub const LEN: usize = 32 * (4 + 4 * DISCLOSE_NOTES);
+
+    /// ABI-encode exactly as the disclose guest commits it.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = alloc::vec![0u8; Self::LEN];
+        out[0..32].copy_from_slice(&self.viewing_pubkey);
+        out[32..64].copy_from_slice(&self.audit_id);
+        out[88..96].copy_from_slice(&(self.notes.len() as u64).to_be_bytes());
+        for (i, note) in self.notes.iter().enumerate().take(DISCLOSE_NOTES) {
+            let slot = |column: usize| 32 * (3 + column * DISCLOSE_NOTES + i);
+            out[slot(0)..slot(0) + 32].copy_from_slice(&note.commitment);
+            out[slot(1)..slot(1) + 32].copy_from_slice(&note.payload_hash);
+            out[slot(2) + 24..slot(2) + 32].copy_from_slice(&note.amount.to_be_bytes());
+            out[slot(3)..slot(3) + 32].copy_from_slice(&note.recipient);
+        }
+        out[Self::LEN - 32..].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong,
+    /// the count is out of range, an amount does not fit in a u64, or an
+    /// unused slot is not zero.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN || bytes[64..88].iter().any(|&b| b != 0) {
+            return None;
+        }
+        let count = u64::from_be_bytes(bytes[88..96].try_into().unwrap()) as usize;
+        if count > DISCLOSE_NOTES {
+            return None;
+        }
+        let word = |column: usize, i: usize| -> &[u8] {
+            let at = 32 * (3 + column * DISCLOSE_NOTES + i);
+            &bytes[at..at + 32]
+        };
+        let mut notes = Vec::with_capacity(count);
+        for i in 0..DISCLOSE_NOTES {
+            let amount = word(2, i);
+            if amount[..24].iter().any(|&b| b != 0) {
+                return None;
+            }
+            if i >= count {
+                if (0..4).any(|column| word(column, i).iter().any(|&b| b != 0)) {
+                    return None;
+                }
+                continue;
+            }
+            notes.push(DisclosedNote {
+                commitment: word(0, i).try_into().unwrap(),
+                payload_hash: word(1, i).try_into().unwrap(),
+                amount: u64::from_be_bytes(amount[24..].try_into().unwrap()),
+                recipient: word(3, i).try_into().unwrap(),
+            });
+        }
+        Some(DisclosurePublicValues {
+            viewing_pubkey: bytes[0..32].try_into().unwrap(),
+            audit_id: bytes[32..64].try_into().unwrap(),
+            notes,
+            pool_binding: bytes[Self::LEN - 32..].try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note_encryption::OutputEncryption;
+    use crate::Note;
+    use alloc::vec;
+    use crypto_box::{aead::Aead, Nonce, PublicKey, SalsaBox, SecretKey};
+
+    const VIEWING_SECRET: [u8; 32] = [0x33; 32];
+
+    fn payload(amount: u64, viewing_secret: &[u8; 32]) -> (Note, Vec<u8>) {
+        let note = Note { amount, pubkey: [0xab; 32], blinding: [amount as u8; 32] };
+        let encryption = OutputEncryption {
+            viewing_pubkey: viewing_pubkey(viewing_secret),
+            ephemeral_secret: [amount as u8 ^ 0x44; 32],
+            nonce: [0x55; 24],
+        };
+        let payload = encryption.encrypt(&note);
+        (note, payload)
+    }
+
+    #[test]
+    fn test_disclosure_opens_chosen_payloads() {
+        let (first, p1) = payload(1_000_000, &VIEWING_SECRET);
+        let (second, p2) = payload(250_000, &VIEWING_SECRET);
+        let inputs = DisclosurePrivateInputs {
+            viewing_secret: VIEWING_SECRET,
+            payloads: vec![p1.clone(), p2],
+            audit_id: [7; 32],
+            pool_binding: [0x5b; 32],
+        };
+        let pv = inputs.check().unwrap();
+        assert_eq!(pv.viewing_pubkey, viewing_pubkey(&VIEWING_SECRET));
+        assert_eq!(pv.notes.len(), 2);
+        assert_eq!(pv.notes[0].commitment, first.commitment());
+        assert_eq!(pv.notes[0].payload_hash, keccak256(&p1));
+        assert_eq!((pv.notes[1].amount, pv.notes[1].recipient), (second.amount, second.pubkey));
+        assert_eq!(pv.pool_binding, [0x5b; 32]);
+
+        assert_eq!(DisclosurePublicValues::LEN, 1152);
+        let bytes = pv.encode();
+        assert_eq!(bytes.len(), DisclosurePublicValues::LEN);
+        assert_eq!(DisclosurePublicValues::decode(&bytes), Some(pv.clone()));
+        let mut stray = bytes.clone();
+        stray[32 * (3 + 2) + 1] = 1; // commitments[2], past the count
+        assert_eq!(DisclosurePublicValues::decode(&stray), None);
+    }
+
+    #[test]
+    fn test_disclosure_rejects_foreign_or_repeated_payloads() {
+        let (_, mine) = payload(1_000_000, &VIEWING_SECRET);
+        let (_, theirs) = payload(1_000_000, &[0x34; 32]);
+        let disclose = |payloads: Vec<Vec<u8>>| {
+            DisclosurePrivateInputs {
+                viewing_secret: VIEWING_SECRET,
+                payloads,
+                audit_id: [7; 32],
+                pool_binding: [0; 32],
+            }
+            .check()
+        };
+        assert_eq!(disclose(vec![]), Err("disclosure opens 1 to 8 payloads"));
+        assert_eq!(disclose(vec![mine.clone(); DISCLOSE_NOTES + 1]), Err("disclosure opens 1 to 8 payloads"));
+        assert_eq!(disclose(vec![theirs]), Err("payload does not decrypt under the viewing key"));
+        assert_eq!(disclose(vec![mine.clone(), mine.clone()]), Err("duplicate payload"));
+
+        // A payload that decrypts but carries something other than a note
+        let ephemeral = SecretKey::from([0x66; 32]);
+        let nonce = [0x77; 24];
+        let mut not_a_note = ephemeral.public_key().as_bytes().to_vec();
+        not_a_note.extend_from_slice(&nonce);
+        let salsa_box = SalsaBox::new(&PublicKey::from(viewing_pubkey(&VIEWING_SECRET)), &ephemeral);
+        not_a_note.extend(salsa_box.encrypt(&Nonce::from(nonce), &b"hello"[..]).unwrap());
+        assert_eq!(disclose(vec![not_a_note]), Err("payload does not hold a note"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Tree state as a fixture for the Foundry tests (feature `test_vectors`).
+//!
+//! `TreeFixture` captures what MerkleTree.sol stores for a tree (`zeros`,
+//! `filledSubtrees`, the `roots` ring buffer, `currentRootIndex`,
+//! `nextIndex`) plus its leaves and proofs of chosen leaves, so contract
+//! tests can start from the exact state of this crate's reference tree
+//! instead of recomputing it in Solidity.
+//!
+//! Two forms:
+//!   - `to_json`: keys are named after the contract's state variables and
+//!     sorted, so `vm.parseJson` decodes it into a struct whose fields are in
+//!     alphabetical order (as Foundry requires)
+//!   - `to_solidity`: a library of constants and pure getters, for suites
+//!     that should not read files
+//!
+//! Byte strings are 0x-prefixed lowercase hex.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use serde::{Deserialize, Serialize};
+
+use crate::{IncrementalMerkleTree, MerkleProof, ROOT_HISTORY_SIZE};
+
+/// One tree's state. Fields are in alphabetical order, as in the JSON.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeFixture {
+    pub current_root_index: u32,
+    /// `filledSubtrees[0..levels]`
+    pub filled_subtrees: Vec<String>,
+    /// Leaves in insertion order
+    pub leaves: Vec<String>,
+    pub levels: u32,
+    pub next_index: u32,
+    pub proofs: Vec<ProofFixture>,
+    /// Latest root (`getLastRoot()`)
+    pub root: String,
+    /// The whole ring buffer, ROOT_HISTORY_SIZE entries (unused slots zero)
+    pub roots: Vec<String>,
+    /// `zeros[0..levels]`
+    pub zeros: Vec<String>,
+}
+
+/// Membership proof of one leaf against `TreeFixture::root`, bottom up.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProofFixture {
+    /// Whether the proven node is the left child at each level
+    pub is_left: Vec<bool>,
+    pub leaf: String,
+    pub leaf_index: u32,
+    /// `MerkleProof::to_packed_bytes` of the same proof
+    pub packed: String,
+    pub siblings: Vec<String>,
+}
+
+fn hex0x(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+impl TreeFixture {
+    /// Fixture of `tree` with proofs of the leaves at `proof_indices`.
+    pub fn new(tree: &IncrementalMerkleTree, proof_indices: &[u32]) -> Result<Self, &'static str> {
+        let proofs = proof_indices
+            .iter()
+            .map(|&index| {
+                let steps = tree.try_get_proof(index)?;
+                Ok(ProofFixture {
+                    is_left: steps.iter().map(|s| s.is_left).collect(),
+                    leaf: hex0x(&tree.leaves[index as usize]),
+                    leaf_index: index,
+                    packed: hex0x(&MerkleProof::from_steps(&steps)?.to_packed_bytes()),
+                    siblings: steps.iter().map(|s| hex0x(&s.sibling)).collect(),
+                })
+            })
+            .collect::<Result<_, &'static str>>()?;
+
+        Ok(TreeFixture {
+            current_root_index: tree.current_root_index as u32,
+            filled_subtrees: tree.filled_subtrees.iter().map(|n| hex0x(n)).collect(),
+            leaves: tree.leaves.iter().map(|l| hex0x(l)).collect(),
+            levels: tree.levels as u32,
+            next_index: tree.next_index,
+            proofs,
+            root: hex0x(&tree.get_root()),
+            roots: tree.roots.iter().map(|r| hex0x(r)).collect(),
+            zeros: tree.zeros.iter().map(|z| hex0x(z)).collect(),
+        })
+    }
+
+    /// Pretty-printed JSON (trailing newline included).
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("tree fixture serializes");
+        json.push('\n');
+        json
+    }
+
+    /// A Solidity library named `name` holding this state: constants for the
+    /// scalars and the root, pure getters returning the arrays, and
+    /// `proof(i)` for the i-th proof.
+    pub fn to_solidity(&self, name: &str) -> String {
+        let mut sol = String::new();
+        let _ = writeln!(sol, "// SPDX-License-Identifier: MIT");
+        let _ = writeln!(sol, "// Generated by shielded_pool_lib::fixtures from the Rust reference tree. Do not edit.");
+        let _ = writeln!(sol, "pragma solidity ^0.8.20;\n");
+        let _ = writeln!(sol, "library {name} {{");
+        let _ = writeln!(sol, "    uint32 internal constant LEVELS = {};", self.levels);
+        let _ = writeln!(sol, "    uint32 internal constant NEXT_INDEX = {};", self.next_index);
+        let _ = writeln!(sol, "    uint32 internal constant CURRENT_ROOT_INDEX = {};", self.current_root_index);
+        let _ = writeln!(sol, "    uint32 internal constant ROOT_HISTORY_SIZE = {ROOT_HISTORY_SIZE};");
+        let _ = writeln!(sol, "    bytes32 internal constant ROOT = {};", self.root);
+        for (getter, values) in [
+            ("zeros", &self.zeros),
+            ("filledSubtrees", &self.filled_subtrees),
+            ("roots", &self.roots),
+            ("leaves", &self.leaves),
+        ] {
+            let _ = writeln!(sol, "\n    function {getter}() internal pure returns (bytes32[] memory out) {{");
+            let _ = writeln!(sol, "        out = new bytes32[]({});", values.len());
+            for (i, value) in values.iter().enumerate() {
+                let _ = writeln!(sol, "        out[{i}] = {value};");
+            }
+            let _ = writeln!(sol, "    }}");
+        }
+
+        let _ = writeln!(sol, "\n    uint256 internal constant PROOF_COUNT = {};", self.proofs.len());
+        let _ = writeln!(
+            sol,
+            "\n    function proof(uint256 i)\n        internal\n        pure\n        returns (uint32 leafIndex, bytes32 leaf, bytes32[] memory siblings, bool[] memory isLeft)\n    {{"
+        );
+        for (i, p) in self.proofs.iter().enumerate() {
+            let _ = writeln!(sol, "        if (i == {i}) {{");
+            let _ = writeln!(sol, "            leafIndex = {};", p.leaf_index);
+            let _ = writeln!(sol, "            leaf = {};", p.leaf);
+            let _ = writeln!(sol, "            siblings = new bytes32[]({});", p.siblings.len());
+            let _ = writeln!(sol, "            isLeft = new bool[]({});", p.is_left.len());
+            for (j, (sibling, is_left)) in p.siblings.iter().zip(&p.is_left).enumerate() {
+                let _ = writeln!(sol, "            siblings[{j}] = {sibling};");
+                let _ = writeln!(sol, "            isLeft[{j}] = {is_left};");
+            }
+            let _ = writeln!(sol, "            return (leafIndex, leaf, siblings, isLeft);");
+            let _ = writeln!(sol, "        }}");
+        }
+        let _ = writeln!(sol, "        revert(\"no such proof\");");
+        let _ = writeln!(sol, "    }}");
+        let _ = writeln!(sol, "}}");
+        sol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keccak256, verify_merkle_proof, MerkleProofStep};
+
+    fn unhex32(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        hex::decode_to_slice(s.trim_start_matches("0x"), &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_tree_fixture() {
+        let mut tree = IncrementalMerkleTree::new(4);
+        for i in 0..5u32 {
+            tree.insert(keccak256(&i.to_be_bytes()));
+        }
+        let fixture = TreeFixture::new(&tree, &[0, 4]).unwrap();
+        assert_eq!(fixture.roots.len(), ROOT_HISTORY_SIZE);
+        assert_eq!(fixture.roots[fixture.current_root_index as usize], fixture.root);
+        assert_eq!((fixture.zeros.len(), fixture.filled_subtrees.len()), (4, 4));
+        for p in &fixture.proofs {
+            let steps: Vec<_> = p
+                .siblings
+                .iter()
+                .zip(&p.is_left)
+                .map(|(s, &is_left)| MerkleProofStep { is_left, sibling: unhex32(s) })
+                .collect();
+            assert!(verify_merkle_proof(unhex32(&p.leaf), &steps, unhex32(&fixture.root)));
+        }
+        assert_eq!(TreeFixture::new(&tree, &[5]), Err("leaf index out of range"));
+
+        // Keys sorted, as vm.parseJson needs
+        let json = fixture.to_json();
+        let keys: Vec<&str> = ["currentRootIndex", "filledSubtrees", "leaves", "levels", "nextIndex", "proofs", "root", "roots", "zeros"]
+            .into_iter()
+            .filter(|k| json.contains(&format!("\"{k}\":")))
+            .collect();
+        assert_eq!(keys.len(), 9);
+        let positions: Vec<usize> = keys.iter().map(|k| json.find(&format!("\n  \"{k}\":")).unwrap()).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(serde_json::from_str::<TreeFixture>(&json).unwrap(), fixture);
+
+        let sol = fixture.to_solidity("TreeFixture");
+        assert!(sol.contains(&format!("bytes32 internal constant ROOT = {};", fixture.root)));
+        assert!(sol.contains("uint32 internal constant NEXT_INDEX = 5;"));
+        assert!(sol.contains("        if (i == 1) {\n            leafIndex = 4;"));
+        assert_eq!(sol.matches('{').count(), sol.matches('}').count());
+    }
+}
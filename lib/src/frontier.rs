@@ -0,0 +1,234 @@
+//! Frontier-only Merkle tree for light wallets.
+//!
+//! `IncrementalMerkleTree` keeps every leaf (and the nodes above them) so it
+//! can prove any leaf. A wallet only ever proves its own notes. A
+//! `FrontierTree` keeps what MerkleTree.sol keeps, `filled_subtrees`,
+//! `next_index` and the recent roots, plus an authentication path for each
+//! leaf the wallet registered. Memory is O(levels) per tracked leaf, however
+//! many leaves the pool holds.
+//!
+//! A leaf can only be tracked when it is inserted (`insert_tracked`), or
+//! later from a `Witness` taken while its path was still known. Leaves
+//! inserted untracked cannot be proven afterwards.
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute_zeros, hash_pair, tree_capacity,
+    witness::{hash_up, proof_steps, Witness},
+    IncrementalMerkleTree, MerkleProofStep, ROOT_HISTORY_SIZE,
+};
+
+/// Rightmost frontier of the pool's tree plus paths of tracked leaves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrontierTree {
+    levels: usize,
+    zeros: Vec<[u8; 32]>,
+    filled_subtrees: Vec<[u8; 32]>,
+    next_index: u32,
+    /// Circular buffer of recent roots, as in `IncrementalMerkleTree`
+    roots: Vec<[u8; 32]>,
+    current_root_index: usize,
+    /// Leaf index → (leaf, sibling at each height)
+    tracked: BTreeMap<u32, ([u8; 32], Vec<[u8; 32]>)>,
+}
+
+impl FrontierTree {
+    /// Empty tree. Matches MerkleTree.sol constructor.
+    pub fn new(levels: usize) -> Self {
+        let zeros = compute_zeros(levels);
+        let mut roots = vec![[0u8; 32]; ROOT_HISTORY_SIZE];
+        roots[0] = hash_pair(&zeros[levels - 1], &zeros[levels - 1]);
+        FrontierTree {
+            levels,
+            filled_subtrees: zeros.clone(),
+            zeros,
+            next_index: 0,
+            roots,
+            current_root_index: 0,
+            tracked: BTreeMap::new(),
+        }
+    }
+
+    /// Frontier of `tree`, tracking the leaves at `track`.
+    pub fn from_tree(tree: &IncrementalMerkleTree, track: &[u32]) -> Result<Self, &'static str> {
+        let mut frontier = FrontierTree {
+            levels: tree.levels,
+            zeros: tree.zeros.clone(),
+            filled_subtrees: tree.filled_subtrees.clone(),
+            next_index: tree.next_index,
+            roots: tree.roots.clone(),
+            current_root_index: tree.current_root_index,
+            tracked: BTreeMap::new(),
+        };
+        for &index in track {
+            frontier.track_witness(&tree.witness(index)?)?;
+        }
+        Ok(frontier)
+    }
+
+    /// Append `leaf` without tracking it. Returns its index.
+    pub fn try_insert(&mut self, leaf: [u8; 32]) -> Result<u32, &'static str> {
+        self.append(leaf, false)
+    }
+
+    /// Append `leaf` and keep its path current from now on. Returns its index.
+    pub fn insert_tracked(&mut self, leaf: [u8; 32]) -> Result<u32, &'static str> {
+        self.append(leaf, true)
+    }
+
+    /// Matches MerkleTree.sol _insert(), updating tracked paths on the way up.
+    fn append(&mut self, leaf: [u8; 32], track: bool) -> Result<u32, &'static str> {
+        let index = self.next_index;
+        if index as u64 >= tree_capacity(self.levels) {
+            return Err("Merkle tree is full");
+        }
+
+        let mut path = Vec::with_capacity(if track { self.levels } else { 0 });
+        let tracked = &mut self.tracked;
+        let root = hash_up(&mut self.filled_subtrees, &self.zeros, index, leaf, |height, node_index, node, sibling| {
+            // The new leaf's ancestor is a tracked leaf's sibling here until the subtree fills up
+            for (tracked_index, (_, tracked_path)) in tracked.iter_mut() {
+                if node_index == (tracked_index >> height) ^ 1 {
+                    tracked_path[height] = node;
+                }
+            }
+            if track {
+                path.push(sibling);
+            }
+        });
+
+        self.current_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE;
+        self.roots[self.current_root_index] = root;
+        self.next_index = index + 1;
+        if track {
+            self.tracked.insert(index, (leaf, path));
+        }
+        Ok(index)
+    }
+
+    /// Start tracking a leaf from a witness of this tree's current state.
+    pub fn track_witness(&mut self, witness: &Witness) -> Result<(), &'static str> {
+        if witness.levels() != self.levels || witness.next_index() != self.next_index {
+            return Err("witness is not for this tree's current state");
+        }
+        if witness.root() != self.get_root() {
+            return Err("witness root differs from the tree's root");
+        }
+        let path = witness.proof().into_iter().map(|step| step.sibling).collect();
+        self.tracked.insert(witness.leaf_index(), (witness.leaf(), path));
+        Ok(())
+    }
+
+    /// Stop tracking a leaf (e.g. once its note is spent). Returns whether it was tracked.
+    pub fn untrack(&mut self, leaf_index: u32) -> bool {
+        self.tracked.remove(&leaf_index).is_some()
+    }
+
+    /// Indices of the tracked leaves, ascending.
+    pub fn tracked(&self) -> impl Iterator<Item = u32> + '_ {
+        self.tracked.keys().copied()
+    }
+
+    /// Merkle proof for a tracked leaf against `get_root()`.
+    pub fn try_get_proof(&self, leaf_index: u32) -> Result<Vec<MerkleProofStep>, &'static str> {
+        let (_, path) = self.tracked.get(&leaf_index).ok_or("leaf is not tracked")?;
+        Ok(proof_steps(leaf_index, path))
+    }
+
+    /// The tracked leaf at `leaf_index`.
+    pub fn leaf(&self, leaf_index: u32) -> Option<[u8; 32]> {
+        self.tracked.get(&leaf_index).map(|(leaf, _)| *leaf)
+    }
+
+    /// Most recent root.
+    pub fn get_root(&self) -> [u8; 32] {
+        self.roots[self.current_root_index]
+    }
+
+    /// Whether `root` is among the last ROOT_HISTORY_SIZE roots.
+    pub fn is_known_root(&self, root: [u8; 32]) -> bool {
+        root != [0u8; 32] && self.roots.contains(&root)
+    }
+
+    /// The roots `is_known_root` accepts, newest first.
+    pub fn recent_roots(&self) -> Vec<[u8; 32]> {
+        crate::recent_roots(&self.roots, self.current_root_index)
+    }
+
+    /// Number of leaves inserted.
+    pub fn next_index(&self) -> u32 {
+        self.next_index
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keccak256, verify_merkle_proof};
+
+    fn leaf(i: u32) -> [u8; 32] {
+        keccak256(&i.to_be_bytes())
+    }
+
+    #[test]
+    fn test_frontier_tracks_full_tree() {
+        let mut tree = IncrementalMerkleTree::new(5);
+        let mut frontier = FrontierTree::new(5);
+        for i in 0..32 {
+            let index = tree.insert(leaf(i));
+            let tracked = if i % 7 == 3 { frontier.insert_tracked(leaf(i)) } else { frontier.try_insert(leaf(i)) };
+            assert_eq!(tracked, Ok(index));
+            assert_eq!(frontier.get_root(), tree.get_root());
+            for t in frontier.tracked() {
+                assert_eq!(frontier.try_get_proof(t).unwrap(), tree.get_proof(t), "leaf {t} after {i}");
+            }
+        }
+        assert_eq!(frontier.tracked().collect::<Vec<_>>(), [3, 10, 17, 24, 31]);
+        assert!(frontier.is_known_root(tree.roots[tree.current_root_index]));
+        assert_eq!(frontier.recent_roots(), tree.recent_roots());
+        assert_eq!(frontier.try_insert(leaf(32)), Err("Merkle tree is full"));
+        assert_eq!(frontier.try_get_proof(4), Err("leaf is not tracked"));
+
+        assert!(frontier.untrack(10));
+        assert!(!frontier.untrack(10));
+        assert!(frontier.try_get_proof(10).is_err());
+    }
+
+    #[test]
+    fn test_frontier_from_tree_and_witness() {
+        let mut tree = IncrementalMerkleTree::new(6);
+        for i in 0..9 {
+            tree.insert(leaf(i));
+        }
+        let mut frontier = FrontierTree::from_tree(&tree, &[2]).unwrap();
+        assert!(FrontierTree::from_tree(&tree, &[9]).is_err());
+
+        // A witness of an older state does not fit
+        let stale = tree.witness(5).unwrap();
+        tree.insert(leaf(9));
+        frontier.try_insert(leaf(9)).unwrap();
+        assert!(frontier.track_witness(&stale).is_err());
+        frontier.track_witness(&tree.witness(5).unwrap()).unwrap();
+
+        for i in 10..40 {
+            tree.insert(leaf(i));
+            frontier.try_insert(leaf(i)).unwrap();
+        }
+        for t in [2, 5] {
+            let proof = frontier.try_get_proof(t).unwrap();
+            assert_eq!(proof, tree.get_proof(t));
+            assert!(verify_merkle_proof(frontier.leaf(t).unwrap(), &proof, frontier.get_root()));
+        }
+        // Serializes without any leaves but the tracked ones
+        let json = serde_json::to_string(&frontier).unwrap();
+        assert_eq!(serde_json::from_str::<FrontierTree>(&json).unwrap(), frontier);
+    }
+}
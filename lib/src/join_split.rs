@@ -0,0 +1,357 @@
+//! Join-split transfers: up to four notes in, up to four out, in one proof.
+//!
+//! The transfer circuit spends exactly two notes into exactly two, so
+//! merging five notes or paying three people takes a chain of transfers.
+//! The join-split circuit spends 1 to `JOIN_SPLIT_INPUTS` notes into 1 to
+//! `JOIN_SPLIT_OUTPUTS`, under the same rules: every input is authorized
+//! (owner, claim or reclaim), is in the tree under `root` and is spent once,
+//! and the amounts balance.
+//!
+//! The public values always carry four nullifiers and four commitments. The
+//! host pads both sides with dummy notes (`JoinSplitPrivateInputs::padded`),
+//! so a proof does not reveal how many notes it really spent or created:
+//!
+//!   - a dummy input is a zero-amount note of a throwaway key. It has no
+//!     leaf, so the circuit skips the Merkle check for zero-amount inputs.
+//!     They cannot move value, so no check is lost. Its nullifier is derived
+//!     as for any note and looks like any other
+//!   - a dummy output is a zero-amount note to a throwaway pubkey
+//!
+//! Dummies are derived from a random seed the host supplies, so they never
+//! repeat across proofs.
+//!
+//! Public values committed (384 bytes = 12 × 32-byte slots):
+//!   [root, nullifier0..3, outCommitment0..3, reclaimDeadline (uint256 BE),
+//!    treeLevels (uint256 BE), poolBinding]
+//! for `ShieldedPool.joinSplit` to read with
+//!   abi.decode(publicValues, (bytes32, bytes32[4], bytes32[4], uint256, uint256, bytes32))
+//! It rejects a spent or repeated nullifier, requires `treeLevels` to be its
+//! tree's depth and `poolBinding` its own, and inserts all four commitments,
+//! as `privateTransfer` does for two.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::cancellable::{authorize_spend, committed_deadline, CancellableLock, SpendAuthorization};
+use crate::{
+    check_output_amount, derive_pubkey, keccak256, probe, spent_proof_levels, verify_merkle_proof, CheckError,
+    MerkleProofStep, Note,
+};
+
+/// Input slots of the join-split circuit.
+pub const JOIN_SPLIT_INPUTS: usize = 4;
+
+/// Output slots of the join-split circuit.
+pub const JOIN_SPLIT_OUTPUTS: usize = 4;
+
+/// Domain separator of the dummy notes' keys and blindings.
+const DUMMY_DOMAIN: &[u8] = b"shielded-pool/join-split-dummy/1";
+
+/// One note to spend.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JoinSplitInput {
+    pub note: Note,
+    pub spending_key: [u8; 32],
+    /// Merkle proof under the request's root (empty for a dummy)
+    pub merkle_proof: Vec<MerkleProofStep>,
+    /// Set when the note is cancellable
+    #[serde(default)]
+    pub lock: Option<CancellableLock>,
+}
+
+/// Private inputs for the join-split circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JoinSplitPrivateInputs {
+    /// Exactly `JOIN_SPLIT_INPUTS`, real ones first
+    pub inputs: Vec<JoinSplitInput>,
+    /// Exactly `JOIN_SPLIT_OUTPUTS`, real ones first
+    pub output_notes: Vec<Note>,
+    /// The Merkle root every real input's proof verifies against
+    pub root: [u8; 32],
+    /// The pool the proof is for (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the join-split circuit (384 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JoinSplitPublicValues {
+    pub root: [u8; 32],
+    pub nullifiers: [[u8; 32]; JOIN_SPLIT_INPUTS],
+    pub out_commitments: [[u8; 32]; JOIN_SPLIT_OUTPUTS],
+    /// Zero unless an input is a reclaimed cancellable note
+    pub reclaim_deadline: u64,
+    /// Depth of the inputs' Merkle proofs (see `spent_proof_levels`)
+    pub tree_levels: u64,
+    /// The pool the proof is for (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+/// `keccak256(DUMMY_DOMAIN || seed || role || slot)`
+fn dummy_word(seed: &[u8; 32], role: u8, slot: usize) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(DUMMY_DOMAIN.len() + 34);
+    preimage.extend_from_slice(DUMMY_DOMAIN);
+    preimage.extend_from_slice(seed);
+    preimage.extend_from_slice(&[role, slot as u8]);
+    keccak256(&preimage)
+}
+
+impl JoinSplitInput {
+    /// Zero-amount input for `slot`, derived from `seed`.
+    pub fn dummy(seed: &[u8; 32], slot: usize) -> Self {
+        let spending_key = dummy_word(seed, 0, slot);
+        JoinSplitInput {
+            note: Note { amount: 0, pubkey: derive_pubkey(&spending_key), blinding: dummy_word(seed, 1, slot) },
+            spending_key,
+            merkle_proof: Vec::new(),
+            lock: None,
+        }
+    }
+
+    /// Authorize the spend (owner, or claim/reclaim of a cancellable note);
+    /// the circuits' first step for one input.
+    pub fn authorize(&self) -> Result<SpendAuthorization, &'static str> {
+        authorize_spend(&self.note, &self.spending_key, self.lock.as_ref())
+    }
+
+    /// Whether the note is in the tree under `root`; the circuits' second
+    /// step. A zero-amount input moves no value, so a dummy needs no leaf.
+    pub fn is_member(&self, root: [u8; 32]) -> bool {
+        self.note.amount == 0 || verify_merkle_proof(self.note.commitment(), &self.merkle_proof, root)
+    }
+}
+
+/// Zero-amount output for `slot`, derived from `seed`.
+pub fn dummy_output(seed: &[u8; 32], slot: usize) -> Note {
+    Note { amount: 0, pubkey: dummy_word(seed, 2, slot), blinding: dummy_word(seed, 3, slot) }
+}
+
+impl JoinSplitPrivateInputs {
+    /// `inputs` and `outputs` padded with dummies derived from `seed`, which
+    /// must be fresh randomness. `pool_binding` is left zero; set it for the
+    /// pool the proof is for.
+    pub fn padded(
+        root: [u8; 32],
+        mut inputs: Vec<JoinSplitInput>,
+        mut outputs: Vec<Note>,
+        seed: [u8; 32],
+    ) -> Result<Self, &'static str> {
+        if inputs.is_empty() || inputs.len() > JOIN_SPLIT_INPUTS {
+            return Err("a join-split spends 1 to 4 notes");
+        }
+        if outputs.is_empty() || outputs.len() > JOIN_SPLIT_OUTPUTS {
+            return Err("a join-split creates 1 to 4 notes");
+        }
+        for slot in inputs.len()..JOIN_SPLIT_INPUTS {
+            inputs.push(JoinSplitInput::dummy(&seed, slot));
+        }
+        for slot in outputs.len()..JOIN_SPLIT_OUTPUTS {
+            outputs.push(dummy_output(&seed, slot));
+        }
+        Ok(JoinSplitPrivateInputs { inputs, output_notes: outputs, root, pool_binding: [0u8; 32] })
+    }
+
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks
+    /// (`guest_check`).
+    pub fn check(&self) -> Result<JoinSplitPublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The join-split guest's checks, in its order. A rejected input is
+    /// named.
+    pub fn guest_check(&self) -> Result<JoinSplitPublicValues, CheckError> {
+        // Every slot is filled, so the public values never reveal how many
+        // notes are real
+        probe!(start "join_split/shape");
+        if self.inputs.len() != JOIN_SPLIT_INPUTS || self.output_notes.len() != JOIN_SPLIT_OUTPUTS {
+            return Err("join-split needs exactly 4 inputs and 4 outputs (pad with dummies)".into());
+        }
+        probe!(end "join_split/shape");
+        let mut auths = Vec::with_capacity(JOIN_SPLIT_INPUTS);
+        for (i, input) in self.inputs.iter().enumerate() {
+            probe!(start "join_split/authorize");
+            let auth = input.authorize().map_err(|msg| CheckError::at("input note", i, msg))?;
+            probe!(end "join_split/authorize");
+            probe!(arm "join_split/authorize", crate::coverage::spend_arm(auth.path));
+            probe!(start "join_split/merkle");
+            if !input.is_member(self.root) {
+                return Err(CheckError::at("input note", i, "Merkle proof invalid"));
+            }
+            probe!(end "join_split/merkle");
+            probe!(arm "join_split/merkle", if input.note.amount != 0 { "member" } else { "zero_amount" });
+            auths.push(auth);
+        }
+        // Spending one note in two slots would repeat its nullifier and mint
+        // its value twice
+        probe!(start "join_split/distinct_inputs");
+        let mut nullifiers = [[0u8; 32]; JOIN_SPLIT_INPUTS];
+        for (i, auth) in auths.iter().enumerate() {
+            if nullifiers[..i].contains(&auth.nullifier) {
+                return Err("duplicate input note".into());
+            }
+            nullifiers[i] = auth.nullifier;
+        }
+        probe!(end "join_split/distinct_inputs");
+        // A shorter proof can reach a known root from an inner node
+        probe!(start "join_split/proof_depth");
+        let tree_levels = spent_proof_levels(self.inputs.iter().map(|input| (&input.note, &input.merkle_proof)))?;
+        probe!(end "join_split/proof_depth");
+        probe!(start "join_split/output_range");
+        self.output_notes.iter().try_for_each(|note| check_output_amount(note.amount))?;
+        probe!(end "join_split/output_range");
+        probe!(start "join_split/conservation");
+        let input_sum: u128 = self.inputs.iter().map(|input| input.note.amount as u128).sum();
+        let output_sum: u128 = self.output_notes.iter().map(|n| n.amount as u128).sum();
+        if input_sum != output_sum {
+            return Err("amounts don't balance".into());
+        }
+        probe!(end "join_split/conservation");
+        let mut out_commitments = [[0u8; 32]; JOIN_SPLIT_OUTPUTS];
+        for (slot, note) in out_commitments.iter_mut().zip(&self.output_notes) {
+            *slot = note.commitment();
+        }
+        Ok(JoinSplitPublicValues {
+            root: self.root,
+            nullifiers,
+            out_commitments,
+            reclaim_deadline: committed_deadline(&auths),
+            tree_levels,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl JoinSplitPublicValues {
+    pub const LEN: usize = 32 * (4 + JOIN_SPLIT_INPUTS + JOIN_SPLIT_OUTPUTS);
+
+    /// ABI-encode exactly as the join-split guest commits it.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        let words = core::iter::once(&self.root).chain(&self.nullifiers).chain(&self.out_commitments);
+        for (chunk, word) in out.chunks_mut(32).zip(words) {
+            chunk.copy_from_slice(word);
+        }
+        out[Self::LEN - 72..Self::LEN - 64].copy_from_slice(&self.reclaim_deadline.to_be_bytes());
+        out[Self::LEN - 40..Self::LEN - 32].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[Self::LEN - 32..].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the deadline or the tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN
+            || bytes[Self::LEN - 96..Self::LEN - 72].iter().any(|&b| b != 0)
+            || bytes[Self::LEN - 64..Self::LEN - 40].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(JoinSplitPublicValues {
+            root: word(0),
+            nullifiers: core::array::from_fn(|i| word(1 + i)),
+            out_commitments: core::array::from_fn(|i| word(1 + JOIN_SPLIT_INPUTS + i)),
+            reclaim_deadline: u64::from_be_bytes(bytes[Self::LEN - 72..Self::LEN - 64].try_into().unwrap()),
+            tree_levels: u64::from_be_bytes(bytes[Self::LEN - 40..Self::LEN - 32].try_into().unwrap()),
+            pool_binding: bytes[Self::LEN - 32..].try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use crate::{compute_nullifier, IncrementalMerkleTree};
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    fn note(amount: u64, blinding: u8) -> Note {
+        Note { amount, pubkey: derive_pubkey(&KEY), blinding: [blinding; 32] }
+    }
+
+    /// A tree holding `notes`, and each note as an input against it.
+    fn spendable(notes: &[Note]) -> ([u8; 32], Vec<JoinSplitInput>) {
+        let mut tree = IncrementalMerkleTree::new(8);
+        for n in notes {
+            tree.insert(n.commitment());
+        }
+        let inputs = notes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| JoinSplitInput {
+                note: n.clone(),
+                spending_key: KEY,
+                merkle_proof: tree.get_proof(i as u32),
+                lock: None,
+            })
+            .collect();
+        (tree.get_root(), inputs)
+    }
+
+    #[test]
+    fn test_join_split_pads_and_balances() {
+        // Three notes merged into one payment and its change
+        let notes = [note(100, 1), note(250, 2), note(50, 3)];
+        let (root, inputs) = spendable(&notes);
+        let outputs = vec![Note { amount: 300, pubkey: [9u8; 32], blinding: [4u8; 32] }, note(100, 5)];
+        let request = JoinSplitPrivateInputs::padded(root, inputs.clone(), outputs.clone(), [1u8; 32]).unwrap();
+        assert_eq!((request.inputs.len(), request.output_notes.len()), (4, 4));
+
+        let pv = request.check().unwrap();
+        assert_eq!(pv.root, root);
+        for (i, n) in notes.iter().enumerate() {
+            assert_eq!(pv.nullifiers[i], compute_nullifier(&n.commitment(), &KEY));
+        }
+        assert_eq!(pv.out_commitments[0], outputs[0].commitment());
+        assert_eq!((pv.reclaim_deadline, pv.tree_levels), (0, 8));
+        assert_eq!(JoinSplitPublicValues::decode(&pv.encode()), Some(pv.clone()));
+        assert_eq!(JoinSplitPublicValues::LEN, 384);
+
+        // Dummies differ per seed, so padding never repeats a nullifier
+        let other = JoinSplitPrivateInputs::padded(root, inputs.clone(), outputs.clone(), [2u8; 32]).unwrap();
+        let other_pv = other.check().unwrap();
+        assert_ne!(other_pv.nullifiers[3], pv.nullifiers[3]);
+        assert_ne!(other_pv.out_commitments[2], pv.out_commitments[2]);
+        assert_eq!(other_pv.nullifiers[..3], pv.nullifiers[..3]);
+
+        // Shapes the circuit does not take
+        assert!(JoinSplitPrivateInputs::padded(root, Vec::new(), outputs.clone(), [1u8; 32]).is_err());
+        assert!(JoinSplitPrivateInputs::padded(root, inputs.clone(), vec![note(1, 0); 5], [1u8; 32]).is_err());
+        let mut short = request.clone();
+        short.output_notes.pop();
+        assert!(short.check().is_err());
+    }
+
+    #[test]
+    fn test_join_split_rejects_bad_spends() {
+        let notes = [note(100, 1), note(250, 2)];
+        let (root, inputs) = spendable(&notes);
+        let outputs = vec![note(350, 9)];
+        let base = JoinSplitPrivateInputs::padded(root, inputs.clone(), outputs, [1u8; 32]).unwrap();
+        assert!(base.check().is_ok());
+
+        let mut unbalanced = base.clone();
+        unbalanced.output_notes[1].amount = 1;
+        assert_eq!(unbalanced.check(), Err("amounts don't balance"));
+
+        let mut duplicate = base.clone();
+        duplicate.inputs[1] = duplicate.inputs[0].clone();
+        duplicate.output_notes[0].amount = 200;
+        assert_eq!(duplicate.check(), Err("duplicate input note"));
+
+        let mut wrong_key = base.clone();
+        wrong_key.inputs[1].spending_key = [8u8; 32];
+        assert_eq!(wrong_key.check(), Err("spending key does not match note pubkey"));
+
+        // A note with value must be in the tree; only zero-amount ones skip the check
+        let mut forged = base.clone();
+        forged.inputs[2].note.amount = 1;
+        forged.output_notes[0].amount = 351;
+        assert_eq!(forged.check(), Err("Merkle proof invalid"));
+        let mut stale = base.clone();
+        stale.root = [0u8; 32];
+        assert_eq!(stale.check(), Err("Merkle proof invalid"));
+    }
+}
@@ -0,0 +1,399 @@
+//! Encrypted keystore for spending keys.
+//!
+//! The JSON layout follows EIP-2335 (kdf / cipher modules, hex-encoded params):
+//!   kdf:    scrypt(passphrase, salt, n, r, p) -> 32-byte decryption key
+//!   cipher: aes-256-gcm(decryption key, iv); message = ciphertext || 16-byte tag
+//!
+//! The GCM tag authenticates the ciphertext, so EIP-2335's separate checksum
+//! module is not needed: a wrong passphrase fails decryption outright.
+//! The derived shielded pubkey is stored in the clear so wallets can match
+//! notes to keys without unlocking them.
+
+use std::fmt;
+use std::path::Path;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use serde::{Deserialize, Serialize};
+
+use crate::{ct_eq_32, SpendingKey};
+
+/// Keystore format version.
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KDF_FUNCTION: &str = "scrypt";
+const CIPHER_FUNCTION: &str = "aes-256-gcm";
+const DKLEN: u32 = 32;
+const SALT_LEN: usize = 32;
+const IV_LEN: usize = 12;
+
+// =============================================================================
+//                              JSON LAYOUT
+// =============================================================================
+
+/// An encrypted spending key, serialized as EIP-2335-style JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keystore {
+    pub crypto: KeystoreCrypto,
+    /// Free-form label (e.g. "sender")
+    #[serde(default)]
+    pub description: String,
+    /// Hex-encoded shielded pubkey: keccak256(spending_key)
+    pub pubkey: String,
+    pub version: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeystoreCrypto {
+    pub kdf: KdfModule,
+    pub cipher: CipherModule,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KdfModule {
+    pub function: String,
+    pub params: ScryptParams,
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScryptParams {
+    pub dklen: u32,
+    pub n: u32,
+    pub r: u32,
+    pub p: u32,
+    /// Hex-encoded salt
+    pub salt: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CipherModule {
+    pub function: String,
+    pub params: CipherParams,
+    /// Hex-encoded ciphertext || GCM tag
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CipherParams {
+    /// Hex-encoded 12-byte nonce
+    pub iv: String,
+}
+
+// =============================================================================
+//                              KDF PARAMETERS
+// =============================================================================
+
+/// scrypt cost parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    /// log2(n)
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl KdfParams {
+    /// EIP-2335 defaults: n = 2^18, r = 8, p = 1.
+    pub const DEFAULT: KdfParams = KdfParams { log_n: 18, r: 8, p: 1 };
+
+    /// Cheap parameters for tests. Never use for real keys.
+    pub const INSECURE_FAST: KdfParams = KdfParams { log_n: 4, r: 8, p: 1 };
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams::DEFAULT
+    }
+}
+
+// =============================================================================
+//                                ERRORS
+// =============================================================================
+
+#[derive(Debug)]
+pub enum KeystoreError {
+    UnsupportedVersion(u32),
+    UnsupportedKdf(String),
+    UnsupportedCipher(String),
+    InvalidParams(&'static str),
+    InvalidHex(&'static str),
+    /// Decryption failed: wrong passphrase or tampered file.
+    WrongPassphrase,
+    /// Decrypted key does not derive the stored pubkey.
+    PubkeyMismatch,
+    Random(getrandom::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::UnsupportedVersion(v) => write!(f, "unsupported keystore version {v}"),
+            KeystoreError::UnsupportedKdf(k) => write!(f, "unsupported kdf function '{k}'"),
+            KeystoreError::UnsupportedCipher(c) => write!(f, "unsupported cipher function '{c}'"),
+            KeystoreError::InvalidParams(what) => write!(f, "invalid keystore params: {what}"),
+            KeystoreError::InvalidHex(field) => write!(f, "invalid hex in keystore field '{field}'"),
+            KeystoreError::WrongPassphrase => write!(f, "wrong passphrase or corrupted keystore"),
+            KeystoreError::PubkeyMismatch => {
+                write!(f, "decrypted key does not match keystore pubkey")
+            }
+            KeystoreError::Random(e) => write!(f, "failed to gather randomness: {e}"),
+            KeystoreError::Io(e) => write!(f, "keystore i/o error: {e}"),
+            KeystoreError::Json(e) => write!(f, "keystore json error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<std::io::Error> for KeystoreError {
+    fn from(e: std::io::Error) -> Self {
+        KeystoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for KeystoreError {
+    fn from(e: serde_json::Error) -> Self {
+        KeystoreError::Json(e)
+    }
+}
+
+// =============================================================================
+//                           ENCRYPT / DECRYPT
+// =============================================================================
+
+impl Keystore {
+    /// Encrypt a spending key with default scrypt params and a fresh salt/iv.
+    pub fn encrypt(
+        spending_key: &SpendingKey,
+        passphrase: &str,
+        description: &str,
+    ) -> Result<Self, KeystoreError> {
+        Self::encrypt_with_params(spending_key, passphrase, description, KdfParams::DEFAULT)
+    }
+
+    /// Encrypt a spending key with the given scrypt params and a fresh salt/iv.
+    pub fn encrypt_with_params(
+        spending_key: &SpendingKey,
+        passphrase: &str,
+        description: &str,
+        params: KdfParams,
+    ) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut iv = [0u8; IV_LEN];
+        getrandom::getrandom(&mut salt).map_err(KeystoreError::Random)?;
+        getrandom::getrandom(&mut iv).map_err(KeystoreError::Random)?;
+        Self::encrypt_deterministic(spending_key, passphrase, description, params, salt, iv)
+    }
+
+    /// Encrypt with caller-supplied salt and iv. Only reuse these for test vectors.
+    pub fn encrypt_deterministic(
+        spending_key: &SpendingKey,
+        passphrase: &str,
+        description: &str,
+        params: KdfParams,
+        salt: [u8; SALT_LEN],
+        iv: [u8; IV_LEN],
+    ) -> Result<Self, KeystoreError> {
+        Ok(Keystore {
+            crypto: KeystoreCrypto::encrypt_deterministic(spending_key.as_bytes(), passphrase, params, salt, iv)?,
+            description: description.to_string(),
+            pubkey: hex::encode(spending_key.pubkey()),
+            version: KEYSTORE_VERSION,
+        })
+    }
+
+    /// Decrypt the spending key, checking it against the stored pubkey.
+    pub fn decrypt(&self, passphrase: &str) -> Result<SpendingKey, KeystoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeystoreError::UnsupportedVersion(self.version));
+        }
+        let plaintext = self.crypto.decrypt(passphrase)?;
+        let key: [u8; 32] = plaintext
+            .as_slice()
+            .try_into()
+            .map_err(|_| KeystoreError::InvalidParams("decrypted key is not 32 bytes"))?;
+        let key = SpendingKey::from(key);
+
+        if !ct_eq_32(&key.pubkey(), &self.pubkey()?) {
+            return Err(KeystoreError::PubkeyMismatch);
+        }
+        Ok(key)
+    }
+
+    /// The stored shielded pubkey (readable without the passphrase).
+    pub fn pubkey(&self) -> Result<[u8; 32], KeystoreError> {
+        decode_fixed::<32>(&self.pubkey, "pubkey")
+    }
+
+    pub fn to_json(&self) -> Result<String, KeystoreError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, KeystoreError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Read a keystore JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, KeystoreError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Write this keystore as JSON, creating parent directories as needed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), KeystoreError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+impl KeystoreCrypto {
+    /// Encrypt arbitrary bytes the way a keystore encrypts its key, with a
+    /// fresh salt/iv (used for backups that hold more than one key).
+    pub fn encrypt(plaintext: &[u8], passphrase: &str, params: KdfParams) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut iv = [0u8; IV_LEN];
+        getrandom::getrandom(&mut salt).map_err(KeystoreError::Random)?;
+        getrandom::getrandom(&mut iv).map_err(KeystoreError::Random)?;
+        Self::encrypt_deterministic(plaintext, passphrase, params, salt, iv)
+    }
+
+    fn encrypt_deterministic(
+        plaintext: &[u8],
+        passphrase: &str,
+        params: KdfParams,
+        salt: [u8; SALT_LEN],
+        iv: [u8; IV_LEN],
+    ) -> Result<Self, KeystoreError> {
+        let dk = derive_key(passphrase, &salt, params)?;
+        let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::from(dk));
+        let message = cipher
+            .encrypt(&Nonce::from(iv), plaintext)
+            .map_err(|_| KeystoreError::InvalidParams("aes-gcm encryption failed"))?;
+
+        Ok(KeystoreCrypto {
+            kdf: KdfModule {
+                function: KDF_FUNCTION.to_string(),
+                params: ScryptParams {
+                    dklen: DKLEN,
+                    n: 1u32 << params.log_n,
+                    r: params.r,
+                    p: params.p,
+                    salt: hex::encode(salt),
+                },
+                message: String::new(),
+            },
+            cipher: CipherModule {
+                function: CIPHER_FUNCTION.to_string(),
+                params: CipherParams { iv: hex::encode(iv) },
+                message: hex::encode(message),
+            },
+        })
+    }
+
+    /// Decrypt the bytes sealed by `encrypt`.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Vec<u8>, KeystoreError> {
+        let kdf = &self.kdf;
+        if kdf.function != KDF_FUNCTION {
+            return Err(KeystoreError::UnsupportedKdf(kdf.function.clone()));
+        }
+        let cipher_module = &self.cipher;
+        if cipher_module.function != CIPHER_FUNCTION {
+            return Err(KeystoreError::UnsupportedCipher(cipher_module.function.clone()));
+        }
+        if kdf.params.dklen != DKLEN {
+            return Err(KeystoreError::InvalidParams("dklen must be 32"));
+        }
+        if !kdf.params.n.is_power_of_two() || kdf.params.n < 2 {
+            return Err(KeystoreError::InvalidParams("scrypt n must be a power of two"));
+        }
+        let params = KdfParams {
+            log_n: kdf.params.n.trailing_zeros() as u8,
+            r: kdf.params.r,
+            p: kdf.params.p,
+        };
+
+        let salt = hex::decode(&kdf.params.salt).map_err(|_| KeystoreError::InvalidHex("salt"))?;
+        let iv = decode_fixed::<IV_LEN>(&cipher_module.params.iv, "iv")?;
+        let message = hex::decode(&cipher_module.message)
+            .map_err(|_| KeystoreError::InvalidHex("message"))?;
+
+        let dk = derive_key(passphrase, &salt, params)?;
+        let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::from(dk));
+        cipher
+            .decrypt(&Nonce::from(iv), message.as_slice())
+            .map_err(|_| KeystoreError::WrongPassphrase)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<[u8; 32], KeystoreError> {
+    let scrypt_params = scrypt::Params::new(params.log_n, params.r, params.p, DKLEN as usize)
+        .map_err(|_| KeystoreError::InvalidParams("scrypt n/r/p out of range"))?;
+    let mut dk = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &scrypt_params, &mut dk)
+        .map_err(|_| KeystoreError::InvalidParams("scrypt output length"))?;
+    Ok(dk)
+}
+
+fn decode_fixed<const N: usize>(s: &str, field: &'static str) -> Result<[u8; N], KeystoreError> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let bytes: Vec<u8> = hex::decode(s).map_err(|_| KeystoreError::InvalidHex(field))?;
+    bytes.as_slice().try_into().map_err(|_| KeystoreError::InvalidHex(field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_keystore(key: &SpendingKey) -> Keystore {
+        Keystore::encrypt_deterministic(
+            key,
+            "correct horse",
+            "test",
+            KdfParams::INSECURE_FAST,
+            [0x11; SALT_LEN],
+            [0x22; IV_LEN],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let key = SpendingKey::from([0xABu8; 32]);
+        let ks = test_keystore(&key);
+        assert_eq!(ks.pubkey().unwrap(), key.pubkey());
+
+        let json = ks.to_json().unwrap();
+        let parsed = Keystore::from_json(&json).unwrap();
+        assert_eq!(parsed.decrypt("correct horse").unwrap(), key);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let ks = test_keystore(&SpendingKey::from([0xABu8; 32]));
+        assert!(matches!(ks.decrypt("wrong"), Err(KeystoreError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn test_encrypt_bytes_roundtrip() {
+        let plaintext = b"more than one key's worth of backup material".to_vec();
+        let crypto = KeystoreCrypto::encrypt(&plaintext, "kit pass", KdfParams::INSECURE_FAST).unwrap();
+        assert_eq!(crypto.decrypt("kit pass").unwrap(), plaintext);
+        assert!(matches!(crypto.decrypt("wrong"), Err(KeystoreError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn test_pubkey_tamper_detected() {
+        let mut ks = test_keystore(&SpendingKey::from([0xABu8; 32]));
+        ks.pubkey = hex::encode([0u8; 32]);
+        assert!(matches!(ks.decrypt("correct horse"), Err(KeystoreError::PubkeyMismatch)));
+    }
+}
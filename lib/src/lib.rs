@@ -1,11 +1,111 @@
 #![no_std]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+#[cfg(not(any(feature = "full", feature = "verify-only")))]
+compile_error!("enable the `full` feature (default) or `verify-only`");
+
+#[cfg(feature = "full")]
+pub mod abort;
+#[cfg(feature = "adversarial")]
+pub mod adversarial;
+#[cfg(feature = "full")]
+pub mod association;
+#[cfg(feature = "full")]
+pub mod attest;
+#[cfg(feature = "full")]
+pub mod batch_withdraw;
+#[cfg(feature = "full")]
+pub mod cancellable;
+#[cfg(feature = "full")]
+pub mod canonical;
+#[cfg(feature = "full")]
+pub mod claim;
+#[cfg(feature = "full")]
+pub mod coin_selection;
+#[cfg(feature = "full")]
+pub mod commitment_index;
+#[cfg(feature = "full")]
+pub mod consistency;
+#[cfg(feature = "full")]
+pub mod consolidate;
+#[cfg(feature = "coverage")]
+pub mod coverage;
+#[cfg(feature = "full")]
+pub mod deposit;
+#[cfg(feature = "detection")]
+pub mod detection;
+#[cfg(feature = "full")]
+pub mod disclosure;
+#[cfg(feature = "test_vectors")]
+pub mod fixtures;
+#[cfg(feature = "full")]
+pub mod frontier;
+#[cfg(feature = "full")]
+pub mod join_split;
+#[cfg(feature = "keystore")]
+pub mod keystore;
+#[cfg(feature = "full")]
+pub mod multisig;
+#[cfg(feature = "note-bundle")]
+pub mod note_bundle;
+#[cfg(feature = "full")]
+pub mod note_encryption;
+#[cfg(feature = "full")]
+pub mod note_lifecycle;
+#[cfg(feature = "full")]
+pub mod nullifier_set;
+#[cfg(feature = "full")]
+pub mod payout;
+#[cfg(feature = "full")]
+pub mod policy;
+#[cfg(feature = "full")]
+pub mod prover_fee;
+#[cfg(feature = "full")]
+pub mod rebuild;
+#[cfg(feature = "full")]
+pub mod simulate;
+#[cfg(feature = "full")]
+pub mod snapshot;
+#[cfg(feature = "test_vectors")]
+pub mod test_vectors;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "tree-store")]
+pub mod tree_store;
+#[cfg(feature = "full")]
+pub mod witness;
+
+#[cfg(feature = "full")]
 use alloc::vec;
 use alloc::vec::Vec;
+#[cfg(feature = "full")]
+use core::fmt;
+#[cfg(feature = "full")]
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 use tiny_keccak::{Hasher, Keccak};
 
+#[cfg(feature = "full")]
+pub use cancellable::CancellableLock;
+
+// =============================================================================
+//                          VERIFY-ONLY SUBSET
+// =============================================================================
+//
+// With `default-features = false, features = ["verify-only"]` the crate
+// builds only what a light verifier needs, on tiny-keccak, subtle and
+// ed25519-dalek (for the spend-authorization key in `derive_pubkey`) alone:
+//   keccak256, hash_pair, hash_children
+//   Note (amount, pubkey, blinding), Note::commitment, Note::is_owned_by
+//   derive_pubkey, legacy_pubkey, owns_pubkey, ct_eq_32, compute_nullifier
+//   MerkleProofStep, verify_merkle_proof, verify_merkle_proof_any_root
+//   NaryProofStep, verify_nary_merkle_proof
+// These are stable: their signatures and results only change with a major
+// version. Everything else (serde, the tree, circuit inputs, builders)
+// needs `full`.
+
 // =============================================================================
 //                          KECCAK256 HELPERS
 // =============================================================================
@@ -30,6 +130,19 @@ pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
     keccak256(&data)
 }
 
+/// Hash the children of one node of an arity-N tree. Matches Solidity:
+///   keccak256(abi.encodePacked(c0, c1, ..., cN-1))
+/// For two children this is `hash_pair`.
+pub fn hash_children(children: &[[u8; 32]]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    for child in children {
+        hasher.update(child);
+    }
+    let mut output = [0u8; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
 // =============================================================================
 //                              NOTE TYPE
 // =============================================================================
@@ -38,12 +151,14 @@ pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
 ///
 /// Off-chain representation:
 ///   commitment = keccak256(amount_be_8bytes || pubkey || blinding)
-///   nullifier  = keccak256(commitment || spending_key)
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+///   nullifier  = keccak256(commitment || nullifier_key)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct Note {
     /// Token amount (e.g., USDT with 6 decimals)
     pub amount: u64,
-    /// Owner's public key: keccak256(spending_key)
+    /// Owner's public key (see `note_pubkey`)
     pub pubkey: [u8; 32],
     /// Random blinding factor for hiding
     pub blinding: [u8; 32],
@@ -61,28 +176,357 @@ impl Note {
         preimage[40..72].copy_from_slice(&self.blinding);
         keccak256(&preimage)
     }
+
+    /// Check whether `spending_key` owns this note, in constant time.
+    pub fn is_owned_by(&self, spending_key: &[u8; 32]) -> bool {
+        owns_pubkey(spending_key, &self.pubkey)
+    }
+
+    /// A dummy input: zero amount, owned by `DUMMY_SPENDING_KEY`, in no
+    /// tree. `blinding` must be fresh randomness, or the dummy's nullifier
+    /// repeats and the pool rejects the second spend.
+    #[cfg(feature = "full")]
+    pub fn dummy(blinding: [u8; 32]) -> Self {
+        Note { amount: 0, pubkey: derive_pubkey(&DUMMY_SPENDING_KEY), blinding }
+    }
 }
 
+/// Spending key of dummy inputs. It is public: a dummy holds no value, so
+/// there is nothing for its key to protect.
+#[cfg(feature = "full")]
+pub const DUMMY_SPENDING_KEY: [u8; 32] = *b"shielded-pool/dummy-spending-key";
+
 // =============================================================================
 //                          KEY DERIVATION
 // =============================================================================
 
+/// Domain of the nullifier key, so it is never the hash of anything else
+/// derived from a spending key (see `derive_nullifier_key`).
+pub const NULLIFIER_KEY_DOMAIN: [u8; 32] = *b"shielded-pool/nullifier-key/1\0\0\0";
+
+/// Domain of the spend-authorization key (see `derive_spend_auth_key`).
+pub const SPEND_AUTH_KEY_DOMAIN: [u8; 32] = *b"shielded-pool/spend-auth-key/1\0\0";
+
+/// Domain of note pubkeys, so no pubkey is the hash of a bare 32-byte key
+/// like a legacy one (see `legacy_pubkey`).
+pub const PUBKEY_DOMAIN: [u8; 32] = *b"shielded-pool/note-pubkey/2\0\0\0\0\0";
+
+/// Derive the nullifier key from a spending key.
+/// nullifier_key = keccak256(NULLIFIER_KEY_DOMAIN || spending_key)
+///
+/// It derives the nullifiers of the key's notes. A delegated prover is given
+/// it as half of the `ProvingKey`; the spending key stays on the user's
+/// device with everything else it derives (the spend-authorization key, the
+/// viewing key, the keystore).
+pub fn derive_nullifier_key(spending_key: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = [0u8; 64];
+    preimage[0..32].copy_from_slice(&NULLIFIER_KEY_DOMAIN);
+    preimage[32..64].copy_from_slice(spending_key);
+    keccak256(&preimage)
+}
+
+/// Derive the spend-authorization key from a spending key: the Ed25519
+/// secret key (RFC 8032 seed) that signs every transfer and withdrawal.
+/// spend_auth_key = keccak256(SPEND_AUTH_KEY_DOMAIN || spending_key)
+pub fn derive_spend_auth_key(spending_key: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = [0u8; 64];
+    preimage[0..32].copy_from_slice(&SPEND_AUTH_KEY_DOMAIN);
+    preimage[32..64].copy_from_slice(spending_key);
+    keccak256(&preimage)
+}
+
+/// The Ed25519 public key of a spend-authorization key, which spend
+/// signatures verify under.
+pub fn spend_auth_pubkey(spend_auth_key: &[u8; 32]) -> [u8; 32] {
+    ed25519_dalek::SigningKey::from_bytes(spend_auth_key).verifying_key().to_bytes()
+}
+
+/// The note pubkey of a spend-authorization public key and a nullifier key.
+/// pubkey = keccak256(PUBKEY_DOMAIN || auth_key || nullifier_key)
+pub fn note_pubkey(auth_key: &[u8; 32], nullifier_key: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = [0u8; 96];
+    preimage[0..32].copy_from_slice(&PUBKEY_DOMAIN);
+    preimage[32..64].copy_from_slice(auth_key);
+    preimage[64..96].copy_from_slice(nullifier_key);
+    keccak256(&preimage)
+}
+
 /// Derive the public key from a spending key.
-/// pubkey = keccak256(spending_key)
+/// pubkey = note_pubkey(spend_auth_pubkey(derive_spend_auth_key(spending_key)), derive_nullifier_key(spending_key))
 pub fn derive_pubkey(spending_key: &[u8; 32]) -> [u8; 32] {
+    note_pubkey(&spend_auth_pubkey(&derive_spend_auth_key(spending_key)), &derive_nullifier_key(spending_key))
+}
+
+/// The pubkey notes had before spend-authorization and nullifier keys.
+/// pubkey = keccak256(spending_key)
+///
+/// Their nullifier was keccak256(commitment || spending_key), which is
+/// `nullifier_from_key` with the spending key in place of the nullifier key.
+/// The transfer and withdraw circuits still spend them (see `SpenderKey`).
+pub fn legacy_pubkey(spending_key: &[u8; 32]) -> [u8; 32] {
     keccak256(spending_key)
 }
 
+/// Constant-time equality for 32-byte values.
+/// Use this instead of `==` whenever either side is a secret or derived from one.
+pub fn ct_eq_32(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.ct_eq(b).into()
+}
+
+/// Check whether `spending_key` derives `pubkey`, in constant time.
+pub fn owns_pubkey(spending_key: &[u8; 32], pubkey: &[u8; 32]) -> bool {
+    ct_eq_32(&derive_pubkey(spending_key), pubkey)
+}
+
+/// A 32-byte spending key.
+///
+/// Equality is constant-time and `Debug` never prints the key material.
+/// Serializes exactly like the raw `[u8; 32]` it wraps.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SpendingKey(pub [u8; 32]);
+
+#[cfg(feature = "full")]
+impl SpendingKey {
+    /// Derive the public key (see `derive_pubkey`)
+    pub fn pubkey(&self) -> [u8; 32] {
+        derive_pubkey(&self.0)
+    }
+
+    /// The key a delegated prover is given (see `ProvingKey`)
+    pub fn proving_key(&self) -> ProvingKey {
+        ProvingKey::derive(&self.0)
+    }
+
+    /// Check whether this key derives `pubkey`, in constant time.
+    pub fn owns(&self, pubkey: &[u8; 32]) -> bool {
+        owns_pubkey(&self.0, pubkey)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "full")]
+impl From<[u8; 32]> for SpendingKey {
+    fn from(bytes: [u8; 32]) -> Self {
+        SpendingKey(bytes)
+    }
+}
+
+#[cfg(feature = "full")]
+impl ConstantTimeEq for SpendingKey {
+    fn ct_eq(&self, other: &Self) -> subtle::Choice {
+        self.0.ct_eq(&other.0)
+    }
+}
+
+#[cfg(feature = "full")]
+impl PartialEq for SpendingKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.ct_eq(other).into()
+    }
+}
+
+#[cfg(feature = "full")]
+impl Eq for SpendingKey {}
+
+#[cfg(feature = "full")]
+impl fmt::Debug for SpendingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SpendingKey(<redacted>)")
+    }
+}
+
+/// What a delegated prover is given to prove transfers and withdrawals of a
+/// key's notes: the spend-authorization public key and the nullifier key.
+/// Together they open the notes' pubkey and derive their nullifiers, but
+/// each spend also needs a `SpendSignature` by the spend-authorization key,
+/// which stays on the user's device. The prover learns which notes are
+/// spent; it cannot spend them or change where a spend sends the funds.
+///
+/// Only the transfer and withdraw circuits take it. The other circuits take
+/// the spending key itself, so they are proven on the owner's device.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct ProvingKey {
+    /// Ed25519 public key the spend signatures verify under (see
+    /// `spend_auth_pubkey`)
+    pub auth_key: [u8; 32],
+    /// See `derive_nullifier_key`
+    pub nullifier_key: [u8; 32],
+}
+
+#[cfg(feature = "full")]
+impl ProvingKey {
+    /// The proving key of `spending_key`.
+    pub fn derive(spending_key: &[u8; 32]) -> Self {
+        ProvingKey {
+            auth_key: spend_auth_pubkey(&derive_spend_auth_key(spending_key)),
+            nullifier_key: derive_nullifier_key(spending_key),
+        }
+    }
+
+    /// The pubkey of the notes it opens (see `note_pubkey`).
+    pub fn pubkey(&self) -> [u8; 32] {
+        note_pubkey(&self.auth_key, &self.nullifier_key)
+    }
+}
+
+/// The key a transfer or withdrawal input is spent with.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub enum SpenderKey {
+    /// The owner's proving key; the spend must also be signed under its
+    /// `auth_key`.
+    Proving(ProvingKey),
+    /// The spending key of a note from before proving keys (see
+    /// `legacy_pubkey`). It is full spend authority, so it needs no signature
+    /// and must not leave the owner's device: spend legacy notes there, into
+    /// notes under the same key's current pubkey.
+    Legacy([u8; 32]),
+}
+
+#[cfg(feature = "full")]
+impl SpenderKey {
+    /// The proving key of `spending_key`.
+    pub fn derive(spending_key: &[u8; 32]) -> Self {
+        SpenderKey::Proving(ProvingKey::derive(spending_key))
+    }
+
+    /// The pubkey it opens.
+    pub fn pubkey(&self) -> [u8; 32] {
+        match self {
+            SpenderKey::Proving(key) => key.pubkey(),
+            SpenderKey::Legacy(spending_key) => legacy_pubkey(spending_key),
+        }
+    }
+
+    /// What its notes' nullifiers derive from: the nullifier key, or a
+    /// legacy note's spending key.
+    pub fn nullifier_key(&self) -> &[u8; 32] {
+        match self {
+            SpenderKey::Proving(key) => &key.nullifier_key,
+            SpenderKey::Legacy(spending_key) => spending_key,
+        }
+    }
+
+    /// The public key the spend must be signed under; None for a legacy key.
+    pub fn auth_key(&self) -> Option<[u8; 32]> {
+        match self {
+            SpenderKey::Proving(key) => Some(key.auth_key),
+            SpenderKey::Legacy(_) => None,
+        }
+    }
+
+    /// Check whether it opens `pubkey`, in constant time.
+    pub fn owns(&self, pubkey: &[u8; 32]) -> bool {
+        ct_eq_32(&self.pubkey(), pubkey)
+    }
+
+    /// Whether a change note with `pubkey` goes back to this spender. A
+    /// legacy key's change may also go to its current pubkey, so a partial
+    /// withdrawal moves the rest of a legacy note onto current keys.
+    pub fn owns_change(&self, pubkey: &[u8; 32]) -> bool {
+        match self {
+            SpenderKey::Proving(_) => self.owns(pubkey),
+            SpenderKey::Legacy(spending_key) => self.owns(pubkey) || owns_pubkey(spending_key, pubkey),
+        }
+    }
+}
+
+/// Domain of the message spend signatures sign (see `spend_auth_message`).
+#[cfg(feature = "full")]
+pub const SPEND_AUTH_MESSAGE_DOMAIN: [u8; 32] = *b"shielded-pool/spend-auth-msg/1\0\0";
+
+/// What the owners of a transfer's or withdrawal's inputs sign: everything
+/// the proof commits, so whoever holds the proving keys can neither spend
+/// without the owners nor change the outputs, recipient, fee or pool.
+///   message = keccak256(SPEND_AUTH_MESSAGE_DOMAIN || circuit (1 byte) || public values)
+/// The circuit byte is the `CircuitKind` discriminant.
+#[cfg(feature = "full")]
+pub fn spend_auth_message(kind: CircuitKind, public_values: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    hasher.update(&SPEND_AUTH_MESSAGE_DOMAIN);
+    hasher.update(&[kind as u8]);
+    hasher.update(public_values);
+    let mut output = [0u8; 32];
+    hasher.finalize(&mut output);
+    output
+}
+
+/// An Ed25519 signature of a `spend_auth_message` by a spend-authorization
+/// key.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct SpendSignature {
+    /// The public key it verifies under (a `ProvingKey::auth_key`)
+    pub auth_key: [u8; 32],
+    /// First half of the signature (the point R)
+    pub r: [u8; 32],
+    /// Second half of the signature (the scalar s)
+    pub s: [u8; 32],
+}
+
+#[cfg(feature = "full")]
+impl SpendSignature {
+    /// Sign `message` with the spend-authorization key of `spending_key`.
+    pub fn sign(spending_key: &[u8; 32], message: &[u8; 32]) -> Self {
+        use ed25519_dalek::Signer;
+        let key = ed25519_dalek::SigningKey::from_bytes(&derive_spend_auth_key(spending_key));
+        let signature = key.sign(message);
+        SpendSignature { auth_key: key.verifying_key().to_bytes(), r: *signature.r_bytes(), s: *signature.s_bytes() }
+    }
+
+    /// Whether it is a valid signature of `message` (RFC 8032, strict: no
+    /// small-order keys, no malleable signatures).
+    pub fn verify(&self, message: &[u8; 32]) -> bool {
+        let Ok(key) = ed25519_dalek::VerifyingKey::from_bytes(&self.auth_key) else {
+            return false;
+        };
+        key.verify_strict(message, &ed25519_dalek::Signature::from_components(self.r, self.s)).is_ok()
+    }
+}
+
+/// Check that each of `signers` signed `message` among `signatures`.
+/// Signatures under other keys are ignored.
+#[cfg(feature = "full")]
+pub fn check_spend_signatures(
+    signers: impl IntoIterator<Item = [u8; 32]>,
+    signatures: &[SpendSignature],
+    message: &[u8; 32],
+) -> Result<(), &'static str> {
+    for signer in signers {
+        match signatures.iter().find(|s| s.auth_key == signer) {
+            None => return Err("missing spend authorization signature"),
+            Some(signature) if !signature.verify(message) => return Err("invalid spend authorization signature"),
+            Some(_) => {}
+        }
+    }
+    Ok(())
+}
+
 // =============================================================================
 //                           NULLIFIER
 // =============================================================================
 
-/// Compute the nullifier for a note.
-/// nullifier = keccak256(commitment || spending_key)
+/// Compute the nullifier for a note spent by `spending_key`.
+/// nullifier = keccak256(commitment || derive_nullifier_key(spending_key))
 pub fn compute_nullifier(commitment: &[u8; 32], spending_key: &[u8; 32]) -> [u8; 32] {
+    nullifier_from_key(commitment, &derive_nullifier_key(spending_key))
+}
+
+/// Compute the nullifier for a note from its owner's nullifier key.
+/// nullifier = keccak256(commitment || nullifier_key)
+pub fn nullifier_from_key(commitment: &[u8; 32], nullifier_key: &[u8; 32]) -> [u8; 32] {
     let mut preimage = [0u8; 64];
     preimage[0..32].copy_from_slice(commitment);
-    preimage[32..64].copy_from_slice(spending_key);
+    preimage[32..64].copy_from_slice(nullifier_key);
     keccak256(&preimage)
 }
 
@@ -91,7 +535,9 @@ pub fn compute_nullifier(commitment: &[u8; 32], spending_key: &[u8; 32]) -> [u8;
 // =============================================================================
 
 /// A single step in a Merkle proof.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
 pub struct MerkleProofStep {
     /// true if the current node is the LEFT child (index even at this level).
     /// When is_left=true:  parent = hash(current, sibling)
@@ -110,6 +556,41 @@ pub fn verify_merkle_proof(
     proof: &[MerkleProofStep],
     expected_root: [u8; 32],
 ) -> bool {
+    compute_merkle_root(leaf, proof) == expected_root
+}
+
+/// Verify a Merkle proof against any of `roots`, e.g. the window returned by
+/// `IncrementalMerkleTree::recent_roots`. This is the check the pool makes
+/// (`isKnownRoot`), so a proof that passes it is still accepted on-chain as
+/// long as its root stays in the window. The zero root never matches.
+pub fn verify_merkle_proof_any_root(leaf: [u8; 32], proof: &[MerkleProofStep], roots: &[[u8; 32]]) -> bool {
+    let root = compute_merkle_root(leaf, proof);
+    root != [0u8; 32] && roots.contains(&root)
+}
+
+/// The common depth of the Merkle proofs of the inputs that need one (those
+/// with a non-zero amount), or 0 if none does. The circuits commit it and the
+/// pool requires its tree's depth, so a shorter proof that happens to hash to
+/// a known root is not accepted.
+pub fn spent_proof_levels<'a>(
+    inputs: impl IntoIterator<Item = (&'a Note, &'a Vec<MerkleProofStep>)>,
+) -> Result<u64, &'static str> {
+    proof_levels(inputs.into_iter().filter(|(note, _)| note.amount != 0).map(|(_, proof)| proof))
+}
+
+/// The common depth of `proofs`, or 0 if there are none; for the circuits
+/// that check every input's membership (see `spent_proof_levels`).
+pub fn proof_levels<'a>(proofs: impl IntoIterator<Item = &'a Vec<MerkleProofStep>>) -> Result<u64, &'static str> {
+    let mut levels = None;
+    for proof in proofs {
+        if *levels.get_or_insert(proof.len()) != proof.len() {
+            return Err("Merkle proofs differ in depth");
+        }
+    }
+    Ok(levels.unwrap_or(0) as u64)
+}
+
+fn compute_merkle_root(leaf: [u8; 32], proof: &[MerkleProofStep]) -> [u8; 32] {
     let mut current = leaf;
     for step in proof {
         if step.is_left {
@@ -120,19 +601,203 @@ pub fn verify_merkle_proof(
             current = hash_pair(&step.sibling, &current);
         }
     }
+    current
+}
+
+/// One level of a proof from a tree of any arity (`get_nary_proof`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(Serialize, Deserialize))]
+pub struct NaryProofStep {
+    /// Position of the current node among its parent's children (0..ARITY)
+    pub position: u8,
+    /// The other ARITY - 1 children, in order, skipping `position`
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Verify a proof from an `IncrementalMerkleTree<ARITY>`. At each level the
+/// current node is put back at `position` among the siblings and the
+/// children are hashed with `hash_children`.
+pub fn verify_nary_merkle_proof<const ARITY: usize>(
+    leaf: [u8; 32],
+    proof: &[NaryProofStep],
+    expected_root: [u8; 32],
+) -> bool {
+    let mut current = leaf;
+    for step in proof {
+        let position = step.position as usize;
+        if position >= ARITY || step.siblings.len() != ARITY - 1 {
+            return false;
+        }
+        let mut children = [[0u8; 32]; ARITY];
+        children[..position].copy_from_slice(&step.siblings[..position]);
+        children[position] = current;
+        children[position + 1..].copy_from_slice(&step.siblings[position..]);
+        current = hash_children(&children);
+    }
     current == expected_root
 }
 
+/// Roots of a circular history buffer, newest first, skipping unused slots.
+#[cfg(feature = "full")]
+fn recent_roots(roots: &[[u8; 32]], current_root_index: usize) -> Vec<[u8; 32]> {
+    (0..roots.len())
+        .map(|age| roots[(current_root_index + roots.len() - age) % roots.len()])
+        .filter(|root| *root != [0u8; 32])
+        .collect()
+}
+
+/// Single-leaf Merkle proof in the layout Solidity verifiers usually take,
+/// `(bytes32[] siblings, uint256 pathBits)`: bit `h` of `path_bits` is set
+/// when the path node at height `h` is a right child (`is_left == false`),
+/// so for a proof from `get_proof` it equals the leaf index.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub path_bits: u32,
+    /// Siblings from the leaf up
+    pub siblings: Vec<[u8; 32]>,
+}
+
+#[cfg(feature = "full")]
+impl MerkleProof {
+    /// Pack a proof; fails past 32 levels, like `verify_merkle_multiproof`.
+    pub fn from_steps(steps: &[MerkleProofStep]) -> Result<Self, &'static str> {
+        if steps.len() > 32 {
+            return Err("Merkle proof deeper than 32 levels");
+        }
+        let path_bits = steps
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (height, step)| bits | ((!step.is_left as u32) << height));
+        Ok(MerkleProof { path_bits, siblings: steps.iter().map(|step| step.sibling).collect() })
+    }
+
+    pub fn steps(&self) -> Vec<MerkleProofStep> {
+        self.siblings
+            .iter()
+            .enumerate()
+            .map(|(height, sibling)| MerkleProofStep { is_left: (self.path_bits >> height) & 1 == 0, sibling: *sibling })
+            .collect()
+    }
+
+    /// pathBits as a 32-byte big-endian word, then the siblings: the
+    /// abi.encodePacked form of `(uint256, bytes32[])`, 32 bytes per level
+    /// plus one word.
+    pub fn to_packed_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(32 * (self.siblings.len() + 1));
+        out.extend_from_slice(&[0u8; 28]);
+        out.extend_from_slice(&self.path_bits.to_be_bytes());
+        for sibling in &self.siblings {
+            out.extend_from_slice(sibling);
+        }
+        out
+    }
+
+    /// Inverse of `to_packed_bytes`. Rejects path bits above the proof's depth.
+    pub fn from_packed_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() < 32 || !bytes.len().is_multiple_of(32) {
+            return Err("packed Merkle proof is not a whole number of words");
+        }
+        let levels = bytes.len() / 32 - 1;
+        if levels > 32 {
+            return Err("Merkle proof deeper than 32 levels");
+        }
+        if bytes[..28].iter().any(|&b| b != 0) {
+            return Err("path bits above the proof's depth");
+        }
+        let path_bits = u32::from_be_bytes(bytes[28..32].try_into().unwrap());
+        if levels < 32 && path_bits >> levels != 0 {
+            return Err("path bits above the proof's depth");
+        }
+        let siblings = bytes[32..].chunks_exact(32).map(|chunk| chunk.try_into().unwrap()).collect();
+        Ok(MerkleProof { path_bits, siblings })
+    }
+}
+
+/// Proof for several leaves against one root.
+///
+/// The paths are walked together, level by level: a node whose sibling is on
+/// another proven path is hashed from it instead of being sent, and siblings
+/// shared by several paths are sent once.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MerkleMultiProof {
+    /// Proven leaf indices, strictly increasing
+    pub leaf_indices: Vec<u32>,
+    /// Tree depth
+    pub levels: usize,
+    /// Siblings in the order the verifier needs them: by height from the
+    /// leaves up, left to right within a height
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Verify a multiproof. `leaves` are in the order of `proof.leaf_indices`.
+#[cfg(feature = "full")]
+pub fn verify_merkle_multiproof(
+    leaves: &[[u8; 32]],
+    proof: &MerkleMultiProof,
+    expected_root: [u8; 32],
+) -> bool {
+    let indices = &proof.leaf_indices;
+    if leaves.is_empty()
+        || leaves.len() != indices.len()
+        || proof.levels > 32
+        || indices.windows(2).any(|w| w[0] >= w[1])
+        || indices[indices.len() - 1] as u64 >= 1u64 << proof.levels
+    {
+        return false;
+    }
+
+    let mut current: Vec<(u32, [u8; 32])> = indices.iter().copied().zip(leaves.iter().copied()).collect();
+    let mut siblings = proof.siblings.iter();
+    for _ in 0..proof.levels {
+        let mut next = Vec::with_capacity(current.len());
+        let mut i = 0;
+        while i < current.len() {
+            let (index, node) = current[i];
+            let parent = match current.get(i + 1) {
+                // Both children are proven
+                Some(&(right_index, right)) if index % 2 == 0 && right_index == index + 1 => {
+                    i += 1;
+                    hash_pair(&node, &right)
+                }
+                _ => {
+                    let Some(sibling) = siblings.next() else {
+                        return false;
+                    };
+                    if index % 2 == 0 {
+                        hash_pair(&node, sibling)
+                    } else {
+                        hash_pair(sibling, &node)
+                    }
+                }
+            };
+            next.push((index / 2, parent));
+            i += 1;
+        }
+        current = next;
+    }
+    siblings.next().is_none() && current == [(0, expected_root)]
+}
+
 /// Compute the zero values for each level of the Merkle tree.
 /// Matches MerkleTree.sol constructor logic:
 ///   zeros[0] = keccak256(abi.encodePacked(bytes32(0)))  // keccak256 of 32 zero bytes
 ///   zeros[i] = keccak256(abi.encodePacked(zeros[i-1], zeros[i-1]))
+#[cfg(feature = "full")]
 pub fn compute_zeros(levels: usize) -> Vec<[u8; 32]> {
+    compute_nary_zeros::<2>(levels)
+}
+
+/// Zero values for a tree of any arity: zeros[0] as in `compute_zeros`, and
+/// zeros[i] the hash of ARITY copies of zeros[i-1].
+#[cfg(feature = "full")]
+pub fn compute_nary_zeros<const ARITY: usize>(levels: usize) -> Vec<[u8; 32]> {
     let mut zeros = vec![[0u8; 32]; levels];
     // zeros[0] = keccak256(bytes32(0)) where bytes32(0) is 32 zero bytes
     zeros[0] = keccak256(&[0u8; 32]);
     for i in 1..levels {
-        zeros[i] = hash_pair(&zeros[i - 1], &zeros[i - 1]);
+        zeros[i] = hash_children(&[zeros[i - 1]; ARITY]);
     }
     zeros
 }
@@ -140,21 +805,40 @@ pub fn compute_zeros(levels: usize) -> Vec<[u8; 32]> {
 /// Compute the initial root of an empty tree with the given number of levels.
 /// Matches MerkleTree.sol: roots[0] = _hashPair(currentZero, currentZero)
 /// where currentZero is zeros[levels-1].
+#[cfg(feature = "full")]
 pub fn compute_empty_root(levels: usize) -> [u8; 32] {
     let zeros = compute_zeros(levels);
     hash_pair(&zeros[levels - 1], &zeros[levels - 1])
 }
 
+/// Number of leaves a tree with the given number of levels holds (2^levels).
+#[cfg(feature = "full")]
+pub fn tree_capacity(levels: usize) -> u64 {
+    1u64 << levels
+}
+
 // =============================================================================
 //                      CLIENT-SIDE MERKLE TREE
 // =============================================================================
 
 /// An incremental Merkle tree that mirrors MerkleTree.sol exactly.
 /// Used by the client to track on-chain state and generate proofs.
-#[derive(Clone, Debug)]
-pub struct IncrementalMerkleTree {
+/// See `snapshot` for saving and restoring it.
+///
+/// `ARITY` is the number of children per node. The pool is binary (the
+/// default); `QuaternaryMerkleTree` mirrors an arity-4 pool, which hashes
+/// four children at a time (`hash_children`) for fewer levels and so fewer
+/// hashes per insert. Pair inserts, binary proofs, rebuilds and the binary
+/// snapshot format are binary-only; other arities prove with
+/// `get_nary_proof`.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "snapshot::TreeParts")]
+pub struct IncrementalMerkleTree<const ARITY: usize = 2> {
     pub levels: usize,
     pub zeros: Vec<[u8; 32]>,
+    /// Left siblings of the next insert's path: `ARITY - 1` per level, so
+    /// `filled_subtrees[h * (ARITY - 1) + j]` is child `j` at height `h`
     pub filled_subtrees: Vec<[u8; 32]>,
     pub next_index: u32,
     /// Circular buffer of recent roots (matches ROOT_HISTORY_SIZE = 30)
@@ -162,19 +846,52 @@ pub struct IncrementalMerkleTree {
     pub current_root_index: usize,
     /// All inserted leaves in order
     pub leaves: Vec<[u8; 32]>,
+    /// Interior nodes with at least one inserted leaf below them:
+    /// `nodes[h - 1][i]` is node `i` at height `h` (1..levels). Absent nodes
+    /// are `zeros[h]`. Filled in by `insert` so proofs take O(levels).
+    nodes: Vec<Vec<[u8; 32]>>,
+    /// States `rollback` can return to (not part of snapshots)
+    #[serde(skip)]
+    checkpoints: Vec<SavedState>,
 }
 
-const ROOT_HISTORY_SIZE: usize = 30;
+/// Arity-4 tree, for a pool contract that hashes four children per node.
+#[cfg(feature = "full")]
+pub type QuaternaryMerkleTree = IncrementalMerkleTree<4>;
 
-impl IncrementalMerkleTree {
-    /// Create a new empty tree. Matches MerkleTree.sol constructor.
-    pub fn new(levels: usize) -> Self {
-        let zeros = compute_zeros(levels);
-        let filled_subtrees = zeros.clone();
+/// Handle to a tree state saved by `IncrementalMerkleTree::checkpoint`.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckpointId(usize);
+
+/// Everything an insert changes besides the appended leaf and nodes.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug)]
+struct SavedState {
+    next_index: u32,
+    current_root_index: usize,
+    roots: Vec<[u8; 32]>,
+    filled_subtrees: Vec<[u8; 32]>,
+    /// Last cached node per height; later inserts may overwrite it
+    last_nodes: Vec<Option<[u8; 32]>>,
+}
+
+/// Number of recent roots the pool accepts proofs against (MerkleTree.sol ROOT_HISTORY_SIZE).
+#[cfg(feature = "full")]
+pub const ROOT_HISTORY_SIZE: usize = 30;
+
+#[cfg(feature = "full")]
+impl<const ARITY: usize> IncrementalMerkleTree<ARITY> {
+    /// Create a new empty tree of this arity. Matches MerkleTree.sol
+    /// constructor for arity 2.
+    pub fn with_levels(levels: usize) -> Self {
+        assert!(ARITY >= 2, "a Merkle tree needs at least two children per node");
+        let zeros = compute_nary_zeros::<ARITY>(levels);
+        let filled_subtrees = zeros.iter().flat_map(|zero| core::iter::repeat_n(*zero, ARITY - 1)).collect();
 
         let mut roots = vec![[0u8; 32]; ROOT_HISTORY_SIZE];
-        // Initial root = hash of (zeros[levels-1], zeros[levels-1])
-        roots[0] = hash_pair(&zeros[levels - 1], &zeros[levels - 1]);
+        // Initial root = hash of ARITY copies of zeros[levels-1]
+        roots[0] = hash_children(&[zeros[levels - 1]; ARITY]);
 
         IncrementalMerkleTree {
             levels,
@@ -184,46 +901,123 @@ impl IncrementalMerkleTree {
             roots,
             current_root_index: 0,
             leaves: Vec::new(),
+            nodes: vec![Vec::new(); levels.saturating_sub(1)],
+            checkpoints: Vec::new(),
         }
     }
 
+    /// Number of leaves the tree holds when full (ARITY^levels).
+    pub fn capacity(&self) -> u64 {
+        (ARITY as u64).checked_pow(self.levels as u32).unwrap_or(u64::MAX)
+    }
+
+    /// Number of leaves that can still be inserted.
+    pub fn remaining_capacity(&self) -> u64 {
+        self.capacity().saturating_sub(self.next_index as u64)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.remaining_capacity() == 0
+    }
+
     /// Insert a leaf into the tree. Returns the leaf index.
     /// Matches MerkleTree.sol _insert() exactly.
+    ///
+    /// Panics if the tree is full; see `try_insert`.
     pub fn insert(&mut self, leaf: [u8; 32]) -> u32 {
+        self.try_insert(leaf).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Insert a leaf, or fail without changing the tree if it is full.
+    pub fn try_insert(&mut self, leaf: [u8; 32]) -> Result<u32, &'static str> {
+        if self.is_full() {
+            return Err("Merkle tree is full");
+        }
         let index = self.next_index;
-        assert!(
-            (index as u64) < (1u64 << self.levels),
-            "Merkle tree is full"
-        );
 
+        self.leaves.push(leaf);
+        let root = self.hash_up(0, index, leaf);
+        self.push_root(root);
+        self.next_index = index + 1;
+
+        Ok(index)
+    }
+
+    /// Hash `node` (at position `index` of `height`) up to the root, updating
+    /// filled subtrees and cached nodes along the way. Returns the root.
+    fn hash_up(&mut self, height: usize, index: u32, node: [u8; 32]) -> [u8; 32] {
         let mut current_index = index;
-        let mut current_hash = leaf;
-
-        for i in 0..self.levels {
-            if current_index % 2 == 0 {
-                // Left child: pair with zero on the right
-                let left = current_hash;
-                let right = self.zeros[i];
-                self.filled_subtrees[i] = current_hash;
-                current_hash = hash_pair(&left, &right);
-            } else {
-                // Right child: pair with filled subtree on the left
-                let left = self.filled_subtrees[i];
-                let right = current_hash;
-                current_hash = hash_pair(&left, &right);
+        let mut current_hash = node;
+
+        for i in height..self.levels {
+            // Children to the left are filled subtrees, those to the right zero
+            let position = current_index as usize % ARITY;
+            let filled = &mut self.filled_subtrees[i * (ARITY - 1)..(i + 1) * (ARITY - 1)];
+            let mut children = [self.zeros[i]; ARITY];
+            children[..position].copy_from_slice(&filled[..position]);
+            children[position] = current_hash;
+            if position < ARITY - 1 {
+                filled[position] = current_hash;
             }
-            current_index /= 2;
+            current_hash = hash_children(&children);
+            current_index /= ARITY as u32;
+            if i + 1 < self.levels {
+                self.cache_node(i + 1, current_index, current_hash);
+            }
+        }
+        current_hash
+    }
+
+    /// Store node `index` at `height` (1..levels) for later proofs.
+    fn cache_node(&mut self, height: usize, index: u32, node: [u8; 32]) {
+        let stored = &mut self.nodes[height - 1];
+        let pos = index as usize;
+        if pos < stored.len() {
+            stored[pos] = node;
+        } else {
+            stored.push(node);
         }
+    }
 
-        // Update root in circular buffer
+    /// Update root in circular buffer
+    fn push_root(&mut self, root: [u8; 32]) {
         let new_root_index = (self.current_root_index + 1) % ROOT_HISTORY_SIZE;
         self.current_root_index = new_root_index;
-        self.roots[new_root_index] = current_hash;
+        self.roots[new_root_index] = root;
+    }
 
-        self.next_index = index + 1;
-        self.leaves.push(leaf);
+    /// Remember the current state so a later `rollback` can undo inserts
+    /// made after it (e.g. leaves from blocks that were reorged away).
+    pub fn checkpoint(&mut self) -> CheckpointId {
+        self.checkpoints.push(SavedState {
+            next_index: self.next_index,
+            current_root_index: self.current_root_index,
+            roots: self.roots.clone(),
+            filled_subtrees: self.filled_subtrees.clone(),
+            last_nodes: self.nodes.iter().map(|stored| stored.last().copied()).collect(),
+        });
+        CheckpointId(self.checkpoints.len() - 1)
+    }
 
-        index
+    /// Undo every insert made since `id` was taken. Checkpoints taken after
+    /// `id` are discarded; `id` itself stays valid.
+    pub fn rollback(&mut self, id: CheckpointId) -> Result<(), &'static str> {
+        let saved = self.checkpoints.get(id.0).ok_or("unknown checkpoint")?.clone();
+        self.checkpoints.truncate(id.0 + 1);
+
+        let n = saved.next_index as usize;
+        self.leaves.truncate(n);
+        for (height, (stored, last)) in (1..).zip(self.nodes.iter_mut().zip(saved.last_nodes)) {
+            stored.truncate(n.div_ceil(ARITY.saturating_pow(height)));
+            if let (Some(node), Some(slot)) = (last, stored.last_mut()) {
+                *slot = node;
+            }
+        }
+        self.next_index = saved.next_index;
+        self.current_root_index = saved.current_root_index;
+        self.roots = saved.roots;
+        self.filled_subtrees = saved.filled_subtrees;
+        Ok(())
     }
 
     /// Get the most recent root.
@@ -252,79 +1046,510 @@ impl IncrementalMerkleTree {
         false
     }
 
-    /// Generate a Merkle proof for the leaf at the given index.
-    ///
-    /// This rebuilds the tree to compute sibling hashes at each level.
-    /// For a hackathon this is fine; production code would cache the tree.
-    pub fn get_proof(&self, leaf_index: u32) -> Vec<MerkleProofStep> {
-        assert!(
-            (leaf_index as usize) < self.leaves.len(),
-            "leaf index out of range"
-        );
+    /// The roots `is_known_root` accepts, newest first: the window a proof
+    /// must land in to be accepted on-chain (at most ROOT_HISTORY_SIZE).
+    pub fn recent_roots(&self) -> Vec<[u8; 32]> {
+        recent_roots(&self.roots, self.current_root_index)
+    }
 
-        // Rebuild tree level by level
-        let num_leaves = 1usize << self.levels;
-        let mut current_level: Vec<[u8; 32]> = Vec::with_capacity(num_leaves);
+    /// Proof for the leaf at `leaf_index` in the format of any arity; see
+    /// `verify_nary_merkle_proof`.
+    ///
+    /// Panics if no leaf has been inserted at `leaf_index`; see
+    /// `try_get_nary_proof`.
+    pub fn get_nary_proof(&self, leaf_index: u32) -> Vec<NaryProofStep> {
+        self.try_get_nary_proof(leaf_index).unwrap_or_else(|e| panic!("{e}"))
+    }
 
-        // Fill in inserted leaves, pad rest with zeros[0]
-        for i in 0..num_leaves {
-            if i < self.leaves.len() {
-                current_level.push(self.leaves[i]);
-            } else {
-                current_level.push(self.zeros[0]);
-            }
+    /// Proof for the leaf at `leaf_index`, or an error if no leaf has been
+    /// inserted there.
+    pub fn try_get_nary_proof(&self, leaf_index: u32) -> Result<Vec<NaryProofStep>, &'static str> {
+        if leaf_index as usize >= self.leaves.len() {
+            return Err("leaf index out of range");
         }
 
-        let mut proof = Vec::with_capacity(self.levels);
         let mut idx = leaf_index as usize;
+        Ok((0..self.levels)
+            .map(|height| {
+                let first = idx - idx % ARITY;
+                let step = NaryProofStep {
+                    position: (idx % ARITY) as u8,
+                    siblings: (first..first + ARITY).filter(|&i| i != idx).map(|i| self.node(height, i)).collect(),
+                };
+                idx /= ARITY;
+                step
+            })
+            .collect())
+    }
 
-        for _level in 0..self.levels {
-            let sibling_idx = idx ^ 1;
-            let sibling = current_level[sibling_idx];
-            let is_left = idx % 2 == 0;
-
-            proof.push(MerkleProofStep { is_left, sibling });
+    /// Leaf index of `commitment`, if it has been inserted (the first
+    /// occurrence if it was inserted more than once). A scan over the leaves.
+    pub fn index_of(&self, commitment: &[u8; 32]) -> Option<u32> {
+        self.leaves.iter().position(|leaf| leaf == commitment).map(|i| i as u32)
+    }
 
-            // Compute next level
-            let next_len = current_level.len() / 2;
-            let mut next_level = Vec::with_capacity(next_len);
-            for j in 0..next_len {
-                next_level.push(hash_pair(&current_level[2 * j], &current_level[2 * j + 1]));
-            }
-            current_level = next_level;
-            idx /= 2;
+    /// Root of subtree `index` at `level` (0 = leaves, `levels` = the whole
+    /// tree), as the tree stands now. Subtrees with nothing inserted below
+    /// them are `zeros[level]`.
+    ///
+    /// Two copies of the tree can be compared top-down: a subtree whose roots
+    /// match holds the same leaves, so only mismatching subtrees need their
+    /// children (and, at the bottom, `subtree_leaves`) fetched.
+    pub fn subtree_root(&self, level: usize, index: u32) -> Result<[u8; 32], &'static str> {
+        if level > self.levels {
+            return Err("subtree level above the root");
+        }
+        let width = (ARITY as u64).checked_pow((self.levels - level) as u32).unwrap_or(u64::MAX);
+        if index as u64 >= width {
+            return Err("subtree index out of range");
         }
+        if level == self.levels {
+            return Ok(self.get_root());
+        }
+        Ok(self.node(level, index as usize))
+    }
 
-        proof
+    /// The inserted leaves under subtree `index` at `level`, in order.
+    /// Empty if the subtree is still empty.
+    pub fn subtree_leaves(&self, level: usize, index: u32) -> Result<&[[u8; 32]], &'static str> {
+        self.subtree_root(level, index)?;
+        let span = (ARITY as u64).pow(level as u32);
+        let start = (index as u64 * span).min(self.leaves.len() as u64) as usize;
+        let end = ((index as u64 + 1) * span).min(self.leaves.len() as u64) as usize;
+        Ok(&self.leaves[start..end])
+    }
+
+    /// Node `index` at `height` (0 = leaves), or the zero value if nothing
+    /// has been inserted below it.
+    fn node(&self, height: usize, index: usize) -> [u8; 32] {
+        let stored = if height == 0 { &self.leaves } else { &self.nodes[height - 1] };
+        stored.get(index).copied().unwrap_or(self.zeros[height])
     }
 }
 
-// =============================================================================
-//                    SP1 PROGRAM INPUT TYPES
-// =============================================================================
+#[cfg(feature = "full")]
+impl IncrementalMerkleTree {
+    /// Create a new empty tree. Matches MerkleTree.sol constructor.
+    pub fn new(levels: usize) -> Self {
+        Self::with_levels(levels)
+    }
 
-/// Private inputs for the 2-in-2-out transfer circuit.
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct TransferPrivateInputs {
+    /// Insert two leaves as one batch, recording a single new root.
+    /// Returns the index of `left`; `right` lands at the next index.
+    /// Matches MerkleTree.sol _insertPair(), which hashes the pair together
+    /// at level 0 first when `left` falls on an even index.
+    ///
+    /// Panics if fewer than two slots are left; see `try_insert_pair`.
+    pub fn insert_pair(&mut self, left: [u8; 32], right: [u8; 32]) -> u32 {
+        self.try_insert_pair(left, right).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Insert two leaves as one batch, or fail without changing the tree if
+    /// fewer than two slots are left.
+    pub fn try_insert_pair(&mut self, left: [u8; 32], right: [u8; 32]) -> Result<u32, &'static str> {
+        if self.remaining_capacity() < 2 {
+            return Err("Merkle tree is full");
+        }
+        let index = self.next_index;
+
+        self.leaves.extend([left, right]);
+        let root = if index.is_multiple_of(2) {
+            let pair = hash_pair(&left, &right);
+            self.filled_subtrees[0] = left;
+            if self.levels > 1 {
+                self.cache_node(1, index / 2, pair);
+            }
+            self.hash_up(1, index / 2, pair)
+        } else {
+            // The pair straddles two level-1 nodes: walk each leaf separately
+            self.hash_up(0, index, left);
+            self.hash_up(0, index + 1, right)
+        };
+        self.push_root(root);
+        self.next_index = index + 2;
+
+        Ok(index)
+    }
+
+    /// Generate a Merkle proof for the leaf at the given index.
+    ///
+    /// Siblings come from the nodes cached by `insert`, so this is O(levels).
+    ///
+    /// Panics if no leaf has been inserted at `leaf_index`; see `try_get_proof`.
+    pub fn get_proof(&self, leaf_index: u32) -> Vec<MerkleProofStep> {
+        self.try_get_proof(leaf_index).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Merkle proof for the leaf at `leaf_index`, or an error if no leaf has
+    /// been inserted there.
+    pub fn try_get_proof(&self, leaf_index: u32) -> Result<Vec<MerkleProofStep>, &'static str> {
+        if leaf_index as usize >= self.leaves.len() {
+            return Err("leaf index out of range");
+        }
+
+        let mut idx = leaf_index as usize;
+        Ok((0..self.levels)
+            .map(|height| {
+                let step = MerkleProofStep {
+                    is_left: idx.is_multiple_of(2),
+                    sibling: self.node(height, idx ^ 1),
+                };
+                idx /= 2;
+                step
+            })
+            .collect())
+    }
+
+    /// Generate one proof for several leaves; see `MerkleMultiProof`.
+    /// Indices may be given in any order and repeat.
+    pub fn get_multiproof(&self, indices: &[u32]) -> MerkleMultiProof {
+        assert!(!indices.is_empty(), "no leaves to prove");
+        let mut leaf_indices = indices.to_vec();
+        leaf_indices.sort_unstable();
+        leaf_indices.dedup();
+        assert!(
+            (leaf_indices[leaf_indices.len() - 1] as usize) < self.leaves.len(),
+            "leaf index out of range"
+        );
+
+        let mut siblings = Vec::new();
+        let mut current: Vec<usize> = leaf_indices.iter().map(|&i| i as usize).collect();
+        for height in 0..self.levels {
+            let mut next: Vec<usize> = Vec::with_capacity(current.len());
+            let mut i = 0;
+            while i < current.len() {
+                let index = current[i];
+                if index.is_multiple_of(2) && current.get(i + 1) == Some(&(index + 1)) {
+                    i += 1;
+                } else {
+                    siblings.push(self.node(height, index ^ 1));
+                }
+                next.push(index / 2);
+                i += 1;
+            }
+            current = next;
+        }
+        MerkleMultiProof { leaf_indices, levels: self.levels, siblings }
+    }
+}
+
+// =============================================================================
+//                          OUTPUT AMOUNTS
+// =============================================================================
+
+/// Exclusive upper bound on the amount of every note a circuit creates:
+/// 2^60 base units, over a trillion USDT. Sixteen such amounts still sum
+/// within a u64, so conservation cannot be won by wrapping even if amounts
+/// are later widened or summed in a narrower type. Changing it changes the
+/// guests' vkeys.
+#[cfg(feature = "full")]
+pub const MAX_AMOUNT: u64 = 1 << 60;
+
+/// Whether the circuits also reject zero-amount outputs. Off: sending a
+/// note's whole amount leaves a zero change output, and join-split pads with
+/// zero-amount dummies. Changing it changes the guests' vkeys.
+#[cfg(feature = "full")]
+pub const REQUIRE_NONZERO_OUTPUTS: bool = false;
+
+/// Range-check the amount of a note a circuit creates. The guests panic with
+/// the same message.
+#[cfg(feature = "full")]
+pub fn check_output_amount(amount: u64) -> Result<(), &'static str> {
+    if amount >= MAX_AMOUNT {
+        return Err("output amount exceeds MAX_AMOUNT");
+    }
+    if REQUIRE_NONZERO_OUTPUTS && amount == 0 {
+        return Err("output amount is zero");
+    }
+    Ok(())
+}
+
+/// Range-check a transfer's output amounts on the host, before any proving.
+/// `TransferPrivateInputs::check` runs it too.
+#[cfg(feature = "full")]
+pub fn validate_transfer_inputs(inputs: &TransferPrivateInputs) -> Result<(), &'static str> {
+    inputs.output_notes.iter().try_for_each(|note| check_output_amount(note.amount))
+}
+
+// =============================================================================
+//                          COVERAGE PROBES
+// =============================================================================
+
+/// Cycle-tracker probe for coverage runs (see `coverage`). The circuits'
+/// `check`s bracket each check with `probe!(start ..)` / `probe!(end ..)` and
+/// report the branch it took with `probe!(arm check, arm)` right after the
+/// check's end.
+///
+/// Probes only print inside a guest built with the `coverage` feature. Without
+/// it every form expands to nothing, so the default ELFs and their vkeys are
+/// unchanged, and host runs of the same checks stay silent.
+#[cfg(all(feature = "coverage", target_os = "zkvm"))]
+#[macro_export]
+macro_rules! probe {
+    (start $label:expr) => {
+        ::std::println!("cycle-tracker-report-start: {}{}", $crate::coverage::PROBE_PREFIX, $label)
+    };
+    (end $label:expr) => {
+        ::std::println!("cycle-tracker-report-end: {}{}", $crate::coverage::PROBE_PREFIX, $label)
+    };
+    (arm $check:expr, $arm:expr) => {{
+        ::std::println!("cycle-tracker-report-start: {}{}/{}", $crate::coverage::PROBE_PREFIX, $check, $arm);
+        ::std::println!("cycle-tracker-report-end: {}{}/{}", $crate::coverage::PROBE_PREFIX, $check, $arm);
+    }};
+}
+
+/// Compiled-out `probe!` (no `coverage` feature, or not in a guest).
+#[cfg(not(all(feature = "coverage", target_os = "zkvm")))]
+#[macro_export]
+macro_rules! probe {
+    ($($probe:tt)*) => {};
+}
+
+// =============================================================================
+//                    SP1 PROGRAM INPUT TYPES
+// =============================================================================
+
+/// Why a circuit rejects its inputs, as its guest panics with it. Circuits
+/// with several notes or payloads name the one at fault ("input note 1:
+/// spending key does not match note pubkey"); `check` returns the message
+/// alone.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckError {
+    pub message: &'static str,
+    /// What the message is about and its index, e.g. `("input note", 1)`
+    pub item: Option<(&'static str, usize)>,
+}
+
+#[cfg(feature = "full")]
+impl CheckError {
+    /// `message`, about item `index` of the inputs.
+    pub fn at(item: &'static str, index: usize, message: &'static str) -> Self {
+        CheckError { message, item: Some((item, index)) }
+    }
+}
+
+#[cfg(feature = "full")]
+impl From<&'static str> for CheckError {
+    fn from(message: &'static str) -> Self {
+        CheckError { message, item: None }
+    }
+}
+
+#[cfg(feature = "full")]
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.item {
+            Some((item, index)) => write!(f, "{} {}: {}", item, index, self.message),
+            None => f.write_str(self.message),
+        }
+    }
+}
+
+/// Private inputs for the 2-in-2-out transfer circuit.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransferPrivateInputs {
     /// Two input notes to spend
     pub input_notes: [Note; 2],
-    /// Spending keys for each input note
-    pub spending_keys: [[u8; 32]; 2],
-    /// Merkle proofs for each input note
+    /// Key each input note is spent with (see `SpenderKey`); only a legacy
+    /// note's spending key reaches the prover
+    pub spender_keys: [SpenderKey; 2],
+    /// Merkle proofs for each input note (empty for a zero-amount input,
+    /// such as a dummy)
     pub merkle_proofs: [Vec<MerkleProofStep>; 2],
     /// Two output notes to create
     pub output_notes: [Note; 2],
     /// The Merkle root both proofs verify against
     pub root: [u8; 32],
+    /// Lock for each input that is a cancellable note (None for plain notes)
+    #[serde(default)]
+    pub locks: [Option<CancellableLock>; 2],
+    /// Proof the inputs descend from an association set's deposits (None to
+    /// not opt in; see `association`)
+    #[serde(default)]
+    pub association: Option<association::TransferAssociation>,
+    /// The pool the proof is for (see `pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+    /// What each output's posted payload is encrypted with, to prove the
+    /// payloads open to the output notes (None to post them unproven; see
+    /// `note_encryption`)
+    #[serde(default)]
+    pub output_encryption: Option<[note_encryption::OutputEncryption; 2]>,
+    /// Cosigner for each input that is a multisig note (None otherwise; see
+    /// `multisig`)
+    #[serde(default)]
+    pub multisig: [Option<multisig::MultisigSpend>; 2],
+    /// The owners' signatures of the spend (see `TransferPrivateInputs::sign`)
+    #[serde(default)]
+    pub spend_signatures: Vec<SpendSignature>,
+}
+
+#[cfg(feature = "full")]
+impl TransferPrivateInputs {
+    /// A 1-in-2-out transfer: `input` in slot 0 and a dummy (see
+    /// `Note::dummy`) in slot 1, so spending one note needs no second one.
+    /// `dummy_blinding` must be fresh randomness. `pool_binding` is left
+    /// zero and `output_encryption` unset; set them for the pool the proof is
+    /// for and the payloads it posts, then `sign` the spend.
+    pub fn single_input(
+        input: join_split::JoinSplitInput,
+        output_notes: [Note; 2],
+        root: [u8; 32],
+        dummy_blinding: [u8; 32],
+    ) -> Self {
+        TransferPrivateInputs {
+            input_notes: [input.note, Note::dummy(dummy_blinding)],
+            spender_keys: [SpenderKey::derive(&input.spending_key), SpenderKey::derive(&DUMMY_SPENDING_KEY)],
+            merkle_proofs: [input.merkle_proof, Vec::new()],
+            output_notes,
+            root,
+            locks: [input.lock, None],
+            association: None,
+            pool_binding: [0u8; 32],
+            output_encryption: None,
+            multisig: [None, None],
+            spend_signatures: Vec::new(),
+        }
+    }
+
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. Run it before proving: the
+    /// guest runs the same checks (`guest_check`), after the prover has
+    /// spent its cycles.
+    pub fn check(&self) -> Result<TransferPublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The transfer guest's checks, in its order. An input's authorization
+    /// failure names the input.
+    pub fn guest_check(&self) -> Result<TransferPublicValues, CheckError> {
+        let public_values = self.check_unsigned()?;
+        // The owners sign everything the proof commits, so whoever holds
+        // their proving keys cannot spend on their own
+        probe!(start "transfer/spend_auth");
+        check_spend_signatures(self.signers(), &self.spend_signatures, &self.message_for(&public_values))?;
+        probe!(end "transfer/spend_auth");
+        Ok(public_values)
+    }
+
+    /// The public keys the spend must be signed under: those of each input
+    /// that moves value (see `multisig::input_signers`). A zero-amount input,
+    /// such as a dummy, needs no signature.
+    pub fn signers(&self) -> impl Iterator<Item = [u8; 32]> + '_ {
+        (0..2)
+            .filter(|&i| self.input_notes[i].amount != 0)
+            .flat_map(|i| multisig::input_signers(&self.spender_keys[i], self.multisig[i].as_ref()))
+    }
+
+    /// The message the inputs' owners sign (see `spend_auth_message`), or the
+    /// circuit's panic message if it would reject the inputs for anything but
+    /// their signatures.
+    pub fn spend_message(&self) -> Result<[u8; 32], &'static str> {
+        Ok(self.message_for(&self.check_unsigned().map_err(|err| err.message)?))
+    }
+
+    /// Sign the spend with `spending_key`'s spend-authorization key,
+    /// replacing its earlier signature. Sign last: the signature covers every
+    /// public value, so changing any input afterwards voids it.
+    pub fn sign(&mut self, spending_key: &[u8; 32]) -> Result<(), &'static str> {
+        let signature = SpendSignature::sign(spending_key, &self.spend_message()?);
+        self.spend_signatures.retain(|s| s.auth_key != signature.auth_key);
+        self.spend_signatures.push(signature);
+        Ok(())
+    }
+
+    fn message_for(&self, public_values: &TransferPublicValues) -> [u8; 32] {
+        spend_auth_message(CircuitKind::Transfer, &public_values.encode())
+    }
+
+    /// Every check but the spend signatures'.
+    fn check_unsigned(&self) -> Result<TransferPublicValues, CheckError> {
+        const MERKLE_ERRORS: [&str; 2] = ["Merkle proof invalid for input note 0", "Merkle proof invalid for input note 1"];
+        let mut auths = Vec::with_capacity(2);
+        for (i, merkle_error) in MERKLE_ERRORS.iter().enumerate() {
+            // Ownership, claim/reclaim of a cancellable note, or both keys
+            // of a multisig note
+            let note = &self.input_notes[i];
+            probe!(start alloc::format!("transfer/input{}/authorize", i));
+            let auth = multisig::authorize_input(
+                note,
+                &self.spender_keys[i],
+                self.locks[i].as_ref(),
+                self.multisig[i].as_ref(),
+            )
+            .map_err(|msg| CheckError::at("input note", i, msg))?;
+            probe!(end alloc::format!("transfer/input{}/authorize", i));
+            probe!(arm alloc::format!("transfer/input{}/authorize", i), coverage::spend_arm(auth.path));
+            auths.push(auth);
+            // A zero-amount input moves no value, so a dummy needs no leaf
+            probe!(start alloc::format!("transfer/input{}/merkle", i));
+            if note.amount != 0 && !verify_merkle_proof(note.commitment(), &self.merkle_proofs[i], self.root) {
+                return Err((*merkle_error).into());
+            }
+            probe!(end alloc::format!("transfer/input{}/merkle", i));
+            probe!(
+                arm alloc::format!("transfer/input{}/merkle", i),
+                if note.amount != 0 { "member" } else { "zero_amount" }
+            );
+        }
+        // The same note in both slots has one nullifier, which the pool would
+        // mark spent once while counting the amount twice
+        probe!(start "transfer/distinct_inputs");
+        if auths[0].nullifier == auths[1].nullifier {
+            return Err("duplicate input note".into());
+        }
+        probe!(end "transfer/distinct_inputs");
+        // A shorter proof can reach a known root from an inner node, so the
+        // depth is committed and the pool requires its tree's
+        probe!(start "transfer/proof_depth");
+        let tree_levels = spent_proof_levels(self.input_notes.iter().zip(&self.merkle_proofs))?;
+        probe!(end "transfer/proof_depth");
+        probe!(start "transfer/output_range");
+        validate_transfer_inputs(self)?;
+        probe!(end "transfer/output_range");
+        probe!(start "transfer/conservation");
+        let sum = |notes: &[Note; 2]| notes.iter().map(|n| n.amount as u128).sum::<u128>();
+        if sum(&self.input_notes) != sum(&self.output_notes) {
+            return Err("amounts don't balance".into());
+        }
+        probe!(end "transfer/conservation");
+        probe!(start "transfer/association");
+        if let Some(association) = &self.association {
+            association.check(&self.input_notes, &self.output_notes)?;
+        }
+        probe!(end "transfer/association");
+        probe!(arm "transfer/association", if self.association.is_some() { "associated" } else { "none" });
+        // Proven payloads are encrypted here, from the notes just checked
+        probe!(start "transfer/encryption");
+        let ciphertexts_hash = self
+            .output_encryption
+            .as_ref()
+            .map_or([0u8; 32], |e| note_encryption::encrypt_outputs(e, &self.output_notes));
+        probe!(end "transfer/encryption");
+        probe!(arm "transfer/encryption", if self.output_encryption.is_some() { "proven" } else { "none" });
+        Ok(TransferPublicValues {
+            root: self.root,
+            nullifiers: [auths[0].nullifier, auths[1].nullifier],
+            out_commitments: [self.output_notes[0].commitment(), self.output_notes[1].commitment()],
+            reclaim_deadline: cancellable::committed_deadline(&auths),
+            association_root: self.association.as_ref().map_or([0u8; 32], |a| a.root),
+            pool_binding: self.pool_binding,
+            ciphertexts_hash,
+            tree_levels,
+        })
+    }
 }
 
 /// Private inputs for the withdrawal circuit.
+#[cfg(feature = "full")]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WithdrawPrivateInputs {
     /// The input note to spend
     pub input_note: Note,
-    /// Spending key for the input note
-    pub spending_key: [u8; 32],
+    /// Key the input note is spent with (see `SpenderKey`)
+    pub spender_key: SpenderKey,
     /// Merkle proof for the input note
     pub merkle_proof: Vec<MerkleProofStep>,
     /// The Merkle root the proof verifies against
@@ -335,13 +1560,513 @@ pub struct WithdrawPrivateInputs {
     pub withdraw_amount: u64,
     /// Change note for partial withdrawals (None for full withdrawal)
     pub change_note: Option<Note>,
+    /// Lock if the input is a cancellable note (None for a plain note)
+    #[serde(default)]
+    pub lock: Option<CancellableLock>,
+    /// Paid out of the note to `relayer` for submitting the withdrawal
+    /// (publicly visible on-chain; 0 when the recipient submits it)
+    #[serde(default)]
+    pub fee: u64,
+    /// Address the fee is paid to (zero when there is no fee)
+    #[serde(default)]
+    pub relayer: [u8; 20],
+    /// Proof the input descends from an association set's deposits (None to
+    /// not opt in; see `association`)
+    #[serde(default)]
+    pub association: Option<association::WithdrawAssociation>,
+    /// The pool the proof is for (see `pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+    /// Last block timestamp at which the pool accepts the withdrawal, so a
+    /// relayer holding the proof cannot submit it later (publicly visible
+    /// on-chain; 0 for no deadline)
+    #[serde(default)]
+    pub deadline: u64,
+    /// Contract the pool calls after paying out, for withdraw-and-call
+    /// (zero for a plain withdrawal; see `call_binding`)
+    #[serde(default)]
+    pub call_target: [u8; 20],
+    /// keccak256 of the calldata the pool sends `call_target` (zero for a
+    /// plain withdrawal)
+    #[serde(default)]
+    pub calldata_hash: [u8; 32],
+    /// Cosigner if the input is a multisig note (None otherwise; see
+    /// `multisig`). Change then goes back to the multisig.
+    #[serde(default)]
+    pub multisig: Option<multisig::MultisigSpend>,
+    /// The owners' signatures of the spend (see `WithdrawPrivateInputs::sign`)
+    #[serde(default)]
+    pub spend_signatures: Vec<SpendSignature>,
+}
+
+#[cfg(feature = "full")]
+impl WithdrawPrivateInputs {
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. Run it before proving: the
+    /// guest runs the same checks (`guest_check`), after the prover has
+    /// spent its cycles.
+    pub fn check(&self) -> Result<WithdrawPublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The withdraw guest's checks, in its order.
+    pub fn guest_check(&self) -> Result<WithdrawPublicValues, CheckError> {
+        let public_values = self.check_unsigned()?;
+        // The owners sign everything the proof commits, so whoever holds
+        // their proving keys cannot spend on their own
+        probe!(start "withdraw/spend_auth");
+        check_spend_signatures(self.signers(), &self.spend_signatures, &self.message_for(&public_values))?;
+        probe!(end "withdraw/spend_auth");
+        Ok(public_values)
+    }
+
+    /// The public keys the spend must be signed under (see
+    /// `multisig::input_signers`).
+    pub fn signers(&self) -> impl Iterator<Item = [u8; 32]> {
+        multisig::input_signers(&self.spender_key, self.multisig.as_ref())
+    }
+
+    /// The message the input's owners sign (see `spend_auth_message`), or the
+    /// circuit's panic message if it would reject the inputs for anything but
+    /// their signatures.
+    pub fn spend_message(&self) -> Result<[u8; 32], &'static str> {
+        Ok(self.message_for(&self.check_unsigned()?))
+    }
+
+    /// Sign the spend with `spending_key`'s spend-authorization key,
+    /// replacing its earlier signature. Sign last: the signature covers every
+    /// public value, so changing any input afterwards voids it.
+    pub fn sign(&mut self, spending_key: &[u8; 32]) -> Result<(), &'static str> {
+        let signature = SpendSignature::sign(spending_key, &self.spend_message()?);
+        self.spend_signatures.retain(|s| s.auth_key != signature.auth_key);
+        self.spend_signatures.push(signature);
+        Ok(())
+    }
+
+    fn message_for(&self, public_values: &WithdrawPublicValues) -> [u8; 32] {
+        spend_auth_message(CircuitKind::Withdraw, &public_values.encode())
+    }
+
+    /// Every check but the spend signatures'.
+    fn check_unsigned(&self) -> Result<WithdrawPublicValues, &'static str> {
+        // Ownership for a plain note, claim/reclaim for a cancellable one,
+        // both owners' keys for a multisig one
+        probe!(start "withdraw/authorize");
+        let auth = multisig::authorize_input(
+            &self.input_note,
+            &self.spender_key,
+            self.lock.as_ref(),
+            self.multisig.as_ref(),
+        )?;
+        probe!(end "withdraw/authorize");
+        probe!(arm "withdraw/authorize", coverage::spend_arm(auth.path));
+        probe!(start "withdraw/merkle");
+        if !verify_merkle_proof(self.input_note.commitment(), &self.merkle_proof, self.root) {
+            return Err("Merkle proof invalid");
+        }
+        probe!(end "withdraw/merkle");
+        // A fee needs somewhere to go; the zero address would burn it
+        probe!(start "withdraw/relayer");
+        if self.fee != 0 && self.relayer == [0u8; 20] {
+            return Err("relayer fee paid to the zero address");
+        }
+        probe!(end "withdraw/relayer");
+        probe!(arm "withdraw/relayer", if self.fee == 0 { "no_fee" } else { "fee" });
+        // Change goes back to whoever spends the note
+        if let Some(change_note) = &self.change_note {
+            probe!(start "withdraw/change_owner");
+            if !multisig::owns_change(&self.spender_key, self.multisig.as_ref(), &change_note.pubkey) {
+                return Err("change note is not owned by the spender");
+            }
+            probe!(end "withdraw/change_owner");
+            probe!(start "withdraw/change_range");
+            check_output_amount(change_note.amount)?;
+            probe!(end "withdraw/change_range");
+        }
+        // Checked: a wrapped sum could balance
+        probe!(start "withdraw/conservation");
+        let change = self.change_note.as_ref().map_or(0, |n| n.amount);
+        let paid_out = self.withdraw_amount.checked_add(change).and_then(|sum| sum.checked_add(self.fee));
+        if paid_out != Some(self.input_note.amount) {
+            return Err(match self.change_note {
+                Some(_) => "partial withdrawal amounts don't balance",
+                None => "full withdrawal amount mismatch",
+            });
+        }
+        probe!(end "withdraw/conservation");
+        probe!(arm "withdraw/conservation", if self.change_note.is_some() { "partial" } else { "full" });
+        probe!(start "withdraw/association");
+        if let Some(association) = &self.association {
+            association.check(&self.input_note, self.change_note.as_ref())?;
+        }
+        probe!(end "withdraw/association");
+        probe!(arm "withdraw/association", if self.association.is_some() { "associated" } else { "none" });
+        // A withdraw-and-call binds the call the pool makes after paying out
+        probe!(start "withdraw/call");
+        let call_binding = call_binding(&self.call_target, &self.calldata_hash)?;
+        probe!(end "withdraw/call");
+        probe!(arm "withdraw/call", if call_binding == [0u8; 32] { "none" } else { "call" });
+        Ok(WithdrawPublicValues {
+            root: self.root,
+            nullifier: auth.nullifier,
+            recipient: self.recipient,
+            amount: self.withdraw_amount,
+            change_commitment: self.change_note.as_ref().map_or([0u8; 32], Note::commitment),
+            reclaim_deadline: auth.reclaim_deadline.unwrap_or(0),
+            relayer: self.relayer,
+            fee: self.fee,
+            association_root: self.association.as_ref().map_or([0u8; 32], |a| a.root),
+            pool_binding: self.pool_binding,
+            deadline: self.deadline,
+            call_binding,
+            tree_levels: self.merkle_proof.len() as u64,
+        })
+    }
+}
+
+// =============================================================================
+//                          POOL BINDING
+// =============================================================================
+
+/// Identifies one pool deployment, so a proof for one pool cannot be replayed
+/// against another sharing its tree state (a fork, a redeployment on another
+/// chain). Every circuit but deposit commits it as given; the pool, or
+/// whichever contract verifies the proof, rejects any other value than that
+/// pool's.
+///   pool_binding = keccak256(chain_id (uint256 BE) || pool address (left-padded to 32))
+/// Matches ShieldedPool.sol poolBinding(): keccak256(abi.encode(block.chainid, address(this))).
+#[cfg(feature = "full")]
+pub fn pool_binding(chain_id: u64, pool: &[u8; 20]) -> [u8; 32] {
+    let mut preimage = [0u8; 64];
+    preimage[24..32].copy_from_slice(&chain_id.to_be_bytes());
+    preimage[44..64].copy_from_slice(pool);
+    keccak256(&preimage)
+}
+
+// =============================================================================
+//                          WITHDRAW AND CALL
+// =============================================================================
+
+/// Binds a withdrawal to the call the pool makes after paying out, so the
+/// withdrawn funds can go into a DEX or bridge call in the same transaction
+/// and nobody submitting the proof can change the call.
+///   call_binding = keccak256(target (left-padded to 32) || calldata_hash)
+/// Matches ShieldedPool.sol withdrawAndCall(): keccak256(abi.encode(target, keccak256(data))).
+/// Zero for a plain withdrawal (zero target), which only `withdraw()` accepts.
+#[cfg(feature = "full")]
+pub fn call_binding(target: &[u8; 20], calldata_hash: &[u8; 32]) -> Result<[u8; 32], &'static str> {
+    if *target == [0u8; 20] {
+        if *calldata_hash != [0u8; 32] {
+            return Err("calldata hash without a call target");
+        }
+        return Ok([0u8; 32]);
+    }
+    let mut preimage = [0u8; 64];
+    preimage[12..32].copy_from_slice(target);
+    preimage[32..64].copy_from_slice(calldata_hash);
+    Ok(keccak256(&preimage))
+}
+
+// =============================================================================
+//                        PUBLIC VALUES LAYOUT
+// =============================================================================
+
+/// Public values committed by the withdraw circuit (416 bytes).
+/// Matches ShieldedPool.sol:
+///   abi.decode(publicValues, (bytes32, bytes32, address, uint256, bytes32, uint256, address, uint256, bytes32, bytes32,
+///                             uint256, bytes32, uint256))
+#[cfg(feature = "full")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WithdrawPublicValues {
+    pub root: [u8; 32],
+    pub nullifier: [u8; 32],
+    pub recipient: [u8; 20],
+    pub amount: u64,
+    /// Zero for full withdrawals
+    pub change_commitment: [u8; 32],
+    /// Zero unless the input is a reclaimed cancellable note
+    pub reclaim_deadline: u64,
+    /// Zero when there is no fee
+    pub relayer: [u8; 20],
+    pub fee: u64,
+    /// Zero unless the spend proved its association (see `association`)
+    pub association_root: [u8; 32],
+    /// The pool the proof is for (see `pool_binding`)
+    pub pool_binding: [u8; 32],
+    /// Zero when the withdrawal has no deadline
+    pub deadline: u64,
+    /// Zero unless this is a withdraw-and-call (see `call_binding`)
+    pub call_binding: [u8; 32],
+    /// Depth of the Merkle proof, which the pool requires to be its tree's
+    pub tree_levels: u64,
+}
+
+#[cfg(feature = "full")]
+impl WithdrawPublicValues {
+    pub const LEN: usize = 416;
+
+    /// ABI-encode exactly as the withdraw guest commits it.
+    pub fn encode(&self) -> [u8; 416] {
+        let mut out = [0u8; 416];
+        out[0..32].copy_from_slice(&self.root);
+        out[32..64].copy_from_slice(&self.nullifier);
+        out[76..96].copy_from_slice(&self.recipient);
+        out[120..128].copy_from_slice(&self.amount.to_be_bytes());
+        out[128..160].copy_from_slice(&self.change_commitment);
+        out[184..192].copy_from_slice(&self.reclaim_deadline.to_be_bytes());
+        out[204..224].copy_from_slice(&self.relayer);
+        out[248..256].copy_from_slice(&self.fee.to_be_bytes());
+        out[256..288].copy_from_slice(&self.association_root);
+        out[288..320].copy_from_slice(&self.pool_binding);
+        out[344..352].copy_from_slice(&self.deadline.to_be_bytes());
+        out[352..384].copy_from_slice(&self.call_binding);
+        out[408..416].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong,
+    /// the address padding is non-zero, or the amount, a deadline, the fee or
+    /// the tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN {
+            return None;
+        }
+        if bytes[64..76].iter().any(|&b| b != 0)
+            || bytes[96..120].iter().any(|&b| b != 0)
+            || bytes[160..184].iter().any(|&b| b != 0)
+            || bytes[192..204].iter().any(|&b| b != 0)
+            || bytes[224..248].iter().any(|&b| b != 0)
+            || bytes[320..344].iter().any(|&b| b != 0)
+            || bytes[384..408].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let mut v = WithdrawPublicValues {
+            root: [0u8; 32],
+            nullifier: [0u8; 32],
+            recipient: [0u8; 20],
+            amount: 0,
+            change_commitment: [0u8; 32],
+            reclaim_deadline: 0,
+            relayer: [0u8; 20],
+            fee: 0,
+            association_root: [0u8; 32],
+            pool_binding: [0u8; 32],
+            deadline: 0,
+            call_binding: [0u8; 32],
+            tree_levels: 0,
+        };
+        v.root.copy_from_slice(&bytes[0..32]);
+        v.nullifier.copy_from_slice(&bytes[32..64]);
+        v.recipient.copy_from_slice(&bytes[76..96]);
+        let mut amount = [0u8; 8];
+        amount.copy_from_slice(&bytes[120..128]);
+        v.amount = u64::from_be_bytes(amount);
+        v.change_commitment.copy_from_slice(&bytes[128..160]);
+        let mut deadline = [0u8; 8];
+        deadline.copy_from_slice(&bytes[184..192]);
+        v.reclaim_deadline = u64::from_be_bytes(deadline);
+        v.relayer.copy_from_slice(&bytes[204..224]);
+        let mut fee = [0u8; 8];
+        fee.copy_from_slice(&bytes[248..256]);
+        v.fee = u64::from_be_bytes(fee);
+        v.association_root.copy_from_slice(&bytes[256..288]);
+        v.pool_binding.copy_from_slice(&bytes[288..320]);
+        let mut deadline = [0u8; 8];
+        deadline.copy_from_slice(&bytes[344..352]);
+        v.deadline = u64::from_be_bytes(deadline);
+        v.call_binding.copy_from_slice(&bytes[352..384]);
+        let mut tree_levels = [0u8; 8];
+        tree_levels.copy_from_slice(&bytes[408..416]);
+        v.tree_levels = u64::from_be_bytes(tree_levels);
+        Some(v)
+    }
+}
+
+/// Public values committed by the transfer circuit (320 bytes).
+/// Matches ShieldedPool.sol: abi.decode(publicValues, (bytes32[10])), with
+/// word 5 read as a uint256 deadline and word 9 as the uint256 tree depth.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransferPublicValues {
+    pub root: [u8; 32],
+    pub nullifiers: [[u8; 32]; 2],
+    pub out_commitments: [[u8; 32]; 2],
+    /// Zero unless an input is a reclaimed cancellable note
+    pub reclaim_deadline: u64,
+    /// Zero unless the spend proved its association (see `association`)
+    pub association_root: [u8; 32],
+    /// The pool the proof is for (see `pool_binding`)
+    pub pool_binding: [u8; 32],
+    /// Zero unless the outputs' payloads are proven (see `note_encryption`)
+    pub ciphertexts_hash: [u8; 32],
+    /// Depth of the inputs' Merkle proofs (see `spent_proof_levels`), which
+    /// the pool requires to be its tree's
+    pub tree_levels: u64,
+}
+
+#[cfg(feature = "full")]
+impl TransferPublicValues {
+    pub const LEN: usize = 320;
+
+    /// ABI-encode exactly as the transfer guest commits it.
+    pub fn encode(&self) -> [u8; 320] {
+        let mut out = [0u8; 320];
+        out[0..32].copy_from_slice(&self.root);
+        out[32..64].copy_from_slice(&self.nullifiers[0]);
+        out[64..96].copy_from_slice(&self.nullifiers[1]);
+        out[96..128].copy_from_slice(&self.out_commitments[0]);
+        out[128..160].copy_from_slice(&self.out_commitments[1]);
+        out[184..192].copy_from_slice(&self.reclaim_deadline.to_be_bytes());
+        out[192..224].copy_from_slice(&self.association_root);
+        out[224..256].copy_from_slice(&self.pool_binding);
+        out[256..288].copy_from_slice(&self.ciphertexts_hash);
+        out[312..320].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the deadline or the tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN
+            || bytes[160..184].iter().any(|&b| b != 0)
+            || bytes[288..312].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let word = |i: usize| {
+            let mut w = [0u8; 32];
+            w.copy_from_slice(&bytes[i * 32..(i + 1) * 32]);
+            w
+        };
+        Some(TransferPublicValues {
+            root: word(0),
+            nullifiers: [word(1), word(2)],
+            out_commitments: [word(3), word(4)],
+            reclaim_deadline: u64::from_be_bytes(word(5)[24..32].try_into().unwrap()),
+            association_root: word(6),
+            pool_binding: word(7),
+            ciphertexts_hash: word(8),
+            tree_levels: u64::from_be_bytes(word(9)[24..32].try_into().unwrap()),
+        })
+    }
+}
+
+// =============================================================================
+//                      GUEST INPUT FRAMING
+// =============================================================================
+
+/// Version of the host → guest input layout.
+/// Bump whenever `TransferPrivateInputs`, `WithdrawPrivateInputs`,
+/// `ClaimPrivateInputs`, `JoinSplitPrivateInputs`, `DepositPrivateInputs`,
+/// `BatchWithdrawPrivateInputs`, `ConsolidatePrivateInputs`,
+/// `AttestPrivateInputs`, `DisclosurePrivateInputs`, `PolicySpendPrivateInputs`
+/// or `PayoutPrivateInputs` change shape.
+#[cfg(feature = "full")]
+pub const INPUT_VERSION: u32 = 12;
+
+/// Written by the host after the private inputs. The guest reads it back last
+/// to prove it consumed exactly the stream the host intended to send.
+#[cfg(feature = "full")]
+pub const INPUT_END_MARKER: [u8; 32] = *b"shielded-pool/end-of-guest-input";
+
+/// Which circuit a stdin stream was built for.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CircuitKind {
+    Transfer,
+    Withdraw,
+    /// Historical ownership claims (see `claim`)
+    Claim,
+    /// Up to 4-in-4-out transfers (see `join_split`)
+    JoinSplit,
+    /// Proven deposits (see `deposit`)
+    Deposit,
+    /// Up to 8 notes withdrawn in one proof (see `batch_withdraw`)
+    BatchWithdraw,
+    /// Up to 8 notes of one key merged into one (see `consolidate`)
+    Consolidate,
+    /// Proof of funds without spending (see `attest`)
+    Attest,
+    /// Chosen notes opened to an auditor under a viewing key (see `disclosure`)
+    Disclose,
+    /// A spend under a daily spending-limit policy (see `policy`)
+    PolicySpend,
+    /// Up to 2 notes paying up to 8 distinct recipients (see `payout`)
+    Payout,
+}
+
+/// Leading tag written before the private inputs.
+///
+/// Host writes: `InputHeader`, then the private inputs, then `INPUT_END_MARKER`.
+/// Guests reject any stream that does not follow this layout.
+#[cfg(feature = "full")]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InputHeader {
+    pub kind: CircuitKind,
+    pub version: u32,
+}
+
+#[cfg(feature = "full")]
+impl InputHeader {
+    /// Header for the current input layout.
+    pub fn new(kind: CircuitKind) -> Self {
+        InputHeader { kind, version: INPUT_VERSION }
+    }
+
+    /// Check that this header matches the circuit reading it.
+    pub fn validate(&self, expected: CircuitKind) -> Result<(), &'static str> {
+        if self.kind != expected {
+            return Err(match expected {
+                CircuitKind::Transfer => "input header is not for the transfer circuit",
+                CircuitKind::Withdraw => "input header is not for the withdraw circuit",
+                CircuitKind::Claim => "input header is not for the claim circuit",
+                CircuitKind::JoinSplit => "input header is not for the join-split circuit",
+                CircuitKind::Deposit => "input header is not for the deposit circuit",
+                CircuitKind::BatchWithdraw => "input header is not for the batch-withdraw circuit",
+                CircuitKind::Consolidate => "input header is not for the consolidation circuit",
+                CircuitKind::Attest => "input header is not for the attest circuit",
+                CircuitKind::Disclose => "input header is not for the disclose circuit",
+                CircuitKind::PolicySpend => "input header is not for the policy-spend circuit",
+                CircuitKind::Payout => "input header is not for the payout circuit",
+            });
+        }
+        if self.version != INPUT_VERSION {
+            return Err("input header version does not match this guest");
+        }
+        Ok(())
+    }
+}
+
+/// Check the trailing marker read after the private inputs.
+#[cfg(feature = "full")]
+pub fn validate_end_marker(marker: &[u8; 32]) -> Result<(), &'static str> {
+    if *marker != INPUT_END_MARKER {
+        return Err("unexpected data after private inputs (missing end marker)");
+    }
+    Ok(())
+}
+
+/// What SP1's `syscall_hint_len` returns once every stdin item has been read.
+#[cfg(feature = "full")]
+pub const STDIN_EXHAUSTED: usize = u32::MAX as usize;
+
+/// Check that nothing follows the end marker. `next_len` is the guest's
+/// `syscall_hint_len()`: the length of the next stdin item, or
+/// `STDIN_EXHAUSTED` if there is none.
+#[cfg(feature = "full")]
+pub fn validate_input_consumed(next_len: usize) -> Result<(), &'static str> {
+    if next_len != STDIN_EXHAUSTED {
+        return Err("unexpected data after the end marker");
+    }
+    Ok(())
 }
 
 // =============================================================================
 //                              TESTS
 // =============================================================================
 
-#[cfg(test)]
+#[cfg(all(test, feature = "full"))]
 mod tests {
     extern crate alloc;
     use super::*;
@@ -395,6 +2120,67 @@ mod tests {
         assert_ne!(commitment, [0u8; 32]);
     }
 
+    #[test]
+    fn test_proving_key_opens_pubkey_and_nullifier() {
+        let spending_key = [0xABu8; 32];
+        let proving_key = ProvingKey::derive(&spending_key);
+        let pubkey = derive_pubkey(&spending_key);
+        assert_eq!(proving_key.pubkey(), pubkey);
+        assert_eq!(proving_key.nullifier_key, derive_nullifier_key(&spending_key));
+        assert_eq!(proving_key.auth_key, spend_auth_pubkey(&derive_spend_auth_key(&spending_key)));
+        assert_eq!(SpendingKey(spending_key).proving_key(), proving_key);
+        assert!(SpenderKey::Proving(proving_key).owns(&pubkey));
+        // Neither key alone, nor the spending key itself, opens the pubkey
+        assert!(!SpenderKey::Legacy(spending_key).owns(&pubkey));
+        assert!(!SpenderKey::Legacy(proving_key.nullifier_key).owns(&pubkey));
+        assert_ne!(note_pubkey(&proving_key.nullifier_key, &proving_key.auth_key), pubkey);
+
+        let commitment = Note { amount: 1, pubkey, blinding: [0x42u8; 32] }.commitment();
+        let nullifier = nullifier_from_key(&commitment, &proving_key.nullifier_key);
+        assert_eq!(nullifier, compute_nullifier(&commitment, &spending_key));
+    }
+
+    #[test]
+    fn test_legacy_key_opens_pre_proving_key_notes() {
+        let spending_key = [0xABu8; 32];
+        let legacy = SpenderKey::Legacy(spending_key);
+        assert_eq!(legacy_pubkey(&spending_key), keccak256(&spending_key));
+        assert!(legacy.owns(&legacy_pubkey(&spending_key)));
+        assert_eq!(legacy.auth_key(), None);
+        // Its nullifiers are the ones it had: keccak256(commitment || spending_key)
+        let commitment = Note { amount: 1, pubkey: legacy.pubkey(), blinding: [0x42u8; 32] }.commitment();
+        let mut preimage = [0u8; 64];
+        preimage[..32].copy_from_slice(&commitment);
+        preimage[32..].copy_from_slice(&spending_key);
+        assert_eq!(nullifier_from_key(&commitment, legacy.nullifier_key()), keccak256(&preimage));
+        // Change may move onto the key's current pubkey, not anyone else's
+        assert!(legacy.owns_change(&legacy_pubkey(&spending_key)));
+        assert!(legacy.owns_change(&derive_pubkey(&spending_key)));
+        assert!(!legacy.owns_change(&derive_pubkey(&[0xACu8; 32])));
+        assert!(!SpenderKey::derive(&spending_key).owns_change(&legacy_pubkey(&spending_key)));
+    }
+
+    #[test]
+    fn test_spend_signature() {
+        let spending_key = [0xABu8; 32];
+        let message = spend_auth_message(CircuitKind::Transfer, &[1u8; 320]);
+        let signature = SpendSignature::sign(&spending_key, &message);
+        assert_eq!(signature.auth_key, ProvingKey::derive(&spending_key).auth_key);
+        assert!(signature.verify(&message));
+        assert!(!signature.verify(&spend_auth_message(CircuitKind::Withdraw, &[1u8; 320])));
+        assert!(!SpendSignature { s: [0u8; 32], ..signature }.verify(&message));
+        assert!(!SpendSignature { auth_key: [0u8; 32], ..signature }.verify(&message));
+
+        let signer = [signature.auth_key];
+        assert_eq!(check_spend_signatures(signer, &[signature], &message), Ok(()));
+        assert_eq!(check_spend_signatures([], &[], &message), Ok(()));
+        assert_eq!(check_spend_signatures(signer, &[], &message), Err("missing spend authorization signature"));
+        let other = SpendSignature::sign(&[0xACu8; 32], &message);
+        assert_eq!(check_spend_signatures(signer, &[other], &message), Err("missing spend authorization signature"));
+        let forged = SpendSignature { auth_key: signature.auth_key, ..other };
+        assert_eq!(check_spend_signatures(signer, &[forged], &message), Err("invalid spend authorization signature"));
+    }
+
     #[test]
     fn test_nullifier() {
         let spending_key = [0xABu8; 32];
@@ -455,6 +2241,189 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_merkle_tree_proofs_match_full_rebuild() {
+        // Reference: rebuild every level from the leaves
+        fn rebuild_proof(tree: &IncrementalMerkleTree, leaf_index: usize) -> Vec<MerkleProofStep> {
+            let mut level: Vec<[u8; 32]> = (0..1usize << tree.levels)
+                .map(|i| tree.leaves.get(i).copied().unwrap_or(tree.zeros[0]))
+                .collect();
+            let mut idx = leaf_index;
+            let mut proof = Vec::new();
+            for _ in 0..tree.levels {
+                proof.push(MerkleProofStep { is_left: idx.is_multiple_of(2), sibling: level[idx ^ 1] });
+                level = level.chunks(2).map(|pair| hash_pair(&pair[0], &pair[1])).collect();
+                idx /= 2;
+            }
+            proof
+        }
+
+        let mut tree = IncrementalMerkleTree::new(5);
+        for n in 0..21u32 {
+            tree.insert(keccak256(&n.to_be_bytes()));
+            let root = tree.get_root();
+            for i in 0..=n {
+                let proof = tree.get_proof(i);
+                assert_eq!(proof, rebuild_proof(&tree, i as usize), "leaf {i} after {n} inserts");
+                assert!(verify_merkle_proof(tree.leaves[i as usize], &proof, root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merkle_tree_insert_pair() {
+        let leaf = |i: u32| keccak256(&i.to_be_bytes());
+        let mut paired = IncrementalMerkleTree::new(5);
+        let mut single = IncrementalMerkleTree::new(5);
+        // Even start, then odd starts after a lone insert
+        for (i, lone) in [(0, false), (2, true), (5, false), (7, true)] {
+            if lone {
+                paired.insert(leaf(100 + i));
+                single.insert(leaf(100 + i));
+            }
+            let roots_before = paired.current_root_index;
+            let index = paired.insert_pair(leaf(i), leaf(i + 1));
+            single.insert(leaf(i));
+            single.insert(leaf(i + 1));
+
+            assert_eq!(index, single.next_index - 2);
+            assert_eq!(paired.current_root_index, (roots_before + 1) % ROOT_HISTORY_SIZE);
+            assert_eq!(paired.get_root(), single.get_root());
+            assert_eq!(paired.filled_subtrees, single.filled_subtrees);
+            assert_eq!(paired.nodes, single.nodes);
+        }
+        for i in 0..paired.next_index {
+            let proof = paired.get_proof(i);
+            assert!(verify_merkle_proof(paired.leaves[i as usize], &proof, paired.get_root()));
+        }
+    }
+
+    #[test]
+    fn test_merkle_tree_index_of() {
+        let leaf = |i: u32| keccak256(&i.to_be_bytes());
+        let mut tree = IncrementalMerkleTree::new(4);
+        let base = tree.checkpoint();
+        for i in 0..5 {
+            tree.insert(leaf(i));
+        }
+        assert_eq!(tree.index_of(&leaf(3)), Some(3));
+        assert_eq!(tree.index_of(&leaf(9)), None);
+        tree.rollback(base).unwrap();
+        assert_eq!(tree.index_of(&leaf(3)), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Merkle tree is full")]
+    fn test_merkle_tree_insert_pair_needs_two_slots() {
+        let mut tree = IncrementalMerkleTree::new(2);
+        for i in 0..3u32 {
+            tree.insert(keccak256(&i.to_be_bytes()));
+        }
+        tree.insert_pair([1u8; 32], [2u8; 32]);
+    }
+
+    #[test]
+    fn test_merkle_tree_try_insert_capacity() {
+        let leaf = |i: u32| keccak256(&i.to_be_bytes());
+        let mut tree = IncrementalMerkleTree::new(2);
+        assert_eq!(tree.capacity(), 4);
+        assert_eq!(tree.try_get_proof(0), Err("leaf index out of range"));
+        for i in 0..3 {
+            assert_eq!(tree.try_insert(leaf(i)), Ok(i));
+        }
+        assert_eq!(tree.remaining_capacity(), 1);
+        assert!(!tree.is_full());
+
+        // A failed insert leaves the tree as it was
+        let root = tree.get_root();
+        assert_eq!(tree.try_insert_pair(leaf(3), leaf(4)), Err("Merkle tree is full"));
+        assert_eq!((tree.get_root(), tree.leaves.len()), (root, 3));
+
+        assert_eq!(tree.try_insert(leaf(3)), Ok(3));
+        assert!(tree.is_full());
+        assert_eq!(tree.try_insert(leaf(4)), Err("Merkle tree is full"));
+        assert_eq!(tree.try_get_proof(3).unwrap(), tree.get_proof(3));
+        assert!(tree.try_get_proof(4).is_err());
+    }
+
+    #[test]
+    fn test_merkle_multiproof() {
+        let leaf = |i: u32| keccak256(&i.to_be_bytes());
+        let mut tree = IncrementalMerkleTree::new(5);
+        for i in 0..13 {
+            tree.insert(leaf(i));
+        }
+        let root = tree.get_root();
+
+        for indices in [vec![0], vec![12], vec![3, 2], vec![0, 1, 2, 3], vec![1, 6, 7, 12], (0..13).collect()] {
+            let proof = tree.get_multiproof(&indices);
+            let leaves: Vec<[u8; 32]> = proof.leaf_indices.iter().map(|&i| leaf(i)).collect();
+            assert!(verify_merkle_multiproof(&leaves, &proof, root), "{indices:?}");
+            // Shared nodes are never sent twice
+            assert!(proof.siblings.len() <= indices.len() * tree.levels);
+        }
+
+        // A single leaf's multiproof carries the same siblings as its proof
+        let single = tree.get_multiproof(&[5]);
+        let steps: Vec<[u8; 32]> = tree.get_proof(5).iter().map(|s| s.sibling).collect();
+        assert_eq!(single.siblings, steps);
+
+        // Adjacent leaves share every sibling
+        assert_eq!(tree.get_multiproof(&[6, 7]).siblings.len(), tree.levels - 1);
+
+        let proof = tree.get_multiproof(&[1, 6, 12]);
+        let leaves = [leaf(1), leaf(6), leaf(12)];
+        assert!(!verify_merkle_multiproof(&[leaf(1), leaf(7), leaf(12)], &proof, root));
+        assert!(!verify_merkle_multiproof(&leaves[..2], &proof, root));
+        let mut swapped = proof.clone();
+        swapped.leaf_indices = vec![1, 7, 12];
+        assert!(!verify_merkle_multiproof(&leaves, &swapped, root));
+        let mut extra = proof.clone();
+        extra.siblings.push([0u8; 32]);
+        assert!(!verify_merkle_multiproof(&leaves, &extra, root));
+    }
+
+    #[test]
+    fn test_merkle_tree_rollback() {
+        let leaf = |i: u32| keccak256(&i.to_be_bytes());
+        let mut tree = IncrementalMerkleTree::new(5);
+        let empty = tree.checkpoint();
+        for i in 0..5 {
+            tree.insert(leaf(i));
+        }
+        let expected = tree.clone();
+        let five = tree.checkpoint();
+        for i in 5..12 {
+            tree.insert(leaf(i));
+        }
+        let twelve = tree.checkpoint();
+        tree.insert(leaf(12));
+
+        tree.rollback(five).unwrap();
+        assert_eq!(tree.next_index, 5);
+        assert_eq!(tree.leaves, expected.leaves);
+        assert_eq!(tree.nodes, expected.nodes);
+        assert_eq!(tree.filled_subtrees, expected.filled_subtrees);
+        assert_eq!(tree.roots, expected.roots);
+        assert_eq!(tree.current_root_index, expected.current_root_index);
+        // Later checkpoints are gone, this one can be reused
+        assert!(tree.rollback(twelve).is_err());
+        tree.insert(keccak256(b"replacement"));
+        tree.rollback(five).unwrap();
+        assert_eq!(tree.get_root(), expected.get_root());
+
+        // Replaying the same leaves reproduces the original tree exactly
+        for i in 5..12 {
+            tree.insert(leaf(i));
+        }
+        for i in 0..12 {
+            assert!(verify_merkle_proof(leaf(i), &tree.get_proof(i), tree.get_root()));
+        }
+        tree.rollback(empty).unwrap();
+        assert_eq!(tree.get_root(), IncrementalMerkleTree::new(5).get_root());
+        assert!(tree.leaves.is_empty());
+    }
+
     #[test]
     fn test_merkle_tree_root_history() {
         let mut tree = IncrementalMerkleTree::new(4);
@@ -472,6 +2441,130 @@ mod tests {
         assert!(!tree.is_known_root([0u8; 32]));
     }
 
+    #[test]
+    fn test_verify_merkle_proof_any_root() {
+        let mut tree = IncrementalMerkleTree::new(5);
+        tree.insert(keccak256(b"leaf"));
+        assert_eq!(tree.recent_roots().len(), 2);
+        let proof = tree.get_proof(0);
+        let leaf = keccak256(b"leaf");
+
+        // Still accepted while its root is in the window, however many leaves follow
+        for i in 1..ROOT_HISTORY_SIZE {
+            tree.insert(keccak256(&(i as u32).to_be_bytes()));
+            assert!(verify_merkle_proof_any_root(leaf, &proof, &tree.recent_roots()));
+        }
+        assert_eq!(tree.recent_roots()[0], tree.get_root());
+        assert_eq!(tree.recent_roots().len(), ROOT_HISTORY_SIZE);
+        assert!(tree.recent_roots().iter().all(|root| tree.is_known_root(*root)));
+
+        tree.insert(keccak256(b"one too many"));
+        assert!(!verify_merkle_proof_any_root(leaf, &proof, &tree.recent_roots()));
+        assert!(verify_merkle_proof_any_root(leaf, &tree.get_proof(0), &tree.recent_roots()));
+        assert!(!verify_merkle_proof_any_root(keccak256(b"fake"), &tree.get_proof(0), &tree.recent_roots()));
+    }
+
+    #[test]
+    fn test_quaternary_tree_matches_full_rebuild() {
+        // Reference: hash every level of four children from the leaves
+        fn rebuild_root(tree: &QuaternaryMerkleTree) -> [u8; 32] {
+            let mut level: Vec<[u8; 32]> =
+                (0..64).map(|i| tree.leaves.get(i).copied().unwrap_or(tree.zeros[0])).collect();
+            while level.len() > 1 {
+                level = level.chunks(4).map(hash_children).collect();
+            }
+            level[0]
+        }
+
+        let mut tree = QuaternaryMerkleTree::with_levels(3);
+        assert_eq!(tree.capacity(), 64);
+        assert_eq!(tree.get_root(), rebuild_root(&tree));
+        let mut checkpoint = None;
+        for n in 0..64u32 {
+            tree.insert(keccak256(&n.to_be_bytes()));
+            let root = tree.get_root();
+            assert_eq!(root, rebuild_root(&tree), "root after {n} inserts");
+            for i in 0..=n {
+                let proof = tree.get_nary_proof(i);
+                assert_eq!(proof[0].position as u32, i % 4);
+                assert!(verify_nary_merkle_proof::<4>(tree.leaves[i as usize], &proof, root));
+            }
+            if n == 20 {
+                checkpoint = Some((tree.checkpoint(), root));
+            }
+        }
+        assert_eq!(tree.try_insert([1u8; 32]), Err("Merkle tree is full"));
+        assert!(!verify_nary_merkle_proof::<4>(keccak256(b"fake"), &tree.get_nary_proof(5), tree.get_root()));
+        assert!(!verify_nary_merkle_proof::<2>(tree.leaves[5], &tree.get_nary_proof(5), tree.get_root()));
+
+        let (id, root) = checkpoint.unwrap();
+        tree.rollback(id).unwrap();
+        assert_eq!(tree.get_root(), root);
+        tree.insert(keccak256(b"after rollback"));
+        assert_eq!(tree.get_root(), rebuild_root(&tree));
+    }
+
+    #[test]
+    fn test_binary_nary_proof_matches_get_proof() {
+        let mut tree = IncrementalMerkleTree::new(4);
+        for n in 0..11u32 {
+            tree.insert(keccak256(&n.to_be_bytes()));
+        }
+        assert_eq!(compute_zeros(4), compute_nary_zeros::<2>(4));
+        for i in 0..11 {
+            let nary = tree.get_nary_proof(i);
+            let steps: Vec<_> = nary
+                .iter()
+                .map(|step| MerkleProofStep { is_left: step.position == 0, sibling: step.siblings[0] })
+                .collect();
+            assert_eq!(steps, tree.get_proof(i));
+            assert!(verify_nary_merkle_proof::<2>(tree.leaves[i as usize], &nary, tree.get_root()));
+        }
+    }
+
+    #[test]
+    fn test_subtree_roots() {
+        let mut tree = IncrementalMerkleTree::new(4);
+        for n in 0..11u32 {
+            tree.insert(keccak256(&n.to_be_bytes()));
+        }
+        assert_eq!(tree.subtree_root(4, 0), Ok(tree.get_root()));
+        assert_eq!(tree.subtree_root(0, 3), Ok(tree.leaves[3]));
+        assert_eq!(tree.subtree_root(1, 2), Ok(hash_pair(&tree.leaves[4], &tree.leaves[5])));
+        assert_eq!(tree.subtree_root(2, 3), Ok(tree.zeros[2]));
+        assert_eq!(tree.subtree_root(4, 1), Err("subtree index out of range"));
+        assert_eq!(tree.subtree_root(1, 8), Err("subtree index out of range"));
+        assert_eq!(tree.subtree_root(5, 0), Err("subtree level above the root"));
+        assert_eq!(tree.subtree_leaves(2, 2).unwrap(), &tree.leaves[8..11]);
+        assert!(tree.subtree_leaves(2, 3).unwrap().is_empty());
+
+        // Sync a stale copy by descending only into mismatching subtrees
+        let mut stale = IncrementalMerkleTree::new(4);
+        for leaf in &tree.leaves[..6] {
+            stale.insert(*leaf);
+        }
+        let mut pending = vec![(4usize, 0u32)];
+        let mut fetched = Vec::new();
+        while let Some((level, index)) = pending.pop() {
+            if stale.subtree_root(level, index) == tree.subtree_root(level, index) {
+                continue;
+            }
+            if level == 1 {
+                fetched.extend_from_slice(tree.subtree_leaves(level, index).unwrap());
+            } else {
+                pending.extend([(level - 1, index * 2 + 1), (level - 1, index * 2)]);
+            }
+        }
+        assert_eq!(fetched, &tree.leaves[6..11]);
+
+        let mut quaternary = QuaternaryMerkleTree::with_levels(2);
+        for n in 0..6u32 {
+            quaternary.insert(keccak256(&n.to_be_bytes()));
+        }
+        assert_eq!(quaternary.subtree_root(1, 0), Ok(hash_children(&quaternary.leaves[0..4])));
+        assert_eq!(quaternary.subtree_leaves(1, 1).unwrap(), &quaternary.leaves[4..6]);
+    }
+
     #[test]
     fn test_invalid_merkle_proof() {
         let mut tree = IncrementalMerkleTree::new(4);
@@ -488,14 +2581,411 @@ mod tests {
     fn test_derive_pubkey() {
         let key = [0x01u8; 32];
         let pubkey = derive_pubkey(&key);
-        assert_eq!(pubkey, keccak256(&key));
+        assert_eq!(pubkey, note_pubkey(&ProvingKey::derive(&key).auth_key, &derive_nullifier_key(&key)));
+        assert_ne!(pubkey, legacy_pubkey(&key));
         // Different key → different pubkey
         let other_key = [0x02u8; 32];
         assert_ne!(derive_pubkey(&key), derive_pubkey(&other_key));
     }
 
+    #[test]
+    fn test_constant_time_ownership() {
+        let key = [0x01u8; 32];
+        let pubkey = derive_pubkey(&key);
+        assert!(owns_pubkey(&key, &pubkey));
+        assert!(!owns_pubkey(&[0x02u8; 32], &pubkey));
+
+        let note = Note { amount: 1, pubkey, blinding: [0u8; 32] };
+        assert!(note.is_owned_by(&key));
+        assert!(!note.is_owned_by(&[0x02u8; 32]));
+    }
+
+    #[test]
+    fn test_spending_key_eq_and_debug() {
+        let a = SpendingKey::from([0x11u8; 32]);
+        let b = SpendingKey::from([0x11u8; 32]);
+        let c = SpendingKey::from([0x22u8; 32]);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.owns(&derive_pubkey(&[0x11u8; 32])));
+        let dbg = alloc::format!("{a:?}");
+        assert!(!dbg.contains("17"), "Debug must not leak key bytes");
+    }
+
+    #[test]
+    fn test_input_header_validation() {
+        let header = InputHeader::new(CircuitKind::Transfer);
+        assert!(header.validate(CircuitKind::Transfer).is_ok());
+        assert!(header.validate(CircuitKind::Withdraw).is_err());
+
+        let stale = InputHeader { kind: CircuitKind::Withdraw, version: INPUT_VERSION + 1 };
+        assert!(stale.validate(CircuitKind::Withdraw).is_err());
+
+        assert!(validate_end_marker(&INPUT_END_MARKER).is_ok());
+        assert!(validate_end_marker(&[0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_trailing_input_is_rejected() {
+        assert!(validate_input_consumed(STDIN_EXHAUSTED).is_ok());
+        // Anything the host wrote after the marker: another marker, an empty item, a second copy of the inputs
+        for next_len in [INPUT_END_MARKER.len(), 0, 1, 4_096] {
+            assert_eq!(validate_input_consumed(next_len), Err("unexpected data after the end marker"));
+        }
+    }
+
+    #[test]
+    fn test_transfer_check_rejects_duplicate_input() {
+        let key = [0x31u8; 32];
+        let pubkey = derive_pubkey(&key);
+        let notes = [
+            Note { amount: 700, pubkey, blinding: [1u8; 32] },
+            Note { amount: 300, pubkey, blinding: [2u8; 32] },
+        ];
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert(notes[0].commitment());
+        tree.insert(notes[1].commitment());
+        let mut inputs = TransferPrivateInputs {
+            input_notes: notes.clone(),
+            spender_keys: [SpenderKey::derive(&key); 2],
+            merkle_proofs: [tree.get_proof(0), tree.get_proof(1)],
+            output_notes: [
+                Note { amount: 600, pubkey: [9u8; 32], blinding: [3u8; 32] },
+                Note { amount: 400, pubkey, blinding: [4u8; 32] },
+            ],
+            root: tree.get_root(),
+            locks: [None, None],
+            association: None,
+            pool_binding: [0u8; 32],
+            output_encryption: None,
+            multisig: [None, None],
+            spend_signatures: Vec::new(),
+        };
+        inputs.sign(&key).unwrap();
+        let pv = inputs.check().unwrap();
+        assert_eq!(pv.nullifiers[0], compute_nullifier(&notes[0].commitment(), &key));
+        assert_eq!(pv.out_commitments[1], inputs.output_notes[1].commitment());
+        assert_eq!(pv.tree_levels, 4);
+
+        // Proofs of two depths: the shorter one could end at an inner node
+        let mut shallow = inputs.merkle_proofs.clone();
+        shallow[1].pop();
+        assert_eq!(spent_proof_levels(notes.iter().zip(&shallow)), Err("Merkle proofs differ in depth"));
+        assert_eq!(proof_levels(&shallow), Err("Merkle proofs differ in depth"));
+        assert_eq!(proof_levels(&inputs.merkle_proofs), Ok(4));
+
+        // The first note twice: both proofs verify, the outputs balance 1400
+        let mut duplicate = inputs.clone();
+        duplicate.input_notes[1] = notes[0].clone();
+        duplicate.merkle_proofs[1] = tree.get_proof(0);
+        duplicate.output_notes[0].amount = 1_000;
+        assert_eq!(duplicate.check(), Err("duplicate input note"));
+
+        let mut unbalanced = inputs.clone();
+        unbalanced.output_notes[0].amount += 1;
+        assert_eq!(unbalanced.check(), Err("amounts don't balance"));
+        // Out of range is reported before the (also failing) balance check
+        let mut out_of_range = inputs.clone();
+        out_of_range.output_notes[0].amount = MAX_AMOUNT;
+        assert_eq!(validate_transfer_inputs(&out_of_range), Err("output amount exceeds MAX_AMOUNT"));
+        assert_eq!(out_of_range.check(), Err("output amount exceeds MAX_AMOUNT"));
+        let mut bad_proof = inputs;
+        bad_proof.merkle_proofs[1] = tree.get_proof(0);
+        assert_eq!(bad_proof.check(), Err("Merkle proof invalid for input note 1"));
+    }
+
+    #[test]
+    fn test_transfer_single_input_with_dummy() {
+        let key = [0x31u8; 32];
+        let pubkey = derive_pubkey(&key);
+        let note = Note { amount: 700, pubkey, blinding: [1u8; 32] };
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert(note.commitment());
+        let input = |note: &Note| join_split::JoinSplitInput {
+            note: note.clone(),
+            spending_key: key,
+            merkle_proof: tree.get_proof(0),
+            lock: None,
+        };
+        let outputs = [
+            Note { amount: 500, pubkey: [9u8; 32], blinding: [3u8; 32] },
+            Note { amount: 200, pubkey, blinding: [4u8; 32] },
+        ];
+        let mut inputs = TransferPrivateInputs::single_input(input(&note), outputs, tree.get_root(), [5u8; 32]);
+        inputs.sign(&key).unwrap();
+        assert!(inputs.merkle_proofs[1].is_empty());
+        let pv = inputs.check().unwrap();
+        // The dummy's empty proof does not count
+        assert_eq!(pv.tree_levels, 4);
+        assert_eq!(pv.nullifiers[0], compute_nullifier(&note.commitment(), &key));
+        assert_eq!(pv.nullifiers[1], compute_nullifier(&Note::dummy([5u8; 32]).commitment(), &DUMMY_SPENDING_KEY));
+
+        // A fresh blinding gives the dummy a fresh nullifier
+        let mut other =
+            TransferPrivateInputs::single_input(input(&note), inputs.output_notes.clone(), tree.get_root(), [6u8; 32]);
+        other.sign(&key).unwrap();
+        assert_ne!(other.check().unwrap().nullifiers[1], pv.nullifiers[1]);
+
+        // With value, the dummy needs a Merkle proof like any input
+        let mut valued = inputs.clone();
+        valued.input_notes[1].amount = 100;
+        valued.output_notes[1].amount += 100;
+        assert_eq!(valued.check(), Err("Merkle proof invalid for input note 1"));
+
+        // The dummy key does not spend a real note
+        let mut stolen = inputs.clone();
+        stolen.spender_keys[0] = SpenderKey::derive(&DUMMY_SPENDING_KEY);
+        assert!(stolen.check().is_err());
+        // Nor does the owner's spending key as a legacy key
+        let mut raw = inputs;
+        raw.spender_keys[0] = SpenderKey::Legacy(key);
+        assert_eq!(raw.check(), Err("spending key does not match note pubkey"));
+        // The guest names the input it rejects
+        let err = raw.guest_check().unwrap_err();
+        assert_eq!(err, CheckError::at("input note", 0, "spending key does not match note pubkey"));
+        assert_eq!(alloc::format!("{err}"), "input note 0: spending key does not match note pubkey");
+    }
+
+    #[test]
+    fn test_transfer_output_encryption() {
+        let key = [0x31u8; 32];
+        let pubkey = derive_pubkey(&key);
+        let note = Note { amount: 700, pubkey, blinding: [1u8; 32] };
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert(note.commitment());
+        let input = join_split::JoinSplitInput { note, spending_key: key, merkle_proof: tree.get_proof(0), lock: None };
+        let outputs = [
+            Note { amount: 500, pubkey: [9u8; 32], blinding: [3u8; 32] },
+            Note { amount: 200, pubkey, blinding: [4u8; 32] },
+        ];
+        let mut inputs = TransferPrivateInputs::single_input(input, outputs, tree.get_root(), [5u8; 32]);
+        inputs.sign(&key).unwrap();
+        assert_eq!(inputs.check().unwrap().ciphertexts_hash, [0u8; 32]);
+
+        let encryption = |seed: u8| note_encryption::OutputEncryption {
+            viewing_pubkey: [seed; 32],
+            ephemeral_secret: [seed + 1; 32],
+            nonce: [seed + 2; 24],
+        };
+        let encryptions = [encryption(0x10), encryption(0x20)];
+        inputs.output_encryption = Some(encryptions.clone());
+        inputs.sign(&key).unwrap();
+        let payloads = [encryptions[0].encrypt(&inputs.output_notes[0]), encryptions[1].encrypt(&inputs.output_notes[1])];
+        let pv = inputs.check().unwrap();
+        assert_eq!(pv.ciphertexts_hash, note_encryption::ciphertexts_hash([&payloads[0], &payloads[1]]));
+
+        // The hash is of the outputs actually created, not of other notes
+        let mut other = inputs.clone();
+        other.output_notes[0].blinding = [7u8; 32];
+        assert_eq!(other.check(), Err("invalid spend authorization signature"));
+        other.sign(&key).unwrap();
+        assert_ne!(other.check().unwrap().ciphertexts_hash, pv.ciphertexts_hash);
+    }
+
+    #[test]
+    fn test_transfer_needs_owner_signature() {
+        let key = [0x31u8; 32];
+        let pubkey = derive_pubkey(&key);
+        let note = Note { amount: 700, pubkey, blinding: [1u8; 32] };
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert(note.commitment());
+        let input = join_split::JoinSplitInput { note, spending_key: key, merkle_proof: tree.get_proof(0), lock: None };
+        let outputs = [
+            Note { amount: 500, pubkey: [9u8; 32], blinding: [3u8; 32] },
+            Note { amount: 200, pubkey, blinding: [4u8; 32] },
+        ];
+        let mut inputs = TransferPrivateInputs::single_input(input, outputs, tree.get_root(), [5u8; 32]);
+        // Only the input with value signs; the dummy needs nothing
+        assert!(inputs.signers().eq([ProvingKey::derive(&key).auth_key]));
+        assert_eq!(inputs.check(), Err("missing spend authorization signature"));
+        let message = inputs.spend_message().unwrap();
+        assert_eq!(message, spend_auth_message(CircuitKind::Transfer, &inputs.check_unsigned().unwrap().encode()));
+
+        // A prover with the proving key cannot sign for the owner
+        let mut prover = inputs.clone();
+        prover.sign(&[0x32u8; 32]).unwrap();
+        assert_eq!(prover.check(), Err("missing spend authorization signature"));
+
+        // Signed, the spend is bound to its outputs
+        inputs.sign(&key).unwrap();
+        inputs.sign(&key).unwrap();
+        assert_eq!(inputs.spend_signatures.len(), 1);
+        assert!(inputs.check().is_ok());
+        let mut redirected = inputs.clone();
+        redirected.output_notes[0].pubkey = [8u8; 32];
+        assert_eq!(redirected.check(), Err("invalid spend authorization signature"));
+        let mut other_pool = inputs;
+        other_pool.pool_binding = [1u8; 32];
+        assert_eq!(other_pool.check(), Err("invalid spend authorization signature"));
+    }
+
+    #[test]
+    fn test_legacy_notes_spend_without_signature() {
+        let key = [0x31u8; 32];
+        let note = Note { amount: 700, pubkey: legacy_pubkey(&key), blinding: [1u8; 32] };
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert(note.commitment());
+        let mut withdraw = WithdrawPrivateInputs {
+            input_note: note.clone(),
+            spender_key: SpenderKey::Legacy(key),
+            merkle_proof: tree.get_proof(0),
+            root: tree.get_root(),
+            recipient: [0xDEu8; 20],
+            withdraw_amount: 500,
+            // The rest moves onto the key's current pubkey
+            change_note: Some(Note { amount: 200, pubkey: derive_pubkey(&key), blinding: [2u8; 32] }),
+            lock: None,
+            fee: 0,
+            relayer: [0u8; 20],
+            association: None,
+            pool_binding: [0u8; 32],
+            deadline: 0,
+            call_target: [0u8; 20],
+            calldata_hash: [0u8; 32],
+            multisig: None,
+            spend_signatures: Vec::new(),
+        };
+        let pv = withdraw.check().unwrap();
+        let mut preimage = [0u8; 64];
+        preimage[..32].copy_from_slice(&note.commitment());
+        preimage[32..].copy_from_slice(&key);
+        assert_eq!(pv.nullifier, keccak256(&preimage));
+
+        // A current-key note still needs its signature
+        withdraw.input_note.pubkey = derive_pubkey(&key);
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert(withdraw.input_note.commitment());
+        withdraw.merkle_proof = tree.get_proof(0);
+        withdraw.root = tree.get_root();
+        assert_eq!(withdraw.check(), Err("spending key does not match note pubkey"));
+        withdraw.spender_key = SpenderKey::derive(&key);
+        assert_eq!(withdraw.check(), Err("missing spend authorization signature"));
+        withdraw.sign(&key).unwrap();
+        assert!(withdraw.check().is_ok());
+    }
+
+    #[test]
+    fn test_output_amount_bounds() {
+        assert_eq!(check_output_amount(MAX_AMOUNT - 1), Ok(()));
+        assert_eq!(check_output_amount(MAX_AMOUNT), Err("output amount exceeds MAX_AMOUNT"));
+        assert_eq!(check_output_amount(u64::MAX), Err("output amount exceeds MAX_AMOUNT"));
+        assert_eq!(check_output_amount(0).is_ok(), !REQUIRE_NONZERO_OUTPUTS);
+        // Sixteen in-range amounts cannot wrap a u64
+        assert!((MAX_AMOUNT - 1).checked_mul(16).is_some());
+    }
+
+    #[test]
+    fn test_public_values_roundtrip() {
+        let w = WithdrawPublicValues {
+            root: [1u8; 32],
+            nullifier: [2u8; 32],
+            recipient: [0xDEu8; 20],
+            amount: 600_000,
+            change_commitment: [3u8; 32],
+            reclaim_deadline: 1_700_000_000,
+            relayer: [0xBEu8; 20],
+            fee: 20_000,
+            association_root: [6u8; 32],
+            pool_binding: [7u8; 32],
+            deadline: 1_700_003_600,
+            call_binding: [8u8; 32],
+            tree_levels: 20,
+        };
+        let bytes = w.encode();
+        assert_eq!(WithdrawPublicValues::decode(&bytes), Some(w));
+        let mut dirty = bytes;
+        dirty[100] = 1; // amount wider than u64
+        assert_eq!(WithdrawPublicValues::decode(&dirty), None);
+        let mut dirty = bytes;
+        dirty[170] = 1; // deadline wider than u64
+        assert_eq!(WithdrawPublicValues::decode(&dirty), None);
+        let mut dirty = bytes;
+        dirty[200] = 1; // relayer padding
+        assert_eq!(WithdrawPublicValues::decode(&dirty), None);
+        let mut dirty = bytes;
+        dirty[330] = 1; // withdrawal deadline wider than u64
+        assert_eq!(WithdrawPublicValues::decode(&dirty), None);
+        let mut dirty = bytes;
+        dirty[400] = 1; // tree depth wider than u64
+        assert_eq!(WithdrawPublicValues::decode(&dirty), None);
+        assert_eq!(WithdrawPublicValues::decode(&bytes[..384]), None);
+
+        let t = TransferPublicValues {
+            root: [1u8; 32],
+            nullifiers: [[2u8; 32], [3u8; 32]],
+            out_commitments: [[4u8; 32], [5u8; 32]],
+            reclaim_deadline: 0,
+            association_root: [6u8; 32],
+            pool_binding: [7u8; 32],
+            ciphertexts_hash: [8u8; 32],
+            tree_levels: 20,
+        };
+        let bytes = t.encode();
+        assert_eq!((&bytes[288..319], bytes[319]), (&[0u8; 31][..], 20)); // uint256 tree depth
+        assert_eq!(TransferPublicValues::decode(&bytes), Some(t));
+        let mut dirty = bytes;
+        dirty[300] = 1; // tree depth wider than u64
+        assert_eq!(TransferPublicValues::decode(&dirty), None);
+        assert_eq!(TransferPublicValues::decode(&bytes[..288]), None);
+    }
+
+    #[test]
+    fn test_pool_binding() {
+        let pool = [0x11u8; 20];
+        // keccak256(abi.encode(uint256(9745), address(pool)))
+        let mut encoded = [0u8; 64];
+        encoded[30..32].copy_from_slice(&9745u16.to_be_bytes());
+        encoded[44..].copy_from_slice(&pool);
+        assert_eq!(pool_binding(9745, &pool), keccak256(&encoded));
+        // Another chain or another pool is another binding
+        assert_ne!(pool_binding(9745, &pool), pool_binding(9746, &pool));
+        assert_ne!(pool_binding(9745, &pool), pool_binding(9745, &[0x12u8; 20]));
+    }
+
+    #[test]
+    fn test_call_binding() {
+        let target = [0x22u8; 20];
+        let data_hash = keccak256(b"swap()");
+        // keccak256(abi.encode(address(target), keccak256(data)))
+        let mut encoded = [0u8; 64];
+        encoded[12..32].copy_from_slice(&target);
+        encoded[32..].copy_from_slice(&data_hash);
+        assert_eq!(call_binding(&target, &data_hash), Ok(keccak256(&encoded)));
+        // A plain withdrawal commits zero; calldata alone is meaningless
+        assert_eq!(call_binding(&[0u8; 20], &[0u8; 32]), Ok([0u8; 32]));
+        assert_eq!(call_binding(&[0u8; 20], &data_hash), Err("calldata hash without a call target"));
+    }
+
+    #[test]
+    fn test_merkle_proof_packing() {
+        let mut tree = IncrementalMerkleTree::new(20);
+        for i in 0..13u32 {
+            tree.insert(keccak256(&i.to_be_bytes()));
+        }
+        for index in [0u32, 5, 12] {
+            let steps = tree.get_proof(index);
+            let proof = MerkleProof::from_steps(&steps).unwrap();
+            assert_eq!(proof.path_bits, index);
+            assert_eq!(proof.steps(), steps);
+
+            let packed = proof.to_packed_bytes();
+            assert_eq!(packed.len(), 32 * 21);
+            assert_eq!(MerkleProof::from_packed_bytes(&packed), Ok(proof.clone()));
+            assert!(verify_merkle_proof(tree.leaves[index as usize], &proof.steps(), tree.get_root()));
+        }
+
+        let packed = MerkleProof::from_steps(&tree.get_proof(12)).unwrap().to_packed_bytes();
+        assert!(MerkleProof::from_packed_bytes(&packed[..packed.len() - 1]).is_err());
+        // Bit 20 set on a 20-level proof
+        let mut high = packed.clone();
+        high[29] |= 0x10;
+        assert_eq!(MerkleProof::from_packed_bytes(&high), Err("path bits above the proof's depth"));
+        let step = MerkleProofStep { is_left: true, sibling: [0u8; 32] };
+        assert!(MerkleProof::from_steps(&vec![step; 33]).is_err());
+    }
+
     // Helper to convert hex string to [u8; 32]
-    fn hex_to_bytes32(hex: &str) -> [u8; 32] {
+    pub(crate) fn hex_to_bytes32(hex: &str) -> [u8; 32] {
         let mut result = [0u8; 32];
         for i in 0..32 {
             result[i] = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
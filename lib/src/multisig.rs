@@ -0,0 +1,224 @@
+//! 2-of-2 multisig notes.
+//!
+//! A multisig note is held jointly by two owners and can only be spent with
+//! both of their keys (see `SpenderKey`). Like a cancellable note it is an
+//! ordinary `Note` whose `pubkey` is a hash committing to the owners:
+//!
+//!   multisig_pubkey = keccak256(MULTISIG_DOMAIN || owner_pubkey_0 || owner_pubkey_1)
+//!
+//! so commitments, the Merkle tree and the pool contract do not change, and
+//! shared funds stay in one note instead of being split between the owners.
+//! The spender reveals the owners privately to the circuit, gives owner 0's
+//! key as the input's and owner 1's as the cosigner's, and the circuit checks
+//! both against the note's pubkey. Both owners sign the spend (see
+//! `input_signers`), unless their keys are legacy ones.
+//!
+//! The nullifier takes both nullifier keys, so neither owner alone (nor the
+//! payer, who knows the blinding) can compute it:
+//!
+//!   nullifier = keccak256(NULLIFIER_DOMAIN || commitment || nullifier_key_0 || nullifier_key_1)
+//!
+//! Proving needs both owners' proving keys in one witness, so whoever runs
+//! the prover learns both; it still cannot spend without both signatures.
+//! A withdrawal's change goes back to the same two owners.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cancellable::{authorize_with_key, CancellableLock, SpendAuthorization, SpendPath};
+use crate::{keccak256, Note, SpenderKey};
+
+/// Domain tag for multisig pubkeys (exactly 32 bytes).
+pub const MULTISIG_DOMAIN: [u8; 32] = *b"shielded-pool/multisig-pubkeys/1";
+
+/// Domain tag for multisig-note nullifiers (exactly 32 bytes).
+pub const NULLIFIER_DOMAIN: [u8; 32] = *b"shielded-pool/multisig-nullify/1";
+
+/// The two owners of a multisig note, in order.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MultisigLock {
+    pub owner_pubkeys: [[u8; 32]; 2],
+}
+
+impl MultisigLock {
+    /// The value stored in the note's `pubkey` field.
+    pub fn multisig_pubkey(&self) -> [u8; 32] {
+        let mut preimage = [0u8; 96];
+        preimage[0..32].copy_from_slice(&MULTISIG_DOMAIN);
+        preimage[32..64].copy_from_slice(&self.owner_pubkeys[0]);
+        preimage[64..96].copy_from_slice(&self.owner_pubkeys[1]);
+        keccak256(&preimage)
+    }
+
+    /// Build the multisig note for `amount`.
+    pub fn note(&self, amount: u64, blinding: [u8; 32]) -> Note {
+        Note { amount, pubkey: self.multisig_pubkey(), blinding }
+    }
+
+    /// Whether `note` is held by these owners.
+    pub fn locks(&self, note: &Note) -> bool {
+        self.multisig_pubkey() == note.pubkey
+    }
+}
+
+/// The second owner's half of a multisig spend. The input's key is owner 0's;
+/// this carries owner 1's.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "testing", derive(arbitrary::Arbitrary))]
+pub struct MultisigSpend {
+    pub lock: MultisigLock,
+    /// Key of `lock.owner_pubkeys[1]`
+    pub cosigner: SpenderKey,
+}
+
+/// Nullifier of a multisig note, from both owners' nullifier keys in lock
+/// order.
+pub fn multisig_nullifier(commitment: &[u8; 32], nullifier_keys: [&[u8; 32]; 2]) -> [u8; 32] {
+    let mut preimage = [0u8; 128];
+    preimage[0..32].copy_from_slice(&NULLIFIER_DOMAIN);
+    preimage[32..64].copy_from_slice(commitment);
+    preimage[64..96].copy_from_slice(nullifier_keys[0]);
+    preimage[96..128].copy_from_slice(nullifier_keys[1]);
+    keccak256(&preimage)
+}
+
+/// Check that `key` and the cosigner's together may spend the multisig
+/// `note` and derive its nullifier.
+pub fn authorize_multisig_spend(
+    note: &Note,
+    key: &SpenderKey,
+    spend: &MultisigSpend,
+) -> Result<SpendAuthorization, &'static str> {
+    if !spend.lock.locks(note) {
+        return Err("multisig lock does not match note pubkey");
+    }
+    let [owner0, owner1] = &spend.lock.owner_pubkeys;
+    if !key.owns(owner0) || !spend.cosigner.owns(owner1) {
+        return Err("keys do not match the multisig owners");
+    }
+    Ok(SpendAuthorization {
+        path: SpendPath::Multisig,
+        nullifier: multisig_nullifier(&note.commitment(), [key.nullifier_key(), spend.cosigner.nullifier_key()]),
+        reclaim_deadline: None,
+    })
+}
+
+/// Authorize an input that may be plain, cancellable or multisig, from the
+/// spender's key. At most one of `lock` and `multisig` may be set.
+pub fn authorize_input(
+    note: &Note,
+    key: &SpenderKey,
+    lock: Option<&CancellableLock>,
+    multisig: Option<&MultisigSpend>,
+) -> Result<SpendAuthorization, &'static str> {
+    match (lock, multisig) {
+        (_, None) => authorize_with_key(note, key, lock),
+        (None, Some(spend)) => authorize_multisig_spend(note, key, spend),
+        (Some(_), Some(_)) => Err("input note cannot be both cancellable and multisig"),
+    }
+}
+
+/// The public keys an input's spend must be signed under: the spender's and,
+/// for a multisig input, the cosigner's. Legacy keys sign nothing.
+pub fn input_signers(key: &SpenderKey, multisig: Option<&MultisigSpend>) -> impl Iterator<Item = [u8; 32]> {
+    key.auth_key().into_iter().chain(multisig.and_then(|spend| spend.cosigner.auth_key()))
+}
+
+/// Whether a change note with `pubkey` goes back to whoever spent the input:
+/// the key's owner, or both owners of a multisig input.
+pub fn owns_change(key: &SpenderKey, multisig: Option<&MultisigSpend>, pubkey: &[u8; 32]) -> bool {
+    match multisig {
+        Some(spend) => spend.lock.multisig_pubkey() == *pubkey,
+        None => key.owns_change(pubkey),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{legacy_pubkey, nullifier_from_key};
+
+    const ALICE_SK: [u8; 32] = [0x11; 32];
+    const BOB_SK: [u8; 32] = [0x22; 32];
+
+    fn alice() -> SpenderKey {
+        SpenderKey::derive(&ALICE_SK)
+    }
+
+    fn bob() -> SpenderKey {
+        SpenderKey::derive(&BOB_SK)
+    }
+
+    fn spend() -> MultisigSpend {
+        MultisigSpend { lock: MultisigLock { owner_pubkeys: [alice().pubkey(), bob().pubkey()] }, cosigner: bob() }
+    }
+
+    #[test]
+    fn test_both_keys_spend_the_note() {
+        let spend = spend();
+        let note = spend.lock.note(500, [0x33; 32]);
+        let auth = authorize_input(&note, &alice(), None, Some(&spend)).unwrap();
+        assert_eq!(auth.path, SpendPath::Multisig);
+        assert_eq!(auth.reclaim_deadline, None);
+        let [alice_nk, bob_nk] = [*alice().nullifier_key(), *bob().nullifier_key()];
+        assert_eq!(auth.nullifier, multisig_nullifier(&note.commitment(), [&alice_nk, &bob_nk]));
+        assert_ne!(auth.nullifier, nullifier_from_key(&note.commitment(), &alice_nk));
+        assert_ne!(auth.nullifier, multisig_nullifier(&note.commitment(), [&bob_nk, &alice_nk]));
+        // Both owners sign
+        assert!(input_signers(&alice(), Some(&spend)).eq([alice().auth_key().unwrap(), bob().auth_key().unwrap()]));
+    }
+
+    #[test]
+    fn test_legacy_owners_spend_with_their_old_nullifier() {
+        let lock = MultisigLock { owner_pubkeys: [legacy_pubkey(&ALICE_SK), legacy_pubkey(&BOB_SK)] };
+        let spend = MultisigSpend { lock, cosigner: SpenderKey::Legacy(BOB_SK) };
+        let note = lock.note(500, [0x33; 32]);
+        let auth = authorize_input(&note, &SpenderKey::Legacy(ALICE_SK), None, Some(&spend)).unwrap();
+        assert_eq!(auth.nullifier, multisig_nullifier(&note.commitment(), [&ALICE_SK, &BOB_SK]));
+        assert_eq!(input_signers(&SpenderKey::Legacy(ALICE_SK), Some(&spend)).count(), 0);
+        // Current keys do not open a legacy multisig note
+        let current = MultisigSpend { cosigner: bob(), ..spend };
+        assert!(authorize_input(&note, &alice(), None, Some(&current)).is_err());
+    }
+
+    #[test]
+    fn test_rejects_one_key_or_wrong_lock() {
+        let spend = spend();
+        let note = spend.lock.note(500, [0x33; 32]);
+        // Neither key spends it alone
+        assert!(authorize_input(&note, &alice(), None, None).is_err());
+        assert!(authorize_input(&note, &bob(), None, None).is_err());
+        let alone = MultisigSpend { cosigner: alice(), ..spend };
+        assert!(authorize_multisig_spend(&note, &alice(), &alone).is_err());
+        // Owners are ordered
+        let swapped = MultisigSpend { cosigner: alice(), ..spend };
+        assert!(authorize_multisig_spend(&note, &bob(), &swapped).is_err());
+        // Another pair of owners does not open it
+        let carol = SpenderKey::derive(&[0x44; 32]);
+        let lock = MultisigLock { owner_pubkeys: [alice().pubkey(), carol.pubkey()] };
+        let other = MultisigSpend { lock, cosigner: carol };
+        assert_eq!(
+            authorize_multisig_spend(&note, &alice(), &other),
+            Err("multisig lock does not match note pubkey")
+        );
+        // A plain note cannot be spent as multisig
+        let plain = Note { amount: 500, pubkey: alice().pubkey(), blinding: [0x33; 32] };
+        assert!(authorize_input(&plain, &alice(), None, Some(&spend)).is_err());
+        let lock =
+            CancellableLock { recipient_pubkey: alice().pubkey(), sender_pubkey: bob().pubkey(), reclaim_deadline: 1 };
+        assert_eq!(
+            authorize_input(&note, &alice(), Some(&lock), Some(&spend)),
+            Err("input note cannot be both cancellable and multisig")
+        );
+    }
+
+    #[test]
+    fn test_change_goes_back_to_both_owners() {
+        let spend = spend();
+        let shared = spend.lock.multisig_pubkey();
+        assert!(owns_change(&alice(), Some(&spend), &shared));
+        assert!(!owns_change(&alice(), Some(&spend), &alice().pubkey()));
+        assert!(owns_change(&alice(), None, &alice().pubkey()));
+        assert!(!owns_change(&alice(), None, &shared));
+    }
+}
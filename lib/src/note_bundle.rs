@@ -0,0 +1,288 @@
+//! Note bundles: a compact, copy-pasteable encoding of a single note.
+//!
+//! A sender hands the recipient everything needed to spend a note they
+//! received out-of-band (chat, QR code, ...), without the encrypted on-chain
+//! payload. The bundle is `spnote1:` followed by unpadded base64url of:
+//!
+//!   version      1 byte   (NOTE_BUNDLE_VERSION)
+//!   flags        1 byte   (bit 0: label present, bit 1: cancellable lock present)
+//!   amount       8 bytes  big-endian
+//!   pubkey       32 bytes
+//!   blinding     32 bytes
+//!   leaf_index   4 bytes  big-endian
+//!   lock         recipient pubkey 32 + sender pubkey 32 + deadline 8 BE (only if flagged)
+//!   label        1-byte length + UTF-8 (only if flagged)
+//!   checksum     4 bytes  keccak256(everything above)[..4]
+//!
+//! The checksum catches truncated or mangled copies; it is not authentication.
+//! Anyone holding a bundle learns the note's amount and commitment, but still
+//! needs the owner's spending key to spend it. For a cancellable note the
+//! bundle carries the lock, which the recipient needs to claim it.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::{keccak256, CancellableLock, Note};
+
+/// Human-readable prefix; the trailing digit tracks the major format.
+pub const NOTE_BUNDLE_PREFIX: &str = "spnote1:";
+
+/// Binary layout version.
+pub const NOTE_BUNDLE_VERSION: u8 = 1;
+
+/// Longest label a bundle can carry (length is a single byte).
+pub const MAX_LABEL_LEN: usize = 255;
+
+const FLAG_LABEL: u8 = 0x01;
+const FLAG_LOCK: u8 = 0x02;
+const FIXED_LEN: usize = 1 + 1 + 8 + 32 + 32 + 4;
+const LOCK_LEN: usize = 32 + 32 + 8;
+const CHECKSUM_LEN: usize = 4;
+
+/// A note plus its position in the pool's Merkle tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NoteBundle {
+    pub note: Note,
+    pub leaf_index: u32,
+    pub label: Option<String>,
+    /// Present for cancellable notes
+    pub lock: Option<CancellableLock>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteBundleError {
+    /// Missing `spnote1:` prefix.
+    BadPrefix,
+    /// Payload is not valid base64url.
+    BadEncoding,
+    /// Payload is shorter or longer than its header says.
+    BadLength,
+    /// Unknown binary layout version.
+    UnsupportedVersion(u8),
+    /// Reserved flag bits are set.
+    UnknownFlags(u8),
+    /// Label is not valid UTF-8.
+    BadLabel,
+    /// Label does not fit in a bundle.
+    LabelTooLong,
+    /// Checksum does not match the payload.
+    ChecksumMismatch,
+    /// The cancellable lock does not hash to the note's pubkey.
+    LockMismatch,
+}
+
+impl core::fmt::Display for NoteBundleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            NoteBundleError::BadPrefix => {
+                write!(f, "not a note bundle (expected prefix {NOTE_BUNDLE_PREFIX})")
+            }
+            NoteBundleError::BadEncoding => write!(f, "note bundle is not valid base64url"),
+            NoteBundleError::BadLength => write!(f, "note bundle has the wrong length"),
+            NoteBundleError::UnsupportedVersion(v) => {
+                write!(f, "unsupported note bundle version {v}")
+            }
+            NoteBundleError::UnknownFlags(flags) => {
+                write!(f, "note bundle has unknown flags {flags:#04x}")
+            }
+            NoteBundleError::BadLabel => write!(f, "note bundle label is not valid UTF-8"),
+            NoteBundleError::LabelTooLong => {
+                write!(f, "note label exceeds {MAX_LABEL_LEN} bytes")
+            }
+            NoteBundleError::ChecksumMismatch => {
+                write!(f, "note bundle checksum mismatch (truncated or mistyped?)")
+            }
+            NoteBundleError::LockMismatch => {
+                write!(f, "note bundle lock does not match the note's pubkey")
+            }
+        }
+    }
+}
+
+impl NoteBundle {
+    pub fn new(note: Note, leaf_index: u32, label: Option<String>) -> Self {
+        NoteBundle { note, leaf_index, label, lock: None }
+    }
+
+    /// Attach the lock of a cancellable note.
+    pub fn with_lock(mut self, lock: CancellableLock) -> Self {
+        self.lock = Some(lock);
+        self
+    }
+
+    /// Encode as `spnote1:<base64url>`.
+    pub fn encode(&self) -> Result<String, NoteBundleError> {
+        let label = self.label.as_deref().unwrap_or("");
+        if label.len() > MAX_LABEL_LEN {
+            return Err(NoteBundleError::LabelTooLong);
+        }
+        if self.lock.is_some_and(|lock| !lock.locks(&self.note)) {
+            return Err(NoteBundleError::LockMismatch);
+        }
+
+        let mut flags = 0;
+        if self.label.is_some() {
+            flags |= FLAG_LABEL;
+        }
+        if self.lock.is_some() {
+            flags |= FLAG_LOCK;
+        }
+
+        let mut buf = Vec::with_capacity(FIXED_LEN + LOCK_LEN + 1 + label.len() + CHECKSUM_LEN);
+        buf.push(NOTE_BUNDLE_VERSION);
+        buf.push(flags);
+        buf.extend_from_slice(&self.note.amount.to_be_bytes());
+        buf.extend_from_slice(&self.note.pubkey);
+        buf.extend_from_slice(&self.note.blinding);
+        buf.extend_from_slice(&self.leaf_index.to_be_bytes());
+        if let Some(lock) = &self.lock {
+            buf.extend_from_slice(&lock.recipient_pubkey);
+            buf.extend_from_slice(&lock.sender_pubkey);
+            buf.extend_from_slice(&lock.reclaim_deadline.to_be_bytes());
+        }
+        if self.label.is_some() {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        let checksum = keccak256(&buf);
+        buf.extend_from_slice(&checksum[..CHECKSUM_LEN]);
+
+        let mut out = String::from(NOTE_BUNDLE_PREFIX);
+        URL_SAFE_NO_PAD.encode_string(&buf, &mut out);
+        Ok(out)
+    }
+
+    /// Decode a bundle produced by [`NoteBundle::encode`].
+    /// Surrounding whitespace is ignored.
+    pub fn decode(s: &str) -> Result<Self, NoteBundleError> {
+        let payload = s.trim().strip_prefix(NOTE_BUNDLE_PREFIX).ok_or(NoteBundleError::BadPrefix)?;
+        let buf = URL_SAFE_NO_PAD.decode(payload).map_err(|_| NoteBundleError::BadEncoding)?;
+        if buf.len() < FIXED_LEN + CHECKSUM_LEN {
+            return Err(NoteBundleError::BadLength);
+        }
+
+        let (body, checksum) = buf.split_at(buf.len() - CHECKSUM_LEN);
+        if keccak256(body)[..CHECKSUM_LEN] != *checksum {
+            return Err(NoteBundleError::ChecksumMismatch);
+        }
+        if body[0] != NOTE_BUNDLE_VERSION {
+            return Err(NoteBundleError::UnsupportedVersion(body[0]));
+        }
+        let flags = body[1];
+        if flags & !(FLAG_LABEL | FLAG_LOCK) != 0 {
+            return Err(NoteBundleError::UnknownFlags(flags));
+        }
+
+        let mut amount = [0u8; 8];
+        amount.copy_from_slice(&body[2..10]);
+        let mut pubkey = [0u8; 32];
+        pubkey.copy_from_slice(&body[10..42]);
+        let mut blinding = [0u8; 32];
+        blinding.copy_from_slice(&body[42..74]);
+        let mut leaf_index = [0u8; 4];
+        leaf_index.copy_from_slice(&body[74..78]);
+
+        let mut rest = &body[FIXED_LEN..];
+        let lock = if flags & FLAG_LOCK != 0 {
+            if rest.len() < LOCK_LEN {
+                return Err(NoteBundleError::BadLength);
+            }
+            let (fields, tail) = rest.split_at(LOCK_LEN);
+            rest = tail;
+            let mut lock = CancellableLock {
+                recipient_pubkey: [0u8; 32],
+                sender_pubkey: [0u8; 32],
+                reclaim_deadline: 0,
+            };
+            lock.recipient_pubkey.copy_from_slice(&fields[0..32]);
+            lock.sender_pubkey.copy_from_slice(&fields[32..64]);
+            let mut deadline = [0u8; 8];
+            deadline.copy_from_slice(&fields[64..72]);
+            lock.reclaim_deadline = u64::from_be_bytes(deadline);
+            Some(lock)
+        } else {
+            None
+        };
+
+        let label = if flags & FLAG_LABEL != 0 {
+            let (&len, text) = rest.split_first().ok_or(NoteBundleError::BadLength)?;
+            if text.len() != len as usize {
+                return Err(NoteBundleError::BadLength);
+            }
+            let text = core::str::from_utf8(text).map_err(|_| NoteBundleError::BadLabel)?;
+            Some(String::from(text))
+        } else {
+            if !rest.is_empty() {
+                return Err(NoteBundleError::BadLength);
+            }
+            None
+        };
+
+        let note = Note { amount: u64::from_be_bytes(amount), pubkey, blinding };
+        if lock.is_some_and(|lock| !lock.locks(&note)) {
+            return Err(NoteBundleError::LockMismatch);
+        }
+        Ok(NoteBundle { note, leaf_index: u32::from_be_bytes(leaf_index), label, lock })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle(label: Option<&str>) -> NoteBundle {
+        NoteBundle::new(
+            Note { amount: 500_000, pubkey: [0xAB; 32], blinding: [0xCD; 32] },
+            7,
+            label.map(String::from),
+        )
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for b in [bundle(None), bundle(Some("from alice")), bundle(Some(""))] {
+            let encoded = b.encode().unwrap();
+            assert!(encoded.starts_with(NOTE_BUNDLE_PREFIX));
+            assert_eq!(NoteBundle::decode(&encoded).unwrap(), b);
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_corruption() {
+        let encoded = bundle(Some("from alice")).encode().unwrap();
+        assert_eq!(NoteBundle::decode(&encoded[1..]), Err(NoteBundleError::BadPrefix));
+        assert_eq!(
+            NoteBundle::decode(&encoded[..encoded.len() - 4]),
+            Err(NoteBundleError::ChecksumMismatch)
+        );
+
+        // Flip one character in the payload.
+        let mut chars: Vec<char> = encoded.chars().collect();
+        let i = NOTE_BUNDLE_PREFIX.len() + 5;
+        chars[i] = if chars[i] == 'A' { 'B' } else { 'A' };
+        let mangled: String = chars.into_iter().collect();
+        assert_eq!(NoteBundle::decode(&mangled), Err(NoteBundleError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn test_cancellable_roundtrip() {
+        let lock = CancellableLock {
+            recipient_pubkey: [0x11; 32],
+            sender_pubkey: [0x22; 32],
+            reclaim_deadline: 1_700_000_000,
+        };
+        let b = NoteBundle::new(lock.note(250_000, [0x33; 32]), 9, Some("refundable".into())).with_lock(lock);
+        assert_eq!(NoteBundle::decode(&b.encode().unwrap()).unwrap(), b);
+
+        let other = CancellableLock { reclaim_deadline: 1, ..lock };
+        let wrong = NoteBundle { lock: Some(other), ..b };
+        assert_eq!(wrong.encode(), Err(NoteBundleError::LockMismatch));
+    }
+
+    #[test]
+    fn test_label_too_long() {
+        let long = "a".repeat(MAX_LABEL_LEN + 1);
+        assert_eq!(bundle(Some(&long)).encode(), Err(NoteBundleError::LabelTooLong));
+    }
+}
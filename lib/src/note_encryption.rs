@@ -0,0 +1,266 @@
+//! Provable note encryption: binding a transfer's encrypted outputs to its
+//! output notes.
+//!
+//! A transfer posts one encrypted payload per output so the recipient can
+//! find and spend the note. The payload is a NaCl box (x25519 +
+//! XSalsa20-Poly1305) under a one-time ephemeral key:
+//!
+//!   ephemeral_pubkey(32) || nonce(24) || box(note_plaintext(note))
+//!
+//! Nothing stops a sender from posting garbage instead, and the recipient
+//! then can never reconstruct the note. A transfer can opt in to proving its
+//! payloads: given the recipient's viewing pubkey, the ephemeral secret and
+//! the nonce, the circuit encrypts each output note itself and commits
+//!
+//!   ciphertexts_hash = keccak256(keccak256(payload1) || keccak256(payload2))
+//!
+//! and the pool rejects the transfer unless the posted payloads hash to it.
+//! Zero means the transfer did not opt in (a payload carrying a wallet
+//! message, for instance, is more than the note); the pool accepts both.
+//!
+//! The proof shows the payload opens to the note under the viewing key the
+//! sender chose. Whether that is the recipient's key is still up to the
+//! sender, as it is for who the note is paid to.
+
+use alloc::vec::Vec;
+use crypto_box::{aead::Aead, Nonce, PublicKey, SalsaBox, SecretKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{keccak256, Note};
+
+/// Bytes before the ciphertext in a payload: ephemeral pubkey and nonce.
+pub const PAYLOAD_HEADER_LEN: usize = 32 + 24;
+
+/// What an output's payload is encrypted with. Both the ephemeral secret
+/// and the nonce must be fresh randomness for every payload (the nonce may
+/// start with a detection tag, see `detection`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputEncryption {
+    /// The recipient's viewing public key (x25519)
+    pub viewing_pubkey: [u8; 32],
+    /// One-time x25519 secret; its public key leads the payload
+    pub ephemeral_secret: [u8; 32],
+    pub nonce: [u8; 24],
+}
+
+impl OutputEncryption {
+    /// The payload to post for `note`:
+    /// ephemeral_pubkey(32) || nonce(24) || ciphertext
+    pub fn encrypt(&self, note: &Note) -> Vec<u8> {
+        let ephemeral = SecretKey::from(self.ephemeral_secret);
+        let salsa_box = SalsaBox::new(&PublicKey::from(self.viewing_pubkey), &ephemeral);
+        let ciphertext = salsa_box
+            .encrypt(&Nonce::from(self.nonce), note_plaintext(note).as_slice())
+            .expect("encryption should not fail");
+        let mut payload = Vec::with_capacity(PAYLOAD_HEADER_LEN + ciphertext.len());
+        payload.extend_from_slice(ephemeral.public_key().as_bytes());
+        payload.extend_from_slice(&self.nonce);
+        payload.extend_from_slice(&ciphertext);
+        payload
+    }
+}
+
+/// The plaintext of a note's payload, byte for byte what the TypeScript
+/// SDK's encryptNote() encrypts:
+///   {"amount":"<decimal>","pubkey":"0x<hex>","blinding":"0x<hex>"}
+pub fn note_plaintext(note: &Note) -> Vec<u8> {
+    let mut out = Vec::with_capacity(176);
+    out.extend_from_slice(b"{\"amount\":\"");
+    decimal(&mut out, note.amount);
+    out.extend_from_slice(b"\",\"pubkey\":\"0x");
+    hex(&mut out, &note.pubkey);
+    out.extend_from_slice(b"\",\"blinding\":\"0x");
+    hex(&mut out, &note.blinding);
+    out.extend_from_slice(b"\"}");
+    out
+}
+
+/// Read a note back from a payload's plaintext. Accepts the three string
+/// fields in any order, as the TypeScript SDK and the host's serde_json
+/// encoding differ in key order; anything else is `None`.
+pub fn parse_note_plaintext(plaintext: &[u8]) -> Option<Note> {
+    let body = plaintext.strip_prefix(b"{")?.strip_suffix(b"}")?;
+    let (mut amount, mut pubkey, mut blinding) = (None, None, None);
+    for field in body.split(|&b| b == b',') {
+        let (key, value) = match field.iter().position(|&b| b == b':') {
+            Some(i) => (&field[..i], &field[i + 1..]),
+            None => return None,
+        };
+        let value = value.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+        let repeated = match key {
+            b"\"amount\"" => amount.replace(parse_decimal(value)?).is_some(),
+            b"\"pubkey\"" => pubkey.replace(parse_hex32(value)?).is_some(),
+            b"\"blinding\"" => blinding.replace(parse_hex32(value)?).is_some(),
+            _ => return None,
+        };
+        if repeated {
+            return None;
+        }
+    }
+    Some(Note { amount: amount?, pubkey: pubkey?, blinding: blinding? })
+}
+
+/// The x25519 public key of a viewing secret.
+pub fn viewing_pubkey(viewing_secret: &[u8; 32]) -> [u8; 32] {
+    *SecretKey::from(*viewing_secret).public_key().as_bytes()
+}
+
+/// Decrypt a payload posted for `viewing_secret`'s public key, or `None` if
+/// it is not for that key.
+pub fn open_payload(payload: &[u8], viewing_secret: &[u8; 32]) -> Option<Vec<u8>> {
+    if payload.len() < PAYLOAD_HEADER_LEN {
+        return None;
+    }
+    let ephemeral = PublicKey::from(<[u8; 32]>::try_from(&payload[..32]).ok()?);
+    let nonce = Nonce::from(<[u8; 24]>::try_from(&payload[32..PAYLOAD_HEADER_LEN]).ok()?);
+    SalsaBox::new(&ephemeral, &SecretKey::from(*viewing_secret))
+        .decrypt(&nonce, &payload[PAYLOAD_HEADER_LEN..])
+        .ok()
+}
+
+/// The value a transfer commits for its two posted payloads.
+/// Matches ShieldedPool.sol: keccak256(abi.encode(keccak256(enc1), keccak256(enc2))).
+pub fn ciphertexts_hash(payloads: [&[u8]; 2]) -> [u8; 32] {
+    let mut preimage = [0u8; 64];
+    preimage[..32].copy_from_slice(&keccak256(payloads[0]));
+    preimage[32..].copy_from_slice(&keccak256(payloads[1]));
+    keccak256(&preimage)
+}
+
+/// Encrypt both outputs of a transfer and hash the payloads, as the
+/// transfer circuit does.
+pub fn encrypt_outputs(encryptions: &[OutputEncryption; 2], notes: &[Note; 2]) -> [u8; 32] {
+    let payloads = [encryptions[0].encrypt(&notes[0]), encryptions[1].encrypt(&notes[1])];
+    ciphertexts_hash([&payloads[0], &payloads[1]])
+}
+
+fn decimal(out: &mut Vec<u8>, mut value: u64) {
+    let mut digits = [0u8; 20];
+    let mut i = digits.len();
+    loop {
+        i -= 1;
+        digits[i] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&digits[i..]);
+}
+
+fn parse_decimal(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 20 {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |value, &d| {
+        if !d.is_ascii_digit() {
+            return None;
+        }
+        value.checked_mul(10)?.checked_add((d - b'0') as u64)
+    })
+}
+
+fn parse_hex32(text: &[u8]) -> Option<[u8; 32]> {
+    let digits = text.strip_prefix(b"0x")?;
+    if digits.len() != 64 {
+        return None;
+    }
+    let nibble = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+    let mut out = [0u8; 32];
+    for (byte, pair) in out.iter_mut().zip(digits.chunks(2)) {
+        *byte = (nibble(pair[0])? << 4) | nibble(pair[1])?;
+    }
+    Some(out)
+}
+
+fn hex(out: &mut Vec<u8>, bytes: &[u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    for b in bytes {
+        out.push(DIGITS[(b >> 4) as usize]);
+        out.push(DIGITS[(b & 0xf) as usize]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note() -> Note {
+        Note { amount: 1_000_000, pubkey: [0xAB; 32], blinding: [0x01; 32] }
+    }
+
+    #[test]
+    fn test_note_plaintext_matches_sdk_json() {
+        let expected = alloc::format!(
+            "{{\"amount\":\"1000000\",\"pubkey\":\"0x{}\",\"blinding\":\"0x{}\"}}",
+            "ab".repeat(32),
+            "01".repeat(32)
+        );
+        assert_eq!(note_plaintext(&note()), expected.as_bytes());
+
+        let zero = Note { amount: 0, pubkey: [0; 32], blinding: [0; 32] };
+        assert!(note_plaintext(&zero).starts_with(b"{\"amount\":\"0\","));
+        let max = Note { amount: u64::MAX, ..zero };
+        assert!(note_plaintext(&max).starts_with(b"{\"amount\":\"18446744073709551615\","));
+    }
+
+    #[test]
+    fn test_payload_opens_to_note() {
+        let viewing_secret = SecretKey::from([0x33; 32]);
+        let encryption = OutputEncryption {
+            viewing_pubkey: *viewing_secret.public_key().as_bytes(),
+            ephemeral_secret: [0x44; 32],
+            nonce: [0x55; 24],
+        };
+        let payload = encryption.encrypt(&note());
+        assert_eq!(payload, encryption.encrypt(&note()), "deterministic given the randomness");
+
+        let ephemeral = PublicKey::from(<[u8; 32]>::try_from(&payload[..32]).unwrap());
+        assert_eq!(&payload[32..PAYLOAD_HEADER_LEN], &encryption.nonce);
+        let nonce = Nonce::from(<[u8; 24]>::try_from(&payload[32..PAYLOAD_HEADER_LEN]).unwrap());
+        let plaintext = SalsaBox::new(&ephemeral, &viewing_secret)
+            .decrypt(&nonce, &payload[PAYLOAD_HEADER_LEN..])
+            .unwrap();
+        assert_eq!(plaintext, note_plaintext(&note()));
+        assert_eq!(open_payload(&payload, &[0x33; 32]), Some(plaintext));
+        assert_eq!(open_payload(&payload, &[0x34; 32]), None);
+        assert_eq!(open_payload(&payload[..PAYLOAD_HEADER_LEN - 1], &[0x33; 32]), None);
+        assert_eq!(viewing_pubkey(&[0x33; 32]), encryption.viewing_pubkey);
+    }
+
+    #[test]
+    fn test_parse_note_plaintext_accepts_either_key_order() {
+        assert_eq!(parse_note_plaintext(&note_plaintext(&note())), Some(note()));
+        // serde_json sorts the keys
+        let sorted = alloc::format!(
+            "{{\"amount\":\"1000000\",\"blinding\":\"0x{}\",\"pubkey\":\"0x{}\"}}",
+            "01".repeat(32),
+            "AB".repeat(32)
+        );
+        assert_eq!(parse_note_plaintext(sorted.as_bytes()), Some(note()));
+
+        let max = Note { amount: u64::MAX, ..note() };
+        assert_eq!(parse_note_plaintext(&note_plaintext(&max)), Some(max));
+        let text = alloc::string::String::from_utf8(note_plaintext(&note())).unwrap();
+        for bad in [
+            text.replace("1000000", "18446744073709551616"),
+            text.replace("1000000", "-1"),
+            text.replace("0xabab", "0xzzab"),
+            text.replace(",\"blinding\"", ",\"pubkey\""),
+            text.replace("}", ",\"memo\":\"x\"}"),
+            text.replace("{", ""),
+        ] {
+            assert_eq!(parse_note_plaintext(bad.as_bytes()), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_ciphertexts_hash_binds_both_payloads_in_order() {
+        let a: &[u8] = b"payload a";
+        let b: &[u8] = b"payload b";
+        let hash = ciphertexts_hash([a, b]);
+        assert_ne!(hash, ciphertexts_hash([b, a]));
+        assert_ne!(hash, ciphertexts_hash([a, a]));
+        assert_ne!(hash, [0u8; 32]);
+    }
+}
@@ -0,0 +1,312 @@
+//! Lifecycle of wallet notes across proving, submission and reorgs.
+//!
+//! A note the wallet learns about is `Incoming` until its commitment is in
+//! a block, then `Unspent`. A spend reserves its inputs (`Pending`) for as
+//! long as it is being proven, relayed or retried. The spend then either
+//! lands (`Spent`) or gives its inputs back (`release`).
+//!
+//!   Incoming ──confirm──▶ Unspent ──reserve──▶ Pending ──settle──▶ Spent
+//!                            ▲                    │
+//!                            └──────release───────┘
+//!
+//! Only `rollback` moves a note backwards along confirmed history. After a
+//! reorg to block `b`, spends settled after `b` are undone, and notes
+//! confirmed after `b` are `Incoming` again (dropping any reservation, since
+//! a proof against a root that no longer exists is useless).
+//!
+//! Every transition is all-or-nothing: an operation that fails leaves the
+//! ledger unchanged.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// Identifies one spend (a relayer job, an exit step) holding reservations.
+pub type SpendId = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteState {
+    /// Known to the wallet, not yet in a block
+    Incoming,
+    /// In the tree and free to spend
+    Unspent,
+    /// Reserved as an input of a spend in progress
+    Pending(SpendId),
+    /// Nullifier seen on-chain at this block
+    Spent(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackedNote {
+    pub amount: u64,
+    /// Block the commitment was included in (None while `Incoming`)
+    pub confirmed_at: Option<u64>,
+    pub state: NoteState,
+}
+
+/// Totals by state, in token base units.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Balances {
+    pub incoming: u128,
+    pub spendable: u128,
+    pub pending: u128,
+    pub spent: u128,
+}
+
+impl Balances {
+    pub fn total(&self) -> u128 {
+        self.incoming + self.spendable + self.pending + self.spent
+    }
+}
+
+/// Notes by commitment, with their lifecycle state.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NoteLedger {
+    notes: BTreeMap<[u8; 32], TrackedNote>,
+}
+
+impl NoteLedger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a note that is not yet in a block.
+    pub fn receive(&mut self, commitment: [u8; 32], amount: u64) -> Result<(), &'static str> {
+        if self.notes.contains_key(&commitment) {
+            return Err("note already tracked");
+        }
+        self.notes.insert(commitment, TrackedNote { amount, confirmed_at: None, state: NoteState::Incoming });
+        Ok(())
+    }
+
+    /// The note's commitment was included at `block`.
+    pub fn confirm(&mut self, commitment: [u8; 32], block: u64) -> Result<(), &'static str> {
+        let note = self.notes.get_mut(&commitment).ok_or("note not tracked")?;
+        if note.state != NoteState::Incoming {
+            return Err("note is not incoming");
+        }
+        note.confirmed_at = Some(block);
+        note.state = NoteState::Unspent;
+        Ok(())
+    }
+
+    /// Reserve `inputs` for `spend`. Fails unless every input is unspent and
+    /// distinct, and `spend` holds no reservations yet.
+    pub fn reserve(&mut self, spend: SpendId, inputs: &[[u8; 32]]) -> Result<(), &'static str> {
+        if self.reserved(spend).next().is_some() {
+            return Err("spend already holds reservations");
+        }
+        for (i, commitment) in inputs.iter().enumerate() {
+            if inputs[..i].contains(commitment) {
+                return Err("duplicate input note");
+            }
+            match self.notes.get(commitment) {
+                None => return Err("note not tracked"),
+                Some(note) if note.state != NoteState::Unspent => return Err("note is not unspent"),
+                Some(_) => {}
+            }
+        }
+        for commitment in inputs {
+            self.notes.get_mut(commitment).expect("checked above").state = NoteState::Pending(spend);
+        }
+        Ok(())
+    }
+
+    /// Give back the inputs of a spend that failed or was abandoned.
+    /// Returns how many notes were released.
+    pub fn release(&mut self, spend: SpendId) -> usize {
+        self.transition(spend, NoteState::Unspent)
+    }
+
+    /// The spend landed at `block`. Returns how many notes it spent.
+    pub fn settle(&mut self, spend: SpendId, block: u64) -> Result<usize, &'static str> {
+        match self.transition(spend, NoteState::Spent(block)) {
+            0 => Err("spend holds no reservations"),
+            n => Ok(n),
+        }
+    }
+
+    fn transition(&mut self, spend: SpendId, to: NoteState) -> usize {
+        let mut n = 0;
+        for note in self.notes.values_mut().filter(|n| n.state == NoteState::Pending(spend)) {
+            note.state = to;
+            n += 1;
+        }
+        n
+    }
+
+    /// Undo everything after `block` (a reorg).
+    pub fn rollback(&mut self, block: u64) {
+        for note in self.notes.values_mut() {
+            if note.confirmed_at.is_some_and(|at| at > block) {
+                note.confirmed_at = None;
+                note.state = NoteState::Incoming;
+            } else if matches!(note.state, NoteState::Spent(at) if at > block) {
+                note.state = NoteState::Unspent;
+            }
+        }
+    }
+
+    /// Commitments reserved by `spend`.
+    pub fn reserved(&self, spend: SpendId) -> impl Iterator<Item = &[u8; 32]> {
+        self.notes.iter().filter(move |(_, n)| n.state == NoteState::Pending(spend)).map(|(c, _)| c)
+    }
+
+    pub fn get(&self, commitment: &[u8; 32]) -> Option<&TrackedNote> {
+        self.notes.get(commitment)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8; 32], &TrackedNote)> {
+        self.notes.iter()
+    }
+
+    /// Unspent notes, the ones coin selection may pick from.
+    pub fn spendable(&self) -> Vec<([u8; 32], u64)> {
+        self.notes.iter().filter(|(_, n)| n.state == NoteState::Unspent).map(|(c, n)| (*c, n.amount)).collect()
+    }
+
+    pub fn balances(&self) -> Balances {
+        let mut b = Balances::default();
+        for note in self.notes.values() {
+            let bucket = match note.state {
+                NoteState::Incoming => &mut b.incoming,
+                NoteState::Unspent => &mut b.spendable,
+                NoteState::Pending(_) => &mut b.pending,
+                NoteState::Spent(_) => &mut b.spent,
+            };
+            *bucket += note.amount as u128;
+        }
+        b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    fn c(i: u8) -> [u8; 32] {
+        [i; 32]
+    }
+
+    #[test]
+    fn test_lifecycle_and_reorg() {
+        let mut ledger = NoteLedger::new();
+        ledger.receive(c(1), 70).unwrap();
+        ledger.receive(c(2), 30).unwrap();
+        assert_eq!(ledger.receive(c(1), 1), Err("note already tracked"));
+        assert_eq!(ledger.reserve(7, &[c(1)]), Err("note is not unspent"));
+        ledger.confirm(c(1), 10).unwrap();
+        ledger.confirm(c(2), 12).unwrap();
+
+        ledger.reserve(7, &[c(1), c(2)]).unwrap();
+        assert_eq!(ledger.reserve(8, &[c(2)]), Err("note is not unspent"));
+        assert_eq!(ledger.release(7), 2);
+        assert_eq!(ledger.reserve(8, &[c(2), c(2)]), Err("duplicate input note"));
+        ledger.reserve(8, &[c(1), c(2)]).unwrap();
+        assert_eq!(ledger.settle(8, 15), Ok(2));
+        assert_eq!(ledger.settle(8, 15), Err("spend holds no reservations"));
+        assert_eq!(ledger.balances().spent, 100);
+
+        // Reorg to 12 undoes the spend; to 11 also drops note 2's inclusion
+        ledger.rollback(12);
+        assert_eq!(ledger.balances(), Balances { spendable: 100, ..Default::default() });
+        ledger.rollback(11);
+        assert_eq!(ledger.get(&c(2)).unwrap().state, NoteState::Incoming);
+        assert_eq!(ledger.spendable(), [(c(1), 70)]);
+    }
+
+    /// Operations on a handful of notes and spends, so sequences collide often.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Receive(u8, u64),
+        Confirm(u8),
+        Reserve(SpendId, Vec<u8>),
+        Release(SpendId),
+        Settle(SpendId),
+        NextBlock,
+        Reorg(u64),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..8u8, any::<u64>()).prop_map(|(i, a)| Op::Receive(i, a)),
+            (0..8u8).prop_map(Op::Confirm),
+            (0..4u64, vec(0..8u8, 1..=2)).prop_map(|(s, i)| Op::Reserve(s, i)),
+            (0..4u64).prop_map(Op::Release),
+            (0..4u64).prop_map(Op::Settle),
+            Just(Op::NextBlock),
+            (0..4u64).prop_map(Op::Reorg),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_model_invariants(ops in vec(op(), 1..64)) {
+            let mut ledger = NoteLedger::new();
+            let mut received: BTreeMap<[u8; 32], u64> = BTreeMap::new();
+            let mut head = 0u64;
+
+            for op in ops {
+                let before = ledger.clone();
+                let ok = match &op {
+                    Op::Receive(i, amount) => ledger.receive(c(*i), *amount).map(|_| received.insert(c(*i), *amount)).is_ok(),
+                    Op::Confirm(i) => ledger.confirm(c(*i), head).is_ok(),
+                    Op::Reserve(spend, inputs) => {
+                        let inputs: Vec<_> = inputs.iter().map(|i| c(*i)).collect();
+                        let ok = ledger.reserve(*spend, &inputs).is_ok();
+                        // Never reserve a note that was not unspent, or one twice
+                        let all_free = inputs.iter().all(|i| before.get(i).is_some_and(|n| n.state == NoteState::Unspent));
+                        let distinct = inputs.len() < 2 || inputs[0] != inputs[1];
+                        prop_assert_eq!(ok, all_free && distinct && before.reserved(*spend).next().is_none());
+                        ok
+                    }
+                    Op::Release(spend) => ledger.release(*spend) > 0,
+                    Op::Settle(spend) => ledger.settle(*spend, head).is_ok(),
+                    Op::NextBlock => {
+                        head += 1;
+                        true
+                    }
+                    Op::Reorg(depth) => {
+                        head = head.saturating_sub(*depth);
+                        ledger.rollback(head);
+                        true
+                    }
+                };
+                if !ok {
+                    prop_assert_eq!(&ledger, &before, "failed {:?} changed the ledger", op);
+                }
+
+                // Amounts are conserved across states: nothing lost, nothing made up
+                let total: u128 = received.values().map(|a| *a as u128).sum();
+                prop_assert_eq!(ledger.balances().total(), total);
+
+                for (commitment, note) in ledger.iter() {
+                    let was = before.get(commitment).map(|n| n.state);
+                    match note.state {
+                        NoteState::Incoming => prop_assert!(note.confirmed_at.is_none()),
+                        _ => prop_assert!(note.confirmed_at.is_some_and(|at| at <= head)),
+                    }
+                    if let NoteState::Spent(at) = note.state {
+                        prop_assert!(at <= head);
+                    }
+                    // Spent notes only come back through a reorg past their spend
+                    if let Some(NoteState::Spent(at)) = was {
+                        if note.state != NoteState::Spent(at) {
+                            prop_assert!(matches!(op, Op::Reorg(_)) && at > head, "{:?} unspent a note", op);
+                        }
+                    }
+                    // A reservation only ends by release, settlement or a reorg
+                    if let Some(NoteState::Pending(spend)) = was {
+                        match note.state {
+                            NoteState::Pending(s) => prop_assert_eq!(s, spend),
+                            NoteState::Unspent => prop_assert!(matches!(op, Op::Release(s) if s == spend)),
+                            NoteState::Spent(_) => prop_assert!(matches!(op, Op::Settle(s) if s == spend)),
+                            NoteState::Incoming => prop_assert!(matches!(op, Op::Reorg(_))),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
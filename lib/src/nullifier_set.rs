@@ -0,0 +1,246 @@
+//! Local set of spent nullifiers, committed to by a sparse Merkle tree.
+//!
+//! The tree is keyed by the 256 bits of the nullifier, most significant bit
+//! first. It is kept compact: an empty subtree hashes to zero and a subtree
+//! holding a single nullifier hashes to that nullifier's leaf, wherever it
+//! sits, so only the branch points between nullifiers are ever hashed.
+//!
+//!   empty    = 0x00..00
+//!   leaf(n)  = keccak256(0x00 || n)
+//!   branch   = keccak256(0x01 || left || right)
+//!
+//! The prefixes keep a leaf from ever being read as a branch and the other
+//! way round. Hosts fill the set from the nullifiers in `PrivateTransfer`
+//! and `Withdrawal` events and answer "is this note spent?" from it; a
+//! `NullifierProof` shows membership or non-membership against `root()`.
+//!
+//! Roots and proofs are computed on demand from the sorted nullifiers, in
+//! O(n) hashes for the root and O(n) for a proof.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::keccak256;
+
+/// Root of the empty set.
+pub const EMPTY_NULLIFIER_ROOT: [u8; 32] = [0u8; 32];
+
+/// Spent nullifiers, with a sparse Merkle root over them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NullifierSet {
+    nullifiers: BTreeSet<[u8; 32]>,
+}
+
+/// What a proof ends in once the path leaves the other nullifiers behind.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProofTerminal {
+    /// An empty subtree: the nullifier is not in the set
+    Empty,
+    /// The only nullifier in the subtree (the queried one, or another one
+    /// sharing its prefix)
+    Leaf([u8; 32]),
+}
+
+/// Membership or non-membership proof for one nullifier.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NullifierProof {
+    /// Sibling subtree roots from the root down
+    pub siblings: Vec<[u8; 32]>,
+    pub terminal: ProofTerminal,
+}
+
+fn bit(key: &[u8; 32], depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+fn leaf_hash(nullifier: &[u8; 32]) -> [u8; 32] {
+    let mut data = [0u8; 33];
+    data[1..].copy_from_slice(nullifier);
+    keccak256(&data)
+}
+
+fn branch_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut data = [0u8; 65];
+    data[0] = 1;
+    data[1..33].copy_from_slice(left);
+    data[33..].copy_from_slice(right);
+    keccak256(&data)
+}
+
+/// Root of the subtree holding `keys` (sorted, distinct, sharing their
+/// first `depth` bits).
+fn subtree_root(keys: &[[u8; 32]], depth: usize) -> [u8; 32] {
+    match keys {
+        [] => EMPTY_NULLIFIER_ROOT,
+        [key] => leaf_hash(key),
+        _ => {
+            let split = keys.partition_point(|k| !bit(k, depth));
+            branch_hash(&subtree_root(&keys[..split], depth + 1), &subtree_root(&keys[split..], depth + 1))
+        }
+    }
+}
+
+impl NullifierSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a nullifier. Returns false if it was already in the set.
+    pub fn insert(&mut self, nullifier: [u8; 32]) -> bool {
+        self.nullifiers.insert(nullifier)
+    }
+
+    pub fn contains(&self, nullifier: &[u8; 32]) -> bool {
+        self.nullifiers.contains(nullifier)
+    }
+
+    pub fn len(&self) -> usize {
+        self.nullifiers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nullifiers.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8; 32]> {
+        self.nullifiers.iter()
+    }
+
+    fn sorted(&self) -> Vec<[u8; 32]> {
+        self.nullifiers.iter().copied().collect()
+    }
+
+    /// Sparse Merkle root over the set.
+    pub fn root(&self) -> [u8; 32] {
+        subtree_root(&self.sorted(), 0)
+    }
+
+    /// Proof that `nullifier` is (or is not) in the set, against `root()`.
+    pub fn prove(&self, nullifier: &[u8; 32]) -> NullifierProof {
+        let sorted = self.sorted();
+        let mut keys = sorted.as_slice();
+        let mut siblings = Vec::new();
+        let mut depth = 0;
+        while keys.len() > 1 {
+            let split = keys.partition_point(|k| !bit(k, depth));
+            let (left, right) = keys.split_at(split);
+            let (ours, other) = if bit(nullifier, depth) { (right, left) } else { (left, right) };
+            siblings.push(subtree_root(other, depth + 1));
+            keys = ours;
+            depth += 1;
+        }
+        let terminal = match keys {
+            [key] => ProofTerminal::Leaf(*key),
+            _ => ProofTerminal::Empty,
+        };
+        NullifierProof { siblings, terminal }
+    }
+}
+
+impl NullifierProof {
+    /// Check the proof against `root`. Returns whether `nullifier` is in the
+    /// set, or an error if the proof does not lead to `root`.
+    pub fn verify(&self, root: [u8; 32], nullifier: &[u8; 32]) -> Result<bool, &'static str> {
+        let depth = self.siblings.len();
+        if depth > 256 {
+            return Err("proof is deeper than the key");
+        }
+        let (mut node, member) = match &self.terminal {
+            ProofTerminal::Empty => (EMPTY_NULLIFIER_ROOT, false),
+            ProofTerminal::Leaf(key) => {
+                // The leaf must sit on the queried nullifier's path
+                if (0..depth).any(|d| bit(key, d) != bit(nullifier, d)) {
+                    return Err("terminal leaf is not on the nullifier's path");
+                }
+                (leaf_hash(key), key == nullifier)
+            }
+        };
+        for (d, sibling) in self.siblings.iter().enumerate().rev() {
+            node = if bit(nullifier, d) { branch_hash(sibling, &node) } else { branch_hash(&node, sibling) };
+        }
+        if node == root {
+            Ok(member)
+        } else {
+            Err("proof does not match the root")
+        }
+    }
+}
+
+impl Extend<[u8; 32]> for NullifierSet {
+    fn extend<I: IntoIterator<Item = [u8; 32]>>(&mut self, iter: I) {
+        self.nullifiers.extend(iter);
+    }
+}
+
+impl FromIterator<[u8; 32]> for NullifierSet {
+    fn from_iter<I: IntoIterator<Item = [u8; 32]>>(iter: I) -> Self {
+        NullifierSet { nullifiers: iter.into_iter().collect() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nullifier(i: u32) -> [u8; 32] {
+        keccak256(&i.to_be_bytes())
+    }
+
+    #[test]
+    fn test_nullifier_set_membership_proofs() {
+        let empty = NullifierSet::new();
+        assert_eq!(empty.root(), EMPTY_NULLIFIER_ROOT);
+        assert_eq!(empty.prove(&nullifier(0)).verify(empty.root(), &nullifier(0)), Ok(false));
+
+        let mut set = NullifierSet::new();
+        for i in 0..64 {
+            assert!(set.insert(nullifier(i)));
+        }
+        assert!(!set.insert(nullifier(3)));
+        assert_eq!(set.len(), 64);
+        let root = set.root();
+
+        // Order of insertion does not matter
+        assert_eq!((0..64).rev().map(nullifier).collect::<NullifierSet>().root(), root);
+
+        for i in 0..128 {
+            let n = nullifier(i);
+            assert_eq!(set.prove(&n).verify(root, &n), Ok(i < 64), "nullifier {i}");
+            assert_eq!(set.contains(&n), i < 64);
+        }
+
+        // Keys sharing a long prefix, and the ones next to them
+        let mut close = NullifierSet::new();
+        let mut a = [0xffu8; 32];
+        a[31] = 0xfe;
+        close.extend([a, [0xff; 32]]);
+        let mut b = a;
+        b[31] = 0xfc;
+        assert_eq!(close.prove(&a).siblings.len(), 256);
+        assert_eq!(close.prove(&a).verify(close.root(), &a), Ok(true));
+        assert_eq!(close.prove(&b).verify(close.root(), &b), Ok(false));
+    }
+
+    #[test]
+    fn test_nullifier_proof_rejects_tampering() {
+        let set: NullifierSet = (0..16).map(nullifier).collect();
+        let root = set.root();
+        let spent = nullifier(5);
+        let unspent = nullifier(99);
+
+        // A membership proof cannot be reused for another nullifier
+        let proof = set.prove(&spent);
+        assert!(proof.verify(root, &unspent).is_err());
+
+        // Claiming a spent nullifier's subtree is empty fails
+        let forged = NullifierProof { terminal: ProofTerminal::Empty, ..proof.clone() };
+        assert!(forged.verify(root, &spent).is_err());
+
+        // Stale root
+        let mut grown = set.clone();
+        grown.insert(unspent);
+        assert!(set.prove(&unspent).verify(grown.root(), &unspent).is_err());
+        assert_eq!(grown.prove(&unspent).verify(grown.root(), &unspent), Ok(true));
+    }
+}
@@ -0,0 +1,293 @@
+//! Payouts: up to two notes in, up to eight recipients paid, in one proof.
+//!
+//! Paying a payroll through the transfer circuit takes one proof per
+//! recipient, each waiting on the previous one's change. The payout circuit
+//! spends 1 to `PAYOUT_INPUTS` notes into 1 to `PAYOUT_OUTPUTS` notes, under
+//! the join-split rules (every input authorized, in the tree under `root`
+//! and spent once, amounts balanced), and adds one: no two outputs share a
+//! pubkey, so each output pays a different recipient (or the payer's
+//! change).
+//!
+//! Both sides are padded with the join-split's dummies
+//! (`PayoutPrivateInputs::padded`), so a proof does not reveal how many
+//! people were paid. Dummy outputs have random pubkeys and never collide.
+//!
+//! Public values committed (448 bytes = 14 × 32-byte slots):
+//!   [root, nullifier0..1, outCommitment0..7, reclaimDeadline (uint256 BE),
+//!    treeLevels (uint256 BE), poolBinding]
+//! for `ShieldedPool.payout` to read with
+//!   abi.decode(publicValues, (bytes32, bytes32[2], bytes32[8], uint256, uint256, bytes32))
+//! It rejects a spent or repeated nullifier, requires `treeLevels` to be its
+//! tree's depth and `poolBinding` its own, and inserts all eight commitments.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::cancellable::committed_deadline;
+use crate::join_split::{dummy_output, JoinSplitInput};
+use crate::{check_output_amount, probe, spent_proof_levels, CheckError, Note};
+
+/// Input slots of the payout circuit.
+pub const PAYOUT_INPUTS: usize = 2;
+
+/// Output slots of the payout circuit.
+pub const PAYOUT_OUTPUTS: usize = 8;
+
+/// Private inputs for the payout circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PayoutPrivateInputs {
+    /// Exactly `PAYOUT_INPUTS`, real ones first
+    pub inputs: Vec<JoinSplitInput>,
+    /// Exactly `PAYOUT_OUTPUTS`, real ones first, each to a different pubkey
+    pub output_notes: Vec<Note>,
+    /// The Merkle root every real input's proof verifies against
+    pub root: [u8; 32],
+    /// The pool the proof is for (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the payout circuit (448 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayoutPublicValues {
+    pub root: [u8; 32],
+    pub nullifiers: [[u8; 32]; PAYOUT_INPUTS],
+    pub out_commitments: [[u8; 32]; PAYOUT_OUTPUTS],
+    /// Zero unless an input is a reclaimed cancellable note
+    pub reclaim_deadline: u64,
+    /// Depth of the inputs' Merkle proofs (see `spent_proof_levels`)
+    pub tree_levels: u64,
+    /// The pool the proof is for (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+impl PayoutPrivateInputs {
+    /// `inputs` and `outputs` padded with dummies derived from `seed`, which
+    /// must be fresh randomness. `pool_binding` is left zero; set it for the
+    /// pool the proof is for.
+    pub fn padded(
+        root: [u8; 32],
+        mut inputs: Vec<JoinSplitInput>,
+        mut outputs: Vec<Note>,
+        seed: [u8; 32],
+    ) -> Result<Self, &'static str> {
+        if inputs.is_empty() || inputs.len() > PAYOUT_INPUTS {
+            return Err("a payout spends 1 to 2 notes");
+        }
+        if outputs.is_empty() || outputs.len() > PAYOUT_OUTPUTS {
+            return Err("a payout creates 1 to 8 notes");
+        }
+        for slot in inputs.len()..PAYOUT_INPUTS {
+            inputs.push(JoinSplitInput::dummy(&seed, slot));
+        }
+        for slot in outputs.len()..PAYOUT_OUTPUTS {
+            outputs.push(dummy_output(&seed, slot));
+        }
+        Ok(PayoutPrivateInputs { inputs, output_notes: outputs, root, pool_binding: [0u8; 32] })
+    }
+
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks
+    /// (`guest_check`).
+    pub fn check(&self) -> Result<PayoutPublicValues, &'static str> {
+        self.guest_check().map_err(|err| err.message)
+    }
+
+    /// The payout guest's checks, in its order. A rejected input is named.
+    pub fn guest_check(&self) -> Result<PayoutPublicValues, CheckError> {
+        // Every slot is filled, so the public values never reveal how many
+        // people were paid
+        probe!(start "payout/shape");
+        if self.inputs.len() != PAYOUT_INPUTS || self.output_notes.len() != PAYOUT_OUTPUTS {
+            return Err("payout needs exactly 2 inputs and 8 outputs (pad with dummies)".into());
+        }
+        probe!(end "payout/shape");
+        let mut auths = Vec::with_capacity(PAYOUT_INPUTS);
+        for (i, input) in self.inputs.iter().enumerate() {
+            probe!(start "payout/authorize");
+            let auth = input.authorize().map_err(|msg| CheckError::at("input note", i, msg))?;
+            probe!(end "payout/authorize");
+            probe!(arm "payout/authorize", crate::coverage::spend_arm(auth.path));
+            probe!(start "payout/merkle");
+            if !input.is_member(self.root) {
+                return Err(CheckError::at("input note", i, "Merkle proof invalid"));
+            }
+            probe!(end "payout/merkle");
+            probe!(arm "payout/merkle", if input.note.amount != 0 { "member" } else { "zero_amount" });
+            auths.push(auth);
+        }
+        // Spending one note in both slots would repeat its nullifier and mint
+        // its value twice
+        probe!(start "payout/distinct_inputs");
+        if auths[0].nullifier == auths[1].nullifier {
+            return Err("duplicate input note".into());
+        }
+        probe!(end "payout/distinct_inputs");
+        // A shorter proof can reach a known root from an inner node
+        probe!(start "payout/proof_depth");
+        let tree_levels = spent_proof_levels(self.inputs.iter().map(|input| (&input.note, &input.merkle_proof)))?;
+        probe!(end "payout/proof_depth");
+        probe!(start "payout/output_range");
+        self.output_notes.iter().try_for_each(|note| check_output_amount(note.amount))?;
+        probe!(end "payout/output_range");
+        // One output per recipient
+        probe!(start "payout/distinct_recipients");
+        for (i, note) in self.output_notes.iter().enumerate() {
+            if self.output_notes[..i].iter().any(|earlier| earlier.pubkey == note.pubkey) {
+                return Err("payout recipients must be distinct".into());
+            }
+        }
+        probe!(end "payout/distinct_recipients");
+        probe!(start "payout/conservation");
+        let input_sum: u128 = self.inputs.iter().map(|input| input.note.amount as u128).sum();
+        let output_sum: u128 = self.output_notes.iter().map(|n| n.amount as u128).sum();
+        if input_sum != output_sum {
+            return Err("amounts don't balance".into());
+        }
+        probe!(end "payout/conservation");
+        Ok(PayoutPublicValues {
+            root: self.root,
+            nullifiers: [auths[0].nullifier, auths[1].nullifier],
+            out_commitments: core::array::from_fn(|i| self.output_notes[i].commitment()),
+            reclaim_deadline: committed_deadline(&auths),
+            tree_levels,
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl PayoutPublicValues {
+    pub const LEN: usize = 32 * (4 + PAYOUT_INPUTS + PAYOUT_OUTPUTS);
+
+    /// ABI-encode exactly as the payout guest commits it.
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut out = [0u8; Self::LEN];
+        let words = core::iter::once(&self.root).chain(&self.nullifiers).chain(&self.out_commitments);
+        for (chunk, word) in out.chunks_mut(32).zip(words) {
+            chunk.copy_from_slice(word);
+        }
+        out[Self::LEN - 72..Self::LEN - 64].copy_from_slice(&self.reclaim_deadline.to_be_bytes());
+        out[Self::LEN - 40..Self::LEN - 32].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[Self::LEN - 32..].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the deadline or the tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN
+            || bytes[Self::LEN - 96..Self::LEN - 72].iter().any(|&b| b != 0)
+            || bytes[Self::LEN - 64..Self::LEN - 40].iter().any(|&b| b != 0)
+        {
+            return None;
+        }
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(PayoutPublicValues {
+            root: word(0),
+            nullifiers: core::array::from_fn(|i| word(1 + i)),
+            out_commitments: core::array::from_fn(|i| word(1 + PAYOUT_INPUTS + i)),
+            reclaim_deadline: u64::from_be_bytes(bytes[Self::LEN - 72..Self::LEN - 64].try_into().unwrap()),
+            tree_levels: u64::from_be_bytes(bytes[Self::LEN - 40..Self::LEN - 32].try_into().unwrap()),
+            pool_binding: bytes[Self::LEN - 32..].try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_nullifier, derive_pubkey, IncrementalMerkleTree};
+    use alloc::vec;
+
+    const KEY: [u8; 32] = [7u8; 32];
+
+    /// A tree holding the payer's notes of `amounts`, and each as an input.
+    fn spendable(amounts: &[u64]) -> ([u8; 32], Vec<JoinSplitInput>) {
+        let mut tree = IncrementalMerkleTree::new(8);
+        let notes: Vec<Note> = amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note { amount, pubkey: derive_pubkey(&KEY), blinding: [i as u8 + 1; 32] })
+            .collect();
+        for n in &notes {
+            tree.insert(n.commitment());
+        }
+        let inputs = notes
+            .into_iter()
+            .enumerate()
+            .map(|(i, note)| JoinSplitInput {
+                note,
+                spending_key: KEY,
+                merkle_proof: tree.get_proof(i as u32),
+                lock: None,
+            })
+            .collect();
+        (tree.get_root(), inputs)
+    }
+
+    /// `amount` to recipient number `who`.
+    fn pay(who: u8, amount: u64) -> Note {
+        Note { amount, pubkey: [who; 32], blinding: [who ^ 0x80; 32] }
+    }
+
+    #[test]
+    fn test_payout_pays_many_recipients() {
+        let (root, inputs) = spendable(&[6_000, 4_000]);
+        let mut outputs: Vec<Note> = (1..=6).map(|who| pay(who, 1_500)).collect();
+        outputs.push(Note { amount: 1_000, pubkey: derive_pubkey(&KEY), blinding: [0xcc; 32] });
+        let request = PayoutPrivateInputs::padded(root, inputs.clone(), outputs.clone(), [1u8; 32]).unwrap();
+        assert_eq!((request.inputs.len(), request.output_notes.len()), (2, 8));
+
+        let pv = request.check().unwrap();
+        assert_eq!(pv.nullifiers[1], compute_nullifier(&inputs[1].note.commitment(), &KEY));
+        assert_eq!(pv.out_commitments[5], outputs[5].commitment());
+        assert_eq!((pv.reclaim_deadline, pv.tree_levels), (0, 8));
+        assert_eq!(PayoutPublicValues::LEN, 448);
+        assert_eq!(PayoutPublicValues::decode(&pv.encode()), Some(pv.clone()));
+
+        // One input and one recipient pads out the rest
+        let (root, inputs) = spendable(&[500]);
+        let single = PayoutPrivateInputs::padded(root, inputs.clone(), vec![pay(1, 500)], [2u8; 32]).unwrap();
+        assert!(single.check().is_ok());
+
+        assert!(PayoutPrivateInputs::padded(root, Vec::new(), vec![pay(1, 500)], [1u8; 32]).is_err());
+        assert!(PayoutPrivateInputs::padded(root, inputs, vec![pay(1, 0); 9], [1u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_payout_rejects_bad_payouts() {
+        let (root, inputs) = spendable(&[600, 400]);
+        let outputs = vec![pay(1, 500), pay(2, 300), pay(3, 200)];
+        let base = PayoutPrivateInputs::padded(root, inputs, outputs, [1u8; 32]).unwrap();
+        assert!(base.check().is_ok());
+
+        let mut short = base.clone();
+        short.output_notes.pop();
+        assert_eq!(short.check(), Err("payout needs exactly 2 inputs and 8 outputs (pad with dummies)"));
+
+        let mut twice = base.clone();
+        twice.output_notes[1].pubkey = twice.output_notes[0].pubkey;
+        assert_eq!(twice.check(), Err("payout recipients must be distinct"));
+        // Including a dummy's pubkey
+        let mut onto_dummy = base.clone();
+        onto_dummy.output_notes[0].pubkey = onto_dummy.output_notes[7].pubkey;
+        assert_eq!(onto_dummy.check(), Err("payout recipients must be distinct"));
+
+        let mut unbalanced = base.clone();
+        unbalanced.output_notes[0].amount += 1;
+        assert_eq!(unbalanced.check(), Err("amounts don't balance"));
+
+        let mut duplicate = base.clone();
+        duplicate.inputs[1] = duplicate.inputs[0].clone();
+        duplicate.output_notes[0].amount = 700;
+        assert_eq!(duplicate.check(), Err("duplicate input note"));
+
+        let mut wrong_key = base.clone();
+        wrong_key.inputs[0].spending_key = [8u8; 32];
+        assert_eq!(wrong_key.check(), Err("spending key does not match note pubkey"));
+
+        let mut stale = base.clone();
+        stale.root = [0u8; 32];
+        assert_eq!(stale.check(), Err("Merkle proof invalid"));
+    }
+}
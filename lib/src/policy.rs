@@ -0,0 +1,486 @@
+//! Spending-limit policies: notes that can only be spent up to a daily limit.
+//!
+//! A treasury locks its funds to a policy, and whoever spends them (an
+//! employee's spending key) can move at most `limit` per epoch, however
+//! many spends or keys that takes. A policy note is an ordinary `Note` whose
+//! `pubkey` commits to its owner and the policy:
+//!
+//!   policy_key    = keccak256(POLICY_KEY_DOMAIN || limit_be8 || secret)
+//!   policy_pubkey = keccak256(POLICY_DOMAIN || owner_pubkey || policy_key)
+//!
+//! so no key spends it through the plain circuits, and commitments and the
+//! note tree do not change. The policy `secret` is shared by everyone who
+//! spends under the policy; it keeps the policy's nullifiers unlinkable.
+//!
+//! What was spent in an epoch is tracked by a counter note, kept in a
+//! separate counter tree that only policy spends append to, so a counter
+//! cannot be forged with a deposit:
+//!
+//!   counter    = keccak256(COUNTER_DOMAIN || policy_key || epoch_be8 || spent_be8 || blinding)
+//!   nullifier  = keccak256(COUNTER_NULLIFIER_DOMAIN || counter || secret)
+//!
+//! A policy spend pays `payment` out of one policy note, returns the change
+//! to the same lock, and replaces the epoch's counter with one that adds the
+//! payment, which must stay within `limit`. The first spend of an epoch has
+//! no counter to open; it reveals the epoch nullifier instead, which exists
+//! once per policy and epoch:
+//!
+//!   epoch_nullifier = keccak256(EPOCH_DOMAIN || secret || epoch_be8)
+//!
+//! so every epoch has exactly one chain of counters. Both kinds of counter
+//! nullifier go in the pool's nullifier set and look alike. An epoch is a
+//! UTC day (`EPOCH_SECONDS`); the pool checks the committed epoch against
+//! `block.timestamp / 1 days`.
+//!
+//! `ShieldedPool.policySpend` checks `poolBinding` against its own, `root`
+//! and `treeLevels` against the note tree and `counterRoot` and
+//! `counterLevels` against its `COUNTER_TREE` (unchecked on an epoch's first
+//! spend, whose zero levels say there is no counter), rejects spent
+//! nullifiers, then records both nullifiers, inserts the two notes and
+//! appends the new counter to the counter tree. A counter always has a
+//! non-empty Merkle proof, so zero levels cannot hide a forged one.
+//!
+//! Public values committed (352 bytes = 11 × 32-byte slots):
+//!   [root, nullifier, paymentCommitment, changeCommitment, counterRoot,
+//!    counterNullifier, newCounter, epoch (uint256 BE), treeLevels (uint256 BE),
+//!    counterLevels (uint256 BE), poolBinding]
+//! for a contract to read with
+//!   abi.decode(publicValues,
+//!       (bytes32, bytes32, bytes32, bytes32, bytes32, bytes32, bytes32, uint256, uint256, uint256, bytes32))
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    check_output_amount, compute_nullifier, derive_pubkey, keccak256, probe, verify_merkle_proof, MerkleProofStep,
+    Note,
+};
+
+/// Domain tag for policy note pubkeys (exactly 32 bytes).
+pub const POLICY_DOMAIN: [u8; 32] = *b"shielded-pool/policy-note-keys/1";
+
+/// Domain tag for policy keys (exactly 32 bytes).
+pub const POLICY_KEY_DOMAIN: [u8; 32] = *b"shielded-pool/policy-limit-key/1";
+
+/// Domain tag for counter commitments (exactly 32 bytes).
+pub const COUNTER_DOMAIN: [u8; 32] = *b"shielded-pool/policy-counter/1\0\0";
+
+/// Domain tag for counter nullifiers (exactly 32 bytes).
+pub const COUNTER_NULLIFIER_DOMAIN: [u8; 32] = *b"shielded-pool/counter-nullify/1\0";
+
+/// Domain tag for epoch nullifiers (exactly 32 bytes).
+pub const EPOCH_DOMAIN: [u8; 32] = *b"shielded-pool/policy-epoch/1\0\0\0\0";
+
+/// Length of a policy epoch: one day.
+pub const EPOCH_SECONDS: u64 = 86_400;
+
+/// The epoch `timestamp` (unix seconds) falls in.
+pub fn epoch_at(timestamp: u64) -> u64 {
+    timestamp / EPOCH_SECONDS
+}
+
+/// A spending-limit policy.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Policy {
+    /// Most that may be paid out per epoch
+    pub limit: u64,
+    /// Shared by everyone spending under the policy
+    pub secret: [u8; 32],
+}
+
+impl Policy {
+    /// The key policy notes and counters carry.
+    pub fn policy_key(&self) -> [u8; 32] {
+        let mut preimage = [0u8; 72];
+        preimage[0..32].copy_from_slice(&POLICY_KEY_DOMAIN);
+        preimage[32..40].copy_from_slice(&self.limit.to_be_bytes());
+        preimage[40..72].copy_from_slice(&self.secret);
+        keccak256(&preimage)
+    }
+
+    /// The `pubkey` of a note of `owner_pubkey` under this policy.
+    pub fn note_pubkey(&self, owner_pubkey: &[u8; 32]) -> [u8; 32] {
+        let mut preimage = [0u8; 96];
+        preimage[0..32].copy_from_slice(&POLICY_DOMAIN);
+        preimage[32..64].copy_from_slice(owner_pubkey);
+        preimage[64..96].copy_from_slice(&self.policy_key());
+        keccak256(&preimage)
+    }
+
+    /// Build a note of `owner_pubkey` for `amount` under this policy.
+    pub fn note(&self, owner_pubkey: &[u8; 32], amount: u64, blinding: [u8; 32]) -> Note {
+        Note { amount, pubkey: self.note_pubkey(owner_pubkey), blinding }
+    }
+
+    /// Revealed by the first spend of `epoch`.
+    pub fn epoch_nullifier(&self, epoch: u64) -> [u8; 32] {
+        let mut preimage = [0u8; 72];
+        preimage[0..32].copy_from_slice(&EPOCH_DOMAIN);
+        preimage[32..64].copy_from_slice(&self.secret);
+        preimage[64..72].copy_from_slice(&epoch.to_be_bytes());
+        keccak256(&preimage)
+    }
+
+    /// Revealed when `counter` is replaced.
+    pub fn counter_nullifier(&self, counter: &PolicyCounter) -> [u8; 32] {
+        let mut preimage = [0u8; 96];
+        preimage[0..32].copy_from_slice(&COUNTER_NULLIFIER_DOMAIN);
+        preimage[32..64].copy_from_slice(&counter.commitment());
+        preimage[64..96].copy_from_slice(&self.secret);
+        keccak256(&preimage)
+    }
+}
+
+/// What a policy has paid out so far in one epoch.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PolicyCounter {
+    pub policy_key: [u8; 32],
+    pub epoch: u64,
+    pub spent: u64,
+    /// Fresh randomness, so counters with equal totals differ
+    pub blinding: [u8; 32],
+}
+
+impl PolicyCounter {
+    /// The leaf stored in the counter tree.
+    pub fn commitment(&self) -> [u8; 32] {
+        let mut preimage = [0u8; 112];
+        preimage[0..32].copy_from_slice(&COUNTER_DOMAIN);
+        preimage[32..64].copy_from_slice(&self.policy_key);
+        preimage[64..72].copy_from_slice(&self.epoch.to_be_bytes());
+        preimage[72..80].copy_from_slice(&self.spent.to_be_bytes());
+        preimage[80..112].copy_from_slice(&self.blinding);
+        keccak256(&preimage)
+    }
+}
+
+/// The epoch's current counter and its proof under `counter_root`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CounterInput {
+    pub counter: PolicyCounter,
+    pub merkle_proof: Vec<MerkleProofStep>,
+}
+
+/// Private inputs for the policy-spend circuit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PolicySpendPrivateInputs {
+    /// The policy note being spent
+    pub note: Note,
+    /// Spending key of the note's owner
+    pub spending_key: [u8; 32],
+    pub policy: Policy,
+    /// Merkle proof of the note under `root`
+    pub merkle_proof: Vec<MerkleProofStep>,
+    /// A root of the note tree
+    pub root: [u8; 32],
+    /// Counts against the limit
+    pub payment_note: Note,
+    /// Goes back to the same owner and policy
+    pub change_note: Note,
+    /// The epoch's counter, or `None` on its first spend
+    pub counter: Option<CounterInput>,
+    /// A root of the counter tree
+    pub counter_root: [u8; 32],
+    /// The epoch the spend counts against
+    pub epoch: u64,
+    /// Blinding of the new counter; must be fresh randomness
+    pub counter_blinding: [u8; 32],
+    /// The pool the proof is for (see `crate::pool_binding`)
+    #[serde(default)]
+    pub pool_binding: [u8; 32],
+}
+
+/// Public values committed by the policy-spend circuit (352 bytes).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicySpendPublicValues {
+    pub root: [u8; 32],
+    pub nullifier: [u8; 32],
+    pub payment_commitment: [u8; 32],
+    pub change_commitment: [u8; 32],
+    pub counter_root: [u8; 32],
+    /// The old counter's nullifier, or the epoch nullifier
+    pub counter_nullifier: [u8; 32],
+    pub new_counter: [u8; 32],
+    pub epoch: u64,
+    /// Depth of the note's Merkle proof
+    pub tree_levels: u64,
+    /// Depth of the counter's Merkle proof, or zero on an epoch's first spend
+    pub counter_levels: u64,
+    /// The pool the proof is for (see `crate::pool_binding`)
+    pub pool_binding: [u8; 32],
+}
+
+impl PolicySpendPrivateInputs {
+    /// The counter the spend appends: the epoch's total including this payment.
+    pub fn new_counter(&self) -> Result<PolicyCounter, &'static str> {
+        let spent = self.counter.as_ref().map_or(0, |c| c.counter.spent);
+        let spent = spent.checked_add(self.payment_note.amount).ok_or("spend exceeds the policy's epoch limit")?;
+        if spent > self.policy.limit {
+            return Err("spend exceeds the policy's epoch limit");
+        }
+        Ok(PolicyCounter {
+            policy_key: self.policy.policy_key(),
+            epoch: self.epoch,
+            spent,
+            blinding: self.counter_blinding,
+        })
+    }
+
+    /// The public values a proof of these inputs commits, or the circuit's
+    /// panic message if it would reject them. The guest runs the same checks.
+    pub fn check(&self) -> Result<PolicySpendPublicValues, &'static str> {
+        // The owner's key and the policy together open the note's pubkey
+        probe!(start "policy_spend/authorize");
+        let lock_pubkey = self.policy.note_pubkey(&derive_pubkey(&self.spending_key));
+        if lock_pubkey != self.note.pubkey {
+            return Err("spending key and policy do not match note pubkey");
+        }
+        probe!(end "policy_spend/authorize");
+        let commitment = self.note.commitment();
+        probe!(start "policy_spend/merkle");
+        if !verify_merkle_proof(commitment, &self.merkle_proof, self.root) {
+            return Err("Merkle proof invalid");
+        }
+        probe!(end "policy_spend/merkle");
+        probe!(start "policy_spend/output_range");
+        check_output_amount(self.payment_note.amount)?;
+        check_output_amount(self.change_note.amount)?;
+        probe!(end "policy_spend/output_range");
+        probe!(start "policy_spend/conservation");
+        if self.payment_note.amount as u128 + self.change_note.amount as u128 != self.note.amount as u128 {
+            return Err("amounts don't balance");
+        }
+        probe!(end "policy_spend/conservation");
+        // Only the payment leaves the policy
+        probe!(start "policy_spend/change_owner");
+        if self.change_note.pubkey != lock_pubkey {
+            return Err("change note leaves the policy");
+        }
+        probe!(end "policy_spend/change_owner");
+        // Consume the epoch's counter, or the epoch nullifier on its first
+        // spend
+        probe!(start "policy_spend/counter");
+        let counter_nullifier = match &self.counter {
+            Some(input) => {
+                if input.counter.policy_key != self.policy.policy_key() {
+                    return Err("counter is for another policy");
+                }
+                if input.counter.epoch != self.epoch {
+                    return Err("counter is from another epoch");
+                }
+                // An empty proof would commit zero levels, which the pool
+                // reads as an epoch's first spend and checks no root for
+                if input.merkle_proof.is_empty()
+                    || !verify_merkle_proof(input.counter.commitment(), &input.merkle_proof, self.counter_root)
+                {
+                    return Err("counter Merkle proof invalid");
+                }
+                self.policy.counter_nullifier(&input.counter)
+            }
+            None => self.policy.epoch_nullifier(self.epoch),
+        };
+        probe!(end "policy_spend/counter");
+        probe!(arm "policy_spend/counter", if self.counter.is_some() { "counter" } else { "first_of_epoch" });
+        // The epoch's total stays within the limit
+        probe!(start "policy_spend/limit");
+        let new_counter = self.new_counter()?;
+        probe!(end "policy_spend/limit");
+        Ok(PolicySpendPublicValues {
+            root: self.root,
+            nullifier: compute_nullifier(&commitment, &self.spending_key),
+            payment_commitment: self.payment_note.commitment(),
+            change_commitment: self.change_note.commitment(),
+            counter_root: self.counter_root,
+            counter_nullifier,
+            new_counter: new_counter.commitment(),
+            epoch: self.epoch,
+            tree_levels: self.merkle_proof.len() as u64,
+            counter_levels: self.counter.as_ref().map_or(0, |input| input.merkle_proof.len() as u64),
+            pool_binding: self.pool_binding,
+        })
+    }
+}
+
+impl PolicySpendPublicValues {
+    pub const LEN: usize = 352;
+
+    /// ABI-encode exactly as the policy-spend guest commits it.
+    pub fn encode(&self) -> [u8; 352] {
+        let mut out = [0u8; 352];
+        let words = [
+            &self.root,
+            &self.nullifier,
+            &self.payment_commitment,
+            &self.change_commitment,
+            &self.counter_root,
+            &self.counter_nullifier,
+            &self.new_counter,
+        ];
+        for (chunk, word) in out.chunks_mut(32).zip(words) {
+            chunk.copy_from_slice(word);
+        }
+        out[248..256].copy_from_slice(&self.epoch.to_be_bytes());
+        out[280..288].copy_from_slice(&self.tree_levels.to_be_bytes());
+        out[312..320].copy_from_slice(&self.counter_levels.to_be_bytes());
+        out[320..352].copy_from_slice(&self.pool_binding);
+        out
+    }
+
+    /// Decode committed public values. Returns `None` if the length is wrong
+    /// or the epoch or a tree depth does not fit in a u64.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LEN || [224, 256, 288].iter().any(|&at| bytes[at..at + 24].iter().any(|&b| b != 0)) {
+            return None;
+        }
+        let number = |at: usize| u64::from_be_bytes(bytes[at..at + 8].try_into().unwrap());
+        let word = |i: usize| -> [u8; 32] { bytes[i * 32..(i + 1) * 32].try_into().unwrap() };
+        Some(PolicySpendPublicValues {
+            root: word(0),
+            nullifier: word(1),
+            payment_commitment: word(2),
+            change_commitment: word(3),
+            counter_root: word(4),
+            counter_nullifier: word(5),
+            new_counter: word(6),
+            epoch: number(248),
+            tree_levels: number(280),
+            counter_levels: number(312),
+            pool_binding: word(10),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IncrementalMerkleTree;
+
+    const TREASURER: [u8; 32] = [0x11; 32];
+    const CLERK: [u8; 32] = [0x22; 32];
+    const POLICY: Policy = Policy { limit: 1_000, secret: [0x5e; 32] };
+    const EPOCH: u64 = 20_000;
+
+    /// The spends below share one note tree and one counter tree, as a pool
+    /// verifying policy spends would keep them.
+    struct Pool {
+        notes: IncrementalMerkleTree,
+        counters: IncrementalMerkleTree,
+    }
+
+    impl Pool {
+        fn new() -> Self {
+            Pool { notes: IncrementalMerkleTree::new(8), counters: IncrementalMerkleTree::new(8) }
+        }
+
+        /// A spend by `key` of a fresh policy note of `amount`, paying `payment`.
+        fn spend(
+            &mut self,
+            key: [u8; 32],
+            amount: u64,
+            payment: u64,
+            counter: Option<(PolicyCounter, u32)>,
+        ) -> PolicySpendPrivateInputs {
+            let owner = derive_pubkey(&key);
+            let note = POLICY.note(&owner, amount, [self.notes.leaves.len() as u8 + 1; 32]);
+            let leaf = self.notes.leaves.len() as u32;
+            self.notes.insert(note.commitment());
+            PolicySpendPrivateInputs {
+                merkle_proof: self.notes.get_proof(leaf),
+                root: self.notes.get_root(),
+                payment_note: Note { amount: payment, pubkey: [0xab; 32], blinding: [0xcd; 32] },
+                change_note: POLICY.note(&owner, amount - payment, [0xef; 32]),
+                note,
+                spending_key: key,
+                policy: POLICY,
+                counter: counter
+                    .map(|(counter, leaf)| CounterInput { counter, merkle_proof: self.counters.get_proof(leaf) }),
+                counter_root: self.counters.get_root(),
+                epoch: EPOCH,
+                counter_blinding: [self.counters.leaves.len() as u8 + 0x70; 32],
+                pool_binding: [0x5b; 32],
+            }
+        }
+
+        /// Append the spend's new counter, returning it and its leaf.
+        fn record(&mut self, spend: &PolicySpendPrivateInputs) -> (PolicyCounter, u32) {
+            let counter = spend.new_counter().unwrap();
+            let leaf = self.counters.leaves.len() as u32;
+            self.counters.insert(counter.commitment());
+            (counter, leaf)
+        }
+    }
+
+    #[test]
+    fn test_policy_spends_add_up_within_an_epoch() {
+        let mut pool = Pool::new();
+        let first = pool.spend(TREASURER, 5_000, 600, None);
+        let pv = first.check().unwrap();
+        assert_eq!(pv.counter_nullifier, POLICY.epoch_nullifier(EPOCH));
+        assert_eq!(pv.nullifier, compute_nullifier(&first.note.commitment(), &TREASURER));
+        assert_eq!((pv.epoch, pv.tree_levels, pv.counter_levels), (EPOCH, 8, 0));
+        assert_eq!(pv.pool_binding, [0x5b; 32]);
+        assert_eq!(PolicySpendPublicValues::LEN, 352);
+        assert_eq!(PolicySpendPublicValues::decode(&pv.encode()), Some(pv.clone()));
+        let (counter, leaf) = pool.record(&first);
+        assert_eq!(counter.spent, 600);
+
+        // Another key under the same policy shares the epoch's limit
+        let second = pool.spend(CLERK, 2_000, 400, Some((counter.clone(), leaf)));
+        let pv = second.check().unwrap();
+        assert_eq!(pv.counter_nullifier, POLICY.counter_nullifier(&counter));
+        assert_eq!(pv.counter_levels, 8);
+        let (counter, leaf) = pool.record(&second);
+        assert_eq!(counter.spent, 1_000);
+
+        let over = pool.spend(CLERK, 2_000, 1, Some((counter, leaf)));
+        assert_eq!(over.check(), Err("spend exceeds the policy's epoch limit"));
+        // A new epoch starts from zero
+        let tomorrow = PolicySpendPrivateInputs { counter: None, epoch: EPOCH + 1, ..over };
+        assert!(tomorrow.check().is_ok());
+        assert_eq!(epoch_at(EPOCH * EPOCH_SECONDS + EPOCH_SECONDS - 1), EPOCH);
+    }
+
+    #[test]
+    fn test_policy_spend_rejects_escapes() {
+        let mut pool = Pool::new();
+        let first = pool.spend(TREASURER, 5_000, 600, None);
+        let (counter, leaf) = pool.record(&first);
+        let base = pool.spend(TREASURER, 5_000, 100, Some((counter, leaf)));
+        assert!(base.check().is_ok());
+
+        let rejected = |change: fn(&mut PolicySpendPrivateInputs)| {
+            let mut spend = base.clone();
+            change(&mut spend);
+            spend.check().unwrap_err()
+        };
+        assert_eq!(rejected(|s| s.spending_key = CLERK), "spending key and policy do not match note pubkey");
+        // Raising the limit changes the policy key, so the note no longer opens
+        assert_eq!(rejected(|s| s.policy.limit = u64::MAX), "spending key and policy do not match note pubkey");
+        assert_eq!(rejected(|s| s.root = [0u8; 32]), "Merkle proof invalid");
+        assert_eq!(rejected(|s| s.change_note.amount += 1), "amounts don't balance");
+        assert_eq!(rejected(|s| s.change_note.pubkey = derive_pubkey(&TREASURER)), "change note leaves the policy");
+        assert_eq!(rejected(|s| s.epoch += 1), "counter is from another epoch");
+        assert_eq!(rejected(|s| s.counter.as_mut().unwrap().counter.spent = 0), "counter Merkle proof invalid");
+        // A counter proven by an empty path to itself would commit zero
+        // counter levels, which the pool takes for an epoch's first spend
+        let forged = |s: &mut PolicySpendPrivateInputs| {
+            let input = s.counter.as_mut().unwrap();
+            input.counter.spent = 0;
+            input.merkle_proof.clear();
+            s.counter_root = input.counter.commitment();
+        };
+        assert_eq!(rejected(forged), "counter Merkle proof invalid");
+        assert_eq!(
+            rejected(|s| s.counter.as_mut().unwrap().counter.policy_key = [0u8; 32]),
+            "counter is for another policy"
+        );
+
+        // A plain note is not spendable as a policy note, nor a policy note
+        // as a plain one
+        let plain = Note { amount: 5_000, pubkey: derive_pubkey(&TREASURER), blinding: [1; 32] };
+        let mut as_policy = base.clone();
+        as_policy.note = plain;
+        assert_eq!(as_policy.check(), Err("spending key and policy do not match note pubkey"));
+        assert!(!base.note.is_owned_by(&TREASURER));
+    }
+}
@@ -0,0 +1,220 @@
+//! Fee-in-kind payments to a proving relayer.
+//!
+//! A relayer that proves transfers for its users can be paid in shielded
+//! USDT instead of off-chain. It hands out a `FeeQuote` from its `/quote`
+//! endpoint; the user's transfer then sends one of its two outputs to the
+//! relayer's shielded pubkey:
+//!
+//!   outputs = [payment, fee note]
+//!
+//! The fee note takes the slot that would otherwise carry change, so its
+//! amount is everything the inputs hold beyond the payment, and at least the
+//! quoted fee. `plan_fee_transfer` picks the inputs that overpay least.
+//!
+//! The fee note's blinding is derived from the quote id, so the relayer can
+//! recognise and later spend the note from its quote records alone. Before
+//! proving it checks the private inputs (`check_transfer`); the proof's
+//! public values then commit to the fee note (`check_public_values`), so the
+//! relayer is paid exactly when the proven transfer lands on-chain.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coin_selection::{NoteRef, PlannedStep, SelectionError, SpendPlan},
+    keccak256, Note, TransferPrivateInputs, TransferPublicValues,
+};
+
+/// Domain separator for fee-note blindings.
+pub const FEE_BLINDING_DOMAIN: &[u8] = b"prover-fee";
+
+/// Output slot the fee note occupies in a fee-paying transfer.
+pub const FEE_OUTPUT_INDEX: usize = 1;
+
+/// A relayer's price for proving one transfer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeQuote {
+    /// Random id issued by the relayer; also seeds the fee note's blinding
+    pub quote_id: [u8; 32],
+    /// Relayer's shielded pubkey (keccak256 of its spending key)
+    pub prover_pubkey: [u8; 32],
+    /// Minimum fee in raw token units (6 decimals)
+    pub fee: u64,
+    /// Unix time after which the relayer no longer honours the quote
+    pub expires_at: u64,
+}
+
+impl FeeQuote {
+    /// keccak256("prover-fee" || quote_id)
+    pub fn fee_blinding(&self) -> [u8; 32] {
+        let mut preimage = Vec::with_capacity(FEE_BLINDING_DOMAIN.len() + 32);
+        preimage.extend_from_slice(FEE_BLINDING_DOMAIN);
+        preimage.extend_from_slice(&self.quote_id);
+        keccak256(&preimage)
+    }
+
+    /// The note paying `amount` (at least `fee`) to the relayer under this quote.
+    pub fn fee_note(&self, amount: u64) -> Note {
+        Note {
+            amount,
+            pubkey: self.prover_pubkey,
+            blinding: self.fee_blinding(),
+        }
+    }
+
+    /// Check a transfer the relayer is asked to prove: the quote is still
+    /// valid at `now` and the fee output pays it. Returns the amount paid.
+    pub fn check_transfer(&self, inputs: &TransferPrivateInputs, now: u64) -> Result<u64, &'static str> {
+        if now > self.expires_at {
+            return Err("fee quote expired");
+        }
+        let note = &inputs.output_notes[FEE_OUTPUT_INDEX];
+        if note.pubkey != self.prover_pubkey || note.blinding != self.fee_blinding() {
+            return Err("fee output is not addressed to the prover under this quote");
+        }
+        if note.amount < self.fee {
+            return Err("fee output is below the quoted fee");
+        }
+        Ok(note.amount)
+    }
+
+    /// Check that a proof's public values commit to the fee note of `amount`.
+    pub fn check_public_values(&self, pv: &TransferPublicValues, amount: u64) -> Result<(), &'static str> {
+        if pv.out_commitments[FEE_OUTPUT_INDEX] != self.fee_note(amount).commitment() {
+            return Err("proof does not commit to the fee note");
+        }
+        Ok(())
+    }
+}
+
+/// Plan a single transfer of `amount` that pays `quote` in its second
+/// output. Picks the pair of notes whose surplus over `amount + quote.fee`
+/// is smallest; the whole surplus goes to the relayer, so pairs paying more
+/// than `max_fee` are not considered. Returns the plan (the `Pay` step's
+/// change is the fee paid).
+pub fn plan_fee_transfer(
+    notes: &[Note],
+    amount: u64,
+    quote: &FeeQuote,
+    max_fee: u64,
+) -> Result<SpendPlan, SelectionError> {
+    if amount == 0 {
+        return Err(SelectionError::ZeroAmount);
+    }
+    let required = amount.saturating_add(quote.fee);
+    let available: u64 = notes.iter().map(|n| n.amount).fold(0u64, u64::saturating_add);
+    if available < required {
+        return Err(SelectionError::InsufficientFunds { available, required });
+    }
+    if notes.len() < 2 {
+        return Err(SelectionError::NeedsTwoNotes);
+    }
+
+    let mut best: Option<(u64, usize, usize)> = None;
+    for a in 0..notes.len() {
+        for b in a + 1..notes.len() {
+            let sum = notes[a].amount as u128 + notes[b].amount as u128;
+            if sum < required as u128 || sum - (amount as u128) > max_fee as u128 {
+                continue;
+            }
+            let key = ((sum - amount as u128) as u64, a, b);
+            if best.is_none_or(|k| key < k) {
+                best = Some(key);
+            }
+        }
+    }
+    let (fee, a, b) = best.ok_or(SelectionError::NoPairWithinFee { fee: quote.fee, max_fee })?;
+    Ok(SpendPlan {
+        steps: alloc::vec![PlannedStep::Pay {
+            inputs: [NoteRef::Wallet(a), NoteRef::Wallet(b)],
+            amount,
+            change: fee,
+        }],
+        change_split: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleProofStep, SpenderKey, TransferPublicValues};
+    use alloc::vec;
+
+    fn quote() -> FeeQuote {
+        FeeQuote { quote_id: [7u8; 32], prover_pubkey: [9u8; 32], fee: 20_000, expires_at: 1_000 }
+    }
+
+    fn notes(amounts: &[u64]) -> Vec<Note> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, &amount)| Note { amount, pubkey: [1u8; 32], blinding: [i as u8; 32] })
+            .collect()
+    }
+
+    #[test]
+    fn test_fee_transfer_checks() {
+        let q = quote();
+        let ns = notes(&[600_000, 430_000]);
+        let plan = plan_fee_transfer(&ns, 1_000_000, &q, 50_000).unwrap();
+        assert_eq!(plan.payment(), ([NoteRef::Wallet(0), NoteRef::Wallet(1)], 1_000_000, 30_000));
+
+        let step = MerkleProofStep { is_left: true, sibling: [0u8; 32] };
+        let mut inputs = TransferPrivateInputs {
+            input_notes: [ns[0].clone(), ns[1].clone()],
+            spender_keys: [SpenderKey::Legacy([1u8; 32]); 2],
+            merkle_proofs: [vec![step.clone()], vec![step]],
+            output_notes: [Note { amount: 1_000_000, pubkey: [2u8; 32], blinding: [3u8; 32] }, q.fee_note(30_000)],
+            root: [0u8; 32],
+            locks: [None, None],
+            association: None,
+            pool_binding: [0u8; 32],
+            output_encryption: None,
+            multisig: [None, None],
+            spend_signatures: Vec::new(),
+        };
+        assert_eq!(q.check_transfer(&inputs, 999), Ok(30_000));
+        assert_eq!(q.check_transfer(&inputs, 1_001), Err("fee quote expired"));
+
+        let pv = TransferPublicValues {
+            root: [0u8; 32],
+            nullifiers: [[1u8; 32], [2u8; 32]],
+            out_commitments: [inputs.output_notes[0].commitment(), inputs.output_notes[1].commitment()],
+            reclaim_deadline: 0,
+            association_root: [0u8; 32],
+            pool_binding: [0u8; 32],
+            ciphertexts_hash: [0u8; 32],
+            tree_levels: 20,
+        };
+        assert_eq!(q.check_public_values(&pv, 30_000), Ok(()));
+        assert!(q.check_public_values(&pv, 20_000).is_err());
+
+        // Wrong slot, short fee, foreign blinding
+        inputs.output_notes.swap(0, 1);
+        assert!(q.check_transfer(&inputs, 0).is_err());
+        inputs.output_notes.swap(0, 1);
+        inputs.output_notes[1] = q.fee_note(19_999);
+        assert_eq!(q.check_transfer(&inputs, 0), Err("fee output is below the quoted fee"));
+        inputs.output_notes[1].amount = 20_000;
+        inputs.output_notes[1].blinding = [0u8; 32];
+        assert!(q.check_transfer(&inputs, 0).is_err());
+    }
+
+    #[test]
+    fn test_plan_fee_transfer_limits_overpayment() {
+        let q = quote();
+        // 700k + 330k pays 30k, 700k + 320k pays exactly 20k
+        let ns = notes(&[700_000, 330_000, 320_000]);
+        let plan = plan_fee_transfer(&ns, 1_000_000, &q, 50_000).unwrap();
+        assert_eq!(plan.payment(), ([NoteRef::Wallet(0), NoteRef::Wallet(2)], 1_000_000, 20_000));
+
+        assert_eq!(
+            plan_fee_transfer(&notes(&[700_000, 400_000]), 1_000_000, &q, 50_000),
+            Err(SelectionError::NoPairWithinFee { fee: 20_000, max_fee: 50_000 })
+        );
+        assert_eq!(
+            plan_fee_transfer(&notes(&[700_000, 310_000]), 1_000_000, &q, 50_000),
+            Err(SelectionError::InsufficientFunds { available: 1_010_000, required: 1_020_000 })
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Building an `IncrementalMerkleTree` from a whole leaf list at once.
+//!
+//! Inserting n leaves one by one hashes `levels` nodes per leaf. A rebuild
+//! instead hashes the tree level by level, each node once, and with the
+//! `parallel` feature hashes each level on all cores (rayon).
+//!
+//! Leaves are streamed from an iterator (e.g. a database cursor) in batches
+//! of `BATCH`: each batch's completed nodes are hashed before the next is
+//! read, so besides the tree itself only one batch of parents and the last
+//! ROOT_HISTORY_SIZE - 1 leaves are held. Those last leaves are inserted one
+//! by one on top of the bulk build, so the root history is filled exactly as
+//! the pool fills it, and the result is the same as inserting every leaf.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::{hash_pair, IncrementalMerkleTree, ROOT_HISTORY_SIZE};
+
+/// Leaves read from the iterator between hashing passes.
+const BATCH: usize = 1 << 12;
+
+/// Parents of a level of nodes; an odd last node is paired with `zero`.
+fn hash_level(children: &[[u8; 32]], zero: &[u8; 32]) -> Vec<[u8; 32]> {
+    let parent = |pair: &[[u8; 32]]| hash_pair(&pair[0], pair.get(1).unwrap_or(zero));
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        children.par_chunks(2).map(parent).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        children.chunks(2).map(parent).collect()
+    }
+}
+
+impl IncrementalMerkleTree {
+    /// Tree holding `leaves` in order, equal to inserting them one by one
+    /// into `IncrementalMerkleTree::new(levels)`. Reads the iterator once;
+    /// fails as soon as it yields more leaves than the tree holds.
+    pub fn from_leaves(levels: usize, leaves: impl IntoIterator<Item = [u8; 32]>) -> Result<Self, &'static str> {
+        let mut tree = IncrementalMerkleTree::new(levels);
+        let capacity = tree.capacity();
+        let mut count = 0u64;
+        // The last ROOT_HISTORY_SIZE - 1 leaves seen; older ones go to the bulk build
+        let mut tail = VecDeque::with_capacity(ROOT_HISTORY_SIZE);
+        for leaf in leaves {
+            count += 1;
+            if count > capacity {
+                return Err("Merkle tree is full");
+            }
+            tail.push_back(leaf);
+            if tail.len() == ROOT_HISTORY_SIZE {
+                tree.leaves.extend(tail.pop_front());
+                if tree.leaves.len().is_multiple_of(BATCH) {
+                    tree.hash_pending(false);
+                }
+            }
+        }
+        if !tree.leaves.is_empty() {
+            tree.finish_bulk();
+        }
+        for leaf in tail {
+            tree.try_insert(leaf)?;
+        }
+        Ok(tree)
+    }
+
+    /// Hash the parents of all children not hashed yet, bottom up. Pairs
+    /// still missing their right child are left for later unless `last`,
+    /// which pairs them with the zero value.
+    fn hash_pending(&mut self, last: bool) {
+        for height in 1..self.levels {
+            let children = self.node_level(height - 1);
+            let done = self.nodes[height - 1].len() * 2;
+            let end = if last { children.len() } else { children.len() & !1 };
+            if end > done {
+                let parents = hash_level(&children[done..end], &self.zeros[height - 1]);
+                self.nodes[height - 1].extend(parents);
+            }
+        }
+    }
+
+    /// Complete a bulk build of `self.leaves` (at least one) into an empty
+    /// tree. Only the final root enters the root history.
+    fn finish_bulk(&mut self) {
+        self.hash_pending(true);
+        let last = self.leaves.len() - 1;
+        for height in 0..self.levels {
+            self.filled_subtrees[height] = self.node_level(height)[(last >> height) & !1];
+        }
+        let top = self.node_level(self.levels - 1);
+        let root = hash_pair(&top[0], top.get(1).unwrap_or(&self.zeros[self.levels - 1]));
+
+        self.next_index = self.leaves.len() as u32;
+        self.current_root_index = self.leaves.len() % ROOT_HISTORY_SIZE;
+        self.roots[self.current_root_index] = root;
+    }
+
+    fn node_level(&self, height: usize) -> &[[u8; 32]] {
+        if height == 0 {
+            &self.leaves
+        } else {
+            &self.nodes[height - 1]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak256;
+
+    #[test]
+    fn test_from_leaves_matches_inserts() {
+        let leaves: Vec<[u8; 32]> = (0..100u32).map(|i| keccak256(&i.to_be_bytes())).collect();
+        for n in [0, 1, 2, 28, 29, 30, 31, 59, 64, 100] {
+            let mut inserted = IncrementalMerkleTree::new(7);
+            for leaf in &leaves[..n] {
+                inserted.insert(*leaf);
+            }
+            let mut rebuilt = IncrementalMerkleTree::from_leaves(7, leaves[..n].iter().copied()).unwrap();
+            assert_eq!(rebuilt.get_root(), inserted.get_root(), "{n} leaves");
+            assert_eq!(rebuilt.roots, inserted.roots, "{n} leaves");
+            assert_eq!(rebuilt.current_root_index, inserted.current_root_index);
+            assert_eq!(rebuilt.filled_subtrees, inserted.filled_subtrees, "{n} leaves");
+            assert_eq!(rebuilt.nodes, inserted.nodes, "{n} leaves");
+
+            // Both keep growing the same way
+            let next = keccak256(b"next");
+            assert_eq!(rebuilt.insert(next), inserted.insert(next));
+            assert_eq!(rebuilt.get_root(), inserted.get_root());
+            assert_eq!(rebuilt.get_proof(n as u32), inserted.get_proof(n as u32));
+        }
+        assert!(IncrementalMerkleTree::from_leaves(3, leaves[..9].iter().copied()).is_err());
+        // An endless stream fails once it overflows the tree
+        assert_eq!(IncrementalMerkleTree::from_leaves(3, core::iter::repeat([1u8; 32])).err(), Some("Merkle tree is full"));
+        assert_eq!(IncrementalMerkleTree::from_leaves(3, leaves[..8].iter().copied()).unwrap().remaining_capacity(), 0);
+    }
+
+    #[test]
+    fn test_from_leaves_across_batches() {
+        let mut inserted = IncrementalMerkleTree::new(14);
+        let mut n = 0;
+        for count in [BATCH - 1, BATCH, BATCH + ROOT_HISTORY_SIZE - 1, BATCH + ROOT_HISTORY_SIZE, 2 * BATCH + 77] {
+            while n < count {
+                inserted.insert(keccak256(&(n as u32).to_be_bytes()));
+                n += 1;
+            }
+            let rebuilt =
+                IncrementalMerkleTree::from_leaves(14, (0..count as u32).map(|i| keccak256(&i.to_be_bytes()))).unwrap();
+            assert_eq!(rebuilt.roots, inserted.roots, "{count} leaves");
+            assert_eq!(rebuilt.filled_subtrees, inserted.filled_subtrees, "{count} leaves");
+            assert_eq!(rebuilt.nodes, inserted.nodes, "{count} leaves");
+        }
+    }
+}
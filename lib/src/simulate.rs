@@ -0,0 +1,162 @@
+//! Native runs of the transfer and withdraw guests.
+//!
+//! Executing a guest takes seconds and proving it minutes, only to find out
+//! the inputs were bad. `simulate_transfer` and `simulate_withdraw` make the
+//! guest's checks on the host instead, in the guest's order, and return what
+//! it would commit or why it would abort: its panic message and the abort
+//! code it would halt with (see `abort`).
+//!
+//! The checks are `TransferPrivateInputs::guest_check` and
+//! `WithdrawPrivateInputs::guest_check`, the same code the guests run between
+//! reading their inputs and committing, so the two cannot drift apart. The
+//! framing the guests also validate
+//! (`InputHeader` and the end marker) is the host's own to write, so it is
+//! not simulated.
+
+use core::fmt;
+
+use crate::abort::AbortCode;
+use crate::{CheckError, TransferPrivateInputs, TransferPublicValues, WithdrawPrivateInputs, WithdrawPublicValues};
+
+/// Why a guest would reject its inputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rejection {
+    /// The guest's panic message, without the "input note N: " it prefixes
+    /// to an input's authorization failure
+    pub message: &'static str,
+    /// The abort code the guest halts with
+    pub code: AbortCode,
+}
+
+impl From<&'static str> for Rejection {
+    fn from(message: &'static str) -> Self {
+        Rejection { message, code: AbortCode::classify(message) }
+    }
+}
+
+impl From<CheckError> for Rejection {
+    fn from(err: CheckError) -> Self {
+        Rejection::from(err.message)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (abort code {}: {})", self.message, self.code.exit_code(), self.code.explanation())
+    }
+}
+
+/// The public values the transfer guest commits for `inputs`, or why it
+/// would abort.
+pub fn simulate_transfer(inputs: &TransferPrivateInputs) -> Result<TransferPublicValues, Rejection> {
+    inputs.guest_check().map_err(Rejection::from)
+}
+
+/// The public values the withdraw guest commits for `inputs`, or why it
+/// would abort.
+pub fn simulate_withdraw(inputs: &WithdrawPrivateInputs) -> Result<WithdrawPublicValues, Rejection> {
+    inputs.guest_check().map_err(Rejection::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compute_nullifier, derive_pubkey, IncrementalMerkleTree, Note, SpenderKey};
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    const KEY: [u8; 32] = [0x31; 32];
+
+    /// Breaks one rule of otherwise valid withdraw inputs.
+    type BreakRule = fn(&mut WithdrawPrivateInputs);
+
+    /// Withdraw 600 of a 1000 note, 380 in change and 20 to a relayer.
+    fn withdraw() -> WithdrawPrivateInputs {
+        let note = Note { amount: 1_000, pubkey: derive_pubkey(&KEY), blinding: [1u8; 32] };
+        let mut tree = IncrementalMerkleTree::new(4);
+        tree.insert([9u8; 32]);
+        tree.insert(note.commitment());
+        let mut inputs = WithdrawPrivateInputs {
+            input_note: note,
+            spender_key: SpenderKey::derive(&KEY),
+            merkle_proof: tree.get_proof(1),
+            root: tree.get_root(),
+            recipient: [0x42; 20],
+            withdraw_amount: 600,
+            change_note: Some(Note { amount: 380, pubkey: derive_pubkey(&KEY), blinding: [2u8; 32] }),
+            lock: None,
+            fee: 20,
+            relayer: [0x52; 20],
+            association: None,
+            pool_binding: [7u8; 32],
+            deadline: 1_700_000_000,
+            call_target: [0u8; 20],
+            calldata_hash: [0u8; 32],
+            multisig: None,
+            spend_signatures: Vec::new(),
+        };
+        inputs.sign(&KEY).unwrap();
+        inputs
+    }
+
+    #[test]
+    fn test_simulate_withdraw_commits_the_guests_values() {
+        let inputs = withdraw();
+        let pv = simulate_withdraw(&inputs).unwrap();
+        assert_eq!(pv.nullifier, compute_nullifier(&inputs.input_note.commitment(), &KEY));
+        assert_eq!(pv.change_commitment, inputs.change_note.as_ref().unwrap().commitment());
+        assert_eq!((pv.amount, pv.fee, pv.reclaim_deadline, pv.tree_levels), (600, 20, 0, 4));
+        assert_eq!((pv.deadline, pv.call_binding), (1_700_000_000, [0u8; 32]));
+        assert_eq!(WithdrawPublicValues::decode(&pv.encode()), Some(pv));
+
+        // All of it, less the fee
+        let mut full = inputs;
+        full.change_note = None;
+        full.withdraw_amount = 980;
+        full.sign(&KEY).unwrap();
+        assert_eq!(simulate_withdraw(&full).unwrap().change_commitment, [0u8; 32]);
+    }
+
+    #[test]
+    fn test_simulate_withdraw_rejects_like_the_guest() {
+        let cases: [(BreakRule, &str, AbortCode); 9] = [
+            (|w| w.spender_key = SpenderKey::derive(&[8u8; 32]), "match note pubkey", AbortCode::Ownership),
+            (|w| w.root[0] ^= 1, "Merkle proof invalid", AbortCode::Merkle),
+            (|w| w.relayer = [0u8; 20], "relayer fee paid to the zero address", AbortCode::Relayer),
+            (
+                |w| w.change_note.as_mut().unwrap().pubkey = [9u8; 32],
+                "change note is not owned by the spender",
+                AbortCode::OutputOwner,
+            ),
+            (|w| w.withdraw_amount += 1, "partial withdrawal amounts don't balance", AbortCode::Conservation),
+            (
+                |w| {
+                    w.change_note = None;
+                    w.fee = u64::MAX - 599;
+                },
+                "full withdrawal amount mismatch",
+                AbortCode::Conservation,
+            ),
+            (|w| w.calldata_hash = [3u8; 32], "calldata hash without a call target", AbortCode::Call),
+            (|w| w.spend_signatures.clear(), "missing spend authorization", AbortCode::SpendAuthorization),
+            (|w| w.recipient = [0x43; 20], "invalid spend authorization", AbortCode::SpendAuthorization),
+        ];
+        for (break_rule, message, code) in cases {
+            let mut inputs = withdraw();
+            break_rule(&mut inputs);
+            let rejection = simulate_withdraw(&inputs).unwrap_err();
+            assert!(rejection.message.contains(message), "{rejection}");
+            assert_eq!(rejection.code, code, "{rejection}");
+        }
+    }
+
+    #[test]
+    fn test_rejection_explains_its_abort_code() {
+        let rejection = Rejection::from("duplicate input note");
+        assert_eq!(rejection.code, AbortCode::DuplicateInput);
+        assert_eq!(
+            rejection.to_string(),
+            "duplicate input note (abort code 22: the same note is spent twice in one proof)"
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Snapshots of an `IncrementalMerkleTree`.
+//!
+//! A snapshot holds the whole tree state (leaves, cached interior nodes,
+//! filled subtrees and root history), so restoring it needs no hashing
+//! beyond a consistency check. The tree implements serde with its field
+//! names (JSON for inspection), and there is a compact binary format
+//! (`to_snapshot_bytes` / `from_snapshot_bytes`, `save_snapshot` /
+//! `load_snapshot` with std):
+//!
+//!   magic "SPMT" | version u8 | levels u8 | next_index u32 |
+//!   current_root_index u8 | roots (ROOT_HISTORY_SIZE x 32) |
+//!   filled_subtrees (levels x 32) | leaves (next_index x 32) |
+//!   nodes, height 1 to levels-1 (ceil(next_index / 2^h) x 32 each) |
+//!   keccak256 of everything before it (32)
+//!
+//! `zeros` and the per-height node counts follow from `levels` and
+//! `next_index` and are not stored.
+//!
+//! serde works for trees of any arity; the binary format is for binary
+//! trees only.
+//!
+//! Both decoders reject snapshots whose shape does not match their header or
+//! whose cached top nodes do not hash to the current root.
+
+use alloc::vec::Vec;
+use core::fmt;
+use serde::Deserialize;
+
+use crate::{compute_nary_zeros, hash_children, keccak256, IncrementalMerkleTree, ROOT_HISTORY_SIZE};
+
+/// Binary snapshot format version.
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+const MAGIC: [u8; 4] = *b"SPMT";
+const MAX_LEVELS: usize = 32;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    UnsupportedVersion(u8),
+    /// The snapshot does not describe a consistent tree
+    Malformed(&'static str),
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported tree snapshot version {v}"),
+            SnapshotError::Malformed(what) => write!(f, "malformed tree snapshot: {what}"),
+            #[cfg(feature = "std")]
+            SnapshotError::Io(e) => write!(f, "tree snapshot i/o error: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SnapshotError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for SnapshotError {
+    fn from(e: std::io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+/// Deserialized tree fields, checked before becoming a tree.
+#[derive(Deserialize)]
+pub(crate) struct TreeParts {
+    levels: usize,
+    zeros: Vec<[u8; 32]>,
+    filled_subtrees: Vec<[u8; 32]>,
+    next_index: u32,
+    roots: Vec<[u8; 32]>,
+    current_root_index: usize,
+    leaves: Vec<[u8; 32]>,
+    nodes: Vec<Vec<[u8; 32]>>,
+}
+
+impl<const ARITY: usize> TryFrom<TreeParts> for IncrementalMerkleTree<ARITY> {
+    type Error = SnapshotError;
+
+    fn try_from(p: TreeParts) -> Result<Self, SnapshotError> {
+        if p.levels == 0 || p.levels > MAX_LEVELS {
+            return Err(SnapshotError::Malformed("levels out of range"));
+        }
+        if p.zeros != compute_nary_zeros::<ARITY>(p.levels) {
+            return Err(SnapshotError::Malformed("zeros do not match levels"));
+        }
+        let tree = IncrementalMerkleTree {
+            levels: p.levels,
+            zeros: p.zeros,
+            filled_subtrees: p.filled_subtrees,
+            next_index: p.next_index,
+            roots: p.roots,
+            current_root_index: p.current_root_index,
+            leaves: p.leaves,
+            nodes: p.nodes,
+            checkpoints: Vec::new(),
+        };
+        check_shape(&tree)?;
+        Ok(tree)
+    }
+}
+
+/// Lengths agree with `levels` and `next_index`, and the cached top nodes
+/// hash to the current root.
+fn check_shape<const ARITY: usize>(tree: &IncrementalMerkleTree<ARITY>) -> Result<(), SnapshotError> {
+    let n = tree.next_index as u64;
+    if n > tree.capacity() || tree.leaves.len() as u64 != n {
+        return Err(SnapshotError::Malformed("leaf count does not match next_index"));
+    }
+    if tree.filled_subtrees.len() != tree.levels * (ARITY - 1)
+        || tree.roots.len() != ROOT_HISTORY_SIZE
+        || tree.current_root_index >= ROOT_HISTORY_SIZE
+        || tree.nodes.len() != tree.levels - 1
+    {
+        return Err(SnapshotError::Malformed("wrong number of subtrees, roots or node levels"));
+    }
+    for (i, stored) in tree.nodes.iter().enumerate() {
+        if stored.len() as u64 != nodes_at::<ARITY>(n, i + 1) {
+            return Err(SnapshotError::Malformed("wrong number of cached nodes"));
+        }
+    }
+    let top = tree.levels - 1;
+    let children: Vec<_> = (0..ARITY).map(|i| tree.node(top, i)).collect();
+    if hash_children(&children) != tree.get_root() {
+        return Err(SnapshotError::Malformed("cached nodes do not hash to the current root"));
+    }
+    Ok(())
+}
+
+/// Nodes at `height` with at least one of `leaves` below them.
+fn nodes_at<const ARITY: usize>(leaves: u64, height: usize) -> u64 {
+    leaves.div_ceil((ARITY as u64).saturating_pow(height as u32))
+}
+
+impl IncrementalMerkleTree {
+    /// Encode the tree in the compact binary snapshot format.
+    pub fn to_snapshot_bytes(&self) -> Vec<u8> {
+        let words = ROOT_HISTORY_SIZE
+            + self.levels
+            + self.leaves.len()
+            + self.nodes.iter().map(Vec::len).sum::<usize>();
+        let mut out = Vec::with_capacity(11 + 32 * (words + 1));
+        out.extend_from_slice(&MAGIC);
+        out.push(SNAPSHOT_VERSION);
+        out.push(self.levels as u8);
+        out.extend_from_slice(&self.next_index.to_be_bytes());
+        out.push(self.current_root_index as u8);
+        let nodes = self.nodes.iter().flatten();
+        for word in self.roots.iter().chain(&self.filled_subtrees).chain(&self.leaves).chain(nodes) {
+            out.extend_from_slice(word);
+        }
+        let checksum = keccak256(&out);
+        out.extend_from_slice(&checksum);
+        out
+    }
+
+    /// Decode a binary snapshot written by `to_snapshot_bytes`.
+    pub fn from_snapshot_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < 11 + 32 || bytes[0..4] != MAGIC {
+            return Err(SnapshotError::Malformed("bad header"));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 32);
+        if keccak256(body)[..] != *checksum {
+            return Err(SnapshotError::Malformed("checksum mismatch"));
+        }
+        if body[4] != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(body[4]));
+        }
+        let levels = body[5] as usize;
+        if levels == 0 || levels > MAX_LEVELS {
+            return Err(SnapshotError::Malformed("levels out of range"));
+        }
+        let next_index = u32::from_be_bytes(body[6..10].try_into().unwrap());
+        let n = next_index as u64;
+        if n > 1u64 << levels {
+            return Err(SnapshotError::Malformed("next_index exceeds capacity"));
+        }
+
+        let node_counts: Vec<u64> = (1..levels).map(|h| nodes_at::<2>(n, h)).collect();
+        let words = ROOT_HISTORY_SIZE as u64 + levels as u64 + n + node_counts.iter().sum::<u64>();
+        if (body.len() - 11) as u64 != 32 * words {
+            return Err(SnapshotError::Malformed("length does not match header"));
+        }
+        let mut words = body[11..].chunks_exact(32).map(|w| <[u8; 32]>::try_from(w).unwrap());
+        let mut take = |count: u64| -> Vec<[u8; 32]> { words.by_ref().take(count as usize).collect() };
+
+        TreeParts {
+            levels,
+            zeros: compute_nary_zeros::<2>(levels),
+            roots: take(ROOT_HISTORY_SIZE as u64),
+            filled_subtrees: take(levels as u64),
+            leaves: take(n),
+            nodes: node_counts.iter().map(|&count| take(count)).collect(),
+            next_index,
+            current_root_index: body[10] as usize,
+        }
+        .try_into()
+    }
+
+    /// Write a binary snapshot to `path` (via a temp file and rename).
+    #[cfg(feature = "std")]
+    pub fn save_snapshot(&self, path: impl AsRef<std::path::Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.to_snapshot_bytes())?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Read a binary snapshot written by `save_snapshot`.
+    #[cfg(feature = "std")]
+    pub fn load_snapshot(path: impl AsRef<std::path::Path>) -> Result<Self, SnapshotError> {
+        Self::from_snapshot_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(levels: usize, leaves: u32) -> IncrementalMerkleTree {
+        let mut tree = IncrementalMerkleTree::new(levels);
+        for i in 0..leaves {
+            tree.insert(keccak256(&i.to_be_bytes()));
+        }
+        tree
+    }
+
+    fn assert_same(a: &IncrementalMerkleTree, b: &IncrementalMerkleTree) {
+        assert_eq!(a.get_root(), b.get_root());
+        assert_eq!(a.roots, b.roots);
+        assert_eq!(a.current_root_index, b.current_root_index);
+        assert_eq!(a.filled_subtrees, b.filled_subtrees);
+        assert_eq!(a.leaves, b.leaves);
+        assert_eq!(a.nodes, b.nodes);
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        for (levels, leaves) in [(1, 0), (1, 2), (4, 0), (4, 7), (5, 32), (8, 45)] {
+            let original = tree(levels, leaves);
+            let restored = IncrementalMerkleTree::from_snapshot_bytes(&original.to_snapshot_bytes()).unwrap();
+            assert_same(&original, &restored);
+        }
+    }
+
+    #[test]
+    fn test_restored_tree_keeps_working() {
+        let mut original = tree(6, 13);
+        let mut restored = IncrementalMerkleTree::from_snapshot_bytes(&original.to_snapshot_bytes()).unwrap();
+        let old_root = original.get_root();
+        let leaf = keccak256(b"after restore");
+        assert_eq!(original.insert(leaf), restored.insert(leaf));
+        assert_same(&original, &restored);
+        assert!(restored.is_known_root(old_root));
+        assert!(crate::verify_merkle_proof(leaf, &restored.get_proof(13), restored.get_root()));
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let original = tree(5, 9);
+        let json = serde_json::to_string(&original).unwrap();
+        let restored: IncrementalMerkleTree = serde_json::from_str(&json).unwrap();
+        assert_same(&original, &restored);
+        // A binary tree is not a quaternary one
+        assert!(serde_json::from_str::<crate::QuaternaryMerkleTree>(&json).is_err());
+
+        let mut quaternary = crate::QuaternaryMerkleTree::with_levels(3);
+        for i in 0..22u32 {
+            quaternary.insert(keccak256(&i.to_be_bytes()));
+        }
+        let json = serde_json::to_string(&quaternary).unwrap();
+        let mut restored: crate::QuaternaryMerkleTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get_nary_proof(13), quaternary.get_nary_proof(13));
+        restored.insert([7u8; 32]);
+        quaternary.insert([7u8; 32]);
+        assert_eq!(restored.get_root(), quaternary.get_root());
+    }
+
+    #[test]
+    fn test_rejects_damaged_snapshots() {
+        let bytes = tree(4, 5).to_snapshot_bytes();
+
+        let mut flipped = bytes.clone();
+        flipped[200] ^= 1;
+        assert!(matches!(
+            IncrementalMerkleTree::from_snapshot_bytes(&flipped),
+            Err(SnapshotError::Malformed("checksum mismatch"))
+        ));
+        assert!(IncrementalMerkleTree::from_snapshot_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // A valid checksum over a cached node that does not match the root
+        let mut tampered = tree(4, 5);
+        tampered.nodes[2][0][0] ^= 1;
+        assert!(matches!(
+            IncrementalMerkleTree::from_snapshot_bytes(&tampered.to_snapshot_bytes()),
+            Err(SnapshotError::Malformed("cached nodes do not hash to the current root"))
+        ));
+    }
+}
@@ -0,0 +1,571 @@
+//! Canonical test vectors for cross-implementation compatibility.
+//!
+//! Everything here is derived from fixed inputs, so the JSON is identical on
+//! every run. The Solidity contracts and the TS SDK can load it and assert
+//! byte-for-byte agreement with this crate, the reference implementation:
+//!
+//!   keccak / hash_pair   — raw hashing
+//!   keys                 — spending key → nullifier and spend-auth keys → shielded pubkey (and legacy pubkey)
+//!   notes                — commitment and nullifier per note
+//!   zeros                — MerkleTree.sol zero values and empty root
+//!   cancellable          — lock pubkey, commitment and nullifier per cancellable note
+//!   multisig             — multisig pubkey, commitment and nullifier per 2-of-2 note
+//!   trees                — roots and membership proofs (steps and packed) after inserting the notes
+//!   pool_bindings        — pool binding per (chain id, pool address)
+//!   note_encryptions     — payload plaintext and envelope per note, viewing key and randomness
+//!   public_values        — ABI encodings for both circuits
+//!   spend_signatures     — spend-auth message and Ed25519 signature per public values vector
+//!
+//! All byte strings are 0x-prefixed lowercase hex; amounts are decimal
+//! strings so JavaScript can parse them without precision loss.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::cancellable::cancellable_nullifier;
+use crate::multisig::{multisig_nullifier, MultisigLock};
+use crate::note_encryption::{ciphertexts_hash, note_plaintext, OutputEncryption};
+use crate::{
+    call_binding, compute_empty_root, compute_nullifier, compute_zeros, derive_nullifier_key, derive_pubkey,
+    derive_spend_auth_key, hash_pair, keccak256, legacy_pubkey, pool_binding, spend_auth_message, spend_auth_pubkey,
+    CancellableLock, CircuitKind, IncrementalMerkleTree, MerkleProof, Note, SpendSignature, TransferPublicValues,
+    WithdrawPublicValues,
+};
+
+/// Bump when vectors are added, removed or change meaning.
+pub const TEST_VECTORS_VERSION: u32 = 12;
+
+/// Depth of the tree vectors (small enough to read by eye).
+pub const TREE_VECTOR_LEVELS: usize = 4;
+
+/// Depth of the zero-value vector (the deployed pool's default).
+pub const ZEROS_VECTOR_LEVELS: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVectors {
+    pub version: u32,
+    pub keccak: Vec<KeccakVector>,
+    pub hash_pair: Vec<HashPairVector>,
+    pub keys: Vec<KeyVector>,
+    pub notes: Vec<NoteVector>,
+    pub cancellable: Vec<CancellableVector>,
+    pub multisig: Vec<MultisigVector>,
+    pub zeros: ZerosVector,
+    pub trees: Vec<TreeVector>,
+    pub pool_bindings: Vec<PoolBindingVector>,
+    pub note_encryptions: Vec<NoteEncryptionVector>,
+    pub public_values: PublicValuesVectors,
+    pub spend_signatures: Vec<SpendSignatureVector>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeccakVector {
+    pub input: String,
+    pub output: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashPairVector {
+    pub left: String,
+    pub right: String,
+    pub output: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyVector {
+    pub spending_key: String,
+    pub nullifier_key: String,
+    /// Ed25519 seed of the spend signatures
+    pub spend_auth_key: String,
+    /// Its Ed25519 public key
+    pub auth_key: String,
+    pub pubkey: String,
+    /// keccak256(spending_key), the pubkey of notes created before proving keys
+    pub legacy_pubkey: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteVector {
+    pub amount: String,
+    pub pubkey: String,
+    pub blinding: String,
+    pub spending_key: String,
+    pub nullifier_key: String,
+    pub commitment: String,
+    pub nullifier: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CancellableVector {
+    pub recipient_pubkey: String,
+    pub sender_pubkey: String,
+    pub reclaim_deadline: String,
+    pub lock_pubkey: String,
+    pub amount: String,
+    pub blinding: String,
+    pub commitment: String,
+    /// Same for the claim and reclaim paths
+    pub nullifier: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigVector {
+    /// In lock order: owner 0 spends, owner 1 cosigns
+    pub spending_keys: [String; 2],
+    /// Derived from `spending_keys`; these open the nullifier
+    pub nullifier_keys: [String; 2],
+    pub owner_pubkeys: [String; 2],
+    pub multisig_pubkey: String,
+    pub amount: String,
+    pub blinding: String,
+    pub commitment: String,
+    pub nullifier: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZerosVector {
+    pub levels: usize,
+    pub zeros: Vec<String>,
+    pub empty_root: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeVector {
+    pub levels: usize,
+    /// Commitments in insertion order
+    pub leaves: Vec<String>,
+    /// Root after each insertion
+    pub roots: Vec<String>,
+    /// Membership proof for every leaf against the final root
+    pub proofs: Vec<ProofVector>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofVector {
+    pub leaf_index: u32,
+    pub steps: Vec<ProofStepVector>,
+    /// `MerkleProof::to_packed_bytes` of the same proof
+    pub packed: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStepVector {
+    pub is_left: bool,
+    pub sibling: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolBindingVector {
+    pub chain_id: String,
+    pub pool: String,
+    pub binding: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteEncryptionVector {
+    pub amount: String,
+    pub pubkey: String,
+    pub blinding: String,
+    pub viewing_pubkey: String,
+    pub ephemeral_secret: String,
+    pub nonce: String,
+    pub plaintext: String,
+    /// ephemeral_pubkey || nonce || ciphertext
+    pub payload: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicValuesVectors {
+    pub withdraw: Vec<WithdrawPublicValuesVector>,
+    pub transfer: Vec<TransferPublicValuesVector>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WithdrawPublicValuesVector {
+    pub root: String,
+    pub nullifier: String,
+    pub recipient: String,
+    pub amount: String,
+    pub change_commitment: String,
+    pub reclaim_deadline: String,
+    pub relayer: String,
+    pub fee: String,
+    pub association_root: String,
+    pub pool_binding: String,
+    pub deadline: String,
+    pub call_binding: String,
+    pub tree_levels: String,
+    pub encoded: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendSignatureVector {
+    /// "transfer" or "withdraw"
+    pub circuit: String,
+    /// The `encoded` public values signed
+    pub public_values: String,
+    pub spending_key: String,
+    pub message: String,
+    pub auth_key: String,
+    /// r || s
+    pub signature: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferPublicValuesVector {
+    pub root: String,
+    pub nullifiers: [String; 2],
+    pub out_commitments: [String; 2],
+    pub reclaim_deadline: String,
+    pub association_root: String,
+    pub pool_binding: String,
+    pub ciphertexts_hash: String,
+    pub tree_levels: String,
+    pub encoded: String,
+}
+
+fn hex0x(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+/// Fixed note set: (amount, spending key byte, blinding byte).
+const NOTES: [(u64, u8, u8); 4] = [
+    (700_000, 0xAB, 0x01),
+    (300_000, 0xAB, 0x02),
+    (1, 0xCD, 0x03),
+    (u64::MAX, 0xCD, 0x04),
+];
+
+/// Build the full vector set.
+pub fn generate() -> TestVectors {
+    let keccak = [&b""[..], b"abc", &[0u8; 32], b"shielded-pool"]
+        .iter()
+        .map(|input| KeccakVector { input: hex0x(input), output: hex0x(&keccak256(input)) })
+        .collect();
+
+    let hash_pair = [([0u8; 32], [0u8; 32]), ([0x11; 32], [0x22; 32]), ([0x22; 32], [0x11; 32])]
+        .iter()
+        .map(|(l, r)| HashPairVector { left: hex0x(l), right: hex0x(r), output: hex0x(&hash_pair(l, r)) })
+        .collect();
+
+    let keys = [[0u8; 32], [0xAB; 32], [0xCD; 32], [0xFF; 32]]
+        .iter()
+        .map(|sk| KeyVector {
+            spending_key: hex0x(sk),
+            nullifier_key: hex0x(&derive_nullifier_key(sk)),
+            spend_auth_key: hex0x(&derive_spend_auth_key(sk)),
+            auth_key: hex0x(&spend_auth_pubkey(&derive_spend_auth_key(sk))),
+            pubkey: hex0x(&derive_pubkey(sk)),
+            legacy_pubkey: hex0x(&legacy_pubkey(sk)),
+        })
+        .collect();
+
+    let notes: Vec<(Note, [u8; 32])> = NOTES
+        .iter()
+        .map(|&(amount, key_byte, blinding_byte)| {
+            let sk = [key_byte; 32];
+            (Note { amount, pubkey: derive_pubkey(&sk), blinding: [blinding_byte; 32] }, sk)
+        })
+        .collect();
+    let note_vectors = notes
+        .iter()
+        .map(|(note, sk)| {
+            let commitment = note.commitment();
+            NoteVector {
+                amount: note.amount.to_string(),
+                pubkey: hex0x(&note.pubkey),
+                blinding: hex0x(&note.blinding),
+                spending_key: hex0x(sk),
+                nullifier_key: hex0x(&derive_nullifier_key(sk)),
+                commitment: hex0x(&commitment),
+                nullifier: hex0x(&compute_nullifier(&commitment, sk)),
+            }
+        })
+        .collect();
+
+    let cancellable = [(0xCD, 0xAB, 1_700_000_000u64, 0x06), (0xAB, 0xCD, u64::MAX, 0x07)]
+        .iter()
+        .map(|&(recipient_byte, sender_byte, reclaim_deadline, blinding_byte)| {
+            let lock = CancellableLock {
+                recipient_pubkey: derive_pubkey(&[recipient_byte; 32]),
+                sender_pubkey: derive_pubkey(&[sender_byte; 32]),
+                reclaim_deadline,
+            };
+            let note = lock.note(250_000, [blinding_byte; 32]);
+            let commitment = note.commitment();
+            CancellableVector {
+                recipient_pubkey: hex0x(&lock.recipient_pubkey),
+                sender_pubkey: hex0x(&lock.sender_pubkey),
+                reclaim_deadline: reclaim_deadline.to_string(),
+                lock_pubkey: hex0x(&note.pubkey),
+                amount: note.amount.to_string(),
+                blinding: hex0x(&note.blinding),
+                commitment: hex0x(&commitment),
+                nullifier: hex0x(&cancellable_nullifier(&commitment, &note.blinding)),
+            }
+        })
+        .collect();
+
+    let multisig = [([0xAB, 0xCD], 0x08), ([0xCD, 0xAB], 0x08)]
+        .iter()
+        .map(|&(key_bytes, blinding_byte)| {
+            let spending_keys = key_bytes.map(|b| [b; 32]);
+            let nullifier_keys = spending_keys.map(|sk| derive_nullifier_key(&sk));
+            let lock = MultisigLock { owner_pubkeys: spending_keys.map(|sk| derive_pubkey(&sk)) };
+            let note = lock.note(400_000, [blinding_byte; 32]);
+            let commitment = note.commitment();
+            MultisigVector {
+                spending_keys: spending_keys.map(|sk| hex0x(&sk)),
+                nullifier_keys: nullifier_keys.map(|nk| hex0x(&nk)),
+                owner_pubkeys: lock.owner_pubkeys.map(|pk| hex0x(&pk)),
+                multisig_pubkey: hex0x(&note.pubkey),
+                amount: note.amount.to_string(),
+                blinding: hex0x(&note.blinding),
+                commitment: hex0x(&commitment),
+                nullifier: hex0x(&multisig_nullifier(&commitment, [&nullifier_keys[0], &nullifier_keys[1]])),
+            }
+        })
+        .collect();
+
+    let zeros = ZerosVector {
+        levels: ZEROS_VECTOR_LEVELS,
+        zeros: compute_zeros(ZEROS_VECTOR_LEVELS).iter().map(|z| hex0x(z)).collect(),
+        empty_root: hex0x(&compute_empty_root(ZEROS_VECTOR_LEVELS)),
+    };
+
+    let mut tree = IncrementalMerkleTree::new(TREE_VECTOR_LEVELS);
+    let mut leaves = Vec::new();
+    let mut roots = Vec::new();
+    for (note, _) in &notes {
+        let commitment = note.commitment();
+        tree.insert(commitment);
+        leaves.push(hex0x(&commitment));
+        roots.push(hex0x(&tree.get_root()));
+    }
+    let proofs = (0..notes.len() as u32)
+        .map(|i| {
+            let steps = tree.get_proof(i);
+            let packed = MerkleProof::from_steps(&steps).expect("vector tree is shallow").to_packed_bytes();
+            ProofVector {
+                leaf_index: i,
+                steps: steps
+                    .iter()
+                    .map(|s| ProofStepVector { is_left: s.is_left, sibling: hex0x(&s.sibling) })
+                    .collect(),
+                packed: hex0x(&packed),
+            }
+        })
+        .collect();
+    let trees = alloc::vec![TreeVector { levels: TREE_VECTOR_LEVELS, leaves, roots, proofs }];
+
+    // Plasma mainnet and testnet, and a local devnet
+    let pool_bindings: Vec<PoolBindingVector> = [(9745u64, [0x11u8; 20]), (9746, [0x11; 20]), (31337, [0x22; 20])]
+        .iter()
+        .map(|(chain_id, pool)| PoolBindingVector {
+            chain_id: chain_id.to_string(),
+            pool: hex0x(pool),
+            binding: hex0x(&pool_binding(*chain_id, pool)),
+        })
+        .collect();
+    let binding = pool_binding(9745, &[0x11; 20]);
+
+    // The transfer vector's outputs, encrypted to two viewing keys
+    let encryptions = [0x61u8, 0x71].map(|seed| OutputEncryption {
+        viewing_pubkey: *crypto_box::SecretKey::from([seed; 32]).public_key().as_bytes(),
+        ephemeral_secret: [seed + 1; 32],
+        nonce: [seed + 2; 24],
+    });
+    let payloads = [encryptions[0].encrypt(&notes[2].0), encryptions[1].encrypt(&notes[3].0)];
+    let note_encryptions: Vec<NoteEncryptionVector> = encryptions
+        .iter()
+        .zip([&notes[2].0, &notes[3].0])
+        .zip(&payloads)
+        .map(|((e, note), payload)| NoteEncryptionVector {
+            amount: note.amount.to_string(),
+            pubkey: hex0x(&note.pubkey),
+            blinding: hex0x(&note.blinding),
+            viewing_pubkey: hex0x(&e.viewing_pubkey),
+            ephemeral_secret: hex0x(&e.ephemeral_secret),
+            nonce: hex0x(&e.nonce),
+            plaintext: hex0x(&note_plaintext(note)),
+            payload: hex0x(payload),
+        })
+        .collect();
+
+    let root = tree.get_root();
+    let nullifier = |i: usize| compute_nullifier(&notes[i].0.commitment(), &notes[i].1);
+    let withdraw_pvs = [
+        WithdrawPublicValues {
+            root,
+            nullifier: nullifier(0),
+            recipient: [0x42; 20],
+            amount: 700_000,
+            change_commitment: [0u8; 32],
+            reclaim_deadline: 0,
+            relayer: [0u8; 20],
+            fee: 0,
+            association_root: [0u8; 32],
+            pool_binding: binding,
+            deadline: 0,
+            call_binding: [0u8; 32],
+            tree_levels: TREE_VECTOR_LEVELS as u64,
+        },
+        WithdrawPublicValues {
+            root,
+            nullifier: nullifier(3),
+            recipient: [0xEE; 20],
+            amount: u64::MAX - 1,
+            change_commitment: Note { amount: 1, pubkey: notes[3].0.pubkey, blinding: [0x05; 32] }
+                .commitment(),
+            reclaim_deadline: 1_700_000_000,
+            relayer: [0x52; 20],
+            fee: 20_000,
+            association_root: [0x5A; 32],
+            pool_binding: binding,
+            deadline: 1_700_003_600,
+            call_binding: call_binding(&[0xCA; 20], &keccak256(b"bridge(uint256)")).unwrap(),
+            tree_levels: TREE_VECTOR_LEVELS as u64,
+        },
+    ];
+    let withdraw = withdraw_pvs
+        .iter()
+        .map(|pv| WithdrawPublicValuesVector {
+            root: hex0x(&pv.root),
+            nullifier: hex0x(&pv.nullifier),
+            recipient: hex0x(&pv.recipient),
+            amount: pv.amount.to_string(),
+            change_commitment: hex0x(&pv.change_commitment),
+            reclaim_deadline: pv.reclaim_deadline.to_string(),
+            relayer: hex0x(&pv.relayer),
+            fee: pv.fee.to_string(),
+            association_root: hex0x(&pv.association_root),
+            pool_binding: hex0x(&pv.pool_binding),
+            deadline: pv.deadline.to_string(),
+            call_binding: hex0x(&pv.call_binding),
+            tree_levels: pv.tree_levels.to_string(),
+            encoded: hex0x(&pv.encode()),
+        })
+        .collect();
+
+    let transfer_pv = TransferPublicValues {
+        root,
+        nullifiers: [nullifier(0), nullifier(1)],
+        out_commitments: [notes[2].0.commitment(), notes[3].0.commitment()],
+        reclaim_deadline: 0,
+        association_root: [0u8; 32],
+        pool_binding: binding,
+        ciphertexts_hash: ciphertexts_hash([&payloads[0], &payloads[1]]),
+        tree_levels: TREE_VECTOR_LEVELS as u64,
+    };
+    let transfer = alloc::vec![TransferPublicValuesVector {
+        root: hex0x(&transfer_pv.root),
+        nullifiers: transfer_pv.nullifiers.map(|n| hex0x(&n)),
+        out_commitments: transfer_pv.out_commitments.map(|c| hex0x(&c)),
+        reclaim_deadline: transfer_pv.reclaim_deadline.to_string(),
+        association_root: hex0x(&transfer_pv.association_root),
+        pool_binding: hex0x(&transfer_pv.pool_binding),
+        ciphertexts_hash: hex0x(&transfer_pv.ciphertexts_hash),
+        tree_levels: transfer_pv.tree_levels.to_string(),
+        encoded: hex0x(&transfer_pv.encode()),
+    }];
+
+    // Withdrawals signed by the first note's owner, the transfer by the second's
+    let signed = withdraw_pvs
+        .iter()
+        .map(|pv| ("withdraw", CircuitKind::Withdraw, pv.encode().to_vec(), notes[0].1))
+        .chain([("transfer", CircuitKind::Transfer, transfer_pv.encode().to_vec(), notes[1].1)]);
+    let spend_signatures = signed
+        .map(|(circuit, kind, encoded, sk)| {
+            let message = spend_auth_message(kind, &encoded);
+            let signature = SpendSignature::sign(&sk, &message);
+            SpendSignatureVector {
+                circuit: circuit.to_string(),
+                public_values: hex0x(&encoded),
+                spending_key: hex0x(&sk),
+                message: hex0x(&message),
+                auth_key: hex0x(&signature.auth_key),
+                signature: format!("{}{}", hex0x(&signature.r), hex::encode(signature.s)),
+            }
+        })
+        .collect();
+
+    TestVectors {
+        version: TEST_VECTORS_VERSION,
+        keccak,
+        hash_pair,
+        keys,
+        notes: note_vectors,
+        cancellable,
+        multisig,
+        zeros,
+        trees,
+        pool_bindings,
+        note_encryptions,
+        public_values: PublicValuesVectors { withdraw, transfer },
+        spend_signatures,
+    }
+}
+
+/// The vectors as pretty-printed JSON (trailing newline included).
+pub fn to_json() -> String {
+    let mut json = serde_json::to_string_pretty(&generate()).expect("test vectors serialize");
+    json.push('\n');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_merkle_proof, MerkleProofStep};
+
+    fn unhex32(s: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        hex::decode_to_slice(s.trim_start_matches("0x"), &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_vectors_are_deterministic() {
+        assert_eq!(to_json(), to_json());
+        let parsed: TestVectors = serde_json::from_str(&to_json()).unwrap();
+        assert_eq!(parsed, generate());
+    }
+
+    #[test]
+    fn test_spend_signature_vectors_verify() {
+        let v = generate();
+        assert_eq!(v.spend_signatures.len(), v.public_values.withdraw.len() + 1);
+        for vector in &v.spend_signatures {
+            let signature = hex::decode(vector.signature.trim_start_matches("0x")).unwrap();
+            let signature = SpendSignature {
+                auth_key: unhex32(&vector.auth_key),
+                r: signature[..32].try_into().unwrap(),
+                s: signature[32..].try_into().unwrap(),
+            };
+            assert!(signature.verify(&unhex32(&vector.message)));
+            let auth_key = spend_auth_pubkey(&derive_spend_auth_key(&unhex32(&vector.spending_key)));
+            assert_eq!(signature.auth_key, auth_key);
+        }
+    }
+
+    #[test]
+    fn test_tree_vectors_verify() {
+        let v = generate();
+        let tree = &v.trees[0];
+        let root = unhex32(tree.roots.last().unwrap());
+        for p in &tree.proofs {
+            let steps: Vec<MerkleProofStep> = p
+                .steps
+                .iter()
+                .map(|s| MerkleProofStep { is_left: s.is_left, sibling: unhex32(&s.sibling) })
+                .collect();
+            let leaf = unhex32(&tree.leaves[p.leaf_index as usize]);
+            assert!(verify_merkle_proof(leaf, &steps, root));
+            let packed = MerkleProof::from_packed_bytes(&hex::decode(p.packed.trim_start_matches("0x")).unwrap()).unwrap();
+            assert_eq!(packed.path_bits, p.leaf_index);
+            assert_eq!(packed.steps(), steps);
+        }
+    }
+}
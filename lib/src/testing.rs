@@ -0,0 +1,309 @@
+//! Input generators for fuzzers and property tests (feature `testing`).
+//!
+//! `Note` and `MerkleProofStep` derive `arbitrary::Arbitrary` directly. The
+//! circuit input types get hand-written impls instead: a derived
+//! `TransferPrivateInputs` would almost never pass the guest's ownership,
+//! inclusion and conservation checks, so these build *well-formed* inputs:
+//!
+//!   - every input note is owned by its spending key
+//!   - every Merkle proof verifies against `root` (notes sit among filler leaves)
+//!   - amounts balance and never overflow
+//!   - the spending keys sign the spend, unless the circuit would reject it
+//!     for another reason (an output above `MAX_AMOUNT`, say)
+//!
+//! Fuzz targets that want malformed inputs can mutate the result.
+//!
+//! The `strategies` module exposes the same generators as proptest strategies.
+
+use alloc::vec::Vec;
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::{
+    derive_pubkey, IncrementalMerkleTree, MerkleProofStep, Note, SpenderKey, TransferPrivateInputs,
+    WithdrawPrivateInputs,
+};
+
+/// Deepest tree the `Arbitrary` impls generate (keeps fuzz inputs cheap).
+pub const ARBITRARY_MAX_LEVELS: usize = 8;
+
+/// Most filler leaves inserted around the generated notes.
+pub const MAX_FILLER_LEAVES: usize = 16;
+
+/// Insert `filler[..split]`, then `commitments`, then the rest of `filler`.
+/// Returns the root and a proof per commitment.
+fn place_in_tree(
+    levels: usize,
+    filler: &[[u8; 32]],
+    split: usize,
+    commitments: &[[u8; 32]],
+) -> ([u8; 32], Vec<Vec<MerkleProofStep>>) {
+    let capacity = (1usize << levels).saturating_sub(commitments.len());
+    let filler = &filler[..filler.len().min(capacity)];
+    let split = split.min(filler.len());
+
+    let mut tree = IncrementalMerkleTree::new(levels);
+    for leaf in &filler[..split] {
+        tree.insert(*leaf);
+    }
+    let indices: Vec<u32> = commitments.iter().map(|c| tree.insert(*c)).collect();
+    for leaf in &filler[split..] {
+        tree.insert(*leaf);
+    }
+    let proofs = indices.iter().map(|&i| tree.get_proof(i)).collect();
+    (tree.get_root(), proofs)
+}
+
+/// Well-formed transfer inputs.
+///
+/// Input amounts are halved so their sum fits in a u64; `pay` is reduced
+/// modulo the total, and the remainder becomes the change output.
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_inputs(
+    levels: usize,
+    spending_keys: [[u8; 32]; 2],
+    amounts: [u64; 2],
+    blindings: [[u8; 32]; 4],
+    recipient_pubkey: [u8; 32],
+    pay: u64,
+    filler: &[[u8; 32]],
+    split: usize,
+) -> TransferPrivateInputs {
+    let amounts = amounts.map(|a| a / 2);
+    let mut input_blindings = [blindings[0], blindings[1]];
+    if spending_keys[0] == spending_keys[1] && amounts[0] == amounts[1] && input_blindings[0] == input_blindings[1] {
+        // Two identical notes would share a nullifier
+        input_blindings[1][0] ^= 1;
+    }
+    let input_notes = [0, 1].map(|i| Note {
+        amount: amounts[i],
+        pubkey: derive_pubkey(&spending_keys[i]),
+        blinding: input_blindings[i],
+    });
+
+    let total = amounts[0] + amounts[1];
+    let pay = pay % (total + 1);
+    let output_notes = [
+        Note { amount: pay, pubkey: recipient_pubkey, blinding: blindings[2] },
+        Note { amount: total - pay, pubkey: input_notes[0].pubkey, blinding: blindings[3] },
+    ];
+
+    let commitments = [input_notes[0].commitment(), input_notes[1].commitment()];
+    let (root, mut proofs) = place_in_tree(levels.max(1), filler, split, &commitments);
+    let proof1 = proofs.pop().unwrap();
+    let proof0 = proofs.pop().unwrap();
+
+    let mut inputs = TransferPrivateInputs {
+        input_notes,
+        spender_keys: spending_keys.map(|sk| SpenderKey::derive(&sk)),
+        merkle_proofs: [proof0, proof1],
+        output_notes,
+        root,
+        locks: [None, None],
+        association: None,
+        pool_binding: [0u8; 32],
+        output_encryption: None,
+        multisig: [None, None],
+        spend_signatures: Vec::new(),
+    };
+    for key in &spending_keys {
+        if inputs.sign(key).is_err() {
+            break;
+        }
+    }
+    inputs
+}
+
+/// Well-formed withdraw inputs. `withdraw_amount` is reduced modulo
+/// `amount + 1`; a change note is created unless the withdrawal is full.
+#[allow(clippy::too_many_arguments)]
+pub fn withdraw_inputs(
+    levels: usize,
+    spending_key: [u8; 32],
+    amount: u64,
+    blinding: [u8; 32],
+    change_blinding: [u8; 32],
+    withdraw_amount: u64,
+    recipient: [u8; 20],
+    filler: &[[u8; 32]],
+    split: usize,
+) -> WithdrawPrivateInputs {
+    let amount = amount.min(u64::MAX - 1);
+    let input_note = Note { amount, pubkey: derive_pubkey(&spending_key), blinding };
+    let withdraw_amount = withdraw_amount % (amount + 1);
+    let change_note = (withdraw_amount < amount).then(|| Note {
+        amount: amount - withdraw_amount,
+        pubkey: input_note.pubkey,
+        blinding: change_blinding,
+    });
+
+    let (root, mut proofs) = place_in_tree(levels, filler, split, &[input_note.commitment()]);
+
+    let mut inputs = WithdrawPrivateInputs {
+        input_note,
+        spender_key: SpenderKey::derive(&spending_key),
+        merkle_proof: proofs.pop().unwrap(),
+        root,
+        recipient,
+        withdraw_amount,
+        change_note,
+        lock: None,
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: [0u8; 32],
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: None,
+        spend_signatures: Vec::new(),
+    };
+    let _ = inputs.sign(&spending_key);
+    inputs
+}
+
+fn arbitrary_filler(u: &mut Unstructured<'_>) -> arbitrary::Result<Vec<[u8; 32]>> {
+    let n = u.int_in_range(0..=MAX_FILLER_LEAVES)?;
+    (0..n).map(|_| u.arbitrary()).collect()
+}
+
+impl<'a> Arbitrary<'a> for TransferPrivateInputs {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let levels = u.int_in_range(1..=ARBITRARY_MAX_LEVELS)?;
+        let spending_keys = u.arbitrary()?;
+        let amounts = u.arbitrary()?;
+        let blindings = u.arbitrary()?;
+        let recipient_pubkey = u.arbitrary()?;
+        let pay = u.arbitrary()?;
+        let filler = arbitrary_filler(u)?;
+        let split = u.int_in_range(0..=filler.len())?;
+        Ok(transfer_inputs(levels, spending_keys, amounts, blindings, recipient_pubkey, pay, &filler, split))
+    }
+}
+
+impl<'a> Arbitrary<'a> for WithdrawPrivateInputs {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let levels = u.int_in_range(1..=ARBITRARY_MAX_LEVELS)?;
+        let spending_key = u.arbitrary()?;
+        let amount = u.arbitrary()?;
+        let blinding = u.arbitrary()?;
+        let change_blinding = u.arbitrary()?;
+        let withdraw_amount = u.arbitrary()?;
+        let recipient = u.arbitrary()?;
+        let filler = arbitrary_filler(u)?;
+        let split = u.int_in_range(0..=filler.len())?;
+        Ok(withdraw_inputs(
+            levels,
+            spending_key,
+            amount,
+            blinding,
+            change_blinding,
+            withdraw_amount,
+            recipient,
+            &filler,
+            split,
+        ))
+    }
+}
+
+/// Proptest strategies for the same types.
+pub mod strategies {
+    use super::*;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    pub fn note() -> impl Strategy<Value = Note> {
+        (any::<u64>(), any::<[u8; 32]>(), any::<[u8; 32]>())
+            .prop_map(|(amount, pubkey, blinding)| Note { amount, pubkey, blinding })
+    }
+
+    pub fn merkle_proof_step() -> impl Strategy<Value = MerkleProofStep> {
+        (any::<bool>(), any::<[u8; 32]>()).prop_map(|(is_left, sibling)| MerkleProofStep { is_left, sibling })
+    }
+
+    fn filler() -> impl Strategy<Value = (Vec<[u8; 32]>, usize)> {
+        vec(any::<[u8; 32]>(), 0..=MAX_FILLER_LEAVES)
+            .prop_flat_map(|f| {
+                let n = f.len();
+                (Just(f), 0..=n)
+            })
+    }
+
+    /// Well-formed transfer inputs in a tree of `levels` (at least 1).
+    pub fn transfer_inputs(levels: usize) -> impl Strategy<Value = TransferPrivateInputs> {
+        (
+            any::<[[u8; 32]; 2]>(),
+            any::<[u64; 2]>(),
+            any::<[[u8; 32]; 4]>(),
+            any::<[u8; 32]>(),
+            any::<u64>(),
+            filler(),
+        )
+            .prop_map(move |(keys, amounts, blindings, recipient, pay, (filler, split))| {
+                super::transfer_inputs(levels, keys, amounts, blindings, recipient, pay, &filler, split)
+            })
+    }
+
+    /// Well-formed withdraw inputs in a tree of `levels`.
+    pub fn withdraw_inputs(levels: usize) -> impl Strategy<Value = WithdrawPrivateInputs> {
+        (
+            any::<[u8; 32]>(),
+            any::<u64>(),
+            any::<[[u8; 32]; 2]>(),
+            any::<u64>(),
+            any::<[u8; 20]>(),
+            filler(),
+        )
+            .prop_map(move |(key, amount, [blinding, change_blinding], withdraw, recipient, (filler, split))| {
+                super::withdraw_inputs(
+                    levels,
+                    key,
+                    amount,
+                    blinding,
+                    change_blinding,
+                    withdraw,
+                    recipient,
+                    &filler,
+                    split,
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strategies;
+    use crate::{check_spend_signatures, nullifier_from_key, verify_merkle_proof};
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn test_transfer_inputs_well_formed(inputs in strategies::transfer_inputs(6)) {
+            for i in 0..2 {
+                let note = &inputs.input_notes[i];
+                prop_assert!(inputs.spender_keys[i].owns(&note.pubkey));
+                prop_assert!(verify_merkle_proof(note.commitment(), &inputs.merkle_proofs[i], inputs.root));
+            }
+            let nullifiers = [0, 1].map(|i| {
+                nullifier_from_key(&inputs.input_notes[i].commitment(), inputs.spender_keys[i].nullifier_key())
+            });
+            prop_assert_ne!(nullifiers[0], nullifiers[1]);
+            let sum_in: u128 = inputs.input_notes.iter().map(|n| n.amount as u128).sum();
+            let sum_out: u128 = inputs.output_notes.iter().map(|n| n.amount as u128).sum();
+            prop_assert_eq!(sum_in, sum_out);
+            if let Ok(message) = inputs.spend_message() {
+                prop_assert!(check_spend_signatures(inputs.signers(), &inputs.spend_signatures, &message).is_ok());
+            }
+        }
+
+        #[test]
+        fn test_withdraw_inputs_well_formed(inputs in strategies::withdraw_inputs(6)) {
+            prop_assert!(inputs.spender_key.owns(&inputs.input_note.pubkey));
+            prop_assert!(verify_merkle_proof(inputs.input_note.commitment(), &inputs.merkle_proof, inputs.root));
+            let change = inputs.change_note.as_ref().map_or(0, |n| n.amount);
+            prop_assert_eq!(inputs.withdraw_amount.checked_add(change), Some(inputs.input_note.amount));
+            if let Ok(message) = inputs.spend_message() {
+                prop_assert!(check_spend_signatures(inputs.signers(), &inputs.spend_signatures, &message).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Disk-backed `IncrementalMerkleTree`.
+//!
+//! A store is a directory with two files:
+//!
+//!   leaves  — append-only, one 32-byte leaf per record, in insertion order
+//!   commit  — fixed-size record naming how many leaves are durable:
+//!             magic(8) | version u32 | levels u32 | leaf_count u64 |
+//!             synced_block u64 | root(32) | keccak256 of the preceding bytes(32)
+//!
+//! `append` only writes to `leaves`. `flush` fsyncs `leaves`, then writes
+//! the new commit record to a temp file and renames it over `commit`.
+//! Whatever happens in between, `open` sees either the old or the new commit
+//! record: leaves past `leaf_count` (unflushed or torn appends) are truncated
+//! away, and the rebuilt root must equal the committed one.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{keccak256, IncrementalMerkleTree};
+
+/// Store format version.
+pub const TREE_STORE_VERSION: u32 = 1;
+
+const MAGIC: [u8; 8] = *b"SPTREE\0\0";
+const COMMIT_LEN: usize = 8 + 4 + 4 + 8 + 8 + 32 + 32;
+const LEAVES_FILE: &str = "leaves";
+const COMMIT_FILE: &str = "commit";
+const COMMIT_TMP_FILE: &str = "commit.tmp";
+
+#[derive(Debug)]
+pub enum TreeStoreError {
+    /// The store was created for a tree of a different depth
+    LevelsMismatch { stored: u32, requested: usize },
+    UnsupportedVersion(u32),
+    /// The commit record or leaf file is damaged
+    Corrupt(&'static str),
+    /// Every leaf slot of the tree is taken
+    Full,
+    Io(std::io::Error),
+}
+
+impl fmt::Display for TreeStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeStoreError::LevelsMismatch { stored, requested } => {
+                write!(f, "tree store holds a {stored}-level tree, not {requested}")
+            }
+            TreeStoreError::UnsupportedVersion(v) => write!(f, "unsupported tree store version {v}"),
+            TreeStoreError::Corrupt(what) => write!(f, "corrupt tree store: {what}"),
+            TreeStoreError::Full => write!(f, "tree store is full"),
+            TreeStoreError::Io(e) => write!(f, "tree store i/o error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TreeStoreError {}
+
+impl From<std::io::Error> for TreeStoreError {
+    fn from(e: std::io::Error) -> Self {
+        TreeStoreError::Io(e)
+    }
+}
+
+/// An `IncrementalMerkleTree` mirrored to disk.
+#[derive(Debug)]
+pub struct PersistentMerkleTree {
+    dir: PathBuf,
+    leaves_file: File,
+    tree: IncrementalMerkleTree,
+    /// Leaves covered by the on-disk commit record
+    durable_leaves: u32,
+    synced_block: Option<u64>,
+    durable_block: Option<u64>,
+}
+
+impl PersistentMerkleTree {
+    /// Open the store at `dir`, creating an empty one if it does not exist.
+    pub fn open(dir: impl AsRef<Path>, levels: usize) -> Result<Self, TreeStoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let commit = match fs::read(dir.join(COMMIT_FILE)) {
+            Ok(bytes) => Some(Commit::decode(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        if let Some(ref c) = commit {
+            if c.levels as usize != levels {
+                return Err(TreeStoreError::LevelsMismatch { stored: c.levels, requested: levels });
+            }
+        }
+        let leaf_count = commit.as_ref().map_or(0, |c| c.leaf_count);
+
+        let mut leaves_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(LEAVES_FILE))?;
+        let durable_len = leaf_count as u64 * 32;
+        if leaves_file.metadata()?.len() < durable_len {
+            return Err(TreeStoreError::Corrupt("leaf file is shorter than the commit record"));
+        }
+        // Drop appends that never made it into a commit record
+        leaves_file.set_len(durable_len)?;
+
+        // Stream the leaves into the rebuild rather than reading the file whole
+        leaves_file.seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&leaves_file);
+        let mut read_error = None;
+        let leaves = (0..leaf_count).map_while(|_| {
+            let mut leaf = [0u8; 32];
+            reader.read_exact(&mut leaf).map_err(|e| read_error = Some(e)).ok().map(|_| leaf)
+        });
+        let tree = IncrementalMerkleTree::from_leaves(levels, leaves).map_err(|_| TreeStoreError::Full)?;
+        if let Some(e) = read_error {
+            return Err(e.into());
+        }
+        if let Some(ref c) = commit {
+            if tree.get_root() != c.root {
+                return Err(TreeStoreError::Corrupt("leaves do not hash to the committed root"));
+            }
+        }
+        leaves_file.seek(SeekFrom::End(0))?;
+
+        let synced_block = commit.and_then(|c| c.synced_block);
+        Ok(PersistentMerkleTree {
+            dir,
+            leaves_file,
+            tree,
+            durable_leaves: leaf_count,
+            synced_block,
+            durable_block: synced_block,
+        })
+    }
+
+    /// The in-memory tree, including leaves not yet flushed.
+    pub fn tree(&self) -> &IncrementalMerkleTree {
+        &self.tree
+    }
+
+    /// Directory the store lives in.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Block the leaves were synced up to (as of the last `set_synced_block`).
+    pub fn synced_block(&self) -> Option<u64> {
+        self.synced_block
+    }
+
+    /// Record the block the current leaves were synced up to; persisted by `flush`.
+    pub fn set_synced_block(&mut self, block: u64) {
+        self.synced_block = Some(block);
+    }
+
+    /// Insert a leaf. It is durable only after the next `flush`.
+    pub fn append(&mut self, leaf: [u8; 32]) -> Result<u32, TreeStoreError> {
+        if self.tree.is_full() {
+            return Err(TreeStoreError::Full);
+        }
+        self.leaves_file.write_all(&leaf)?;
+        Ok(self.tree.insert(leaf))
+    }
+
+    /// Make all appended leaves and the synced block durable.
+    pub fn flush(&mut self) -> Result<(), TreeStoreError> {
+        let leaf_count = self.tree.next_index;
+        if leaf_count == self.durable_leaves && self.synced_block == self.durable_block {
+            return Ok(());
+        }
+        self.leaves_file.sync_data()?;
+
+        let commit = Commit {
+            levels: self.tree.levels as u32,
+            leaf_count,
+            synced_block: self.synced_block,
+            root: self.tree.get_root(),
+        };
+        let tmp = self.dir.join(COMMIT_TMP_FILE);
+        let mut file = File::create(&tmp)?;
+        file.write_all(&commit.encode())?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join(COMMIT_FILE))?;
+        // Persist the rename itself (not supported for directories on every platform)
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+
+        self.durable_leaves = leaf_count;
+        self.durable_block = self.synced_block;
+        Ok(())
+    }
+}
+
+struct Commit {
+    levels: u32,
+    leaf_count: u32,
+    synced_block: Option<u64>,
+    root: [u8; 32],
+}
+
+impl Commit {
+    fn encode(&self) -> [u8; COMMIT_LEN] {
+        let mut out = [0u8; COMMIT_LEN];
+        out[0..8].copy_from_slice(&MAGIC);
+        out[8..12].copy_from_slice(&TREE_STORE_VERSION.to_be_bytes());
+        out[12..16].copy_from_slice(&self.levels.to_be_bytes());
+        out[16..24].copy_from_slice(&(self.leaf_count as u64).to_be_bytes());
+        // u64::MAX = never synced
+        out[24..32].copy_from_slice(&self.synced_block.unwrap_or(u64::MAX).to_be_bytes());
+        out[32..64].copy_from_slice(&self.root);
+        let checksum = keccak256(&out[..64]);
+        out[64..96].copy_from_slice(&checksum);
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, TreeStoreError> {
+        if bytes.len() != COMMIT_LEN || bytes[0..8] != MAGIC {
+            return Err(TreeStoreError::Corrupt("bad commit record"));
+        }
+        if keccak256(&bytes[..64])[..] != bytes[64..96] {
+            return Err(TreeStoreError::Corrupt("commit record checksum mismatch"));
+        }
+        let version = u32::from_be_bytes(bytes[8..12].try_into().unwrap());
+        if version != TREE_STORE_VERSION {
+            return Err(TreeStoreError::UnsupportedVersion(version));
+        }
+        let leaf_count = u64::from_be_bytes(bytes[16..24].try_into().unwrap());
+        let synced_block = u64::from_be_bytes(bytes[24..32].try_into().unwrap());
+        Ok(Commit {
+            levels: u32::from_be_bytes(bytes[12..16].try_into().unwrap()),
+            leaf_count: u32::try_from(leaf_count)
+                .map_err(|_| TreeStoreError::Corrupt("leaf count out of range"))?,
+            synced_block: (synced_block != u64::MAX).then_some(synced_block),
+            root: bytes[32..64].try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(std::format!("tree-store-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn leaf(i: u32) -> [u8; 32] {
+        keccak256(&i.to_be_bytes())
+    }
+
+    #[test]
+    fn test_reopen_restores_flushed_tree() {
+        let dir = temp_dir("reopen");
+        let mut store = PersistentMerkleTree::open(&dir, 8).unwrap();
+        assert_eq!(store.synced_block(), None);
+        for i in 0..5 {
+            store.append(leaf(i)).unwrap();
+        }
+        store.set_synced_block(42);
+        store.flush().unwrap();
+        let root = store.tree().get_root();
+        drop(store);
+
+        let mut store = PersistentMerkleTree::open(&dir, 8).unwrap();
+        assert_eq!(store.tree().leaves.len(), 5);
+        assert_eq!(store.tree().get_root(), root);
+        assert_eq!(store.synced_block(), Some(42));
+        assert_eq!(store.append(leaf(5)).unwrap(), 5);
+        assert!(matches!(
+            PersistentMerkleTree::open(&dir, 9),
+            Err(TreeStoreError::LevelsMismatch { stored: 8, requested: 9 })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unflushed_and_torn_appends_are_dropped() {
+        let dir = temp_dir("torn");
+        let mut store = PersistentMerkleTree::open(&dir, 8).unwrap();
+        store.append(leaf(0)).unwrap();
+        store.set_synced_block(7);
+        store.flush().unwrap();
+        let root = store.tree().get_root();
+        // Crash after appending without flushing, mid-way through a record
+        store.append(leaf(1)).unwrap();
+        drop(store);
+        let mut file = OpenOptions::new().append(true).open(dir.join(LEAVES_FILE)).unwrap();
+        file.write_all(&[0xAB; 13]).unwrap();
+        drop(file);
+
+        let store = PersistentMerkleTree::open(&dir, 8).unwrap();
+        assert_eq!(store.tree().leaves.len(), 1);
+        assert_eq!(store.tree().get_root(), root);
+        assert_eq!(store.synced_block(), Some(7));
+        assert_eq!(fs::metadata(dir.join(LEAVES_FILE)).unwrap().len(), 32);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let dir = temp_dir("corrupt");
+        let mut store = PersistentMerkleTree::open(&dir, 8).unwrap();
+        store.append(leaf(0)).unwrap();
+        store.append(leaf(1)).unwrap();
+        store.flush().unwrap();
+        drop(store);
+
+        // Flipped leaf byte: root no longer matches
+        let mut leaves = fs::read(dir.join(LEAVES_FILE)).unwrap();
+        leaves[40] ^= 1;
+        fs::write(dir.join(LEAVES_FILE), &leaves).unwrap();
+        assert!(matches!(PersistentMerkleTree::open(&dir, 8), Err(TreeStoreError::Corrupt(_))));
+
+        // Truncated leaf file: fewer leaves than committed
+        fs::write(dir.join(LEAVES_FILE), &leaves[..32]).unwrap();
+        assert!(matches!(PersistentMerkleTree::open(&dir, 8), Err(TreeStoreError::Corrupt(_))));
+
+        // Damaged commit record
+        let mut commit = fs::read(dir.join(COMMIT_FILE)).unwrap();
+        commit[20] ^= 1;
+        fs::write(dir.join(COMMIT_FILE), &commit).unwrap();
+        assert!(matches!(PersistentMerkleTree::open(&dir, 8), Err(TreeStoreError::Corrupt(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! Incremental Merkle witnesses.
+//!
+//! A `Witness` follows one leaf's authentication path as the tree grows.
+//! Besides the path it keeps the tree's frontier (`filled_subtrees` and
+//! `next_index`, the same state MerkleTree.sol keeps), so each appended leaf
+//! is hashed up once, exactly as `_insert` does, and the path sibling it
+//! lands in is replaced along the way: O(levels) per leaf, without the
+//! leaves themselves.
+//!
+//! A wallet can take a witness when its note is inserted, feed it every
+//! later commitment while syncing, and prove the note against the current
+//! root without rebuilding the tree.
+
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{hash_pair, IncrementalMerkleTree, MerkleProofStep};
+
+/// Authentication path of one leaf, kept current as leaves are appended.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Witness {
+    leaf: [u8; 32],
+    leaf_index: u32,
+    /// Sibling at each height, zero values where nothing is inserted yet
+    path: Vec<[u8; 32]>,
+    /// The tree's filled subtrees, as in `IncrementalMerkleTree`
+    frontier: Vec<[u8; 32]>,
+    next_index: u32,
+    zeros: Vec<[u8; 32]>,
+}
+
+impl Witness {
+    /// Witness for the leaf at `leaf_index` in `tree`'s current state.
+    pub fn from_tree(tree: &IncrementalMerkleTree, leaf_index: u32) -> Result<Self, &'static str> {
+        let leaf = *tree.leaves.get(leaf_index as usize).ok_or("leaf index out of range")?;
+        Ok(Witness {
+            leaf,
+            leaf_index,
+            path: tree.get_proof(leaf_index).into_iter().map(|step| step.sibling).collect(),
+            frontier: tree.filled_subtrees.clone(),
+            next_index: tree.next_index,
+            zeros: tree.zeros.clone(),
+        })
+    }
+
+    /// Append `leaf` to the witnessed tree and return its index.
+    /// Matches MerkleTree.sol _insert().
+    pub fn append(&mut self, leaf: [u8; 32]) -> Result<u32, &'static str> {
+        let levels = self.path.len();
+        let index = self.next_index;
+        if index as u64 >= 1u64 << levels {
+            return Err("Merkle tree is full");
+        }
+
+        let leaf_index = self.leaf_index;
+        let path = &mut self.path;
+        hash_up(&mut self.frontier, &self.zeros, index, leaf, |height, node_index, node, _| {
+            // The new leaf's ancestor is our sibling here until the subtree fills up
+            if node_index == (leaf_index >> height) ^ 1 {
+                path[height] = node;
+            }
+        });
+
+        self.next_index = index + 1;
+        Ok(index)
+    }
+
+    /// Merkle proof for the witnessed leaf against `root()`.
+    pub fn proof(&self) -> Vec<MerkleProofStep> {
+        proof_steps(self.leaf_index, &self.path)
+    }
+
+    /// Root of the witnessed tree after the leaves appended so far.
+    pub fn root(&self) -> [u8; 32] {
+        self.proof().iter().fold(self.leaf, |node, step| {
+            if step.is_left {
+                hash_pair(&node, &step.sibling)
+            } else {
+                hash_pair(&step.sibling, &node)
+            }
+        })
+    }
+
+    pub fn leaf(&self) -> [u8; 32] {
+        self.leaf
+    }
+
+    pub fn leaf_index(&self) -> u32 {
+        self.leaf_index
+    }
+
+    /// Number of leaves in the witnessed tree.
+    pub fn next_index(&self) -> u32 {
+        self.next_index
+    }
+
+    /// Depth of the witnessed tree.
+    pub fn levels(&self) -> usize {
+        self.path.len()
+    }
+}
+
+/// Hash `leaf`, appended at `index`, up to the root as MerkleTree.sol
+/// _insert() does, updating `frontier` (the filled subtrees) on the way.
+/// `visit(height, node_index, node, sibling)` sees the new leaf's ancestor at
+/// each height and the sibling it is hashed with. Returns the new root.
+pub(crate) fn hash_up(
+    frontier: &mut [[u8; 32]],
+    zeros: &[[u8; 32]],
+    index: u32,
+    leaf: [u8; 32],
+    mut visit: impl FnMut(usize, u32, [u8; 32], [u8; 32]),
+) -> [u8; 32] {
+    let mut node_index = index;
+    let mut node = leaf;
+    for height in 0..frontier.len() {
+        node = if node_index.is_multiple_of(2) {
+            visit(height, node_index, node, zeros[height]);
+            frontier[height] = node;
+            hash_pair(&node, &zeros[height])
+        } else {
+            visit(height, node_index, node, frontier[height]);
+            hash_pair(&frontier[height], &node)
+        };
+        node_index /= 2;
+    }
+    node
+}
+
+/// Merkle proof steps for the leaf at `leaf_index` with siblings `path`.
+pub(crate) fn proof_steps(leaf_index: u32, path: &[[u8; 32]]) -> Vec<MerkleProofStep> {
+    path.iter()
+        .enumerate()
+        .map(|(height, sibling)| MerkleProofStep { is_left: (leaf_index >> height).is_multiple_of(2), sibling: *sibling })
+        .collect()
+}
+
+impl IncrementalMerkleTree {
+    /// Shorthand for `Witness::from_tree(self, leaf_index)`.
+    pub fn witness(&self, leaf_index: u32) -> Result<Witness, &'static str> {
+        Witness::from_tree(self, leaf_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{keccak256, verify_merkle_proof};
+
+    fn leaf(i: u32) -> [u8; 32] {
+        keccak256(&i.to_be_bytes())
+    }
+
+    #[test]
+    fn test_witness_tracks_tree() {
+        let mut tree = IncrementalMerkleTree::new(5);
+        for i in 0..6 {
+            tree.insert(leaf(i));
+        }
+        // First, middle and most recent leaf
+        let mut witnesses: Vec<Witness> = [0, 3, 5].iter().map(|&i| tree.witness(i).unwrap()).collect();
+        for i in 6..32 {
+            let index = tree.insert(leaf(i));
+            for w in &mut witnesses {
+                assert_eq!(w.append(leaf(i)), Ok(index));
+                assert_eq!(w.proof(), tree.get_proof(w.leaf_index()), "leaf {} after {i}", w.leaf_index());
+                assert_eq!(w.root(), tree.get_root());
+                assert!(verify_merkle_proof(w.leaf(), &w.proof(), tree.get_root()));
+            }
+        }
+        for w in &mut witnesses {
+            assert_eq!(w.append(leaf(32)), Err("Merkle tree is full"));
+        }
+    }
+
+    #[test]
+    fn test_witness_out_of_range() {
+        let mut tree = IncrementalMerkleTree::new(3);
+        assert!(tree.witness(0).is_err());
+        tree.insert(leaf(0));
+        let w = tree.witness(0).unwrap();
+        assert_eq!(w.root(), tree.get_root());
+        assert_eq!(w.levels(), 3);
+        assert!(tree.witness(1).is_err());
+    }
+}
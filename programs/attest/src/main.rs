@@ -0,0 +1,59 @@
+//! SP1 Attest Circuit: proof of funds without spending.
+//!
+//! Proves the prover holds at least a threshold in the shielded pool:
+//! - 1 to 8 notes, each owned by its spending key
+//! - Every note exists in the Merkle tree
+//! - No note's nullifier is in the snapshot's nullifier set (unspent)
+//! - No note is counted twice
+//! - The notes' amounts sum to at least the threshold
+//!
+//! Nothing is nullified and no nullifier is revealed; only a counterparty's
+//! audit nonce is committed with the statement. See
+//! `shielded_pool_lib::attest`.
+//!
+//! Public values committed (192 bytes = 6 × bytes32):
+//!   [root, nullifierRoot, threshold (uint256 BE), nonce, treeLevels (uint256 BE),
+//!    poolBinding]
+//! For contracts:
+//!   abi.decode(publicValues, (bytes32, bytes32, uint256, bytes32, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::attest::AttestPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, AttestPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "attest/header");
+    if let Err(msg) = header.validate(CircuitKind::Attest) {
+        panic!("{}", msg);
+    }
+    probe!(end "attest/header");
+    let inputs = sp1_zkvm::io::read::<AttestPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "attest/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "attest/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `AttestPrivateInputs::check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
+
+    // 3. Commit public values
+    // Must produce exactly 192 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32, uint256, bytes32, uint256, bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
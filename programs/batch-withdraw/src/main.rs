@@ -0,0 +1,58 @@
+//! SP1 Batch-Withdraw Circuit: up to 8 notes out of the pool in one proof.
+//!
+//! Proves a valid batched withdrawal from the shielded pool:
+//! - 1 to 8 input notes are consumed (nullified)
+//! - Sender may spend every input (owner, or claim/reclaim of a cancellable
+//!   note)
+//! - Every input with a non-zero amount exists in the Merkle tree
+//! - The public amount is the sum of the inputs; there is no change note
+//! - Recipient address is committed (prevents front-running)
+//!
+//! See `shielded_pool_lib::batch_withdraw`.
+//!
+//! Public values committed (448 bytes = 14 × bytes32):
+//!   [root, nullifier0..7 (zero when unused), recipient (left-padded),
+//!    amount (uint256 BE), reclaimDeadline, treeLevels, poolBinding]
+//! For contracts:
+//!   abi.decode(publicValues, (bytes32, bytes32[8], address, uint256, uint256, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::batch_withdraw::BatchWithdrawPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, BatchWithdrawPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "batch_withdraw/header");
+    if let Err(msg) = header.validate(CircuitKind::BatchWithdraw) {
+        panic!("{}", msg);
+    }
+    probe!(end "batch_withdraw/header");
+    let inputs = sp1_zkvm::io::read::<BatchWithdrawPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "batch_withdraw/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "batch_withdraw/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `BatchWithdrawPrivateInputs::check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
+
+    // 3. Commit public values
+    // Must produce exactly 448 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32[8], address, uint256, uint256, uint256, bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
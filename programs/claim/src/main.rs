@@ -0,0 +1,58 @@
+//! SP1 Claim Circuit: prove a note was held at a past snapshot of the pool.
+//!
+//! Proves, for an airdrop to historical shielded balances:
+//! - The note exists in the Merkle tree under the snapshot root
+//! - Claimer owns the note (plain notes only)
+//! - The note's nullifier is not in the snapshot's spent-nullifier set
+//! - The claim nullifier is derived from the note, key and airdrop id, so
+//!   each note claims once per airdrop
+//! - Recipient address is committed (prevents front-running)
+//!
+//! See `shielded_pool_lib::claim`.
+//!
+//! Public values committed (256 bytes = 8 × 32-byte slots):
+//!   [root, nullifierRoot, airdropId, claimNullifier, recipient (left-padded),
+//!    amount (uint256 BE), treeLevels (uint256 BE), poolBinding]
+//! For airdrop contracts:
+//!   abi.decode(publicValues, (bytes32, bytes32, bytes32, bytes32, address, uint256, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::claim::ClaimPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, ClaimPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "claim/header");
+    if let Err(msg) = header.validate(CircuitKind::Claim) {
+        panic!("{}", msg);
+    }
+    probe!(end "claim/header");
+    let inputs = sp1_zkvm::io::read::<ClaimPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "claim/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "claim/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `ClaimPrivateInputs::check`)
+    let public_values = inputs.check().unwrap_or_else(|msg| panic!("{}", msg));
+
+    // 3. Commit public values
+    // Must produce exactly 256 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32, bytes32, bytes32, address, uint256, uint256, bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
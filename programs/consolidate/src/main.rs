@@ -0,0 +1,57 @@
+//! SP1 Consolidation Circuit: merge up to 8 notes of one key into one.
+//!
+//! Proves a valid consolidation within the shielded pool:
+//! - 2 to 8 input notes are consumed (nullified)
+//! - One spending key owns every input and the output note
+//! - Every input exists in the Merkle tree
+//! - The output's amount is the sum of the inputs (conservation) and below
+//!   MAX_AMOUNT
+//!
+//! See `shielded_pool_lib::consolidate`.
+//!
+//! Public values committed (384 bytes = 12 × bytes32):
+//!   [root, nullifier0..7 (zero when unused), outCommitment, treeLevels (uint256 BE),
+//!    poolBinding]
+//! For contracts:
+//!   abi.decode(publicValues, (bytes32, bytes32[8], bytes32, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::consolidate::ConsolidatePrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, ConsolidatePrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "consolidate/header");
+    if let Err(msg) = header.validate(CircuitKind::Consolidate) {
+        panic!("{}", msg);
+    }
+    probe!(end "consolidate/header");
+    let inputs = sp1_zkvm::io::read::<ConsolidatePrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "consolidate/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "consolidate/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `ConsolidatePrivateInputs::check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
+
+    // 3. Commit public values
+    // Must produce exactly 384 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32[8], bytes32, uint256, bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
@@ -0,0 +1,55 @@
+//! SP1 Deposit Circuit: prove a deposit's commitment opens to its amount.
+//!
+//! Proves, for a deposit into the shielded pool:
+//! - The commitment is keccak256(amount || pubkey || blinding) of a note
+//!   whose amount is the public deposit amount
+//! - The amount is non-zero
+//!
+//! The note's pubkey and blinding stay private. See
+//! `shielded_pool_lib::deposit`.
+//!
+//! Public values committed (64 bytes = 2 × 32-byte slots):
+//!   [commitment, amount (uint256 BE)]
+//! For contracts:
+//!   abi.decode(publicValues, (bytes32, uint256))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::deposit::DepositPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, DepositPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "deposit/header");
+    if let Err(msg) = header.validate(CircuitKind::Deposit) {
+        panic!("{}", msg);
+    }
+    probe!(end "deposit/header");
+    let inputs = sp1_zkvm::io::read::<DepositPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "deposit/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "deposit/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `DepositPrivateInputs::check`)
+    let public_values = inputs.check().unwrap_or_else(|msg| panic!("{}", msg));
+
+    // 3. Commit public values
+    // Must produce exactly 64 bytes matching:
+    //   abi.decode(publicValues, (bytes32, uint256))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
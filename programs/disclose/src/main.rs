@@ -0,0 +1,60 @@
+//! SP1 Disclose Circuit: chosen notes opened to an auditor.
+//!
+//! Proves, for payloads the holder picks, what a viewing key received:
+//! - 1 to 8 posted payloads
+//! - Each decrypts under the viewing secret whose public key is committed
+//! - Each plaintext is a note; its commitment, amount and recipient pubkey
+//!   are committed next to the payload's hash
+//! - No payload is disclosed twice
+//!
+//! The viewing secret and the blindings stay private, and no other note is
+//! opened. See `shielded_pool_lib::disclosure`.
+//!
+//! Public values committed (1152 bytes = 36 × bytes32):
+//!   [viewingPubkey, auditId, count, commitments[8], payloadHashes[8],
+//!    amounts[8], recipients[8], poolBinding] (zero when unused)
+//! For contracts:
+//!   abi.decode(publicValues,
+//!     (bytes32, bytes32, uint256, bytes32[8], bytes32[8], uint256[8], bytes32[8], bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::disclosure::DisclosurePrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, DisclosurePrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "disclose/header");
+    if let Err(msg) = header.validate(CircuitKind::Disclose) {
+        panic!("{}", msg);
+    }
+    probe!(end "disclose/header");
+    let inputs = sp1_zkvm::io::read::<DisclosurePrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "disclose/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "disclose/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `DisclosurePrivateInputs::check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
+
+    // 3. Commit public values
+    // Must produce exactly 1152 bytes matching:
+    //   abi.decode(publicValues,
+    //     (bytes32, bytes32, uint256, bytes32[8], bytes32[8], uint256[8], bytes32[8], bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
@@ -0,0 +1,61 @@
+//! SP1 Join-Split Circuit: up to 4-in-4-out private transfer.
+//!
+//! Proves a valid private transfer within the shielded pool:
+//! - Four input notes are consumed (nullified); unused slots hold
+//!   zero-amount dummy notes
+//! - Four output notes are created; unused slots hold zero-amount dummies
+//! - Sum of inputs == sum of outputs (conservation)
+//! - Every output amount is below MAX_AMOUNT
+//! - Sender may spend every input (owner, or claim/reclaim of a cancellable
+//!   note)
+//! - Every input with a non-zero amount exists in the Merkle tree
+//!
+//! See `shielded_pool_lib::join_split`.
+//!
+//! Public values committed (384 bytes = 12 × bytes32):
+//!   [root, nullifier0..3, outCommitment0..3, reclaimDeadline, treeLevels,
+//!    poolBinding]
+//! For contracts:
+//!   abi.decode(publicValues, (bytes32, bytes32[4], bytes32[4], uint256, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::join_split::JoinSplitPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, JoinSplitPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "join_split/header");
+    if let Err(msg) = header.validate(CircuitKind::JoinSplit) {
+        panic!("{}", msg);
+    }
+    probe!(end "join_split/header");
+    let inputs = sp1_zkvm::io::read::<JoinSplitPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "join_split/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "join_split/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `JoinSplitPrivateInputs::check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
+
+    // 3. Commit public values
+    // Must produce exactly 384 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32[4], bytes32[4], uint256, uint256, bytes32))
+    // Fixed-size arrays are encoded in place, with no offset or length.
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
@@ -0,0 +1,61 @@
+//! SP1 Payout Circuit: up to 2 notes paying up to 8 recipients.
+//!
+//! Proves a batch disbursement within the shielded pool:
+//! - Two input notes are consumed (nullified); an unused slot holds a
+//!   zero-amount dummy note
+//! - Eight output notes are created; unused slots hold zero-amount dummies
+//! - No two outputs share a pubkey, so each pays a different recipient
+//! - Sum of inputs == sum of outputs (conservation)
+//! - Every output amount is below MAX_AMOUNT
+//! - Sender may spend every input (owner, or claim/reclaim of a cancellable
+//!   note)
+//! - Every input with a non-zero amount exists in the Merkle tree
+//!
+//! See `shielded_pool_lib::payout`.
+//!
+//! Public values committed (448 bytes = 14 × bytes32):
+//!   [root, nullifier0..1, outCommitment0..7, reclaimDeadline, treeLevels,
+//!    poolBinding]
+//! For contracts:
+//!   abi.decode(publicValues, (bytes32, bytes32[2], bytes32[8], uint256, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::payout::PayoutPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, PayoutPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "payout/header");
+    if let Err(msg) = header.validate(CircuitKind::Payout) {
+        panic!("{}", msg);
+    }
+    probe!(end "payout/header");
+    let inputs = sp1_zkvm::io::read::<PayoutPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "payout/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "payout/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `PayoutPrivateInputs::check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
+
+    // 3. Commit public values
+    // Must produce exactly 448 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32[2], bytes32[8], uint256, uint256, bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
@@ -0,0 +1,61 @@
+//! SP1 Policy-Spend Circuit: a payment under a daily spending limit.
+//!
+//! Proves, for one note locked to a spending-limit policy:
+//! - The spending key and the policy open the note's pubkey
+//! - The note exists in the Merkle tree
+//! - Payment + change == note amount, and the change stays under the policy
+//! - The epoch's counter (or, on its first spend, the epoch nullifier) is
+//!   consumed, and the new counter's total is within the policy's limit
+//!
+//! The policy, its limit, the amounts and the counters stay private. See
+//! `shielded_pool_lib::policy`.
+//!
+//! Public values committed (352 bytes = 11 × bytes32):
+//!   [root, nullifier, paymentCommitment, changeCommitment, counterRoot,
+//!    counterNullifier, newCounter, epoch (uint256 BE), treeLevels (uint256 BE),
+//!    counterLevels (uint256 BE), poolBinding]
+//! For contracts:
+//!   abi.decode(publicValues,
+//!       (bytes32, bytes32, bytes32, bytes32, bytes32, bytes32, bytes32, uint256, uint256, uint256, bytes32))
+
+#![no_main]
+sp1_zkvm::entrypoint!(main);
+
+use shielded_pool_lib::policy::PolicySpendPrivateInputs;
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader};
+
+pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
+    // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, PolicySpendPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "policy_spend/header");
+    if let Err(msg) = header.validate(CircuitKind::PolicySpend) {
+        panic!("{}", msg);
+    }
+    probe!(end "policy_spend/header");
+    let inputs = sp1_zkvm::io::read::<PolicySpendPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "policy_spend/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "policy_spend/end_marker");
+
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `PolicySpendPrivateInputs::check`)
+    let public_values = inputs.check().unwrap_or_else(|msg| panic!("{}", msg));
+
+    // 3. Commit public values
+    // Must produce exactly 352 bytes matching:
+    //   abi.decode(publicValues,
+    //       (bytes32, bytes32, bytes32, bytes32, bytes32, bytes32, bytes32, uint256, uint256, uint256, bytes32))
+    sp1_zkvm::io::commit_slice(&public_values.encode());
+}
@@ -4,66 +4,72 @@
 //! - Two input notes are consumed (nullified)
 //! - Two output notes are created
 //! - Sum of inputs == sum of outputs (conservation)
-//! - Sender owns both input notes
-//! - Both inputs exist in the Merkle tree
+//! - Every output amount is below MAX_AMOUNT
+//! - Sender owns both input notes (or, for cancellable notes, is the
+//!   recipient claiming or the sender reclaiming them), shown with each
+//!   owner's proving key: the spending keys never reach the prover
+//!   (see `shielded_pool_lib::ProvingKey`). Notes from before proving keys
+//!   are opened with their legacy key instead
+//! - The owner of every input that moves value signed the public values
+//!   with its spend-authorization key, so a prover holding the proving keys
+//!   cannot spend on its own or redirect the outputs
+//!   (see `shielded_pool_lib::spend_auth_message`)
+//! - Both inputs exist in the Merkle tree, unless zero-amount (a dummy
+//!   input pads a single-note spend), with proofs of the same depth; the
+//!   depth is committed for the pool to check against its tree's
+//! - Optionally, both inputs descend from an association set's approved
+//!   deposits and both outputs carry one of their labels
+//!   (see `shielded_pool_lib::association`)
+//! - The pool binding is committed, so the proof is only accepted by the pool
+//!   it was made for (see `shielded_pool_lib::pool_binding`)
+//! - Optionally, the outputs' encrypted payloads are built here from the
+//!   output notes and their hash committed, so the pool only accepts the
+//!   transfer with payloads that open to the notes
+//!   (see `shielded_pool_lib::note_encryption`)
 //!
-//! Public values committed (160 bytes = 5 × bytes32):
-//!   [root, nullifier1, nullifier2, outCommitment1, outCommitment2]
-//! Matches ShieldedPool.sol: abi.decode(publicValues, (bytes32[5]))
+//! Public values committed (320 bytes = 10 × bytes32):
+//!   [root, nullifier1, nullifier2, outCommitment1, outCommitment2, reclaimDeadline,
+//!    associationRoot (zero when not associated), poolBinding,
+//!    ciphertextsHash (zero when the payloads are not proven), treeLevels]
+//! Matches ShieldedPool.sol: abi.decode(publicValues, (bytes32[10]))
 
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
-use shielded_pool_lib::{
-    compute_nullifier, derive_pubkey, verify_merkle_proof, TransferPrivateInputs,
-};
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader, TransferPrivateInputs};
 
 pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
     // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, TransferPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "transfer/header");
+    if let Err(msg) = header.validate(CircuitKind::Transfer) {
+        panic!("{}", msg);
+    }
+    probe!(end "transfer/header");
     let inputs = sp1_zkvm::io::read::<TransferPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "transfer/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "transfer/end_marker");
 
-    // 2. Verify input note 0
-    let commitment0 = inputs.input_notes[0].commitment();
-    let pubkey0 = derive_pubkey(&inputs.spending_keys[0]);
-    assert_eq!(
-        pubkey0, inputs.input_notes[0].pubkey,
-        "spending key mismatch for input note 0"
-    );
-    let nullifier0 = compute_nullifier(&commitment0, &inputs.spending_keys[0]);
-    assert!(
-        verify_merkle_proof(commitment0, &inputs.merkle_proofs[0], inputs.root),
-        "Merkle proof invalid for input note 0"
-    );
-
-    // 3. Verify input note 1
-    let commitment1 = inputs.input_notes[1].commitment();
-    let pubkey1 = derive_pubkey(&inputs.spending_keys[1]);
-    assert_eq!(
-        pubkey1, inputs.input_notes[1].pubkey,
-        "spending key mismatch for input note 1"
-    );
-    let nullifier1 = compute_nullifier(&commitment1, &inputs.spending_keys[1]);
-    assert!(
-        verify_merkle_proof(commitment1, &inputs.merkle_proofs[1], inputs.root),
-        "Merkle proof invalid for input note 1"
-    );
-
-    // 4. Compute output commitments
-    let out_commitment0 = inputs.output_notes[0].commitment();
-    let out_commitment1 = inputs.output_notes[1].commitment();
-
-    // 5. Conservation check: sum(inputs) == sum(outputs)
-    let input_sum = inputs.input_notes[0].amount as u128 + inputs.input_notes[1].amount as u128;
-    let output_sum = inputs.output_notes[0].amount as u128 + inputs.output_notes[1].amount as u128;
-    assert_eq!(input_sum, output_sum, "amounts don't balance");
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `TransferPrivateInputs::guest_check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
 
-    // 6. Commit public values
-    // Must produce exactly 160 bytes matching:
-    //   abi.decode(publicValues, (bytes32[5]))
-    // which is 5 contiguous bytes32 with no length prefix.
-    sp1_zkvm::io::commit_slice(&inputs.root);     // 32 bytes: Merkle root
-    sp1_zkvm::io::commit_slice(&nullifier0);       // 32 bytes: nullifier for input 0
-    sp1_zkvm::io::commit_slice(&nullifier1);       // 32 bytes: nullifier for input 1
-    sp1_zkvm::io::commit_slice(&out_commitment0);  // 32 bytes: output commitment 0
-    sp1_zkvm::io::commit_slice(&out_commitment1);  // 32 bytes: output commitment 1
+    // 3. Commit public values
+    // Must produce exactly 320 bytes matching:
+    //   abi.decode(publicValues, (bytes32[10]))
+    // which is 10 contiguous bytes32 with no length prefix.
+    sp1_zkvm::io::commit_slice(&public_values.encode());
 }
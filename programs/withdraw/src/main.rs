@@ -1,86 +1,84 @@
 //! SP1 Withdraw Circuit: consume a note and withdraw to a public address.
 //!
 //! Proves a valid withdrawal from the shielded pool:
-//! - Input note exists in the Merkle tree
-//! - Sender owns the input note
-//! - Nullifier is correctly derived
-//! - Withdrawal amount + change amount == input note amount
+//! - Input note exists in the Merkle tree; the proof's depth is committed
+//!   for the pool to check against its tree's
+//! - Sender owns the input note (or, for a cancellable note, is the
+//!   recipient claiming or the sender reclaiming it), shown with the owner's
+//!   proving key: the spending key never reaches the prover
+//!   (see `shielded_pool_lib::ProvingKey`). A note from before proving keys
+//!   is opened with its legacy key instead
+//! - Nullifier is correctly derived from the nullifier key
+//! - The note's owners signed the public values with their
+//!   spend-authorization keys, so a prover holding the proving keys cannot
+//!   spend on its own or change the recipient, fee or call
+//!   (see `shielded_pool_lib::spend_auth_message`)
+//! - Withdrawal amount + change amount + relayer fee == input note amount
+//! - The change note belongs to the spender (its pubkey is the proving
+//!   key's, or a legacy key's current pubkey), so no one building the inputs
+//!   can divert the change
+//! - The change amount is below MAX_AMOUNT
 //! - Recipient address is committed (prevents front-running)
+//! - Relayer and fee are committed, so whoever submits the proof pays the
+//!   fee to the relayer the owner chose
+//! - Optionally, the input descends from an association set's approved
+//!   deposits and the change note carries its label
+//!   (see `shielded_pool_lib::association`)
+//! - The pool binding is committed, so the proof is only accepted by the pool
+//!   it was made for (see `shielded_pool_lib::pool_binding`)
+//! - The owner's deadline is committed, so a relayer holding the proof cannot
+//!   submit it after that time
+//! - For a withdraw-and-call, the call target and calldata hash are committed,
+//!   so the pool makes exactly the call the owner chose after paying out
+//!   (see `shielded_pool_lib::call_binding`)
 //!
-//! Public values committed (160 bytes = 5 × 32-byte slots):
-//!   [root, nullifier, recipient (left-padded), amount (uint256 BE), changeCommitment]
+//! Public values committed (416 bytes = 13 × 32-byte slots):
+//!   [root, nullifier, recipient (left-padded), amount (uint256 BE), changeCommitment,
+//!    reclaimDeadline (uint256 BE), relayer (left-padded), fee (uint256 BE),
+//!    associationRoot (zero when not associated), poolBinding, deadline (uint256 BE),
+//!    callBinding (zero for a plain withdrawal), treeLevels (uint256 BE)]
 //! Matches ShieldedPool.sol:
-//!   abi.decode(publicValues, (bytes32, bytes32, address, uint256, bytes32))
+//!   abi.decode(publicValues, (bytes32, bytes32, address, uint256, bytes32, uint256, address, uint256, bytes32, bytes32,
+//!                             uint256, bytes32, uint256))
 
 #![no_main]
 sp1_zkvm::entrypoint!(main);
 
-use shielded_pool_lib::{
-    compute_nullifier, derive_pubkey, verify_merkle_proof, WithdrawPrivateInputs,
-};
+use shielded_pool_lib::probe;
+use shielded_pool_lib::{validate_end_marker, validate_input_consumed, CircuitKind, InputHeader, WithdrawPrivateInputs};
 
 pub fn main() {
+    // A rejection halts with an abort code the host can explain (see
+    // `shielded_pool_lib::abort`)
+    shielded_pool_lib::install_abort_hook!();
+
     // 1. Read all private inputs from the prover (host)
+    //    Layout: InputHeader, WithdrawPrivateInputs, INPUT_END_MARKER, nothing after
+    let header = sp1_zkvm::io::read::<InputHeader>();
+    probe!(start "withdraw/header");
+    if let Err(msg) = header.validate(CircuitKind::Withdraw) {
+        panic!("{}", msg);
+    }
+    probe!(end "withdraw/header");
     let inputs = sp1_zkvm::io::read::<WithdrawPrivateInputs>();
+    let end_marker = sp1_zkvm::io::read::<[u8; 32]>();
+    probe!(start "withdraw/end_marker");
+    if let Err(msg) = validate_end_marker(&end_marker) {
+        panic!("{}", msg);
+    }
+    if let Err(msg) = validate_input_consumed(sp1_zkvm::syscalls::syscall_hint_len()) {
+        panic!("{}", msg);
+    }
+    probe!(end "withdraw/end_marker");
 
-    // 2. Verify spending key ownership
-    let pubkey = derive_pubkey(&inputs.spending_key);
-    assert_eq!(
-        pubkey, inputs.input_note.pubkey,
-        "spending key does not match note pubkey"
-    );
-
-    // 3. Compute commitment and nullifier
-    let commitment = inputs.input_note.commitment();
-    let nullifier = compute_nullifier(&commitment, &inputs.spending_key);
-
-    // 4. Verify Merkle inclusion
-    assert!(
-        verify_merkle_proof(commitment, &inputs.merkle_proof, inputs.root),
-        "Merkle proof invalid"
-    );
+    // 2. The circuit's checks, the same ones the host runs before proving
+    //    (see `WithdrawPrivateInputs::guest_check`)
+    let public_values = inputs.guest_check().unwrap_or_else(|err| panic!("{}", err));
 
-    // 5. Compute change commitment and verify conservation
-    let change_commitment: [u8; 32] = if let Some(ref change_note) = inputs.change_note {
-        // Partial withdrawal: input = withdraw + change
-        assert_eq!(
-            inputs.input_note.amount,
-            inputs.withdraw_amount + change_note.amount,
-            "partial withdrawal amounts don't balance"
-        );
-        change_note.commitment()
-    } else {
-        // Full withdrawal: entire note amount
-        assert_eq!(
-            inputs.input_note.amount, inputs.withdraw_amount,
-            "full withdrawal amount mismatch"
-        );
-        [0u8; 32]
-    };
-
-    // 6. Commit public values
-    // Must produce exactly 160 bytes matching:
-    //   abi.decode(publicValues, (bytes32, bytes32, address, uint256, bytes32))
+    // 3. Commit public values
+    // Must produce exactly 416 bytes matching:
+    //   abi.decode(publicValues, (bytes32, bytes32, address, uint256, bytes32, uint256, address, uint256, bytes32, bytes32,
+    //                             uint256, bytes32, uint256))
     // ABI encoding: each field is a 32-byte slot.
-
-    // root: bytes32 (32 bytes)
-    sp1_zkvm::io::commit_slice(&inputs.root);
-
-    // nullifier: bytes32 (32 bytes)
-    sp1_zkvm::io::commit_slice(&nullifier);
-
-    // recipient: address left-padded to 32 bytes
-    // ABI encoding of `address`: 12 zero bytes + 20 address bytes
-    let mut recipient_padded = [0u8; 32];
-    recipient_padded[12..32].copy_from_slice(&inputs.recipient);
-    sp1_zkvm::io::commit_slice(&recipient_padded);
-
-    // amount: uint256 big-endian (32 bytes)
-    // withdraw_amount is u64, pad to 32 bytes: 24 zero bytes + 8 BE bytes
-    let mut amount_be = [0u8; 32];
-    amount_be[24..32].copy_from_slice(&inputs.withdraw_amount.to_be_bytes());
-    sp1_zkvm::io::commit_slice(&amount_be);
-
-    // changeCommitment: bytes32 (32 bytes)
-    sp1_zkvm::io::commit_slice(&change_commitment);
+    sp1_zkvm::io::commit_slice(&public_values.encode());
 }
@@ -7,17 +7,37 @@
  * Routes:
  *   POST /prove/transfer  — Generate a transfer proof
  *   POST /prove/withdraw  — Generate a withdraw proof
+ *   GET  /quote           — Price for proving a transfer (fee in shielded USDT)
  *   GET  /health          — Health check
+ *
+ * Fee-in-kind (optional): with PROVER_FEE_PUBKEY set, transfers must pay the
+ * operator in their second output. Clients fetch a quote from /quote, build
+ * the fee note from it (see shielded_pool_lib::prover_fee) and send the quote
+ * id in the X-Fee-Quote header. The prover refuses inputs that do not pay the
+ * quote and checks the proof commits to the fee note. Quotes are single-use.
+ *
+ *   PROVER_FEE_PUBKEY  — operator's shielded pubkey (hex)
+ *   PROVER_FEE         — fee per transfer in raw units (default: 10000 = 0.01 USDT)
+ *   QUOTE_TTL_SECS     — how long a quote is honoured (default: 300)
+ *
+ * Jobs are keyed by the request hash of their inputs (the canonical hash from
+ * shielded_pool_lib::canonical, printed by `shielded-pool-script request-hash`).
+ * A request identical to one still proving waits for that job instead of
+ * proving twice. With PROOF_ARCHIVE_DIR set, finished proofs are kept there as
+ * <request hash>.json and served again for the same request.
+ *
+ *   PROOF_ARCHIVE_DIR  — directory to archive proofs in (default: none)
  */
 
 import express from 'express';
 import cors from 'cors';
 import https from 'https';
 import { execFile } from 'child_process';
-import { writeFileSync, readFileSync, mkdtempSync, rmSync, existsSync } from 'fs';
+import { writeFileSync, readFileSync, mkdtempSync, rmSync, existsSync, mkdirSync } from 'fs';
 import { join } from 'path';
 import { tmpdir } from 'os';
 import { promisify } from 'util';
+import { randomBytes } from 'crypto';
 
 const execFileAsync = promisify(execFile);
 
@@ -32,10 +52,119 @@ app.get('/health', (_req, res) => {
   res.status(200).json({ status: 'ok' });
 });
 
+// ── Fee quotes ──────────────────────────────────────────────────────────────
+
+interface FeeQuote {
+  quoteId: string;      // 0x-prefixed 32 bytes
+  proverPubkey: string; // 0x-prefixed 32 bytes
+  fee: string;          // raw units, decimal string
+  expiresAt: number;    // unix seconds
+}
+
+const strip0x = (s: string) => s.startsWith('0x') ? s.slice(2) : s;
+const PROVER_FEE_PUBKEY = process.env.PROVER_FEE_PUBKEY ? `0x${strip0x(process.env.PROVER_FEE_PUBKEY)}` : undefined;
+const PROVER_FEE = BigInt(process.env.PROVER_FEE || '10000');
+const QUOTE_TTL_SECS = Number(process.env.QUOTE_TTL_SECS || 300);
+const quotes = new Map<string, FeeQuote>();
+
+/** Quote in the JSON layout `shielded-pool-script transfer --fee-quote` reads. */
+function quoteForProver(q: FeeQuote): string {
+  const bytes = (hex: string) => Array.from(Buffer.from(strip0x(hex), 'hex'));
+  return JSON.stringify({
+    quote_id: bytes(q.quoteId),
+    prover_pubkey: bytes(q.proverPubkey),
+    fee: Number(q.fee),
+    expires_at: q.expiresAt,
+  });
+}
+
+app.get('/quote', (_req, res) => {
+  if (!PROVER_FEE_PUBKEY) {
+    res.status(404).json({ error: 'this prover does not charge fees' });
+    return;
+  }
+  const now = Math.floor(Date.now() / 1000);
+  for (const [id, q] of quotes) {
+    if (q.expiresAt < now) quotes.delete(id);
+  }
+  const quote: FeeQuote = {
+    quoteId: `0x${randomBytes(32).toString('hex')}`,
+    proverPubkey: PROVER_FEE_PUBKEY,
+    fee: PROVER_FEE.toString(),
+    expiresAt: now + QUOTE_TTL_SECS,
+  };
+  quotes.set(quote.quoteId, quote);
+  res.json(quote);
+});
+
+// ── Proving jobs ────────────────────────────────────────────────────────────
+
+interface ProofResult {
+  proof: string;
+  publicValues: string;
+  vkey: string;
+  requestHash: string;
+}
+
+const PROOF_ARCHIVE_DIR = process.env.PROOF_ARCHIVE_DIR;
+const jobs = new Map<string, Promise<ProofResult>>();
+
+const CARGO_RUN = ['run', '--release', '-p', 'shielded-pool-script', '--bin', 'shielded-pool-script', '--'];
+
+/** Canonical request hash of the inputs in `inputPath` (0x-prefixed). */
+async function requestHash(circuit: string, inputPath: string): Promise<string> {
+  const { stdout } = await execFileAsync('cargo', [...CARGO_RUN, 'request-hash', '--circuit', circuit, '--input', inputPath], {
+    cwd: PROJECT_DIR,
+    env: { ...process.env },
+  });
+  return `0x${stdout.trim()}`;
+}
+
+/**
+ * Prove a request once: a request already archived is served from the
+ * archive, one already proving is joined.
+ */
+async function prove(circuit: string, inputJson: string, quote?: FeeQuote): Promise<ProofResult> {
+  const tempDir = mkdtempSync(join(tmpdir(), 'shielded-pool-'));
+  const inputPath = join(tempDir, 'input.json');
+  let key: string;
+  try {
+    writeFileSync(inputPath, inputJson);
+    key = await requestHash(circuit, inputPath);
+  } finally {
+    rmSync(tempDir, { recursive: true, force: true });
+  }
+
+  const archived = PROOF_ARCHIVE_DIR && join(PROOF_ARCHIVE_DIR, `${key}.json`);
+  if (archived && existsSync(archived)) {
+    console.log(`[${circuit}] Request ${key} served from the archive.`);
+    return JSON.parse(readFileSync(archived, 'utf-8'));
+  }
+  const running = jobs.get(key);
+  if (running) {
+    console.log(`[${circuit}] Request ${key} is already proving; waiting for it.`);
+    return running;
+  }
+
+  const job = runProver(circuit, inputJson, quote).then((result) => {
+    if (archived) {
+      mkdirSync(PROOF_ARCHIVE_DIR!, { recursive: true });
+      writeFileSync(archived, JSON.stringify(result, null, 2));
+    }
+    return result;
+  });
+  jobs.set(key, job);
+  try {
+    return await job;
+  } finally {
+    jobs.delete(key);
+  }
+}
+
 /**
  * Run the Rust prover binary for a given circuit.
  */
-async function runProver(circuit: string, inputJson: string): Promise<{ proof: string; publicValues: string; vkey: string }> {
+async function runProver(circuit: string, inputJson: string, quote?: FeeQuote): Promise<ProofResult> {
   const tempDir = mkdtempSync(join(tmpdir(), 'shielded-pool-'));
   const inputPath = join(tempDir, 'input.json');
   const outputPath = join(tempDir, 'output.json');
@@ -44,11 +173,16 @@ async function runProver(circuit: string, inputJson: string): Promise<{ proof: s
     writeFileSync(inputPath, inputJson);
 
     const args = [
-      'run', '--release', '-p', 'shielded-pool-script', '--bin', 'shielded-pool-script', '--',
+      ...CARGO_RUN,
       circuit,
       '--input', inputPath,
       '--output', outputPath,
     ];
+    if (quote) {
+      const quotePath = join(tempDir, 'quote.json');
+      writeFileSync(quotePath, quoteForProver(quote));
+      args.push('--fee-quote', quotePath);
+    }
 
     console.log(`[${circuit}] Starting proof generation...`);
     const { stdout, stderr } = await execFileAsync('cargo', args, {
@@ -71,6 +205,7 @@ async function runProver(circuit: string, inputJson: string): Promise<{ proof: s
       proof: ensure0x(output.proof),
       publicValues: ensure0x(output.public_values),
       vkey: ensure0x(output.vkey),
+      requestHash: ensure0x(output.request_hash),
     };
   } finally {
     rmSync(tempDir, { recursive: true, force: true });
@@ -78,8 +213,18 @@ async function runProver(circuit: string, inputJson: string): Promise<{ proof: s
 }
 
 app.post('/prove/transfer', async (req, res) => {
+  let quote: FeeQuote | undefined;
+  if (PROVER_FEE_PUBKEY) {
+    quote = quotes.get(req.header('x-fee-quote') ?? '');
+    if (!quote || quote.expiresAt < Math.floor(Date.now() / 1000)) {
+      res.status(402).json({ error: 'a valid X-Fee-Quote from GET /quote is required' });
+      return;
+    }
+    // Single-use: a second request with the same quote would pay one fee for two proofs
+    quotes.delete(quote.quoteId);
+  }
   try {
-    const result = await runProver('transfer', JSON.stringify(req.body));
+    const result = await prove('transfer', JSON.stringify(req.body), quote);
     res.json(result);
   } catch (err: any) {
     console.error('[transfer] Proof generation failed:', err.message);
@@ -89,7 +234,7 @@ app.post('/prove/transfer', async (req, res) => {
 
 app.post('/prove/withdraw', async (req, res) => {
   try {
-    const result = await runProver('withdraw', JSON.stringify(req.body));
+    const result = await prove('withdraw', JSON.stringify(req.body));
     res.json(result);
   } catch (err: any) {
     console.error('[withdraw] Proof generation failed:', err.message);
@@ -111,7 +256,8 @@ if (existsSync(certPath) && existsSync(keyPath)) {
     console.log(`  HTTP  → http://localhost:${PORT}`);
     console.log(`  HTTPS → https://localhost:${httpsPort}`);
     console.log(`Project dir: ${PROJECT_DIR}`);
-    console.log(`SP1_PROVER: ${process.env.SP1_PROVER || 'not set'}\n`);
+    console.log(`SP1_PROVER: ${process.env.SP1_PROVER || 'not set'}`);
+    console.log(`Fees: ${PROVER_FEE_PUBKEY ? `${PROVER_FEE} raw units per transfer` : 'none'}\n`);
   });
 
   // Also keep HTTP for local dev
@@ -121,6 +267,7 @@ if (existsSync(certPath) && existsSync(keyPath)) {
     console.log(`\nShielded Pool Proxy running on http://localhost:${PORT}`);
     console.log(`Project dir: ${PROJECT_DIR}`);
     console.log(`SP1_PROVER: ${process.env.SP1_PROVER || 'not set'}`);
+    console.log(`Fees: ${PROVER_FEE_PUBKEY ? `${PROVER_FEE} raw units per transfer` : 'none'}`);
     console.log(`\nTip: To use with HTTPS frontends (e.g. Netlify), run:`);
     console.log(`  brew install mkcert && mkcert -install && cd proxy && mkcert localhost 127.0.0.1 ::1\n`);
   });
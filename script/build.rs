@@ -1,4 +1,13 @@
 fn main() {
     sp1_build::build_program("../programs/transfer");
     sp1_build::build_program("../programs/withdraw");
+    sp1_build::build_program("../programs/claim");
+    sp1_build::build_program("../programs/join-split");
+    sp1_build::build_program("../programs/deposit");
+    sp1_build::build_program("../programs/batch-withdraw");
+    sp1_build::build_program("../programs/consolidate");
+    sp1_build::build_program("../programs/attest");
+    sp1_build::build_program("../programs/disclose");
+    sp1_build::build_program("../programs/policy-spend");
+    sp1_build::build_program("../programs/payout");
 }
@@ -0,0 +1,526 @@
+//! Tamper-evident audit trail of wallet operations.
+//!
+//! Every deposit, private transfer and withdrawal the host tools send is
+//! appended to a JSON Lines log (`AUDIT_LOG`, default
+//! `fixtures/audit-log.jsonl`). An entry links the wallet notes it spent and
+//! created (label, commitment, leaf) to the transaction that carried it, the
+//! keccak256 of the submitted proof and the public values themselves. Entries
+//! for proven operations also carry the request hash of the private inputs
+//! (`shielded_pool_lib::canonical`), which matches the prover's job key
+//! without revealing the inputs.
+//!
+//! Entries are hash-chained: each stores the hash of the previous one and
+//! its own hash over all of its fields, so editing, dropping or reordering
+//! an entry breaks every later hash. Publishing or signing the head hash
+//! pins the whole trail, so dropping entries from the end shows too
+//! (`verify_head`).
+//!
+//! `audit export` turns the log into an `AuditReport` (entries plus the
+//! history of every note they mention); `verify-audit-report` re-checks a
+//! report offline and against the chain.
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use shielded_pool_lib::{keccak256, Note, TransferPublicValues, WithdrawPublicValues};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{decode_hex, idle::unix_now, wallet::WalletNote};
+
+/// Report format version.
+pub const AUDIT_REPORT_VERSION: u32 = 1;
+
+/// `prev_hash` of the first entry.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Pool call an entry records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Deposit,
+    Transfer,
+    Withdraw,
+}
+
+/// A note spent or created by an operation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditNote {
+    pub label: String,
+    /// Hex-encoded commitment
+    pub commitment: String,
+    /// Raw token units (6 decimals)
+    pub amount: u64,
+    /// Leaf index, once known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leaf_index: Option<u32>,
+}
+
+impl AuditNote {
+    pub fn new(label: &str, note: &Note, leaf_index: Option<u32>) -> Self {
+        AuditNote {
+            label: label.to_string(),
+            commitment: hex::encode(note.commitment()),
+            amount: note.amount,
+            leaf_index,
+        }
+    }
+
+    pub fn from_wallet(note: &WalletNote) -> Self {
+        AuditNote {
+            label: note.label.clone(),
+            commitment: note.commitment.clone(),
+            amount: note.amount,
+            leaf_index: Some(note.leaf_index),
+        }
+    }
+}
+
+/// One operation in the log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, from 0
+    pub seq: u64,
+    /// Unix time the entry was recorded
+    pub recorded_at: u64,
+    pub action: AuditAction,
+    /// Tool that sent the transaction (e.g. "e2e", "exit", "sweep-idle")
+    pub source: String,
+    pub chain_id: u64,
+    /// 0x-prefixed ShieldedPool address
+    pub pool: String,
+    /// 0x-prefixed transaction hash
+    pub tx_hash: String,
+    pub block_number: u64,
+    /// Wallet notes consumed
+    pub spent: Vec<AuditNote>,
+    /// Notes created, in tree order
+    pub created: Vec<AuditNote>,
+    /// Amount that entered or left the pool publicly (deposits and withdrawals)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_amount: Option<u64>,
+    /// Hex-encoded keccak256 of the proof bytes as submitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof_hash: Option<String>,
+    /// Hex-encoded public values as submitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_values: Option<String>,
+    /// Hex-encoded canonical hash of the private inputs that were proven
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_hash: Option<String>,
+    /// `hash` of the previous entry (`GENESIS_HASH` for the first)
+    pub prev_hash: String,
+    /// keccak256 of this entry serialized with `hash` empty
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// A new entry; `AuditLog::append` fills in the position and hashes.
+    pub fn new(action: AuditAction, source: &str, chain_id: u64, pool: &str, tx_hash: &str, block_number: u64) -> Self {
+        AuditEntry {
+            seq: 0,
+            recorded_at: 0,
+            action,
+            source: source.to_string(),
+            chain_id,
+            pool: pool.to_string(),
+            tx_hash: tx_hash.to_string(),
+            block_number,
+            spent: Vec::new(),
+            created: Vec::new(),
+            public_amount: None,
+            proof_hash: None,
+            public_values: None,
+            request_hash: None,
+            prev_hash: String::new(),
+            hash: String::new(),
+        }
+    }
+
+    pub fn with_spent(mut self, note: AuditNote) -> Self {
+        self.spent.push(note);
+        self
+    }
+
+    pub fn with_created(mut self, note: AuditNote) -> Self {
+        self.created.push(note);
+        self
+    }
+
+    pub fn with_public_amount(mut self, amount: u64) -> Self {
+        self.public_amount = Some(amount);
+        self
+    }
+
+    /// Record the proof (by hash) and public values that were submitted.
+    pub fn with_proof(mut self, proof: &[u8], public_values: &[u8]) -> Self {
+        self.proof_hash = Some(hex::encode(keccak256(proof)));
+        self.public_values = Some(hex::encode(public_values));
+        self
+    }
+
+    /// Record the request hash of the private inputs behind the proof.
+    pub fn with_request(mut self, request_hash: [u8; 32]) -> Self {
+        self.request_hash = Some(hex::encode(request_hash));
+        self
+    }
+
+    /// Hash over every field except `hash` itself.
+    pub fn compute_hash(&self) -> String {
+        let mut unhashed = self.clone();
+        unhashed.hash = String::new();
+        let bytes = serde_json::to_vec(&unhashed).expect("audit entries always serialize");
+        hex::encode(keccak256(&bytes))
+    }
+
+    /// Check the public values against the recorded notes and amount.
+    /// Nullifiers cannot be tied to spent notes without the spending key,
+    /// so only their presence is checked.
+    pub fn check_public_values(&self) -> Result<()> {
+        let pv = match &self.public_values {
+            Some(pv) => decode_hex(pv).with_context(|| format!("entry {}: invalid public_values hex", self.seq))?,
+            None => {
+                ensure!(
+                    self.action == AuditAction::Deposit,
+                    "entry {}: {:?} without public values",
+                    self.seq,
+                    self.action
+                );
+                ensure!(
+                    self.spent.is_empty() && self.created.len() == 1,
+                    "entry {}: a deposit creates one note and spends none",
+                    self.seq
+                );
+                ensure!(
+                    self.public_amount == Some(self.created[0].amount),
+                    "entry {}: deposit amount differs from the note",
+                    self.seq
+                );
+                return Ok(());
+            }
+        };
+        let commitments: Vec<String> = self.created.iter().map(|n| n.commitment.clone()).collect();
+        match self.action {
+            AuditAction::Deposit => bail!("entry {}: deposit with public values", self.seq),
+            AuditAction::Transfer => {
+                let pv = TransferPublicValues::decode(&pv)
+                    .with_context(|| format!("entry {}: malformed transfer public values", self.seq))?;
+                ensure!(
+                    commitments == pv.out_commitments.map(hex::encode),
+                    "entry {}: created notes differ from the output commitments",
+                    self.seq
+                );
+                ensure!(self.spent.len() == 2, "entry {}: a transfer spends two notes", self.seq);
+            }
+            AuditAction::Withdraw => {
+                let pv = WithdrawPublicValues::decode(&pv)
+                    .with_context(|| format!("entry {}: malformed withdraw public values", self.seq))?;
+                let expected: Vec<String> = if pv.change_commitment == [0u8; 32] {
+                    Vec::new()
+                } else {
+                    vec![hex::encode(pv.change_commitment)]
+                };
+                ensure!(commitments == expected, "entry {}: created notes differ from the change commitment", self.seq);
+                ensure!(self.public_amount == Some(pv.amount), "entry {}: amount differs from public values", self.seq);
+                ensure!(self.spent.len() == 1, "entry {}: a withdrawal spends one note", self.seq);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The append-only log file.
+pub struct AuditLog;
+
+impl AuditLog {
+    /// `fixtures/audit-log.jsonl`.
+    pub fn default_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("fixtures/audit-log.jsonl")
+    }
+
+    /// `AUDIT_LOG` if set, otherwise the default path.
+    pub fn path() -> PathBuf {
+        std::env::var("AUDIT_LOG").map(PathBuf::from).unwrap_or_else(|_| Self::default_path())
+    }
+
+    /// Every entry in the log (none if it does not exist yet).
+    pub fn load(path: &Path) -> Result<Vec<AuditEntry>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read audit log: {}", path.display()))?;
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line).with_context(|| format!("{}: line {} is not an entry", path.display(), i + 1))
+            })
+            .collect()
+    }
+
+    /// Chain `entry` onto the log and append it. The existing log must verify.
+    /// A transaction already in the log is not recorded twice (a resumed run
+    /// may confirm it again); the existing entry is returned instead.
+    pub fn append(path: &Path, mut entry: AuditEntry) -> Result<AuditEntry> {
+        let entries = Self::load(path)?;
+        verify_chain(&entries).with_context(|| format!("refusing to extend {}", path.display()))?;
+        if let Some(existing) = entries.iter().find(|e| e.chain_id == entry.chain_id && e.tx_hash == entry.tx_hash) {
+            return Ok(existing.clone());
+        }
+        entry.seq = entries.len() as u64;
+        entry.recorded_at = unix_now();
+        entry.prev_hash = entries.last().map_or_else(|| GENESIS_HASH.to_string(), |e| e.hash.clone());
+        entry.hash = entry.compute_hash();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        Ok(entry)
+    }
+
+    /// Append `entry` to the log at `AUDIT_LOG` and say so.
+    pub fn record(entry: AuditEntry) -> Result<()> {
+        let path = Self::path();
+        let entry = Self::append(&path, entry)?;
+        println!("    Audit log: entry {} ({})", entry.seq, path.display());
+        Ok(())
+    }
+}
+
+/// Check positions, links and hashes of a whole log.
+pub fn verify_chain(entries: &[AuditEntry]) -> Result<()> {
+    let mut prev = GENESIS_HASH.to_string();
+    for (i, entry) in entries.iter().enumerate() {
+        ensure!(entry.seq == i as u64, "entry {i} is numbered {}", entry.seq);
+        ensure!(entry.prev_hash == prev, "entry {i} does not link to the entry before it");
+        ensure!(entry.hash == entry.compute_hash(), "entry {i} was modified after it was recorded");
+        prev = entry.hash.clone();
+    }
+    Ok(())
+}
+
+/// Check a whole log against a published head hash, which also catches
+/// entries dropped from the end.
+pub fn verify_head(entries: &[AuditEntry], head: &str) -> Result<()> {
+    verify_chain(entries)?;
+    let last = entries.last().map_or(GENESIS_HASH, |e| e.hash.as_str());
+    ensure!(last == head, "the log ends at 0x{last}, not at the head 0x{head}");
+    Ok(())
+}
+
+/// Where a note appears in the trail.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NoteTrail {
+    pub label: String,
+    pub commitment: String,
+    pub amount: u64,
+    /// Entry that created the note (None if it predates the log)
+    pub created_in: Option<u64>,
+    /// Entry that spent it
+    pub spent_in: Option<u64>,
+    /// Whether the wallet still held it when the report was exported
+    pub in_wallet: bool,
+}
+
+/// Exported trail for auditors.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditReport {
+    pub version: u32,
+    pub exported_at: u64,
+    /// Hash of the last entry
+    pub head: String,
+    pub entries: Vec<AuditEntry>,
+    /// Every note the entries mention, by commitment
+    pub notes: Vec<NoteTrail>,
+}
+
+impl AuditReport {
+    /// Build a report from a verified log; `wallet_notes` marks which notes are still held.
+    pub fn export(entries: Vec<AuditEntry>, wallet_notes: &[WalletNote]) -> Result<Self> {
+        verify_chain(&entries)?;
+        let held: Vec<&str> = wallet_notes.iter().map(|n| n.commitment.as_str()).collect();
+        let mut notes = note_trails(&entries)?;
+        for trail in &mut notes {
+            trail.in_wallet = held.contains(&trail.commitment.as_str());
+        }
+        Ok(AuditReport {
+            version: AUDIT_REPORT_VERSION,
+            exported_at: unix_now(),
+            head: entries.last().map_or_else(|| GENESIS_HASH.to_string(), |e| e.hash.clone()),
+            entries,
+            notes,
+        })
+    }
+
+    /// Everything that can be checked without the chain: the hash chain, the
+    /// head, each entry's public values and the note histories.
+    pub fn check_offline(&self) -> Result<()> {
+        ensure!(self.version == AUDIT_REPORT_VERSION, "unsupported audit report version {}", self.version);
+        verify_head(&self.entries, &self.head)?;
+        for entry in &self.entries {
+            entry.check_public_values()?;
+        }
+        let expected = note_trails(&self.entries)?;
+        ensure!(self.notes.len() == expected.len(), "note histories do not match the entries");
+        for (trail, expected) in self.notes.iter().zip(&expected) {
+            ensure!(
+                NoteTrail { in_wallet: trail.in_wallet, ..expected.clone() } == *trail,
+                "history of note '{}' does not match the entries",
+                trail.label
+            );
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read audit report: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+/// One trail per commitment, in order of first appearance. A note is
+/// created at most once and spent at most once.
+fn note_trails(entries: &[AuditEntry]) -> Result<Vec<NoteTrail>> {
+    let mut order: Vec<String> = Vec::new();
+    let mut trails: BTreeMap<String, NoteTrail> = BTreeMap::new();
+    for entry in entries {
+        for (note, created) in entry.spent.iter().map(|n| (n, false)).chain(entry.created.iter().map(|n| (n, true))) {
+            let trail = trails.entry(note.commitment.clone()).or_insert_with(|| {
+                order.push(note.commitment.clone());
+                NoteTrail {
+                    label: note.label.clone(),
+                    commitment: note.commitment.clone(),
+                    amount: note.amount,
+                    created_in: None,
+                    spent_in: None,
+                    in_wallet: false,
+                }
+            });
+            let slot = if created { &mut trail.created_in } else { &mut trail.spent_in };
+            ensure!(slot.is_none(), "note '{}' is {} twice", note.label, if created { "created" } else { "spent" });
+            *slot = Some(entry.seq);
+        }
+    }
+    Ok(order.into_iter().map(|c| trails.remove(&c).unwrap()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh log with a deposit, a transfer and a withdrawal.
+    fn log(name: &str) -> (PathBuf, Vec<AuditEntry>) {
+        let path = std::env::temp_dir().join(format!("audit-{name}-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let note = |label: &str, byte: u8| AuditNote {
+            label: label.into(),
+            commitment: hex::encode([byte; 32]),
+            amount: 1_000,
+            leaf_index: Some(byte as u32),
+        };
+        let entries = [
+            AuditEntry::new(AuditAction::Deposit, "test", 1, "0xpool", "0x01", 10).with_created(note("a", 0)),
+            AuditEntry::new(AuditAction::Transfer, "test", 1, "0xpool", "0x02", 11)
+                .with_spent(note("a", 0))
+                .with_created(note("b", 1)),
+            AuditEntry::new(AuditAction::Withdraw, "test", 1, "0xpool", "0x03", 12)
+                .with_spent(note("b", 1))
+                .with_public_amount(1_000),
+        ];
+        for entry in entries {
+            AuditLog::append(&path, entry).unwrap();
+        }
+        let entries = AuditLog::load(&path).unwrap();
+        (path, entries)
+    }
+
+    fn rejection(entries: &[AuditEntry], head: &str) -> String {
+        format!("{:#}", verify_head(entries, head).unwrap_err())
+    }
+
+    #[test]
+    fn test_valid_chain_verifies() {
+        let (path, entries) = log("valid");
+        assert_eq!(entries.len(), 3);
+        verify_head(&entries, &entries[2].hash).unwrap();
+        // Appending checks the log it extends, and keeps it valid
+        let entry = AuditLog::append(&path, AuditEntry::new(AuditAction::Deposit, "test", 1, "0xpool", "0x04", 13))
+            .unwrap();
+        assert_eq!((entry.seq, &entry.prev_hash), (3, &entries[2].hash));
+        verify_head(&AuditLog::load(&path).unwrap(), &entry.hash).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_changed_entry_fails() {
+        let (path, mut entries) = log("changed");
+        let head = entries[2].hash.clone();
+        entries[1].created[0].amount = 2_000;
+        assert!(rejection(&entries, &head).contains("entry 1 was modified"));
+        // Rehashing the changed entry breaks the link from the next one
+        entries[1].hash = entries[1].compute_hash();
+        assert!(rejection(&entries, &head).contains("entry 2 does not link"));
+
+        // The log on disk refuses to grow past a change
+        let text = std::fs::read_to_string(&path).unwrap().replace("\"block_number\":11", "\"block_number\":9");
+        std::fs::write(&path, text).unwrap();
+        let next = AuditEntry::new(AuditAction::Deposit, "test", 1, "0xpool", "0x04", 13);
+        assert!(format!("{:#}", AuditLog::append(&path, next).unwrap_err()).contains("refusing to extend"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_entry_fails() {
+        let (path, mut entries) = log("deleted");
+        let head = entries[2].hash.clone();
+        entries.remove(1);
+        assert!(rejection(&entries, &head).contains("entry 1 is numbered 2"));
+        // Renumbering does not help: the hashes cover the position
+        entries[1].seq = 1;
+        assert!(rejection(&entries, &head).contains("entry 1 does not link"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reordered_entries_fail() {
+        let (path, mut entries) = log("reordered");
+        let head = entries[2].hash.clone();
+        entries.swap(1, 2);
+        assert!(rejection(&entries, &head).contains("entry 1 is numbered 2"));
+        entries.swap(0, 1);
+        entries.iter_mut().enumerate().for_each(|(i, e)| e.seq = i as u64);
+        assert!(rejection(&entries, &head).contains("entry 0 does not link"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_tail_fails() {
+        let (path, mut entries) = log("truncated");
+        let head = entries[2].hash.clone();
+        entries.pop();
+        // What is left is a valid chain, but not the one the head pins
+        verify_chain(&entries).unwrap();
+        assert!(rejection(&entries, &head).contains("not at the head"));
+        assert!(rejection(&[], &head).contains(&format!("ends at 0x{GENESIS_HASH}")));
+
+        // Nor does a report that drops entries but keeps the head
+        let mut report = AuditReport::export(AuditLog::load(&path).unwrap(), &[]).unwrap();
+        report.entries.pop();
+        assert!(format!("{:#}", report.check_offline().unwrap_err()).contains("not at the head"));
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,170 @@
+//! Execute the adversarial input corpus against both guests.
+//!
+//! Every case in `shielded_pool_lib::adversarial::corpus()` breaks one rule
+//! the circuits enforce and must be rejected inside the guest (the guest
+//! panics, so `execute` fails). The control inputs they were derived from
+//! must execute. Any accepted case or rejected control fails the run, which
+//! makes this usable as a CI gate for constraint coverage.
+//!
+//! Execution only — no proofs are generated, so no prover network is needed.
+//!
+//! With `--coverage`, the guests are the ones built with their `coverage`
+//! feature (`make coverage-guests`), whose cycle-tracker probes show which
+//! branch of each check every case took. See `shielded_pool_script::coverage`.
+//!
+//! Usage:
+//!   SP1_PROVER=mock cargo run --release -p shielded-pool-script --bin adversarial -- [--dump PATH]
+//!     [--coverage PATH [--baseline PATH] [--elf-dir DIR]] [FILTER]
+//!
+//!   FILTER           — only run cases whose name contains it (e.g. "withdraw/")
+//!   --dump PATH      — also write the corpus (controls first) as JSON to PATH
+//!   --coverage PATH  — write a branch coverage report to PATH
+//!   --baseline PATH  — print the coverage delta against an earlier report; fail if a branch was lost
+//!   --elf-dir DIR    — where the coverage guests were built (default: target/coverage-elf)
+
+use anyhow::{bail, ensure, Context, Result};
+use shielded_pool_lib::{
+    adversarial::{controls, corpus, AdversarialCase, CaseInputs},
+    CircuitKind,
+};
+use shielded_pool_script::{coverage::CoverageReport, framed_stdin};
+use sp1_sdk::{include_elf, ProverClient};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+pub const TRANSFER_ELF: &[u8] = include_elf!("transfer-program");
+pub const WITHDRAW_ELF: &[u8] = include_elf!("withdraw-program");
+
+fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+
+    let mut filter = None;
+    let mut dump = None;
+    let mut coverage_path = None;
+    let mut baseline = None;
+    let mut elf_dir = PathBuf::from("target/coverage-elf");
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dump" => dump = Some(args.next().context("--dump needs a path")?),
+            "--coverage" => coverage_path = Some(PathBuf::from(args.next().context("--coverage needs a path")?)),
+            "--baseline" => baseline = Some(PathBuf::from(args.next().context("--baseline needs a path")?)),
+            "--elf-dir" => elf_dir = PathBuf::from(args.next().context("--elf-dir needs a directory")?),
+            _ => filter = Some(arg),
+        }
+    }
+
+    let controls = controls();
+    let corpus = corpus();
+    if let Some(path) = dump {
+        let all: Vec<&AdversarialCase> = controls.iter().chain(&corpus).collect();
+        std::fs::write(&path, serde_json::to_string_pretty(&all)?)?;
+        println!("Corpus written to {path} ({} cases)", all.len());
+    }
+    let selected = |case: &&AdversarialCase| filter.as_ref().is_none_or(|f| case.name.contains(f.as_str()));
+
+    ensure!(baseline.is_none() || coverage_path.is_some(), "--baseline needs --coverage");
+    let (transfer_elf, withdraw_elf) = if coverage_path.is_some() {
+        let read = |name: &str| {
+            let path = elf_dir.join(name);
+            std::fs::read(&path)
+                .with_context(|| format!("coverage guest {} not found; run `make coverage-guests`", path.display()))
+        };
+        (read("transfer-program")?, read("withdraw-program")?)
+    } else {
+        (TRANSFER_ELF.to_vec(), WITHDRAW_ELF.to_vec())
+    };
+
+    let client = ProverClient::from_env();
+    let execute = |case: &AdversarialCase| -> Result<(u64, HashMap<String, u64>), String> {
+        let (elf, stdin) = match &case.inputs {
+            CaseInputs::Transfer(inputs) => (&transfer_elf, framed_stdin(CircuitKind::Transfer, inputs)),
+            CaseInputs::Withdraw(inputs) => (&withdraw_elf, framed_stdin(CircuitKind::Withdraw, inputs)),
+        };
+        client
+            .execute(elf, &stdin)
+            .run()
+            .map(|(_, report)| (report.total_instruction_count(), report.cycle_tracker))
+            .map_err(|e| e.to_string())
+    };
+
+    let mut failures = Vec::new();
+    let mut coverage = CoverageReport::new();
+
+    println!("Controls (must execute):");
+    for case in controls.iter().filter(selected) {
+        match execute(case) {
+            Ok((cycles, tracker)) => {
+                println!("  ✓ {} ({cycles} cycles)", case.name);
+                coverage.record_accepted(case, &tracker);
+            }
+            Err(e) => {
+                println!("  ✗ {} rejected: {e}", case.name);
+                coverage.record_rejected(case, &e);
+                failures.push(format!("control {} was rejected", case.name));
+            }
+        }
+    }
+
+    println!("\nAdversarial cases (must be rejected):");
+    let mut rejected = 0;
+    for case in corpus.iter().filter(selected) {
+        match execute(case) {
+            Err(e) => {
+                rejected += 1;
+                println!("  ✓ {} — {}", case.name, first_line(&e));
+                coverage.record_rejected(case, &e);
+            }
+            Ok((_, tracker)) => {
+                coverage.record_accepted(case, &tracker);
+                println!("  ✗ {} ACCEPTED (expected the {} check to reject it)", case.name, case.rejected_by);
+                failures.push(format!("{} was accepted", case.name));
+            }
+        }
+    }
+
+    println!("\n{rejected} case(s) rejected, {} failure(s)", failures.len());
+
+    if let Some(path) = coverage_path {
+        println!("\nBranch coverage:");
+        for guest in &coverage.guests {
+            println!("  {}: {}/{} ({:.1}%)", guest.circuit, guest.branches_hit, guest.branches_total, guest.percent());
+            for branch in guest.checks.iter().flat_map(|c| &c.branches).filter(|b| b.cases.is_empty()) {
+                println!("    not hit: {}", branch.id);
+            }
+            for case in &guest.unattributed {
+                println!("    unattributed rejection: {case}");
+            }
+        }
+        coverage.save(&path)?;
+        println!("Coverage report written to {}", path.display());
+
+        if let Some(baseline) = baseline {
+            let delta = coverage.delta(&CoverageReport::load(&baseline)?);
+            println!("\nDelta against {}:", baseline.display());
+            for id in &delta.gained {
+                println!("  + {id}");
+            }
+            for id in &delta.lost {
+                println!("  - {id}");
+            }
+            for id in &delta.new_uncovered {
+                println!("  ? {id} (new, not hit)");
+            }
+            if delta.gained.is_empty() && delta.lost.is_empty() && delta.new_uncovered.is_empty() {
+                println!("  no change");
+            }
+            for id in &delta.lost {
+                failures.push(format!("branch {id} is no longer covered"));
+            }
+        }
+    }
+    if !failures.is_empty() {
+        bail!("adversarial corpus failed:\n  {}", failures.join("\n  "));
+    }
+    Ok(())
+}
+
+fn first_line(s: &str) -> &str {
+    s.lines().next().unwrap_or(s)
+}
@@ -7,7 +7,7 @@
 //!   4. Verifies on-chain state (nullifiers, Merkle tree, balances)
 //!
 //! Usage:
-//!   SP1_PROVER=network cargo run --release -p shielded-pool-script --bin e2e
+//!   SP1_PROVER=network cargo run --release -p shielded-pool-script --bin e2e [-- --strict]
 //!
 //! Required env vars (from .env):
 //!   RPC_URL           — Plasma RPC endpoint
@@ -26,31 +26,66 @@
 //!                            If not set, a random recipient key is generated.
 //!   RECIPIENT_VIEWING_PUBKEY — Recipient's viewing public key (hex, 64 chars).
 //!                              If not set, derived from recipient spending key.
+//!   WALLET_FILE            — Where to write the wallet (default: fixtures/wallet.db; a .json path writes JSON)
+//!   WALLET_PASSPHRASE      — Passphrase for the encrypted keys in the wallet.
+//!                            If not set, prompted for on the terminal.
+//!   CANCELLABLE_WINDOW     — If set (seconds), the recipient's note is cancellable: the
+//!                            sender can reclaim it for this long; the recipient claims
+//!                            it through the withdrawal.
+//!   ENTROPY_SOURCE         — Where keys and blindings come from: os (default),
+//!                            device:<path> (hardware RNG) or pkcs11:<module> (HSM,
+//!                            with PKCS11_SLOT / PKCS11_PIN)
+//!   ENTROPY_SEED           — 32-byte hex seed XORed into that source's output
+//!   PROOF_CACHE_DIR        — Where generated proofs are cached (default: ~/.cache/shielded-pool/proofs;
+//!                            off disables it). A re-run deposits fresh notes, so it proves anew
+//!   STRICT                 — 1 to fail on any fallback, as with --strict (default: on when CI is set)
 
 use alloy::{
     consensus::Transaction as _,
+    eips::BlockId,
     primitives::{ Address, Bytes, FixedBytes, U256 },
     providers::{Provider, ProviderBuilder},
+    rpc::types::TransactionReceipt,
     signers::local::PrivateKeySigner,
     sol,
 };
 use anyhow::{ ensure, Context, Result };
-use crypto_box::{
-    aead::{ AeadCore, OsRng },
-    PublicKey, SecretKey, SalsaBox,
-};
+use crypto_box::PublicKey;
 use rand::Rng;
-use serde::{Deserialize, Serialize};
 use shielded_pool_lib::{
+    cancellable::authorize_spend,
+    coin_selection::{ plan_transfer_split, ChangeSplit, NoteRef, SelectionConfig },
     compute_nullifier,
     derive_pubkey,
-    keccak256,
+    pool_binding,
+    tree_capacity,
+    CancellableLock,
+    CheckpointId,
+    CircuitKind,
     IncrementalMerkleTree,
     Note,
+    SpenderKey,
+    SpendingKey,
     TransferPrivateInputs,
     WithdrawPrivateInputs,
 };
-use sp1_sdk::{ include_elf, ProverClient, SP1Stdin };
+use shielded_pool_script::{
+    audit::{ AuditAction, AuditEntry, AuditLog, AuditNote },
+    chains::FinalityPolicy,
+    derive_viewing_keypair,
+    encrypt_note,
+    entropy,
+    key_cache,
+    keygen,
+    framed_stdin,
+    output_encryption,
+    parse_usdt,
+    proof_cache,
+    read_passphrase,
+    strict::{ self, StrictViolation },
+    wallet::{ wallet_path, WalletNote, WalletState },
+};
+use sp1_sdk::{ include_elf, ProverClient };
 
 pub const TRANSFER_ELF: &[u8] = include_elf!("transfer-program");
 pub const WITHDRAW_ELF: &[u8] = include_elf!("withdraw-program");
@@ -87,124 +122,172 @@ sol! {
 // Helpers
 // ---------------------------------------------------------------------------
 
-/// Parse a USDT amount string like "0.7" into u64 with 6 decimals (700000).
-fn parse_usdt(s: &str) -> Result<u64> {
-    let f: f64 = s.parse().context("invalid USDT amount")?;
-    Ok((f * 1_000_000.0).round() as u64)
-}
+/// Re-read events this many times when a reorg is detected during sync.
+const MAX_SYNC_ATTEMPTS: u32 = 3;
 
-/// Decode a 32-byte hex string (with or without 0x prefix) into [u8; 32].
-fn decode_hex_32(s: &str) -> Result<[u8; 32]> {
-    let s = s.strip_prefix("0x").unwrap_or(s);
-    let bytes = hex::decode(s).context("invalid hex")?;
-    ensure!(bytes.len() == 32, "expected 32 bytes, got {}", bytes.len());
-    let mut arr = [0u8; 32];
-    arr.copy_from_slice(&bytes);
-    Ok(arr)
+/// Commitments inserted by events in a block range, in tree order.
+struct Replay {
+    leaves: Vec<[u8; 32]>,
+    /// Logs the node flagged as removed by a reorg
+    removed_logs: usize,
 }
 
-/// Derive a viewing keypair from a spending key.
-/// Matches the TypeScript SDK: viewingSecret = keccak256("viewing" || spending_key)
-fn derive_viewing_keypair(spending_key: &[u8; 32]) -> (SecretKey, PublicKey) {
-    let mut preimage = [0u8; 7 + 32];
-    preimage[..7].copy_from_slice(b"viewing");
-    preimage[7..].copy_from_slice(spending_key);
-    let secret_bytes = keccak256(&preimage);
-    let secret = SecretKey::from(secret_bytes);
-    let public = secret.public_key();
-    (secret, public)
+struct Insertion {
+    block: u64,
+    log_index: u64,
+    commitments: Vec<[u8; 32]>,
 }
 
-/// Encrypt a note for a recipient's viewing public key.
-/// Format: ephemeral_pubkey(32) || nonce(24) || ciphertext
-/// Compatible with the TypeScript SDK's decryptNote().
-fn encrypt_note(note: &Note, recipient_viewing_pubkey: &PublicKey) -> Vec<u8> {
-    // Serialize note to JSON (same format as TS SDK)
-    let note_json = serde_json::json!({
-        "amount": note.amount.to_string(),
-        "pubkey": format!("0x{}", hex::encode(note.pubkey)),
-        "blinding": format!("0x{}", hex::encode(note.blinding)),
-    });
-    let plaintext = note_json.to_string().into_bytes();
-
-    // Generate ephemeral keypair
-    let ephemeral_secret = SecretKey::generate(&mut OsRng);
-    let ephemeral_public = ephemeral_secret.public_key();
-
-    // Create NaCl box and encrypt
-    let salsa_box = SalsaBox::new(recipient_viewing_pubkey, &ephemeral_secret);
-    let nonce = SalsaBox::generate_nonce(&mut OsRng);
-
-    use crypto_box::aead::Aead;
-    let ciphertext = salsa_box.encrypt(&nonce, &plaintext[..])
-        .expect("encryption should not fail");
-
-    // Pack: ephemeral_pubkey(32) || nonce(24) || ciphertext
-    let mut result = Vec::with_capacity(32 + 24 + ciphertext.len());
-    result.extend_from_slice(ephemeral_public.as_bytes());
-    result.extend_from_slice(&nonce);
-    result.extend_from_slice(&ciphertext);
-    result
-}
+/// Read every commitment insertion between `from_block` and `to_block`.
+async fn replay_events<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Replay> {
+    // We need to replay ALL commitment insertions in order:
+    //   - Deposit:         1 commitment  (from event)
+    //   - PrivateTransfer: 2 commitments (from event)
+    //   - Withdrawal:      0 or 1 commitment (change, from tx calldata)
+    // Collect them all, sort by (block, logIndex), insert in order.
 
-// ---------------------------------------------------------------------------
-// Wallet state — saved to disk so the exit script can withdraw
-// ---------------------------------------------------------------------------
+    let pool = IShieldedPool::new(pool_addr, provider);
+    let mut insertions: Vec<Insertion> = Vec::new();
+    let mut removed_logs = 0;
 
-#[derive(Serialize, Deserialize)]
-struct WalletNote {
-    /// Human label (e.g. "deposit_a", "transfer_change")
-    label: String,
-    /// Note amount (raw, 6 decimals)
-    amount: u64,
-    /// Hex-encoded 32-byte public key
-    pubkey: String,
-    /// Hex-encoded 32-byte blinding factor
-    blinding: String,
-    /// Hex-encoded 32-byte commitment
-    commitment: String,
-    /// Leaf index in the Merkle tree
-    leaf_index: u32,
-}
+    // 1. Deposits
+    let deposit_logs = pool.Deposit_filter().from_block(from_block).to_block(to_block).query().await?;
+    println!("    Deposits: {}", deposit_logs.len());
+    removed_logs += deposit_logs.iter().filter(|(_, log)| log.removed).count();
+    for (event, log) in &deposit_logs {
+        let (block, log_index) = strict::log_position(log)?;
+        insertions.push(Insertion {
+            block,
+            log_index,
+            commitments: vec![event.commitment.0],
+        });
+    }
 
-#[derive(Serialize, Deserialize)]
-struct WalletState {
-    /// Hex-encoded spending keys (sender, recipient)
-    spending_keys: Vec<WalletSpendingKey>,
-    /// All notes created during this session
-    notes: Vec<WalletNote>,
-}
+    // 2. Private transfers (2 commitments each)
+    let transfer_logs = pool.PrivateTransfer_filter().from_block(from_block).to_block(to_block).query().await?;
+    println!("    Transfers: {}", transfer_logs.len());
+    removed_logs += transfer_logs.iter().filter(|(_, log)| log.removed).count();
+    for (event, log) in &transfer_logs {
+        let (block, log_index) = strict::log_position(log)?;
+        insertions.push(Insertion {
+            block,
+            log_index,
+            commitments: vec![event.newCommitment1.0, event.newCommitment2.0],
+        });
+    }
 
-#[derive(Serialize, Deserialize)]
-struct WalletSpendingKey {
-    label: String,
-    /// Hex-encoded 32-byte spending key
-    spending_key: String,
-    /// Hex-encoded 32-byte derived shielded pubkey
-    pubkey: String,
-    /// Hex-encoded 32-byte viewing public key (x25519)
-    viewing_pubkey: String,
+    // 3. Withdrawals — decode changeCommitment from tx calldata
+    let withdrawal_logs = pool.Withdrawal_filter().from_block(from_block).to_block(to_block).query().await?;
+    println!("    Withdrawals: {}", withdrawal_logs.len());
+    removed_logs += withdrawal_logs.iter().filter(|(_, log)| log.removed).count();
+    for (_event, log) in &withdrawal_logs {
+        let (block, log_index) = strict::log_position(log)?;
+        if let Some(tx_hash) = log.transaction_hash {
+            let tx = provider.get_transaction_by_hash(tx_hash).await?;
+            if tx.is_none() {
+                strict::fallback(StrictViolation::UnreadableWithdrawal { tx_hash: tx_hash.to_string() })?;
+            }
+            if let Some(tx) = tx {
+                let input = tx.input();
+                // withdraw(bytes proof, bytes publicValues, bytes encryptedChange)
+                // Calldata layout: 4-byte selector + ABI-encoded (bytes, bytes, bytes)
+                // Word 0: offset to proof
+                // Word 1: offset to publicValues
+                // Word 2: offset to encryptedChange
+                // At each offset: first 32 bytes = length, then data
+                if input.len() > 4 + 32 * 3 {
+                    let data = &input[4..]; // skip selector
+                    // Read offset to publicValues (word 1, big-endian u256, but it's small)
+                    let pv_offset = u64::from_be_bytes(data[32 + 24..32 + 32].try_into().unwrap()) as usize;
+                    if pv_offset + 32 <= data.len() {
+                        // Read length of publicValues
+                        let pv_len = u64::from_be_bytes(data[pv_offset + 24..pv_offset + 32].try_into().unwrap()) as usize;
+                        let pv_start = pv_offset + 32;
+                        if pv_len >= 160 && pv_start + 160 <= data.len() {
+                            let mut change_comm = [0u8; 32];
+                            change_comm.copy_from_slice(&data[pv_start + 128..pv_start + 160]);
+                            if change_comm != [0u8; 32] {
+                                insertions.push(Insertion {
+                                    block,
+                                    log_index,
+                                    commitments: vec![change_comm],
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Sort by block number, then log index
+    insertions.sort_by_key(|i| (i.block, i.log_index));
+
+    let total_commitments: usize = insertions.iter().map(|i| i.commitments.len()).sum();
+    println!("    Total commitments to insert: {total_commitments}");
+
+    Ok(Replay {
+        leaves: insertions.into_iter().flat_map(|i| i.commitments).collect(),
+        removed_logs,
+    })
 }
 
-fn encode_note(label: &str, note: &Note, leaf_index: u32) -> WalletNote {
-    WalletNote {
-        label: label.to_string(),
-        amount: note.amount,
-        pubkey: hex::encode(note.pubkey),
-        blinding: hex::encode(note.blinding),
-        commitment: hex::encode(note.commitment()),
-        leaf_index,
+/// Roll `tree` back to `base` and insert every commitment since `from_block`,
+/// then check the root against the pool's root at the block read up to.
+///
+/// If the chain reorgs while we read it (logs come back marked removed, or
+/// the roots disagree), the tree is rolled back again and the events re-read.
+async fn sync_tree<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    tree: &mut IncrementalMerkleTree,
+    base: CheckpointId,
+) -> Result<[u8; 32]> {
+    let pool = IShieldedPool::new(pool_addr, provider);
+    let mut attempt = 1;
+    loop {
+        tree.rollback(base).map_err(anyhow::Error::msg)?;
+        let sync_block = provider.get_block_number().await?;
+        let replay = replay_events(provider, pool_addr, from_block, sync_block).await?;
+        for leaf in &replay.leaves {
+            tree.try_insert(*leaf)
+                .map_err(anyhow::Error::msg)
+                .context("pool has more leaves than TREE_LEVELS allows")?;
+        }
+
+        // Verify root matches on-chain
+        let on_chain_root: FixedBytes<32> = pool.getLastRoot().block(BlockId::number(sync_block)).call().await?;
+        let on_chain_leaves: u32 = pool.getLeafCount().block(BlockId::number(sync_block)).call().await?;
+        let audit = tree.audit(on_chain_root.0, on_chain_leaves);
+        if replay.removed_logs == 0 && audit.is_in_sync() {
+            return Ok(tree.get_root());
+        }
+        ensure!(
+            attempt < MAX_SYNC_ATTEMPTS,
+            "Tree does not match the pool: {} ({} removed logs)",
+            audit,
+            replay.removed_logs
+        );
+        let why = if replay.removed_logs > 0 { format!("{} log(s) were removed", replay.removed_logs) } else { audit.to_string() };
+        println!("    ⚠ {why} at block {sync_block} (possible reorg); rolling back and re-reading events");
+        attempt += 1;
     }
 }
 
-fn save_wallet(state: &WalletState) -> Result<()> {
-    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("fixtures");
-    std::fs::create_dir_all(&dir)?;
-    let path = dir.join("wallet.json");
-    let json = serde_json::to_string_pretty(state)?;
-    std::fs::write(&path, &json)?;
-    println!("    Wallet state saved to {}", path.display());
-    Ok(())
+/// Decode a 32-byte hex string (with or without 0x prefix) into [u8; 32].
+fn decode_hex_32(s: &str) -> Result<[u8; 32]> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let bytes = hex::decode(s).context("invalid hex")?;
+    ensure!(bytes.len() == 32, "expected 32 bytes, got {}", bytes.len());
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(&bytes);
+    Ok(arr)
 }
 
 // ---------------------------------------------------------------------------
@@ -213,7 +296,7 @@ fn save_wallet(state: &WalletState) -> Result<()> {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    dotenv::dotenv().ok();
+    shielded_pool_script::config::load()?;
     sp1_sdk::utils::setup_logger();
 
     // ── Step 0: Load config ────────────────────────────────────────────
@@ -248,8 +331,12 @@ async fn main() -> Result<()> {
     let withdraw_amount = parse_usdt(
         &std::env::var("WITHDRAW_AMOUNT").unwrap_or_else(|_| "0.3".to_string())
     )?;
+    let cancellable_window: Option<u64> = std::env::var("CANCELLABLE_WINDOW")
+        .ok()
+        .map(|s| s.parse())
+        .transpose()
+        .context("CANCELLABLE_WINDOW must be a number of seconds")?;
     let total_deposit = deposit_a + deposit_b;
-    let change_from_transfer = total_deposit - transfer_amount;
     let change_from_withdraw = transfer_amount - withdraw_amount;
 
     ensure!(
@@ -268,7 +355,11 @@ async fn main() -> Result<()> {
     println!("Deposit A:        {} USDT", (deposit_a as f64) / 1e6);
     println!("Deposit B:        {} USDT", (deposit_b as f64) / 1e6);
     println!("Transfer amount:  {} USDT", (transfer_amount as f64) / 1e6);
-    println!("Withdraw amount:  {} USDT\n", (withdraw_amount as f64) / 1e6);
+    println!("Withdraw amount:  {} USDT", (withdraw_amount as f64) / 1e6);
+    if let Some(window) = cancellable_window {
+        println!("Cancellable:      sender may reclaim for {window}s");
+    }
+    println!();
 
     // ── Step 1: Connect ────────────────────────────────────────────────
     let signer: PrivateKeySigner = private_key.parse()?;
@@ -279,13 +370,32 @@ async fn main() -> Result<()> {
 
     let token = IERC20::new(token_addr, &provider);
     let pool = IShieldedPool::new(pool_addr, &provider);
+    let chain_id = provider.get_chain_id().await?;
+    let finality = FinalityPolicy::for_chain(chain_id)?;
+    // Proofs commit this, so the pool rejects them anywhere else
+    let binding = pool_binding(chain_id, &pool_addr.0.0);
+    provider.client().set_poll_interval(finality.poll_interval());
+    let audit_entry = |action, receipt: &TransactionReceipt| -> Result<AuditEntry> {
+        let block = strict::block_number(receipt.block_number, || {
+            format!("receipt for tx {}", receipt.transaction_hash)
+        })?;
+        Ok(AuditEntry::new(
+            action,
+            "e2e",
+            chain_id,
+            &pool_addr.to_string(),
+            &receipt.transaction_hash.to_string(),
+            block
+        ))
+    };
 
     // ── Step 2: Generate spending keys + viewing keys ──────────────────
-    let mut rng = rand::thread_rng();
-    let spending_key: [u8; 32] = rng.gen();
+    println!("[2] Entropy:          {}", entropy::init_from_env()?.describe());
+    let mut rng = entropy::rng();
+    let spending_key = *keygen::spending_key_from_mnemonic(&keygen::generate_mnemonic()?).as_bytes();
     let pubkey = derive_pubkey(&spending_key);
     let (_sender_viewing_secret, sender_viewing_pubkey) = derive_viewing_keypair(&spending_key);
-    println!("[2] Sender pubkey:    0x{}", hex::encode(pubkey));
+    println!("    Sender pubkey:    0x{}", hex::encode(pubkey));
     println!("    Sender viewing:   0x{}", hex::encode(sender_viewing_pubkey.as_bytes()));
 
     // Recipient: from env or random
@@ -298,7 +408,7 @@ async fn main() -> Result<()> {
         println!("    Recipient key:    0x{} (from env)", hex::encode(pubkey));
         (pk, pubkey)
     } else {
-        let sk: [u8; 32] = rng.gen();
+        let sk = *keygen::spending_key_from_mnemonic(&keygen::generate_mnemonic()?).as_bytes();
         let pk = derive_pubkey(&sk);
         println!("    Recipient key:    0x{} (random)", hex::encode(pk));
         (sk, pk)
@@ -317,23 +427,15 @@ async fn main() -> Result<()> {
     };
 
     // ── Wallet state — track all notes for the exit script ────────────
-    let mut wallet = WalletState {
-        spending_keys: vec![
-            WalletSpendingKey {
-                label: "sender".into(),
-                spending_key: hex::encode(spending_key),
-                pubkey: hex::encode(pubkey),
-                viewing_pubkey: hex::encode(sender_viewing_pubkey.as_bytes()),
-            },
-            WalletSpendingKey {
-                label: "recipient".into(),
-                spending_key: hex::encode(recipient_spending_key),
-                pubkey: hex::encode(recipient_pubkey),
-                viewing_pubkey: hex::encode(recipient_viewing_pubkey.as_bytes()),
-            },
-        ],
-        notes: Vec::new(),
-    };
+    // Spending keys are stored encrypted; ask for the passphrase now rather
+    // than after minutes of proving.
+    let passphrase = read_passphrase("Wallet passphrase (encrypts the wallet's keys): ", true)?;
+    let sender_entry = keygen::wallet_key("sender", &SpendingKey::from(spending_key), &passphrase)?;
+    let mut recipient_entry = keygen::wallet_key("recipient", &SpendingKey::from(recipient_spending_key), &passphrase)?;
+    // RECIPIENT_VIEWING_PUBKEY may name a viewing key the spending key does not derive
+    recipient_entry.viewing_pubkey = hex::encode(recipient_viewing_pubkey.as_bytes());
+
+    let mut wallet = WalletState { spending_keys: vec![sender_entry, recipient_entry], ..WalletState::default() };
 
     // ── Step 3: Create notes ───────────────────────────────────────────
     let note_a = Note {
@@ -356,9 +458,19 @@ async fn main() -> Result<()> {
     );
 
     // ── Step 4: Deposit ────────────────────────────────────────────────
+    // Refuse up front if the run's leaves would not fit: two deposits, two
+    // transfer outputs and the withdrawal's change
+    let leaf_count: u32 = pool.getLeafCount().call().await?;
+    let needed = 4 + u64::from(change_from_withdraw > 0);
+    let remaining = tree_capacity(tree_levels).saturating_sub(leaf_count as u64);
+    ensure!(
+        remaining >= needed,
+        "Merkle tree has room for {remaining} more leaves, this run needs {needed}; not depositing"
+    );
+
     println!("[4] Approving token spend...");
     let tx = token.approve(pool_addr, U256::from(total_deposit)).send().await?;
-    let receipt = tx.get_receipt().await?;
+    let receipt = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
     println!("    Approve tx: {}", receipt.transaction_hash);
 
     println!("    Depositing {} USDT...", (deposit_a as f64) / 1e6);
@@ -366,140 +478,87 @@ async fn main() -> Result<()> {
     let tx = pool
         .deposit(FixedBytes::from(comm_a), U256::from(deposit_a), Bytes::from(enc_a))
         .send().await?;
-    let receipt = tx.get_receipt().await?;
-    println!("    Deposit A tx: {}", receipt.transaction_hash);
+    let receipt_a = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
+    println!("    Deposit A tx: {}", receipt_a.transaction_hash);
 
     println!("    Depositing {} USDT...", (deposit_b as f64) / 1e6);
     let enc_b = encrypt_note(&note_b, &sender_viewing_pubkey);
     let tx = pool
         .deposit(FixedBytes::from(comm_b), U256::from(deposit_b), Bytes::from(enc_b))
         .send().await?;
-    let receipt = tx.get_receipt().await?;
-    println!("    Deposit B tx: {}", receipt.transaction_hash);
+    let receipt_b = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
+    println!("    Deposit B tx: {}", receipt_b.transaction_hash);
 
     // ── Step 5: Mirror Merkle tree ─────────────────────────────────────
     println!("[5] Building local Merkle tree from all on-chain events...");
     let mut tree = IncrementalMerkleTree::new(tree_levels);
-
-    // We need to replay ALL commitment insertions in order:
-    //   - Deposit:         1 commitment  (from event)
-    //   - PrivateTransfer: 2 commitments (from event)
-    //   - Withdrawal:      0 or 1 commitment (change, from tx calldata)
-    // Collect them all, sort by (block, logIndex), insert in order.
-
-    struct Insertion {
-        block: u64,
-        log_index: u64,
-        commitments: Vec<[u8; 32]>,
-    }
-
-    let mut insertions: Vec<Insertion> = Vec::new();
-
-    // 1. Deposits
-    let deposit_logs = pool.Deposit_filter().from_block(deploy_block).query().await?;
-    println!("    Deposits: {}", deposit_logs.len());
-    for (event, log) in &deposit_logs {
-        insertions.push(Insertion {
-            block: log.block_number.unwrap_or(0),
-            log_index: log.log_index.unwrap_or(0),
-            commitments: vec![event.commitment.0],
-        });
-    }
-
-    // 2. Private transfers (2 commitments each)
-    let transfer_logs = pool.PrivateTransfer_filter().from_block(deploy_block).query().await?;
-    println!("    Transfers: {}", transfer_logs.len());
-    for (event, log) in &transfer_logs {
-        insertions.push(Insertion {
-            block: log.block_number.unwrap_or(0),
-            log_index: log.log_index.unwrap_or(0),
-            commitments: vec![event.newCommitment1.0, event.newCommitment2.0],
-        });
-    }
-
-    // 3. Withdrawals — decode changeCommitment from tx calldata
-    let withdrawal_logs = pool.Withdrawal_filter().from_block(deploy_block).query().await?;
-    println!("    Withdrawals: {}", withdrawal_logs.len());
-    for (_event, log) in &withdrawal_logs {
-        if let Some(tx_hash) = log.transaction_hash {
-            if let Some(tx) = provider.get_transaction_by_hash(tx_hash).await? {
-                let input = tx.input();
-                // withdraw(bytes proof, bytes publicValues, bytes encryptedChange)
-                // Calldata layout: 4-byte selector + ABI-encoded (bytes, bytes, bytes)
-                // Word 0: offset to proof
-                // Word 1: offset to publicValues
-                // Word 2: offset to encryptedChange
-                // At each offset: first 32 bytes = length, then data
-                if input.len() > 4 + 32 * 3 {
-                    let data = &input[4..]; // skip selector
-                    // Read offset to publicValues (word 1, big-endian u256, but it's small)
-                    let pv_offset = u64::from_be_bytes(data[32 + 24..32 + 32].try_into().unwrap()) as usize;
-                    if pv_offset + 32 <= data.len() {
-                        // Read length of publicValues
-                        let pv_len = u64::from_be_bytes(data[pv_offset + 24..pv_offset + 32].try_into().unwrap()) as usize;
-                        let pv_start = pv_offset + 32;
-                        if pv_len >= 160 && pv_start + 160 <= data.len() {
-                            let mut change_comm = [0u8; 32];
-                            change_comm.copy_from_slice(&data[pv_start + 128..pv_start + 160]);
-                            if change_comm != [0u8; 32] {
-                                insertions.push(Insertion {
-                                    block: log.block_number.unwrap_or(0),
-                                    log_index: log.log_index.unwrap_or(0),
-                                    commitments: vec![change_comm],
-                                });
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    // Sort by block number, then log index
-    insertions.sort_by_key(|i| (i.block, i.log_index));
-
-    let total_commitments: usize = insertions.iter().map(|i| i.commitments.len()).sum();
-    println!("    Total commitments to insert: {total_commitments}");
-
-    for ins in &insertions {
-        for comm in &ins.commitments {
-            tree.insert(*comm);
-        }
-    }
-
-    // Verify root matches on-chain
-    let on_chain_root: FixedBytes<32> = pool.getLastRoot().call().await?;
-    let local_root = tree.get_root();
-    ensure!(
-        FixedBytes::from(local_root) == on_chain_root,
-        "Root mismatch! local={} on-chain={}",
-        hex::encode(local_root),
-        on_chain_root
-    );
+    let empty = tree.checkpoint();
+    let local_root = sync_tree(&provider, pool_addr, deploy_block, &mut tree, empty).await?;
     println!("    Root verified: 0x{}...", hex::encode(&local_root[..8]));
 
-    // Our leaves are the last two inserted
-    let leaf_count = tree.leaves.len();
-    let leaf_a_idx = (leaf_count - 2) as u32;
-    let leaf_b_idx = (leaf_count - 1) as u32;
+    // Other deposits may have landed between ours, so look our leaves up
+    let leaf_a_idx = tree.index_of(&comm_a).context("Deposit A not found in the synced tree")?;
+    let leaf_b_idx = tree.index_of(&comm_b).context("Deposit B not found in the synced tree")?;
     println!("    Our leaves: A={leaf_a_idx}, B={leaf_b_idx}");
 
     // Track deposit notes in wallet
-    wallet.notes.push(encode_note("deposit_a", &note_a, leaf_a_idx));
-    wallet.notes.push(encode_note("deposit_b", &note_b, leaf_b_idx));
+    wallet.notes.push(WalletNote::new("deposit_a", &note_a, leaf_a_idx));
+    wallet.notes.push(WalletNote::new("deposit_b", &note_b, leaf_b_idx));
+    for (receipt, i) in [(&receipt_a, 0), (&receipt_b, 1)] {
+        let note = &wallet.notes[i];
+        let entry = audit_entry(AuditAction::Deposit, receipt)?
+            .with_created(AuditNote::from_wallet(note))
+            .with_public_amount(note.amount);
+        wallet.record_tx(&entry);
+        AuditLog::record(entry)?;
+    }
 
     // ── Step 6: Build transfer inputs ──────────────────────────────────
     println!("[6] Building transfer inputs...");
+
+    // Let the coin-selection planner pick inputs from our deposited notes
+    let candidates = [(note_a.clone(), leaf_a_idx), (note_b.clone(), leaf_b_idx)];
+    let candidate_notes: Vec<Note> = candidates.iter().map(|(n, _)| n.clone()).collect();
+    // e2e submits plain transfers, so the change is not split
+    // (ChangeSplit::default); the summary still shows the trade-off
+    let plan = plan_transfer_split(&candidate_notes, transfer_amount, SelectionConfig::default(), &ChangeSplit::default())
+        .map_err(|e| anyhow::anyhow!("coin selection failed: {e}"))?;
+    ensure!(plan.is_single_hop(), "transfer needs {} hops; e2e only runs single-hop plans", plan.hops());
+    for line in plan.to_string().lines() {
+        println!("    {line}");
+    }
+    let (plan_inputs, _, change_from_transfer) = plan.payment();
+    let [(in_note_0, in_leaf_0), (in_note_1, in_leaf_1)] = plan_inputs.map(|r| match r {
+        NoteRef::Wallet(i) => candidates[i].clone(),
+        // Two candidates always fill both slots, so no dummy is planned
+        NoteRef::Merged(_) | NoteRef::Dummy => unreachable!("single-hop plans only reference wallet notes"),
+    });
+
     println!(
-        "    {} USDT → recipient, {} USDT → change",
+        "    {} USDT → recipient, {} USDT → change (inputs: leaves {in_leaf_0}, {in_leaf_1})",
         (transfer_amount as f64) / 1e6,
         (change_from_transfer as f64) / 1e6
     );
 
-    let output_note_0 = Note {
-        amount: transfer_amount,
-        pubkey: recipient_pubkey,
-        blinding: rng.gen(),
+    // Optionally lock the payment so the sender can reclaim it for a while
+    let recipient_lock = match cancellable_window {
+        Some(window) => {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+            Some(CancellableLock {
+                recipient_pubkey,
+                sender_pubkey: pubkey,
+                reclaim_deadline: now + window,
+            })
+        }
+        None => None,
+    };
+    let output_note_0 = match &recipient_lock {
+        Some(lock) => lock.note(transfer_amount, rng.gen()),
+        None => Note {
+            amount: transfer_amount,
+            pubkey: recipient_pubkey,
+            blinding: rng.gen(),
+        },
     };
     let output_note_1 = Note {
         amount: change_from_transfer,
@@ -508,26 +567,36 @@ async fn main() -> Result<()> {
     };
 
     let root = tree.get_root();
-    let proof_a = tree.get_proof(leaf_a_idx);
-    let proof_b = tree.get_proof(leaf_b_idx);
+    let proof_0 = tree.try_get_proof(in_leaf_0).map_err(anyhow::Error::msg)?;
+    let proof_1 = tree.try_get_proof(in_leaf_1).map_err(anyhow::Error::msg)?;
+
+    // Output 0 is for the recipient, output 1 (change) for the sender; the
+    // proof commits their payloads, so the pool only takes these
+    let encryptions = [output_encryption(&recipient_viewing_pubkey)?, output_encryption(&sender_viewing_pubkey)?];
 
-    let transfer_inputs = TransferPrivateInputs {
-        input_notes: [note_a.clone(), note_b.clone()],
-        spending_keys: [spending_key, spending_key],
-        merkle_proofs: [proof_a, proof_b],
+    let mut transfer_inputs = TransferPrivateInputs {
+        input_notes: [in_note_0, in_note_1],
+        spender_keys: [SpenderKey::derive(&spending_key); 2],
+        merkle_proofs: [proof_0, proof_1],
         output_notes: [output_note_0.clone(), output_note_1.clone()],
         root,
+        locks: [None, None],
+        association: None,
+        pool_binding: binding,
+        output_encryption: Some(encryptions.clone()),
+        multisig: [None, None],
+        spend_signatures: Vec::new(),
     };
+    transfer_inputs.sign(&spending_key).map_err(anyhow::Error::msg)?;
 
     // ── Step 7: Generate transfer proof ────────────────────────────────
     println!("[7] Generating transfer Groth16 proof (this may take a few minutes)...");
     let sp1_client = ProverClient::from_env();
 
-    let mut stdin = SP1Stdin::new();
-    stdin.write(&transfer_inputs);
+    let stdin = framed_stdin(CircuitKind::Transfer, &transfer_inputs);
 
-    let (pk, vk) = sp1_client.setup(TRANSFER_ELF);
-    let transfer_proof = sp1_client.prove(&pk, &stdin).groth16().run()?;
+    let (pk, vk) = key_cache::setup(&sp1_client, TRANSFER_ELF);
+    let transfer_proof = proof_cache::prove(&sp1_client, &pk, TRANSFER_ELF, &stdin)?;
     // sp1_client.verify(&transfer_proof, &vk)?;
     // println!("    Transfer proof verified locally");
 
@@ -541,35 +610,60 @@ async fn main() -> Result<()> {
 
     // ── Step 8: Submit transfer ────────────────────────────────────────
     println!("[8] Submitting private transfer on-chain...");
-    // Encrypt output notes: output_note_0 for recipient, output_note_1 (change) for sender
-    let enc_out0 = encrypt_note(&output_note_0, &recipient_viewing_pubkey);
-    let enc_out1 = encrypt_note(&output_note_1, &sender_viewing_pubkey);
+    // The encrypted outputs the proof committed
+    let enc_out0 = encryptions[0].encrypt(&output_note_0);
+    let enc_out1 = encryptions[1].encrypt(&output_note_1);
     let tx = pool
         .privateTransfer(
-            Bytes::from(transfer_proof_bytes),
-            Bytes::from(transfer_public_values),
+            Bytes::from(transfer_proof_bytes.clone()),
+            Bytes::from(transfer_public_values.clone()),
             Bytes::from(enc_out0),
             Bytes::from(enc_out1)
         )
         .send().await?;
-    let receipt = tx.get_receipt().await?;
+    let receipt = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
     println!("    Transfer tx: {}", receipt.transaction_hash);
 
     // Update local tree with output commitments
     let out_comm_0 = output_note_0.commitment();
     let out_comm_1 = output_note_1.commitment();
-    let out_leaf_0 = tree.insert(out_comm_0);
-    let out_leaf_1 = tree.insert(out_comm_1);
-    println!("    Output leaves: {out_leaf_0}, {out_leaf_1}");
+    let mut out_leaf_0 = tree.try_insert(out_comm_0).map_err(anyhow::Error::msg)?;
+    let mut out_leaf_1 = tree.try_insert(out_comm_1).map_err(anyhow::Error::msg)?;
 
-    // Track transfer output notes
-    wallet.notes.push(encode_note("transfer_to_recipient", &output_note_0, out_leaf_0));
-    wallet.notes.push(encode_note("transfer_change", &output_note_1, out_leaf_1));
-
-    // Verify root still matches
+    // Verify root still matches; if not, the chain moved under us: rebuild
+    // the tree from events and look our outputs up again
     let on_chain_root: FixedBytes<32> = pool.getLastRoot().call().await?;
-    ensure!(FixedBytes::from(tree.get_root()) == on_chain_root, "Root mismatch after transfer!");
+    let on_chain_leaves: u32 = pool.getLeafCount().call().await?;
+    let audit = tree.audit(on_chain_root.0, on_chain_leaves);
+    if !audit.is_in_sync() {
+        println!("    ⚠ After transfer, {audit} (possible reorg); re-syncing from events");
+        sync_tree(&provider, pool_addr, deploy_block, &mut tree, empty).await?;
+        (out_leaf_0, out_leaf_1) = tree
+            .index_of(&out_comm_0)
+            .zip(tree.index_of(&out_comm_1))
+            .context("Transfer outputs not found in the tree after re-sync")?;
+    }
     println!("    Root verified after transfer");
+    println!("    Output leaves: {out_leaf_0}, {out_leaf_1}");
+
+    // Track transfer output notes
+    let mut recipient_note = WalletNote::new("transfer_to_recipient", &output_note_0, out_leaf_0);
+    if let Some(lock) = &recipient_lock {
+        recipient_note = recipient_note.with_lock(lock);
+    }
+    wallet.notes.push(recipient_note);
+    wallet.push_change("transfer_change", &out_comm_0, &[(output_note_1.clone(), out_leaf_1)])?;
+    let deposit_label = |leaf| if leaf == leaf_a_idx { "deposit_a" } else { "deposit_b" };
+    let [in_note_0, in_note_1] = &transfer_inputs.input_notes;
+    let transfer_entry = audit_entry(AuditAction::Transfer, &receipt)?
+        .with_spent(AuditNote::new(deposit_label(in_leaf_0), in_note_0, Some(in_leaf_0)))
+        .with_spent(AuditNote::new(deposit_label(in_leaf_1), in_note_1, Some(in_leaf_1)))
+        .with_created(AuditNote::new("transfer_to_recipient", &output_note_0, Some(out_leaf_0)))
+        .with_created(AuditNote::new("transfer_change", &output_note_1, Some(out_leaf_1)))
+        .with_proof(&transfer_proof_bytes, &transfer_public_values)
+        .with_request(transfer_inputs.request_hash());
+    wallet.record_tx(&transfer_entry);
+    AuditLog::record(transfer_entry)?;
 
     // ── Step 9: Build withdraw inputs ──────────────────────────────────
     println!("[9] Building withdraw inputs...");
@@ -590,27 +684,37 @@ async fn main() -> Result<()> {
     };
 
     let root = tree.get_root();
-    let proof_out0 = tree.get_proof(out_leaf_0);
+    let proof_out0 = tree.try_get_proof(out_leaf_0).map_err(anyhow::Error::msg)?;
     let recipient_address: [u8; 20] = wallet_address.0.0;
 
-    let withdraw_inputs = WithdrawPrivateInputs {
+    let mut withdraw_inputs = WithdrawPrivateInputs {
         input_note: output_note_0.clone(),
-        spending_key: recipient_spending_key,
+        spender_key: SpenderKey::derive(&recipient_spending_key),
         merkle_proof: proof_out0,
         root,
         recipient: recipient_address,
         withdraw_amount,
         change_note: change_note.clone(),
+        lock: recipient_lock,
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: binding,
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: None,
+        spend_signatures: Vec::new(),
     };
+    withdraw_inputs.sign(&recipient_spending_key).map_err(anyhow::Error::msg)?;
 
     // ── Step 10: Generate withdraw proof ───────────────────────────────
     println!("[10] Generating withdraw Groth16 proof...");
 
-    let mut stdin = SP1Stdin::new();
-    stdin.write(&withdraw_inputs);
+    let stdin = framed_stdin(CircuitKind::Withdraw, &withdraw_inputs);
 
-    let (pk, vk) = sp1_client.setup(WITHDRAW_ELF);
-    let withdraw_proof = sp1_client.prove(&pk, &stdin).groth16().run()?;
+    let (pk, vk) = key_cache::setup(&sp1_client, WITHDRAW_ELF);
+    let withdraw_proof = proof_cache::prove(&sp1_client, &pk, WITHDRAW_ELF, &stdin)?;
     // sp1_client.verify(&withdraw_proof, &vk)?;
     // println!("     Withdraw proof verified locally");
 
@@ -627,22 +731,32 @@ async fn main() -> Result<()> {
     };
     let tx = pool
         .withdraw(
-            Bytes::from(withdraw_proof_bytes),
-            Bytes::from(withdraw_public_values),
+            Bytes::from(withdraw_proof_bytes.clone()),
+            Bytes::from(withdraw_public_values.clone()),
             enc_change
         )
         .send().await?;
-    let receipt = tx.get_receipt().await?;
+    let receipt = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
     println!("     Withdraw tx: {}", receipt.transaction_hash);
 
     // Update local tree with change commitment
+    let mut withdraw_entry = audit_entry(AuditAction::Withdraw, &receipt)?
+        .with_spent(AuditNote::new("transfer_to_recipient", &output_note_0, Some(out_leaf_0)))
+        .with_public_amount(withdraw_amount)
+        .with_proof(&withdraw_proof_bytes, &withdraw_public_values)
+        .with_request(withdraw_inputs.request_hash());
     if let Some(ref cn) = change_note {
         let change_leaf = tree.insert(cn.commitment());
-        wallet.notes.push(encode_note("withdraw_change", cn, change_leaf));
+        wallet.notes.push(WalletNote::new("withdraw_change", cn, change_leaf));
+        withdraw_entry = withdraw_entry.with_created(AuditNote::new("withdraw_change", cn, Some(change_leaf)));
     }
+    wallet.record_tx(&withdraw_entry);
+    AuditLog::record(withdraw_entry)?;
 
     // ── Save wallet state ──────────────────────────────────────────────
-    save_wallet(&wallet)?;
+    let wallet_file = wallet_path();
+    wallet.save(&wallet_file)?;
+    println!("    Wallet state saved to {}", wallet_file.display());
 
     // ── Step 12: Verify final state ────────────────────────────────────
     println!("\n[12] Verifying final state...");
@@ -657,7 +771,9 @@ async fn main() -> Result<()> {
     println!("     Transfer nullifiers spent: OK");
 
     // Check withdraw nullifier is spent
-    let withdraw_null = compute_nullifier(&out_comm_0, &recipient_spending_key);
+    let withdraw_null = authorize_spend(&output_note_0, &recipient_spending_key, recipient_lock.as_ref())
+        .map_err(anyhow::Error::msg)?
+        .nullifier;
     let spent_w: bool = pool.isSpent(FixedBytes::from(withdraw_null)).call().await?;
     ensure!(spent_w, "Withdraw nullifier not spent!");
     println!("     Withdraw nullifier spent: OK");
@@ -1,11 +1,20 @@
 //! Exit script: withdraw ALL unspent notes from the shielded pool.
 //!
-//! Reads wallet state from fixtures/wallet.json (created by the e2e script),
+//! Reads wallet state from fixtures/wallet.db (created by the e2e script),
 //! checks which notes are still unspent on-chain, and withdraws each one
-//! to the caller's wallet address.
+//! to the caller's wallet address. The withdrawal proofs are generated
+//! concurrently (`shielded_pool_script::proving`), then the transactions are
+//! sent one at a time.
+//!
+//! If the replayed tree does not match the pool (e.g. the RPC truncated log
+//! history), only notes inside the prefix verified against a known on-chain
+//! root are withdrawn; the rest are recorded as skipped in the exit report.
+//! A reorg during sync (removed logs, or a replayed root that disagrees with
+//! the pool at the sync block) rolls the tree back and re-reads the events.
+//! With `--strict` any such skip or partial sync aborts the run instead.
 //!
 //! Usage:
-//!   SP1_PROVER=network cargo run --release -p shielded-pool-script --bin exit
+//!   SP1_PROVER=network cargo run --release -p shielded-pool-script --bin exit [-- --strict]
 //!
 //! Required env vars (from .env):
 //!   RPC_URL               — Plasma RPC endpoint
@@ -17,22 +26,60 @@
 //! Optional env vars:
 //!   DEPLOY_BLOCK          — Block the ShieldedPool was deployed at (default: 0)
 //!   TREE_LEVELS           — Merkle tree depth (default: 20)
-//!   WALLET_FILE           — Path to wallet.json (default: fixtures/wallet.json)
+//!   WALLET_FILE           — Path to the wallet (default: fixtures/wallet.db)
 //!   RECIPIENT_ADDRESS     — Override withdrawal address (default: PRIVATE_KEY's address)
+//!   WALLET_PASSPHRASE     — Passphrase for encrypted wallet keys (prompted if not set)
+//!   EXIT_REPORT           — Where to write the proof-of-exit bundle
+//!                           (default: fixtures/exit-report.json)
+//!   EXIT_STATE            — Progress of an unfinished run: proofs and txs not yet
+//!                           confirmed (default: exit-state.json next to EXIT_REPORT).
+//!                           Saved on every step and on Ctrl-C; the next run resumes
+//!                           from it and deletes it once the report is written
+//!   TREE_CHECKPOINT       — Leaf list from an earlier fully synced run
+//!                           (written to fixtures/tree-checkpoint.json); only newer
+//!                           events are replayed on top of it
+//!   TREE_STORE            — Directory of the persistent Merkle tree (default:
+//!                           fixtures/tree-store/<chain_id>-<pool>); each fully
+//!                           verified sync is saved there and later runs replay
+//!                           only newer events
+//!   FEE_BUMP_PERCENT      — When a withdraw tx from an interrupted run is still
+//!                           pending, resend it with the same proof and fees raised
+//!                           by this much, if its root is still known (default: 25;
+//!                           0 to skip the note until the tx is mined or dropped)
+//!   MULTICALL_ADDRESS     — Multicall3 used to batch isSpent checks ("none" to disable)
+//!   MULTICALL_CHUNK_SIZE  — Calls per multicall batch (default: 200)
+//!   STRICT                — 1 to fail on any fallback, as with --strict (default: on when CI is set)
+//!   PROVER_JOBS           — Proofs generated at once (default: 4 with SP1_PROVER=network, else 1)
+//!   PROOF_CACHE_DIR       — Where proofs are cached, so a re-run reuses them (default:
+//!                           ~/.cache/shielded-pool/proofs; off disables it)
 
 use alloy::{
-    consensus::Transaction as _,
-    primitives::{Address, Bytes, FixedBytes, U256},
+    eips::{BlockId, BlockNumberOrTag},
+    primitives::{Address, Bytes, TxHash, U256},
     providers::{Provider, ProviderBuilder},
     signers::local::PrivateKeySigner,
     sol,
 };
 use anyhow::{ensure, Context, Result};
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use shielded_pool_lib::{
-    compute_nullifier, IncrementalMerkleTree, Note, WithdrawPrivateInputs,
+    cancellable::SpendPath, pool_binding, CancellableLock, CircuitKind, Note, SpenderKey, WithdrawPrivateInputs,
+};
+use shielded_pool_script::{
+    audit::{AuditAction, AuditEntry, AuditLog, AuditNote},
+    decode_hex,
+    exit_report::{ExitReport, ExitState, ExitWithdrawal, SkippedNote, EXIT_REPORT_VERSION},
+    framed_stdin,
+    interrupt::Journal,
+    key_cache,
+    multicall::{BatchConfig, PoolViews},
+    proving::{prove_all, ProofJob},
+    resubmit::{fee_bump_from_env, replace_with_higher_fees},
+    strict::{self, StrictViolation},
+    sync::{replay_nullifiers, sync_pool_tree, PoolSync, TreeSource},
+    wallet::{wallet_path, NoteMetadata, WalletState},
 };
-use sp1_sdk::{include_elf, ProverClient, SP1Stdin};
+use sp1_sdk::{include_elf, ProverClient};
 
 pub const WITHDRAW_ELF: &[u8] = include_elf!("withdraw-program");
 
@@ -49,46 +96,11 @@ sol! {
     #[sol(rpc)]
     interface IShieldedPool {
         function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
-        function getLastRoot() external view returns (bytes32);
-        function getLeafCount() external view returns (uint32);
         function isKnownRoot(bytes32 root) external view returns (bool);
         function isSpent(bytes32 nullifier) external view returns (bool);
-
-        event Deposit(bytes32 indexed commitment, uint256 amount, uint32 leafIndex, uint256 timestamp);
-        event PrivateTransfer(bytes32 indexed nullifier1, bytes32 indexed nullifier2, bytes32 newCommitment1, bytes32 newCommitment2, uint256 timestamp);
-        event Withdrawal(bytes32 indexed nullifier, address indexed recipient, uint256 amount, uint256 timestamp);
     }
 }
 
-// ---------------------------------------------------------------------------
-// Wallet state types (must match e2e.rs)
-// ---------------------------------------------------------------------------
-
-#[derive(Serialize, Deserialize)]
-struct WalletNote {
-    label: String,
-    amount: u64,
-    pubkey: String,
-    blinding: String,
-    commitment: String,
-    leaf_index: u32,
-}
-
-#[derive(Serialize, Deserialize)]
-struct WalletState {
-    spending_keys: Vec<WalletSpendingKey>,
-    notes: Vec<WalletNote>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct WalletSpendingKey {
-    label: String,
-    spending_key: String,
-    pubkey: String,
-    #[serde(default)]
-    viewing_pubkey: String,
-}
-
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
@@ -102,30 +114,13 @@ fn decode_hex_32(s: &str) -> Result<[u8; 32]> {
     Ok(arr)
 }
 
-/// Reconstruct a Note from wallet JSON fields
-fn reconstruct_note(wn: &WalletNote) -> Result<Note> {
-    Ok(Note {
-        amount: wn.amount,
-        pubkey: decode_hex_32(&wn.pubkey)?,
-        blinding: decode_hex_32(&wn.blinding)?,
-    })
-}
-
-/// Find the spending key whose pubkey matches the note's pubkey
-fn find_spending_key<'a>(
-    wallet: &'a WalletState,
-    note_pubkey: &str,
-) -> Option<&'a WalletSpendingKey> {
-    wallet.spending_keys.iter().find(|sk| sk.pubkey == note_pubkey)
-}
-
 // ---------------------------------------------------------------------------
 // Main
 // ---------------------------------------------------------------------------
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    dotenv::dotenv().ok();
+    shielded_pool_script::config::load()?;
     sp1_sdk::utils::setup_logger();
 
     println!("\n=== Shielded Pool Exit — Withdraw All ===\n");
@@ -139,25 +134,25 @@ async fn main() -> Result<()> {
     let pool_addr: Address = std::env::var("POOL_ADDRESS")
         .context("POOL_ADDRESS not set")?
         .parse()?;
-    let tree_levels: usize = std::env::var("TREE_LEVELS")
-        .unwrap_or_else(|_| "20".to_string())
-        .parse()?;
-    let deploy_block: u64 = std::env::var("DEPLOY_BLOCK")
-        .unwrap_or_else(|_| "0".to_string())
-        .parse()
-        .context("DEPLOY_BLOCK must be a number")?;
 
     // Wallet file
-    let default_wallet_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-        .parent()
-        .unwrap()
-        .join("fixtures/wallet.json");
-    let wallet_path = std::env::var("WALLET_FILE")
-        .map(std::path::PathBuf::from)
-        .unwrap_or(default_wallet_path);
+    let wallet_path = wallet_path();
 
     // Recipient override
     let recipient_override = std::env::var("RECIPIENT_ADDRESS").ok();
+    let fee_bump = fee_bump_from_env()?;
+
+    // Proof-of-exit bundle, and the progress kept while the run is unfinished
+    let default_report_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("fixtures/exit-report.json");
+    let report_path = std::env::var("EXIT_REPORT")
+        .map(std::path::PathBuf::from)
+        .unwrap_or(default_report_path);
+    let state_path = std::env::var("EXIT_STATE")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| report_path.with_file_name("exit-state.json"));
 
     // ── Connect ────────────────────────────────────────────────────────
     let signer: PrivateKeySigner = private_key.parse()?;
@@ -180,110 +175,22 @@ async fn main() -> Result<()> {
 
     // ── Load wallet state ──────────────────────────────────────────────
     println!("Wallet file:  {}\n", wallet_path.display());
-    let wallet_json = std::fs::read_to_string(&wallet_path)
-        .context(format!("Failed to read wallet file: {}", wallet_path.display()))?;
-    let wallet: WalletState = serde_json::from_str(&wallet_json)?;
+    let wallet = WalletState::load(&wallet_path)?;
 
     println!("Found {} spending keys, {} notes", wallet.spending_keys.len(), wallet.notes.len());
+    let spending_keys = wallet.unlock_spending_keys()?;
+
+    let chain_id = provider.get_chain_id().await?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
 
     // ── Build Merkle tree from on-chain events ─────────────────────────
     println!("\n[1] Building Merkle tree from all on-chain events...");
-    let mut tree = IncrementalMerkleTree::new(tree_levels);
-
-    // Replay ALL commitment insertions in order:
-    //   Deposit:         1 commitment  (from event)
-    //   PrivateTransfer: 2 commitments (from event)
-    //   Withdrawal:      0 or 1 commitment (change, from tx calldata)
 
-    struct Insertion {
-        block: u64,
-        log_index: u64,
-        commitments: Vec<[u8; 32]>,
-    }
-
-    let mut insertions: Vec<Insertion> = Vec::new();
-
-    // 1. Deposits
-    let deposit_logs = pool.Deposit_filter().from_block(deploy_block).query().await?;
-    println!("    Deposits: {}", deposit_logs.len());
-    for (event, log) in &deposit_logs {
-        insertions.push(Insertion {
-            block: log.block_number.unwrap_or(0),
-            log_index: log.log_index.unwrap_or(0),
-            commitments: vec![event.commitment.0],
-        });
-    }
-
-    // 2. Private transfers (2 commitments each)
-    let transfer_logs = pool.PrivateTransfer_filter().from_block(deploy_block).query().await?;
-    println!("    Transfers: {}", transfer_logs.len());
-    for (event, log) in &transfer_logs {
-        insertions.push(Insertion {
-            block: log.block_number.unwrap_or(0),
-            log_index: log.log_index.unwrap_or(0),
-            commitments: vec![event.newCommitment1.0, event.newCommitment2.0],
-        });
-    }
-
-    // 3. Withdrawals — decode changeCommitment from tx calldata
-    let withdrawal_logs = pool.Withdrawal_filter().from_block(deploy_block).query().await?;
-    println!("    Withdrawals: {}", withdrawal_logs.len());
-    for (_event, log) in &withdrawal_logs {
-        if let Some(tx_hash) = log.transaction_hash {
-            if let Some(tx) = provider.get_transaction_by_hash(tx_hash).await? {
-                let input = tx.input();
-                // withdraw(bytes proof, bytes publicValues, bytes encryptedChange)
-                // Calldata: 4-byte selector + ABI-encoded (bytes, bytes, bytes)
-                // Word 0: offset to proof, Word 1: offset to publicValues, Word 2: offset to encryptedChange
-                // At each offset: first 32 bytes = length, then data
-                if input.len() > 4 + 32 * 3 {
-                    let data = &input[4..];
-                    let pv_offset = u64::from_be_bytes(data[32 + 24..32 + 32].try_into().unwrap()) as usize;
-                    if pv_offset + 32 <= data.len() {
-                        let pv_len = u64::from_be_bytes(data[pv_offset + 24..pv_offset + 32].try_into().unwrap()) as usize;
-                        let pv_start = pv_offset + 32;
-                        if pv_len >= 160 && pv_start + 160 <= data.len() {
-                            let mut change_comm = [0u8; 32];
-                            change_comm.copy_from_slice(&data[pv_start + 128..pv_start + 160]);
-                            if change_comm != [0u8; 32] {
-                                insertions.push(Insertion {
-                                    block: log.block_number.unwrap_or(0),
-                                    log_index: log.log_index.unwrap_or(0),
-                                    commitments: vec![change_comm],
-                                });
-                            }
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    // Sort by block number, then log index
-    insertions.sort_by_key(|i| (i.block, i.log_index));
-
-    let total_commitments: usize = insertions.iter().map(|i| i.commitments.len()).sum();
-    println!("    Total commitments to insert: {total_commitments}");
-
-    for ins in &insertions {
-        for comm in &ins.commitments {
-            tree.insert(*comm);
-        }
-    }
-
-    // Verify root
-    let on_chain_root: FixedBytes<32> = pool.getLastRoot().call().await?;
-    let on_chain_leaves: u32 = pool.getLeafCount().call().await?;
-    println!("    On-chain leaves: {on_chain_leaves}, local leaves: {}", tree.leaves.len());
-
-    if FixedBytes::from(tree.get_root()) == on_chain_root {
-        println!("    Root verified ✓");
-    } else {
-        println!("    ⚠ Root mismatch — tree may be incomplete.");
-        println!("    Local root:    0x{}", hex::encode(tree.get_root()));
-        println!("    On-chain root: {on_chain_root}");
-        println!("    Continuing anyway — will use isKnownRoot() for each withdrawal...");
-    }
+    let source = TreeSource::from_env(chain_id, pool_addr)?;
+    let finality = source.finality;
+    provider.client().set_poll_interval(finality.poll_interval());
+    let PoolSync { tree, status: sync, block: sync_block, mut roots } =
+        sync_pool_tree(&provider, &views, pool_addr, chain_id, &source).await?;
 
     // ── Find unspent notes ─────────────────────────────────────────────
     println!("\n[2] Checking which notes are unspent...");
@@ -291,15 +198,21 @@ async fn main() -> Result<()> {
     struct UnspentNote {
         note: Note,
         spending_key: [u8; 32],
+        /// What the withdraw circuit opens the note with
+        spender: SpenderKey,
+        nullifier: [u8; 32],
+        /// Lock of a cancellable note, and the reclaim deadline if we are its sender
+        lock: Option<CancellableLock>,
+        reclaim_deadline: Option<u64>,
         leaf_index: u32,
         label: String,
+        metadata: NoteMetadata,
     }
 
-    let mut unspent: Vec<UnspentNote> = Vec::new();
-    let mut total_unspent: u64 = 0;
-
+    // Collect every note we hold a key for, then check spent status in one batch
+    let mut candidates: Vec<(UnspentNote, [u8; 32])> = Vec::new();
     for wn in &wallet.notes {
-        let note = reconstruct_note(wn)?;
+        let note = wn.to_note()?;
         let commitment = note.commitment();
 
         // Verify the stored commitment matches
@@ -312,48 +225,107 @@ async fn main() -> Result<()> {
             wn.commitment
         );
 
-        // Find the spending key for this note
-        let sk_entry = find_spending_key(&wallet, &wn.pubkey);
-        let sk_entry = match sk_entry {
-            Some(sk) => sk,
+        // Find a spending key for this note (owner, or claim/reclaim for cancellable notes).
+        // Keys are matched by deriving their pubkeys in constant time rather than
+        // trusting the pubkey stored next to them.
+        let (sk, spender, auth) = match wn.authorize_spender(&spending_keys)? {
+            Some(found) => found,
             None => {
+                strict::fallback(StrictViolation::NoSpendingKey { label: wn.label.clone() })?;
                 println!("    {} — no spending key (skip)", wn.label);
                 continue;
             }
         };
-        let sk = decode_hex_32(&sk_entry.spending_key)?;
+        if auth.path == SpendPath::Reclaim {
+            println!("    {} — cancellable, reclaiming as sender", wn.label);
+        }
 
-        // Check if nullifier is already spent
-        let nullifier = compute_nullifier(&commitment, &sk);
-        let is_spent: bool = pool.isSpent(FixedBytes::from(nullifier)).call().await?;
+        let nullifier = auth.nullifier;
+        candidates.push((
+            UnspentNote {
+                note,
+                spending_key: *sk.as_bytes(),
+                spender,
+                nullifier,
+                lock: wn.to_lock()?,
+                reclaim_deadline: auth.reclaim_deadline,
+                leaf_index: wn.leaf_index,
+                label: wn.label.clone(),
+                metadata: wn.metadata.clone(),
+            },
+            nullifier,
+        ));
+    }
 
+    // Answered from the spent-nullifier events when the tree sync is complete,
+    // otherwise asked of the pool
+    let nullifiers: Vec<[u8; 32]> = candidates.iter().map(|(_, n)| *n).collect();
+    let spent = if sync.is_complete() {
+        let set = replay_nullifiers(&provider, pool_addr, source.deploy_block, sync_block).await?;
+        nullifiers.iter().map(|n| set.contains(n)).collect()
+    } else {
+        views.is_spent_batch(&nullifiers).await?
+    };
+
+    let mut unspent: Vec<UnspentNote> = Vec::new();
+    let mut total_unspent: u64 = 0;
+    for ((un, _), is_spent) in candidates.into_iter().zip(spent) {
         if is_spent {
             println!(
                 "    {} — {} USDT — SPENT",
-                wn.label,
-                wn.amount as f64 / 1e6
+                un.label,
+                un.note.amount as f64 / 1e6
             );
         } else {
             println!(
                 "    {} — {} USDT — UNSPENT ✓",
-                wn.label,
-                wn.amount as f64 / 1e6
+                un.label,
+                un.note.amount as f64 / 1e6
             );
-            total_unspent += wn.amount;
-            unspent.push(UnspentNote {
-                note,
-                spending_key: sk,
-                leaf_index: wn.leaf_index,
-                label: wn.label.clone(),
-            });
+            total_unspent += un.note.amount;
+            unspent.push(un);
         }
     }
 
-    if unspent.is_empty() {
+    // An interrupted run may have left proofs or transactions to finish
+    let resuming = state_path.exists();
+    if unspent.is_empty() && !resuming {
         println!("\nNo unspent notes found. Nothing to withdraw.");
         return Ok(());
     }
 
+    // Refuse to prove notes whose Merkle path runs through unverified leaves,
+    // and reclaims the pool would reject because the window has closed
+    let now = provider
+        .get_block_by_number(BlockNumberOrTag::Number(sync_block))
+        .await?
+        .context("sync block not found")?
+        .header
+        .timestamp;
+    let mut refused: Vec<SkippedNote> = Vec::new();
+    unspent.retain(|un| match sync.refusal(un.leaf_index).or_else(|| {
+        un.reclaim_deadline
+            .filter(|&deadline| now > deadline)
+            .map(|deadline| format!("reclaim window closed at {deadline} (recipient can still claim)"))
+    }) {
+        None => true,
+        Some(reason) => {
+            println!("    {} — not provable: {reason}", un.label);
+            refused.push(SkippedNote {
+                label: un.label.clone(),
+                amount: un.note.amount,
+                reason,
+                metadata: un.metadata.clone(),
+            });
+            false
+        }
+    });
+    ensure!(
+        !unspent.is_empty() || resuming,
+        "none of the {} unspent note(s) lie in a verified part of the tree; fix the sync (see above) and re-run",
+        refused.len()
+    );
+
     println!(
         "\nFound {} unspent note(s) totalling {} USDT",
         unspent.len(),
@@ -363,78 +335,333 @@ async fn main() -> Result<()> {
     // ── Withdraw each unspent note ─────────────────────────────────────
     let sp1_client = ProverClient::from_env();
     let recipient_bytes: [u8; 20] = withdraw_to.0 .0;
-
-    let balance_before: U256 = token.balanceOf(withdraw_to).call().await?;
+    let binding = pool_binding(chain_id, &pool_addr.0 .0);
+
+    let start_block = provider.get_block_number().await?;
+    let balance_before: U256 = token
+        .balanceOf(withdraw_to)
+        .block(BlockId::number(start_block))
+        .call()
+        .await?;
     println!("Balance before: {balance_before}\n");
 
+    let journal = Journal::open(&state_path, || ExitState {
+        report: ExitReport {
+            version: EXIT_REPORT_VERSION,
+            chain_id,
+            pool: pool_addr.to_string(),
+            token: token_addr.to_string(),
+            recipient: withdraw_to.to_string(),
+            balance_before: balance_before.to_string(),
+            balance_after: String::new(),
+            start_block,
+            end_block: start_block,
+            withdrawals: Vec::new(),
+            skipped: Vec::new(),
+        },
+        pending: Vec::new(),
+        interrupted: None,
+    })?;
+    journal.exit_on_ctrl_c();
+    journal.read(|state| state.check_matches(chain_id, &pool_addr.to_string(), &withdraw_to.to_string()))
+        .with_context(|| format!("cannot resume from {}; delete it to start over", state_path.display()))?;
+    let interrupted = journal.update(|state| {
+        state.report.skipped = refused;
+        state.interrupted.take()
+    })?;
+
+    // ── Finish an interrupted run ──────────────────────────────────────
+    // Proofs can be resubmitted; transactions already sent are looked up
+    let mut reusable: HashMap<String, ExitWithdrawal> = HashMap::new();
+    let mut in_flight: HashMap<String, String> = HashMap::new();
+    if resuming {
+        println!("Resuming from {}", state_path.display());
+        if let Some(job) = interrupted {
+            println!("    Last run was interrupted while {job}");
+        }
+        println!(
+            "    Balance before (from first run): {}",
+            journal.read(|state| state.report.balance_before.clone())
+        );
+        for mut p in journal.read(|state| state.pending.clone()) {
+            if !p.tx_hash.is_empty() {
+                let hash: TxHash = p.tx_hash.parse().context("invalid tx hash in exit state")?;
+                let mut receipt = provider.get_transaction_receipt(hash).await?;
+                if receipt.is_none() {
+                    if let Some(stuck) = provider.get_transaction_by_hash(hash).await? {
+                        println!("    '{}' — tx {} is still pending", p.label, p.tx_hash);
+                        // Resend the same proof at higher fees while its root is accepted
+                        let bump = match fee_bump {
+                            Some(percent) if roots.is_known_root(&views, decode_hex_32(&p.root)?).await? => percent,
+                            Some(_) => {
+                                println!("    Its root has left the pool's history; the tx will revert if mined");
+                                in_flight.insert(p.commitment.clone(), p.tx_hash.clone());
+                                continue;
+                            }
+                            None => {
+                                in_flight.insert(p.commitment.clone(), p.tx_hash.clone());
+                                continue;
+                            }
+                        };
+                        journal.set_job(Some(format!("resending the withdrawal of '{}'", p.label)));
+                        let tx = replace_with_higher_fees(&provider, wallet_address, &stuck, bump).await?;
+                        p.tx_hash = tx.tx_hash().to_string();
+                        println!("    Resent with fees raised {bump}%: tx {}", p.tx_hash);
+                        journal.update(|state| {
+                            if let Some(q) = state.pending.iter_mut().find(|q| q.commitment == p.commitment) {
+                                q.tx_hash = p.tx_hash.clone();
+                            }
+                        })?;
+                        let mined = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
+                        journal.set_job(None);
+                        ensure!(mined.status(), "withdraw tx {} reverted", mined.transaction_hash);
+                        receipt = Some(mined);
+                    }
+                }
+                if let Some(receipt) = receipt {
+                    if receipt.status() {
+                        println!("    ✓ '{}' landed in tx {}", p.label, p.tx_hash);
+                        let withdrawal = ExitWithdrawal {
+                            block_number: strict::block_number(receipt.block_number, || {
+                                format!("receipt for tx {}", p.tx_hash)
+                            })?,
+                            ..p.clone()
+                        };
+                        AuditLog::record(audit_entry(chain_id, pool_addr, &withdrawal)?)?;
+                        journal.update(|state| {
+                            state.pending.retain(|q| q.commitment != p.commitment);
+                            state.report.withdrawals.push(withdrawal);
+                        })?;
+                        continue;
+                    }
+                }
+            }
+            println!("    '{}' — proof from the last run not on-chain yet", p.label);
+            reusable.insert(p.commitment.clone(), p);
+        }
+        println!();
+    }
+
+    // ── Reuse, skip or prepare a proof for each note ───────────────────
+    // The proofs are independent (one root, one recipient), so the new ones
+    // are generated together; the transactions then go out one at a time
+    let mut ready: Vec<Option<ExitWithdrawal>> = vec![None; unspent.len()];
+    let mut to_prove: Vec<(usize, WithdrawPrivateInputs)> = Vec::new();
     for (i, un) in unspent.iter().enumerate() {
         println!(
-            "[{}] Withdrawing '{}' — {} USDT (leaf {})",
+            "[{}] '{}' — {} USDT (leaf {})",
             i + 3,
             un.label,
             un.note.amount as f64 / 1e6,
             un.leaf_index,
         );
+        let commitment = hex::encode(un.note.commitment());
+
+        if let Some(tx_hash) = in_flight.get(&commitment) {
+            strict::fallback(StrictViolation::PendingTransaction { tx_hash: tx_hash.clone() })?;
+            println!("    ⚠ Earlier tx {tx_hash} is still pending. Skipping this note; re-run once it is mined.");
+            journal.update(|state| {
+                state.report.skipped.push(SkippedNote {
+                    label: un.label.clone(),
+                    amount: un.note.amount,
+                    reason: format!("earlier withdraw tx {tx_hash} still pending"),
+                    metadata: un.metadata.clone(),
+                })
+            })?;
+            continue;
+        }
 
-        // Build Merkle proof
+        // A proof from an interrupted run is still good while its root is known
+        match reusable.remove(&commitment) {
+            Some(p) if roots.is_known_root(&views, decode_hex_32(&p.root)?).await? => {
+                println!("    Reusing proof from the interrupted run");
+                ready[i] = Some(ExitWithdrawal { tx_hash: String::new(), block_number: 0, ..p });
+                continue;
+            }
+            _ => {}
+        }
+
+        // Build Merkle proof against the verified root
         let root = tree.get_root();
 
-        // Verify root is known on-chain (use recent root)
-        let root_ok: bool = pool.isKnownRoot(FixedBytes::from(root)).call().await?;
-        if !root_ok {
-            println!("    ⚠ Current local root not recognized on-chain. Skipping this note.");
+        // The verified root may have dropped out of the pool's history since sync
+        if !roots.is_known_root(&views, root).await? {
+            strict::fallback(StrictViolation::RootNoLongerKnown)?;
+            println!("    ⚠ Verified root is no longer recognized on-chain. Skipping this note.");
             println!("    Root: 0x{}", hex::encode(root));
+            journal.update(|state| {
+                state.report.skipped.push(SkippedNote {
+                    label: un.label.clone(),
+                    amount: un.note.amount,
+                    reason: format!("local root 0x{} not known on-chain", hex::encode(root)),
+                    metadata: un.metadata.clone(),
+                })
+            })?;
             continue;
         }
 
-        let proof = tree.get_proof(un.leaf_index);
-
-        let withdraw_inputs = WithdrawPrivateInputs {
+        let proof = tree.try_get_proof(un.leaf_index).map_err(anyhow::Error::msg)?;
+        println!("    To prove");
+        let mut inputs = WithdrawPrivateInputs {
             input_note: un.note.clone(),
-            spending_key: un.spending_key,
+            spender_key: un.spender,
             merkle_proof: proof,
             root,
             recipient: recipient_bytes,
             withdraw_amount: un.note.amount, // full withdrawal, no change
             change_note: None,
+            lock: un.lock,
+            fee: 0,
+            relayer: [0u8; 20],
+            association: None,
+            pool_binding: binding,
+            deadline: 0,
+            call_target: [0u8; 20],
+            calldata_hash: [0u8; 32],
+            multisig: None,
+            spend_signatures: Vec::new(),
         };
+        inputs.sign(&un.spending_key).map_err(anyhow::Error::msg)?;
+        to_prove.push((i, inputs));
+    }
 
-        // Generate proof
-        println!("    Generating Groth16 proof...");
-        let mut stdin = SP1Stdin::new();
-        stdin.write(&withdraw_inputs);
-
-        let (pk, _vk) = sp1_client.setup(WITHDRAW_ELF);
-        let proof = sp1_client.prove(&pk, &stdin).groth16().run()?;
+    // ── Generate the new proofs ────────────────────────────────────────
+    if !to_prove.is_empty() {
+        println!();
+        let (pk, _vk) = key_cache::setup(&sp1_client, WITHDRAW_ELF);
+        let jobs = to_prove
+            .iter()
+            .map(|(i, inputs)| ProofJob {
+                label: unspent[*i].label.clone(),
+                pk: &pk,
+                elf: WITHDRAW_ELF,
+                stdin: framed_stdin(CircuitKind::Withdraw, inputs),
+            })
+            .collect();
+        journal.set_job(Some(format!("proving {} withdrawal(s)", to_prove.len())));
+        prove_all(&sp1_client, jobs, |job, proof| {
+            let (i, inputs) = &to_prove[job];
+            let un = &unspent[*i];
+            let withdrawal = ExitWithdrawal {
+                label: un.label.clone(),
+                leaf_index: un.leaf_index,
+                commitment: hex::encode(un.note.commitment()),
+                nullifier: hex::encode(un.nullifier),
+                amount: un.note.amount,
+                root: hex::encode(inputs.root),
+                proof: hex::encode(proof.bytes()),
+                public_values: hex::encode(proof.public_values.to_vec()),
+                request_hash: Some(hex::encode(inputs.request_hash())),
+                tx_hash: String::new(),
+                block_number: 0,
+                metadata: un.metadata.clone(),
+            };
+            // Keep each proof as soon as it exists, so an interrupt does not waste it
+            journal.update(|state| {
+                state.pending.retain(|q| q.commitment != withdrawal.commitment);
+                state.pending.push(withdrawal.clone());
+            })?;
+            ready[*i] = Some(withdrawal);
+            Ok(())
+        })?;
+        journal.set_job(None);
+    }
 
-        let proof_bytes = proof.bytes();
-        let public_values = proof.public_values.to_vec();
-        println!(
-            "    Proof: {} bytes, Public values: {} bytes",
-            proof_bytes.len(),
-            public_values.len()
-        );
+    // ── Submit, one transaction at a time ──────────────────────────────
+    for (i, un) in unspent.iter().enumerate() {
+        let Some(mut withdrawal) = ready[i].take() else {
+            continue;
+        };
+        let commitment = withdrawal.commitment.clone();
+        println!("\n[{}] Withdrawing '{}'", i + 3, un.label);
+        // Keep the proof until its tx is confirmed, so an interrupt does not waste it
+        journal.update(|state| {
+            state.pending.retain(|q| q.commitment != commitment);
+            state.pending.push(withdrawal.clone());
+        })?;
 
         // Submit on-chain
         println!("    Submitting withdraw tx...");
+        journal.set_job(Some(format!("submitting the withdrawal of '{}'", un.label)));
         let tx = pool
             .withdraw(
-                Bytes::from(proof_bytes),
-                Bytes::from(public_values),
+                Bytes::from(decode_hex(&withdrawal.proof)?),
+                Bytes::from(decode_hex(&withdrawal.public_values)?),
                 Bytes::new(),
             )
             .send()
             .await?;
-        let receipt = tx.get_receipt().await?;
+        withdrawal.tx_hash = tx.tx_hash().to_string();
+        journal.update(|state| {
+            if let Some(p) = state.pending.iter_mut().find(|q| q.commitment == commitment) {
+                p.tx_hash = withdrawal.tx_hash.clone();
+            }
+        })?;
+        let receipt = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
+        journal.set_job(None);
+        ensure!(receipt.status(), "withdraw tx {} reverted", receipt.transaction_hash);
         println!("    ✓ Tx: {}", receipt.transaction_hash);
+
+        withdrawal.block_number = strict::block_number(receipt.block_number, || {
+            format!("receipt for tx {}", receipt.transaction_hash)
+        })?;
+        AuditLog::record(audit_entry(chain_id, pool_addr, &withdrawal)?)?;
+        journal.update(|state| {
+            state.pending.retain(|q| q.commitment != commitment);
+            state.report.withdrawals.push(withdrawal);
+        })?;
     }
 
     // ── Final balance ──────────────────────────────────────────────────
-    let balance_after: U256 = token.balanceOf(withdraw_to).call().await?;
+    let end_block = provider.get_block_number().await?;
+    let balance_after: U256 = token
+        .balanceOf(withdraw_to)
+        .block(BlockId::number(end_block))
+        .call()
+        .await?;
+    let report = journal.update(|state| {
+        state.report.end_block = end_block;
+        state.report.balance_after = balance_after.to_string();
+        state.report.clone()
+    })?;
+
+    // ── Proof-of-exit bundle ───────────────────────────────────────────
+    // Proofs that were never sent are dropped; sent ones must be confirmed first
+    let unconfirmed = journal.read(|state| state.pending.iter().filter(|p| !p.tx_hash.is_empty()).count());
+    ensure!(
+        unconfirmed == 0,
+        "{unconfirmed} withdrawal tx(s) are not confirmed yet; re-run to finish them (progress kept in {})",
+        state_path.display()
+    );
+    report.check_offline().context("exit report failed its own consistency check")?;
+    report.save(&report_path)?;
+    journal.finish()?;
+
     println!("\n=== Exit Complete ===");
-    println!("Balance before: {balance_before}");
+    println!("Balance before: {}", report.balance_before);
     println!("Balance after:  {balance_after}");
-    println!("Recovered:      {} USDT\n", total_unspent as f64 / 1e6);
+    println!("Recovered:      {} USDT", report.total_withdrawn() as f64 / 1e6);
+    if !report.skipped.is_empty() {
+        println!("Skipped:        {} note(s) — see report", report.skipped.len());
+    }
+    println!("Exit report:    {}", report_path.display());
+    println!("Verify with:    cargo run --release -p shielded-pool-script --bin verify-exit-report -- {}\n", report_path.display());
 
     Ok(())
 }
+
+/// Audit log entry for a confirmed full withdrawal.
+fn audit_entry(chain_id: u64, pool: Address, w: &ExitWithdrawal) -> Result<AuditEntry> {
+    let spent = AuditNote {
+        label: w.label.clone(),
+        commitment: w.commitment.clone(),
+        amount: w.amount,
+        leaf_index: Some(w.leaf_index),
+    };
+    let mut entry = AuditEntry::new(AuditAction::Withdraw, "exit", chain_id, &pool.to_string(), &w.tx_hash, w.block_number)
+        .with_spent(spent)
+        .with_public_amount(w.amount)
+        .with_proof(&decode_hex(&w.proof)?, &decode_hex(&w.public_values)?);
+    entry.request_hash = w.request_hash.clone();
+    Ok(entry)
+}
@@ -0,0 +1,257 @@
+//! Explorer backend: a read-only REST API over the pool's public data.
+//!
+//! Indexes the pool's events (see `shielded_pool_script::explorer`) and
+//! follows the chain, staying EXPLORER_CONFIRMATIONS blocks behind the head.
+//! If the indexed root ever disagrees with the pool's root at the synced
+//! block (a reorg deeper than the confirmations), the index is rebuilt.
+//!
+//! Routes (GET, JSON, hex 0x-prefixed; lists take `?from=N&limit=N`):
+//!   /health
+//!   /status                  — synced block, leaf count, current root, totals
+//!   /commitments             — commitments in leaf order, with tx and kind
+//!   /commitments/{hex}       — one commitment's leaf index and tx
+//!   /roots                   — root after every insertion, oldest first;
+//!                              `known` marks the ones the pool still accepts
+//!   /roots/{hex}             — when a root was created
+//!   /nullifiers              — spent nullifiers in spend order
+//!   /nullifiers/{hex}        — spent or not, with a proof against the
+//!                              nullifier set's sparse Merkle root
+//!   /blocks                  — blocks with pool activity, newest first
+//!                              (`?before=N&limit=N`)
+//!   /blocks/{n}              — one block's activity
+//!   /tx/{hash}               — decoded public values of a pool transaction
+//!
+//! Nothing private is served: no note contents, no encrypted payloads.
+//!
+//! Usage:
+//!   cargo run --release -p shielded-pool-script --bin explorer
+//!
+//! Required env vars (from .env):
+//!   RPC_URL               — Plasma RPC endpoint
+//!   POOL_ADDRESS          — Deployed ShieldedPool address
+//!
+//! Optional env vars:
+//!   DEPLOY_BLOCK          — Block the ShieldedPool was deployed at (default: 0)
+//!   TREE_LEVELS           — Merkle tree depth (default: 20)
+//!   EXPLORER_PORT         — Port to listen on (default: 3002)
+//!   EXPLORER_POLL_SECS    — How often to look for new blocks (default: the chain registry's poll interval)
+//!   EXPLORER_CONFIRMATIONS — Blocks to stay behind the head (default: the chain registry's confirmations)
+//!   SYNC_CHUNK_BLOCKS, SYNC_CONCURRENCY — as for the exit script
+
+use alloy::{
+    primitives::{Address, B256},
+    providers::{DynProvider, Provider, ProviderBuilder},
+};
+use anyhow::{Context, Result};
+use http_body_util::Full;
+use hyper::{body::Bytes, header, server::conn::http1, service::service_fn, Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use shielded_pool_script::{
+    chains::FinalityPolicy,
+    decode_hex_fixed,
+    explorer::{decode_pool_tx, fetch_events, page, pool_root_at, PoolIndex},
+};
+use std::{collections::HashMap, convert::Infallible, sync::Arc, time::Duration};
+use tokio::{net::TcpListener, sync::RwLock};
+
+/// Most items a list route returns at once.
+const MAX_PAGE: usize = 1000;
+
+struct Explorer {
+    provider: DynProvider,
+    index: RwLock<PoolIndex>,
+    levels: usize,
+    deploy_block: u64,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T> {
+    match std::env::var(name) {
+        Ok(v) => v.parse().ok().with_context(|| format!("{name} is not valid")),
+        Err(_) => Ok(default),
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let levels: usize = env_or("TREE_LEVELS", 20)?;
+    let deploy_block: u64 = env_or("DEPLOY_BLOCK", 0)?;
+    let port: u16 = env_or("EXPLORER_PORT", 3002)?;
+
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?).erased();
+    let finality = FinalityPolicy::for_chain(provider.get_chain_id().await?)?;
+    let poll = match std::env::var("EXPLORER_POLL_SECS") {
+        Ok(secs) => Duration::from_secs(secs.parse().context("EXPLORER_POLL_SECS is not valid")?),
+        Err(_) => finality.poll_interval(),
+    };
+    let confirmations: u64 = env_or("EXPLORER_CONFIRMATIONS", finality.confirmations)?;
+    let explorer = Arc::new(Explorer {
+        provider,
+        index: RwLock::new(PoolIndex::new(pool_addr, levels, deploy_block)),
+        levels,
+        deploy_block,
+    });
+
+    println!("\n=== Shielded Pool Explorer ===\n");
+    println!("Pool: {pool_addr}");
+    tokio::spawn(follow_chain(explorer.clone(), poll, confirmations));
+
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    println!("Serving on http://0.0.0.0:{port}");
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let explorer = explorer.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let explorer = explorer.clone();
+                async move { Ok::<_, Infallible>(handle(&explorer, req).await) }
+            });
+            if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                eprintln!("connection error: {e}");
+            }
+        });
+    }
+}
+
+/// Keep the index a few blocks behind the head, forever.
+async fn follow_chain(explorer: Arc<Explorer>, poll: Duration, confirmations: u64) {
+    loop {
+        if let Err(e) = sync_once(&explorer, confirmations).await {
+            eprintln!("    ⚠ Sync failed: {e:#}");
+        }
+        tokio::time::sleep(poll).await;
+    }
+}
+
+async fn sync_once(explorer: &Explorer, confirmations: u64) -> Result<()> {
+    let (pool, from) = {
+        let index = explorer.index.read().await;
+        (index.pool, index.next_block())
+    };
+    let head = explorer.provider.get_block_number().await?;
+    let to = head.saturating_sub(confirmations);
+    if to < from {
+        return Ok(());
+    }
+    let events = fetch_events(&explorer.provider, pool, from, to).await?;
+    let on_chain_root = pool_root_at(&explorer.provider, pool, to).await?;
+
+    let mut index = explorer.index.write().await;
+    let applied = events.len();
+    if let Err(e) = index.apply(to, events).and_then(|()| {
+        anyhow::ensure!(index.root() == on_chain_root, "indexed root differs from the pool's root at block {to}");
+        Ok(())
+    }) {
+        // A reorg deeper than the confirmations, or a gap in the logs: start over
+        *index = PoolIndex::new(pool, explorer.levels, explorer.deploy_block);
+        return Err(e.context("index reset; re-indexing from DEPLOY_BLOCK"));
+    }
+    if applied > 0 {
+        let status = index.status();
+        println!("    Indexed to block {to}: {} leaves, {} nullifiers", status.leaf_count, status.nullifiers);
+    }
+    Ok(())
+}
+
+fn json<T: Serialize + ?Sized>(status: StatusCode, body: &T) -> Response<Full<Bytes>> {
+    let body = serde_json::to_vec(body).unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Full::new(Bytes::from(body)))
+        .expect("static response parts are valid")
+}
+
+fn ok<T: Serialize + ?Sized>(body: &T) -> Result<Response<Full<Bytes>>> {
+    Ok(json(StatusCode::OK, body))
+}
+
+fn error(status: StatusCode, message: impl std::fmt::Display) -> Response<Full<Bytes>> {
+    json(status, &serde_json::json!({ "error": message.to_string() }))
+}
+
+fn query(req: &Request<hyper::body::Incoming>) -> HashMap<String, String> {
+    req.uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// `?from=N&limit=N`, with limit capped at MAX_PAGE.
+fn paging(query: &HashMap<String, String>) -> Result<(usize, usize)> {
+    let from = query.get("from").map(|v| v.parse()).transpose().context("from must be a number")?;
+    let limit: Option<usize> = query.get("limit").map(|v| v.parse()).transpose().context("limit must be a number")?;
+    Ok((from.unwrap_or(0), limit.unwrap_or(100).min(MAX_PAGE)))
+}
+
+async fn handle(explorer: &Explorer, req: Request<hyper::body::Incoming>) -> Response<Full<Bytes>> {
+    if req.method() != Method::GET {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "read-only API: GET only");
+    }
+    match route(explorer, &req).await {
+        Ok(response) => response,
+        Err(e) => error(StatusCode::BAD_REQUEST, format!("{e:#}")),
+    }
+}
+
+async fn route(explorer: &Explorer, req: &Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>> {
+    let segments: Vec<&str> = req.uri().path().split('/').filter(|s| !s.is_empty()).collect();
+    let query = query(req);
+    let not_found = |what: &str| Ok(error(StatusCode::NOT_FOUND, format!("{what} not found")));
+
+    match segments.as_slice() {
+        ["health"] => ok(&serde_json::json!({ "status": "ok" })),
+        ["status"] => ok(&explorer.index.read().await.status()),
+        ["commitments"] => {
+            let (from, limit) = paging(&query)?;
+            ok(page(explorer.index.read().await.commitments(), from, limit))
+        }
+        ["commitments", hex] => match explorer.index.read().await.commitment(&decode_hex_fixed(hex)?) {
+            Some(entry) => ok(entry),
+            None => not_found("commitment"),
+        },
+        ["roots"] => {
+            let (from, limit) = paging(&query)?;
+            ok(page(explorer.index.read().await.roots(), from, limit))
+        }
+        ["roots", hex] => match explorer.index.read().await.find_root(&decode_hex_fixed(hex)?) {
+            Some(entry) => ok(entry),
+            None => not_found("root"),
+        },
+        ["nullifiers"] => {
+            let (from, limit) = paging(&query)?;
+            ok(page(explorer.index.read().await.nullifiers(), from, limit))
+        }
+        ["nullifiers", hex] => ok(&explorer.index.read().await.nullifier(&decode_hex_fixed(hex)?)),
+        ["blocks"] => {
+            let (_, limit) = paging(&query)?;
+            let before = query.get("before").map(|v| v.parse()).transpose().context("before must be a number")?;
+            let index = explorer.index.read().await;
+            ok(&index.recent_blocks(before, limit))
+        }
+        ["blocks", n] => {
+            let block: u64 = n.parse().context("block must be a number")?;
+            match explorer.index.read().await.block(block) {
+                Some(activity) => ok(activity),
+                None => not_found("activity in this block"),
+            }
+        }
+        ["tx", hash] => {
+            let tx_hash: B256 = hash.parse().context("invalid tx hash")?;
+            let pool = explorer.index.read().await.pool;
+            match decode_pool_tx(&explorer.provider, pool, tx_hash).await? {
+                Some(tx) => ok(&tx),
+                None => not_found("transaction"),
+            }
+        }
+        _ => not_found("route"),
+    }
+}
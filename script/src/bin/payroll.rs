@@ -0,0 +1,215 @@
+//! Batch disbursement through the payout circuit.
+//!
+//! Pays a list of recipients from one payer note, up to seven recipients per
+//! payout proof plus the payer's change:
+//!   1. Funds a payer note in a local Merkle tree with the payroll total
+//!   2. Splits the payroll into batches; each batch spends the previous
+//!      batch's change (the first spends the funding note)
+//!   3. Builds padded `PayoutPrivateInputs` for every batch, checks them and
+//!      writes them to PAYROLL_DIR
+//!   4. Executes the payout guest on each batch (or proves them all with
+//!      --prove, concurrently; see `shielded_pool_script::proving`)
+//!   5. Checks each batch's committed public values against its inputs
+//!
+//! The payer note only exists in the local tree, so nothing is submitted;
+//! this is a dry run of the batches. The inputs written here are what
+//! `shielded-pool-script payout --input` takes; built against the pool's
+//! tree instead, its proof output carries the calldata of `payout()`.
+//!
+//! Usage:
+//!   SP1_PROVER=mock cargo run --release -p shielded-pool-script --bin payroll [-- --prove]
+//!
+//! Optional env vars:
+//!   PAYROLL_FILE     — CSV of `pubkey,amount` lines: a 32-byte hex shielded pubkey
+//!                      and a USDT amount. If not set, 10 random recipients are paid.
+//!   PAYROLL_DIR      — Where to write the batch inputs (default: fixtures/payroll)
+//!   TREE_LEVELS      — Merkle tree depth (default: 20)
+//!   CHAIN_ID, POOL_ADDRESS — The pool every batch is bound to; without both
+//!                      the batches carry no pool binding (`payout --pool` sets it)
+//!   ENTROPY_SOURCE   — Where keys and blindings come from (see the e2e binary)
+//!   ENTROPY_SEED     — 32-byte hex seed XORed into that source's output
+//!   PROVER_JOBS      — Proofs generated at once with --prove (default: 4 with
+//!                      SP1_PROVER=network, else 1)
+
+use anyhow::{bail, ensure, Context, Result};
+use rand::Rng;
+use shielded_pool_lib::{
+    derive_pubkey,
+    join_split::JoinSplitInput,
+    payout::{PayoutPrivateInputs, PayoutPublicValues, PAYOUT_OUTPUTS},
+    pool_binding, CircuitKind, IncrementalMerkleTree, Note,
+};
+use shielded_pool_script::{
+    entropy, explain_rejection, framed_stdin, key_cache, parse_usdt,
+    proving::{prove_all, ProofJob},
+};
+use sp1_sdk::{include_elf, ProverClient};
+use std::path::PathBuf;
+
+pub const PAYOUT_ELF: &[u8] = include_elf!("payout-program");
+
+/// Recipients per batch: every slot but the one kept for the payer's change.
+const RECIPIENTS_PER_BATCH: usize = PAYOUT_OUTPUTS - 1;
+
+/// Decode a 32-byte hex string (with or without 0x prefix) into [u8; 32].
+fn decode_hex_32(s: &str) -> Result<[u8; 32]> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    let bytes = hex::decode(s).context("invalid hex")?;
+    ensure!(bytes.len() == 32, "expected 32 bytes, got {}", bytes.len());
+    let mut arr = [0u8; 32];
+    arr.copy_from_slice(&bytes);
+    Ok(arr)
+}
+
+/// The pool binding for CHAIN_ID and POOL_ADDRESS, or zero when either is unset.
+fn read_pool_binding() -> Result<[u8; 32]> {
+    let (Ok(chain_id), Ok(pool)) = (std::env::var("CHAIN_ID"), std::env::var("POOL_ADDRESS")) else {
+        return Ok([0u8; 32]);
+    };
+    let chain_id: u64 = chain_id.parse().context("CHAIN_ID must be a number")?;
+    let pool = hex::decode(pool.trim_start_matches("0x")).context("POOL_ADDRESS must be hex")?;
+    let pool: [u8; 20] = pool.try_into().map_err(|_| anyhow::anyhow!("POOL_ADDRESS must be 20 bytes"))?;
+    Ok(pool_binding(chain_id, &pool))
+}
+
+/// `(pubkey, amount)` of every recipient in PAYROLL_FILE, or random ones.
+fn read_payroll(rng: &mut impl Rng) -> Result<Vec<([u8; 32], u64)>> {
+    let Ok(path) = std::env::var("PAYROLL_FILE") else {
+        return Ok((1..=10).map(|i| (derive_pubkey(&rng.gen()), i * 100_000)).collect());
+    };
+    let csv = std::fs::read_to_string(&path).with_context(|| format!("reading {path}"))?;
+    let mut payroll = Vec::new();
+    for (n, line) in csv.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let Some((pubkey, amount)) = line.split_once(',') else {
+            bail!("{path}:{}: expected `pubkey,amount`", n + 1);
+        };
+        let pubkey = decode_hex_32(pubkey.trim()).with_context(|| format!("{path}:{}", n + 1))?;
+        let amount = parse_usdt(amount.trim()).with_context(|| format!("{path}:{}", n + 1))?;
+        ensure!(amount > 0, "{path}:{}: amount must be positive", n + 1);
+        payroll.push((pubkey, amount));
+    }
+    ensure!(!payroll.is_empty(), "{path} has no recipients");
+    Ok(payroll)
+}
+
+fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+    sp1_sdk::utils::setup_logger();
+    let prove = std::env::args().any(|a| a == "--prove");
+    let tree_levels: usize = std::env::var("TREE_LEVELS").unwrap_or_else(|_| "20".into()).parse()?;
+    let out_dir = PathBuf::from(std::env::var("PAYROLL_DIR").unwrap_or_else(|_| "fixtures/payroll".into()));
+    let binding = read_pool_binding()?;
+
+    println!("Entropy: {}", entropy::init_from_env()?.describe());
+    let mut rng = entropy::rng();
+    let payroll = read_payroll(&mut rng)?;
+    let total: u64 = payroll.iter().map(|(_, amount)| amount).sum();
+    let batches: Vec<&[([u8; 32], u64)]> = payroll.chunks(RECIPIENTS_PER_BATCH).collect();
+    println!(
+        "Paying {} recipients {} USDT in {} payout proofs",
+        payroll.len(),
+        (total as f64) / 1e6,
+        batches.len()
+    );
+
+    // 1. Fund the payer
+    let payer_key: [u8; 32] = rng.gen();
+    let payer_pubkey = derive_pubkey(&payer_key);
+    let mut tree = IncrementalMerkleTree::new(tree_levels);
+    let mut funds = Note { amount: total, pubkey: payer_pubkey, blinding: rng.gen() };
+    let mut funds_index = tree.insert(funds.commitment());
+
+    let client = ProverClient::from_env();
+    std::fs::create_dir_all(&out_dir).with_context(|| format!("creating {}", out_dir.display()))?;
+
+    // Every batch's inputs first: the change a batch carries to the next is
+    // known from its inputs, so no batch has to wait for another's proof
+    let mut planned = Vec::new();
+    for (b, batch) in batches.iter().enumerate() {
+        // 2. This batch's recipients, then the change carried to the next
+        let mut outputs: Vec<Note> = batch
+            .iter()
+            .map(|&(pubkey, amount)| Note { amount, pubkey, blinding: rng.gen() })
+            .collect();
+        let paid: u64 = batch.iter().map(|(_, amount)| amount).sum();
+        let change = Note { amount: funds.amount - paid, pubkey: payer_pubkey, blinding: rng.gen() };
+        if change.amount > 0 {
+            outputs.push(change.clone());
+        }
+
+        // 3. Padded inputs, checked before anything runs
+        let input = JoinSplitInput {
+            note: funds.clone(),
+            spending_key: payer_key,
+            merkle_proof: tree.get_proof(funds_index),
+            lock: None,
+        };
+        let mut inputs = PayoutPrivateInputs::padded(tree.get_root(), vec![input], outputs, rng.gen())
+            .map_err(|msg| anyhow::anyhow!("batch {b}: {msg}"))?;
+        inputs.pool_binding = binding;
+        let expected = inputs.check().map_err(|msg| anyhow::anyhow!("batch {b}: invalid payout: {msg}"))?;
+        let path = out_dir.join(format!("batch-{b}.json"));
+        std::fs::write(&path, serde_json::to_string_pretty(&inputs)?)?;
+        println!("\n[batch {b}] {} recipients, {} USDT -> {}", batch.len(), (paid as f64) / 1e6, path.display());
+        println!("    Request: 0x{}", hex::encode(inputs.request_hash()));
+
+        // The contract would insert all eight commitments; the change is the
+        // one leaf the payer spends next.
+        for commitment in expected.out_commitments {
+            let index = tree.insert(commitment);
+            if commitment == change.commitment() {
+                funds_index = index;
+            }
+        }
+        funds = change;
+        planned.push((inputs, expected));
+    }
+
+    // 4. Run the guest on every batch (or prove them all, concurrently)
+    let mut committed: Vec<Vec<u8>> = vec![Vec::new(); planned.len()];
+    if prove {
+        println!();
+        let (pk, _vk) = key_cache::setup(&client, PAYOUT_ELF);
+        let jobs = planned
+            .iter()
+            .enumerate()
+            .map(|(b, (inputs, _))| ProofJob {
+                label: format!("batch {b}"),
+                pk: &pk,
+                elf: PAYOUT_ELF,
+                stdin: framed_stdin(CircuitKind::Payout, inputs),
+            })
+            .collect();
+        prove_all(&client, jobs, |b, proof| {
+            println!("    [batch {b}] Proof: {} bytes", proof.bytes().len());
+            committed[b] = proof.public_values.to_vec();
+            Ok(())
+        })?;
+    } else {
+        for (b, (inputs, _)) in planned.iter().enumerate() {
+            let (public_values, report) = client
+                .execute(PAYOUT_ELF, &framed_stdin(CircuitKind::Payout, inputs))
+                .run()
+                .map_err(|e| {
+                    anyhow::anyhow!("batch {b}: payout guest rejected the inputs: {:#}", explain_rejection(e))
+                })?;
+            println!("\n[batch {b}] Cycles: {}", report.total_instruction_count());
+            committed[b] = public_values.to_vec();
+        }
+    }
+
+    // 5. Compare what the guest committed with what the inputs imply
+    for (b, ((batch, (_, expected)), public_values)) in batches.iter().zip(&planned).zip(&committed).enumerate() {
+        let pv = PayoutPublicValues::decode(public_values).context("malformed payout public values")?;
+        ensure!(pv == *expected, "batch {b}: guest committed other public values than the inputs imply");
+        println!("\n[batch {b}] Committed as expected");
+        for ((pubkey, amount), commitment) in batch.iter().zip(&pv.out_commitments) {
+            let usdt = (*amount as f64) / 1e6;
+            println!("    0x{} gets {} USDT (0x{})", hex::encode(pubkey), usdt, hex::encode(commitment));
+        }
+    }
+
+    ensure!(funds.amount == 0, "{} USDT left unpaid", (funds.amount as f64) / 1e6);
+    println!("\nPayroll done: {} USDT to {} recipients", (total as f64) / 1e6, payroll.len());
+    Ok(())
+}
@@ -0,0 +1,297 @@
+//! Relayer: submits other people's transfer and withdraw proofs from a
+//! funded key, so using the pool needs no gas.
+//!
+//! A user proves as usual, committing this relayer's address and fee in a
+//! withdrawal (see `/info`), and posts the proof instead of sending it. The
+//! relayer checks it locally first (`shielded_pool_script::relayer`: pool
+//! binding, tree depth, deadlines, fee, encrypted outputs, and the Groth16
+//! proof against the pool's vkey), then against the pool (known root,
+//! unspent nullifiers), simulates the call, and only then sends it. A
+//! submission whose nullifiers are already being relayed is refused, so a
+//! retried request cannot make the relayer pay for a revert.
+//!
+//! Routes (JSON, hex 0x-prefixed or not):
+//!   GET  /health
+//!   GET  /info     — relayer address, minimum fee, pool, chain id and tree depth
+//!   POST /relay    — `{proof, publicValues, encryptedOutputs}`; a transfer
+//!                    posts its two encrypted outputs, a withdrawal its
+//!                    encrypted change or none. Answers `{txHash, block}`
+//!                    once the transaction is mined, or `{error}`
+//!
+//! Usage:
+//!   cargo run --release -p shielded-pool-script --bin relayer
+//!
+//! Required env vars (from .env):
+//!   RPC_URL               — Plasma RPC endpoint
+//!   POOL_ADDRESS          — Deployed ShieldedPool address
+//!   RELAYER_PRIVATE_KEY   — The relayer's funded key (default: PRIVATE_KEY)
+//!
+//! Optional env vars:
+//!   RELAYER_PORT          — Port to listen on (default: 3003)
+//!   RELAYER_MIN_FEE       — Smallest withdrawal fee taken, in USDT (default: 0).
+//!                           Transfers commit no fee, so they are only relayed at 0
+//!   SP1_PROVER            — `mock` relays mock proofs unverified, for a pool on
+//!                           the mock verifier (the integration tests)
+
+use alloy::{
+    eips::BlockNumberOrTag,
+    network::ReceiptResponse,
+    primitives::{Address, Bytes, FixedBytes},
+    providers::{DynProvider, Provider, ProviderBuilder},
+    rpc::types::{TransactionInput, TransactionRequest},
+    signers::local::PrivateKeySigner,
+    sol,
+    sol_types::SolCall,
+};
+use anyhow::{Context, Result};
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use shielded_pool_lib::pool_binding;
+use shielded_pool_script::{
+    chains::FinalityPolicy,
+    parse_usdt,
+    relayer::{Relay, RelayPolicy, Submission},
+};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tokio::net::TcpListener;
+
+sol! {
+    #[sol(rpc)]
+    interface IShieldedPool {
+        function privateTransfer(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedOutput1, bytes calldata encryptedOutput2) external;
+        function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
+        function isKnownRoot(bytes32 root) external view returns (bool);
+        function isSpent(bytes32 nullifier) external view returns (bool);
+        function levels() external view returns (uint32);
+        function TRANSFER_VKEY() external view returns (bytes32);
+        function WITHDRAW_VKEY() external view returns (bytes32);
+    }
+}
+
+struct Relayer {
+    provider: DynProvider,
+    pool: Address,
+    chain_id: u64,
+    policy: RelayPolicy,
+    finality: FinalityPolicy,
+    /// Nullifiers of the submissions being relayed
+    in_flight: Mutex<HashSet<[u8; 32]>>,
+    /// Held while sending, so transactions get their nonces in order
+    sending: tokio::sync::Mutex<()>,
+}
+
+/// Releases a submission's nullifiers once it is relayed or refused.
+struct InFlight<'a> {
+    relayer: &'a Relayer,
+    nullifiers: Vec<[u8; 32]>,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.relayer.in_flight.lock().unwrap();
+        for nullifier in &self.nullifiers {
+            in_flight.remove(nullifier);
+        }
+    }
+}
+
+/// A refused or failed relay, as the HTTP status and message returned.
+struct RelayError(StatusCode, String);
+
+impl RelayError {
+    fn rejected(e: impl std::fmt::Display) -> Self {
+        RelayError(StatusCode::BAD_REQUEST, e.to_string())
+    }
+
+    fn failed(e: impl std::fmt::Display) -> Self {
+        RelayError(StatusCode::BAD_GATEWAY, e.to_string())
+    }
+}
+
+impl IntoResponse for RelayError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Relayed {
+    tx_hash: String,
+    block: Option<u64>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let private_key = std::env::var("RELAYER_PRIVATE_KEY")
+        .or_else(|_| std::env::var("PRIVATE_KEY"))
+        .context("RELAYER_PRIVATE_KEY not set")?;
+    let signer: PrivateKeySigner = private_key.parse()?;
+    let address = signer.address();
+    let port: u16 = match std::env::var("RELAYER_PORT") {
+        Ok(port) => port.parse().context("RELAYER_PORT is not valid")?,
+        Err(_) => 3003,
+    };
+    let min_fee = match std::env::var("RELAYER_MIN_FEE") {
+        Ok(fee) => parse_usdt(&fee).context("RELAYER_MIN_FEE is not valid")?,
+        Err(_) => 0,
+    };
+
+    let provider = ProviderBuilder::new().wallet(signer).connect_http(rpc_url.parse()?).erased();
+    let chain_id = provider.get_chain_id().await?;
+    let finality = FinalityPolicy::for_chain(chain_id)?;
+    provider.client().set_poll_interval(finality.poll_interval());
+    let pool = IShieldedPool::new(pool_addr, &provider);
+    let policy = RelayPolicy {
+        relayer: address.0 .0,
+        min_fee,
+        pool_binding: pool_binding(chain_id, &pool_addr.0 .0),
+        tree_levels: pool.levels().call().await?.into(),
+        transfer_vkey: pool.TRANSFER_VKEY().call().await?.to_string(),
+        withdraw_vkey: pool.WITHDRAW_VKEY().call().await?.to_string(),
+        mock_proofs: std::env::var("SP1_PROVER").is_ok_and(|p| p == "mock"),
+    };
+
+    println!("\n=== Shielded Pool Relayer ===\n");
+    println!("Pool:     {pool_addr} (chain {chain_id})");
+    println!("Relayer:  {address}");
+    println!("Min fee:  {} USDT", (min_fee as f64) / 1e6);
+    if policy.mock_proofs {
+        println!("⚠ SP1_PROVER=mock: mock proofs are relayed unverified (for pools on the mock verifier)");
+    }
+    let balance = provider.get_balance(address).await?;
+    if balance.is_zero() {
+        eprintln!("⚠ The relayer has no balance to pay gas with");
+    }
+
+    let relayer = Arc::new(Relayer {
+        provider,
+        pool: pool_addr,
+        chain_id,
+        policy,
+        finality,
+        in_flight: Mutex::new(HashSet::new()),
+        sending: tokio::sync::Mutex::new(()),
+    });
+    let app = Router::new()
+        .route("/health", get(|| async { Json(serde_json::json!({ "status": "ok" })) }))
+        .route("/info", get(info))
+        .route("/relay", post(relay).options(|| async { StatusCode::NO_CONTENT }))
+        .layer(axum::middleware::map_response(allow_cors))
+        .with_state(relayer);
+
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    println!("Serving on http://0.0.0.0:{port}");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Let wallets in the browser post to the relayer.
+async fn allow_cors(mut response: Response) -> Response {
+    let headers = response.headers_mut();
+    headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_static("GET, POST"));
+    headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, HeaderValue::from_static("content-type"));
+    response
+}
+
+async fn info(State(relayer): State<Arc<Relayer>>) -> Json<serde_json::Value> {
+    let policy = &relayer.policy;
+    Json(serde_json::json!({
+        "relayer": format!("0x{}", hex::encode(policy.relayer)),
+        "minFee": policy.min_fee,
+        "pool": relayer.pool.to_string(),
+        "chainId": relayer.chain_id,
+        "treeLevels": policy.tree_levels,
+        "relaysTransfers": policy.min_fee == 0,
+    }))
+}
+
+async fn relay(
+    State(relayer): State<Arc<Relayer>>,
+    Json(submission): Json<Submission>,
+) -> Result<Json<Relayed>, RelayError> {
+    let latest = relayer
+        .provider
+        .get_block_by_number(BlockNumberOrTag::Latest)
+        .await
+        .map_err(RelayError::failed)?
+        .ok_or_else(|| RelayError::failed("the node returned no latest block"))?;
+    let relay = relayer
+        .policy
+        .check(&submission, latest.header.timestamp)
+        .map_err(|e| RelayError::rejected(format!("{e:#}")))?;
+
+    let nullifiers = relay.nullifiers();
+    {
+        let mut in_flight = relayer.in_flight.lock().unwrap();
+        if nullifiers.iter().any(|n| in_flight.contains(n)) {
+            return Err(RelayError(StatusCode::CONFLICT, "a submission spending these notes is being relayed".into()));
+        }
+        in_flight.extend(nullifiers.iter().copied());
+    }
+    let _in_flight = InFlight { relayer: &relayer, nullifiers: nullifiers.clone() };
+
+    let pool = IShieldedPool::new(relayer.pool, &relayer.provider);
+    let known = pool.isKnownRoot(FixedBytes(relay.root())).call().await.map_err(RelayError::failed)?;
+    if !known {
+        return Err(RelayError::rejected("the proof's root is not one the pool still accepts; prove again"));
+    }
+    for nullifier in &nullifiers {
+        if pool.isSpent(FixedBytes(*nullifier)).call().await.map_err(RelayError::failed)? {
+            let spent = format!("nullifier 0x{} is already spent", hex::encode(nullifier));
+            return Err(RelayError(StatusCode::CONFLICT, spent));
+        }
+    }
+
+    let calldata = match &relay {
+        Relay::Transfer { proof, public_values, encrypted_outputs, .. } => IShieldedPool::privateTransferCall {
+            proof: Bytes::copy_from_slice(proof),
+            publicValues: Bytes::copy_from_slice(public_values),
+            encryptedOutput1: Bytes::copy_from_slice(&encrypted_outputs[0]),
+            encryptedOutput2: Bytes::copy_from_slice(&encrypted_outputs[1]),
+        }
+        .abi_encode(),
+        Relay::Withdraw { proof, public_values, encrypted_change, .. } => IShieldedPool::withdrawCall {
+            proof: Bytes::copy_from_slice(proof),
+            publicValues: Bytes::copy_from_slice(public_values),
+            encryptedChange: Bytes::copy_from_slice(encrypted_change),
+        }
+        .abi_encode(),
+    };
+    let tx = TransactionRequest::default().to(relayer.pool).input(TransactionInput::new(calldata.into()));
+    // The pool's own checks, for free: a revert here costs the relayer nothing
+    relayer
+        .provider
+        .call(tx.clone())
+        .await
+        .map_err(|e| RelayError::rejected(format!("the pool would reject the {}: {e}", relay.kind())))?;
+
+    let pending = {
+        let _sending = relayer.sending.lock().await;
+        relayer.provider.send_transaction(tx).await.map_err(RelayError::failed)?
+    };
+    println!("    Relaying {} (fee {} USDT): {}", relay.kind(), (relay.fee() as f64) / 1e6, pending.tx_hash());
+    let receipt = pending
+        .with_required_confirmations(relayer.finality.required_confirmations())
+        .get_receipt()
+        .await
+        .map_err(RelayError::failed)?;
+    if !receipt.status() {
+        return Err(RelayError::failed(format!("tx {} reverted", receipt.transaction_hash)));
+    }
+    Ok(Json(Relayed { tx_hash: receipt.transaction_hash.to_string(), block: receipt.block_number() }))
+}
@@ -0,0 +1,504 @@
+//! Sweep idle notes: consolidate or withdraw every note the wallet's idle
+//! policy reports, in one run.
+//!
+//! With the `consolidate` policy, idle notes spendable by the same key are
+//! merged pairwise by private transfers into one note back to that key (the
+//! second output is a zero-value note). A note without a partner is left as
+//! is. With `exit`, each idle note is withdrawn in full to the caller's
+//! address. Swept notes are removed from the wallet; merged notes are added
+//! with a fresh timestamp.
+//!
+//! The policy is set with `shielded-pool note idle --remind-after-days N --sweep ACTION`
+//! (see `shielded_pool_script::idle`).
+//!
+//! Usage:
+//!   SP1_PROVER=network cargo run --release -p shielded-pool-script --bin sweep-idle [-- --strict]
+//!
+//! Required env vars (from .env):
+//!   RPC_URL               — Plasma RPC endpoint
+//!   PRIVATE_KEY           — Funded wallet private key (pays gas, receives exits)
+//!   POOL_ADDRESS          — Deployed ShieldedPool address
+//!   NETWORK_PRIVATE_KEY   — Succinct Prover Network API key
+//!
+//! Optional env vars:
+//!   WALLET_FILE           — Path to the wallet (default: fixtures/wallet.db)
+//!   WALLET_PASSPHRASE     — Passphrase for encrypted wallet keys (prompted if not set)
+//!   SWEEP_ACTION          — consolidate | exit, overriding the wallet's policy
+//!   SWEEP_DRY_RUN         — If set, print the plan without proving or sending anything
+//!   RECIPIENT_ADDRESS     — Where `exit` withdraws to (default: PRIVATE_KEY's address)
+//!   ENTROPY_SOURCE, ENTROPY_SEED — where blindings come from (see `shielded_pool_script::entropy`)
+//!   DEPLOY_BLOCK, TREE_LEVELS, TREE_CHECKPOINT, TREE_STORE,
+//!   MULTICALL_ADDRESS, MULTICALL_CHUNK_SIZE, STRICT, PROVER_JOBS — as for the exit script
+
+use alloy::{
+    eips::{BlockId, BlockNumberOrTag},
+    primitives::{Address, Bytes},
+    providers::{Provider, ProviderBuilder},
+    rpc::types::TransactionReceipt,
+    signers::local::PrivateKeySigner,
+    sol,
+};
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+use rand::Rng;
+use shielded_pool_lib::{
+    pool_binding, CancellableLock, CircuitKind, Note, SpenderKey, SpendingKey, TransferPrivateInputs,
+    WithdrawPrivateInputs,
+};
+use shielded_pool_script::{
+    audit::{AuditAction, AuditEntry, AuditLog, AuditNote},
+    decode_hex_fixed, encrypt_note, entropy, framed_stdin,
+    idle::{pair_by_owner, unix_now, SweepAction},
+    key_cache,
+    multicall::{BatchConfig, PoolViews},
+    proving::{prove_all, ProofJob},
+    strict::{self, StrictViolation},
+    sync::{replay_events, replay_nullifiers, sync_pool_tree, PoolSync, TreeSource},
+    wallet::{wallet_path, WalletNote, WalletState},
+};
+use sp1_sdk::{include_elf, ProverClient, SP1ProofWithPublicValues};
+
+pub const TRANSFER_ELF: &[u8] = include_elf!("transfer-program");
+pub const WITHDRAW_ELF: &[u8] = include_elf!("withdraw-program");
+
+sol! {
+    #[sol(rpc)]
+    interface IShieldedPool {
+        function privateTransfer(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedOutput1, bytes calldata encryptedOutput2) external;
+        function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
+        function getLeafCount() external view returns (uint32);
+    }
+}
+
+/// An idle note this wallet can spend.
+struct SweepNote {
+    /// Hex commitment, as stored in the wallet
+    commitment: String,
+    note: Note,
+    spending_key: SpendingKey,
+    /// What the transfer and withdraw circuits open the note with
+    spender: SpenderKey,
+    lock: Option<CancellableLock>,
+    leaf_index: u32,
+    label: String,
+}
+
+/// Drop the notes with these commitments from the wallet; returns how many went.
+fn remove_notes(wallet: &mut WalletState, commitments: &[String]) -> usize {
+    let before = wallet.notes.len();
+    wallet.notes.retain(|n| !commitments.contains(&n.commitment));
+    before - wallet.notes.len()
+}
+
+/// Leaf index of `commitment`, inserted by a transaction in `block`: the
+/// pool's leaf count before the block plus its position among the block's
+/// insertions.
+async fn leaf_index_in_block<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    block: u64,
+    commitment: [u8; 32],
+) -> Result<u32> {
+    let pool = IShieldedPool::new(pool_addr, provider);
+    let before = match block.checked_sub(1) {
+        Some(parent) => pool.getLeafCount().block(BlockId::number(parent)).call().await?,
+        None => 0,
+    };
+    let replay = replay_events(provider, pool_addr, block, block).await?;
+    let position = replay
+        .leaves
+        .iter()
+        .position(|leaf| *leaf == commitment)
+        .with_context(|| format!("commitment 0x{} not inserted in block {block}", hex::encode(commitment)))?;
+    Ok(before + position as u32)
+}
+
+/// Encrypt `note` to the viewing key stored next to the key that owns it, so
+/// other clients of the wallet find it by scanning. Empty if there is none.
+fn encrypt_for_owner(wallet: &WalletState, note: &Note) -> Result<Vec<u8>> {
+    let owner = hex::encode(note.pubkey);
+    match wallet
+        .spending_keys
+        .iter()
+        .find(|k| k.pubkey.eq_ignore_ascii_case(&owner) && !k.viewing_pubkey.is_empty())
+    {
+        Some(k) => {
+            let viewing: [u8; 32] = decode_hex_fixed(&k.viewing_pubkey).context("invalid viewing pubkey")?;
+            Ok(encrypt_note(note, &crypto_box::PublicKey::from(viewing)))
+        }
+        None => {
+            strict::fallback(StrictViolation::MissingViewingKey { owner: owner.clone() })?;
+            println!("    ⚠ No viewing key for 0x{owner}; the merged note is only recorded in the wallet file");
+            Ok(Vec::new())
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+    sp1_sdk::utils::setup_logger();
+
+    println!("\n=== Shielded Pool — Sweep Idle Notes ===\n");
+
+    // ── Load config and wallet ─────────────────────────────────────────
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let private_key = std::env::var("PRIVATE_KEY").context("PRIVATE_KEY not set")?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS")
+        .context("POOL_ADDRESS not set")?
+        .parse()?;
+    let dry_run = std::env::var("SWEEP_DRY_RUN").is_ok();
+    let entropy = entropy::init_from_env()?;
+
+    let wallet_path = wallet_path();
+    println!("Wallet file:  {}", wallet_path.display());
+    println!("Entropy:      {}", entropy.describe());
+    let mut wallet = WalletState::load(&wallet_path)?;
+    let policy = wallet.idle_policy();
+    let action = match std::env::var("SWEEP_ACTION") {
+        Ok(s) => SweepAction::from_str(&s, true).map_err(|e| anyhow::anyhow!("SWEEP_ACTION: {e}"))?,
+        Err(_) => policy.sweep,
+    };
+    println!("Policy:       idle after {} days, sweep: {:?}\n", policy.remind_after_days, action);
+
+    let idle = policy.idle_notes(&wallet, unix_now());
+    policy.print_reminders(&wallet, &idle);
+    if idle.is_empty() {
+        println!("No idle notes. Nothing to sweep.");
+        return Ok(());
+    }
+    if action == SweepAction::Off {
+        println!("\nSweeping is off for this wallet; set SWEEP_ACTION or `note idle --sweep` to sweep.");
+        return Ok(());
+    }
+
+    // ── Connect ────────────────────────────────────────────────────────
+    let signer: PrivateKeySigner = private_key.parse()?;
+    let withdraw_to: Address = match std::env::var("RECIPIENT_ADDRESS") {
+        Ok(addr) => addr.parse()?,
+        Err(_) => signer.address(),
+    };
+    let provider = ProviderBuilder::new().wallet(signer).connect_http(rpc_url.parse()?);
+    let pool = IShieldedPool::new(pool_addr, &provider);
+    let chain_id = provider.get_chain_id().await?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
+
+    let spending_keys = wallet.unlock_spending_keys()?;
+
+    // ── Keep the idle notes we can still spend ─────────────────────────
+    println!("\n[1] Checking idle notes...");
+    let mut notes: Vec<SweepNote> = Vec::new();
+    let mut nullifiers: Vec<[u8; 32]> = Vec::new();
+    let mut deadlines: Vec<Option<u64>> = Vec::new();
+    for n in &idle {
+        let wn = &wallet.notes[n.index];
+        let Some((sk, spender, auth)) = wn.authorize_spender(&spending_keys)? else {
+            strict::fallback(StrictViolation::NoSpendingKey { label: wn.label.clone() })?;
+            println!("    {} — no spending key (skip)", wn.label);
+            continue;
+        };
+        nullifiers.push(auth.nullifier);
+        deadlines.push(auth.reclaim_deadline);
+        notes.push(SweepNote {
+            commitment: wn.commitment.clone(),
+            note: wn.to_note()?,
+            spending_key: sk,
+            spender,
+            lock: wn.to_lock()?,
+            leaf_index: wn.leaf_index,
+            label: wn.label.clone(),
+        });
+    }
+
+    // ── Rebuild the tree ───────────────────────────────────────────────
+    println!("\n[2] Building Merkle tree from on-chain events...");
+    let source = TreeSource::from_env(chain_id, pool_addr)?;
+    let finality = source.finality;
+    provider.client().set_poll_interval(finality.poll_interval());
+    let PoolSync { tree, status, block, mut roots } = sync_pool_tree(&provider, &views, pool_addr, chain_id, &source).await?;
+    // Spent checks come from the events when the sync is complete
+    let spent = if status.is_complete() {
+        let set = replay_nullifiers(&provider, pool_addr, source.deploy_block, block).await?;
+        nullifiers.iter().map(|n| set.contains(n)).collect()
+    } else {
+        views.is_spent_batch(&nullifiers).await?
+    };
+    let now = provider
+        .get_block_by_number(BlockNumberOrTag::Number(block))
+        .await?
+        .context("sync block not found")?
+        .header
+        .timestamp;
+
+    let mut spent_notes: Vec<String> = Vec::new();
+    let mut sweepable: Vec<SweepNote> = Vec::new();
+    for ((sn, is_spent), deadline) in notes.into_iter().zip(spent).zip(deadlines) {
+        let refusal = if is_spent {
+            Some("already spent".to_string())
+        } else {
+            status.refusal(sn.leaf_index).or_else(|| {
+                deadline
+                    .filter(|&d| now > d)
+                    .map(|d| format!("reclaim window closed at {d} (recipient can still claim)"))
+            })
+        };
+        match refusal {
+            Some(reason) => {
+                println!("    {} — {reason}", sn.label);
+                if is_spent {
+                    spent_notes.push(sn.commitment.clone());
+                }
+            }
+            None => sweepable.push(sn),
+        }
+    }
+
+    // ── Plan ───────────────────────────────────────────────────────────
+    println!("\n[3] Plan ({action:?}):");
+    let (pairs, singles) = match action {
+        SweepAction::Consolidate => {
+            let owners: Vec<(usize, [u8; 32])> =
+                sweepable.iter().enumerate().map(|(i, sn)| (i, sn.spending_key.pubkey())).collect();
+            pair_by_owner(&owners)
+        }
+        _ => (Vec::new(), (0..sweepable.len()).collect()),
+    };
+    for [a, b] in &pairs {
+        let (a, b) = (&sweepable[*a], &sweepable[*b]);
+        println!(
+            "    merge '{}' + '{}' → {} USDT",
+            a.label,
+            b.label,
+            (a.note.amount + b.note.amount) as f64 / 1e6
+        );
+    }
+    for &i in &singles {
+        let sn = &sweepable[i];
+        match action {
+            SweepAction::Exit => println!("    withdraw '{}' ({} USDT) to {withdraw_to}", sn.label, sn.note.amount as f64 / 1e6),
+            _ => println!("    keep '{}' (no other idle note of the same key)", sn.label),
+        }
+    }
+    if dry_run {
+        println!("\nSWEEP_DRY_RUN is set; nothing was sent.");
+        return Ok(());
+    }
+    let withdrawals: Vec<usize> = if action == SweepAction::Exit { singles } else { Vec::new() };
+    if pairs.is_empty() && withdrawals.is_empty() {
+        println!("\nNothing to sweep.");
+    }
+
+    // ── Sweep ──────────────────────────────────────────────────────────
+    // Every proof is built against the synced root; the pool keeps the last
+    // ROOT_HISTORY_SIZE roots, so a long sweep may have to be re-run.
+    let sp1_client = ProverClient::from_env();
+    let root = tree.get_root();
+    let binding = pool_binding(chain_id, &pool_addr.0 .0);
+    let audit_entry = |action, receipt: &TransactionReceipt| -> Result<AuditEntry> {
+        let block = strict::block_number(receipt.block_number, || {
+            format!("receipt for tx {}", receipt.transaction_hash)
+        })?;
+        Ok(AuditEntry::new(
+            action,
+            "sweep-idle",
+            chain_id,
+            &pool_addr.to_string(),
+            &receipt.transaction_hash.to_string(),
+            block,
+        ))
+    };
+    let mut rng = entropy::rng();
+    let mut removed = 0;
+    let mut added = 0;
+    let mut step = 4;
+
+    // Spent notes have nothing left to sweep
+    if !spent_notes.is_empty() {
+        removed += remove_notes(&mut wallet, &spent_notes);
+        wallet.save(&wallet_path)?;
+    }
+
+    // Inputs first: the proofs do not depend on each other, so they are
+    // generated together and the transactions sent afterwards
+    let merges = pairs
+        .iter()
+        .map(|[a, b]| {
+            let (a, b) = (&sweepable[*a], &sweepable[*b]);
+            let owner = a.spending_key.pubkey();
+            let output_notes = [
+                Note { amount: a.note.amount + b.note.amount, pubkey: owner, blinding: rng.gen() },
+                Note { amount: 0, pubkey: owner, blinding: rng.gen() },
+            ];
+            let mut inputs = TransferPrivateInputs {
+                input_notes: [a.note.clone(), b.note.clone()],
+                spender_keys: [a.spender, b.spender],
+                merkle_proofs: [
+                    tree.try_get_proof(a.leaf_index).map_err(anyhow::Error::msg)?,
+                    tree.try_get_proof(b.leaf_index).map_err(anyhow::Error::msg)?,
+                ],
+                output_notes: output_notes.clone(),
+                root,
+                locks: [a.lock, b.lock],
+                association: None,
+                pool_binding: binding,
+                output_encryption: None,
+                multisig: [None, None],
+                spend_signatures: Vec::new(),
+            };
+            for key in [a.spending_key, b.spending_key] {
+                inputs.sign(key.as_bytes()).map_err(anyhow::Error::msg)?;
+            }
+            Ok((output_notes, inputs))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let exits = withdrawals
+        .iter()
+        .map(|&i| {
+            let sn = &sweepable[i];
+            let mut inputs = WithdrawPrivateInputs {
+                input_note: sn.note.clone(),
+                spender_key: sn.spender,
+                merkle_proof: tree.try_get_proof(sn.leaf_index).map_err(anyhow::Error::msg)?,
+                root,
+                recipient: withdraw_to.0 .0,
+                withdraw_amount: sn.note.amount,
+                change_note: None,
+                lock: sn.lock,
+                fee: 0,
+                relayer: [0u8; 20],
+                association: None,
+                pool_binding: binding,
+                deadline: 0,
+                call_target: [0u8; 20],
+                calldata_hash: [0u8; 32],
+                multisig: None,
+                spend_signatures: Vec::new(),
+            };
+            inputs.sign(sn.spending_key.as_bytes()).map_err(anyhow::Error::msg)?;
+            Ok(inputs)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let transfer_pk = (!merges.is_empty()).then(|| key_cache::setup(&sp1_client, TRANSFER_ELF).0);
+    let withdraw_pk = (!exits.is_empty()).then(|| key_cache::setup(&sp1_client, WITHDRAW_ELF).0);
+    let mut jobs = Vec::new();
+    if let Some(pk) = &transfer_pk {
+        for ([a, b], (_, inputs)) in pairs.iter().zip(&merges) {
+            jobs.push(ProofJob {
+                label: format!("merge '{}' + '{}'", sweepable[*a].label, sweepable[*b].label),
+                pk,
+                elf: TRANSFER_ELF,
+                stdin: framed_stdin(CircuitKind::Transfer, inputs),
+            });
+        }
+    }
+    if let Some(pk) = &withdraw_pk {
+        for (&i, inputs) in withdrawals.iter().zip(&exits) {
+            jobs.push(ProofJob {
+                label: format!("withdraw '{}'", sweepable[i].label),
+                pk,
+                elf: WITHDRAW_ELF,
+                stdin: framed_stdin(CircuitKind::Withdraw, inputs),
+            });
+        }
+    }
+    if !jobs.is_empty() && !roots.is_known_root(&views, root).await? {
+        strict::fallback(StrictViolation::RootNoLongerKnown)?;
+        println!("\n    ⚠ Synced root is no longer known on-chain; re-run to sweep");
+        jobs.clear();
+    }
+    let mut proofs: Vec<Option<SP1ProofWithPublicValues>> = (0..jobs.len()).map(|_| None).collect();
+    if !jobs.is_empty() {
+        println!();
+        prove_all(&sp1_client, jobs, |job, proof| {
+            proofs[job] = Some(proof);
+            Ok(())
+        })?;
+    }
+    let mut merge_proofs: Vec<SP1ProofWithPublicValues> = proofs.into_iter().flatten().collect();
+    let exit_proofs = merge_proofs.split_off(merge_proofs.len().min(merges.len()));
+
+    for (([a, b], (output_notes, inputs)), proof) in pairs.iter().zip(&merges).zip(merge_proofs) {
+        let (a, b) = (&sweepable[*a], &sweepable[*b]);
+        println!("\n[{step}] Merging '{}' + '{}'", a.label, b.label);
+        step += 1;
+        if !roots.is_known_root(&views, root).await? {
+            strict::fallback(StrictViolation::RootNoLongerKnown)?;
+            println!("    ⚠ Synced root is no longer known on-chain; re-run to sweep the rest");
+            break;
+        }
+        let public_values = proof.public_values.to_vec();
+
+        println!("    Submitting private transfer...");
+        let receipt = pool
+            .privateTransfer(
+                Bytes::from(proof.bytes()),
+                Bytes::from(public_values.clone()),
+                Bytes::from(encrypt_for_owner(&wallet, &output_notes[0])?),
+                Bytes::from(encrypt_for_owner(&wallet, &output_notes[1])?),
+            )
+            .send()
+            .await?
+            .with_required_confirmations(finality.required_confirmations())
+            .get_receipt()
+            .await?;
+        ensure!(receipt.status(), "transfer tx {} reverted", receipt.transaction_hash);
+        println!("    ✓ Tx: {}", receipt.transaction_hash);
+
+        // Record the merged note right away, so an error later on cannot lose it
+        let block = receipt.block_number.context("receipt has no block number")?;
+        let leaf_index = leaf_index_in_block(&provider, pool_addr, block, output_notes[0].commitment()).await?;
+        println!("    Merged note at leaf {leaf_index}");
+        removed += remove_notes(&mut wallet, &[a.commitment.clone(), b.commitment.clone()]);
+        let merged_label = format!("{}+{}", a.label, b.label);
+        wallet.notes.push(WalletNote::new(&merged_label, &output_notes[0], leaf_index));
+        added += 1;
+        let entry = audit_entry(AuditAction::Transfer, &receipt)?
+            .with_spent(AuditNote::new(&a.label, &a.note, Some(a.leaf_index)))
+            .with_spent(AuditNote::new(&b.label, &b.note, Some(b.leaf_index)))
+            .with_created(AuditNote::new(&merged_label, &output_notes[0], Some(leaf_index)))
+            .with_created(AuditNote::new(&format!("{merged_label} (zero)"), &output_notes[1], None))
+            .with_proof(&proof.bytes(), &public_values)
+            .with_request(inputs.request_hash());
+        wallet.record_tx(&entry);
+        wallet.save(&wallet_path)?;
+        AuditLog::record(entry)?;
+    }
+
+    for ((&i, inputs), proof) in withdrawals.iter().zip(&exits).zip(exit_proofs) {
+        let sn = &sweepable[i];
+        println!("\n[{step}] Withdrawing '{}' — {} USDT", sn.label, sn.note.amount as f64 / 1e6);
+        step += 1;
+        if !roots.is_known_root(&views, root).await? {
+            strict::fallback(StrictViolation::RootNoLongerKnown)?;
+            println!("    ⚠ Synced root is no longer known on-chain; re-run to sweep the rest");
+            break;
+        }
+        let public_values = proof.public_values.to_vec();
+
+        println!("    Submitting withdraw...");
+        let receipt = pool
+            .withdraw(Bytes::from(proof.bytes()), Bytes::from(public_values.clone()), Bytes::new())
+            .send()
+            .await?
+            .with_required_confirmations(finality.required_confirmations())
+            .get_receipt()
+            .await?;
+        ensure!(receipt.status(), "withdraw tx {} reverted", receipt.transaction_hash);
+        println!("    ✓ Tx: {}", receipt.transaction_hash);
+        removed += remove_notes(&mut wallet, std::slice::from_ref(&sn.commitment));
+        let entry = audit_entry(AuditAction::Withdraw, &receipt)?
+            .with_spent(AuditNote::new(&sn.label, &sn.note, Some(sn.leaf_index)))
+            .with_public_amount(sn.note.amount)
+            .with_proof(&proof.bytes(), &public_values)
+            .with_request(inputs.request_hash());
+        wallet.record_tx(&entry);
+        wallet.save(&wallet_path)?;
+        AuditLog::record(entry)?;
+    }
+
+    println!("\n=== Sweep Complete ===");
+    println!("Removed {removed} spent note(s) from the wallet, added {added} merged note(s)");
+    println!("Wallet: {}\n", wallet_path.display());
+    Ok(())
+}
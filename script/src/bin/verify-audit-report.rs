@@ -0,0 +1,140 @@
+//! Verify an audit report exported with `shielded-pool audit export`.
+//!
+//! Offline, checks that the hash chain is intact and ends at the recorded
+//! head, that every proof's public values match the notes recorded for it,
+//! and that no note is created or spent twice.
+//!
+//! With RPC_URL set, also re-checks every entry against the chain:
+//!   - the transaction exists, targets the recorded pool, succeeded and was
+//!     mined in the recorded block
+//!   - deposits carry the recorded commitment and amount
+//!   - transfers and withdrawals carry a proof with the recorded hash and
+//!     exactly the recorded public values
+//!
+//! Usage:
+//!   cargo run --release -p shielded-pool-script --bin verify-audit-report -- [REPORT] [--strict]
+//!
+//! REPORT defaults to fixtures/audit-report.json.
+//!
+//! Optional env vars (from .env):
+//!   RPC_URL               — Plasma RPC endpoint; without it only the offline checks run
+//!                           (an error in strict mode)
+//!   STRICT                — 1 to fail on skipped checks, as with --strict (default: on when CI is set)
+
+use alloy::{
+    consensus::Transaction as _,
+    primitives::{Address, B256, U256},
+    providers::{Provider, ProviderBuilder},
+    sol,
+    sol_types::SolCall,
+};
+use anyhow::{ensure, Context, Result};
+use shielded_pool_lib::keccak256;
+use shielded_pool_script::{
+    audit::{AuditAction, AuditEntry, AuditReport},
+    decode_hex,
+    strict::{self, StrictViolation},
+};
+
+sol! {
+    interface IShieldedPool {
+        function deposit(bytes32 commitment, uint256 amount, bytes calldata encryptedData) external;
+        function privateTransfer(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedOutput1, bytes calldata encryptedOutput2) external;
+        function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+
+    let default_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("fixtures/audit-report.json");
+    let report_path = strict::args()
+        .next()
+        .map(std::path::PathBuf::from)
+        .unwrap_or(default_path);
+
+    println!("\n=== Verify Audit Report ===\n");
+    println!("Report: {}", report_path.display());
+
+    let report = AuditReport::load(&report_path)?;
+    report.check_offline().context("report is internally inconsistent")?;
+    println!(
+        "Offline checks: OK ({} entries, {} notes, head 0x{})",
+        report.entries.len(),
+        report.notes.len(),
+        report.head
+    );
+
+    let Ok(rpc_url) = std::env::var("RPC_URL") else {
+        strict::fallback(StrictViolation::OnChainChecksSkipped)?;
+        println!("\nRPC_URL not set; on-chain checks skipped\n");
+        return Ok(());
+    };
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+
+    for entry in &report.entries {
+        let name = format!("#{} {:?}", entry.seq, entry.action);
+        println!("\n[{name}] {} tx {}", entry.source, entry.tx_hash);
+        ensure!(
+            entry.chain_id == chain_id,
+            "{name}: recorded on chain {}, connected to chain {chain_id}",
+            entry.chain_id
+        );
+        let pool_addr: Address = entry.pool.parse().with_context(|| format!("{name}: invalid pool address"))?;
+        let tx_hash: B256 = entry.tx_hash.parse().with_context(|| format!("{name}: invalid tx hash"))?;
+
+        let tx = provider
+            .get_transaction_by_hash(tx_hash)
+            .await?
+            .with_context(|| format!("{name}: transaction not found"))?;
+        ensure!(tx.to() == Some(pool_addr), "{name}: transaction does not target the pool");
+        check_calldata(entry, tx.input()).with_context(|| format!("{name}: calldata"))?;
+        println!("    calldata matches");
+
+        let receipt = provider
+            .get_transaction_receipt(tx_hash)
+            .await?
+            .with_context(|| format!("{name}: receipt not found"))?;
+        ensure!(receipt.status(), "{name}: transaction reverted");
+        ensure!(
+            receipt.block_number == Some(entry.block_number),
+            "{name}: mined in block {:?}, report says {}",
+            receipt.block_number,
+            entry.block_number
+        );
+        println!("    succeeded in block {}", entry.block_number);
+    }
+
+    println!("\n=== Audit report verified: {} entries up to 0x{} ===\n", report.entries.len(), report.head);
+    Ok(())
+}
+
+/// Check that the call the pool received is the one the entry records.
+fn check_calldata(entry: &AuditEntry, input: &[u8]) -> Result<()> {
+    let (proof, public_values) = match entry.action {
+        AuditAction::Deposit => {
+            let call = IShieldedPool::depositCall::abi_decode(input).context("not a deposit call")?;
+            let created = entry.created.first().context("deposit records no note")?;
+            ensure!(hex::encode(call.commitment) == created.commitment, "commitment differs");
+            ensure!(entry.public_amount.map(U256::from) == Some(call.amount), "amount differs");
+            return Ok(());
+        }
+        AuditAction::Transfer => {
+            let call = IShieldedPool::privateTransferCall::abi_decode(input).context("not a privateTransfer call")?;
+            (call.proof, call.publicValues)
+        }
+        AuditAction::Withdraw => {
+            let call = IShieldedPool::withdrawCall::abi_decode(input).context("not a withdraw call")?;
+            (call.proof, call.publicValues)
+        }
+    };
+    ensure!(entry.proof_hash == Some(hex::encode(keccak256(&proof))), "proof hash differs");
+    let recorded = decode_hex(entry.public_values.as_deref().unwrap_or_default())?;
+    ensure!(public_values.as_ref() == recorded.as_slice(), "public values differ");
+    Ok(())
+}
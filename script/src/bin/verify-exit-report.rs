@@ -0,0 +1,178 @@
+//! Verify a proof-of-exit bundle written by the exit script.
+//!
+//! Re-checks every recorded withdrawal against the chain:
+//!   - the transaction exists, targets the pool and succeeded
+//!   - its calldata carries exactly the recorded proof and public values
+//!   - the pool emitted a matching Withdrawal event (nullifier, recipient, amount)
+//!   - the nullifier is marked spent
+//!   - the recipient's balance at the recorded blocks matches the bundle
+//!
+//! Usage:
+//!   cargo run --release -p shielded-pool-script --bin verify-exit-report -- [REPORT] [--strict]
+//!
+//! REPORT defaults to fixtures/exit-report.json. Balances that cannot be
+//! queried (old blocks on a pruned node) are skipped unless in strict mode.
+//!
+//! Required env vars (from .env):
+//!   RPC_URL               — Plasma RPC endpoint
+//!
+//! Optional env vars:
+//!   MULTICALL_ADDRESS     — Multicall3 used to batch isSpent checks ("none" to disable)
+//!   MULTICALL_CHUNK_SIZE  — Calls per multicall batch (default: 200)
+//!   STRICT                — 1 to fail on skipped checks, as with --strict (default: on when CI is set)
+
+use alloy::{
+    consensus::Transaction as _,
+    eips::BlockId,
+    primitives::{Address, B256, U256},
+    providers::{Provider, ProviderBuilder},
+    sol,
+    sol_types::SolCall,
+};
+use anyhow::{ensure, Context, Result};
+use shielded_pool_script::{
+    decode_hex, decode_hex_fixed,
+    exit_report::ExitReport,
+    multicall::{BatchConfig, PoolViews},
+    strict::{self, StrictViolation},
+};
+
+sol! {
+    #[sol(rpc)]
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+    }
+
+    #[sol(rpc)]
+    interface IShieldedPool {
+        function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
+
+        event Withdrawal(bytes32 indexed nullifier, address indexed recipient, uint256 amount, uint256 timestamp);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
+
+    let default_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("fixtures/exit-report.json");
+    let report_path = strict::args()
+        .next()
+        .map(std::path::PathBuf::from)
+        .unwrap_or(default_path);
+
+    println!("\n=== Verify Exit Report ===\n");
+    println!("Report: {}", report_path.display());
+
+    let report = ExitReport::load(&report_path)?;
+    report.check_offline().context("report is internally inconsistent")?;
+    println!(
+        "Offline checks: OK ({} withdrawal(s), {} skipped)",
+        report.withdrawals.len(),
+        report.skipped.len()
+    );
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+
+    let chain_id = provider.get_chain_id().await?;
+    ensure!(
+        chain_id == report.chain_id,
+        "connected to chain {chain_id}, report is for chain {}",
+        report.chain_id
+    );
+
+    let pool_addr: Address = report.pool.parse().context("invalid pool address")?;
+    let token_addr: Address = report.token.parse().context("invalid token address")?;
+    let recipient: Address = report.recipient.parse().context("invalid recipient address")?;
+    let token = IERC20::new(token_addr, &provider);
+
+    // Spent status for every recorded nullifier, batched through Multicall3
+    let nullifiers = report
+        .withdrawals
+        .iter()
+        .map(|w| decode_hex_fixed::<32>(&w.nullifier))
+        .collect::<Result<Vec<_>>>()?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
+    let spent = views.is_spent_batch(&nullifiers).await?;
+
+    for ((w, nullifier), spent) in report.withdrawals.iter().zip(nullifiers).zip(spent) {
+        println!("\n[{}] {} USDT, tx {}", w.label, w.amount as f64 / 1e6, w.tx_hash);
+        let tx_hash: B256 = w.tx_hash.parse().context("invalid tx hash")?;
+
+        // Transaction and calldata
+        let tx = provider
+            .get_transaction_by_hash(tx_hash)
+            .await?
+            .with_context(|| format!("{}: transaction not found", w.label))?;
+        ensure!(tx.to() == Some(pool_addr), "{}: transaction does not target the pool", w.label);
+        let call = IShieldedPool::withdrawCall::abi_decode(tx.input())
+            .with_context(|| format!("{}: transaction is not a withdraw call", w.label))?;
+        ensure!(call.proof.as_ref() == decode_hex(&w.proof)?.as_slice(), "{}: proof differs from calldata", w.label);
+        ensure!(
+            call.publicValues.as_ref() == decode_hex(&w.public_values)?.as_slice(),
+            "{}: public values differ from calldata",
+            w.label
+        );
+        println!("    calldata matches");
+
+        // Receipt and Withdrawal event
+        let receipt = provider
+            .get_transaction_receipt(tx_hash)
+            .await?
+            .with_context(|| format!("{}: receipt not found", w.label))?;
+        ensure!(receipt.status(), "{}: transaction reverted", w.label);
+        ensure!(
+            receipt.block_number == Some(w.block_number),
+            "{}: mined in block {:?}, report says {}",
+            w.label,
+            receipt.block_number,
+            w.block_number
+        );
+        let event = receipt
+            .decoded_log::<IShieldedPool::Withdrawal>()
+            .with_context(|| format!("{}: no Withdrawal event in receipt", w.label))?;
+        ensure!(event.address == pool_addr, "{}: Withdrawal event not emitted by the pool", w.label);
+        ensure!(event.data.nullifier.0 == nullifier, "{}: event nullifier mismatch", w.label);
+        ensure!(event.data.recipient == recipient, "{}: event recipient mismatch", w.label);
+        ensure!(event.data.amount == U256::from(w.amount), "{}: event amount mismatch", w.label);
+        println!("    Withdrawal event matches");
+
+        // Nullifier state
+        ensure!(spent, "{}: nullifier is not marked spent", w.label);
+        println!("    nullifier spent");
+    }
+
+    // Balances at the recorded blocks (needs an archive node for old blocks)
+    println!("\nBalances:");
+    for (label, block, expected) in [
+        ("before", report.start_block, &report.balance_before),
+        ("after", report.end_block, &report.balance_after),
+    ] {
+        match token.balanceOf(recipient).block(BlockId::number(block)).call().await {
+            Ok(balance) => {
+                ensure!(
+                    balance.to_string() == *expected,
+                    "balance {label} at block {block} is {balance}, report says {expected}"
+                );
+                println!("    {label:<6} @ {block}: {balance} ✓");
+            }
+            Err(e) => {
+                strict::fallback(StrictViolation::BalanceUnavailable {
+                    what: format!("balance {label} at block {block}"),
+                })?;
+                println!("    {label:<6} @ {block}: could not query ({e}); skipped")
+            }
+        }
+    }
+
+    println!(
+        "\n=== Exit report verified: {} USDT recovered to {} ===\n",
+        report.total_withdrawn() as f64 / 1e6,
+        report.recipient
+    );
+    Ok(())
+}
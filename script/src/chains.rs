@@ -0,0 +1,106 @@
+//! Per-network settings: the chain registry (deploy/chains.json).
+//!
+//! Networks differ in how soon a block can be trusted. Plasma's BFT
+//! consensus finalizes each block as it is produced, while Ethereum L1 can
+//! reorganize its latest blocks. One hard-coded policy either waits for no
+//! reason or trusts state that may still disappear, so each chain's entry
+//! carries a `FinalityPolicy`:
+//!
+//!   - `confirmations`: blocks on top of a block before it is acted on. Sync
+//!     reads events up to `head - confirmations`, the explorer stays that
+//!     far behind the head, and submissions wait for that many blocks on top
+//!     of their receipt.
+//!   - `reorg_depth`: the deepest reorg assumed possible. Only leaves at
+//!     least this deep go into the tree store and checkpoint, which later
+//!     runs trust without re-reading.
+//!   - `poll_interval_ms`: how often to poll for new blocks and receipts.
+//!
+//! Chains without an entry get `FinalityPolicy::DEFAULT`, which is
+//! conservative. `CHAIN_REGISTRY` points at another registry file.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChainRegistry {
+    /// Keyed by decimal chain id
+    pub chains: BTreeMap<String, ChainEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChainEntry {
+    pub name: String,
+    pub finality: FinalityPolicy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalityPolicy {
+    /// Blocks on top of a block before it is acted on
+    pub confirmations: u64,
+    /// Deepest reorg assumed possible
+    pub reorg_depth: u64,
+    /// How often to poll for new blocks and receipts
+    pub poll_interval_ms: u64,
+}
+
+impl FinalityPolicy {
+    /// For chains not in the registry: L1-like reorgs.
+    pub const DEFAULT: FinalityPolicy = FinalityPolicy { confirmations: 2, reorg_depth: 64, poll_interval_ms: 5_000 };
+
+    /// The policy for `chain_id` in the registry at `CHAIN_REGISTRY` or
+    /// deploy/chains.json.
+    pub fn for_chain(chain_id: u64) -> Result<Self> {
+        let path = std::env::var("CHAIN_REGISTRY").map(PathBuf::from).unwrap_or_else(|_| ChainRegistry::default_path());
+        Ok(ChainRegistry::load(&path)?.finality(chain_id))
+    }
+
+    /// Newest block to act on when the chain is at `head`.
+    pub fn final_block(&self, head: u64) -> u64 {
+        head.saturating_sub(self.confirmations)
+    }
+
+    /// Newest block no reorg is assumed to reach when the chain is at `head`.
+    pub fn settled_block(&self, head: u64) -> u64 {
+        head.saturating_sub(self.reorg_depth)
+    }
+
+    /// Receipt confirmations to wait for, counting the inclusion block.
+    pub fn required_confirmations(&self) -> u64 {
+        self.confirmations + 1
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+impl Default for FinalityPolicy {
+    fn default() -> Self {
+        FinalityPolicy::DEFAULT
+    }
+}
+
+impl ChainRegistry {
+    /// deploy/chains.json at the repo root.
+    pub fn default_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("deploy/chains.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read chain registry: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn get(&self, chain_id: u64) -> Option<&ChainEntry> {
+        self.chains.get(&chain_id.to_string())
+    }
+
+    /// The chain's policy, or `FinalityPolicy::DEFAULT` if it has no entry.
+    pub fn finality(&self, chain_id: u64) -> FinalityPolicy {
+        self.get(chain_id).map_or(FinalityPolicy::DEFAULT, |entry| entry.finality)
+    }
+}
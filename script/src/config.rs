@@ -0,0 +1,126 @@
+//! Deployment settings from config.toml.
+//!
+//! Every binary needs the same handful of settings: the RPC endpoint, the
+//! chain, the pool and token, the block the pool was deployed at, the tree
+//! depth and how to prove. Instead of a `.env` per tool they can live in one
+//! file, config.toml at the repo root (`CONFIG_FILE` points at another; see
+//! config.example.toml):
+//!
+//!   rpc_url = "https://testnet-rpc.plasma.to"
+//!   chain_id = 9746
+//!   pool_address = "0x…"
+//!   token_address = "0x…"
+//!   deploy_block = 14677962
+//!   tree_levels = 20
+//!   prover = "network"          # mock | cpu | cuda | network
+//!
+//! `load` runs first in every binary. It reads `.env`, then the config file,
+//! and exports each setting under the variable the code reads (`RPC_URL`,
+//! `CHAIN_ID`, `POOL_ADDRESS`, `TOKEN_ADDRESS`, `DEPLOY_BLOCK`,
+//! `TREE_LEVELS`, `SP1_PROVER`) unless that variable is already set, so the
+//! environment and `.env` override the file. Secrets (`PRIVATE_KEY`,
+//! `NETWORK_PRIVATE_KEY`, passphrases) have no key here and stay in the
+//! environment. A missing config.toml is fine; unknown keys are refused, so
+//! a misspelt setting does not quietly fall back to a default.
+
+use alloy::primitives::Address;
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// JSON-RPC endpoint (`RPC_URL`)
+    pub rpc_url: Option<String>,
+    /// Chain of the pool (`CHAIN_ID`)
+    pub chain_id: Option<u64>,
+    /// Deployed ShieldedPool (`POOL_ADDRESS`)
+    pub pool_address: Option<Address>,
+    /// The pool's ERC20 token (`TOKEN_ADDRESS`)
+    pub token_address: Option<Address>,
+    /// Block the pool was deployed at, where event replay starts (`DEPLOY_BLOCK`)
+    pub deploy_block: Option<u64>,
+    /// Depth of the pool's Merkle tree (`TREE_LEVELS`)
+    pub tree_levels: Option<u64>,
+    /// SP1 prover to use (`SP1_PROVER`)
+    pub prover: Option<ProverMode>,
+}
+
+/// Where proofs are generated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProverMode {
+    /// Execute only and return mock proofs (local testing against a mock verifier)
+    Mock,
+    /// Prove on this machine's CPU
+    Cpu,
+    /// Prove on this machine's GPU
+    Cuda,
+    /// Prove on the Succinct Prover Network (`NETWORK_PRIVATE_KEY`)
+    Network,
+}
+
+impl ProverMode {
+    /// The value of `SP1_PROVER` for this mode.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProverMode::Mock => "mock",
+            ProverMode::Cpu => "cpu",
+            ProverMode::Cuda => "cuda",
+            ProverMode::Network => "network",
+        }
+    }
+}
+
+impl Config {
+    /// config.toml at the repo root.
+    pub fn default_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("config.toml")
+    }
+
+    /// `CONFIG_FILE` if set, otherwise the default path.
+    pub fn path() -> PathBuf {
+        std::env::var("CONFIG_FILE").map(PathBuf::from).unwrap_or_else(|_| Self::default_path())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
+
+    /// Each setting present, with the environment variable it stands for.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        [
+            ("RPC_URL", self.rpc_url.clone()),
+            ("CHAIN_ID", self.chain_id.map(|id| id.to_string())),
+            ("POOL_ADDRESS", self.pool_address.map(|a| a.to_string())),
+            ("TOKEN_ADDRESS", self.token_address.map(|a| a.to_string())),
+            ("DEPLOY_BLOCK", self.deploy_block.map(|b| b.to_string())),
+            ("TREE_LEVELS", self.tree_levels.map(|l| l.to_string())),
+            ("SP1_PROVER", self.prover.map(|p| p.as_str().to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|v| (name, v)))
+        .collect()
+    }
+}
+
+/// Read `.env` and the config file, and export every setting the
+/// environment does not already set. Call it first in `main`, before
+/// anything reads the environment. Returns the config file read, if any.
+pub fn load() -> Result<Option<PathBuf>> {
+    dotenv::dotenv().ok();
+    let path = Config::path();
+    if !path.exists() {
+        ensure!(std::env::var_os("CONFIG_FILE").is_none(), "CONFIG_FILE {} does not exist", path.display());
+        return Ok(None);
+    }
+    for (name, value) in Config::load(&path)?.env_vars() {
+        if std::env::var_os(name).is_none() {
+            std::env::set_var(name, value);
+        }
+    }
+    Ok(Some(path))
+}
@@ -0,0 +1,206 @@
+//! Branch coverage of the guests over the adversarial corpus.
+//!
+//! `adversarial --coverage PATH` executes the controls and the corpus against
+//! guests built with their `coverage` feature and writes a `CoverageReport`:
+//! for every check the guests run (`shielded_pool_lib::coverage`), which of
+//! its passing and rejecting branches some case took, which cases took them,
+//! and the most instructions an accepted execution spent in the check.
+//!
+//! Reports are stable for a given corpus and guest, so a constraint change
+//! comes with a delta against the report before it (`--baseline PATH`):
+//! branches gained, branches lost, and new checks nothing exercises yet.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use shielded_pool_lib::{adversarial::AdversarialCase, coverage, CircuitKind};
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+/// Report format version.
+pub const COVERAGE_REPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CoverageReport {
+    pub version: u32,
+    pub guests: Vec<GuestCoverage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GuestCoverage {
+    /// "transfer" or "withdraw"
+    pub circuit: String,
+    pub branches_total: usize,
+    pub branches_hit: usize,
+    pub checks: Vec<CheckCoverage>,
+    /// Rejected cases whose error matches no known rejection (a panic the
+    /// branch map does not list, or a failure outside the guest's checks)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unattributed: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CheckCoverage {
+    /// `circuit/check`
+    pub name: String,
+    /// Most instructions an accepted execution spent in the check
+    pub max_cycles: u64,
+    pub branches: Vec<BranchCoverage>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BranchCoverage {
+    /// `check/arm`, `check/pass` or `check/reject:reason`
+    pub id: String,
+    /// Names of the cases that took the branch
+    pub cases: Vec<String>,
+}
+
+/// Difference between a report and an earlier one.
+#[derive(Clone, Debug, Default)]
+pub struct CoverageDelta {
+    /// Branches hit now and not before (or new and hit)
+    pub gained: Vec<String>,
+    /// Branches hit before and not now (or removed)
+    pub lost: Vec<String>,
+    /// Branches that are new and not hit
+    pub new_uncovered: Vec<String>,
+}
+
+fn circuit_name(kind: CircuitKind) -> &'static str {
+    match kind {
+        CircuitKind::Transfer => "transfer",
+        CircuitKind::Withdraw => "withdraw",
+        CircuitKind::Claim => "claim",
+        CircuitKind::JoinSplit => "join-split",
+        CircuitKind::Deposit => "deposit",
+        CircuitKind::BatchWithdraw => "batch-withdraw",
+        CircuitKind::Consolidate => "consolidate",
+        CircuitKind::Attest => "attest",
+        CircuitKind::Disclose => "disclose",
+        CircuitKind::PolicySpend => "policy-spend",
+        CircuitKind::Payout => "payout",
+    }
+}
+
+impl Default for CoverageReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoverageReport {
+    /// Every branch of both guests, none hit.
+    pub fn new() -> Self {
+        let guests = [CircuitKind::Transfer, CircuitKind::Withdraw]
+            .into_iter()
+            .map(|kind| GuestCoverage {
+                circuit: circuit_name(kind).to_string(),
+                branches_total: coverage::branches(kind).len(),
+                branches_hit: 0,
+                checks: coverage::checks(kind)
+                    .iter()
+                    .map(|check| CheckCoverage {
+                        name: check.name.to_string(),
+                        max_cycles: 0,
+                        branches: check
+                            .pass_branches()
+                            .into_iter()
+                            .chain(check.reject_branches())
+                            .map(|id| BranchCoverage { id, cases: Vec::new() })
+                            .collect(),
+                    })
+                    .collect(),
+                unattributed: Vec::new(),
+            })
+            .collect();
+        CoverageReport { version: COVERAGE_REPORT_VERSION, guests }
+    }
+
+    fn guest_mut(&mut self, kind: CircuitKind) -> &mut GuestCoverage {
+        let name = circuit_name(kind);
+        self.guests.iter_mut().find(|g| g.circuit == name).expect("report covers both guests")
+    }
+
+    /// Record an accepted execution from its cycle-tracker report.
+    pub fn record_accepted(&mut self, case: &AdversarialCase, cycle_tracker: &HashMap<String, u64>) {
+        let kind = case.inputs.kind();
+        let taken = coverage::pass_branches(kind, cycle_tracker.keys().map(String::as_str));
+        let guest = self.guest_mut(kind);
+        for check in &mut guest.checks {
+            if let Some(cycles) = cycle_tracker.get(&format!("{}{}", coverage::PROBE_PREFIX, check.name)) {
+                check.max_cycles = check.max_cycles.max(*cycles);
+            }
+        }
+        guest.add_cases(&taken, &case.name);
+    }
+
+    /// Record a rejected execution from the error it failed with.
+    pub fn record_rejected(&mut self, case: &AdversarialCase, error: &str) {
+        let kind = case.inputs.kind();
+        let branch = coverage::rejection_branch(kind, error);
+        let guest = self.guest_mut(kind);
+        match branch {
+            Some(branch) => guest.add_cases(&[branch], &case.name),
+            None => guest.unattributed.push(case.name.clone()),
+        }
+    }
+
+    /// Hit branch ids across both guests.
+    pub fn hit(&self) -> BTreeSet<&str> {
+        self.branches().filter(|b| !b.cases.is_empty()).map(|b| b.id.as_str()).collect()
+    }
+
+    fn branches(&self) -> impl Iterator<Item = &BranchCoverage> {
+        self.guests.iter().flat_map(|g| &g.checks).flat_map(|c| &c.branches)
+    }
+
+    /// Compare with an earlier report.
+    pub fn delta(&self, baseline: &CoverageReport) -> CoverageDelta {
+        let (hit, was_hit) = (self.hit(), baseline.hit());
+        let known: BTreeSet<&str> = baseline.branches().map(|b| b.id.as_str()).collect();
+        let mut delta = CoverageDelta {
+            gained: hit.difference(&was_hit).map(|id| id.to_string()).collect(),
+            lost: was_hit.difference(&hit).map(|id| id.to_string()).collect(),
+            new_uncovered: Vec::new(),
+        };
+        for branch in self.branches() {
+            if branch.cases.is_empty() && !known.contains(branch.id.as_str()) {
+                delta.new_uncovered.push(branch.id.clone());
+            }
+        }
+        delta
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read coverage report: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl GuestCoverage {
+    fn add_cases(&mut self, branches: &[String], case: &str) {
+        for branch in self.checks.iter_mut().flat_map(|c| &mut c.branches) {
+            if branches.contains(&branch.id) {
+                branch.cases.push(case.to_string());
+            }
+        }
+        self.branches_hit = self.checks.iter().flat_map(|c| &c.branches).filter(|b| !b.cases.is_empty()).count();
+    }
+
+    /// Percentage of branches hit.
+    pub fn percent(&self) -> f64 {
+        if self.branches_total == 0 {
+            return 100.0;
+        }
+        100.0 * self.branches_hit as f64 / self.branches_total as f64
+    }
+}
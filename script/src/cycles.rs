@@ -0,0 +1,129 @@
+//! Cycle reports of guest executions, for `execute`.
+//!
+//! A `CycleReport` is what SP1's execution report says about one run of a
+//! guest: the instructions it executed, how many times it made each syscall
+//! (precompiles such as keccak are syscalls, so this is where a hashing
+//! change shows up), the cycles spent in each cycle-tracker region the guest
+//! marks, and the gas estimate when the executor computed one. Guests only
+//! mark regions when built with their `coverage` feature, so a regular
+//! build's report has no sections.
+//!
+//! `execute --expected-cycles N` compares the instruction count against a
+//! budget and fails past it, so CI catches a circuit change that makes
+//! proving more expensive before anyone pays for the proof.
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use sp1_sdk::ExecutionReport;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Report format version.
+pub const CYCLE_REPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CycleReport {
+    pub version: u32,
+    pub circuit: String,
+    /// keccak256 of the inputs, as `request-hash` prints it
+    pub request_hash: String,
+    pub total_instructions: u64,
+    pub total_syscalls: u64,
+    /// Calls of each syscall the guest made (only those made at least once)
+    pub syscalls: BTreeMap<String, u64>,
+    /// Cycle-tracker regions, by label
+    pub sections: BTreeMap<String, Section>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gas: Option<u64>,
+    /// The budget the run was held to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_cycles: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Section {
+    /// Cycles spent in the region, over all its invocations
+    pub cycles: u64,
+    pub invocations: u64,
+}
+
+impl CycleReport {
+    pub fn new(circuit: &str, request_hash: [u8; 32], report: &ExecutionReport) -> Self {
+        let syscalls = report
+            .syscall_counts
+            .iter()
+            .filter(|(_, &count)| count > 0)
+            .map(|(code, &count)| (format!("{:?}", code), count))
+            .collect();
+        let sections = report
+            .cycle_tracker
+            .iter()
+            .map(|(label, &cycles)| {
+                let invocations = report.invocation_tracker.get(label).copied().unwrap_or(1);
+                (label.clone(), Section { cycles, invocations })
+            })
+            .collect();
+        CycleReport {
+            version: CYCLE_REPORT_VERSION,
+            circuit: circuit.to_string(),
+            request_hash: hex::encode(request_hash),
+            total_instructions: report.total_instruction_count(),
+            total_syscalls: report.total_syscall_count(),
+            syscalls,
+            sections,
+            gas: report.gas,
+            expected_cycles: None,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("[{}] Instructions: {}", self.circuit, self.total_instructions);
+        if let Some(gas) = self.gas {
+            println!("[{}] Gas:          {}", self.circuit, gas);
+        }
+        println!("[{}] Syscalls:     {}", self.circuit, self.total_syscalls);
+        let mut syscalls: Vec<_> = self.syscalls.iter().collect();
+        syscalls.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (name, count) in syscalls {
+            println!("    {:<28} {:>12}", name, count);
+        }
+        if self.sections.is_empty() {
+            println!("[{}] No cycle-tracker sections (guests mark them in coverage builds)", self.circuit);
+            return;
+        }
+        println!("[{}] Sections:", self.circuit);
+        let mut sections: Vec<_> = self.sections.iter().collect();
+        sections.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(b.0)));
+        for (label, section) in sections {
+            let share = 100.0 * section.cycles as f64 / self.total_instructions.max(1) as f64;
+            println!("    {:<28} {:>12} cycles {:>5.1}%  x{}", label, section.cycles, share, section.invocations);
+        }
+    }
+
+    /// Fail if the run executed more than `expected` instructions.
+    pub fn check_budget(&mut self, expected: u64) -> Result<()> {
+        self.expected_cycles = Some(expected);
+        ensure!(
+            self.total_instructions <= expected,
+            "[{}] {} cycles is over the expected {} by {} ({:.1}%)",
+            self.circuit,
+            self.total_instructions,
+            expected,
+            self.total_instructions - expected,
+            100.0 * (self.total_instructions - expected) as f64 / expected.max(1) as f64
+        );
+        println!(
+            "[{}] Within budget: {} of {} expected cycles ({} to spare)",
+            self.circuit,
+            self.total_instructions,
+            expected,
+            expected - self.total_instructions
+        );
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write cycle report: {}", path.display()))
+    }
+}
@@ -0,0 +1,558 @@
+//! Break-glass exit kit, for escrow off-site.
+//!
+//! `prepare-emergency-kit` bundles everything needed to withdraw every note
+//! from another machine: the spending keys, a wallet snapshot, the tree
+//! checkpoint, and exit instructions written for this wallet and pool. The
+//! kit is one JSON file. Its sections are encrypted together under a kit
+//! passphrase, the way keystores encrypt a key (scrypt, AES-256-GCM).
+//!
+//! The manifest stays readable, so wherever the kit is stored it can be
+//! checked for completeness without the passphrase (`verify-emergency-kit`):
+//! every section present, a key for every note, the checkpoint covering the
+//! notes, and the ciphertext undamaged. It reveals key pubkeys, note
+//! commitments and leaf indices, which are public on-chain; amounts, labels
+//! and keys are encrypted.
+//!
+//! `execute-emergency-kit` decrypts the kit and checks every section against
+//! the manifest. It then restores the wallet (keys re-encrypted under the kit
+//! passphrase) and the checkpoint into a directory, and prints the exit
+//! command or, with `--run`, runs it.
+
+use anyhow::{ensure, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use shielded_pool_lib::{
+    keccak256,
+    keystore::{KdfParams, Keystore, KeystoreCrypto},
+    SpendingKey,
+};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    decode_hex, decode_hex_fixed, derive_viewing_keypair,
+    idle::unix_now,
+    sync::TreeCheckpoint,
+    wallet::{WalletSpendingKey, WalletState},
+};
+
+/// Kit format version.
+pub const EMERGENCY_KIT_VERSION: u32 = 1;
+
+/// Sections every kit carries; `checkpoint` is optional.
+pub const REQUIRED_SECTIONS: &[&str] = &["instructions", "keys", "wallet"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EmergencyKit {
+    pub version: u32,
+    /// Unix time the kit was prepared
+    pub created_at: u64,
+    pub manifest: KitManifest,
+    /// The sections (name → JSON), encrypted under the kit passphrase
+    pub crypto: KeystoreCrypto,
+}
+
+/// What the kit holds, readable without the passphrase.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KitManifest {
+    pub deployment: KitDeployment,
+    /// Hex-encoded pubkeys of the spending keys in the kit
+    pub keys: Vec<String>,
+    /// Every note in the wallet snapshot
+    pub notes: Vec<KitNote>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<KitCheckpoint>,
+    /// Section name → hex keccak256 of its JSON
+    pub sections: BTreeMap<String, String>,
+    /// Hex keccak256 of the ciphertext, to tell a damaged copy
+    pub ciphertext_hash: String,
+}
+
+/// The pool the kit exits from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KitDeployment {
+    /// Taken from the checkpoint or CHAIN_ID; unknown otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chain_id: Option<u64>,
+    /// 0x-prefixed ShieldedPool address
+    pub pool: String,
+    /// 0x-prefixed ERC20 token address
+    pub token: String,
+    pub deploy_block: u64,
+    pub levels: usize,
+}
+
+impl KitDeployment {
+    /// From POOL_ADDRESS, TOKEN_ADDRESS, CHAIN_ID, DEPLOY_BLOCK and TREE_LEVELS.
+    pub fn from_env() -> Result<Self> {
+        Ok(KitDeployment {
+            chain_id: std::env::var("CHAIN_ID").ok().map(|c| c.parse()).transpose().context("invalid CHAIN_ID")?,
+            pool: std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?,
+            token: std::env::var("TOKEN_ADDRESS").context("TOKEN_ADDRESS not set")?,
+            deploy_block: std::env::var("DEPLOY_BLOCK").unwrap_or_else(|_| "0".to_string()).parse()?,
+            levels: std::env::var("TREE_LEVELS").unwrap_or_else(|_| "20".to_string()).parse()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KitNote {
+    /// Hex-encoded commitment
+    pub commitment: String,
+    pub leaf_index: u32,
+    /// Hex-encoded pubkey of the kit key that spends the note (None: no key can)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spender: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KitCheckpoint {
+    /// Block the checkpoint was synced up to
+    pub block: u64,
+    /// Leaves it holds
+    pub leaves: usize,
+}
+
+/// A spending key as stored inside the kit.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct KitKey {
+    label: String,
+    /// Hex-encoded spending key
+    spending_key: String,
+}
+
+/// Outcome of checking a kit without decrypting it.
+#[derive(Clone, Debug, Default)]
+pub struct KitCheck {
+    /// The kit cannot carry out a full exit
+    pub problems: Vec<String>,
+    /// The exit will work, with more effort (e.g. replaying events)
+    pub warnings: Vec<String>,
+}
+
+/// A decrypted kit whose sections match its manifest.
+pub struct OpenedKit {
+    pub deployment: KitDeployment,
+    /// (label, key) in wallet order
+    pub keys: Vec<(String, SpendingKey)>,
+    /// Wallet snapshot without its spending keys
+    pub wallet: WalletState,
+    pub checkpoint: Option<TreeCheckpoint>,
+    pub instructions: String,
+}
+
+/// Files written by `OpenedKit::restore`.
+pub struct RestoredKit {
+    pub wallet: PathBuf,
+    pub checkpoint: Option<PathBuf>,
+    pub instructions: PathBuf,
+    /// Where the exit writes its report (and its progress, next to it)
+    pub exit_report: PathBuf,
+}
+
+fn hash_hex(bytes: &[u8]) -> String {
+    hex::encode(keccak256(bytes))
+}
+
+/// The pubkey of the first key in `keys` that may spend each wallet note.
+fn kit_notes(wallet: &WalletState, keys: &[SpendingKey]) -> Result<Vec<KitNote>> {
+    wallet
+        .notes
+        .iter()
+        .map(|n| {
+            Ok(KitNote {
+                commitment: n.commitment.clone(),
+                leaf_index: n.leaf_index,
+                spender: n.authorize(keys)?.map(|(k, _)| hex::encode(k.pubkey())),
+            })
+        })
+        .collect()
+}
+
+impl EmergencyKit {
+    /// Bundle `wallet` (whose keys, unlocked, are `keys`) and `checkpoint`
+    /// for `deployment`, encrypted under `passphrase`.
+    pub fn prepare(
+        wallet: &WalletState,
+        keys: &[SpendingKey],
+        checkpoint: Option<&TreeCheckpoint>,
+        mut deployment: KitDeployment,
+        passphrase: &str,
+        params: KdfParams,
+    ) -> Result<Self> {
+        ensure!(keys.len() == wallet.spending_keys.len(), "expected one unlocked key per wallet key");
+        if let Some(cp) = checkpoint {
+            ensure!(cp.pool.eq_ignore_ascii_case(&deployment.pool), "checkpoint is for pool {}", cp.pool);
+            ensure!(cp.levels == deployment.levels, "checkpoint is for a {}-level tree", cp.levels);
+            ensure!(
+                deployment.chain_id.is_none_or(|c| c == cp.chain_id),
+                "checkpoint is for chain {}",
+                cp.chain_id
+            );
+            deployment.chain_id = Some(cp.chain_id);
+        }
+
+        let kit_keys: Vec<KitKey> = wallet
+            .spending_keys
+            .iter()
+            .zip(keys)
+            .map(|(entry, key)| KitKey { label: entry.label.clone(), spending_key: hex::encode(key.as_bytes()) })
+            .collect();
+        let mut snapshot = wallet.clone();
+        snapshot.spending_keys.clear();
+        let notes = kit_notes(wallet, keys)?;
+        let created_at = unix_now();
+
+        let mut sections = BTreeMap::new();
+        sections.insert("keys", serde_json::to_string(&kit_keys)?);
+        sections.insert("wallet", serde_json::to_string(&snapshot)?);
+        if let Some(cp) = checkpoint {
+            sections.insert("checkpoint", serde_json::to_string(cp)?);
+        }
+        let instructions = instructions(&deployment, wallet, checkpoint, created_at);
+        sections.insert("instructions", serde_json::to_string(&instructions)?);
+
+        let crypto = KeystoreCrypto::encrypt(&serde_json::to_vec(&sections)?, passphrase, params)?;
+        Ok(EmergencyKit {
+            version: EMERGENCY_KIT_VERSION,
+            created_at,
+            manifest: KitManifest {
+                deployment,
+                keys: keys.iter().map(|k| hex::encode(k.pubkey())).collect(),
+                notes,
+                checkpoint: checkpoint.map(|cp| KitCheckpoint { block: cp.block, leaves: cp.leaves.len() }),
+                sections: sections.iter().map(|(name, json)| (name.to_string(), hash_hex(json.as_bytes()))).collect(),
+                ciphertext_hash: hash_hex(&decode_hex(&crypto.cipher.message)?),
+            },
+            crypto,
+        })
+    }
+
+    /// Check the kit is complete and undamaged, without the passphrase.
+    pub fn check(&self) -> KitCheck {
+        let mut check = KitCheck::default();
+        let m = &self.manifest;
+        if self.version != EMERGENCY_KIT_VERSION {
+            check.problems.push(format!("unsupported kit version {}", self.version));
+        }
+        match decode_hex(&self.crypto.cipher.message) {
+            Ok(ciphertext) if hash_hex(&ciphertext) == m.ciphertext_hash => {}
+            _ => check.problems.push("ciphertext does not match the manifest; this copy is damaged".to_string()),
+        }
+        for section in REQUIRED_SECTIONS {
+            if !m.sections.contains_key(*section) {
+                check.problems.push(format!("missing section '{section}'"));
+            }
+        }
+        if m.keys.is_empty() {
+            check.problems.push("kit holds no spending keys".to_string());
+        }
+        for note in &m.notes {
+            match &note.spender {
+                Some(pubkey) if m.keys.contains(pubkey) => {}
+                Some(pubkey) => check.problems.push(format!(
+                    "note {} is spent by key {pubkey}, which is not in the kit",
+                    note.commitment
+                )),
+                None => check.problems.push(format!("no key in the kit can spend note {}", note.commitment)),
+            }
+        }
+        match (&m.checkpoint, m.sections.contains_key("checkpoint")) {
+            (Some(cp), true) => {
+                let newer = m.notes.iter().filter(|n| n.leaf_index as usize >= cp.leaves).count();
+                if newer > 0 {
+                    check.warnings.push(format!(
+                        "{newer} note(s) are newer than the checkpoint; the exit replays events after block {}",
+                        cp.block
+                    ));
+                }
+            }
+            (None, false) => check.warnings.push(format!(
+                "no tree checkpoint; the exit replays every event from block {}",
+                m.deployment.deploy_block
+            )),
+            _ => check.problems.push("checkpoint section and manifest disagree".to_string()),
+        }
+        if m.deployment.chain_id.is_none() {
+            check.warnings.push("chain id unknown; check the RPC serves the pool's chain".to_string());
+        }
+        check
+    }
+
+    /// Decrypt the kit and check every section against the manifest.
+    pub fn open(&self, passphrase: &str) -> Result<OpenedKit> {
+        ensure!(self.version == EMERGENCY_KIT_VERSION, "unsupported kit version {}", self.version);
+        let plaintext = self.crypto.decrypt(passphrase).context("Failed to decrypt emergency kit")?;
+        let sections: BTreeMap<String, String> = serde_json::from_slice(&plaintext)?;
+        let m = &self.manifest;
+        ensure!(
+            sections.keys().eq(m.sections.keys()),
+            "kit sections {:?} differ from the manifest's",
+            sections.keys().collect::<Vec<_>>()
+        );
+        for (name, json) in &sections {
+            ensure!(hash_hex(json.as_bytes()) == m.sections[name], "section '{name}' does not match the manifest");
+        }
+        fn section<T: DeserializeOwned>(sections: &BTreeMap<String, String>, name: &str) -> Result<Option<T>> {
+            sections
+                .get(name)
+                .map(|json| serde_json::from_str(json).with_context(|| format!("malformed section '{name}'")))
+                .transpose()
+        }
+
+        let kit_keys: Vec<KitKey> = section(&sections, "keys")?.context("missing section 'keys'")?;
+        let keys = kit_keys
+            .into_iter()
+            .map(|k| Ok((k.label, SpendingKey::from(decode_hex_fixed::<32>(&k.spending_key)?))))
+            .collect::<Result<Vec<_>>>()?;
+        let pubkeys: Vec<String> = keys.iter().map(|(_, k)| hex::encode(k.pubkey())).collect();
+        ensure!(pubkeys == m.keys, "kit keys differ from the manifest's");
+
+        let wallet: WalletState = section(&sections, "wallet")?.context("missing section 'wallet'")?;
+        let unlocked: Vec<SpendingKey> = keys.iter().map(|(_, k)| *k).collect();
+        ensure!(kit_notes(&wallet, &unlocked)? == m.notes, "wallet notes differ from the manifest's");
+
+        let checkpoint: Option<TreeCheckpoint> = section(&sections, "checkpoint")?;
+        ensure!(
+            checkpoint.as_ref().map(|cp| KitCheckpoint { block: cp.block, leaves: cp.leaves.len() }) == m.checkpoint,
+            "checkpoint differs from the manifest's"
+        );
+        let instructions = section(&sections, "instructions")?.context("missing section 'instructions'")?;
+
+        Ok(OpenedKit { deployment: m.deployment.clone(), keys, wallet, checkpoint, instructions })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read emergency kit: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
+
+impl OpenedKit {
+    /// Write the wallet (keys encrypted under `passphrase`), the checkpoint
+    /// and the instructions into `dir`.
+    pub fn restore(&self, dir: &Path, passphrase: &str) -> Result<RestoredKit> {
+        std::fs::create_dir_all(dir)?;
+        let mut wallet = self.wallet.clone();
+        for (label, key) in &self.keys {
+            let (_, viewing_pubkey) = derive_viewing_keypair(key.as_bytes());
+            wallet.spending_keys.push(WalletSpendingKey {
+                label: label.clone(),
+                spending_key: String::new(),
+                keystore: Some(Keystore::encrypt(key, passphrase, label)?),
+                pubkey: hex::encode(key.pubkey()),
+                viewing_pubkey: hex::encode(viewing_pubkey.as_bytes()),
+            });
+        }
+        let restored = RestoredKit {
+            wallet: dir.join("wallet.json"),
+            checkpoint: self.checkpoint.as_ref().map(|_| dir.join("tree-checkpoint.json")),
+            instructions: dir.join("INSTRUCTIONS.md"),
+            exit_report: dir.join("exit-report.json"),
+        };
+        wallet.save(&restored.wallet)?;
+        if let (Some(cp), Some(path)) = (&self.checkpoint, &restored.checkpoint) {
+            cp.save(path)?;
+        }
+        std::fs::write(&restored.instructions, &self.instructions)?;
+        Ok(restored)
+    }
+
+    /// Environment the exit binary needs for the restored files, besides
+    /// RPC_URL, PRIVATE_KEY and the prover settings.
+    pub fn exit_env(&self, restored: &RestoredKit) -> Vec<(&'static str, String)> {
+        let d = &self.deployment;
+        let mut env = vec![
+            ("WALLET_FILE", restored.wallet.display().to_string()),
+            ("POOL_ADDRESS", d.pool.clone()),
+            ("TOKEN_ADDRESS", d.token.clone()),
+            ("DEPLOY_BLOCK", d.deploy_block.to_string()),
+            ("TREE_LEVELS", d.levels.to_string()),
+            ("EXIT_REPORT", restored.exit_report.display().to_string()),
+        ];
+        if let Some(path) = &restored.checkpoint {
+            env.push(("TREE_CHECKPOINT", path.display().to_string()));
+        }
+        env
+    }
+}
+
+/// Read the kit passphrase from `KIT_PASSPHRASE`, or prompt on the terminal.
+pub fn read_kit_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("KIT_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("Emergency kit passphrase: ")?;
+    ensure!(!passphrase.is_empty(), "passphrase must not be empty");
+    if confirm {
+        let again = rpassword::prompt_password("Confirm passphrase: ")?;
+        ensure!(passphrase == again, "passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+/// The exit runbook stored in the kit.
+fn instructions(
+    deployment: &KitDeployment,
+    wallet: &WalletState,
+    checkpoint: Option<&TreeCheckpoint>,
+    created_at: u64,
+) -> String {
+    let chain = deployment.chain_id.map_or("unknown".to_string(), |c| c.to_string());
+    let total: u64 = wallet.notes.iter().map(|n| n.amount).sum();
+    let mut out = format!(
+        "# Emergency exit\n\n\
+         Pool {} on chain {chain}, token {}. Kit prepared at unix time {created_at}.\n\n\
+         1. Restore the kit: `shielded-pool execute-emergency-kit --kit <kit.json> --out <dir>`\n\
+         2. Set RPC_URL, PRIVATE_KEY (the address that receives the funds) and\n   \
+            NETWORK_PRIVATE_KEY (Succinct Prover Network) in the environment.\n\
+         3. Withdraw every note: `execute-emergency-kit --run` does it, or run\n   \
+            `cargo run --release -p shielded-pool-script --bin exit` with the\n   \
+            variables printed by step 1. WALLET_PASSPHRASE is the kit passphrase.\n\n",
+        deployment.pool, deployment.token,
+    );
+    match checkpoint {
+        Some(cp) => out += &format!(
+            "The tree checkpoint holds {} leaves up to block {}; only later events are replayed.\n\n",
+            cp.leaves.len(),
+            cp.block
+        ),
+        None => out += &format!("No checkpoint: every event from block {} is replayed.\n\n", deployment.deploy_block),
+    }
+    out += &format!("Notes at preparation ({} USDT in total, spent ones are skipped):\n\n", total as f64 / 1e6);
+    for n in &wallet.notes {
+        out += &format!("- {} — {} USDT, leaf {}, commitment 0x{}\n", n.label, n.amount as f64 / 1e6, n.leaf_index, n.commitment);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::WalletNote;
+    use shielded_pool_lib::Note;
+
+    const PASSPHRASE: &str = "correct horse battery staple";
+
+    /// A kit of a wallet with one key and two notes, and the wallet's key.
+    fn prepared() -> (EmergencyKit, SpendingKey) {
+        let key = SpendingKey::from([7u8; 32]);
+        let (_, viewing) = derive_viewing_keypair(key.as_bytes());
+        let note = |amount, blinding| Note { amount, pubkey: key.pubkey(), blinding: [blinding; 32] };
+        let wallet = WalletState {
+            spending_keys: vec![WalletSpendingKey {
+                label: "alice".into(),
+                spending_key: hex::encode(key.as_bytes()),
+                keystore: None,
+                pubkey: hex::encode(key.pubkey()),
+                viewing_pubkey: hex::encode(viewing.as_bytes()),
+            }],
+            notes: vec![
+                WalletNote::new("deposit_0", &note(1_000_000, 1), 0),
+                WalletNote::new("change_1", &note(400_000, 2), 1),
+            ],
+            ..WalletState::default()
+        };
+        let pool = format!("0x{}", "aa".repeat(20));
+        let checkpoint = TreeCheckpoint {
+            chain_id: 9746,
+            pool: pool.clone(),
+            levels: 20,
+            block: 1_000,
+            leaves: wallet.notes.iter().map(|n| n.commitment.clone()).collect(),
+        };
+        let token = format!("0x{}", "bb".repeat(20));
+        let deployment = KitDeployment { chain_id: None, pool, token, deploy_block: 900, levels: 20 };
+        let params = KdfParams::INSECURE_FAST;
+        let kit = EmergencyKit::prepare(&wallet, &[key], Some(&checkpoint), deployment, PASSPHRASE, params).unwrap();
+        (kit, key)
+    }
+
+    /// Why opening `kit` with `passphrase` fails.
+    fn rejection(kit: &EmergencyKit, passphrase: &str) -> String {
+        match kit.open(passphrase) {
+            Ok(_) => panic!("the kit opened"),
+            Err(e) => format!("{e:#}"),
+        }
+    }
+
+    /// `kit` written out and read back, as it would be from escrow.
+    fn reload(kit: &EmergencyKit) -> EmergencyKit {
+        serde_json::from_str(&serde_json::to_string_pretty(kit).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_kit_round_trips() {
+        let (kit, key) = prepared();
+        let kit = reload(&kit);
+        let check = kit.check();
+        assert!(check.problems.is_empty(), "{:?}", check.problems);
+        assert!(check.warnings.is_empty(), "{:?}", check.warnings);
+        assert_eq!(kit.manifest.keys, [hex::encode(key.pubkey())]);
+        assert!(kit.manifest.notes.iter().all(|n| n.spender == Some(hex::encode(key.pubkey()))));
+
+        let opened = kit.open(PASSPHRASE).unwrap();
+        assert_eq!(opened.deployment.chain_id, Some(9746));
+        assert_eq!(opened.keys.len(), 1);
+        assert_eq!((opened.keys[0].0.as_str(), opened.keys[0].1.as_bytes()), ("alice", key.as_bytes()));
+        // The snapshot leaves the keys to the keys section
+        assert!(opened.wallet.spending_keys.is_empty());
+        let notes: Vec<(&str, u64, u32)> =
+            opened.wallet.notes.iter().map(|n| (n.label.as_str(), n.amount, n.leaf_index)).collect();
+        assert_eq!(notes, [("deposit_0", 1_000_000, 0), ("change_1", 400_000, 1)]);
+        let commitments: Vec<String> = kit.manifest.notes.iter().map(|n| n.commitment.clone()).collect();
+        assert_eq!(opened.checkpoint.unwrap().leaves, commitments);
+        assert!(opened.instructions.contains(&kit.manifest.deployment.pool));
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_rejected() {
+        let (kit, _) = prepared();
+        let error = rejection(&kit, "wrong passphrase");
+        assert!(error.contains("Failed to decrypt emergency kit"), "{error}");
+        assert!(rejection(&kit, "").contains("Failed to decrypt"));
+    }
+
+    #[test]
+    fn test_corrupted_kit_is_rejected() {
+        // A damaged ciphertext shows without the passphrase, and does not decrypt
+        let (mut kit, _) = prepared();
+        let mut ciphertext = decode_hex(&kit.crypto.cipher.message).unwrap();
+        ciphertext[0] ^= 1;
+        kit.crypto.cipher.message = hex::encode(ciphertext);
+        assert!(kit.check().problems.iter().any(|p| p.contains("this copy is damaged")));
+        assert!(rejection(&kit, PASSPHRASE).contains("Failed to decrypt"));
+
+        // So does a changed nonce
+        let (mut kit, _) = prepared();
+        kit.crypto.cipher.params.iv = hex::encode([0u8; 12]);
+        assert!(rejection(&kit, PASSPHRASE).contains("Failed to decrypt"));
+
+        // A manifest that no longer matches the contents is caught on opening
+        let (mut kit, _) = prepared();
+        kit.manifest.notes[1].leaf_index = 5;
+        let error = rejection(&kit, PASSPHRASE);
+        assert!(error.contains("wallet notes differ from the manifest's"), "{error}");
+        let (mut kit, _) = prepared();
+        kit.manifest.keys[0] = hex::encode([9u8; 32]);
+        assert!(kit.check().problems.iter().any(|p| p.contains("not in the kit")));
+        assert!(rejection(&kit, PASSPHRASE).contains("kit keys differ"));
+        let (mut kit, _) = prepared();
+        kit.manifest.sections.insert("wallet".into(), hash_hex(b"{}"));
+        assert!(rejection(&kit, PASSPHRASE).contains("section 'wallet' does not match"));
+
+        // Sections the manifest requires cannot go missing
+        let (mut kit, _) = prepared();
+        kit.manifest.sections.remove("instructions");
+        assert!(kit.check().problems.iter().any(|p| p.contains("missing section 'instructions'")));
+        assert!(rejection(&kit, PASSPHRASE).contains("differ from the manifest's"));
+    }
+}
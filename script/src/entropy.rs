@@ -0,0 +1,252 @@
+//! Where the host binaries get their randomness.
+//!
+//! Spending keys, note blindings and the ephemeral keys and nonces of
+//! encrypted outputs all come from one process-wide `EntropySource`, chosen
+//! by `ENTROPY_SOURCE`:
+//!   - `os` (default): the operating system's RNG
+//!   - `device:<path>`: a hardware RNG character device, e.g. `device:/dev/hwrng`
+//!   - `pkcs11:<module>`: an HSM's RNG (`C_GenerateRandom`), through OpenSC's
+//!     `pkcs11-tool` and the given PKCS#11 module; `PKCS11_SLOT` and
+//!     `PKCS11_PIN` select the slot and log in when set
+//!
+//! With `ENTROPY_SEED` (32 bytes of hex) also set, every output of the chosen
+//! source is XORed with a keystream derived from the seed. The result is no
+//! weaker than the better of the two, so a user-supplied seed covers a
+//! source the user does not trust, and the other way round.
+//!
+//! Embedders with their own source implement `EntropySource` and `install`
+//! it before the first use. Keystore salts and IVs, which are not secret,
+//! still come from the OS.
+
+use anyhow::{bail, ensure, Context, Result};
+use rand::{CryptoRng, RngCore};
+use shielded_pool_lib::keccak256;
+use std::fs::File;
+use std::io::Read;
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use crate::decode_hex_fixed;
+
+/// Domain separator of the `ENTROPY_SEED` keystream.
+const SEED_STREAM_DOMAIN: &[u8] = b"shielded-pool/entropy-seed/1";
+
+/// A source of cryptographically secure random bytes.
+pub trait EntropySource: Send + Sync {
+    /// Fill `dest` with random bytes, or fail without a partial result.
+    fn fill(&self, dest: &mut [u8]) -> Result<()>;
+
+    /// What the source is, for logs (never includes secrets).
+    fn describe(&self) -> String;
+}
+
+/// The operating system's RNG.
+pub struct OsEntropy;
+
+impl EntropySource for OsEntropy {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        rand::rngs::OsRng.try_fill_bytes(dest).context("the OS RNG failed")
+    }
+
+    fn describe(&self) -> String {
+        "os".into()
+    }
+}
+
+/// A hardware RNG exposed as a character device.
+pub struct DeviceEntropy {
+    pub path: String,
+}
+
+impl EntropySource for DeviceEntropy {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        File::open(&self.path)
+            .and_then(|mut device| device.read_exact(dest))
+            .with_context(|| format!("could not read {} random bytes from {}", dest.len(), self.path))
+    }
+
+    fn describe(&self) -> String {
+        format!("device:{}", self.path)
+    }
+}
+
+/// An HSM's RNG, read through `pkcs11-tool --generate-random`.
+pub struct Pkcs11Entropy {
+    /// Path of the vendor's PKCS#11 module (.so)
+    pub module: String,
+    pub slot: Option<String>,
+    pub pin: Option<String>,
+}
+
+impl EntropySource for Pkcs11Entropy {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        let mut command = Command::new("pkcs11-tool");
+        command.args(["--module", &self.module, "--generate-random", &dest.len().to_string()]);
+        if let Some(slot) = &self.slot {
+            command.args(["--slot", slot]);
+        }
+        if let Some(pin) = &self.pin {
+            command.args(["--login", "--pin", pin]);
+        }
+        let output = command.output().context("could not run pkcs11-tool (is OpenSC installed?)")?;
+        ensure!(
+            output.status.success(),
+            "pkcs11-tool failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        ensure!(
+            output.stdout.len() == dest.len(),
+            "the HSM returned {} random bytes, not {}",
+            output.stdout.len(),
+            dest.len()
+        );
+        dest.copy_from_slice(&output.stdout);
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("pkcs11:{}", self.module)
+    }
+}
+
+/// Another source's output XORed with a keystream derived from a seed:
+/// `keccak256(domain || seed || counter)`, one block per 32 bytes.
+pub struct SeedXorEntropy<S> {
+    inner: S,
+    seed: [u8; 32],
+    /// Next keystream block; never reused, so no two outputs share a block
+    counter: AtomicU64,
+}
+
+impl<S: EntropySource> SeedXorEntropy<S> {
+    pub fn new(inner: S, seed: [u8; 32]) -> Self {
+        SeedXorEntropy { inner, seed, counter: AtomicU64::new(0) }
+    }
+}
+
+impl<S: EntropySource> EntropySource for SeedXorEntropy<S> {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        self.inner.fill(dest)?;
+        let blocks = dest.len().div_ceil(32) as u64;
+        let first = self.counter.fetch_add(blocks, Ordering::Relaxed);
+        let mut preimage = [SEED_STREAM_DOMAIN, &self.seed, &[0u8; 8]].concat();
+        let counter_at = preimage.len() - 8;
+        for (i, chunk) in dest.chunks_mut(32).enumerate() {
+            preimage[counter_at..].copy_from_slice(&(first + i as u64).to_be_bytes());
+            for (byte, key) in chunk.iter_mut().zip(keccak256(&preimage)) {
+                *byte ^= key;
+            }
+        }
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        format!("{} xor seed", self.inner.describe())
+    }
+}
+
+impl EntropySource for Box<dyn EntropySource> {
+    fn fill(&self, dest: &mut [u8]) -> Result<()> {
+        (**self).fill(dest)
+    }
+
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+}
+
+/// The source `ENTROPY_SOURCE` and `ENTROPY_SEED` describe.
+pub fn from_env() -> Result<Box<dyn EntropySource>> {
+    let spec = std::env::var("ENTROPY_SOURCE").unwrap_or_else(|_| "os".into());
+    let source: Box<dyn EntropySource> = match spec.split_once(':') {
+        None if spec == "os" => Box::new(OsEntropy),
+        Some(("device", path)) if !path.is_empty() => Box::new(DeviceEntropy { path: path.into() }),
+        Some(("pkcs11", module)) if !module.is_empty() => Box::new(Pkcs11Entropy {
+            module: module.into(),
+            slot: std::env::var("PKCS11_SLOT").ok(),
+            pin: std::env::var("PKCS11_PIN").ok(),
+        }),
+        _ => bail!("ENTROPY_SOURCE must be os, device:<path> or pkcs11:<module>, not {spec:?}"),
+    };
+    match std::env::var("ENTROPY_SEED") {
+        Ok(seed) => {
+            let seed = decode_hex_fixed::<32>(&seed).context("ENTROPY_SEED must be 32 bytes of hex")?;
+            Ok(Box::new(SeedXorEntropy::new(source, seed)))
+        }
+        Err(_) => Ok(source),
+    }
+}
+
+static SOURCE: OnceLock<Box<dyn EntropySource>> = OnceLock::new();
+
+/// Use `source` for the rest of the process. Fails if randomness was
+/// already drawn from another source.
+pub fn install(source: Box<dyn EntropySource>) -> Result<()> {
+    let description = source.describe();
+    SOURCE.set(source).map_err(|_| anyhow::anyhow!("an entropy source is already in use; cannot switch to {description}"))
+}
+
+/// Set up the source from the environment and check it produces output, so
+/// a missing device or HSM fails at startup rather than mid-run.
+pub fn init_from_env() -> Result<&'static dyn EntropySource> {
+    if SOURCE.get().is_none() {
+        // Lost race: another thread installed one first, which is used instead
+        let _ = install(from_env()?);
+    }
+    let source = self::source();
+    source.fill(&mut [0u8; 32]).with_context(|| format!("entropy source {} is not usable", source.describe()))?;
+    Ok(source)
+}
+
+/// The process-wide source (from the environment unless one was installed).
+///
+/// # Panics
+/// If the environment names an invalid source; binaries call
+/// `init_from_env` first to get an error instead.
+pub fn source() -> &'static dyn EntropySource {
+    SOURCE.get_or_init(|| from_env().unwrap_or_else(|e| panic!("{e:#}"))).as_ref()
+}
+
+/// `N` random bytes from the process-wide source.
+pub fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    source().fill(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The process-wide source as a `rand` RNG, for `gen()` and for crates that
+/// take one (crypto_box).
+pub fn rng() -> EntropyRng {
+    EntropyRng(source())
+}
+
+/// See `rng`. Like `OsRng`, the infallible methods panic if the source fails.
+#[derive(Clone, Copy)]
+pub struct EntropyRng(&'static dyn EntropySource);
+
+impl RngCore for EntropyRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(e) = self.0.fill(dest) {
+            panic!("entropy source {} failed: {e:#}", self.0.describe());
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.fill(dest).map_err(rand::Error::new)
+    }
+}
+
+impl CryptoRng for EntropyRng {}
@@ -0,0 +1,174 @@
+//! Proof-of-exit bundle written by the exit script.
+//!
+//! Records everything needed to show, after the fact, that every unspent note
+//! was withdrawn to the intended address: each nullifier, the Groth16 proof and
+//! public values that were submitted, the transaction that carried them, and
+//! the recipient's token balance before and after the run.
+//!
+//! `verify-exit-report` re-checks a bundle against the chain.
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use shielded_pool_lib::WithdrawPublicValues;
+use std::path::Path;
+
+use crate::{decode_hex, decode_hex_fixed, interrupt::Resumable, wallet::NoteMetadata};
+
+/// Bundle format version.
+pub const EXIT_REPORT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExitReport {
+    pub version: u32,
+    pub chain_id: u64,
+    /// 0x-prefixed ShieldedPool address
+    pub pool: String,
+    /// 0x-prefixed ERC20 token address
+    pub token: String,
+    /// 0x-prefixed address every withdrawal pays out to
+    pub recipient: String,
+    /// Recipient token balance before the first withdrawal (decimal string, raw units)
+    pub balance_before: String,
+    /// Recipient token balance after the last withdrawal (decimal string, raw units)
+    pub balance_after: String,
+    /// Block at which `balance_before` was read
+    pub start_block: u64,
+    /// Block at which `balance_after` was read
+    pub end_block: u64,
+    pub withdrawals: Vec<ExitWithdrawal>,
+    /// Unspent notes the run could not withdraw (label and reason)
+    #[serde(default)]
+    pub skipped: Vec<SkippedNote>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExitWithdrawal {
+    pub label: String,
+    pub leaf_index: u32,
+    /// Hex-encoded note commitment
+    pub commitment: String,
+    /// Hex-encoded nullifier published on-chain
+    pub nullifier: String,
+    /// Raw token units (6 decimals)
+    pub amount: u64,
+    /// Hex-encoded Merkle root the proof was generated against
+    pub root: String,
+    /// Hex-encoded Groth16 proof bytes as submitted
+    pub proof: String,
+    /// Hex-encoded public values as submitted
+    pub public_values: String,
+    /// Hex-encoded canonical hash of the proven private inputs (absent in older reports)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_hash: Option<String>,
+    /// 0x-prefixed transaction hash
+    pub tx_hash: String,
+    pub block_number: u64,
+    /// Metadata carried over from the wallet note
+    #[serde(default, skip_serializing_if = "NoteMetadata::is_empty")]
+    pub metadata: NoteMetadata,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SkippedNote {
+    pub label: String,
+    pub amount: u64,
+    pub reason: String,
+    /// Metadata carried over from the wallet note
+    #[serde(default, skip_serializing_if = "NoteMetadata::is_empty")]
+    pub metadata: NoteMetadata,
+}
+
+/// Progress of an unfinished exit run, kept until its report is written.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExitState {
+    /// Report so far; balance_before and start_block come from the first run
+    pub report: ExitReport,
+    /// Proven withdrawals not yet confirmed on-chain (`tx_hash` empty until sent)
+    pub pending: Vec<ExitWithdrawal>,
+    /// What the last run was doing when it was interrupted
+    #[serde(default)]
+    pub interrupted: Option<String>,
+}
+
+impl ExitState {
+    /// Refuse to resume a run for a different pool or recipient.
+    pub fn check_matches(&self, chain_id: u64, pool: &str, recipient: &str) -> Result<()> {
+        ensure!(self.report.chain_id == chain_id, "exit state is for chain {}", self.report.chain_id);
+        ensure!(self.report.pool.eq_ignore_ascii_case(pool), "exit state is for pool {}", self.report.pool);
+        ensure!(
+            self.report.recipient.eq_ignore_ascii_case(recipient),
+            "exit state withdraws to {}",
+            self.report.recipient
+        );
+        Ok(())
+    }
+}
+
+impl Resumable for ExitState {
+    fn mark_interrupted(&mut self, job: Option<String>) {
+        self.interrupted = Some(job.unwrap_or_else(|| "between steps".to_string()));
+    }
+}
+
+impl ExitReport {
+    /// Sum of all recorded withdrawal amounts.
+    pub fn total_withdrawn(&self) -> u128 {
+        self.withdrawals.iter().map(|w| w.amount as u128).sum()
+    }
+
+    /// balance_after - balance_before, if both parse and the balance did not drop.
+    pub fn balance_delta(&self) -> Result<u128> {
+        let before: u128 = self.balance_before.parse().context("invalid balance_before")?;
+        let after: u128 = self.balance_after.parse().context("invalid balance_after")?;
+        ensure!(after >= before, "balance decreased during exit ({before} -> {after})");
+        Ok(after - before)
+    }
+
+    /// Checks that need no RPC: every withdrawal's public values decode and agree
+    /// with the recorded nullifier, root, recipient and amount; nullifiers are
+    /// unique; and the balance delta covers the withdrawn total.
+    pub fn check_offline(&self) -> Result<()> {
+        ensure!(
+            self.version == EXIT_REPORT_VERSION,
+            "unsupported exit report version {}",
+            self.version
+        );
+        let recipient = decode_hex_fixed::<20>(&self.recipient).context("invalid recipient")?;
+
+        let mut seen = std::collections::HashSet::new();
+        for w in &self.withdrawals {
+            let pv_bytes = decode_hex(&w.public_values)
+                .with_context(|| format!("{}: invalid public_values hex", w.label))?;
+            let pv = WithdrawPublicValues::decode(&pv_bytes)
+                .with_context(|| format!("{}: malformed withdraw public values", w.label))?;
+
+            ensure!(pv.nullifier == decode_hex_fixed::<32>(&w.nullifier)?, "{}: nullifier mismatch", w.label);
+            ensure!(pv.root == decode_hex_fixed::<32>(&w.root)?, "{}: root mismatch", w.label);
+            ensure!(pv.recipient == recipient, "{}: withdrawal pays a different recipient", w.label);
+            ensure!(pv.amount == w.amount, "{}: amount mismatch", w.label);
+            ensure!(seen.insert(pv.nullifier), "{}: duplicate nullifier", w.label);
+        }
+
+        let delta = self.balance_delta()?;
+        ensure!(
+            delta >= self.total_withdrawn(),
+            "balance delta {delta} is less than total withdrawn {}",
+            self.total_withdrawn()
+        );
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read exit report: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
@@ -0,0 +1,527 @@
+//! Index of the pool's public data, for a block-explorer-style read API.
+//!
+//! `PoolIndex` replays the pool's events into:
+//!   - every commitment with its leaf index and the transaction that inserted it
+//!   - the root after every insertion, i.e. the pool's whole root timeline
+//!   - every spent nullifier, also kept in a `NullifierSet`
+//!   - per-block activity (counts and public deposit/withdrawal amounts)
+//!
+//! Only data the chain already makes public is indexed. Note contents, the
+//! encrypted note payloads and any link between a nullifier and the
+//! commitment it spends never enter the index. `decode_pool_call` decodes the
+//! public values of any pool call.
+//!
+//! Events are fetched without holding the index (`fetch_events`) and applied
+//! in one go (`PoolIndex::apply`), so readers are only paused for the apply.
+//! The `explorer` binary serves the index over HTTP.
+
+use alloy::{
+    consensus::Transaction as _,
+    eips::BlockId,
+    primitives::{Address, B256},
+    providers::Provider,
+    sol,
+    sol_types::SolCall,
+};
+use anyhow::{ensure, Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use shielded_pool_lib::{
+    keccak256,
+    nullifier_set::{NullifierProof, NullifierSet},
+    IncrementalMerkleTree, TransferPublicValues, WithdrawPublicValues, ROOT_HISTORY_SIZE,
+};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{strict, sync::SyncPipeline};
+
+sol! {
+    #[sol(rpc)]
+    interface IExplorerPool {
+        function getLastRoot() external view returns (bytes32);
+
+        function deposit(bytes32 commitment, uint256 amount, bytes calldata encryptedData) external;
+        function privateTransfer(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedOutput1, bytes calldata encryptedOutput2) external;
+        function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
+
+        event Deposit(bytes32 indexed commitment, uint256 amount, uint32 leafIndex, uint256 timestamp);
+        event PrivateTransfer(bytes32 indexed nullifier1, bytes32 indexed nullifier2, bytes32 newCommitment1, bytes32 newCommitment2, uint256 timestamp);
+        event Withdrawal(bytes32 indexed nullifier, address indexed recipient, uint256 amount, uint256 timestamp);
+    }
+}
+
+/// 0x-prefixed hex, as served by the API.
+fn hex0x(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityKind {
+    Deposit,
+    Transfer,
+    Withdrawal,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CommitmentEntry {
+    pub leaf_index: u32,
+    pub commitment: String,
+    /// Deposit, transfer output or withdrawal change
+    pub kind: ActivityKind,
+    pub block: u64,
+    pub tx_hash: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RootEntry {
+    pub root: String,
+    /// Leaves in the tree under this root
+    pub leaf_count: u32,
+    pub block: u64,
+    pub tx_hash: String,
+    /// Still one of the pool's last ROOT_HISTORY_SIZE roots (proofs against it are accepted)
+    pub known: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct NullifierEntry {
+    pub nullifier: String,
+    pub kind: ActivityKind,
+    pub block: u64,
+    pub tx_hash: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BlockActivity {
+    pub block: u64,
+    pub deposits: u32,
+    pub transfers: u32,
+    pub withdrawals: u32,
+    /// Sum of deposit amounts (raw units)
+    pub deposited: u64,
+    /// Sum of withdrawal amounts (raw units)
+    pub withdrawn: u64,
+    pub tx_hashes: Vec<String>,
+}
+
+/// Answer to "is this nullifier spent?", with a proof against the set's root.
+#[derive(Clone, Debug, Serialize)]
+pub struct NullifierLookup {
+    pub nullifier: String,
+    pub spent: Option<NullifierEntry>,
+    pub set_root: String,
+    pub proof: NullifierProof,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IndexStatus {
+    pub pool: String,
+    /// Last block indexed (None before the first sync)
+    pub synced_block: Option<u64>,
+    pub leaf_count: u32,
+    pub root: String,
+    pub roots: usize,
+    pub nullifiers: usize,
+    pub nullifier_root: String,
+    pub active_blocks: usize,
+}
+
+/// One pool event, decoded.
+#[derive(Clone, Debug)]
+pub struct PoolEvent {
+    pub block: u64,
+    pub log_index: u64,
+    pub tx_hash: String,
+    pub data: EventData,
+}
+
+#[derive(Clone, Debug)]
+pub enum EventData {
+    Deposit { commitment: [u8; 32], amount: u64, leaf_index: u32 },
+    Transfer { nullifiers: [[u8; 32]; 2], commitments: [[u8; 32]; 2] },
+    /// `change` comes from the withdraw call's public values
+    Withdrawal { nullifier: [u8; 32], amount: u64, change: Option<[u8; 32]> },
+}
+
+/// Everything the API serves, built from the pool's events.
+#[derive(Clone, Debug)]
+pub struct PoolIndex {
+    pub pool: Address,
+    /// First block to read on the next sync
+    next_block: u64,
+    synced_block: Option<u64>,
+    tree: IncrementalMerkleTree,
+    commitments: Vec<CommitmentEntry>,
+    roots: Vec<RootEntry>,
+    nullifier_set: NullifierSet,
+    nullifiers: Vec<NullifierEntry>,
+    spent_at: HashMap<[u8; 32], usize>,
+    blocks: BTreeMap<u64, BlockActivity>,
+}
+
+/// `items[from..from + limit]`, clamped.
+pub fn page<T>(items: &[T], from: usize, limit: usize) -> &[T] {
+    let start = from.min(items.len());
+    &items[start..start.saturating_add(limit).min(items.len())]
+}
+
+impl PoolIndex {
+    pub fn new(pool: Address, levels: usize, deploy_block: u64) -> Self {
+        PoolIndex {
+            pool,
+            next_block: deploy_block,
+            synced_block: None,
+            tree: IncrementalMerkleTree::new(levels),
+            commitments: Vec::new(),
+            roots: Vec::new(),
+            nullifier_set: NullifierSet::new(),
+            nullifiers: Vec::new(),
+            spent_at: HashMap::new(),
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// First block the next sync should read.
+    pub fn next_block(&self) -> u64 {
+        self.next_block
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.get_root()
+    }
+
+    /// Apply the events of blocks `[next_block, to_block]` (in any order).
+    pub fn apply(&mut self, to_block: u64, mut events: Vec<PoolEvent>) -> Result<()> {
+        events.sort_by_key(|e| (e.block, e.log_index));
+        for event in events {
+            self.apply_event(event)?;
+        }
+        self.synced_block = Some(to_block);
+        self.next_block = to_block + 1;
+        Ok(())
+    }
+
+    fn apply_event(&mut self, event: PoolEvent) -> Result<()> {
+        let (kind, inserted, spent): (ActivityKind, Vec<[u8; 32]>, Vec<[u8; 32]>) = match event.data {
+            EventData::Deposit { commitment, leaf_index, .. } => {
+                ensure!(
+                    leaf_index == self.tree.next_index,
+                    "deposit in tx {} is at leaf {leaf_index}, expected leaf {}",
+                    event.tx_hash,
+                    self.tree.next_index
+                );
+                (ActivityKind::Deposit, vec![commitment], Vec::new())
+            }
+            EventData::Transfer { nullifiers, commitments } => {
+                (ActivityKind::Transfer, commitments.to_vec(), nullifiers.to_vec())
+            }
+            EventData::Withdrawal { nullifier, change, .. } => {
+                (ActivityKind::Withdrawal, change.into_iter().collect(), vec![nullifier])
+            }
+        };
+
+        for commitment in &inserted {
+            let leaf_index = self
+                .tree
+                .try_insert(*commitment)
+                .map_err(anyhow::Error::msg)
+                .context("pool has more leaves than TREE_LEVELS allows")?;
+            self.commitments.push(CommitmentEntry {
+                leaf_index,
+                commitment: hex0x(commitment),
+                kind,
+                block: event.block,
+                tx_hash: event.tx_hash.clone(),
+            });
+        }
+        if !inserted.is_empty() {
+            if let Some(expired) = self.roots.len().checked_sub(ROOT_HISTORY_SIZE - 1) {
+                self.roots[expired].known = false;
+            }
+            self.roots.push(RootEntry {
+                root: hex0x(&self.tree.get_root()),
+                leaf_count: self.tree.next_index,
+                block: event.block,
+                tx_hash: event.tx_hash.clone(),
+                known: true,
+            });
+        }
+        for nullifier in spent {
+            self.nullifier_set.insert(nullifier);
+            self.spent_at.insert(nullifier, self.nullifiers.len());
+            self.nullifiers.push(NullifierEntry {
+                nullifier: hex0x(&nullifier),
+                kind,
+                block: event.block,
+                tx_hash: event.tx_hash.clone(),
+            });
+        }
+
+        let activity = self.blocks.entry(event.block).or_insert_with(|| BlockActivity {
+            block: event.block,
+            ..Default::default()
+        });
+        match event.data {
+            EventData::Deposit { amount, .. } => {
+                activity.deposits += 1;
+                activity.deposited = activity.deposited.saturating_add(amount);
+            }
+            EventData::Transfer { .. } => activity.transfers += 1,
+            EventData::Withdrawal { amount, .. } => {
+                activity.withdrawals += 1;
+                activity.withdrawn = activity.withdrawn.saturating_add(amount);
+            }
+        }
+        if !activity.tx_hashes.contains(&event.tx_hash) {
+            activity.tx_hashes.push(event.tx_hash);
+        }
+        Ok(())
+    }
+
+    pub fn status(&self) -> IndexStatus {
+        IndexStatus {
+            pool: self.pool.to_string(),
+            synced_block: self.synced_block,
+            leaf_count: self.tree.next_index,
+            root: hex0x(&self.tree.get_root()),
+            roots: self.roots.len(),
+            nullifiers: self.nullifiers.len(),
+            nullifier_root: hex0x(&self.nullifier_set.root()),
+            active_blocks: self.blocks.len(),
+        }
+    }
+
+    /// Commitments in leaf order.
+    pub fn commitments(&self) -> &[CommitmentEntry] {
+        &self.commitments
+    }
+
+    pub fn commitment(&self, commitment: &[u8; 32]) -> Option<&CommitmentEntry> {
+        self.tree.index_of(commitment).map(|i| &self.commitments[i as usize])
+    }
+
+    /// Roots oldest first, one per transaction that inserted leaves.
+    pub fn roots(&self) -> &[RootEntry] {
+        &self.roots
+    }
+
+    pub fn find_root(&self, root: &[u8; 32]) -> Option<&RootEntry> {
+        let root = hex0x(root);
+        self.roots.iter().rev().find(|r| r.root == root)
+    }
+
+    /// Spent nullifiers in the order they were spent.
+    pub fn nullifiers(&self) -> &[NullifierEntry] {
+        &self.nullifiers
+    }
+
+    pub fn nullifier(&self, nullifier: &[u8; 32]) -> NullifierLookup {
+        NullifierLookup {
+            nullifier: hex0x(nullifier),
+            spent: self.spent_at.get(nullifier).map(|&i| self.nullifiers[i].clone()),
+            set_root: hex0x(&self.nullifier_set.root()),
+            proof: self.nullifier_set.prove(nullifier),
+        }
+    }
+
+    pub fn block(&self, block: u64) -> Option<&BlockActivity> {
+        self.blocks.get(&block)
+    }
+
+    /// Blocks with pool activity, newest first, strictly before `before`.
+    pub fn recent_blocks(&self, before: Option<u64>, limit: usize) -> Vec<&BlockActivity> {
+        self.blocks.range(..before.unwrap_or(u64::MAX)).rev().take(limit).map(|(_, a)| a).collect()
+    }
+}
+
+/// Fetch and decode the pool's events in `[from_block, to_block]`.
+///
+/// Uses the chunking and concurrency of `SyncPipeline::from_env`. Logs
+/// flagged as removed by a reorg are an error; the caller retries later.
+pub async fn fetch_events<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<PoolEvent>> {
+    let pipeline = SyncPipeline::from_env()?;
+    let pool = IExplorerPool::new(pool_addr, provider);
+    let mut events = Vec::new();
+    let mut start = from_block;
+    while start <= to_block {
+        let end = start.saturating_add(pipeline.chunk_blocks - 1).min(to_block);
+        let (deposit_filter, transfer_filter, withdrawal_filter) = (
+            pool.Deposit_filter().from_block(start).to_block(end),
+            pool.PrivateTransfer_filter().from_block(start).to_block(end),
+            pool.Withdrawal_filter().from_block(start).to_block(end),
+        );
+        let (deposits, transfers, withdrawals) =
+            futures::try_join!(deposit_filter.query(), transfer_filter.query(), withdrawal_filter.query())?;
+        let removed = deposits.iter().filter(|(_, log)| log.removed).count()
+            + transfers.iter().filter(|(_, log)| log.removed).count()
+            + withdrawals.iter().filter(|(_, log)| log.removed).count();
+        ensure!(removed == 0, "{removed} log(s) in blocks {start}..={end} were removed (reorg)");
+
+        for (event, log) in &deposits {
+            let (block, log_index) = strict::log_position(log)?;
+            events.push(PoolEvent {
+                block,
+                log_index,
+                tx_hash: log.transaction_hash.map(|h| h.to_string()).unwrap_or_default(),
+                data: EventData::Deposit {
+                    commitment: event.commitment.0,
+                    amount: u64::try_from(event.amount).context("deposit amount exceeds u64")?,
+                    leaf_index: event.leafIndex,
+                },
+            });
+        }
+        for (event, log) in &transfers {
+            let (block, log_index) = strict::log_position(log)?;
+            events.push(PoolEvent {
+                block,
+                log_index,
+                tx_hash: log.transaction_hash.map(|h| h.to_string()).unwrap_or_default(),
+                data: EventData::Transfer {
+                    nullifiers: [event.nullifier1.0, event.nullifier2.0],
+                    commitments: [event.newCommitment1.0, event.newCommitment2.0],
+                },
+            });
+        }
+        // The change commitment is only in the calldata: one lookup per withdrawal
+        let decoded: Vec<PoolEvent> = stream::iter(withdrawals)
+            .map(|(event, log)| async move {
+                let (block, log_index) = strict::log_position(&log)?;
+                let tx_hash = log.transaction_hash.context("withdrawal log has no tx hash")?;
+                let tx = provider
+                    .get_transaction_by_hash(tx_hash)
+                    .await?
+                    .with_context(|| format!("withdrawal tx {tx_hash} not found"))?;
+                let call = IExplorerPool::withdrawCall::abi_decode(tx.input())
+                    .with_context(|| format!("tx {tx_hash} emitted Withdrawal but is not a withdraw call"))?;
+                let pv = WithdrawPublicValues::decode(&call.publicValues)
+                    .with_context(|| format!("malformed withdraw public values in tx {tx_hash}"))?;
+                let change = (pv.change_commitment != [0u8; 32]).then_some(pv.change_commitment);
+                Ok::<_, anyhow::Error>(PoolEvent {
+                    block,
+                    log_index,
+                    tx_hash: tx_hash.to_string(),
+                    data: EventData::Withdrawal {
+                        nullifier: event.nullifier.0,
+                        amount: u64::try_from(event.amount).context("withdrawal amount exceeds u64")?,
+                        change,
+                    },
+                })
+            })
+            .buffer_unordered(pipeline.concurrency)
+            .try_collect()
+            .await?;
+        events.extend(decoded);
+        start = end + 1;
+    }
+    Ok(events)
+}
+
+/// The pool's last root at `block`, to check the index against.
+pub async fn pool_root_at<P: Provider>(provider: &P, pool_addr: Address, block: u64) -> Result<[u8; 32]> {
+    let pool = IExplorerPool::new(pool_addr, provider);
+    Ok(pool.getLastRoot().block(BlockId::number(block)).call().await?.0)
+}
+
+/// Public inputs of a pool call. Proofs are summarised by their keccak256
+/// hash; encrypted payloads are left out.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum DecodedCall {
+    Deposit {
+        commitment: String,
+        amount: u64,
+    },
+    PrivateTransfer {
+        proof_hash: String,
+        root: String,
+        nullifiers: [String; 2],
+        out_commitments: [String; 2],
+        reclaim_deadline: u64,
+    },
+    Withdraw {
+        proof_hash: String,
+        root: String,
+        nullifier: String,
+        recipient: String,
+        amount: u64,
+        /// None for full withdrawals
+        change_commitment: Option<String>,
+        reclaim_deadline: u64,
+        /// None when the recipient submitted it without a relayer fee
+        relayer: Option<String>,
+        fee: u64,
+    },
+}
+
+/// Decode `deposit`, `privateTransfer` or `withdraw` calldata.
+pub fn decode_pool_call(input: &[u8]) -> Result<DecodedCall> {
+    let selector = input.get(..4).context("calldata shorter than a selector")?;
+    if selector == IExplorerPool::depositCall::SELECTOR {
+        let call = IExplorerPool::depositCall::abi_decode(input).context("malformed deposit call")?;
+        return Ok(DecodedCall::Deposit {
+            commitment: hex0x(call.commitment.as_slice()),
+            amount: u64::try_from(call.amount).context("deposit amount exceeds u64")?,
+        });
+    }
+    if selector == IExplorerPool::privateTransferCall::SELECTOR {
+        let call =
+            IExplorerPool::privateTransferCall::abi_decode(input).context("malformed privateTransfer call")?;
+        let pv = TransferPublicValues::decode(&call.publicValues).context("malformed transfer public values")?;
+        return Ok(DecodedCall::PrivateTransfer {
+            proof_hash: hex0x(&keccak256(&call.proof)),
+            root: hex0x(&pv.root),
+            nullifiers: pv.nullifiers.map(|n| hex0x(&n)),
+            out_commitments: pv.out_commitments.map(|c| hex0x(&c)),
+            reclaim_deadline: pv.reclaim_deadline,
+        });
+    }
+    if selector == IExplorerPool::withdrawCall::SELECTOR {
+        let call = IExplorerPool::withdrawCall::abi_decode(input).context("malformed withdraw call")?;
+        let pv = WithdrawPublicValues::decode(&call.publicValues).context("malformed withdraw public values")?;
+        return Ok(DecodedCall::Withdraw {
+            proof_hash: hex0x(&keccak256(&call.proof)),
+            root: hex0x(&pv.root),
+            nullifier: hex0x(&pv.nullifier),
+            recipient: Address::from(pv.recipient).to_string(),
+            amount: pv.amount,
+            change_commitment: (pv.change_commitment != [0u8; 32]).then(|| hex0x(&pv.change_commitment)),
+            reclaim_deadline: pv.reclaim_deadline,
+            relayer: (pv.relayer != [0u8; 20]).then(|| Address::from(pv.relayer).to_string()),
+            fee: pv.fee,
+        });
+    }
+    anyhow::bail!("not a deposit, privateTransfer or withdraw call")
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DecodedTx {
+    pub tx_hash: String,
+    /// None while pending
+    pub block: Option<u64>,
+    /// None while pending
+    pub success: Option<bool>,
+    #[serde(flatten)]
+    pub call: DecodedCall,
+}
+
+/// Fetch a transaction and decode it, if it exists and was sent to the pool.
+pub async fn decode_pool_tx<P: Provider>(provider: &P, pool_addr: Address, tx_hash: B256) -> Result<Option<DecodedTx>> {
+    let Some(tx) = provider.get_transaction_by_hash(tx_hash).await? else {
+        return Ok(None);
+    };
+    ensure!(tx.to() == Some(pool_addr), "tx {tx_hash} was not sent to the pool");
+    let call = decode_pool_call(tx.input())?;
+    let receipt = provider.get_transaction_receipt(tx_hash).await?;
+    Ok(Some(DecodedTx {
+        tx_hash: tx_hash.to_string(),
+        block: receipt.as_ref().and_then(|r| r.block_number),
+        success: receipt.map(|r| r.status()),
+        call,
+    }))
+}
@@ -0,0 +1,122 @@
+//! Reminders for notes left untouched, and the sweep that tidies them up.
+//!
+//! A note stays spendable forever: proofs against an old tree state are
+//! still accepted once the tree is rebuilt. But notes nobody has moved for
+//! months are easy to forget. Each wallet note records when it was created
+//! or imported; once that is older than the wallet's policy allows, the note
+//! is idle. `note list` and `note idle` remind about idle notes and, unless
+//! the policy turns it off, suggest a sweep; `make sweep-idle` carries it out.
+//!
+//! Notes from wallets written before timestamps were recorded have none and
+//! are never reported idle.
+
+use serde::{Deserialize, Serialize};
+
+use crate::wallet::WalletState;
+
+/// Remind about notes untouched for this many days unless the wallet says otherwise.
+pub const DEFAULT_REMIND_AFTER_DAYS: u64 = 180;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// What a sweep does with idle notes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SweepAction {
+    /// Only remind; never suggest a sweep
+    Off,
+    /// Merge idle notes of the same key pairwise with private transfers
+    Consolidate,
+    /// Withdraw idle notes to a public address
+    Exit,
+}
+
+/// Wallet-level settings for idle-note reminders.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlePolicy {
+    /// A note untouched for this many days is idle
+    pub remind_after_days: u64,
+    /// What `sweep-idle` does with idle notes
+    pub sweep: SweepAction,
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        IdlePolicy { remind_after_days: DEFAULT_REMIND_AFTER_DAYS, sweep: SweepAction::Consolidate }
+    }
+}
+
+/// A wallet note past the policy's threshold.
+#[derive(Clone, Copy, Debug)]
+pub struct IdleNote {
+    /// Index into `WalletState::notes`
+    pub index: usize,
+    pub idle_days: u64,
+}
+
+impl IdlePolicy {
+    /// Notes in `wallet` untouched for at least `remind_after_days` at `now`
+    /// (unix seconds), longest idle first.
+    ///
+    /// Spent notes are not filtered out; the wallet does not know which are.
+    pub fn idle_notes(&self, wallet: &WalletState, now: u64) -> Vec<IdleNote> {
+        let mut idle: Vec<IdleNote> = wallet
+            .notes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, n)| {
+                let idle_days = now.saturating_sub(n.touched_at?) / SECONDS_PER_DAY;
+                (idle_days >= self.remind_after_days).then_some(IdleNote { index, idle_days })
+            })
+            .collect();
+        idle.sort_by_key(|n| std::cmp::Reverse(n.idle_days));
+        idle
+    }
+
+    /// Print one reminder per idle note, then the sweep suggestion.
+    pub fn print_reminders(&self, wallet: &WalletState, idle: &[IdleNote]) {
+        if idle.is_empty() {
+            return;
+        }
+        for n in idle {
+            let note = &wallet.notes[n.index];
+            println!(
+                "Reminder: note '{}' ({} USDT) untouched for {} days",
+                note.label,
+                (note.amount as f64) / 1e6,
+                n.idle_days
+            );
+        }
+        match self.sweep {
+            SweepAction::Off => {}
+            SweepAction::Consolidate => println!(
+                "Suggestion: `make sweep-idle` merges idle notes held by the same key into fewer notes"
+            ),
+            SweepAction::Exit => {
+                println!("Suggestion: `make sweep-idle` withdraws the idle notes to your address")
+            }
+        }
+    }
+}
+
+/// Pair up items that share an owner, in order. Returns the pairs and the
+/// items left without a partner.
+pub fn pair_by_owner<K: PartialEq + Copy>(items: &[(usize, K)]) -> (Vec<[usize; 2]>, Vec<usize>) {
+    let mut pairs = Vec::new();
+    let mut waiting: Vec<(usize, K)> = Vec::new();
+    for &(item, owner) in items {
+        match waiting.iter().position(|(_, k)| *k == owner) {
+            Some(i) => pairs.push([waiting.remove(i).0, item]),
+            None => waiting.push((item, owner)),
+        }
+    }
+    (pairs, waiting.into_iter().map(|(item, _)| item).collect())
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,116 @@
+//! Ctrl-C handling for long-running jobs.
+//!
+//! A network proof takes minutes. If the run is interrupted, the work done so
+//! far (generated proofs, sent transactions) must not be lost and the next run
+//! must be able to pick it up. A `Journal` holds that state, saves it to disk
+//! on every update, and on Ctrl-C records what was in flight, saves once more
+//! and exits with status 130.
+//!
+//! The SDK has no way to cancel a proof request it is blocked on, so a remote
+//! network job keeps running after an interrupt; its result is simply never
+//! collected. The journal records which job that was.
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Exit status after Ctrl-C (128 + SIGINT).
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// State persisted across interrupted runs.
+pub trait Resumable: Serialize + DeserializeOwned + Send + 'static {
+    /// Record that the run was interrupted while doing `job`.
+    fn mark_interrupted(&mut self, job: Option<String>);
+}
+
+pub struct Journal<T> {
+    path: PathBuf,
+    state: Arc<Mutex<T>>,
+    /// What the run is doing right now, recorded on interrupt
+    job: Arc<Mutex<Option<String>>>,
+}
+
+impl<T> Clone for Journal<T> {
+    fn clone(&self) -> Self {
+        Journal { path: self.path.clone(), state: Arc::clone(&self.state), job: Arc::clone(&self.job) }
+    }
+}
+
+impl<T: Resumable> Journal<T> {
+    /// Load the journal at `path`, or start from `init` if there is none.
+    pub fn open(path: &Path, init: impl FnOnce() -> T) -> Result<Self> {
+        let state = match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => init(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Journal {
+            path: path.to_path_buf(),
+            state: Arc::new(Mutex::new(state)),
+            job: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the current state.
+    pub fn read<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.state.lock().unwrap())
+    }
+
+    /// Change the state and save it.
+    pub fn update<R>(&self, f: impl FnOnce(&mut T) -> R) -> Result<R> {
+        let mut state = self.state.lock().unwrap();
+        let out = f(&mut state);
+        save(&self.path, &*state)?;
+        Ok(out)
+    }
+
+    /// Name the job in flight (None when idle), for the interrupt record.
+    pub fn set_job(&self, job: Option<String>) {
+        *self.job.lock().unwrap() = job;
+    }
+
+    /// Remove the journal once the run has finished.
+    pub fn finish(&self) -> Result<()> {
+        match std::fs::remove_file(&self.path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// On Ctrl-C: record the job in flight, save, and exit with status 130.
+    /// Must be called from within a multi-threaded tokio runtime, so the
+    /// handler still runs while the main task is blocked on a proof.
+    pub fn exit_on_ctrl_c(&self) {
+        let journal = self.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            let job = journal.job.lock().unwrap().clone();
+            eprintln!("\nInterrupted{}", job.as_ref().map(|j| format!(" while {j}")).unwrap_or_default());
+            match journal.update(|state| state.mark_interrupted(job)) {
+                Ok(()) => eprintln!("Progress saved to {}; rerun to resume.", journal.path.display()),
+                Err(e) => eprintln!("Failed to save progress to {}: {e:#}", journal.path.display()),
+            }
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        });
+    }
+}
+
+/// Write `value` as JSON via a temp file and rename, so a crash mid-write
+/// leaves the previous version intact.
+fn save<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(value)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
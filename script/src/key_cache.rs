@@ -0,0 +1,77 @@
+//! On-disk cache of SP1 proving and verifying keys.
+//!
+//! `client.setup(elf)` preprocesses the guest every time it is called, which
+//! takes minutes, and every proof needs its circuit's proving key. `setup`
+//! here runs it once per guest build and keeps the keys under
+//! `~/.cache/shielded-pool/keys/` (`$XDG_CACHE_HOME/shielded-pool/keys/`
+//! when that is set, or `KEY_CACHE_DIR`), in a file named after the pinned
+//! SP1 version and the sha256 of the ELF. A rebuilt guest or an SP1 upgrade
+//! therefore misses the cache rather than reusing stale keys, and every
+//! binary proving the same ELF shares one entry.
+//!
+//! The cache is only ever an optimisation: an entry that cannot be read or
+//! decoded is set up again and replaced, and a cache that cannot be written
+//! just means the next run sets up again. `KEY_CACHE_DIR=off` disables it.
+
+use sp1_sdk::{EnvProver, SP1ProvingKey, SP1VerifyingKey};
+use std::path::{Path, PathBuf};
+
+use crate::reproduce::{sha256_hex, SP1_DOCKER_TAG};
+
+/// The cache directory, or `None` when disabled or there is no home to put
+/// it in.
+pub fn cache_dir() -> Option<PathBuf> {
+    user_cache_dir("KEY_CACHE_DIR", "keys")
+}
+
+/// `shielded-pool/<name>` under the user's cache directory, or the directory
+/// in `var` when set; `None` when `var` is `off`.
+pub(crate) fn user_cache_dir(var: &str, name: &str) -> Option<PathBuf> {
+    match std::env::var(var) {
+        Ok(dir) if dir == "off" => None,
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|cache| cache.join("shielded-pool").join(name)),
+    }
+}
+
+/// Write a cache entry under a temporary name and rename it into place, so a
+/// concurrent or interrupted run never reads half a file.
+pub(crate) fn write_entry(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    let written = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&tmp, bytes))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written
+}
+
+/// `client.setup(elf)`, from the cache when the keys for this ELF are there.
+pub fn setup(client: &EnvProver, elf: &[u8]) -> (SP1ProvingKey, SP1VerifyingKey) {
+    let Some(dir) = cache_dir() else {
+        return client.setup(elf);
+    };
+    let path = dir.join(format!("{}-{}.bin", SP1_DOCKER_TAG, sha256_hex(elf)));
+
+    if let Ok(bytes) = std::fs::read(&path) {
+        match bincode::deserialize(&bytes) {
+            Ok(keys) => return keys,
+            Err(e) => eprintln!("⚠ Ignoring unreadable key cache entry {}: {}", path.display(), e),
+        }
+    }
+
+    let keys = client.setup(elf);
+    let written = bincode::serialize(&keys)
+        .map_err(std::io::Error::other)
+        .and_then(|bytes| write_entry(&path, &bytes));
+    if let Err(e) = written {
+        eprintln!("⚠ Could not cache keys in {}: {}", dir.display(), e);
+    }
+    keys
+}
@@ -0,0 +1,54 @@
+//! Mnemonic-backed spending keys.
+//!
+//! A new key starts as 256 bits from the entropy source (see `entropy`),
+//! written down as a 24-word BIP39 mnemonic. The spending key is derived
+//! from the mnemonic's BIP39 seed:
+//!
+//!   spending_key = keccak256(MNEMONIC_KEY_DOMAIN || seed)
+//!
+//! so the words alone restore the key, its shielded pubkey and its viewing
+//! keypair. BIP32 paths are not used: one mnemonic is one spending key.
+//! In the wallet file the key is kept in an encrypted keystore like any
+//! other; the mnemonic itself is never stored.
+
+use anyhow::{Context, Result};
+use bip39::Mnemonic;
+use shielded_pool_lib::{keccak256, keystore::Keystore, SpendingKey};
+
+use crate::{derive_viewing_keypair, entropy, wallet::WalletSpendingKey};
+
+/// Domain separator of the spending key derived from a mnemonic's seed.
+const MNEMONIC_KEY_DOMAIN: &[u8] = b"shielded-pool/mnemonic-key/1";
+
+/// A fresh 24-word mnemonic from the process-wide entropy source.
+pub fn generate_mnemonic() -> Result<Mnemonic> {
+    let bytes = entropy::random_bytes::<32>()?;
+    Ok(Mnemonic::from_entropy(&bytes)?)
+}
+
+/// Parse mnemonic words (English, checksum checked; case and spacing are
+/// normalized).
+pub fn parse_mnemonic(words: &str) -> Result<Mnemonic> {
+    let words = words.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    Mnemonic::parse(&words).context("invalid mnemonic")
+}
+
+/// The spending key a mnemonic backs up.
+pub fn spending_key_from_mnemonic(mnemonic: &Mnemonic) -> SpendingKey {
+    let mut preimage = MNEMONIC_KEY_DOMAIN.to_vec();
+    preimage.extend_from_slice(&mnemonic.to_seed(""));
+    SpendingKey::from(keccak256(&preimage))
+}
+
+/// A wallet entry for `key`: encrypted under `passphrase`, with the
+/// shielded and viewing pubkeys the wallet shows without unlocking.
+pub fn wallet_key(label: &str, key: &SpendingKey, passphrase: &str) -> Result<WalletSpendingKey> {
+    let (_secret, viewing_pubkey) = derive_viewing_keypair(key.as_bytes());
+    Ok(WalletSpendingKey {
+        label: label.into(),
+        spending_key: String::new(),
+        keystore: Some(Keystore::encrypt(key, passphrase, label)?),
+        pubkey: hex::encode(key.pubkey()),
+        viewing_pubkey: hex::encode(viewing_pubkey.as_bytes()),
+    })
+}
@@ -0,0 +1,236 @@
+//! Shared host-side helpers for the proof CLI, e2e, exit and sweep-idle binaries.
+
+pub mod audit;
+pub mod chains;
+pub mod config;
+pub mod coverage;
+pub mod cycles;
+pub mod emergency;
+pub mod entropy;
+pub mod exit_report;
+pub mod explorer;
+pub mod idle;
+pub mod interrupt;
+pub mod key_cache;
+pub mod keygen;
+pub mod messaging;
+pub mod multicall;
+pub mod proof_cache;
+pub mod proving;
+pub mod relayer;
+pub mod reproduce;
+pub mod resubmit;
+pub mod strict;
+pub mod sync;
+pub mod wallet;
+pub mod wallet_store;
+pub mod walletd;
+
+use anyhow::{ensure, Result};
+use crypto_box::{
+    aead::{Aead, AeadCore},
+    PublicKey, SalsaBox, SecretKey,
+};
+use serde::Serialize;
+use shielded_pool_lib::{
+    abort::AbortCode, keccak256, note_encryption::OutputEncryption, CircuitKind, InputHeader, Note, INPUT_END_MARKER,
+};
+use sp1_sdk::SP1Stdin;
+
+/// Build guest stdin in the framed layout both guests expect:
+///   InputHeader, private inputs, INPUT_END_MARKER
+///
+/// Always go through this helper — the guests reject anything else.
+pub fn framed_stdin<T: Serialize>(kind: CircuitKind, inputs: &T) -> SP1Stdin {
+    let mut stdin = SP1Stdin::new();
+    stdin.write(&InputHeader::new(kind));
+    stdin.write(inputs);
+    stdin.write(&INPUT_END_MARKER);
+    stdin
+}
+
+/// The abort code a rejected execution halted with (see
+/// `shielded_pool_lib::abort`), read off the executor's "exit code N".
+pub fn abort_code(error: &anyhow::Error) -> Option<AbortCode> {
+    let text = format!("{error:#}");
+    let (_, rest) = text.rsplit_once("exit code")?;
+    let digits: String = rest.trim_start().chars().take_while(char::is_ascii_digit).collect();
+    AbortCode::from_exit_code(digits.parse().ok()?)
+}
+
+/// `error` from executing a guest, with its abort code explained when it
+/// has one. Print it with `{:#}` to keep the executor's message.
+pub fn explain_rejection(error: anyhow::Error) -> anyhow::Error {
+    match abort_code(&error) {
+        Some(code) => error.context(format!("abort code {}: {}", code.exit_code(), code.explanation())),
+        None => error,
+    }
+}
+
+/// Derive a viewing keypair from a spending key.
+/// Matches the TypeScript SDK: viewingSecret = keccak256("viewing" || spending_key)
+pub fn derive_viewing_keypair(spending_key: &[u8; 32]) -> (SecretKey, PublicKey) {
+    let mut preimage = [0u8; 7 + 32];
+    preimage[..7].copy_from_slice(b"viewing");
+    preimage[7..].copy_from_slice(spending_key);
+    let secret_bytes = keccak256(&preimage);
+    let secret = SecretKey::from(secret_bytes);
+    let public = secret.public_key();
+    (secret, public)
+}
+
+/// Encrypt a note for a recipient's viewing public key.
+/// Format: ephemeral_pubkey(32) || nonce(24) || ciphertext
+/// Compatible with the TypeScript SDK's decryptNote().
+pub fn encrypt_note(note: &Note, recipient_viewing_pubkey: &PublicKey) -> Vec<u8> {
+    seal(&note_json(note).to_string().into_bytes(), recipient_viewing_pubkey)
+}
+
+/// Fresh randomness (from the process-wide `entropy` source) for a payload
+/// a transfer proves. `OutputEncryption::encrypt` then builds the payload to
+/// post, in the same format as `encrypt_note`.
+pub fn output_encryption(recipient_viewing_pubkey: &PublicKey) -> Result<OutputEncryption> {
+    Ok(OutputEncryption {
+        viewing_pubkey: *recipient_viewing_pubkey.as_bytes(),
+        ephemeral_secret: entropy::random_bytes()?,
+        nonce: entropy::random_bytes()?,
+    })
+}
+
+/// Decrypt a note encrypted by `encrypt_note` (or the TypeScript SDK's
+/// encryptNote()). Returns `None` if it is not for `viewing_secret`.
+pub fn decrypt_note(encrypted: &[u8], viewing_secret: &SecretKey) -> Option<Note> {
+    let plaintext = open(encrypted, viewing_secret)?;
+    note_from_json(&serde_json::from_slice(&plaintext).ok()?)
+}
+
+/// A note as the encrypted payload's JSON object (same format as TS SDK).
+pub(crate) fn note_json(note: &Note) -> serde_json::Value {
+    serde_json::json!({
+        "amount": note.amount.to_string(),
+        "pubkey": format!("0x{}", hex::encode(note.pubkey)),
+        "blinding": format!("0x{}", hex::encode(note.blinding)),
+    })
+}
+
+pub(crate) fn note_from_json(value: &serde_json::Value) -> Option<Note> {
+    Some(Note {
+        amount: value.get("amount")?.as_str()?.parse().ok()?,
+        pubkey: decode_hex_fixed(value.get("pubkey")?.as_str()?).ok()?,
+        blinding: decode_hex_fixed(value.get("blinding")?.as_str()?).ok()?,
+    })
+}
+
+/// Encrypt `plaintext` to a viewing public key under a fresh ephemeral key
+/// (from the process-wide `entropy` source).
+/// Format: ephemeral_pubkey(32) || nonce(24) || ciphertext
+pub(crate) fn seal(plaintext: &[u8], recipient_viewing_pubkey: &PublicKey) -> Vec<u8> {
+    // Generate ephemeral keypair
+    let ephemeral_secret = SecretKey::generate(&mut entropy::rng());
+    let ephemeral_public = ephemeral_secret.public_key();
+
+    // Create NaCl box and encrypt
+    let salsa_box = SalsaBox::new(recipient_viewing_pubkey, &ephemeral_secret);
+    let nonce = SalsaBox::generate_nonce(&mut entropy::rng());
+
+    let ciphertext = salsa_box.encrypt(&nonce, plaintext)
+        .expect("encryption should not fail");
+
+    // Pack: ephemeral_pubkey(32) || nonce(24) || ciphertext
+    let mut result = Vec::with_capacity(32 + 24 + ciphertext.len());
+    result.extend_from_slice(ephemeral_public.as_bytes());
+    result.extend_from_slice(&nonce);
+    result.extend_from_slice(&ciphertext);
+    result
+}
+
+/// Inverse of `seal`; `None` if the payload is not for `viewing_secret`.
+pub(crate) fn open(encrypted: &[u8], viewing_secret: &SecretKey) -> Option<Vec<u8>> {
+    if encrypted.len() < 32 + 24 {
+        return None;
+    }
+    let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(&encrypted[..32]).ok()?);
+    let nonce = crypto_box::Nonce::from(<[u8; 24]>::try_from(&encrypted[32..56]).ok()?);
+    SalsaBox::new(&ephemeral_public, viewing_secret).decrypt(&nonce, &encrypted[56..]).ok()
+}
+
+/// Read a keystore passphrase from `WALLET_PASSPHRASE`, or prompt on the terminal.
+/// With `confirm`, the prompt asks twice and fails if the entries differ.
+pub fn read_passphrase(prompt: &str, confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("WALLET_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password(prompt)?;
+    ensure!(!passphrase.is_empty(), "passphrase must not be empty");
+    if confirm {
+        let again = rpassword::prompt_password("Confirm passphrase: ")?;
+        ensure!(passphrase == again, "passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+/// Decode a hex string (with or without 0x prefix).
+pub fn decode_hex(s: &str) -> Result<Vec<u8>> {
+    Ok(hex::decode(s.strip_prefix("0x").unwrap_or(s))?)
+}
+
+/// Decode a hex string (with or without 0x prefix) into exactly `N` bytes.
+pub fn decode_hex_fixed<const N: usize>(s: &str) -> Result<[u8; N]> {
+    let bytes = decode_hex(s)?;
+    ensure!(bytes.len() == N, "expected {N} bytes, got {}", bytes.len());
+    let mut arr = [0u8; N];
+    arr.copy_from_slice(&bytes);
+    Ok(arr)
+}
+
+/// Decimal places of USDT amounts (1 USDT = 1_000_000 base units).
+pub const USDT_DECIMALS: usize = 6;
+
+/// Parse a USDT amount like "1.5" into base units (1500000), exactly: an
+/// integer part and at most `USDT_DECIMALS` fractional digits. Signs,
+/// exponents, extra decimals and amounts beyond `u64` are errors rather
+/// than rounded or saturated.
+pub fn parse_usdt(s: &str) -> Result<u64> {
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    ensure!(
+        !(whole.is_empty() && fraction.is_empty()) && digits(whole) && digits(fraction),
+        "invalid USDT amount {s:?}: expected digits with an optional decimal point"
+    );
+    ensure!(
+        fraction.len() <= USDT_DECIMALS,
+        "invalid USDT amount {s:?}: USDT has {USDT_DECIMALS} decimal places"
+    );
+    let scale = 10u64.pow(USDT_DECIMALS as u32);
+    let too_large = || anyhow::anyhow!("USDT amount {s:?} is too large");
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| too_large())? };
+    let fraction: u64 = format!("{fraction:0<USDT_DECIMALS$}").parse()?;
+    whole.checked_mul(scale).and_then(|units| units.checked_add(fraction)).ok_or_else(too_large)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_usdt_is_exact() {
+        assert_eq!(parse_usdt("1.5").unwrap(), 1_500_000);
+        assert_eq!(parse_usdt("0.000001").unwrap(), 1);
+        assert_eq!(parse_usdt("7").unwrap(), 7_000_000);
+        assert_eq!(parse_usdt(".25").unwrap(), 250_000);
+        assert_eq!(parse_usdt("3.").unwrap(), 3_000_000);
+        // Above 2^53 base units, where an f64 would drop the last digits
+        assert_eq!(parse_usdt("9007199254.740993").unwrap(), 9_007_199_254_740_993);
+        assert_eq!(parse_usdt("18446744073709.551615").unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_parse_usdt_rejects_what_it_cannot_represent() {
+        for bad in [
+            "", ".", "inf", "NaN", "-1", "+1", "1e3", "0x10", " 1", "1 ", "1.2.3", "1,5", "0.0000001",
+            "18446744073709.551616", "99999999999999999999",
+        ] {
+            assert!(parse_usdt(bad).is_err(), "{bad:?} parsed");
+        }
+    }
+}
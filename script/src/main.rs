@@ -1,18 +1,208 @@
 //! SP1 Proof Generation CLI for the Shielded Pool.
 //!
 //! Subcommands:
-//!   transfer  - Generate a transfer proof (2-in-2-out)
-//!   withdraw  - Generate a withdraw proof
+//!   transfer  - Generate a transfer proof (2-in-2-out), bound to --chain-id/--pool
+//!               (default: CHAIN_ID/POOL_ADDRESS) so only that pool accepts it
+//!   withdraw  - Generate a withdraw proof, bound the same way, or with --submit withdraw
+//!               a wallet note on-chain (partial withdrawals return change to the wallet)
+//!               (transfer and withdraw take --format calldata to write the ready-to-send
+//!               pool calldata instead of the proof JSON, or --format foundry to write a
+//!               fixture for the Solidity tests)
+//!   claim     - Generate a claim proof (a note held at a past snapshot, for airdrops)
+//!   join-split - Generate a join-split proof (up to 4 notes in, up to 4 out)
+//!   deposit   - Generate a deposit proof (the commitment opens to the deposited amount),
+//!               or with --amount and --to make a deposit and record its note in the wallet
+//!   batch-withdraw - Generate a batch-withdraw proof (up to 8 notes to one recipient)
+//!   consolidate - Generate a consolidation proof (up to 8 notes of one key merged into one)
+//!   attest    - Generate a proof of funds (unspent notes worth at least a threshold, nothing spent)
+//!   disclose  - Generate a disclosure proof (chosen payloads opened under a viewing key)
+//!   policy-spend - Generate a policy-spend proof (a payment under a daily spending limit)
+//!   payout    - Generate a payout proof (up to 2 notes paying up to 8 distinct recipients)
+//!   prepare-claim - Build claim inputs for a wallet note against the pool as of a past block or root
+//!   prove-absence  - Prove a commitment was never inserted into the pool (disputed deposits)
+//!   verify-absence - Check an absence proof, and against the chain when RPC_URL is set
 //!   vkeys     - Print verification keys for contract deployment
-//!   execute   - Execute a program without proof generation (for testing)
+//!   verify    - Re-verify a proof output against the embedded vkeys and print its public values
+//!   request-hash - Print the canonical hash of a proof request (job/dedup/audit key)
+//!   execute   - Execute a guest without proving and report its cycles (per syscall and
+//!               per section, optionally as JSON), failing past --expected-cycles
+//!   note      - Export/import a single note as a base64 bundle (out-of-band handoff),
+//!               manage note metadata and idle-note reminders
+//!   balance   - Sync the pool and print the wallet's unspent notes and total (no proofs)
+//!   send      - Pay a shielded address from the wallet: select notes, prove, submit, update the wallet
+//!   scan      - Trial-decrypt the pool's encrypted outputs and add incoming notes to the wallet
+//!   keygen    - Generate a mnemonic-backed spending key into the wallet (or --recover one)
+//!   wallet    - Verify a wallet written by an older version and rewrite it (`wallet fix`),
+//!               import a JSON wallet into a database (`wallet migrate`), list its transactions
+//!   audit     - Export the hash-chained audit log as a report for auditors (`audit export`),
+//!               or prove chosen notes to an auditor under the viewing key (`audit disclose`)
+//!   message   - Read messages carried in encrypted outputs, threaded by counterparty
+//!   walletd   - Serve the wallet over JSON-RPC on localhost (balance, notes, transfers,
+//!               withdrawals, sync status) for GUIs and scripts in other languages
+//!   reproduce - Rebuild a guest reproducibly and check its vkey against the registry and chain
+//!   prepare-emergency-kit - Bundle keys, wallet, checkpoint and exit instructions, encrypted, for escrow
+//!   verify-emergency-kit  - Check a kit is complete and undamaged without decrypting it
+//!   execute-emergency-kit - Decrypt a kit, restore its wallet and checkpoint, and run the exit
 
-use anyhow::Result;
-use clap::{ Parser, Subcommand };
+use alloy::{
+    primitives::{ Address, FixedBytes, U256 },
+    providers::{ Provider, ProviderBuilder },
+    signers::local::PrivateKeySigner,
+    sol,
+    sol_types::SolCall,
+};
+use anyhow::{ bail, ensure, Context, Result };
+use clap::{ Parser, Subcommand, ValueEnum };
+use crypto_box::PublicKey;
+use rand::Rng;
+use shielded_pool_lib::{
+    attest::{ AttestPrivateInputs, AttestPublicValues },
+    batch_withdraw::{ BatchWithdrawPrivateInputs, BatchWithdrawPublicValues },
+    claim::{ ClaimPrivateInputs, ClaimPublicValues },
+    consolidate::{ ConsolidatePrivateInputs, ConsolidatePublicValues },
+    commitment_index::{ AbsenceProof, CommitmentIndex, IndexRoot },
+    deposit::{ DepositPrivateInputs, DepositPublicValues },
+    disclosure::{ DisclosurePrivateInputs, DisclosurePublicValues },
+    policy::{ PolicySpendPrivateInputs, PolicySpendPublicValues },
+    payout::{ PayoutPrivateInputs, PayoutPublicValues },
+    join_split::{ JoinSplitPrivateInputs, JoinSplitPublicValues },
+    note_bundle::NoteBundle,
+    prover_fee::FeeQuote,
+    simulate::{simulate_transfer, simulate_withdraw},
+    coin_selection::{ plan_transfer_split, ChangeSplit, NoteRef, SelectionConfig },
+    compute_nullifier,
+    pool_binding,
+    CircuitKind,
+    IncrementalMerkleTree,
+    Note,
+    SpenderKey,
+    SpendingKey,
+    DUMMY_SPENDING_KEY,
+    TransferPrivateInputs,
+    TransferPublicValues,
+    WithdrawPublicValues,
+};
+use shielded_pool_script::{
+    audit::{ AuditAction, AuditEntry, AuditLog, AuditNote, AuditReport },
+    chains::FinalityPolicy,
+    cycles::CycleReport,
+    decode_hex,
+    decode_hex_fixed,
+    decrypt_note,
+    derive_viewing_keypair,
+    emergency::{ read_kit_passphrase, EmergencyKit, KitDeployment },
+    encrypt_note,
+    entropy,
+    explain_rejection,
+    framed_stdin,
+    key_cache,
+    keygen,
+    output_encryption,
+    parse_usdt,
+    proof_cache,
+    read_passphrase,
+    idle::{ unix_now, IdlePolicy, SweepAction },
+    reproduce::{ self, Check, VkeyRegistry },
+    strict,
+    multicall::{ BatchConfig, PoolViews },
+    sync::{
+        block_hash,
+        check_scan_cursor,
+        encrypted_note,
+        find_root_block,
+        pool_snapshot,
+        replay_nullifiers,
+        scan_outputs,
+        sync_pool_tree,
+        PoolSnapshot,
+        PoolSync,
+        TreeCheckpoint,
+        SyncStatus,
+        TreeSource,
+    },
+    messaging::MessageBody,
+    wallet::{ parse_metadata_arg, wallet_path, MessageDirection, SyncCursor, WalletMessage, WalletNote, WalletState },
+    wallet_store::{ self, Migration },
+    walletd::{ self, ListNotesParams, NoteView, RpcError, TransferParams, WithdrawParams },
+};
 use sp1_sdk::{ include_elf, HashableKey, ProverClient, SP1Stdin };
 use std::fs;
+use std::path::{ Path, PathBuf };
+use std::sync::{ Arc, Mutex };
 
 pub const TRANSFER_ELF: &[u8] = include_elf!("transfer-program");
 pub const WITHDRAW_ELF: &[u8] = include_elf!("withdraw-program");
+pub const CLAIM_ELF: &[u8] = include_elf!("claim-program");
+pub const JOIN_SPLIT_ELF: &[u8] = include_elf!("join-split-program");
+pub const DEPOSIT_ELF: &[u8] = include_elf!("deposit-program");
+pub const BATCH_WITHDRAW_ELF: &[u8] = include_elf!("batch-withdraw-program");
+pub const CONSOLIDATE_ELF: &[u8] = include_elf!("consolidate-program");
+pub const ATTEST_ELF: &[u8] = include_elf!("attest-program");
+pub const DISCLOSE_ELF: &[u8] = include_elf!("disclose-program");
+pub const POLICY_SPEND_ELF: &[u8] = include_elf!("policy-spend-program");
+pub const PAYOUT_ELF: &[u8] = include_elf!("payout-program");
+
+sol! {
+    #[sol(rpc)]
+    interface IERC20 {
+        function approve(address spender, uint256 amount) external returns (bool);
+    }
+
+    #[sol(rpc)]
+    interface IShieldedPool {
+        function TOKEN() external view returns (address);
+        function DEPOSIT_VKEY() external view returns (bytes32);
+        function TRANSFER_VKEY() external view returns (bytes32);
+        function WITHDRAW_VKEY() external view returns (bytes32);
+        function deposit(bytes32 commitment, uint256 amount, bytes calldata encryptedData) external;
+        function depositWithProof(
+            bytes calldata proof,
+            bytes calldata publicValues,
+            bytes calldata encryptedData
+        ) external;
+        function privateTransfer(
+            bytes calldata proof,
+            bytes calldata publicValues,
+            bytes calldata encryptedOutput1,
+            bytes calldata encryptedOutput2
+        ) external;
+        function withdraw(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedChange) external;
+        function withdrawAndCall(
+            bytes calldata proof,
+            bytes calldata publicValues,
+            bytes calldata encryptedChange,
+            address target,
+            bytes calldata data
+        ) external;
+        function joinSplit(bytes calldata proof, bytes calldata publicValues, bytes[4] calldata encryptedOutputs) external;
+        function batchWithdraw(bytes calldata proof, bytes calldata publicValues) external;
+        function consolidate(bytes calldata proof, bytes calldata publicValues, bytes calldata encryptedOutput) external;
+        function payout(bytes calldata proof, bytes calldata publicValues, bytes[8] calldata encryptedOutputs) external;
+        function policySpend(
+            bytes calldata proof,
+            bytes calldata publicValues,
+            bytes calldata encryptedPayment,
+            bytes calldata encryptedChange
+        ) external;
+
+        event Deposit(bytes32 indexed commitment, uint256 amount, uint32 leafIndex, uint256 timestamp);
+    }
+}
+
+/// Circuit names accepted by `request-hash`, `execute`, `reproduce` and `verify`.
+const CIRCUITS: [&str; 11] = [
+    "transfer",
+    "withdraw",
+    "claim",
+    "join-split",
+    "deposit",
+    "batch-withdraw",
+    "consolidate",
+    "attest",
+    "disclose",
+    "policy-spend",
+    "payout",
+];
 
 // Type alias: ProverClient::from_env() returns EnvProver
 type Client = sp1_sdk::EnvProver;
@@ -23,6 +213,9 @@ type Client = sp1_sdk::EnvProver;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Fail instead of falling back on inconsistent state (default when CI is set; see STRICT)
+    #[arg(long, global = true, default_value = "false")]
+    strict: bool,
 }
 
 #[derive(Subcommand)]
@@ -35,13 +228,240 @@ enum Commands {
         /// Path to write proof output JSON
         #[arg(long)]
         output: String,
+        /// json: the proof output; calldata: only the privateTransfer calldata, to send from any wallet;
+        /// foundry: a fixture for the Solidity tests (see FoundryFixture)
+        #[arg(long, value_enum, default_value = "json", conflicts_with = "execute_only")]
+        format: OutputFormat,
         /// Just execute without generating a real proof (fast, for testing)
         #[arg(long, default_value = "false")]
         execute_only: bool,
+        /// Fee quote (JSON) the transfer must pay in its second output; refuse to prove otherwise
+        #[arg(long)]
+        fee_quote: Option<PathBuf>,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+        /// Depth of the pool's tree (default: TREE_LEVELS); refuse Merkle proofs of another depth
+        #[arg(long)]
+        tree_levels: Option<u64>,
     },
     /// Generate a withdraw proof
     Withdraw {
         /// Path to JSON file with WithdrawPrivateInputs
+        #[arg(long, required_unless_present = "submit")]
+        input: Option<String>,
+        /// Path to write proof output JSON
+        #[arg(long, required_unless_present = "submit")]
+        output: Option<String>,
+        /// json: the proof output; calldata: only the withdraw (or withdrawAndCall) calldata;
+        /// foundry: a fixture for the Solidity tests (see FoundryFixture)
+        #[arg(long, value_enum, default_value = "json", conflicts_with_all = ["execute_only", "submit"])]
+        format: OutputFormat,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+        /// Contract the pool calls with the withdrawn funds (withdraw-and-call); sets the input's call target
+        #[arg(long, requires = "calldata")]
+        call_target: Option<String>,
+        /// Hex calldata for --call-target; the proof commits its keccak256
+        #[arg(long, requires = "call_target")]
+        calldata: Option<String>,
+        /// Depth of the pool's tree (default: TREE_LEVELS); refuse a Merkle proof of another depth
+        #[arg(long)]
+        tree_levels: Option<u64>,
+        /// Withdraw a wallet note end to end instead: select it, prove and send the
+        /// withdrawal, and record the change (RPC_URL, PRIVATE_KEY, POOL_ADDRESS)
+        #[arg(
+            long,
+            default_value = "false",
+            conflicts_with_all = ["input", "output", "execute_only", "chain_id", "pool", "call_target"]
+        )]
+        submit: bool,
+        /// With --submit: USDT to withdraw, the rest returning as change (default: the whole note)
+        #[arg(long, requires = "submit")]
+        amount: Option<String>,
+        /// With --submit: the note to withdraw, by label or commitment (default: the smallest that covers --amount)
+        #[arg(long, requires = "submit")]
+        note: Option<String>,
+        /// With --submit: address to pay out to (default: the PRIVATE_KEY account)
+        #[arg(long, requires = "submit")]
+        to: Option<String>,
+        /// With --submit: wallet file (default: WALLET_FILE)
+        #[arg(long, requires = "submit")]
+        wallet: Option<PathBuf>,
+    },
+    /// Generate a claim proof: a note was held at a past snapshot of the pool
+    Claim {
+        /// Path to JSON file with ClaimPrivateInputs (see `prepare-claim`)
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool of the snapshot (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a join-split proof: up to 4 notes in and 4 out, padded with dummies
+    JoinSplit {
+        /// Path to JSON file with JoinSplitPrivateInputs (4 inputs and 4 outputs)
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a deposit proof: the commitment opens to the deposited amount.
+    /// With --amount and --to, make a deposit instead: create the note, approve
+    /// and deposit it (needs RPC_URL, PRIVATE_KEY, POOL_ADDRESS) and record it
+    /// in the wallet
+    Deposit {
+        /// Path to JSON file with DepositPrivateInputs
+        #[arg(long, required_unless_present = "amount")]
+        input: Option<String>,
+        /// Path to write proof output JSON
+        #[arg(long, required_unless_present = "amount")]
+        output: Option<String>,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// USDT to deposit, e.g. 1.5
+        #[arg(long, requires = "to", conflicts_with_all = ["input", "output", "execute_only"])]
+        amount: Option<String>,
+        /// Who the note is for: a wallet key's label or pubkey, or any 32-byte hex shielded pubkey
+        #[arg(long, requires = "amount")]
+        to: Option<String>,
+        /// Viewing pubkey to post the note encrypted to, for a pubkey not in the wallet
+        #[arg(long, requires = "to")]
+        viewing_pubkey: Option<String>,
+        /// Label for the note in the wallet (default: deposit_<leaf index>)
+        #[arg(long, requires = "amount")]
+        label: Option<String>,
+        /// Wallet file to record the note in (default: WALLET_FILE)
+        #[arg(long, requires = "amount")]
+        wallet: Option<PathBuf>,
+    },
+    /// Generate a batch-withdraw proof: up to 8 notes to one recipient, one total amount
+    BatchWithdraw {
+        /// Path to JSON file with BatchWithdrawPrivateInputs
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a consolidation proof: up to 8 notes of one key merged into one
+    Consolidate {
+        /// Path to JSON file with ConsolidatePrivateInputs
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a proof of funds: up to 8 unspent notes worth at least a threshold,
+    /// for a counterparty's nonce, without spending them
+    Attest {
+        /// Path to JSON file with AttestPrivateInputs
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a disclosure proof: up to 8 posted payloads opened under a viewing
+    /// key for an auditor (see also `audit disclose`)
+    Disclose {
+        /// Path to JSON file with DisclosurePrivateInputs
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the payloads were posted to (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a policy-spend proof: a payment out of a note under a daily
+    /// spending-limit policy
+    PolicySpend {
+        /// Path to JSON file with PolicySpendPrivateInputs
+        #[arg(long)]
+        input: String,
+        /// Path to write proof output JSON
+        #[arg(long)]
+        output: String,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Generate a payout proof: up to 2 notes paying up to 8 recipients, each
+    /// to a different pubkey
+    Payout {
+        /// Path to JSON file with PayoutPrivateInputs
         #[arg(long)]
         input: String,
         /// Path to write proof output JSON
@@ -50,89 +470,954 @@ enum Commands {
         /// Just execute without generating a real proof (fast, for testing)
         #[arg(long, default_value = "false")]
         execute_only: bool,
+        /// Chain of the pool the proof is for (default: CHAIN_ID)
+        #[arg(long)]
+        chain_id: Option<u64>,
+        /// ShieldedPool the proof is for (default: POOL_ADDRESS); sets the input's pool binding
+        #[arg(long)]
+        pool: Option<String>,
+    },
+    /// Build claim inputs for a wallet note from the pool as of a past block or root
+    /// (needs RPC_URL with archive state, POOL_ADDRESS, DEPLOY_BLOCK, TREE_LEVELS)
+    PrepareClaim {
+        /// Label or hex commitment of the note in the wallet
+        note: String,
+        /// Snapshot block (default: the block at whose end --root was the pool's root)
+        #[arg(long)]
+        block: Option<u64>,
+        /// Snapshot tree root the airdrop published (hex); fail if the pool's root differs
+        #[arg(long)]
+        root: Option<String>,
+        /// Snapshot nullifier root the airdrop published (hex); fail if it differs
+        #[arg(long)]
+        nullifier_root: Option<String>,
+        /// Airdrop id (hex bytes32) the claim nullifier is scoped to
+        #[arg(long)]
+        airdrop: String,
+        /// Address the airdrop pays
+        #[arg(long)]
+        recipient: String,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+        /// Where to write the claim inputs
+        #[arg(long, default_value = "fixtures/claim-input.json")]
+        out: PathBuf,
+    },
+    /// Prove a commitment was never inserted into the pool, as of a block
+    /// (needs RPC_URL with archive state, POOL_ADDRESS, DEPLOY_BLOCK, TREE_LEVELS)
+    ProveAbsence {
+        /// The commitment (hex) said to have been deposited
+        commitment: String,
+        /// Block to prove it at (default: the last final block)
+        #[arg(long)]
+        block: Option<u64>,
+        /// Where to write the proof
+        #[arg(long, default_value = "fixtures/absence-proof.json")]
+        out: PathBuf,
+    },
+    /// Check an absence proof; with RPC_URL set, also rebuild its index from the chain
+    VerifyAbsence {
+        #[arg(long, default_value = "fixtures/absence-proof.json")]
+        proof: PathBuf,
     },
     /// Print the verification keys (for deploying contracts)
     Vkeys,
+    /// Re-verify a proof output (or Foundry fixture) against the embedded vkeys and print its public values
+    Verify {
+        /// Path to the proof output JSON
+        #[arg(long)]
+        proof: PathBuf,
+        /// Circuit the proof is for (default: the one whose vkey the file names)
+        #[arg(long, value_parser = CIRCUITS)]
+        circuit: Option<String>,
+    },
+    /// Execute a guest on its inputs without proving and report where the cycles go
+    Execute {
+        /// Circuit the inputs are for
+        #[arg(long, value_parser = CIRCUITS)]
+        circuit: String,
+        /// Path to JSON file with the private inputs
+        #[arg(long)]
+        input: String,
+        /// Also write the cycle report as JSON
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Fail if the execution takes more cycles than this (a budget for CI)
+        #[arg(long)]
+        expected_cycles: Option<u64>,
+    },
+    /// Print the canonical hash of a proof request, without proving
+    RequestHash {
+        /// Circuit the inputs are for
+        #[arg(long, value_parser = CIRCUITS)]
+        circuit: String,
+        /// Path to JSON file with the private inputs
+        #[arg(long)]
+        input: String,
+    },
+    /// Rebuild a guest in SP1's pinned Docker image and check its vkey
+    Reproduce {
+        /// Circuit to rebuild
+        #[arg(long, value_parser = CIRCUITS)]
+        circuit: String,
+        /// Vkey registry to compare against (default: deploy/vkeys.json)
+        #[arg(long)]
+        registry: Option<PathBuf>,
+        /// Deployed ShieldedPool to compare against (default: POOL_ADDRESS)
+        #[arg(long)]
+        pool: Option<String>,
+        /// RPC endpoint for the on-chain check (default: RPC_URL)
+        #[arg(long)]
+        rpc_url: Option<String>,
+        /// Record the rebuilt vkey and ELF hash in the registry
+        #[arg(long, default_value = "false")]
+        update_registry: bool,
+    },
+    /// Hand a note to someone else out-of-band
+    Note {
+        #[command(subcommand)]
+        action: NoteCommand,
+    },
+    /// Sync the pool and print each wallet note's status and the unspent total
+    /// (RPC_URL, POOL_ADDRESS; tree sync settings as for the exit binary)
+    Balance {
+        /// Wallet file (default: WALLET_FILE)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Pay a shielded address from the wallet's notes: select inputs, prove the
+    /// transfer, submit it and record the change (RPC_URL, PRIVATE_KEY,
+    /// POOL_ADDRESS; tree sync settings as for the exit binary)
+    Send {
+        /// Who to pay: a wallet key's label or pubkey, or any 32-byte hex shielded pubkey
+        #[arg(long)]
+        to: String,
+        /// USDT to send, e.g. 0.5
+        #[arg(long)]
+        amount: String,
+        /// Viewing pubkey to encrypt the payment to (needed for a pubkey not in the wallet)
+        #[arg(long)]
+        viewing_pubkey: Option<String>,
+        /// Wallet key to spend from (default: the one holding the most)
+        #[arg(long)]
+        from: Option<String>,
+        /// Wallet file (default: WALLET_FILE)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Find incoming notes: trial-decrypt the encrypted outputs of a block range
+    /// with the wallet's viewing keys and add new notes to the wallet
+    /// (RPC_URL, POOL_ADDRESS)
+    Scan {
+        /// First block to scan (default: where the last scan of this pool stopped)
+        #[arg(long)]
+        from_block: Option<u64>,
+        /// Last block to scan (default: the latest final block)
+        #[arg(long)]
+        to_block: Option<u64>,
+        /// Wallet file (default: WALLET_FILE)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Generate a mnemonic-backed spending key, add it encrypted to the wallet
+    /// and print its shielded address
+    Keygen {
+        /// Label of the key in the wallet
+        #[arg(long)]
+        label: String,
+        /// Restore the key from its mnemonic (prompted for) instead of generating one
+        #[arg(long, default_value = "false")]
+        recover: bool,
+        /// Wallet file to add the key to (default: WALLET_FILE); created if missing
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Maintain the wallet (file or database)
+    Wallet {
+        #[command(subcommand)]
+        action: WalletCommand,
+    },
+    /// Export the audit trail of wallet operations
+    Audit {
+        #[command(subcommand)]
+        action: AuditCommand,
+    },
+    /// Read wallet-to-wallet messages
+    Message {
+        #[command(subcommand)]
+        action: MessageCommand,
+    },
+    /// Serve the wallet over JSON-RPC on localhost until stopped: getBalance,
+    /// listNotes, createTransfer, createWithdraw, getSyncStatus (settings as
+    /// for balance, send and withdraw --submit; WALLETD_TOKEN)
+    Walletd {
+        /// Port on 127.0.0.1 to listen on
+        #[arg(long, default_value = "3004")]
+        port: u16,
+        /// Wallet file (default: WALLET_FILE)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+        /// Where to write the generated token when WALLETD_TOKEN is unset
+        /// (default: walletd.token next to the wallet)
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+    },
+    /// Write an encrypted break-glass kit for exiting from another machine
+    /// (passphrase from KIT_PASSPHRASE or prompted; pool from POOL_ADDRESS etc.)
+    PrepareEmergencyKit {
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+        /// Tree checkpoint to include (default: fixtures/tree-checkpoint.json if present)
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+        /// Where to write the kit
+        #[arg(long, default_value = "fixtures/emergency-kit.json")]
+        out: PathBuf,
+    },
+    /// Check an emergency kit is complete and undamaged, without its passphrase
+    VerifyEmergencyKit {
+        #[arg(long, default_value = "fixtures/emergency-kit.json")]
+        kit: PathBuf,
+    },
+    /// Decrypt an emergency kit and restore what the exit needs
+    ExecuteEmergencyKit {
+        #[arg(long, default_value = "fixtures/emergency-kit.json")]
+        kit: PathBuf,
+        /// Directory to restore the wallet, checkpoint and instructions into
+        #[arg(long, default_value = "fixtures/emergency")]
+        out: PathBuf,
+        /// Run the exit (withdraw every note) once restored; needs RPC_URL and PRIVATE_KEY
+        #[arg(long, default_value = "false")]
+        run: bool,
+    },
 }
 
-#[derive(serde::Serialize)]
+#[derive(Subcommand)]
+enum MessageCommand {
+    /// Decrypt an encrypted output and record the message it carries
+    Open {
+        /// Hex-encoded encrypted output (from a PrivateTransfer event)
+        encrypted: String,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Print message threads, one per counterparty
+    List {
+        /// Only the thread with this counterparty (hex viewing pubkey)
+        #[arg(long = "with", value_name = "VIEWING_PUBKEY")]
+        counterparty: Option<String>,
+        /// Print the messages as JSON instead of text
+        #[arg(long, default_value = "false")]
+        json: bool,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Verify the audit log and write it out as a report with per-note histories
+    Export {
+        /// Audit log (default: AUDIT_LOG or fixtures/audit-log.jsonl)
+        #[arg(long)]
+        log: Option<PathBuf>,
+        /// Wallet file, to mark notes still held (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+        /// Where to write the report
+        #[arg(long, default_value = "fixtures/audit-report.json")]
+        out: PathBuf,
+    },
+    /// Prove chosen wallet notes to an auditor: open their posted payloads under
+    /// the viewing key and nothing else (needs RPC_URL and POOL_ADDRESS)
+    Disclose {
+        /// Note to disclose, by label or commitment (1 to 8, repeatable)
+        #[arg(long = "note", required = true)]
+        notes: Vec<String>,
+        /// Audit request id the auditor gave (32 bytes of hex)
+        #[arg(long)]
+        audit_id: String,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+        /// Where to write the disclose inputs
+        #[arg(long, default_value = "fixtures/disclosure-input.json")]
+        input: PathBuf,
+        /// Where to write the disclosure package (proof output JSON)
+        #[arg(long, default_value = "fixtures/disclosure.json")]
+        out: PathBuf,
+        /// Just execute without generating a real proof (fast, for testing)
+        #[arg(long, default_value = "false")]
+        execute_only: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum WalletCommand {
+    /// Verify a wallet from an older script version and rewrite it in the current schema
+    Fix {
+        /// Print the changes without rewriting the file
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Import a JSON wallet into a new wallet database
+    Migrate {
+        /// JSON wallet to import (default: the database path with a .json extension)
+        #[arg(long)]
+        from: Option<PathBuf>,
+        /// Wallet database to create (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// List the pool transactions sent from the wallet
+    History {
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum NoteCommand {
+    /// Print a wallet note as a bundle string
+    Export {
+        /// Label or hex commitment of the note in the wallet
+        note: String,
+        /// Leave the note's label out of the bundle
+        #[arg(long, default_value = "false")]
+        no_label: bool,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Decode a bundle and add the note to the wallet
+    Import {
+        /// Bundle string from `note export`
+        bundle: String,
+        /// Label to store the note under (default: the bundle's label)
+        #[arg(long)]
+        label: Option<String>,
+        /// Metadata to attach, as namespace:name=JSON (repeatable)
+        #[arg(long = "meta", value_name = "KEY=VALUE")]
+        meta: Vec<String>,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// List wallet notes, optionally filtered by metadata
+    List {
+        /// Only notes with this key, or this key=JSON value (repeatable; all must match)
+        #[arg(long = "meta", value_name = "KEY[=VALUE]")]
+        meta: Vec<String>,
+        /// Print matching notes as JSON (including metadata) instead of a table
+        #[arg(long, default_value = "false")]
+        json: bool,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Set a metadata entry on a wallet note
+    Tag {
+        /// Label or hex commitment of the note in the wallet
+        note: String,
+        /// Entry as namespace:name=JSON (bare strings need no quotes)
+        #[arg(value_name = "KEY=VALUE")]
+        entry: String,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Show notes untouched for longer than the wallet's idle policy, or change the policy
+    Idle {
+        /// Set how many days without use make a note idle
+        #[arg(long)]
+        remind_after_days: Option<u64>,
+        /// Set what `make sweep-idle` does with idle notes
+        #[arg(long, value_enum)]
+        sweep: Option<SweepAction>,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+    /// Remove a metadata entry from a wallet note
+    Untag {
+        /// Label or hex commitment of the note in the wallet
+        note: String,
+        /// Key as namespace:name
+        key: String,
+        /// Wallet file (default: WALLET_FILE or fixtures/wallet.db)
+        #[arg(long)]
+        wallet: Option<PathBuf>,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
 struct ProofOutput {
     /// Hex-encoded Groth16 proof bytes (for on-chain verification)
     proof: String,
     /// Hex-encoded public values (ABI-encoded, passed to Solidity)
+    #[serde(alias = "publicValues")]
     public_values: String,
     /// Hex-encoded verification key (bytes32)
     vkey: String,
+    /// Hex-encoded canonical hash of the private inputs (see shielded_pool_lib::canonical)
+    #[serde(default)]
+    request_hash: String,
+    /// 0x-prefixed calldata of the pool call that submits the proof, selector
+    /// included (proofs of circuits the pool verifies)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    calldata: Option<String>,
+}
+
+/// What a proof command writes to `--output`.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// The proof output (proof, public values, vkey, request hash and calldata)
+    Json,
+    /// Only the 0x-prefixed calldata of the pool call
+    Calldata,
+    /// A `FoundryFixture`, for contract tests to read with `vm.readFile`
+    Foundry,
+}
+
+/// A real proof as the Solidity tests read it: `vm.parseJson` decodes it
+/// into a struct whose fields are these keys in alphabetical order, as
+/// Foundry requires. Byte strings are 0x-prefixed hex. `commitments` are the
+/// output commitments the pool will insert (a withdrawal's change, if any)
+/// and `nullifiers` the ones it will mark spent, both in public-values order.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FoundryFixture {
+    circuit: String,
+    commitments: Vec<String>,
+    nullifiers: Vec<String>,
+    proof: String,
+    public_values: String,
+    /// The Merkle root the proof spends against, which the pool must know
+    root: String,
+    vkey: String,
+}
+
+/// What a transfer or withdraw proof will do to the pool, for `FoundryFixture`.
+struct ExpectedEffects {
+    root: [u8; 32],
+    nullifiers: Vec<[u8; 32]>,
+    commitments: Vec<[u8; 32]>,
+}
+
+/// The pool call that submits a proof, all but the proof and public values.
+/// The wider spends are submitted without encrypted outputs.
+enum PoolCall {
+    Transfer { encrypted_outputs: [Vec<u8>; 2] },
+    Withdraw,
+    WithdrawAndCall(WithdrawCall),
+    JoinSplit,
+    BatchWithdraw,
+    Consolidate,
+    Payout,
+    PolicySpend,
+}
+
+/// The call a withdraw-and-call proof commits: `target` is called with `data`.
+struct WithdrawCall {
+    target: [u8; 20],
+    data: Vec<u8>,
+}
+
+impl PoolCall {
+    /// ABI-encoded calldata, selector included.
+    fn calldata(&self, proof: &[u8], public_values: &[u8]) -> Vec<u8> {
+        let (proof, public_values) = (proof.to_vec().into(), public_values.to_vec().into());
+        match self {
+            PoolCall::Transfer { encrypted_outputs: [first, second] } => IShieldedPool::privateTransferCall {
+                proof,
+                publicValues: public_values,
+                encryptedOutput1: first.clone().into(),
+                encryptedOutput2: second.clone().into(),
+            }.abi_encode(),
+            PoolCall::Withdraw => IShieldedPool::withdrawCall {
+                proof,
+                publicValues: public_values,
+                encryptedChange: Default::default(),
+            }.abi_encode(),
+            PoolCall::WithdrawAndCall(call) => IShieldedPool::withdrawAndCallCall {
+                proof,
+                publicValues: public_values,
+                encryptedChange: Default::default(),
+                target: Address::from(call.target),
+                data: call.data.clone().into(),
+            }.abi_encode(),
+            PoolCall::JoinSplit => IShieldedPool::joinSplitCall {
+                proof,
+                publicValues: public_values,
+                encryptedOutputs: Default::default(),
+            }.abi_encode(),
+            PoolCall::BatchWithdraw => IShieldedPool::batchWithdrawCall { proof, publicValues: public_values }.abi_encode(),
+            PoolCall::Consolidate => IShieldedPool::consolidateCall {
+                proof,
+                publicValues: public_values,
+                encryptedOutput: Default::default(),
+            }.abi_encode(),
+            PoolCall::Payout => IShieldedPool::payoutCall {
+                proof,
+                publicValues: public_values,
+                encryptedOutputs: Default::default(),
+            }.abi_encode(),
+            PoolCall::PolicySpend => IShieldedPool::policySpendCall {
+                proof,
+                publicValues: public_values,
+                encryptedPayment: Default::default(),
+                encryptedChange: Default::default(),
+            }.abi_encode(),
+        }
+    }
 }
 
 fn main() -> Result<()> {
+    shielded_pool_script::config::load()?;
     sp1_sdk::utils::setup_logger();
     let cli = Cli::parse();
-    let client = ProverClient::from_env();
+    if cli.strict {
+        strict::enable();
+    }
 
+    // Note bundles, balances, scans, key generation, wallet maintenance, audit
+    // exports, messages, request hashes, claim inputs, absence proofs and
+    // emergency kits never touch the prover; on-chain deposits, sends,
+    // withdrawals and the exit a kit runs set up their own
     match cli.command {
-        Commands::Transfer { input, output, execute_only } => {
-            generate_proof(&client, TRANSFER_ELF, "transfer", &input, &output, execute_only)?;
+        Commands::Note { action } => return run_note_command(action),
+        Commands::Balance { wallet } => return tokio::runtime::Runtime::new()?.block_on(print_balance(wallet)),
+        Commands::Withdraw { submit: true, amount, note, to, wallet, tree_levels, .. } => {
+            let request = WithdrawRequest { amount, note, to, wallet, tree_levels };
+            return tokio::runtime::Runtime::new()?.block_on(withdraw_on_chain(request)).map(|_| ());
         }
-        Commands::Withdraw { input, output, execute_only } => {
-            generate_proof(&client, WITHDRAW_ELF, "withdraw", &input, &output, execute_only)?;
+        Commands::Send { to, amount, viewing_pubkey, from, wallet } => {
+            let request = SendRequest { to, amount, viewing_pubkey, from, wallet };
+            return tokio::runtime::Runtime::new()?.block_on(send_transfer(request)).map(|_| ());
         }
-        Commands::Vkeys => {
-            let (_, transfer_vk) = client.setup(TRANSFER_ELF);
-            let (_, withdraw_vk) = client.setup(WITHDRAW_ELF);
-            println!("TRANSFER_VKEY: {}", transfer_vk.bytes32());
-            println!("WITHDRAW_VKEY: {}", withdraw_vk.bytes32());
+        Commands::Scan { from_block, to_block, wallet } => {
+            return tokio::runtime::Runtime::new()?.block_on(scan_for_notes(from_block, to_block, wallet));
+        }
+        Commands::Keygen { label, recover, wallet } => return run_keygen(&label, recover, wallet),
+        Commands::Wallet { action } => return run_wallet_command(action),
+        Commands::Audit { action } => return run_audit_command(action),
+        Commands::Message { action } => return run_message_command(action),
+        Commands::Walletd { port, wallet, token_file } => return run_walletd(port, wallet, token_file),
+        Commands::RequestHash { circuit, input } => return print_request_hash(&circuit, &input),
+        Commands::PrepareClaim { note, block, root, nullifier_root, airdrop, recipient, wallet, out } => {
+            let snapshot = ClaimSnapshot { block, root, nullifier_root };
+            return tokio::runtime::Runtime
+                ::new()?
+                .block_on(prepare_claim(&note, snapshot, &airdrop, &recipient, wallet, &out));
+        }
+        Commands::ProveAbsence { commitment, block, out } => {
+            return tokio::runtime::Runtime::new()?.block_on(prove_absence(&commitment, block, &out));
         }
+        Commands::VerifyAbsence { proof } => {
+            return tokio::runtime::Runtime::new()?.block_on(verify_absence(&proof));
+        }
+        Commands::Deposit { amount: Some(amount), to: Some(to), viewing_pubkey, label, wallet, .. } => {
+            let request = DepositRequest { amount, to, viewing_pubkey, label, wallet };
+            return tokio::runtime::Runtime::new()?.block_on(deposit_on_chain(request));
+        }
+        Commands::PrepareEmergencyKit { wallet, checkpoint, out } => {
+            return prepare_emergency_kit(wallet, checkpoint, &out);
+        }
+        Commands::VerifyEmergencyKit { kit } => return verify_emergency_kit(&kit),
+        Commands::ExecuteEmergencyKit { kit, out, run } => return execute_emergency_kit(&kit, &out, run),
+        _ => {}
     }
+    let client = ProverClient::from_env();
 
-    Ok(())
-}
-
-fn generate_proof(
-    client: &Client,
-    elf: &[u8],
-    name: &str,
-    input_path: &str,
-    output_path: &str,
-    execute_only: bool
-) -> Result<()> {
-    // 1. Read inputs from JSON file
-    let input_json = fs::read_to_string(input_path)?;
-
-    // 2. Prepare SP1 stdin — write raw JSON bytes, the guest will deserialize
-    let mut stdin = SP1Stdin::new();
-
-    // Depending on the circuit, deserialize the appropriate type and write it
-    match name {
-        "transfer" => {
-            let inputs: shielded_pool_lib::TransferPrivateInputs = serde_json::from_str(
-                &input_json
+    match cli.command {
+        Commands::Transfer { input, output, format, execute_only, fee_quote, chain_id, pool, tree_levels } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let levels = resolve_tree_levels(tree_levels)?;
+            let fee = match fee_quote {
+                Some(path) => Some(check_fee_quote(&path, &input)?),
+                None => None,
+            };
+            // A relayer only hands out proofs that commit to its fee note
+            let check = |public_values: &[u8]| -> Result<()> {
+                if let Some((quote, amount)) = &fee {
+                    let pv = TransferPublicValues::decode(public_values).context(
+                        "malformed transfer public values"
+                    )?;
+                    quote.check_public_values(&pv, *amount).map_err(anyhow::Error::msg)?;
+                    println!("[transfer] Proof commits to the {} USDT fee note", (*amount as f64) / 1e6);
+                }
+                Ok(())
+            };
+            generate_proof(
+                &client, TRANSFER_ELF, "transfer", &input, &output, format, execute_only, binding, None, levels, &check,
             )?;
-            stdin.write(&inputs);
         }
-        "withdraw" => {
-            let inputs: shielded_pool_lib::WithdrawPrivateInputs = serde_json::from_str(
-                &input_json
+        Commands::Withdraw {
+            input: Some(input),
+            output: Some(output),
+            format,
+            execute_only,
+            chain_id,
+            pool,
+            call_target,
+            calldata,
+            tree_levels,
+            ..
+        } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let levels = resolve_tree_levels(tree_levels)?;
+            let call = resolve_withdraw_call(call_target, calldata)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = WithdrawPublicValues::decode(public_values).context("malformed withdraw public values")?;
+                if pv.call_binding != [0u8; 32] {
+                    println!("[withdraw] Call binding 0x{}: submit with withdrawAndCall", hex::encode(pv.call_binding));
+                }
+                Ok(())
+            };
+            generate_proof(
+                &client, WITHDRAW_ELF, "withdraw", &input, &output, format, execute_only, binding, call, levels, &check,
             )?;
-            stdin.write(&inputs);
         }
-        _ => unreachable!(),
+        Commands::Claim { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = ClaimPublicValues::decode(public_values).context("malformed claim public values")?;
+                println!("[claim] Claim nullifier 0x{}", hex::encode(pv.claim_nullifier));
+                Ok(())
+            };
+            generate_proof(
+                &client, CLAIM_ELF, "claim", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::JoinSplit { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = JoinSplitPublicValues::decode(public_values).context("malformed join-split public values")?;
+                println!("[join-split] Root 0x{}, 4 nullifiers, 4 commitments", hex::encode(pv.root));
+                Ok(())
+            };
+            generate_proof(
+                &client, JOIN_SPLIT_ELF, "join-split", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::Deposit { input: Some(input), output: Some(output), execute_only, .. } => {
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = DepositPublicValues::decode(public_values).context("malformed deposit public values")?;
+                println!("[deposit] Commitment 0x{} opens to {} USDT", hex::encode(pv.commitment), (pv.amount as f64) / 1e6);
+                Ok(())
+            };
+            generate_proof(
+                &client, DEPOSIT_ELF, "deposit", &input, &output, OutputFormat::Json,
+                execute_only, None, None, None, &check,
+            )?;
+        }
+        Commands::BatchWithdraw { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = BatchWithdrawPublicValues::decode(public_values).context(
+                    "malformed batch-withdraw public values"
+                )?;
+                let spent = pv.nullifiers.iter().filter(|n| **n != [0u8; 32]).count();
+                println!("[batch-withdraw] {} notes, {} USDT to 0x{}", spent, (pv.amount as f64) / 1e6, hex::encode(pv.recipient));
+                Ok(())
+            };
+            generate_proof(
+                &client, BATCH_WITHDRAW_ELF, "batch-withdraw", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::Consolidate { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = ConsolidatePublicValues::decode(public_values).context("malformed consolidation public values")?;
+                let merged = pv.nullifiers.iter().filter(|n| **n != [0u8; 32]).count();
+                println!("[consolidate] {} notes into 0x{}", merged, hex::encode(pv.out_commitment));
+                Ok(())
+            };
+            generate_proof(
+                &client, CONSOLIDATE_ELF, "consolidate", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::Attest { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = AttestPublicValues::decode(public_values).context("malformed attest public values")?;
+                println!(
+                    "[attest] at least {} USDT unspent at root 0x{}, nonce 0x{}",
+                    (pv.threshold as f64) / 1e6,
+                    hex::encode(pv.root),
+                    hex::encode(pv.nonce)
+                );
+                Ok(())
+            };
+            generate_proof(
+                &client, ATTEST_ELF, "attest", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::Disclose { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = print_disclosure;
+            generate_proof(
+                &client, DISCLOSE_ELF, "disclose", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::PolicySpend { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = PolicySpendPublicValues::decode(public_values)
+                    .context("malformed policy-spend public values")?;
+                println!(
+                    "[policy-spend] epoch {}: nullifier 0x{}, counter 0x{} -> 0x{}",
+                    pv.epoch,
+                    hex::encode(pv.nullifier),
+                    hex::encode(pv.counter_nullifier),
+                    hex::encode(pv.new_counter)
+                );
+                Ok(())
+            };
+            let elf = POLICY_SPEND_ELF;
+            generate_proof(
+                &client, elf, "policy-spend", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::Payout { input, output, execute_only, chain_id, pool } => {
+            let binding = resolve_pool_binding(chain_id, pool)?;
+            let check = |public_values: &[u8]| -> Result<()> {
+                let pv = PayoutPublicValues::decode(public_values).context("malformed payout public values")?;
+                println!("[payout] {} outputs at root 0x{}", pv.out_commitments.len(), hex::encode(pv.root));
+                for (i, commitment) in pv.out_commitments.iter().enumerate() {
+                    println!("  output {}: 0x{}", i, hex::encode(commitment));
+                }
+                Ok(())
+            };
+            generate_proof(
+                &client, PAYOUT_ELF, "payout", &input, &output, OutputFormat::Json,
+                execute_only, binding, None, None, &check,
+            )?;
+        }
+        Commands::Vkeys => {
+            let (_, transfer_vk) = key_cache::setup(&client, TRANSFER_ELF);
+            let (_, withdraw_vk) = key_cache::setup(&client, WITHDRAW_ELF);
+            let (_, claim_vk) = key_cache::setup(&client, CLAIM_ELF);
+            let (_, join_split_vk) = key_cache::setup(&client, JOIN_SPLIT_ELF);
+            let (_, deposit_vk) = key_cache::setup(&client, DEPOSIT_ELF);
+            let (_, batch_withdraw_vk) = key_cache::setup(&client, BATCH_WITHDRAW_ELF);
+            let (_, consolidate_vk) = key_cache::setup(&client, CONSOLIDATE_ELF);
+            let (_, attest_vk) = key_cache::setup(&client, ATTEST_ELF);
+            let (_, disclose_vk) = key_cache::setup(&client, DISCLOSE_ELF);
+            let (_, policy_spend_vk) = key_cache::setup(&client, POLICY_SPEND_ELF);
+            let (_, payout_vk) = key_cache::setup(&client, PAYOUT_ELF);
+            println!("TRANSFER_VKEY:   {}", transfer_vk.bytes32());
+            println!("WITHDRAW_VKEY:   {}", withdraw_vk.bytes32());
+            println!("CLAIM_VKEY:      {}", claim_vk.bytes32());
+            println!("JOIN_SPLIT_VKEY: {}", join_split_vk.bytes32());
+            println!("DEPOSIT_VKEY:    {}", deposit_vk.bytes32());
+            println!("BATCH_WITHDRAW_VKEY: {}", batch_withdraw_vk.bytes32());
+            println!("CONSOLIDATE_VKEY: {}", consolidate_vk.bytes32());
+            println!("ATTEST_VKEY:     {}", attest_vk.bytes32());
+            println!("DISCLOSE_VKEY:   {}", disclose_vk.bytes32());
+            println!("POLICY_SPEND_VKEY: {}", policy_spend_vk.bytes32());
+            println!("PAYOUT_VKEY:     {}", payout_vk.bytes32());
+        }
+        Commands::Reproduce { circuit, registry, pool, rpc_url, update_registry } => {
+            let pool = pool.or_else(|| std::env::var("POOL_ADDRESS").ok());
+            let rpc_url = rpc_url.or_else(|| std::env::var("RPC_URL").ok());
+            let registry = registry.unwrap_or_else(VkeyRegistry::default_path);
+            run_reproduce(&client, &circuit, &registry, pool, rpc_url, update_registry)?;
+        }
+        Commands::Verify { proof, circuit } => verify_proof_output(&client, &proof, circuit.as_deref())?,
+        Commands::Execute { circuit, input, report, expected_cycles } => {
+            execute_with_report(&client, &circuit, &input, report.as_deref(), expected_cycles)?;
+        }
+        Commands::Note { .. }
+        | Commands::Balance { .. }
+        | Commands::Withdraw { .. }
+        | Commands::Send { .. }
+        | Commands::Scan { .. }
+        | Commands::Keygen { .. }
+        | Commands::Wallet { .. }
+        | Commands::Audit { .. }
+        | Commands::Message { .. }
+        | Commands::Walletd { .. }
+        | Commands::RequestHash { .. }
+        | Commands::PrepareClaim { .. }
+        | Commands::ProveAbsence { .. }
+        | Commands::VerifyAbsence { .. }
+        | Commands::PrepareEmergencyKit { .. }
+        | Commands::VerifyEmergencyKit { .. }
+        | Commands::ExecuteEmergencyKit { .. }
+        | Commands::Deposit { .. } => unreachable!(),
     }
 
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_proof(
+    client: &Client,
+    elf: &[u8],
+    name: &str,
+    input_path: &str,
+    output_path: &str,
+    format: OutputFormat,
+    execute_only: bool,
+    pool_binding: Option<[u8; 32]>,
+    withdraw_call: Option<WithdrawCall>,
+    tree_levels: Option<u64>,
+    check_public_values: &dyn Fn(&[u8]) -> Result<()>
+) -> Result<()> {
+    // 1. Read inputs from JSON file
+    let input_json = fs::read_to_string(input_path)?;
+
+    // 2. Prepare SP1 stdin — deserialize the appropriate type and write it
+    //    framed as InputHeader, inputs, INPUT_END_MARKER (see shielded_pool_lib)
+    let mut pool_call = None;
+    let mut effects = None;
+    let (stdin, request_hash) = match name {
+        "transfer" => {
+            let mut inputs: shielded_pool_lib::TransferPrivateInputs = serde_json::from_str(
+                &input_json
+            )?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            // Reject what the guest would reject (e.g. one note in both slots) before proving
+            let pv = simulate_transfer(&inputs)
+                .map_err(|rejection| anyhow::anyhow!("invalid transfer inputs: {rejection}"))?;
+            check_tree_levels(name, pv.tree_levels, tree_levels)?;
+            // Proven payloads must be posted as proven; without them the outputs go out unannounced
+            let encrypted_outputs = match &inputs.output_encryption {
+                Some([first, second]) => {
+                    [first.encrypt(&inputs.output_notes[0]), second.encrypt(&inputs.output_notes[1])]
+                }
+                None => [Vec::new(), Vec::new()],
+            };
+            pool_call = Some(PoolCall::Transfer { encrypted_outputs });
+            effects = Some(ExpectedEffects {
+                root: pv.root,
+                nullifiers: pv.nullifiers.to_vec(),
+                commitments: pv.out_commitments.to_vec(),
+            });
+            (framed_stdin(CircuitKind::Transfer, &inputs), inputs.request_hash())
+        }
+        "withdraw" => {
+            let mut inputs: shielded_pool_lib::WithdrawPrivateInputs = serde_json::from_str(
+                &input_json
+            )?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            bind_to_call(&mut inputs, withdraw_call.as_ref())?;
+            // Likewise, in milliseconds rather than after executing the guest
+            let pv = simulate_withdraw(&inputs)
+                .map_err(|rejection| anyhow::anyhow!("invalid withdraw inputs: {rejection}"))?;
+            check_tree_levels(name, pv.tree_levels, tree_levels)?;
+            // A call the inputs commit by hash alone cannot be encoded
+            pool_call = match withdraw_call {
+                Some(call) => Some(PoolCall::WithdrawAndCall(call)),
+                None if inputs.call_target == [0u8; 20] => Some(PoolCall::Withdraw),
+                None => None,
+            };
+            effects = Some(ExpectedEffects {
+                root: pv.root,
+                nullifiers: vec![pv.nullifier],
+                commitments: [pv.change_commitment].into_iter().filter(|c| *c != [0u8; 32]).collect(),
+            });
+            (framed_stdin(CircuitKind::Withdraw, &inputs), inputs.request_hash())
+        }
+        "claim" => {
+            let mut inputs: ClaimPrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            (framed_stdin(CircuitKind::Claim, &inputs), inputs.request_hash())
+        }
+        "join-split" => {
+            let mut inputs: JoinSplitPrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            pool_call = Some(PoolCall::JoinSplit);
+            (framed_stdin(CircuitKind::JoinSplit, &inputs), inputs.request_hash())
+        }
+        "deposit" => {
+            let inputs: DepositPrivateInputs = serde_json::from_str(&input_json)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid deposit inputs: {msg}"))?;
+            (framed_stdin(CircuitKind::Deposit, &inputs), inputs.request_hash())
+        }
+        "batch-withdraw" => {
+            let mut inputs: BatchWithdrawPrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid batch-withdraw inputs: {msg}"))?;
+            pool_call = Some(PoolCall::BatchWithdraw);
+            (framed_stdin(CircuitKind::BatchWithdraw, &inputs), inputs.request_hash())
+        }
+        "consolidate" => {
+            let mut inputs: ConsolidatePrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid consolidation inputs: {msg}"))?;
+            pool_call = Some(PoolCall::Consolidate);
+            (framed_stdin(CircuitKind::Consolidate, &inputs), inputs.request_hash())
+        }
+        "attest" => {
+            let mut inputs: AttestPrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid attest inputs: {msg}"))?;
+            (framed_stdin(CircuitKind::Attest, &inputs), inputs.request_hash())
+        }
+        "disclose" => {
+            let mut inputs: DisclosurePrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid disclose inputs: {msg}"))?;
+            (framed_stdin(CircuitKind::Disclose, &inputs), inputs.request_hash())
+        }
+        "policy-spend" => {
+            let mut inputs: PolicySpendPrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid policy-spend inputs: {msg}"))?;
+            pool_call = Some(PoolCall::PolicySpend);
+            (framed_stdin(CircuitKind::PolicySpend, &inputs), inputs.request_hash())
+        }
+        "payout" => {
+            let mut inputs: PayoutPrivateInputs = serde_json::from_str(&input_json)?;
+            bind_to_pool(name, &mut inputs.pool_binding, pool_binding)?;
+            inputs.check().map_err(|msg| anyhow::anyhow!("invalid payout inputs: {msg}"))?;
+            pool_call = Some(PoolCall::Payout);
+            (framed_stdin(CircuitKind::Payout, &inputs), inputs.request_hash())
+        }
+        _ => unreachable!(),
+    };
+    println!("[{}] Request 0x{}", name, hex::encode(request_hash));
+    ensure!(
+        format != OutputFormat::Calldata || pool_call.is_some(),
+        "[{}] no calldata to write: the inputs commit a call; give its --call-target and --calldata",
+        name
+    );
+
     if execute_only {
-        // Execute without proof — fast sanity check
-        let (public_values, report) = client.execute(elf, &stdin).run()?;
+        // Execute without proof — fast sanity check.
+        // A guest that rejects its input halts with an abort code; explain it.
+        let (public_values, report) = client
+            .execute(elf, &stdin)
+            .run()
+            .map_err(|e| anyhow::anyhow!("[{}] guest rejected input: {:#}", name, explain_rejection(e)))?;
         println!("[{}] Execution successful. Cycles: {}", name, report.total_instruction_count());
         println!("[{}] Public values size: {} bytes", name, public_values.as_slice().len());
-        return Ok(());
+        return check_public_values(public_values.as_slice());
     }
 
     // 3. Setup proving/verification keys
-    let (pk, vk) = client.setup(elf);
+    let (pk, vk) = key_cache::setup(client, elf);
 
     // 4. Generate Groth16 proof for on-chain verification
     println!("[{}] Generating Groth16 proof...", name);
-    let proof = client.prove(&pk, &stdin).groth16().run()?;
+    let proof = proof_cache::prove(client, &pk, elf, &stdin)?;
 
     // 5. Verify locally
     // client.verify(&proof, &vk)?;
@@ -148,14 +1433,2048 @@ fn generate_proof(
         public_values.len()
     );
 
-    // 7. Write output as JSON
-    let output = ProofOutput {
-        proof: hex::encode(&proof_bytes),
-        public_values: hex::encode(&public_values),
-        vkey: vk.bytes32(),
+    check_public_values(&public_values)?;
+
+    // 7. Write output as JSON, just the calldata to submit it with, or a Foundry fixture
+    let calldata = pool_call.map(|call| format!("0x{}", hex::encode(call.calldata(&proof_bytes, &public_values))));
+    let hex0x = |bytes: &[u8]| format!("0x{}", hex::encode(bytes));
+    match (format, calldata) {
+        (OutputFormat::Calldata, Some(calldata)) => {
+            fs::write(output_path, format!("{calldata}\n"))?;
+            println!("[{}] Calldata ({} bytes) written to {}", name, (calldata.len() - 2) / 2, output_path);
+        }
+        (OutputFormat::Foundry, _) => {
+            let effects = effects.with_context(|| format!("[{}] no Foundry fixture for this circuit", name))?;
+            let fixture = FoundryFixture {
+                circuit: name.to_string(),
+                commitments: effects.commitments.iter().map(|c| hex0x(c)).collect(),
+                nullifiers: effects.nullifiers.iter().map(|n| hex0x(n)).collect(),
+                proof: hex0x(&proof_bytes),
+                public_values: hex0x(&public_values),
+                root: hex0x(&effects.root),
+                vkey: vk.bytes32(),
+            };
+            fs::write(output_path, serde_json::to_string_pretty(&fixture)?)?;
+            println!("[{}] Foundry fixture written to {}", name, output_path);
+        }
+        (_, calldata) => {
+            let output = ProofOutput {
+                proof: hex::encode(&proof_bytes),
+                public_values: hex::encode(&public_values),
+                vkey: vk.bytes32(),
+                request_hash: hex::encode(request_hash),
+                calldata,
+            };
+            fs::write(output_path, serde_json::to_string_pretty(&output)?)?;
+            println!("[{}] Proof written to {}", name, output_path);
+        }
+    }
+
+    Ok(())
+}
+
+/// The pool binding for `--chain-id`/`--pool` (default: CHAIN_ID and
+/// POOL_ADDRESS), or None when either is unset.
+fn resolve_pool_binding(chain_id: Option<u64>, pool: Option<String>) -> Result<Option<[u8; 32]>> {
+    let chain_id = match chain_id {
+        Some(id) => Some(id),
+        None => std::env::var("CHAIN_ID").ok().map(|s| s.parse::<u64>()).transpose().context("CHAIN_ID must be a number")?,
+    };
+    let pool = pool.or_else(|| std::env::var("POOL_ADDRESS").ok());
+    match (chain_id, pool) {
+        (Some(chain_id), Some(pool)) => {
+            let pool = decode_hex_fixed::<20>(&pool).context("--pool must be an address")?;
+            Ok(Some(shielded_pool_lib::pool_binding(chain_id, &pool)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// The depth of the pool's tree, from --tree-levels or TREE_LEVELS; `None`
+/// if neither is set.
+fn resolve_tree_levels(tree_levels: Option<u64>) -> Result<Option<u64>> {
+    match tree_levels {
+        Some(levels) => Ok(Some(levels)),
+        None => std::env::var("TREE_LEVELS")
+            .ok()
+            .map(|s| s.parse::<u64>())
+            .transpose()
+            .context("TREE_LEVELS must be a number"),
+    }
+}
+
+/// Refuse Merkle proofs of another depth than the pool's tree: the proof
+/// commits their depth and the pool rejects it with `WrongTreeDepth`.
+fn check_tree_levels(name: &str, proof_levels: u64, tree_levels: Option<u64>) -> Result<()> {
+    match tree_levels {
+        Some(levels) => ensure!(
+            proof_levels == levels,
+            "the inputs' Merkle proofs are {proof_levels} levels deep, the pool's tree {levels} (resync the tree)"
+        ),
+        None => println!("[{}] warning: tree depth unchecked (set --tree-levels or TREE_LEVELS)", name),
+    }
+    Ok(())
+}
+
+/// Set the inputs' pool binding, or check the one they carry. An unbound
+/// proof still executes, but no pool accepts it.
+fn bind_to_pool(name: &str, inputs_binding: &mut [u8; 32], binding: Option<[u8; 32]>) -> Result<()> {
+    match binding {
+        Some(binding) if *inputs_binding == [0u8; 32] => *inputs_binding = binding,
+        Some(binding) => ensure!(*inputs_binding == binding, "the inputs are bound to another pool (0x{})", hex::encode(inputs_binding)),
+        None if *inputs_binding == [0u8; 32] => {
+            println!(
+                "[{}] warning: no pool binding (set --chain-id and --pool); a verifier checking the pool will reject this proof",
+                name
+            );
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// `--call-target`/`--calldata` as the target and calldata hash a
+/// withdraw-and-call commits, or None for a plain withdrawal.
+fn resolve_withdraw_call(target: Option<String>, calldata: Option<String>) -> Result<Option<WithdrawCall>> {
+    match (target, calldata) {
+        (Some(target), Some(calldata)) => {
+            let target = decode_hex_fixed::<20>(&target).context("--call-target must be an address")?;
+            ensure!(target != [0u8; 20], "--call-target must not be the zero address");
+            let data = decode_hex(&calldata).context("--calldata must be hex")?;
+            Ok(Some(WithdrawCall { target, data }))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Set the inputs' withdraw-and-call fields, or check the ones they carry,
+/// and reject a calldata hash without a target before proving.
+fn bind_to_call(inputs: &mut shielded_pool_lib::WithdrawPrivateInputs, call: Option<&WithdrawCall>) -> Result<()> {
+    if let Some(call) = call {
+        let (target, calldata_hash) = (call.target, shielded_pool_lib::keccak256(&call.data));
+        if inputs.call_target == [0u8; 20] && inputs.calldata_hash == [0u8; 32] {
+            inputs.call_target = target;
+            inputs.calldata_hash = calldata_hash;
+        } else {
+            ensure!(
+                inputs.call_target == target && inputs.calldata_hash == calldata_hash,
+                "the inputs commit another call (0x{} with calldata hash 0x{})",
+                hex::encode(inputs.call_target),
+                hex::encode(inputs.calldata_hash)
+            );
+        }
+    }
+    shielded_pool_lib::call_binding(&inputs.call_target, &inputs.calldata_hash)
+        .map_err(|msg| anyhow::anyhow!("invalid withdraw inputs: {msg}"))?;
+    Ok(())
+}
+
+/// Print the request hash of a proof request's input file.
+fn print_request_hash(circuit: &str, input_path: &str) -> Result<()> {
+    let (_, hash) = load_request(circuit, input_path)?;
+    println!("{}", hex::encode(hash));
+    Ok(())
+}
+
+/// Read a `circuit`'s private inputs as they are (no pool or call binding)
+/// and frame them for the guest. Returns the stdin and the request hash.
+fn load_request(circuit: &str, input_path: &str) -> Result<(SP1Stdin, [u8; 32])> {
+    fn framed<T: serde::de::DeserializeOwned + serde::Serialize>(
+        kind: CircuitKind,
+        input_json: &str,
+        request_hash: fn(&T) -> [u8; 32]
+    ) -> Result<(SP1Stdin, [u8; 32])> {
+        let inputs: T = serde_json::from_str(input_json)?;
+        Ok((framed_stdin(kind, &inputs), request_hash(&inputs)))
+    }
+    let input_json = fs::read_to_string(input_path).with_context(|| format!("Failed to read {}", input_path))?;
+    match circuit {
+        "transfer" => framed(CircuitKind::Transfer, &input_json, TransferPrivateInputs::request_hash),
+        "withdraw" => {
+            framed(CircuitKind::Withdraw, &input_json, shielded_pool_lib::WithdrawPrivateInputs::request_hash)
+        }
+        "claim" => framed(CircuitKind::Claim, &input_json, ClaimPrivateInputs::request_hash),
+        "join-split" => framed(CircuitKind::JoinSplit, &input_json, JoinSplitPrivateInputs::request_hash),
+        "deposit" => framed(CircuitKind::Deposit, &input_json, DepositPrivateInputs::request_hash),
+        "batch-withdraw" => framed(CircuitKind::BatchWithdraw, &input_json, BatchWithdrawPrivateInputs::request_hash),
+        "consolidate" => framed(CircuitKind::Consolidate, &input_json, ConsolidatePrivateInputs::request_hash),
+        "attest" => framed(CircuitKind::Attest, &input_json, AttestPrivateInputs::request_hash),
+        "disclose" => framed(CircuitKind::Disclose, &input_json, DisclosurePrivateInputs::request_hash),
+        "policy-spend" => framed(CircuitKind::PolicySpend, &input_json, PolicySpendPrivateInputs::request_hash),
+        "payout" => framed(CircuitKind::Payout, &input_json, PayoutPrivateInputs::request_hash),
+        _ => unreachable!(),
+    }
+}
+
+/// Execute `circuit`'s embedded guest on the inputs at `input_path`, print
+/// its cycle report and optionally save it; with `expected_cycles`, fail when
+/// the run is over budget (after saving, so CI keeps the report).
+fn execute_with_report(
+    client: &Client,
+    circuit: &str,
+    input_path: &str,
+    report_path: Option<&Path>,
+    expected_cycles: Option<u64>
+) -> Result<()> {
+    let (stdin, request_hash) = load_request(circuit, input_path)?;
+    println!("[{}] Request 0x{}", circuit, hex::encode(request_hash));
+    let (public_values, report) = client
+        .execute(embedded_elf(circuit), &stdin)
+        .run()
+        .map_err(|e| anyhow::anyhow!("[{}] guest rejected input: {:#}", circuit, explain_rejection(e)))?;
+    println!("[{}] Execution successful. Public values size: {} bytes", circuit, public_values.as_slice().len());
+
+    let mut report = CycleReport::new(circuit, request_hash, &report);
+    report.print();
+    let within_budget = match expected_cycles {
+        Some(expected) => report.check_budget(expected),
+        None => Ok(()),
+    };
+    if let Some(path) = report_path {
+        report.save(path)?;
+        println!("[{}] Cycle report written to {}", circuit, path.display());
+    }
+    within_budget
+}
+
+/// Load a fee quote and check the transfer inputs pay it. Returns the quote
+/// and the fee the transfer pays.
+fn check_fee_quote(quote_path: &Path, input_path: &str) -> Result<(FeeQuote, u64)> {
+    let quote: FeeQuote = serde_json::from_str(&fs::read_to_string(quote_path)?)?;
+    let inputs: TransferPrivateInputs = serde_json::from_str(&fs::read_to_string(input_path)?)?;
+    let amount = quote.check_transfer(&inputs, unix_now()).map_err(anyhow::Error::msg)?;
+    println!(
+        "[transfer] Fee quote 0x{}: {} USDT to the prover (quoted {})",
+        hex::encode(&quote.quote_id[..8]),
+        (amount as f64) / 1e6,
+        (quote.fee as f64) / 1e6
+    );
+    Ok((quote, amount))
+}
+
+/// The snapshot a claim is against, as given on the command line.
+struct ClaimSnapshot {
+    block: Option<u64>,
+    root: Option<String>,
+    nullifier_root: Option<String>,
+}
+
+/// Rebuild the pool as of the snapshot, check the note was held then, and
+/// write the claim inputs.
+async fn prepare_claim(
+    note: &str,
+    snapshot: ClaimSnapshot,
+    airdrop: &str,
+    recipient: &str,
+    wallet: Option<PathBuf>,
+    out: &Path
+) -> Result<()> {
+    let state = WalletState::load(&wallet.unwrap_or_else(wallet_path))?;
+    let wn = &state.notes[state.find_note(note)?];
+    ensure!(wn.lock.is_none(), "note '{}' is cancellable; claim it into a plain note to take part in snapshots", wn.label);
+    let keys = state.unlock_spending_keys()?;
+    let (key, _) = wn
+        .authorize(&keys)?
+        .with_context(|| format!("no spending key in this wallet owns note '{}'", wn.label))?;
+    let airdrop_id = decode_hex_fixed::<32>(airdrop).context("--airdrop must be 32 bytes of hex")?;
+    let recipient = decode_hex_fixed::<20>(recipient).context("--recipient must be an address")?;
+    let root = snapshot.root.as_deref().map(decode_hex_fixed::<32>).transpose().context("--root must be 32 bytes of hex")?;
+    let expected_nullifier_root = snapshot.nullifier_root
+        .as_deref()
+        .map(decode_hex_fixed::<32>)
+        .transpose()
+        .context("--nullifier-root must be 32 bytes of hex")?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let source = TreeSource::from_env(chain_id, pool)?;
+    let block = match (snapshot.block, root) {
+        (Some(block), _) => block,
+        (None, Some(root)) => {
+            let head = provider.get_block_number().await?;
+            find_root_block(&provider, pool, &source, root, source.finality.final_block(head)).await?
+        }
+        (None, None) => bail!("give the snapshot as --block, --root or both"),
+    };
+    println!("Rebuilding the pool as of block {}...", block);
+    let snap = pool_snapshot(&provider, pool, &source, block).await?;
+    let (tree_root, nullifier_root) = (snap.tree.get_root(), snap.nullifiers.root());
+    if let Some(root) = root {
+        ensure!(tree_root == root, "the pool's root at block {} is 0x{}, not --root", block, hex::encode(tree_root));
+    }
+    if let Some(expected) = expected_nullifier_root {
+        ensure!(
+            nullifier_root == expected,
+            "the nullifier root at block {} is 0x{}, not --nullifier-root",
+            block,
+            hex::encode(nullifier_root)
+        );
+    }
+
+    let held = wn.to_note()?;
+    let commitment = held.commitment();
+    ensure!(
+        snap.tree.leaves.get(wn.leaf_index as usize) == Some(&commitment),
+        "note '{}' (leaf {}) was not in the pool at block {}",
+        wn.label,
+        wn.leaf_index,
+        block
+    );
+    let nullifier = compute_nullifier(&commitment, key.as_bytes());
+    let inputs = ClaimPrivateInputs {
+        note: held,
+        spending_key: *key.as_bytes(),
+        merkle_proof: snap.tree.get_proof(wn.leaf_index),
+        root: tree_root,
+        nullifier_root,
+        nullifier_proof: snap.nullifiers.prove(&nullifier),
+        airdrop_id,
+        recipient,
+        pool_binding: pool_binding(chain_id, &pool.0 .0),
+    };
+    let pv = inputs
+        .check()
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("note '{}' cannot claim at block {}", wn.label, block))?;
+    fs::write(out, serde_json::to_string_pretty(&inputs)?)?;
+
+    println!("Snapshot at block {}: root 0x{}, nullifier root 0x{}", block, hex::encode(tree_root), hex::encode(nullifier_root));
+    println!(
+        "Claim for '{}': {} USDT to 0x{}, claim nullifier 0x{}",
+        wn.label,
+        (pv.amount as f64) / 1e6,
+        hex::encode(recipient),
+        hex::encode(pv.claim_nullifier)
+    );
+    println!("Claim inputs: {} (they contain the spending key; keep them private)", out.display());
+    println!(
+        "Prove with:   cargo run --release -p shielded-pool-script -- claim --input {} --output fixtures/claim-proof.json",
+        out.display()
+    );
+    Ok(())
+}
+
+/// A `deposit --amount` request, as given on the command line.
+struct DepositRequest {
+    amount: String,
+    to: String,
+    viewing_pubkey: Option<String>,
+    label: Option<String>,
+    wallet: Option<PathBuf>,
+}
+
+/// Who `--to` names, and the viewing pubkey to encrypt their note to.
+struct Recipient {
+    pubkey: [u8; 32],
+    /// Label of the wallet key that owns `pubkey`, if one does
+    key_label: Option<String>,
+    viewing_pubkey: Option<PublicKey>,
+}
+
+/// Resolve `--to`: one of the wallet's keys, by label or pubkey, or any
+/// 32-byte hex shielded pubkey. The viewing pubkey is `--viewing-pubkey`,
+/// else the wallet key's.
+fn resolve_recipient(
+    state: &WalletState,
+    to: &str,
+    viewing_pubkey: Option<String>,
+    wallet: &Path
+) -> Result<Recipient> {
+    let to_pubkey = decode_hex_fixed::<32>(to).ok();
+    let key = state
+        .spending_keys
+        .iter()
+        .find(|k| k.label == to || (to_pubkey.is_some() && decode_hex_fixed::<32>(&k.pubkey).ok() == to_pubkey));
+    let pubkey = match (key, to_pubkey) {
+        (Some(key), _) => decode_hex_fixed::<32>(&key.pubkey).with_context(|| format!("key '{}' pubkey", key.label))?,
+        (None, Some(pubkey)) => pubkey,
+        (None, None) => bail!("--to '{}' is neither a key in {} nor 32 bytes of hex", to, wallet.display()),
+    };
+    let viewing_pubkey = match (viewing_pubkey, key) {
+        (Some(hex), _) => Some(hex),
+        (None, Some(key)) if !key.viewing_pubkey.is_empty() => Some(key.viewing_pubkey.clone()),
+        _ => None,
+    };
+    let viewing_pubkey = viewing_pubkey
+        .map(|hex| decode_hex_fixed::<32>(&hex).map(PublicKey::from))
+        .transpose()
+        .context("the viewing pubkey must be 32 bytes of hex")?;
+    Ok(Recipient { pubkey, key_label: key.map(|k| k.label.clone()), viewing_pubkey })
+}
+
+/// Create a note for `--to`, deposit it into the pool (`RPC_URL`,
+/// `PRIVATE_KEY`, `POOL_ADDRESS`) and record it in the wallet.
+async fn deposit_on_chain(request: DepositRequest) -> Result<()> {
+    let amount = parse_usdt(&request.amount).context("--amount")?;
+    ensure!(amount > 0, "--amount must be positive");
+    shielded_pool_lib::check_output_amount(amount).map_err(anyhow::Error::msg)?;
+    let wallet_file = request.wallet.unwrap_or_else(wallet_path);
+    let mut state = WalletState::load(&wallet_file)?;
+    if let Some(label) = &request.label {
+        ensure!(state.notes.iter().all(|n| &n.label != label), "the wallet already has a note labelled '{label}'");
+    }
+
+    let Recipient { pubkey, key_label, viewing_pubkey } =
+        resolve_recipient(&state, &request.to, request.viewing_pubkey, &wallet_file)?;
+
+    println!("Entropy: {}", entropy::init_from_env()?.describe());
+    let note = Note { amount, pubkey, blinding: entropy::rng().gen() };
+    let commitment = note.commitment();
+    let encrypted = match &viewing_pubkey {
+        Some(vpk) => encrypt_note(&note, vpk),
+        None => {
+            eprintln!("warning: no viewing pubkey for the recipient; the note is not posted encrypted");
+            Vec::new()
+        }
+    };
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let signer: PrivateKeySigner = std::env::var("PRIVATE_KEY").context("PRIVATE_KEY not set")?.parse()?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().wallet(signer).connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let finality = FinalityPolicy::for_chain(chain_id)?;
+    provider.client().set_poll_interval(finality.poll_interval());
+    let pool = IShieldedPool::new(pool_addr, &provider);
+    let token = IERC20::new(pool.TOKEN().call().await?, &provider);
+
+    // Pools with a deposit vkey only take deposits that prove the amount
+    let vkey = pool.DEPOSIT_VKEY().call().await?;
+    let proof = if vkey != FixedBytes::ZERO {
+        println!("The pool requires deposit proofs; proving...");
+        let client = ProverClient::from_env();
+        let (pk, vk) = key_cache::setup(&client, DEPOSIT_ELF);
+        ensure!(
+            vk.bytes32() == vkey.to_string(),
+            "the pool's DEPOSIT_VKEY {} is not this build's {}",
+            vkey,
+            vk.bytes32()
+        );
+        let stdin = framed_stdin(CircuitKind::Deposit, &DepositPrivateInputs { note: note.clone() });
+        let proof = proof_cache::prove(&client, &pk, DEPOSIT_ELF, &stdin)?;
+        Some((proof.bytes(), proof.public_values.to_vec()))
+    } else {
+        None
+    };
+
+    println!("Approving {} USDT...", (amount as f64) / 1e6);
+    let tx = token.approve(pool_addr, U256::from(amount)).send().await?;
+    let receipt = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
+    ensure!(receipt.status(), "approve tx {} reverted", receipt.transaction_hash);
+
+    println!("Depositing...");
+    let tx = match proof {
+        Some((proof, public_values)) => {
+            pool.depositWithProof(proof.into(), public_values.into(), encrypted.into()).send().await?
+        }
+        None => pool.deposit(FixedBytes::from(commitment), U256::from(amount), encrypted.into()).send().await?,
+    };
+    let receipt = tx.with_required_confirmations(finality.required_confirmations()).get_receipt().await?;
+    ensure!(receipt.status(), "deposit tx {} reverted", receipt.transaction_hash);
+    let event = receipt
+        .decoded_log::<IShieldedPool::Deposit>()
+        .context("the deposit receipt has no Deposit event")?;
+    ensure!(event.commitment.0 == commitment, "the Deposit event is for another commitment");
+    let leaf_index = event.leafIndex;
+
+    let wn = WalletNote::new(&request.label.unwrap_or_else(|| format!("deposit_{leaf_index}")), &note, leaf_index);
+    if key_label.is_none() {
+        eprintln!("warning: no key in this wallet owns the note; only the holder of its spending key can spend it");
+    }
+    let block = strict::block_number(receipt.block_number, || {
+        format!("receipt for tx {}", receipt.transaction_hash)
+    })?;
+    let entry = AuditEntry::new(
+        AuditAction::Deposit,
+        "cli",
+        chain_id,
+        &pool_addr.to_string(),
+        &receipt.transaction_hash.to_string(),
+        block
+    )
+        .with_created(AuditNote::from_wallet(&wn))
+        .with_public_amount(amount);
+    state.notes.push(wn.clone());
+    state.record_tx(&entry);
+    state.save(&wallet_file)?;
+    AuditLog::record(entry)?;
+
+    println!("Deposited {} USDT as note '{}' in {}", (amount as f64) / 1e6, wn.label, wallet_file.display());
+    println!("Commitment: 0x{}", hex::encode(commitment));
+    println!("Leaf index: {}", leaf_index);
+    println!("Tx:         {}", receipt.transaction_hash);
+    Ok(())
+}
+
+/// Sync the pool's tree and nullifiers, ask the pool which wallet notes are
+/// spent, and print every note's status and the unspent total.
+async fn print_balance(wallet: Option<PathBuf>) -> Result<()> {
+    let balance = wallet_balance(wallet).await?;
+    println!("\nAs of block {}:", balance.block);
+    for note in &balance.notes {
+        let amount = (note.amount as f64) / 1e6;
+        println!("    {:<24} {:>14} USDT  leaf {:<6} {}", note.label, amount, note.leaf_index, note.status);
+    }
+    println!(
+        "\nUnspent: {} USDT in {} of {} notes",
+        (balance.unspent as f64) / 1e6,
+        balance.unspent_notes,
+        balance.notes.len()
+    );
+    Ok(())
+}
+
+/// Sync the pool and work out each wallet note's status, marking the notes
+/// the pool has seen spent as spent in the wallet.
+async fn wallet_balance(wallet: Option<PathBuf>) -> Result<walletd::Balance> {
+    let wallet_file = wallet.unwrap_or_else(wallet_path);
+    let mut state = WalletState::load(&wallet_file)?;
+    println!("Wallet: {} ({} notes)", wallet_file.display(), state.notes.len());
+    let keys = state.unlock_spending_keys()?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
+    let source = TreeSource::from_env(chain_id, pool_addr)?;
+    println!("Syncing the pool...");
+    let PoolSync { tree, status, block, .. } = sync_pool_tree(&provider, &views, pool_addr, chain_id, &source).await?;
+    let spent_events = replay_nullifiers(&provider, pool_addr, source.deploy_block, block).await?;
+
+    // Notes we hold a key for have a nullifier to ask the pool about
+    let mut owned = Vec::with_capacity(state.notes.len());
+    for wn in &state.notes {
+        owned.push(wn.authorize(&keys)?.map(|(_, auth)| auth.nullifier));
+    }
+    let nullifiers: Vec<[u8; 32]> = owned.iter().flatten().copied().collect();
+    let mut spent = views.is_spent_batch(&nullifiers).await?.into_iter();
+
+    let (mut total, mut unspent_notes, mut newly_spent) = (0u64, 0usize, Vec::new());
+    let mut notes = Vec::with_capacity(state.notes.len());
+    for (i, (wn, nullifier)) in state.notes.iter().zip(&owned).enumerate() {
+        let note = wn.to_note()?;
+        let in_tree = tree.leaves.get(wn.leaf_index as usize) == Some(&note.commitment());
+        let status = match nullifier {
+            None => "no spending key in this wallet".to_string(),
+            Some(nullifier) => {
+                let is_spent = spent.next().context("isSpent answered fewer notes than asked")?;
+                if is_spent != spent_events.contains(nullifier) {
+                    println!("    ⚠ '{}': isSpent and the pool's events disagree; trusting isSpent", wn.label);
+                }
+                if is_spent {
+                    if !wn.spent {
+                        newly_spent.push(i);
+                    }
+                    "spent".to_string()
+                } else if status.covers(wn.leaf_index) && !in_tree {
+                    format!("not in the pool at leaf {}", wn.leaf_index)
+                } else {
+                    total += note.amount;
+                    unspent_notes += 1;
+                    match status.refusal(wn.leaf_index) {
+                        Some(why) => format!("unspent (not provable yet: {why})"),
+                        None => "unspent".to_string(),
+                    }
+                }
+            }
+        };
+        let (label, amount, leaf_index) = (wn.label.clone(), note.amount, wn.leaf_index);
+        notes.push(walletd::NoteBalance { label, amount, leaf_index, status });
+    }
+    if !newly_spent.is_empty() {
+        for &i in &newly_spent {
+            state.notes[i].spent = true;
+        }
+        state.save(&wallet_file)?;
+        println!("Marked {} note(s) spent in the wallet", newly_spent.len());
+    }
+    Ok(walletd::Balance { block, notes, unspent: total, unspent_notes })
+}
+
+/// A `send` request, as given on the command line.
+struct SendRequest {
+    to: String,
+    amount: String,
+    viewing_pubkey: Option<String>,
+    from: Option<String>,
+    wallet: Option<PathBuf>,
+}
+
+/// A wallet note that can be spent now: unspent, in the verified tree and
+/// owned outright by one wallet key.
+struct Spendable {
+    /// Index into the wallet's notes
+    index: usize,
+    /// Index into the wallet's (unlocked) keys
+    key: usize,
+    /// What the transfer and withdraw circuits open the note with
+    spender: SpenderKey,
+    note: Note,
+}
+
+/// The wallet's notes that can be spent now: plain notes (cancellable ones
+/// are claimed or reclaimed by the exit) that a wallet key owns, the pool has
+/// not seen spent, and that sit in the verified `tree`.
+async fn spendable_notes<P: Provider>(
+    state: &WalletState,
+    keys: &[SpendingKey],
+    views: &PoolViews<P>,
+    tree: &IncrementalMerkleTree,
+    status: &SyncStatus
+) -> Result<Vec<Spendable>> {
+    let mut owned = Vec::new();
+    for (index, wn) in state.notes.iter().enumerate() {
+        if wn.lock.is_some() {
+            continue;
+        }
+        if let Some((key, spender, auth)) = wn.authorize_spender(keys)? {
+            let key = keys.iter().position(|k| *k == key).context("authorize returned a key not in the wallet")?;
+            owned.push((index, key, spender, auth.nullifier));
+        }
+    }
+    let nullifiers: Vec<[u8; 32]> = owned.iter().map(|&(_, _, _, n)| n).collect();
+    let spent = views.is_spent_batch(&nullifiers).await?;
+    let mut spendable = Vec::new();
+    for (&(index, key, spender, _), is_spent) in owned.iter().zip(spent) {
+        let note = state.notes[index].to_note()?;
+        let leaf = state.notes[index].leaf_index;
+        if !is_spent && status.covers(leaf) && tree.leaves.get(leaf as usize) == Some(&note.commitment()) {
+            spendable.push(Spendable { index, key, spender, note });
+        }
+    }
+    Ok(spendable)
+}
+
+/// Pay `--to` from the wallet: select notes of one key, prove a transfer of
+/// the amount with change back to that key, submit it, and record the change
+/// (and the payment, if a wallet key owns it) in the wallet.
+async fn send_transfer(request: SendRequest) -> Result<walletd::Transferred> {
+    let amount = parse_usdt(&request.amount).context("--amount")?;
+    ensure!(amount > 0, "--amount must be positive");
+    let wallet_file = request.wallet.unwrap_or_else(wallet_path);
+    let mut state = WalletState::load(&wallet_file)?;
+    let Recipient { pubkey: recipient_pubkey, key_label, viewing_pubkey } =
+        resolve_recipient(&state, &request.to, request.viewing_pubkey, &wallet_file)?;
+    let recipient_viewing_pubkey = viewing_pubkey
+        .context("no viewing pubkey for the recipient: pass --viewing-pubkey so they can find the payment")?;
+    let keys = state.unlock_spending_keys()?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let signer: PrivateKeySigner = std::env::var("PRIVATE_KEY").context("PRIVATE_KEY not set")?.parse()?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().wallet(signer).connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
+    let source = TreeSource::from_env(chain_id, pool_addr)?;
+    provider.client().set_poll_interval(source.finality.poll_interval());
+    println!("Syncing the pool...");
+    let PoolSync { tree, status, .. } = sync_pool_tree(&provider, &views, pool_addr, chain_id, &source).await?;
+
+    let spendable = spendable_notes(&state, &keys, &views, &tree, &status).await?;
+
+    // Spend from --from, else from the key holding the most
+    let balance = |key: usize| spendable.iter().filter(|s| s.key == key).map(|s| s.note.amount).sum::<u64>();
+    let sender = match &request.from {
+        Some(label) => state
+            .spending_keys
+            .iter()
+            .position(|k| &k.label == label)
+            .with_context(|| format!("no key labelled '{label}' in {}", wallet_file.display()))?,
+        None => (0..keys.len()).max_by_key(|&k| balance(k)).context("the wallet has no keys")?,
+    };
+    let sender_label = state.spending_keys[sender].label.clone();
+    let inputs: Vec<&Spendable> = spendable.iter().filter(|s| s.key == sender).collect();
+    let notes: Vec<Note> = inputs.iter().map(|s| s.note.clone()).collect();
+    println!(
+        "Spending from '{}': {} USDT in {} spendable note(s)",
+        sender_label,
+        (balance(sender) as f64) / 1e6,
+        notes.len()
+    );
+    let plan = plan_transfer_split(&notes, amount, SelectionConfig::default(), &ChangeSplit::default())
+        .map_err(|e| anyhow::anyhow!("coin selection failed: {e}"))?;
+    for line in plan.to_string().lines() {
+        println!("    {line}");
+    }
+    ensure!(
+        plan.is_single_hop(),
+        "paying {} USDT takes {} transfers; consolidate the notes of '{}' first",
+        (amount as f64) / 1e6,
+        plan.hops(),
+        sender_label
+    );
+
+    println!("Entropy: {}", entropy::init_from_env()?.describe());
+    let mut rng = entropy::rng();
+    let sender_key = keys[sender];
+    let (slots, _, change) = plan.payment();
+    let slots = slots.map(|slot| match slot {
+        NoteRef::Wallet(i) => (
+            Some(inputs[i]),
+            inputs[i].note.clone(),
+            inputs[i].spender,
+            tree.get_proof(state.notes[inputs[i].index].leaf_index),
+        ),
+        NoteRef::Dummy => (None, Note::dummy(rng.gen()), SpenderKey::derive(&DUMMY_SPENDING_KEY), Vec::new()),
+        NoteRef::Merged(_) => unreachable!("single-hop plans only reference wallet notes"),
+    });
+    let payment = Note { amount, pubkey: recipient_pubkey, blinding: rng.gen() };
+    let change_note = Note { amount: change, pubkey: sender_key.pubkey(), blinding: rng.gen() };
+    let (_, sender_viewing_pubkey) = derive_viewing_keypair(sender_key.as_bytes());
+    // The proof commits the payloads, so the pool only takes these
+    let encryptions = [output_encryption(&recipient_viewing_pubkey)?, output_encryption(&sender_viewing_pubkey)?];
+    let [(spent_0, note_0, key_0, proof_0), (spent_1, note_1, key_1, proof_1)] = slots;
+    let mut inputs = TransferPrivateInputs {
+        input_notes: [note_0, note_1],
+        spender_keys: [key_0, key_1],
+        merkle_proofs: [proof_0, proof_1],
+        output_notes: [payment.clone(), change_note.clone()],
+        root: tree.get_root(),
+        locks: [None, None],
+        association: None,
+        pool_binding: pool_binding(chain_id, &pool_addr.0 .0),
+        output_encryption: Some(encryptions.clone()),
+        multisig: [None, None],
+        spend_signatures: Vec::new(),
+    };
+    inputs.sign(sender_key.as_bytes()).map_err(|e| anyhow::anyhow!("invalid transfer: {e}"))?;
+    let expected = simulate_transfer(&inputs).map_err(|rejection| anyhow::anyhow!("invalid transfer: {rejection}"))?;
+
+    let pool = IShieldedPool::new(pool_addr, &provider);
+    let vkey = pool.TRANSFER_VKEY().call().await?;
+    println!("Proving the transfer (this may take a few minutes)...");
+    let client = ProverClient::from_env();
+    let (pk, vk) = key_cache::setup(&client, TRANSFER_ELF);
+    ensure!(vk.bytes32() == vkey.to_string(), "the pool's TRANSFER_VKEY {} is not this build's {}", vkey, vk.bytes32());
+    let proof = proof_cache::prove(&client, &pk, TRANSFER_ELF, &framed_stdin(CircuitKind::Transfer, &inputs))?;
+    let (proof_bytes, public_values) = (proof.bytes(), proof.public_values.to_vec());
+    ensure!(
+        TransferPublicValues::decode(&public_values).as_ref() == Some(&expected),
+        "the proof commits other public values than the inputs imply"
+    );
+
+    println!("Submitting...");
+    let tx = pool
+        .privateTransfer(
+            proof_bytes.clone().into(),
+            public_values.clone().into(),
+            encryptions[0].encrypt(&payment).into(),
+            encryptions[1].encrypt(&change_note).into()
+        )
+        .send().await?;
+    let receipt = tx.with_required_confirmations(source.finality.required_confirmations()).get_receipt().await?;
+    ensure!(receipt.status(), "transfer tx {} reverted", receipt.transaction_hash);
+    let block = strict::block_number(receipt.block_number, || {
+        format!("receipt for tx {}", receipt.transaction_hash)
+    })?;
+
+    // Other transfers may share the block, so look our outputs up by commitment
+    let outputs = scan_outputs(&provider, pool_addr, block, block).await?;
+    let leaf_of = |note: &Note| {
+        let commitment = note.commitment();
+        outputs
+            .iter()
+            .find(|o| o.commitment == commitment)
+            .map(|o| o.leaf_index)
+            .with_context(|| format!("output 0x{} not found in block {}", hex::encode(commitment), block))
     };
-    fs::write(output_path, serde_json::to_string_pretty(&output)?)?;
-    println!("[{}] Proof written to {}", name, output_path);
+    let (payment_leaf, change_leaf) = (leaf_of(&payment)?, leaf_of(&change_note)?);
 
+    let spent_notes: Vec<AuditNote> = [spent_0, spent_1]
+        .into_iter()
+        .flatten()
+        .map(|s| AuditNote::from_wallet(&state.notes[s.index]))
+        .collect();
+    let payment_label = format!("received_{payment_leaf}");
+    if key_label.is_some() {
+        state.notes.push(WalletNote::new(&payment_label, &payment, payment_leaf));
+    }
+    if change > 0 {
+        let change_notes = [(change_note.clone(), change_leaf)];
+        state.push_change(&format!("change_{change_leaf}"), &payment.commitment(), &change_notes)?;
+    }
+    let mut entry = AuditEntry::new(
+        AuditAction::Transfer,
+        "cli",
+        chain_id,
+        &pool_addr.to_string(),
+        &receipt.transaction_hash.to_string(),
+        block
+    );
+    for note in spent_notes {
+        entry = entry.with_spent(note);
+    }
+    let entry = entry
+        .with_created(AuditNote::new(&payment_label, &payment, Some(payment_leaf)))
+        .with_created(AuditNote::new(&format!("change_{change_leaf}"), &change_note, Some(change_leaf)))
+        .with_proof(&proof_bytes, &public_values)
+        .with_request(inputs.request_hash());
+    state.record_tx(&entry);
+    state.save(&wallet_file)?;
+    AuditLog::record(entry)?;
+
+    println!("Sent {} USDT to 0x{} (leaf {})", (amount as f64) / 1e6, hex::encode(recipient_pubkey), payment_leaf);
+    println!("Change: {} USDT (leaf {})", (change as f64) / 1e6, change_leaf);
+    println!("Tx:     {}", receipt.transaction_hash);
+    Ok(walletd::Transferred {
+        tx_hash: receipt.transaction_hash.to_string(),
+        amount,
+        payment_leaf,
+        change,
+        change_leaf,
+    })
+}
+
+/// A `withdraw --submit` request, as given on the command line.
+struct WithdrawRequest {
+    amount: Option<String>,
+    note: Option<String>,
+    to: Option<String>,
+    wallet: Option<PathBuf>,
+    tree_levels: Option<u64>,
+}
+
+/// Withdraw a wallet note to a public address: prove the withdrawal of the
+/// amount with the rest as change to the note's owner, submit it, and record
+/// the change in the wallet.
+async fn withdraw_on_chain(request: WithdrawRequest) -> Result<walletd::Withdrawn> {
+    let amount = request.amount.as_deref().map(parse_usdt).transpose().context("--amount")?;
+    ensure!(amount != Some(0), "--amount must be positive");
+    let wallet_file = request.wallet.unwrap_or_else(wallet_path);
+    let mut state = WalletState::load(&wallet_file)?;
+    let selected = request.note.as_deref().map(|n| state.find_note(n)).transpose()?;
+    let keys = state.unlock_spending_keys()?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let signer: PrivateKeySigner = std::env::var("PRIVATE_KEY").context("PRIVATE_KEY not set")?.parse()?;
+    let recipient: Address = match &request.to {
+        Some(to) => to.parse().context("--to must be an address")?,
+        None => signer.address(),
+    };
+    ensure!(recipient != Address::ZERO, "--to must not be the zero address");
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().wallet(signer).connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
+    let source = TreeSource::from_env(chain_id, pool_addr)?;
+    provider.client().set_poll_interval(source.finality.poll_interval());
+    println!("Syncing the pool...");
+    let PoolSync { tree, status, .. } = sync_pool_tree(&provider, &views, pool_addr, chain_id, &source).await?;
+    let spendable = spendable_notes(&state, &keys, &views, &tree, &status).await?;
+
+    // The named note, else the smallest that covers the amount
+    let input = match selected {
+        Some(index) => spendable.iter().find(|s| s.index == index).with_context(|| {
+            let label = &state.notes[index].label;
+            format!("note '{label}' cannot be spent now (spent, not yet synced, or not owned outright)")
+        })?,
+        None => {
+            let amount = amount.context("give --note or --amount")?;
+            spendable
+                .iter()
+                .filter(|s| s.note.amount >= amount)
+                .min_by_key(|s| s.note.amount)
+                .with_context(|| format!("no single spendable note holds {} USDT", (amount as f64) / 1e6))?
+        }
+    };
+    let input_label = state.notes[input.index].label.clone();
+    let amount = amount.unwrap_or(input.note.amount);
+    ensure!(
+        amount <= input.note.amount,
+        "note '{}' holds {} USDT, less than {} USDT",
+        input_label,
+        (input.note.amount as f64) / 1e6,
+        (amount as f64) / 1e6
+    );
+    let change = input.note.amount - amount;
+    println!(
+        "Withdrawing {} USDT of note '{}' to {} ({} USDT change)",
+        (amount as f64) / 1e6,
+        input_label,
+        recipient,
+        (change as f64) / 1e6
+    );
+
+    println!("Entropy: {}", entropy::init_from_env()?.describe());
+    let key = keys[input.key];
+    let change_note =
+        (change > 0).then(|| Note { amount: change, pubkey: key.pubkey(), blinding: entropy::rng().gen() });
+    let leaf_index = state.notes[input.index].leaf_index;
+    let mut inputs = shielded_pool_lib::WithdrawPrivateInputs {
+        input_note: input.note.clone(),
+        spender_key: input.spender,
+        merkle_proof: tree.get_proof(leaf_index),
+        root: tree.get_root(),
+        recipient: recipient.0 .0,
+        withdraw_amount: amount,
+        change_note: change_note.clone(),
+        lock: None,
+        fee: 0,
+        relayer: [0u8; 20],
+        association: None,
+        pool_binding: pool_binding(chain_id, &pool_addr.0 .0),
+        deadline: 0,
+        call_target: [0u8; 20],
+        calldata_hash: [0u8; 32],
+        multisig: None,
+        spend_signatures: Vec::new(),
+    };
+    inputs.sign(key.as_bytes()).map_err(|e| anyhow::anyhow!("invalid withdrawal: {e}"))?;
+    let expected = simulate_withdraw(&inputs).map_err(|rejection| anyhow::anyhow!("invalid withdrawal: {rejection}"))?;
+    check_tree_levels("withdraw", expected.tree_levels, resolve_tree_levels(request.tree_levels)?)?;
+
+    let pool = IShieldedPool::new(pool_addr, &provider);
+    let vkey = pool.WITHDRAW_VKEY().call().await?;
+    println!("Proving the withdrawal (this may take a few minutes)...");
+    let client = ProverClient::from_env();
+    let (pk, vk) = key_cache::setup(&client, WITHDRAW_ELF);
+    ensure!(vk.bytes32() == vkey.to_string(), "the pool's WITHDRAW_VKEY {} is not this build's {}", vkey, vk.bytes32());
+    let proof = proof_cache::prove(&client, &pk, WITHDRAW_ELF, &framed_stdin(CircuitKind::Withdraw, &inputs))?;
+    let (proof_bytes, public_values) = (proof.bytes(), proof.public_values.to_vec());
+    ensure!(
+        WithdrawPublicValues::decode(&public_values).as_ref() == Some(&expected),
+        "the proof commits other public values than the inputs imply"
+    );
+
+    println!("Submitting...");
+    // The change is posted encrypted to its owner, so a scan finds it again
+    let (_, viewing_pubkey) = derive_viewing_keypair(key.as_bytes());
+    let encrypted_change = change_note.as_ref().map_or_else(Vec::new, |cn| encrypt_note(cn, &viewing_pubkey));
+    let tx = pool
+        .withdraw(proof_bytes.clone().into(), public_values.clone().into(), encrypted_change.into())
+        .send()
+        .await?;
+    let receipt = tx.with_required_confirmations(source.finality.required_confirmations()).get_receipt().await?;
+    ensure!(receipt.status(), "withdraw tx {} reverted", receipt.transaction_hash);
+    let block = strict::block_number(receipt.block_number, || {
+        format!("receipt for tx {}", receipt.transaction_hash)
+    })?;
+
+    let mut entry = AuditEntry::new(
+        AuditAction::Withdraw,
+        "cli",
+        chain_id,
+        &pool_addr.to_string(),
+        &receipt.transaction_hash.to_string(),
+        block
+    )
+        .with_spent(AuditNote::from_wallet(&state.notes[input.index]))
+        .with_public_amount(amount)
+        .with_proof(&proof_bytes, &public_values)
+        .with_request(inputs.request_hash());
+    let mut change_label = None;
+    if let Some(cn) = &change_note {
+        // Other insertions may share the block, so look the change up by commitment
+        let commitment = cn.commitment();
+        let change_leaf = scan_outputs(&provider, pool_addr, block, block)
+            .await?
+            .iter()
+            .find(|o| o.commitment == commitment)
+            .map(|o| o.leaf_index)
+            .with_context(|| format!("change 0x{} not found in block {}", hex::encode(commitment), block))?;
+        let wn = WalletNote::new(&format!("change_{change_leaf}"), cn, change_leaf);
+        println!("Change: {} USDT as note '{}' (leaf {})", (change as f64) / 1e6, wn.label, change_leaf);
+        entry = entry.with_created(AuditNote::from_wallet(&wn));
+        change_label = Some(wn.label.clone());
+        state.notes.push(wn);
+    }
+    state.record_tx(&entry);
+    state.save(&wallet_file)?;
+    AuditLog::record(entry)?;
+
+    println!("Withdrew {} USDT to {}", (amount as f64) / 1e6, recipient);
+    println!("Tx: {}", receipt.transaction_hash);
+    Ok(walletd::Withdrawn {
+        tx_hash: receipt.transaction_hash.to_string(),
+        amount,
+        recipient: recipient.to_string(),
+        change,
+        change_note: change_label,
+    })
+}
+
+/// Sync the pool tree as `balance` does and report how much of it is verified.
+async fn sync_status() -> Result<walletd::SyncReport> {
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let views = PoolViews::new(&provider, pool_addr, BatchConfig::from_env()?).await?;
+    let source = TreeSource::from_env(chain_id, pool_addr)?;
+    let PoolSync { status, block, .. } = sync_pool_tree(&provider, &views, pool_addr, chain_id, &source).await?;
+    Ok(walletd::SyncReport {
+        block,
+        on_chain_leaves: status.on_chain_leaves,
+        local_leaves: status.local_leaves,
+        verified_leaves: status.verified_leaves,
+        verified_root: status.verified_root.map(|root| format!("0x{}", hex::encode(root))),
+        first_divergence: status.first_divergence,
+        complete: status.is_complete(),
+    })
+}
+
+/// Serve the wallet over JSON-RPC until the process is stopped. Each call
+/// runs as the CLI command it mirrors, one at a time; `listNotes` only reads
+/// the wallet, so it is answered even while a transfer is proving.
+fn run_walletd(port: u16, wallet: Option<PathBuf>, token_file: Option<PathBuf>) -> Result<()> {
+    let wallet_file = wallet.unwrap_or_else(wallet_path);
+    let state = WalletState::load(&wallet_file)?;
+    // Ask for the passphrase once, and check it, rather than on every call
+    if std::env::var("WALLET_PASSPHRASE").is_err() && state.spending_keys.iter().any(|k| k.keystore.is_some()) {
+        std::env::set_var("WALLET_PASSPHRASE", read_passphrase("Wallet passphrase: ", false)?);
+    }
+    let keys = state.unlock_spending_keys()?;
+    println!("Wallet: {} ({} keys, {} notes)", wallet_file.display(), keys.len(), state.notes.len());
+    let token_file = token_file.unwrap_or_else(|| wallet_file.with_file_name("walletd.token"));
+    let token = walletd::token(&token_file)?;
+
+    let busy = Mutex::new(());
+    let handler = move |method: &str, params: serde_json::Value| -> Result<serde_json::Value, RpcError> {
+        let wallet = Some(wallet_file.clone());
+        if method == "listNotes" {
+            let ListNotesParams { include_spent } = walletd::params(params)?;
+            let state = WalletState::load(&wallet_file)?;
+            let notes: Vec<NoteView> = state.notes
+                .iter()
+                .filter(|n| include_spent || !n.spent)
+                .map(NoteView::from)
+                .collect();
+            return Ok(serde_json::to_value(notes).map_err(anyhow::Error::from)?);
+        }
+
+        let _busy = busy.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        println!("[walletd] {method}");
+        // Runs on a blocking thread of the server's runtime
+        let runtime = tokio::runtime::Handle::current();
+        let result = match method {
+            "getBalance" => serde_json::to_value(runtime.block_on(wallet_balance(wallet))?),
+            "getSyncStatus" => serde_json::to_value(runtime.block_on(sync_status())?),
+            "createTransfer" => {
+                let TransferParams { to, amount, viewing_pubkey, from } = walletd::params(params)?;
+                let request = SendRequest { to, amount, viewing_pubkey, from, wallet };
+                serde_json::to_value(runtime.block_on(send_transfer(request))?)
+            }
+            "createWithdraw" => {
+                let WithdrawParams { amount, note, to } = walletd::params(params)?;
+                let request = WithdrawRequest { amount, note, to, wallet, tree_levels: None };
+                serde_json::to_value(runtime.block_on(withdraw_on_chain(request))?)
+            }
+            _ => unreachable!("walletd only dispatches its METHODS"),
+        };
+        Ok(result.map_err(anyhow::Error::from)?)
+    };
+    tokio::runtime::Runtime::new()?.block_on(walletd::serve(port, token, Arc::new(handler)))
+}
+
+/// Trial-decrypt every encrypted output inserted in the block range with the
+/// wallet's viewing keys, and add the notes its keys own (and the messages
+/// they carry) to the wallet.
+async fn scan_for_notes(from_block: Option<u64>, to_block: Option<u64>, wallet: Option<PathBuf>) -> Result<()> {
+    let wallet_file = wallet.unwrap_or_else(wallet_path);
+    let mut state = WalletState::load(&wallet_file)?;
+    let keys = state.unlock_spending_keys()?;
+    let viewing_secrets: Vec<_> = keys.iter().map(|k| derive_viewing_keypair(k.as_bytes()).0).collect();
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool_addr: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let finality = FinalityPolicy::for_chain(chain_id)?;
+    if let Some(rollback) = check_scan_cursor(&provider, pool_addr, chain_id, &finality, &mut state).await? {
+        println!(
+            "The chain reorged below the last scanned block; rolled back {} note(s), {} transaction(s)",
+            rollback.removed_notes.len(),
+            rollback.dropped_txs.len()
+        );
+        for label in &rollback.removed_notes {
+            println!("    - {label}");
+        }
+        for label in &rollback.unspent_notes {
+            println!("    {label} is unspent again");
+        }
+    }
+    let from_block = match from_block {
+        Some(block) => block,
+        None => state
+            .scan_cursor(chain_id, &pool_addr.to_string())
+            .context("this wallet has not scanned the pool yet; give --from-block")?,
+    };
+    let to_block = match to_block {
+        Some(block) => block,
+        None => finality.final_block(provider.get_block_number().await?),
+    };
+    ensure!(from_block <= to_block, "nothing to scan: block {} is not final yet", from_block);
+    println!("Scanning blocks {}..={}...", from_block, to_block);
+    let outputs = scan_outputs(&provider, pool_addr, from_block, to_block).await?;
+
+    let (mut found, mut amount, mut messages) = (0usize, 0u64, 0usize);
+    for output in outputs.iter().filter(|o| !o.encrypted.is_empty()) {
+        let Some(note) = viewing_secrets.iter().find_map(|secret| decrypt_note(&output.encrypted, secret)) else {
+            continue;
+        };
+        let commitment = hex::encode(output.commitment);
+        if note.commitment() != output.commitment {
+            println!("    ⚠ leaf {}: payload is for another note than 0x{commitment}; skipped", output.leaf_index);
+            continue;
+        }
+        if state.receive_message(&output.encrypted, &keys)?.is_some() {
+            messages += 1;
+        }
+        if state.notes.iter().any(|n| n.commitment == commitment) {
+            continue;
+        }
+        if !keys.iter().any(|k| k.owns(&note.pubkey)) {
+            println!("    leaf {}: addressed to this wallet but owned by another key; not added", output.leaf_index);
+            continue;
+        }
+        let wn = WalletNote::new(&format!("received_{}", output.leaf_index), &note, output.leaf_index);
+        let usdt = (note.amount as f64) / 1e6;
+        println!("    + {} — {} USDT (leaf {}, block {})", wn.label, usdt, wn.leaf_index, output.block);
+        state.notes.push(wn);
+        found += 1;
+        amount += note.amount;
+    }
+    // Only move the cursor forward, so rescanning an old range keeps it
+    if state.scan_cursor(chain_id, &pool_addr.to_string()).is_none_or(|next| next <= to_block) {
+        let block_hash = Some(block_hash(&provider, to_block).await?);
+        let pool = pool_addr.to_string();
+        state.sync_cursor = Some(SyncCursor { chain_id, pool, next_block: to_block + 1, block_hash });
+    }
+    state.save(&wallet_file)?;
+    println!(
+        "Scanned {} outputs: {} new note(s) worth {} USDT, {} new message(s)",
+        outputs.len(),
+        found,
+        (amount as f64) / 1e6,
+        messages
+    );
+    println!("The next scan continues from block {}", to_block + 1);
+    Ok(())
+}
+
+/// A commitment's absence from the pool as of one block, as written by
+/// `prove-absence`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct AbsenceStatement {
+    chain_id: u64,
+    pool: String,
+    block: u64,
+    /// Hex commitment
+    commitment: String,
+    /// Sorted index of the pool's leaves at `block`
+    index: IndexRoot,
+    proof: AbsenceProof,
+}
+
+/// Rebuild the pool as of `block` (`RPC_URL`, `POOL_ADDRESS`, ...).
+async fn rebuild_pool_at(block: Option<u64>) -> Result<(u64, Address, PoolSnapshot)> {
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+    let source = TreeSource::from_env(chain_id, pool)?;
+    let block = match block {
+        Some(block) => block,
+        None => source.finality.final_block(provider.get_block_number().await?),
+    };
+    println!("Rebuilding the pool as of block {}...", block);
+    Ok((chain_id, pool, pool_snapshot(&provider, pool, &source, block).await?))
+}
+
+/// Rebuild the pool, index its leaves in sorted order and write a proof
+/// that `commitment` is not among them.
+async fn prove_absence(commitment: &str, block: Option<u64>, out: &Path) -> Result<()> {
+    let commitment = decode_hex_fixed::<32>(commitment).context("the commitment must be 32 bytes of hex")?;
+    let (chain_id, pool, snap) = rebuild_pool_at(block).await?;
+    if let Some(leaf) = snap.tree.index_of(&commitment) {
+        bail!("0x{} is in the pool at leaf {} (block {})", hex::encode(commitment), leaf, snap.block);
+    }
+    let index = CommitmentIndex::new(&snap.tree);
+    let proof = index.prove_absence(&commitment).map_err(anyhow::Error::msg)?;
+    proof.verify(&index.root(), &commitment).map_err(anyhow::Error::msg)?;
+    let statement = AbsenceStatement {
+        chain_id,
+        pool: pool.to_string(),
+        block: snap.block,
+        commitment: hex::encode(commitment),
+        index: index.root(),
+        proof,
+    };
+    fs::write(out, serde_json::to_string_pretty(&statement)?)?;
+
+    println!(
+        "0x{} was never inserted: the pool had {} leaves at block {} (root 0x{})",
+        statement.commitment,
+        statement.index.leaf_count,
+        snap.block,
+        hex::encode(statement.index.tree_root)
+    );
+    println!("Index digest: 0x{}", hex::encode(statement.index.digest()));
+    println!("Absence proof: {} (check with verify-absence)", out.display());
+    Ok(())
+}
+
+/// Check an absence proof against its index root, then (with `RPC_URL`)
+/// rebuild the index from the chain and compare.
+async fn verify_absence(path: &Path) -> Result<()> {
+    let statement: AbsenceStatement = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let commitment = decode_hex_fixed::<32>(&statement.commitment).context("malformed commitment")?;
+    statement.proof
+        .verify(&statement.index, &commitment)
+        .map_err(anyhow::Error::msg)
+        .context("the absence proof does not hold")?;
+    println!(
+        "✓ 0x{} is not among the {} leaves of index 0x{}",
+        statement.commitment,
+        statement.index.leaf_count,
+        hex::encode(statement.index.digest())
+    );
+
+    if std::env::var("RPC_URL").is_err() {
+        println!("RPC_URL not set: the index was not checked against the pool");
+        return Ok(());
+    }
+    let (chain_id, pool, snap) = rebuild_pool_at(Some(statement.block)).await?;
+    ensure!(
+        chain_id == statement.chain_id && pool.to_string().eq_ignore_ascii_case(&statement.pool),
+        "the proof is for pool {} on chain {}, not POOL_ADDRESS on this RPC",
+        statement.pool,
+        statement.chain_id
+    );
+    let rebuilt = CommitmentIndex::new(&snap.tree).root();
+    ensure!(
+        rebuilt == statement.index,
+        "the proof's index does not match the pool at block {} (its root there is 0x{})",
+        statement.block,
+        hex::encode(rebuilt.tree_root)
+    );
+    println!("✓ The index matches the pool at block {}", statement.block);
+    Ok(())
+}
+
+/// The guest ELF this binary embeds for `circuit` (one of `CIRCUITS`).
+fn embedded_elf(circuit: &str) -> &'static [u8] {
+    match circuit {
+        "transfer" => TRANSFER_ELF,
+        "withdraw" => WITHDRAW_ELF,
+        "claim" => CLAIM_ELF,
+        "join-split" => JOIN_SPLIT_ELF,
+        "deposit" => DEPOSIT_ELF,
+        "batch-withdraw" => BATCH_WITHDRAW_ELF,
+        "consolidate" => CONSOLIDATE_ELF,
+        "attest" => ATTEST_ELF,
+        "disclose" => DISCLOSE_ELF,
+        "policy-spend" => POLICY_SPEND_ELF,
+        _ => PAYOUT_ELF,
+    }
+}
+
+/// Verify a proof output's Groth16 proof against the vkey of the embedded
+/// guest and print what its public values commit to. The file's own `vkey`
+/// only picks the circuit; a file naming a vkey this binary does not embed is
+/// refused rather than checked against it.
+fn verify_proof_output(client: &Client, path: &Path, circuit: Option<&str>) -> Result<()> {
+    let output: ProofOutput = serde_json::from_str(
+        &fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?
+    ).with_context(|| format!("{} is not a proof output", path.display()))?;
+    let same = |a: &str, b: &str| a.trim_start_matches("0x").eq_ignore_ascii_case(b.trim_start_matches("0x"));
+    let embedded_vkey = |circuit: &str| key_cache::setup(client, embedded_elf(circuit)).1.bytes32();
+
+    let (circuit, vkey) = match circuit {
+        Some(circuit) => {
+            let vkey = embedded_vkey(circuit);
+            ensure!(
+                same(&output.vkey, &vkey),
+                "{} is for vkey {}, not the embedded {} vkey {}",
+                path.display(),
+                output.vkey,
+                circuit,
+                vkey
+            );
+            (circuit, vkey)
+        }
+        None => CIRCUITS.iter()
+            .find_map(|&circuit| {
+                let vkey = embedded_vkey(circuit);
+                same(&output.vkey, &vkey).then_some((circuit, vkey))
+            })
+            .with_context(|| format!("vkey {} is not one of this binary's circuits (see `vkeys`)", output.vkey))?,
+    };
+    println!("[verify] {} proof, vkey {}", circuit, vkey);
+
+    let proof = decode_hex(&output.proof).context("proof must be hex")?;
+    let public_values = decode_hex(&output.public_values).context("public_values must be hex")?;
+    ensure!(!proof.is_empty(), "{} holds a mock proof (SP1_PROVER=mock), there is nothing to verify", path.display());
+    sp1_verifier::Groth16Verifier::verify(&proof, &public_values, &vkey, *sp1_verifier::GROTH16_VK_BYTES)
+        .map_err(|e| anyhow::anyhow!("[verify] proof does not verify: {e}"))?;
+    println!("[verify] ✓ Groth16 proof verifies against the embedded vkey");
+
+    println!("Public values:");
+    for (field, value) in describe_public_values(circuit, &public_values)? {
+        println!("  {:<20} {}", field, value);
+    }
+    if !output.request_hash.is_empty() {
+        // Not part of the proof: whoever wrote the file could have put anything here
+        println!("Request hash (unproven): 0x{}", output.request_hash.trim_start_matches("0x"));
+    }
+    Ok(())
+}
+
+/// The public values of a `circuit` proof as labelled, readable fields.
+/// Amounts are in USDT; zero deadlines, bindings and optional outputs read
+/// "none".
+fn describe_public_values(circuit: &str, bytes: &[u8]) -> Result<Vec<(String, String)>> {
+    fn hex0x(bytes: &[u8]) -> String {
+        format!("0x{}", hex::encode(bytes))
+    }
+    fn or_none(bytes: &[u8]) -> String {
+        if bytes.iter().all(|&b| b == 0) { "none".to_string() } else { hex0x(bytes) }
+    }
+    fn usdt(amount: u64) -> String {
+        format!("{} USDT", (amount as f64) / 1e6)
+    }
+    fn address(bytes: [u8; 20]) -> String {
+        if bytes == [0u8; 20] { "none".to_string() } else { Address::from(bytes).to_string() }
+    }
+    fn deadline(timestamp: u64) -> String {
+        if timestamp == 0 { "none".to_string() } else { format!("{} (unix time)", timestamp) }
+    }
+    fn numbered(name: &str, values: &[[u8; 32]]) -> Vec<(String, String)> {
+        values.iter().enumerate().map(|(i, v)| (format!("{} {}", name, i + 1), hex0x(v))).collect()
+    }
+    let malformed = || anyhow::anyhow!("malformed {} public values ({} bytes)", circuit, bytes.len());
+    let field = |name: &str, value: String| vec![(name.to_string(), value)];
+
+    let fields = match circuit {
+        "transfer" => {
+            let pv = TransferPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                numbered("nullifier", &pv.nullifiers),
+                numbered("output commitment", &pv.out_commitments),
+                field("reclaim deadline", deadline(pv.reclaim_deadline)),
+                field("association root", or_none(&pv.association_root)),
+                field("pool binding", or_none(&pv.pool_binding)),
+                field("ciphertexts hash", or_none(&pv.ciphertexts_hash)),
+                field("tree levels", pv.tree_levels.to_string()),
+            ].concat()
+        }
+        "withdraw" => {
+            let pv = WithdrawPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                field("nullifier", hex0x(&pv.nullifier)),
+                field("recipient", address(pv.recipient)),
+                field("amount", usdt(pv.amount)),
+                field("change commitment", or_none(&pv.change_commitment)),
+                field("reclaim deadline", deadline(pv.reclaim_deadline)),
+                field("relayer", address(pv.relayer)),
+                field("fee", usdt(pv.fee)),
+                field("association root", or_none(&pv.association_root)),
+                field("pool binding", or_none(&pv.pool_binding)),
+                field("deadline", deadline(pv.deadline)),
+                field("call binding", or_none(&pv.call_binding)),
+                field("tree levels", pv.tree_levels.to_string()),
+            ].concat()
+        }
+        "claim" => {
+            let pv = ClaimPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                field("nullifier root", hex0x(&pv.nullifier_root)),
+                field("airdrop id", hex0x(&pv.airdrop_id)),
+                field("claim nullifier", hex0x(&pv.claim_nullifier)),
+                field("recipient", address(pv.recipient)),
+                field("amount", usdt(pv.amount)),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        "join-split" => {
+            let pv = JoinSplitPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                numbered("nullifier", &pv.nullifiers),
+                numbered("output commitment", &pv.out_commitments),
+                field("reclaim deadline", deadline(pv.reclaim_deadline)),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        "deposit" => {
+            let pv = DepositPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [field("commitment", hex0x(&pv.commitment)), field("amount", usdt(pv.amount))].concat()
+        }
+        "batch-withdraw" => {
+            let pv = BatchWithdrawPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                numbered("nullifier", &pv.nullifiers),
+                field("recipient", address(pv.recipient)),
+                field("amount", usdt(pv.amount)),
+                field("reclaim deadline", deadline(pv.reclaim_deadline)),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        "consolidate" => {
+            let pv = ConsolidatePublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                numbered("nullifier", &pv.nullifiers),
+                field("output commitment", hex0x(&pv.out_commitment)),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        "attest" => {
+            let pv = AttestPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                field("nullifier root", hex0x(&pv.nullifier_root)),
+                field("threshold", usdt(pv.threshold)),
+                field("nonce", hex0x(&pv.nonce)),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        "disclose" => {
+            let pv = DisclosurePublicValues::decode(bytes).ok_or_else(malformed)?;
+            let notes = pv.notes.iter().enumerate().map(|(i, note)| {
+                (
+                    format!("note {}", i + 1),
+                    format!(
+                        "{} {} to {} (payload {})",
+                        hex0x(&note.commitment),
+                        usdt(note.amount),
+                        hex0x(&note.recipient),
+                        hex0x(&note.payload_hash)
+                    ),
+                )
+            });
+            [
+                field("viewing pubkey", hex0x(&pv.viewing_pubkey)),
+                field("audit id", hex0x(&pv.audit_id)),
+                notes.collect(),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        "policy-spend" => {
+            let pv = PolicySpendPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                field("nullifier", hex0x(&pv.nullifier)),
+                field("payment commitment", hex0x(&pv.payment_commitment)),
+                field("change commitment", or_none(&pv.change_commitment)),
+                field("counter root", hex0x(&pv.counter_root)),
+                field("counter nullifier", hex0x(&pv.counter_nullifier)),
+                field("new counter", hex0x(&pv.new_counter)),
+                field("epoch", pv.epoch.to_string()),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("counter levels", pv.counter_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+        _ => {
+            let pv = PayoutPublicValues::decode(bytes).ok_or_else(malformed)?;
+            [
+                field("root", hex0x(&pv.root)),
+                numbered("nullifier", &pv.nullifiers),
+                numbered("output commitment", &pv.out_commitments),
+                field("reclaim deadline", deadline(pv.reclaim_deadline)),
+                field("tree levels", pv.tree_levels.to_string()),
+                field("pool binding", or_none(&pv.pool_binding)),
+            ].concat()
+        }
+    };
+    Ok(fields)
+}
+
+fn run_reproduce(
+    client: &Client,
+    circuit: &str,
+    registry_path: &Path,
+    pool: Option<String>,
+    rpc_url: Option<String>,
+    update_registry: bool
+) -> Result<()> {
+    let out_dir = std::env::temp_dir().join(format!("shielded-pool-reproduce-{}", circuit));
+    println!("[{}] Rebuilding guest with SP1 Docker image {}...", circuit, reproduce::SP1_DOCKER_TAG);
+    let elf = reproduce::build_guest_reproducibly(circuit, &out_dir)?;
+    let elf_hash = reproduce::sha256_hex(&elf);
+    let (_, vk) = client.setup(&elf);
+    let vkey = vk.bytes32();
+    println!("[{}] ELF sha256: {}", circuit, elf_hash);
+    println!("[{}] vkey:       {}", circuit, vkey);
+
+    let mut registry = if registry_path.exists() {
+        VkeyRegistry::load(registry_path)?
+    } else {
+        VkeyRegistry::default()
+    };
+    let entry = registry.circuits.get(circuit).cloned().unwrap_or_default();
+    let same = |a: &str, b: &str| a.trim_start_matches("0x").eq_ignore_ascii_case(b.trim_start_matches("0x"));
+
+    let mut checks = Vec::new();
+    checks.push(Check {
+        name: "registry vkey",
+        matches: (!entry.vkey.is_empty()).then(|| same(&entry.vkey, &vkey)),
+        expected: entry.vkey.clone(),
+    });
+    checks.push(Check {
+        name: "registry ELF sha256",
+        matches: (!entry.elf_sha256.is_empty()).then(|| same(&entry.elf_sha256, &elf_hash)),
+        expected: entry.elf_sha256.clone(),
+    });
+
+    let embedded_hash = reproduce::sha256_hex(embedded_elf(circuit));
+    checks.push(Check {
+        name: "embedded ELF sha256",
+        matches: Some(embedded_hash == elf_hash),
+        expected: embedded_hash,
+    });
+
+    match (pool, rpc_url) {
+        // Claims are verified by airdrop contracts, attestations by the
+        // counterparty and disclosures by the auditor
+        _ if matches!(circuit, "claim" | "attest" | "disclose") => {
+            checks.push(Check { name: "on-chain vkey", matches: None, expected: "(not a pool circuit)".into() });
+        }
+        (Some(pool), Some(rpc_url)) => {
+            let onchain = tokio::runtime::Runtime
+                ::new()?
+                .block_on(reproduce::onchain_vkey(&rpc_url, pool.parse()?, circuit))?;
+            let zero = onchain.trim_start_matches("0x").bytes().all(|b| b == b'0');
+            if zero && circuit == "deposit" {
+                // A zero deposit vkey means the pool takes deposits without proofs
+                checks.push(Check { name: "on-chain vkey", matches: None, expected: "(pool does not require deposit proofs)".into() });
+            } else if zero && !matches!(circuit, "transfer" | "withdraw") {
+                // A zero vkey disables the circuit's entry point
+                checks.push(Check { name: "on-chain vkey", matches: None, expected: "(entry point disabled on this pool)".into() });
+            } else {
+                checks.push(Check { name: "on-chain vkey", matches: Some(same(&onchain, &vkey)), expected: onchain });
+            }
+        }
+        _ => checks.push(Check { name: "on-chain vkey", matches: None, expected: "(POOL_ADDRESS/RPC_URL not set)".into() }),
+    }
+
+    let ok = reproduce::print_verdict(circuit, &checks);
+
+    if update_registry {
+        registry.sp1_docker_tag = reproduce::SP1_DOCKER_TAG.to_string();
+        registry.circuits.insert(circuit.to_string(), reproduce::RegistryEntry { vkey, elf_sha256: elf_hash });
+        registry.save(registry_path)?;
+        println!("[{}] Registry updated: {}", circuit, registry_path.display());
+        return Ok(());
+    }
+    ensure!(ok, "{} guest does not reproduce the published artifacts", circuit);
+    Ok(())
+}
+
+fn run_note_command(action: NoteCommand) -> Result<()> {
+    match action {
+        NoteCommand::Export { note, no_label, wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let state = WalletState::load(&path)?;
+            let wn = &state.notes[state.find_note(&note)?];
+
+            let label = if no_label { None } else { Some(wn.label.clone()) };
+            let mut bundle = NoteBundle::new(wn.to_note()?, wn.leaf_index, label);
+            if let Some(lock) = wn.to_lock()? {
+                bundle = bundle.with_lock(lock);
+            }
+            println!("{}", bundle.encode().map_err(anyhow::Error::msg)?);
+        }
+        NoteCommand::Import { bundle, label, meta, wallet } => {
+            let bundle = NoteBundle::decode(&bundle).map_err(anyhow::Error::msg)?;
+            let path = wallet.unwrap_or_else(wallet_path);
+            let mut state = WalletState::load(&path)?;
+
+            let commitment = hex::encode(bundle.note.commitment());
+            ensure!(
+                !state.notes.iter().any(|n| n.commitment.to_lowercase() == commitment),
+                "note {} is already in {}",
+                commitment,
+                path.display()
+            );
+
+            let label = label.or(bundle.label).unwrap_or_else(|| format!("imported_{}", bundle.leaf_index));
+            let holds = |pubkey: &[u8; 32]| {
+                state.spending_keys
+                    .iter()
+                    .any(|k| decode_hex_fixed::<32>(&k.pubkey).is_ok_and(|pk| pk == *pubkey))
+            };
+            match &bundle.lock {
+                None if !holds(&bundle.note.pubkey) => {
+                    println!("Warning: no spending key in this wallet owns this note; it cannot be spent from here");
+                }
+                Some(lock) if holds(&lock.recipient_pubkey) => {
+                    println!("Cancellable note: this wallet can claim it (sender may reclaim until {})", lock.reclaim_deadline);
+                }
+                Some(lock) if holds(&lock.sender_pubkey) => {
+                    println!("Cancellable note: this wallet can reclaim it until {}", lock.reclaim_deadline);
+                }
+                Some(_) => {
+                    println!("Warning: this wallet holds neither key of the cancellable note; it cannot be spent from here");
+                }
+                None => {}
+            }
+
+            let mut wn = WalletNote::new(&label, &bundle.note, bundle.leaf_index);
+            if let Some(lock) = &bundle.lock {
+                wn = wn.with_lock(lock);
+            }
+            for arg in &meta {
+                let (key, value) = parse_metadata_arg(arg)?;
+                ensure!(value.is_some(), "--meta {} needs a value on import", key);
+                wn.set_metadata(&key, value)?;
+            }
+            state.notes.push(wn);
+            state.save(&path)?;
+            println!(
+                "Imported '{}': {} USDT at leaf {} (commitment 0x{})",
+                label,
+                (bundle.note.amount as f64) / 1e6,
+                bundle.leaf_index,
+                commitment
+            );
+        }
+        NoteCommand::List { meta, json, wallet } => {
+            let state = WalletState::load(&wallet.unwrap_or_else(wallet_path))?;
+            let filters = meta
+                .iter()
+                .map(|arg| parse_metadata_arg(arg))
+                .collect::<Result<Vec<_>>>()?;
+            let notes: Vec<&WalletNote> = state.notes
+                .iter()
+                .filter(|n| filters.iter().all(|(k, v)| n.metadata_matches(k, v.as_ref())))
+                .collect();
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&notes)?);
+            } else {
+                for n in notes {
+                    println!(
+                        "{:<24} {:>14} USDT  leaf {:<6} 0x{}",
+                        n.label,
+                        (n.amount as f64) / 1e6,
+                        n.leaf_index,
+                        n.commitment
+                    );
+                    if let Some(lock) = &n.lock {
+                        println!(
+                            "    cancellable: claim by 0x{}, reclaim by 0x{} until {}",
+                            lock.recipient_pubkey, lock.sender_pubkey, lock.reclaim_deadline
+                        );
+                    }
+                    for (k, v) in &n.metadata {
+                        println!("    {} = {}", k, v);
+                    }
+                }
+                let policy = state.idle_policy();
+                policy.print_reminders(&state, &policy.idle_notes(&state, unix_now()));
+            }
+        }
+        NoteCommand::Idle { remind_after_days, sweep, wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let mut state = WalletState::load(&path)?;
+            if remind_after_days.is_some() || sweep.is_some() {
+                let mut policy = state.idle_policy();
+                policy.remind_after_days = remind_after_days.unwrap_or(policy.remind_after_days);
+                policy.sweep = sweep.unwrap_or(policy.sweep);
+                state.idle_policy = (policy != IdlePolicy::default()).then_some(policy);
+                state.save(&path)?;
+            }
+            let policy = state.idle_policy();
+            println!("Idle after {} days, sweep: {:?}", policy.remind_after_days, policy.sweep);
+            let idle = policy.idle_notes(&state, unix_now());
+            if idle.is_empty() {
+                println!("No idle notes");
+            }
+            policy.print_reminders(&state, &idle);
+        }
+        NoteCommand::Tag { note, entry, wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let mut state = WalletState::load(&path)?;
+            let (key, value) = parse_metadata_arg(&entry)?;
+            ensure!(value.is_some(), "expected KEY=VALUE, got '{}'", entry);
+            let i = state.find_note(&note)?;
+            state.notes[i].set_metadata(&key, value)?;
+            state.save(&path)?;
+            println!("Tagged '{}' with {}", state.notes[i].label, entry);
+        }
+        NoteCommand::Untag { note, key, wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let mut state = WalletState::load(&path)?;
+            let i = state.find_note(&note)?;
+            ensure!(state.notes[i].metadata.contains_key(&key), "note '{}' has no '{}' entry", state.notes[i].label, key);
+            state.notes[i].set_metadata(&key, None)?;
+            state.save(&path)?;
+            println!("Removed '{}' from '{}'", key, state.notes[i].label);
+        }
+    }
+    Ok(())
+}
+
+/// Add a new (or recovered) mnemonic-backed key to the wallet, creating the
+/// wallet file if there is none.
+fn run_keygen(label: &str, recover: bool, wallet: Option<PathBuf>) -> Result<()> {
+    let wallet_file = wallet.unwrap_or_else(wallet_path);
+    let mut state = WalletState::load_or_default(&wallet_file)?;
+    ensure!(!label.is_empty() && !label.chars().any(char::is_whitespace), "--label must be one word");
+    ensure!(state.spending_keys.iter().all(|k| k.label != label), "the wallet already has a key labelled '{label}'");
+
+    let mnemonic = if recover {
+        keygen::parse_mnemonic(&rpassword::prompt_password("Mnemonic: ")?)?
+    } else {
+        println!("Entropy: {}", entropy::init_from_env()?.describe());
+        keygen::generate_mnemonic()?
+    };
+    let key = keygen::spending_key_from_mnemonic(&mnemonic);
+    let pubkey = Some(key.pubkey());
+    if let Some(existing) = state.spending_keys.iter().find(|k| decode_hex_fixed::<32>(&k.pubkey).ok() == pubkey) {
+        bail!("the wallet already holds this key as '{}'", existing.label);
+    }
+
+    // One passphrase unlocks the whole wallet, so a new key must use the one
+    // the other keys are under
+    let locked = state.spending_keys.iter().find_map(|k| k.keystore.as_ref());
+    let passphrase = read_passphrase("Wallet passphrase (encrypts the new key): ", locked.is_none())?;
+    if let Some(keystore) = locked {
+        keystore.decrypt(&passphrase).context("the passphrase does not unlock the wallet's other keys")?;
+    }
+    let entry = keygen::wallet_key(label, &key, &passphrase)?;
+    println!("Key '{}' added to {}", label, wallet_file.display());
+    println!("Shielded address: 0x{}", entry.pubkey);
+    println!("Viewing pubkey:   0x{}", entry.viewing_pubkey);
+    state.spending_keys.push(entry);
+    state.save(&wallet_file)?;
+    if !recover {
+        println!("\nMnemonic (write it down; it restores this key with `keygen --recover`):\n{mnemonic}");
+    }
+    Ok(())
+}
+
+fn run_wallet_command(action: WalletCommand) -> Result<()> {
+    match action {
+        WalletCommand::Fix { dry_run, wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let (state, fixes) = WalletState::load_raw(&path)?;
+            for fix in &fixes {
+                println!("  {}", fix);
+            }
+            state.verify()?;
+            println!("Verified {} key(s) and {} note(s)", state.spending_keys.len(), state.notes.len());
+
+            // A database always holds the current schema; only its contents can need fixing
+            let current = if wallet_store::is_store_path(&path) {
+                fixes.is_empty()
+            } else {
+                serde_json::to_string_pretty(&state)? == fs::read_to_string(&path)?
+            };
+            if current {
+                println!("{} is already in the current schema", path.display());
+                return Ok(());
+            }
+            if dry_run {
+                println!("Dry run: {} not rewritten", path.display());
+                return Ok(());
+            }
+            let mut backup = path.clone().into_os_string();
+            backup.push(".bak");
+            fs::copy(&path, &backup)?;
+            state.save(&path)?;
+            println!("Rewrote {} (original kept as {})", path.display(), Path::new(&backup).display());
+        }
+        WalletCommand::Migrate { from, wallet } => {
+            let to = wallet.unwrap_or_else(wallet_path);
+            let from = from.unwrap_or_else(|| to.with_extension("json"));
+            match wallet_store::migrate(&from, &to)? {
+                Migration::Imported { state, fixes } => {
+                    for fix in &fixes {
+                        println!("  {}", fix);
+                    }
+                    println!(
+                        "Imported {} key(s), {} note(s) and {} message(s) from {} into {}",
+                        state.spending_keys.len(),
+                        state.notes.len(),
+                        state.messages.len(),
+                        from.display(),
+                        to.display()
+                    );
+                }
+                Migration::AlreadyDone => println!("{} already holds the wallet in {}", to.display(), from.display()),
+            }
+            println!("{} is left as it was; remove it once the database works for you", from.display());
+        }
+        WalletCommand::History { wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let state = WalletState::load(&path)?;
+            if state.transactions.is_empty() {
+                println!("No transactions recorded in {}", path.display());
+            }
+            for t in &state.transactions {
+                let amount = t.public_amount.map_or_else(String::new, |a| format!("{} USDT", (a as f64) / 1e6));
+                println!(
+                    "block {:<10} {:<9} {:>16}  {} spent, {} created  {}",
+                    t.block_number,
+                    format!("{:?}", t.action).to_lowercase(),
+                    amount,
+                    t.spent.len(),
+                    t.created.len(),
+                    t.tx_hash
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn prepare_emergency_kit(wallet: Option<PathBuf>, checkpoint: Option<PathBuf>, out: &Path) -> Result<()> {
+    let wallet = WalletState::load(&wallet.unwrap_or_else(wallet_path))?;
+    let keys = wallet.unlock_spending_keys()?;
+    let checkpoint = match checkpoint {
+        Some(path) => Some(TreeCheckpoint::load(&path)?),
+        None => {
+            let path = TreeCheckpoint::default_path();
+            if path.exists() { Some(TreeCheckpoint::load(&path)?) } else { None }
+        }
+    };
+    let passphrase = read_kit_passphrase(true)?;
+    let kit = EmergencyKit::prepare(
+        &wallet,
+        &keys,
+        checkpoint.as_ref(),
+        KitDeployment::from_env()?,
+        &passphrase,
+        Default::default()
+    )?;
+    kit.save(out)?;
+    println!(
+        "Wrote {} ({} key(s), {} note(s), sections: {})",
+        out.display(),
+        kit.manifest.keys.len(),
+        kit.manifest.notes.len(),
+        kit.manifest.sections.keys().cloned().collect::<Vec<_>>().join(", ")
+    );
+    report_kit_check(&kit)
+}
+
+fn verify_emergency_kit(path: &Path) -> Result<()> {
+    let kit = EmergencyKit::load(path)?;
+    let d = &kit.manifest.deployment;
+    println!("Kit {} prepared at unix time {} for pool {}", path.display(), kit.created_at, d.pool);
+    println!(
+        "  {} key(s), {} note(s), checkpoint: {}",
+        kit.manifest.keys.len(),
+        kit.manifest.notes.len(),
+        kit.manifest.checkpoint.as_ref().map_or("none".to_string(), |cp| format!("{} leaves @ block {}", cp.leaves, cp.block))
+    );
+    report_kit_check(&kit)
+}
+
+/// Print the completeness check of a kit; fail if it cannot carry out a full exit.
+fn report_kit_check(kit: &EmergencyKit) -> Result<()> {
+    let check = kit.check();
+    for warning in &check.warnings {
+        println!("  warning: {}", warning);
+    }
+    for problem in &check.problems {
+        println!("  PROBLEM: {}", problem);
+    }
+    ensure!(check.problems.is_empty(), "emergency kit is incomplete ({} problem(s))", check.problems.len());
+    println!("Kit is complete");
+    Ok(())
+}
+
+fn execute_emergency_kit(path: &Path, out: &Path, run: bool) -> Result<()> {
+    let kit = EmergencyKit::load(path)?;
+    let check = kit.check();
+    for warning in check.warnings.iter().chain(&check.problems) {
+        println!("  warning: {}", warning);
+    }
+    let passphrase = read_kit_passphrase(false)?;
+    let opened = kit.open(&passphrase)?;
+    let restored = opened.restore(out, &passphrase)?;
+    println!("Restored {} key(s) and {} note(s) into {}", opened.keys.len(), opened.wallet.notes.len(), out.display());
+    println!("{}", opened.instructions);
+
+    let env = opened.exit_env(&restored);
+    if !run {
+        println!("Exit with (WALLET_PASSPHRASE is the kit passphrase):");
+        for (name, value) in &env {
+            println!("  {}={}", name, value);
+        }
+        println!("  cargo run --release -p shielded-pool-script --bin exit");
+        return Ok(());
+    }
+    let exit = std::env::current_exe()?.with_file_name("exit");
+    ensure!(exit.exists(), "exit binary not found next to this one: {}", exit.display());
+    let status = std::process::Command::new(&exit).envs(env).env("WALLET_PASSPHRASE", &passphrase).status()?;
+    ensure!(status.success(), "exit failed ({}); rerun with the same --out to resume", status);
+    Ok(())
+}
+
+fn run_message_command(action: MessageCommand) -> Result<()> {
+    match action {
+        MessageCommand::Open { encrypted, wallet } => {
+            let path = wallet.unwrap_or_else(wallet_path);
+            let mut state = WalletState::load(&path)?;
+            let keys = state.unlock_spending_keys()?;
+            let encrypted = shielded_pool_script::decode_hex(&encrypted)?;
+            match state.receive_message(&encrypted, &keys)? {
+                Some(m) => {
+                    print_message(m);
+                    state.save(&path)?;
+                }
+                None => println!("No new message for this wallet in that output"),
+            }
+        }
+        MessageCommand::List { counterparty, json, wallet } => {
+            let state = WalletState::load(&wallet.unwrap_or_else(wallet_path))?;
+            let threads = match &counterparty {
+                Some(c) => [(c.as_str(), state.thread(c))].into_iter().collect(),
+                None => state.threads(),
+            };
+            if json {
+                println!("{}", serde_json::to_string_pretty(&threads)?);
+                return Ok(());
+            }
+            if threads.values().all(|t| t.is_empty()) {
+                println!("No messages");
+            }
+            for (counterparty, thread) in threads.iter().filter(|(_, t)| !t.is_empty()) {
+                println!("Thread with 0x{} ({} message(s))", counterparty, thread.len());
+                for m in thread {
+                    print_message(m);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_message(m: &WalletMessage) {
+    let arrow = match m.direction {
+        MessageDirection::Sent => "→",
+        MessageDirection::Received => "←",
+    };
+    let body = match &m.message.body {
+        MessageBody::Text { text } => text.clone(),
+        MessageBody::PaymentAck { commitment } => format!("received note 0x{}", commitment),
+        MessageBody::RefundAddress { pubkey, viewing_pubkey } => {
+            format!("refund to 0x{} (viewing 0x{})", pubkey, viewing_pubkey)
+        }
+    };
+    println!("  {} {:>10}  {}  [note 0x{}]", arrow, m.message.sent_at, body, m.commitment);
+}
+
+fn run_audit_command(action: AuditCommand) -> Result<()> {
+    match action {
+        AuditCommand::Export { log, wallet, out } => {
+            let log = log.unwrap_or_else(AuditLog::path);
+            let entries = AuditLog::load(&log)?;
+            ensure!(!entries.is_empty(), "{} has no entries", log.display());
+            let wallet = wallet.unwrap_or_else(wallet_path);
+            let notes = if wallet.exists() { WalletState::load(&wallet)?.notes } else { Vec::new() };
+
+            let report = AuditReport::export(entries, &notes)?;
+            report.check_offline()?;
+            report.save(&out)?;
+            println!("Verified {} entries; head 0x{}", report.entries.len(), report.head);
+            println!("{} note(s), {} still in the wallet", report.notes.len(), report.notes.iter().filter(|n| n.in_wallet).count());
+            println!("Audit report: {}", out.display());
+            println!(
+                "Verify with:  cargo run --release -p shielded-pool-script --bin verify-audit-report -- {}",
+                out.display()
+            );
+        }
+        AuditCommand::Disclose { notes, audit_id, wallet, input, out, execute_only } => {
+            let inputs = tokio::runtime::Runtime::new()?.block_on(prepare_disclosure(&notes, &audit_id, wallet))?;
+            fs::write(&input, serde_json::to_string_pretty(&inputs)?)?;
+            println!("Disclose inputs: {}", input.display());
+            let client = ProverClient::from_env();
+            let (input, out) = (input.to_string_lossy(), out.to_string_lossy());
+            let check = print_disclosure;
+            generate_proof(
+                &client, DISCLOSE_ELF, "disclose", &input, &out, OutputFormat::Json,
+                execute_only, None, None, None, &check,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the payloads posted with wallet notes and build disclose inputs
+/// under the viewing key of the spending key that owns them.
+async fn prepare_disclosure(
+    notes: &[String],
+    audit_id: &str,
+    wallet: Option<PathBuf>
+) -> Result<DisclosurePrivateInputs> {
+    let state = WalletState::load(&wallet.unwrap_or_else(wallet_path))?;
+    let keys = state.unlock_spending_keys()?;
+    let audit_id = decode_hex_fixed::<32>(audit_id).context("--audit-id must be 32 bytes of hex")?;
+
+    let rpc_url = std::env::var("RPC_URL").context("RPC_URL not set")?;
+    let pool: Address = std::env::var("POOL_ADDRESS").context("POOL_ADDRESS not set")?.parse()?;
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let chain_id = provider.get_chain_id().await?;
+
+    let mut viewing_secret = None;
+    let mut payloads = Vec::with_capacity(notes.len());
+    for selector in notes {
+        let wn = &state.notes[state.find_note(selector)?];
+        let (key, _) = wn
+            .authorize(&keys)?
+            .with_context(|| format!("no spending key in this wallet owns note '{}'", wn.label))?;
+        let (secret, _) = derive_viewing_keypair(key.as_bytes());
+        let secret = secret.to_bytes();
+        ensure!(
+            *viewing_secret.get_or_insert(secret) == secret,
+            "note '{}' belongs to another key; disclose each key's notes separately",
+            wn.label
+        );
+        let payload = encrypted_note(&provider, pool, wn.leaf_index).await?;
+        ensure!(
+            !payload.is_empty(),
+            "no encrypted payload was posted for note '{}' (leaf {})",
+            wn.label,
+            wn.leaf_index
+        );
+        payloads.push(payload);
+    }
+
+    let inputs = DisclosurePrivateInputs {
+        viewing_secret: viewing_secret.context("no notes to disclose")?,
+        payloads,
+        audit_id,
+        pool_binding: pool_binding(chain_id, &pool.0 .0),
+    };
+    inputs.check().map_err(|msg| anyhow::anyhow!("invalid disclose inputs: {msg}"))?;
+    Ok(inputs)
+}
+
+/// Print what a disclosure opened, for `disclose` and `audit disclose`.
+fn print_disclosure(public_values: &[u8]) -> Result<()> {
+    let pv = DisclosurePublicValues::decode(public_values).context("malformed disclose public values")?;
+    println!(
+        "[disclose] {} note(s) under viewing key 0x{}, audit 0x{}",
+        pv.notes.len(),
+        hex::encode(pv.viewing_pubkey),
+        hex::encode(pv.audit_id)
+    );
+    for note in &pv.notes {
+        println!(
+            "  0x{}: {} USDT to 0x{} (payload 0x{})",
+            hex::encode(note.commitment),
+            (note.amount as f64) / 1e6,
+            hex::encode(note.recipient),
+            hex::encode(note.payload_hash)
+        );
+    }
     Ok(())
 }
@@ -0,0 +1,162 @@
+//! Wallet-to-wallet messages carried in encrypted note payloads.
+//!
+//! Counterparties exchange payment acknowledgments and refund addresses
+//! without a side channel: the sender puts a `Message` into the encrypted
+//! payload of a transfer output addressed to the recipient. The payload is
+//! the usual note JSON with one extra field, so wallets that do not know
+//! about messages still decrypt the note:
+//!
+//!   { "amount", "pubkey", "blinding", "message": { ... } }
+//!
+//! A message can ride on a payment note, or on a note of its own worth
+//! `MESSAGE_NOTE_AMOUNT` (nothing; the transfer's inputs all return as
+//! change). Amounts are hidden, so on-chain such a note looks like any other
+//! output.
+//!
+//! Payloads are encrypted under a one-time ephemeral key, so they do not
+//! name the sender. The message instead carries the sender's viewing pubkey
+//! and an `auth` tag only the holder of that viewing key could compute for
+//! this recipient and this note. Threads are keyed by that (verified)
+//! viewing pubkey, which is also where replies go.
+
+use anyhow::{ensure, Context, Result};
+use crypto_box::{aead::Aead, PublicKey, SalsaBox, SecretKey};
+use serde::{Deserialize, Serialize};
+use shielded_pool_lib::{keccak256, Note};
+
+use crate::{decode_hex_fixed, note_from_json, note_json, open, seal};
+
+/// Current message format.
+pub const MESSAGE_VERSION: u8 = 1;
+
+/// Value of a note sent only to carry a message.
+pub const MESSAGE_NOTE_AMOUNT: u64 = 0;
+
+/// Largest message body (as JSON) a payload may carry, to keep calldata small.
+pub const MAX_MESSAGE_BYTES: usize = 512;
+
+/// Domain separator for the sender authentication tag.
+const AUTH_DOMAIN: &[u8] = b"shielded-pool/message-auth/v1";
+
+/// What a message says.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageBody {
+    /// Free text
+    Text { text: String },
+    /// The note with this commitment (hex) was received
+    PaymentAck { commitment: String },
+    /// Where to send refunds: a shielded pubkey and its viewing pubkey (hex)
+    RefundAddress { pubkey: String, viewing_pubkey: String },
+}
+
+/// A message as carried in an encrypted payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub version: u8,
+    /// Hex-encoded viewing pubkey of the sender; replies are encrypted to it
+    pub sender: String,
+    /// Unix time the sender wrote the message (as claimed by the sender)
+    pub sent_at: u64,
+    pub body: MessageBody,
+    /// Hex-encoded 16-byte tag binding the message to the sender's viewing
+    /// key, the recipient and the carrying note
+    pub auth: String,
+}
+
+/// A note and the message it carries, decrypted from a payload.
+#[derive(Clone, Debug)]
+pub struct ReceivedMessage {
+    pub note: Note,
+    pub message: Message,
+}
+
+impl Message {
+    /// Write `body` from the holder of `sender_viewing_secret` to
+    /// `recipient_viewing_pubkey`, carried by `note`.
+    pub fn new(
+        sender_viewing_secret: &SecretKey,
+        recipient_viewing_pubkey: &PublicKey,
+        note: &Note,
+        body: MessageBody,
+        sent_at: u64,
+    ) -> Result<Self> {
+        let size = serde_json::to_vec(&body)?.len();
+        ensure!(size <= MAX_MESSAGE_BYTES, "message is {size} bytes; at most {MAX_MESSAGE_BYTES} fit in a payload");
+        let mut message = Message {
+            version: MESSAGE_VERSION,
+            sender: hex::encode(sender_viewing_secret.public_key().as_bytes()),
+            sent_at,
+            body,
+            auth: String::new(),
+        };
+        let nonce = message.auth_nonce(recipient_viewing_pubkey, note)?;
+        let tag = SalsaBox::new(recipient_viewing_pubkey, sender_viewing_secret)
+            .encrypt(&nonce, &[][..])
+            .expect("encryption should not fail");
+        message.auth = hex::encode(tag);
+        Ok(message)
+    }
+
+    /// The sender's viewing pubkey.
+    pub fn sender_pubkey(&self) -> Result<PublicKey> {
+        Ok(PublicKey::from(decode_hex_fixed::<32>(&self.sender).context("invalid message sender")?))
+    }
+
+    /// Check the message came from the holder of `sender`'s viewing key and
+    /// was written for `recipient_viewing_secret` and `note`.
+    pub fn verify(&self, recipient_viewing_secret: &SecretKey, note: &Note) -> Result<()> {
+        ensure!(self.version == MESSAGE_VERSION, "unsupported message version {}", self.version);
+        let nonce = self.auth_nonce(&recipient_viewing_secret.public_key(), note)?;
+        let tag = decode_hex_fixed::<16>(&self.auth).context("invalid message auth tag")?;
+        SalsaBox::new(&self.sender_pubkey()?, recipient_viewing_secret)
+            .decrypt(&nonce, &tag[..])
+            .ok()
+            .context("message auth tag does not verify; the sender is not who it claims")?;
+        Ok(())
+    }
+
+    /// keccak256(domain || everything but the tag || recipient || commitment),
+    /// cut to a NaCl nonce. The tag is the MAC of an empty box under it.
+    fn auth_nonce(&self, recipient_viewing_pubkey: &PublicKey, note: &Note) -> Result<crypto_box::Nonce> {
+        let mut preimage = AUTH_DOMAIN.to_vec();
+        preimage.push(self.version);
+        preimage.extend_from_slice(self.sender.as_bytes());
+        preimage.extend_from_slice(&self.sent_at.to_be_bytes());
+        preimage.extend_from_slice(&serde_json::to_vec(&self.body)?);
+        preimage.extend_from_slice(recipient_viewing_pubkey.as_bytes());
+        preimage.extend_from_slice(&note.commitment());
+        let nonce: [u8; 24] = keccak256(&preimage)[..24].try_into()?;
+        Ok(crypto_box::Nonce::from(nonce))
+    }
+}
+
+/// A note worth `MESSAGE_NOTE_AMOUNT` to `recipient_pubkey`, to carry a
+/// message when no payment is due.
+pub fn message_note(recipient_pubkey: [u8; 32], blinding: [u8; 32]) -> Note {
+    Note { amount: MESSAGE_NOTE_AMOUNT, pubkey: recipient_pubkey, blinding }
+}
+
+/// Encrypt `note` with `message` attached for a recipient's viewing key.
+/// Same envelope as `encrypt_note`.
+pub fn encrypt_note_with_message(note: &Note, message: &Message, recipient_viewing_pubkey: &PublicKey) -> Result<Vec<u8>> {
+    let mut payload = note_json(note);
+    payload["message"] = serde_json::to_value(message)?;
+    Ok(seal(payload.to_string().as_bytes(), recipient_viewing_pubkey))
+}
+
+/// Decrypt a payload and its message, if it is for `viewing_secret` and
+/// carries one. A message that fails to verify is an error, not `None`.
+pub fn decrypt_message(encrypted: &[u8], viewing_secret: &SecretKey) -> Result<Option<ReceivedMessage>> {
+    let Some(plaintext) = open(encrypted, viewing_secret) else {
+        return Ok(None);
+    };
+    let payload: serde_json::Value = serde_json::from_slice(&plaintext).context("payload is not JSON")?;
+    let Some(message) = payload.get("message") else {
+        return Ok(None);
+    };
+    let note = note_from_json(&payload).context("payload carries a message but no valid note")?;
+    let message: Message = serde_json::from_value(message.clone()).context("malformed message")?;
+    message.verify(viewing_secret, &note)?;
+    Ok(Some(ReceivedMessage { note, message }))
+}
@@ -0,0 +1,238 @@
+//! Batched pool view calls via Multicall3.
+//!
+//! Checking `isSpent` / `isKnownRoot` one note at a time costs one RPC round
+//! trip per note, which is slow for large wallets and trips public endpoints'
+//! rate limits. `PoolViews` packs the calls into Multicall3 `aggregate3`
+//! batches:
+//!
+//!   - calls are sent in chunks of `chunk_size`
+//!   - a failing chunk is retried with exponential backoff and half the size
+//!   - if Multicall3 is not deployed (or a chunk keeps failing), the calls in
+//!     question fall back to plain `eth_call`s
+//!
+//! Results come back in input order.
+//!
+//! `root_history` reads the pool's whole root buffer the same way, so root
+//! membership can be answered locally (see `sync::RootHistory`).
+
+use alloy::{
+    primitives::{address, Address, FixedBytes, U256},
+    providers::Provider,
+    rpc::types::{TransactionInput, TransactionRequest},
+    sol,
+    sol_types::SolCall,
+};
+use anyhow::{bail, Context, Result};
+use shielded_pool_lib::ROOT_HISTORY_SIZE;
+use std::time::Duration;
+
+/// Canonical Multicall3 deployment (same address on every EVM chain that has one).
+pub const MULTICALL3_ADDRESS: Address = address!("cA11bde05977b3631167028862bE2a173976CA11");
+
+sol! {
+    #[sol(rpc)]
+    interface IMulticall3 {
+        struct Call3 {
+            address target;
+            bool allowFailure;
+            bytes callData;
+        }
+
+        struct Result {
+            bool success;
+            bytes returnData;
+        }
+
+        function aggregate3(Call3[] calldata calls) external payable returns (Result[] memory returnData);
+    }
+
+    interface IPoolViews {
+        function isSpent(bytes32 nullifier) external view returns (bool);
+        function isKnownRoot(bytes32 root) external view returns (bool);
+        function currentRootIndex() external view returns (uint32);
+        function roots(uint256 index) external view returns (bytes32);
+    }
+}
+
+/// Reads of the root buffer attempted before giving up on a busy pool.
+const ROOT_HISTORY_READS: u32 = 3;
+
+/// Batching knobs.
+#[derive(Clone, Debug)]
+pub struct BatchConfig {
+    /// Multicall3 contract; `None` disables batching.
+    pub multicall: Option<Address>,
+    /// Calls per `aggregate3` request.
+    pub chunk_size: usize,
+    /// Retries per chunk before falling back to single calls.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles on each further retry.
+    pub backoff: Duration,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            multicall: Some(MULTICALL3_ADDRESS),
+            chunk_size: 200,
+            max_retries: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl BatchConfig {
+    /// Defaults, overridden by:
+    ///   MULTICALL_ADDRESS     — Multicall3 address, or "none" to disable batching
+    ///   MULTICALL_CHUNK_SIZE  — calls per batch
+    pub fn from_env() -> Result<Self> {
+        let mut config = BatchConfig::default();
+        if let Ok(addr) = std::env::var("MULTICALL_ADDRESS") {
+            config.multicall = if addr.eq_ignore_ascii_case("none") {
+                None
+            } else {
+                Some(addr.parse().context("invalid MULTICALL_ADDRESS")?)
+            };
+        }
+        if let Ok(size) = std::env::var("MULTICALL_CHUNK_SIZE") {
+            config.chunk_size = size.parse().context("MULTICALL_CHUNK_SIZE must be a number")?;
+        }
+        if config.chunk_size == 0 {
+            bail!("MULTICALL_CHUNK_SIZE must be at least 1");
+        }
+        Ok(config)
+    }
+}
+
+/// Batched read-only queries against a ShieldedPool.
+pub struct PoolViews<P> {
+    provider: P,
+    pool: Address,
+    config: BatchConfig,
+}
+
+impl<P: Provider> PoolViews<P> {
+    /// Checks once whether the configured Multicall3 has code; if not,
+    /// every query uses plain `eth_call`s.
+    pub async fn new(provider: P, pool: Address, mut config: BatchConfig) -> Result<Self> {
+        if let Some(mc) = config.multicall {
+            let code = provider.get_code_at(mc).await?;
+            if code.is_empty() {
+                println!("    Multicall3 not deployed at {mc}; querying one call at a time");
+                config.multicall = None;
+            }
+        }
+        Ok(PoolViews { provider, pool, config })
+    }
+
+    /// `isSpent` for every nullifier, in order.
+    pub async fn is_spent_batch(&self, nullifiers: &[[u8; 32]]) -> Result<Vec<bool>> {
+        let calls: Vec<_> = nullifiers
+            .iter()
+            .map(|n| IPoolViews::isSpentCall { nullifier: FixedBytes::from(*n) })
+            .collect();
+        self.call_all(&calls).await
+    }
+
+    /// `isKnownRoot` for every root, in order.
+    pub async fn is_known_root_batch(&self, roots: &[[u8; 32]]) -> Result<Vec<bool>> {
+        let calls: Vec<_> = roots
+            .iter()
+            .map(|r| IPoolViews::isKnownRootCall { root: FixedBytes::from(*r) })
+            .collect();
+        self.call_all(&calls).await
+    }
+
+    /// The pool's `currentRootIndex` and all `ROOT_HISTORY_SIZE` `roots` slots.
+    ///
+    /// The slots come in one batch; `currentRootIndex` is read before and
+    /// after it, and the whole read is repeated if an insertion landed in
+    /// between.
+    pub async fn root_history(&self) -> Result<(u32, Vec<[u8; 32]>)> {
+        let slots: Vec<_> = (0..ROOT_HISTORY_SIZE)
+            .map(|i| IPoolViews::rootsCall { index: U256::from(i) })
+            .collect();
+        for _ in 0..ROOT_HISTORY_READS {
+            let before = self.call_each(&[IPoolViews::currentRootIndexCall {}]).await?[0];
+            let roots = self.call_all(&slots).await?;
+            let after = self.call_each(&[IPoolViews::currentRootIndexCall {}]).await?[0];
+            if before == after {
+                return Ok((after, roots.into_iter().map(|r| r.0).collect()));
+            }
+        }
+        bail!("pool root history changed during {ROOT_HISTORY_READS} consecutive reads")
+    }
+
+    async fn call_all<C: SolCall>(&self, calls: &[C]) -> Result<Vec<C::Return>> {
+        let Some(multicall) = self.config.multicall else {
+            return self.call_each(calls).await;
+        };
+
+        let mut out = Vec::with_capacity(calls.len());
+        let mut chunk_size = self.config.chunk_size;
+        let mut attempt = 0u32;
+        let mut pos = 0usize;
+        while pos < calls.len() {
+            let end = (pos + chunk_size).min(calls.len());
+            match self.aggregate(multicall, &calls[pos..end]).await {
+                Ok(results) => {
+                    out.extend(results);
+                    pos = end;
+                    attempt = 0;
+                }
+                Err(e) if attempt < self.config.max_retries => {
+                    let delay = self.config.backoff * 2u32.pow(attempt);
+                    attempt += 1;
+                    chunk_size = (chunk_size / 2).max(1);
+                    println!(
+                        "    Multicall batch failed ({e}); retrying in {delay:?} with {chunk_size} calls per batch"
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) => {
+                    println!("    Multicall batch failed ({e}); falling back to single calls");
+                    out.extend(self.call_each(&calls[pos..end]).await?);
+                    pos = end;
+                    attempt = 0;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    async fn aggregate<C: SolCall>(&self, multicall: Address, calls: &[C]) -> Result<Vec<C::Return>> {
+        let batch: Vec<IMulticall3::Call3> = calls
+            .iter()
+            .map(|c| IMulticall3::Call3 {
+                target: self.pool,
+                allowFailure: false,
+                callData: c.abi_encode().into(),
+            })
+            .collect();
+        let results = IMulticall3::new(multicall, &self.provider).aggregate3(batch).call().await?;
+        if results.len() != calls.len() {
+            bail!("multicall returned {} results for {} calls", results.len(), calls.len());
+        }
+        results
+            .iter()
+            .map(|r| {
+                if !r.success {
+                    bail!("{} reverted inside multicall", C::SIGNATURE);
+                }
+                Ok(C::abi_decode_returns(&r.returnData)?)
+            })
+            .collect()
+    }
+
+    async fn call_each<C: SolCall>(&self, calls: &[C]) -> Result<Vec<C::Return>> {
+        let mut out = Vec::with_capacity(calls.len());
+        for c in calls {
+            let tx = TransactionRequest::default()
+                .to(self.pool)
+                .input(TransactionInput::new(c.abi_encode().into()));
+            let data = self.provider.call(tx).await?;
+            out.push(C::abi_decode_returns(&data)?);
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,72 @@
+//! On-disk cache of generated Groth16 proofs.
+//!
+//! A proof on the prover network takes minutes and is paid for, and a flow
+//! that fails after proving (an RPC error while submitting, a root that
+//! moved) used to throw it away. `prove` here keeps every real proof under
+//! `~/.cache/shielded-pool/proofs/` (`$XDG_CACHE_HOME/shielded-pool/proofs/`
+//! when that is set, or `PROOF_CACHE_DIR`), as JSON in a file named after the
+//! pinned SP1 version, the sha256 of the ELF and the sha256 of the serialized
+//! inputs. Proving the same inputs with the same guest again returns the
+//! stored proof instead.
+//!
+//! Only identical inputs hit the cache. Flows that build their inputs from
+//! a wallet and a tree (`transfer --input`, `exit`, the withdrawals of a
+//! sweep) rebuild the same ones on a re-run, as long as the root has not
+//! moved; a note with a fresh blinding, as e2e creates on every run, is a
+//! new request. Mock proofs are never stored, so a network run cannot pick
+//! one up. Like the key cache, this is only an optimisation: an unreadable
+//! entry is proved again and replaced. `PROOF_CACHE_DIR=off` disables it.
+
+use anyhow::Result;
+use sp1_sdk::{EnvProver, SP1ProofWithPublicValues, SP1ProvingKey, SP1Stdin};
+use std::path::PathBuf;
+
+use crate::key_cache::{user_cache_dir, write_entry};
+use crate::reproduce::{sha256_hex, SP1_DOCKER_TAG};
+
+/// The cache directory, or `None` when disabled or there is no home to put
+/// it in.
+pub fn cache_dir() -> Option<PathBuf> {
+    user_cache_dir("PROOF_CACHE_DIR", "proofs")
+}
+
+/// Where the proof of `stdin` for `elf` is cached.
+fn entry_path(dir: PathBuf, elf: &[u8], stdin: &SP1Stdin) -> Result<PathBuf> {
+    let inputs = bincode::serialize(stdin)?;
+    Ok(dir.join(format!("{}-{}-{}.json", SP1_DOCKER_TAG, sha256_hex(elf), sha256_hex(&inputs))))
+}
+
+/// `client.prove(pk, stdin).groth16().run()`, from the cache when this
+/// guest already proved these inputs.
+pub fn prove(
+    client: &EnvProver,
+    pk: &SP1ProvingKey,
+    elf: &[u8],
+    stdin: &SP1Stdin,
+) -> Result<SP1ProofWithPublicValues> {
+    let Some(path) = cache_dir().map(|dir| entry_path(dir, elf, stdin)).transpose()? else {
+        return client.prove(pk, stdin).groth16().run();
+    };
+
+    if let Ok(json) = std::fs::read(&path) {
+        match serde_json::from_slice::<SP1ProofWithPublicValues>(&json) {
+            Ok(proof) => {
+                println!("    Reusing cached proof {}", path.display());
+                return Ok(proof);
+            }
+            Err(e) => eprintln!("⚠ Ignoring unreadable proof cache entry {}: {}", path.display(), e),
+        }
+    }
+
+    let proof = client.prove(pk, stdin).groth16().run()?;
+    // A mock proof has no bytes and would not verify on-chain
+    if !proof.bytes().is_empty() {
+        let written = serde_json::to_vec(&proof)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_entry(&path, &json));
+        if let Err(e) = written {
+            eprintln!("⚠ Could not cache the proof in {}: {}", path.display(), e);
+        }
+    }
+    Ok(proof)
+}
@@ -0,0 +1,105 @@
+//! Groth16 proofs of independent jobs, generated concurrently.
+//!
+//! Flows that spend several notes against the same root (the exit, a sweep,
+//! a payroll run) used to prove one note after the other, although none of
+//! the proofs depends on another. `prove_all` hands the jobs to a bounded
+//! number of workers instead and reports each proof to the caller as it
+//! completes, in completion order, on the calling thread. The caller
+//! journals or submits it from there, so a proof is never lost because a
+//! later one failed.
+//!
+//! How many proofs run at once is `PROVER_JOBS`. By default it is 4 with
+//! `SP1_PROVER=network`, where the work happens remotely, and 1 otherwise,
+//! as a local prover already uses every core and most of the memory for a
+//! single proof.
+//!
+//! Each job goes through the proof cache, so a re-run reuses whatever
+//! proofs an earlier, failed run already generated.
+//!
+//! When a job fails, no further jobs start. The ones already running still
+//! finish and are reported, and then the first error is returned.
+
+use anyhow::{Context, Result};
+use sp1_sdk::{EnvProver, SP1ProofWithPublicValues, SP1ProvingKey, SP1Stdin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Instant;
+
+use crate::proof_cache;
+
+/// One proof to generate.
+pub struct ProofJob<'a> {
+    /// Shown in progress lines and errors
+    pub label: String,
+    pub pk: &'a SP1ProvingKey,
+    /// The guest `pk` was set up for, which keys the proof cache
+    pub elf: &'a [u8],
+    pub stdin: SP1Stdin,
+}
+
+/// Proofs generated at once: `PROVER_JOBS`, or the prover's default.
+pub fn parallelism() -> Result<usize> {
+    match std::env::var("PROVER_JOBS") {
+        Ok(jobs) => {
+            let jobs: usize = jobs.parse().context("PROVER_JOBS must be a number")?;
+            Ok(jobs.max(1))
+        }
+        Err(_) if std::env::var("SP1_PROVER").is_ok_and(|p| p == "network") => Ok(4),
+        Err(_) => Ok(1),
+    }
+}
+
+/// Prove every job, at most `parallelism()` at a time, calling `on_proof`
+/// with the job's index and its proof as each completes.
+pub fn prove_all<F>(client: &EnvProver, jobs: Vec<ProofJob<'_>>, mut on_proof: F) -> Result<()>
+where
+    F: FnMut(usize, SP1ProofWithPublicValues) -> Result<()>,
+{
+    let total = jobs.len();
+    if total == 0 {
+        return Ok(());
+    }
+    let workers = parallelism()?.min(total);
+    println!("Generating {} Groth16 proof(s), {} at a time...", total, workers);
+
+    let queue = Mutex::new(jobs.into_iter().enumerate());
+    let stop = AtomicBool::new(false);
+    let (queue, stop) = (&queue, &stop);
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            scope.spawn(move || loop {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let Some((index, job)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                println!("    [{}] proving...", job.label);
+                let started = Instant::now();
+                let proof = proof_cache::prove(client, job.pk, job.elf, &job.stdin);
+                if tx.send((index, job.label, proof, started.elapsed())).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        let mut first_error = None;
+        for (done, (index, label, proof, elapsed)) in (1..).zip(rx) {
+            let reported = proof
+                .with_context(|| format!("proving {label} failed"))
+                .and_then(|proof| {
+                    println!("    [{}] proved in {:.0?} ({}/{})", label, elapsed, done, total);
+                    on_proof(index, proof)
+                });
+            if let Err(e) = reported {
+                stop.store(true, Ordering::Relaxed);
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    })
+}
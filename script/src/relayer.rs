@@ -0,0 +1,411 @@
+//! What a relayer checks before paying gas for someone else's proof.
+//!
+//! The `relayer` binary submits transfers and withdrawals from its own funded
+//! key, so users of the pool need neither gas nor a public address tied to
+//! their notes. Anyone can post it a `Submission`, and a submission the pool
+//! rejects still costs the relayer its gas, so `RelayPolicy::check` runs
+//! every check that needs no chain state first:
+//!   - the public values decode as a transfer (320 bytes) or a withdrawal
+//!     (416 bytes), for this pool and this tree's depth
+//!   - the Groth16 proof verifies against the pool's vkey for that circuit
+//!   - the posted encrypted outputs are the ones a transfer commits
+//!   - deadlines leave time for the transaction to be mined
+//!   - a withdrawal pays this relayer at least its minimum fee; transfers
+//!     commit no fee, so they are only relayed when the minimum is zero
+//!
+//! The binary then checks the root and nullifiers against the pool and
+//! simulates the call before sending it.
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+use shielded_pool_lib::{note_encryption::ciphertexts_hash, TransferPublicValues, WithdrawPublicValues};
+
+use crate::decode_hex;
+
+/// Largest encrypted output relayed. Real payloads are a few hundred bytes;
+/// the pool stores whatever is posted, at the relayer's expense.
+pub const MAX_ENCRYPTED_OUTPUT_LEN: usize = 1024;
+
+/// Deadlines closer than this (in seconds) are refused, as the transaction
+/// could be mined after them and revert.
+pub const MIN_DEADLINE_SLACK: u64 = 60;
+
+/// A proof posted to the relayer, hex strings 0x-prefixed or not. A transfer
+/// carries its two encrypted outputs; a withdrawal its encrypted change, if
+/// any.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Submission {
+    pub proof: String,
+    pub public_values: String,
+    #[serde(default)]
+    pub encrypted_outputs: Vec<String>,
+}
+
+/// What the relayer accepts.
+#[derive(Clone, Debug)]
+pub struct RelayPolicy {
+    /// The relayer's address, which withdrawals must name to pay it a fee
+    pub relayer: [u8; 20],
+    /// Smallest fee taken, in USDT base units
+    pub min_fee: u64,
+    pub pool_binding: [u8; 32],
+    pub tree_levels: u64,
+    /// The pool's vkeys, as `bytes32` hex
+    pub transfer_vkey: String,
+    pub withdraw_vkey: String,
+    /// Relay mock proofs (`SP1_PROVER=mock`) without verifying them, for a
+    /// pool on the mock verifier
+    pub mock_proofs: bool,
+}
+
+/// A submission that passed `RelayPolicy::check`.
+#[derive(Clone, Debug)]
+pub enum Relay {
+    Transfer {
+        proof: Vec<u8>,
+        public_values: Vec<u8>,
+        values: TransferPublicValues,
+        encrypted_outputs: [Vec<u8>; 2],
+    },
+    Withdraw {
+        proof: Vec<u8>,
+        public_values: Vec<u8>,
+        values: WithdrawPublicValues,
+        encrypted_change: Vec<u8>,
+    },
+}
+
+impl Relay {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Relay::Transfer { .. } => "transfer",
+            Relay::Withdraw { .. } => "withdraw",
+        }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        match self {
+            Relay::Transfer { values, .. } => values.root,
+            Relay::Withdraw { values, .. } => values.root,
+        }
+    }
+
+    pub fn nullifiers(&self) -> Vec<[u8; 32]> {
+        match self {
+            Relay::Transfer { values, .. } => values.nullifiers.to_vec(),
+            Relay::Withdraw { values, .. } => vec![values.nullifier],
+        }
+    }
+
+    /// The fee the relayer is paid (0 for transfers).
+    pub fn fee(&self) -> u64 {
+        match self {
+            Relay::Transfer { .. } => 0,
+            Relay::Withdraw { values, .. } => values.fee,
+        }
+    }
+}
+
+impl RelayPolicy {
+    /// Check `submission` as of the chain time `now`.
+    pub fn check(&self, submission: &Submission, now: u64) -> Result<Relay> {
+        let proof = decode_hex(&submission.proof).context("proof must be hex")?;
+        let public_values = decode_hex(&submission.public_values).context("publicValues must be hex")?;
+        ensure!(!proof.is_empty() || self.mock_proofs, "mock proofs (SP1_PROVER=mock) are not relayed");
+        let encrypted_outputs = submission
+            .encrypted_outputs
+            .iter()
+            .map(|output| decode_hex(output).context("encryptedOutputs must be hex"))
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            encrypted_outputs.iter().all(|output| output.len() <= MAX_ENCRYPTED_OUTPUT_LEN),
+            "encrypted outputs are limited to {MAX_ENCRYPTED_OUTPUT_LEN} bytes"
+        );
+
+        match public_values.len() {
+            TransferPublicValues::LEN => self.check_transfer(proof, public_values, encrypted_outputs, now),
+            WithdrawPublicValues::LEN => self.check_withdraw(proof, public_values, encrypted_outputs, now),
+            len => bail!(
+                "publicValues are {len} bytes; a transfer's are {} and a withdrawal's {}",
+                TransferPublicValues::LEN,
+                WithdrawPublicValues::LEN
+            ),
+        }
+    }
+
+    fn check_transfer(
+        &self,
+        proof: Vec<u8>,
+        public_values: Vec<u8>,
+        encrypted_outputs: Vec<Vec<u8>>,
+        now: u64,
+    ) -> Result<Relay> {
+        let values = TransferPublicValues::decode(&public_values).context("malformed transfer public values")?;
+        ensure!(values.pool_binding == self.pool_binding, "the transfer is bound to another pool");
+        ensure!(
+            values.tree_levels == self.tree_levels,
+            "the transfer proves {} levels, the pool's tree has {}",
+            values.tree_levels,
+            self.tree_levels
+        );
+        ensure!(values.nullifiers[0] != values.nullifiers[1], "the transfer spends one nullifier twice");
+        check_deadline("reclaim deadline", values.reclaim_deadline, now)?;
+        ensure!(self.min_fee == 0, "transfers commit no relayer fee, and this relayer charges one");
+
+        let encrypted_outputs: [Vec<u8>; 2] = encrypted_outputs.try_into().map_err(|outputs: Vec<_>| {
+            anyhow::anyhow!("a transfer carries 2 encrypted outputs, not {}", outputs.len())
+        })?;
+        ensure!(
+            values.ciphertexts_hash == [0u8; 32]
+                || values.ciphertexts_hash == ciphertexts_hash([&encrypted_outputs[0], &encrypted_outputs[1]]),
+            "the encrypted outputs are not the ones the proof commits"
+        );
+        verify(&proof, &public_values, &self.transfer_vkey, "transfer")?;
+        Ok(Relay::Transfer { proof, public_values, values, encrypted_outputs })
+    }
+
+    fn check_withdraw(
+        &self,
+        proof: Vec<u8>,
+        public_values: Vec<u8>,
+        mut encrypted_outputs: Vec<Vec<u8>>,
+        now: u64,
+    ) -> Result<Relay> {
+        let values = WithdrawPublicValues::decode(&public_values).context("malformed withdraw public values")?;
+        ensure!(values.pool_binding == self.pool_binding, "the withdrawal is bound to another pool");
+        ensure!(
+            values.tree_levels == self.tree_levels,
+            "the withdrawal proves {} levels, the pool's tree has {}",
+            values.tree_levels,
+            self.tree_levels
+        );
+        ensure!(values.recipient != [0u8; 20], "the withdrawal pays the zero address");
+        ensure!(values.call_binding == [0u8; 32], "withdraw-and-call proofs are not relayed");
+        check_deadline("deadline", values.deadline, now)?;
+        check_deadline("reclaim deadline", values.reclaim_deadline, now)?;
+        if self.min_fee > 0 || values.fee > 0 {
+            ensure!(
+                values.relayer == self.relayer,
+                "the withdrawal pays its fee to 0x{}, not this relayer (0x{})",
+                hex::encode(values.relayer),
+                hex::encode(self.relayer)
+            );
+        }
+        ensure!(
+            values.fee >= self.min_fee,
+            "the fee is {} USDT, this relayer takes at least {} USDT",
+            (values.fee as f64) / 1e6,
+            (self.min_fee as f64) / 1e6
+        );
+
+        ensure!(
+            encrypted_outputs.len() <= 1,
+            "a withdrawal carries at most 1 encrypted output (its change), not {}",
+            encrypted_outputs.len()
+        );
+        let encrypted_change = encrypted_outputs.pop().unwrap_or_default();
+        verify(&proof, &public_values, &self.withdraw_vkey, "withdrawal")?;
+        Ok(Relay::Withdraw { proof, public_values, values, encrypted_change })
+    }
+}
+
+/// A committed deadline (0 = none) must leave `MIN_DEADLINE_SLACK` seconds.
+fn check_deadline(what: &str, deadline: u64, now: u64) -> Result<()> {
+    ensure!(
+        deadline == 0 || deadline >= now.saturating_add(MIN_DEADLINE_SLACK),
+        "the {what} {deadline} has passed or is less than {MIN_DEADLINE_SLACK}s away"
+    );
+    Ok(())
+}
+
+/// The Groth16 check the pool's verifier makes, done locally. An empty proof
+/// is a mock one, which only reaches here when the policy relays those.
+fn verify(proof: &[u8], public_values: &[u8], vkey: &str, what: &str) -> Result<()> {
+    if proof.is_empty() {
+        return Ok(());
+    }
+    sp1_verifier::Groth16Verifier::verify(proof, public_values, vkey, *sp1_verifier::GROTH16_VK_BYTES)
+        .map_err(|e| anyhow::anyhow!("the {what} proof does not verify against the pool's vkey: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    /// Breaks one rule of otherwise relayable public values.
+    type BreakTransfer = fn(&mut TransferPublicValues);
+    type BreakWithdraw = fn(&mut WithdrawPublicValues);
+
+    fn policy(min_fee: u64) -> RelayPolicy {
+        RelayPolicy {
+            relayer: [0xaa; 20],
+            min_fee,
+            pool_binding: [7u8; 32],
+            tree_levels: 20,
+            transfer_vkey: format!("0x{}", "11".repeat(32)),
+            withdraw_vkey: format!("0x{}", "22".repeat(32)),
+            mock_proofs: false,
+        }
+    }
+
+    fn transfer() -> TransferPublicValues {
+        TransferPublicValues {
+            root: [1u8; 32],
+            nullifiers: [[2u8; 32], [3u8; 32]],
+            out_commitments: [[4u8; 32], [5u8; 32]],
+            reclaim_deadline: 0,
+            association_root: [0u8; 32],
+            pool_binding: [7u8; 32],
+            ciphertexts_hash: ciphertexts_hash([b"output 0", b"output 1"]),
+            tree_levels: 20,
+        }
+    }
+
+    fn withdraw() -> WithdrawPublicValues {
+        WithdrawPublicValues {
+            root: [1u8; 32],
+            nullifier: [2u8; 32],
+            recipient: [0x42; 20],
+            amount: 900_000,
+            change_commitment: [0u8; 32],
+            reclaim_deadline: 0,
+            relayer: [0xaa; 20],
+            fee: 100_000,
+            association_root: [0u8; 32],
+            pool_binding: [7u8; 32],
+            deadline: NOW + 3_600,
+            call_binding: [0u8; 32],
+            tree_levels: 20,
+        }
+    }
+
+    fn submission(public_values: &[u8], encrypted_outputs: &[&[u8]]) -> Submission {
+        Submission {
+            proof: format!("0x{}", hex::encode([0x99; 260])),
+            public_values: format!("0x{}", hex::encode(public_values)),
+            encrypted_outputs: encrypted_outputs.iter().map(hex::encode).collect(),
+        }
+    }
+
+    fn transfer_submission(values: &TransferPublicValues) -> Submission {
+        submission(&values.encode(), &[b"output 0", b"output 1"])
+    }
+
+    fn rejection(policy: &RelayPolicy, submission: &Submission) -> String {
+        format!("{:#}", policy.check(submission, NOW).unwrap_err())
+    }
+
+    /// Every check but the Groth16 one passed: the fake proof fails there.
+    fn reaches_verification(policy: &RelayPolicy, submission: &Submission) -> bool {
+        rejection(policy, submission).contains("does not verify against the pool's vkey")
+    }
+
+    #[test]
+    fn test_check_rejects_malformed_submissions() {
+        let mut bad_proof = transfer_submission(&transfer());
+        bad_proof.proof = "0xzz".into();
+        assert!(rejection(&policy(0), &bad_proof).contains("proof must be hex"));
+
+        let mut bad_values = transfer_submission(&transfer());
+        bad_values.public_values = "not hex".into();
+        assert!(rejection(&policy(0), &bad_values).contains("publicValues must be hex"));
+
+        let mut mock = transfer_submission(&transfer());
+        mock.proof = "0x".into();
+        assert!(rejection(&policy(0), &mock).contains("mock proofs"));
+
+        let oversized = [0u8; MAX_ENCRYPTED_OUTPUT_LEN + 1];
+        let huge = submission(&transfer().encode(), &[b"output 0", &oversized]);
+        assert!(rejection(&policy(0), &huge).contains("limited to 1024 bytes"));
+
+        let short = submission(&[0u8; 288], &[]);
+        assert!(rejection(&policy(0), &short).contains("publicValues are 288 bytes"));
+    }
+
+    #[test]
+    fn test_check_relays_mock_proofs_only_for_a_mock_pool() {
+        let mut mock = transfer_submission(&transfer());
+        mock.proof = "0x".into();
+        let mock_pool = RelayPolicy { mock_proofs: true, ..policy(0) };
+        assert_eq!(mock_pool.check(&mock, NOW).unwrap().kind(), "transfer");
+        // The other checks still apply
+        let other_pool = TransferPublicValues { pool_binding: [8u8; 32], ..transfer() };
+        let mut misbound = transfer_submission(&other_pool);
+        misbound.proof = "0x".into();
+        assert!(rejection(&mock_pool, &misbound).contains("bound to another pool"));
+        // A real proof is still verified
+        assert!(reaches_verification(&mock_pool, &transfer_submission(&transfer())));
+    }
+
+    #[test]
+    fn test_check_rejects_transfers_it_should_not_pay_for() {
+        assert!(reaches_verification(&policy(0), &transfer_submission(&transfer())));
+
+        let cases: [(BreakTransfer, &str); 4] = [
+            (|t| t.pool_binding = [8u8; 32], "bound to another pool"),
+            (|t| t.tree_levels = 19, "proves 19 levels, the pool's tree has 20"),
+            (|t| t.nullifiers[1] = t.nullifiers[0], "spends one nullifier twice"),
+            (|t| t.reclaim_deadline = NOW + MIN_DEADLINE_SLACK - 1, "reclaim deadline"),
+        ];
+        for (break_rule, message) in cases {
+            let mut values = transfer();
+            break_rule(&mut values);
+            let error = rejection(&policy(0), &transfer_submission(&values));
+            assert!(error.contains(message), "{error}");
+        }
+
+        // Transfers commit no fee, so a relayer that charges one refuses them
+        let error = rejection(&policy(1), &transfer_submission(&transfer()));
+        assert!(error.contains("transfers commit no relayer fee"), "{error}");
+
+        let one_output = submission(&transfer().encode(), &[b"output 0"]);
+        assert!(rejection(&policy(0), &one_output).contains("carries 2 encrypted outputs, not 1"));
+
+        let swapped = submission(&transfer().encode(), &[b"output 1", b"output 0"]);
+        assert!(rejection(&policy(0), &swapped).contains("not the ones the proof commits"));
+
+        // Unproven payloads commit a zero hash and are posted as given
+        let unproven = TransferPublicValues { ciphertexts_hash: [0u8; 32], ..transfer() };
+        assert!(reaches_verification(&policy(0), &submission(&unproven.encode(), &[b"any", b"thing"])));
+    }
+
+    #[test]
+    fn test_check_rejects_withdrawals_it_should_not_pay_for() {
+        let policy = policy(50_000);
+        assert!(reaches_verification(&policy, &submission(&withdraw().encode(), &[])));
+        assert!(reaches_verification(&policy, &submission(&withdraw().encode(), &[b"change"])));
+
+        let cases: [(BreakWithdraw, &str); 8] = [
+            (|w| w.pool_binding = [8u8; 32], "bound to another pool"),
+            (|w| w.tree_levels = 32, "proves 32 levels, the pool's tree has 20"),
+            (|w| w.recipient = [0u8; 20], "pays the zero address"),
+            (|w| w.call_binding = [3u8; 32], "withdraw-and-call proofs are not relayed"),
+            (|w| w.deadline = NOW + MIN_DEADLINE_SLACK - 1, "the deadline"),
+            (|w| w.reclaim_deadline = NOW - 1, "the reclaim deadline"),
+            (|w| w.relayer = [0xbb; 20], "pays its fee to 0xbbbb"),
+            (|w| w.fee = 49_999, "the fee is 0.049999 USDT, this relayer takes at least 0.05 USDT"),
+        ];
+        for (break_rule, message) in cases {
+            let mut values = withdraw();
+            break_rule(&mut values);
+            let error = rejection(&policy, &submission(&values.encode(), &[]));
+            assert!(error.contains(message), "{error}");
+        }
+
+        let two_changes = submission(&withdraw().encode(), &[b"change", b"more"]);
+        assert!(rejection(&policy, &two_changes).contains("at most 1 encrypted output"));
+    }
+
+    #[test]
+    fn test_check_without_a_fee_takes_any_relayer() {
+        // A free relayer takes a fee-less withdrawal whoever it names
+        let free = WithdrawPublicValues { fee: 0, relayer: [0u8; 20], deadline: 0, ..withdraw() };
+        assert!(reaches_verification(&policy(0), &submission(&free.encode(), &[])));
+        // but a fee must still be paid to it
+        let misdirected = WithdrawPublicValues { relayer: [0xbb; 20], ..withdraw() };
+        assert!(rejection(&policy(0), &submission(&misdirected.encode(), &[])).contains("pays its fee to 0xbbbb"));
+    }
+}
@@ -0,0 +1,149 @@
+//! Reproducible guest builds and vkey checks.
+//!
+//! Anyone verifying a deployed pool wants to know that the vkeys baked into
+//! the contract come from the published guest source. `reproduce` rebuilds a
+//! guest inside SP1's pinned Docker image, hashes the ELF, derives its vkey
+//! and compares it against:
+//!
+//!   - the vkey registry checked into the repo (deploy/vkeys.json)
+//!   - the ELF embedded in this binary at build time
+//!   - the TRANSFER_VKEY / WITHDRAW_VKEY / DEPOSIT_VKEY immutables of a
+//!     deployed pool
+//!
+//! Docker builds are byte-for-byte reproducible across machines; local
+//! toolchain builds generally are not.
+
+use alloy::{primitives::Address, providers::ProviderBuilder, sol};
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// SP1 Docker image tag used for reproducible builds. Must match the pinned sp1-sdk.
+pub const SP1_DOCKER_TAG: &str = "v5.2.4";
+
+sol! {
+    #[sol(rpc)]
+    interface IShieldedPoolVkeys {
+        function TRANSFER_VKEY() external view returns (bytes32);
+        function WITHDRAW_VKEY() external view returns (bytes32);
+        function DEPOSIT_VKEY() external view returns (bytes32);
+        function JOIN_SPLIT_VKEY() external view returns (bytes32);
+        function BATCH_WITHDRAW_VKEY() external view returns (bytes32);
+        function CONSOLIDATE_VKEY() external view returns (bytes32);
+        function PAYOUT_VKEY() external view returns (bytes32);
+        function POLICY_SPEND_VKEY() external view returns (bytes32);
+    }
+}
+
+/// Published vkeys (and ELF hashes) per circuit.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct VkeyRegistry {
+    /// SP1 Docker tag the entries were built with
+    pub sp1_docker_tag: String,
+    /// Keyed by circuit name ("transfer", "withdraw")
+    pub circuits: BTreeMap<String, RegistryEntry>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    /// 0x-prefixed bytes32 vkey as passed to the pool constructor
+    pub vkey: String,
+    /// Hex-encoded SHA-256 of the guest ELF (empty if not recorded yet)
+    #[serde(default)]
+    pub elf_sha256: String,
+}
+
+impl VkeyRegistry {
+    /// deploy/vkeys.json at the repo root.
+    pub fn default_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("deploy/vkeys.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read vkey registry: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+}
+
+/// Rebuild `programs/<circuit>` in SP1's Docker image and return the ELF bytes.
+pub fn build_guest_reproducibly(circuit: &str, out_dir: &Path) -> Result<Vec<u8>> {
+    let program_dir = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("programs").join(circuit);
+    ensure!(program_dir.is_dir(), "no guest program at {}", program_dir.display());
+    std::fs::create_dir_all(out_dir)?;
+    let elf_name = format!("{circuit}-program");
+
+    let status = Command::new("cargo")
+        .args(["prove", "build", "--docker", "--locked", "--tag", SP1_DOCKER_TAG, "--elf-name"])
+        .arg(&elf_name)
+        .arg("--output-directory")
+        .arg(out_dir)
+        .current_dir(&program_dir)
+        .status()
+        .context("failed to run `cargo prove build` (is the SP1 toolchain installed?)")?;
+    if !status.success() {
+        bail!("`cargo prove build --docker` failed for {circuit} ({status})");
+    }
+
+    let elf_path = out_dir.join(&elf_name);
+    std::fs::read(&elf_path).with_context(|| format!("rebuilt ELF not found at {}", elf_path.display()))
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Read the vkey a deployed pool verifies `circuit` proofs against.
+pub async fn onchain_vkey(rpc_url: &str, pool: Address, circuit: &str) -> Result<String> {
+    let provider = ProviderBuilder::new().connect_http(rpc_url.parse()?);
+    let pool = IShieldedPoolVkeys::new(pool, &provider);
+    let vkey = match circuit {
+        "transfer" => pool.TRANSFER_VKEY().call().await?,
+        "withdraw" => pool.WITHDRAW_VKEY().call().await?,
+        "deposit" => pool.DEPOSIT_VKEY().call().await?,
+        "join-split" => pool.JOIN_SPLIT_VKEY().call().await?,
+        "batch-withdraw" => pool.BATCH_WITHDRAW_VKEY().call().await?,
+        "consolidate" => pool.CONSOLIDATE_VKEY().call().await?,
+        "payout" => pool.PAYOUT_VKEY().call().await?,
+        "policy-spend" => pool.POLICY_SPEND_VKEY().call().await?,
+        _ => bail!("unknown circuit {circuit}"),
+    };
+    Ok(vkey.to_string())
+}
+
+/// One line of the reproducibility report.
+pub struct Check {
+    pub name: &'static str,
+    pub expected: String,
+    /// `None` when the reference was unavailable
+    pub matches: Option<bool>,
+}
+
+/// Print the checks and return whether every available one passed.
+pub fn print_verdict(circuit: &str, checks: &[Check]) -> bool {
+    println!("\n[{circuit}] Reproducibility report");
+    for c in checks {
+        let mark = match c.matches {
+            Some(true) => "✓ match",
+            Some(false) => "✗ MISMATCH",
+            None => "- skipped",
+        };
+        println!("    {:<22} {:<11} {}", c.name, mark, c.expected);
+    }
+    let ok = checks.iter().all(|c| c.matches != Some(false));
+    let any = checks.iter().any(|c| c.matches.is_some());
+    match (ok, any) {
+        (true, true) => println!("[{circuit}] Verdict: REPRODUCIBLE"),
+        (true, false) => println!("[{circuit}] Verdict: INCONCLUSIVE (nothing to compare against)"),
+        (false, _) => println!("[{circuit}] Verdict: NOT REPRODUCIBLE"),
+    }
+    ok
+}
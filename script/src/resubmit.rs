@@ -0,0 +1,89 @@
+//! Replacing a stuck transaction with the same one at higher fees.
+//!
+//! A withdrawal's proof takes minutes to generate, but its transaction can
+//! sit unmined for good when its fees were too low. No new proof is needed:
+//! the proof and public values stay valid while the root they commit to is
+//! in the pool's history. The transaction is resent with the same nonce,
+//! recipient and calldata and with higher fees, which replaces the stuck one
+//! in the mempool. Nodes only accept a replacement that raises both the fee
+//! cap and the tip, by at least 10% on geth, reth and anvil.
+//!
+//! `FEE_BUMP_PERCENT` sets the raise (default 25, 0 to never resend).
+
+use alloy::{
+    consensus::Transaction as TxFields,
+    network::{TransactionBuilder, TransactionResponse},
+    primitives::Address,
+    providers::{PendingTransactionBuilder, Provider},
+    rpc::types::{Transaction, TransactionRequest},
+};
+use anyhow::{bail, ensure, Context, Result};
+
+/// Default fee raise of a replacement, in percent.
+pub const DEFAULT_FEE_BUMP_PERCENT: u64 = 25;
+
+/// Smallest raise nodes accept for a replacement, in percent.
+pub const MIN_FEE_BUMP_PERCENT: u64 = 10;
+
+/// `FEE_BUMP_PERCENT` if set, otherwise the default. `None` when resending
+/// is disabled (0).
+pub fn fee_bump_from_env() -> Result<Option<u64>> {
+    let percent = match std::env::var("FEE_BUMP_PERCENT") {
+        Ok(s) => s.parse().context("FEE_BUMP_PERCENT must be a number")?,
+        Err(_) => DEFAULT_FEE_BUMP_PERCENT,
+    };
+    if percent == 0 {
+        return Ok(None);
+    }
+    ensure!(
+        percent >= MIN_FEE_BUMP_PERCENT,
+        "FEE_BUMP_PERCENT must be 0 or at least {MIN_FEE_BUMP_PERCENT} (nodes reject smaller replacements)"
+    );
+    Ok(Some(percent))
+}
+
+/// `fee` raised by `percent`, rounded up.
+pub fn bump_fee(fee: u128, percent: u64) -> u128 {
+    fee.saturating_add((fee.saturating_mul(percent as u128)).div_ceil(100))
+}
+
+/// Resend `stuck`, a pending transaction sent by `from`, with the same
+/// nonce, recipient, value, calldata and gas limit and its fees raised by
+/// `percent`. Fees are never below the network's current estimate.
+pub async fn replace_with_higher_fees<P: Provider>(
+    provider: &P,
+    from: Address,
+    stuck: &Transaction,
+    percent: u64,
+) -> Result<PendingTransactionBuilder<alloy::network::Ethereum>> {
+    let hash = stuck.tx_hash();
+    ensure!(stuck.block_number.is_none(), "tx {hash} is already mined");
+    ensure!(stuck.from() == from, "tx {hash} was sent by {}, not by this wallet ({from})", stuck.from());
+    let to = stuck.to().with_context(|| format!("tx {hash} creates a contract"))?;
+
+    let mut request = TransactionRequest::default()
+        .with_from(from)
+        .with_to(to)
+        .with_nonce(stuck.nonce())
+        .with_value(stuck.value())
+        .with_input(stuck.input().clone())
+        .with_gas_limit(stuck.gas_limit());
+    if stuck.is_dynamic_fee() {
+        let estimate = provider.estimate_eip1559_fees().await?;
+        let tip = bump_fee(stuck.max_priority_fee_per_gas().unwrap_or(0), percent)
+            .max(estimate.max_priority_fee_per_gas);
+        let cap = bump_fee(TxFields::max_fee_per_gas(stuck), percent).max(estimate.max_fee_per_gas).max(tip);
+        request = request.with_max_fee_per_gas(cap).with_max_priority_fee_per_gas(tip);
+    } else {
+        let Some(price) = TxFields::gas_price(stuck) else {
+            bail!("tx {hash} has no gas price");
+        };
+        let price = bump_fee(price, percent).max(provider.get_gas_price().await?);
+        request = request.with_gas_price(price);
+    }
+
+    provider
+        .send_transaction(request)
+        .await
+        .with_context(|| format!("replacement for tx {hash} was rejected (was the original mined meanwhile?)"))
+}
@@ -0,0 +1,128 @@
+//! Strict mode: fail instead of falling back.
+//!
+//! The host binaries are forgiving by default. They skip notes they cannot
+//! spend yet, take a partial sync, and read a missing block number as 0. An
+//! operator watching the output can tell when that happened; unattended
+//! automation cannot. In strict mode every such fallback is a hard
+//! `StrictViolation` error instead.
+//!
+//! Strict mode is on when any of these hold:
+//!   - `--strict` is passed
+//!   - STRICT=1 (or true) is set
+//!   - CI is set and STRICT is not 0 (or false)
+
+use anyhow::Result;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// A state the binaries would otherwise work around.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StrictViolation {
+    /// An RPC returned a log or receipt without its block number
+    MissingBlockNumber { what: String },
+    /// An RPC returned a log without its index in the block
+    MissingLogIndex { what: String },
+    /// A withdrawal's transaction could not be fetched or decoded, so its
+    /// change commitment is unknown
+    UnreadableWithdrawal { tx_hash: String },
+    /// Only part of the local tree matches a root the pool accepts
+    PartialSync { verified_leaves: u32, on_chain_leaves: u32 },
+    /// A wallet note has no spending key
+    NoSpendingKey { label: String },
+    /// A note's owner has no viewing key, so nobody else can find the note
+    MissingViewingKey { owner: String },
+    /// An earlier transaction for a note is still pending
+    PendingTransaction { tx_hash: String },
+    /// The root proofs were built against has left the pool's history
+    RootNoLongerKnown,
+    /// A spending key is stored unencrypted
+    PlaintextKey { label: String },
+    /// A balance needed for a cross-check could not be queried
+    BalanceUnavailable { what: String },
+    /// Only the offline half of a verification ran
+    OnChainChecksSkipped,
+}
+
+impl fmt::Display for StrictViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingBlockNumber { what } => write!(f, "{what} has no block number"),
+            Self::MissingLogIndex { what } => write!(f, "{what} has no log index"),
+            Self::UnreadableWithdrawal { tx_hash } => {
+                write!(f, "withdrawal tx {tx_hash} could not be read; its change commitment is unknown")
+            }
+            Self::PartialSync { verified_leaves, on_chain_leaves } => {
+                write!(f, "partial sync: only {verified_leaves} of {on_chain_leaves} leaves verified")
+            }
+            Self::NoSpendingKey { label } => write!(f, "note '{label}' has no spending key"),
+            Self::MissingViewingKey { owner } => write!(f, "no viewing key for 0x{owner}"),
+            Self::PendingTransaction { tx_hash } => write!(f, "earlier tx {tx_hash} is still pending"),
+            Self::RootNoLongerKnown => write!(f, "verified root is no longer known on-chain"),
+            Self::PlaintextKey { label } => write!(f, "key '{label}' is stored in plaintext"),
+            Self::BalanceUnavailable { what } => write!(f, "could not query {what}"),
+            Self::OnChainChecksSkipped => write!(f, "RPC_URL not set; on-chain checks skipped"),
+        }
+    }
+}
+
+impl std::error::Error for StrictViolation {}
+
+static FORCED: AtomicBool = AtomicBool::new(false);
+static FROM_ENV: OnceLock<bool> = OnceLock::new();
+
+/// Turn strict mode on (e.g. from a parsed `--strict` flag).
+pub fn enable() {
+    FORCED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    FORCED.load(Ordering::Relaxed) || *FROM_ENV.get_or_init(from_env)
+}
+
+fn from_env() -> bool {
+    if std::env::args().skip(1).any(|arg| arg == "--strict") {
+        return true;
+    }
+    match std::env::var("STRICT") {
+        Ok(v) => matches!(v.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"),
+        Err(_) => std::env::var_os("CI").is_some_and(|v| !v.is_empty()),
+    }
+}
+
+/// Command-line arguments with `--strict` taken out, for binaries that read
+/// positional arguments by hand.
+pub fn args() -> impl Iterator<Item = String> {
+    std::env::args().skip(1).filter(|arg| arg != "--strict")
+}
+
+/// Report a fallback about to be taken: an error in strict mode, nothing
+/// otherwise (callers print their own warning and carry on).
+pub fn fallback(violation: StrictViolation) -> Result<()> {
+    if is_enabled() {
+        return Err(anyhow::Error::new(violation).context("strict mode"));
+    }
+    Ok(())
+}
+
+/// A block number an RPC should always have returned; 0 outside strict mode.
+pub fn block_number(block: Option<u64>, what: impl FnOnce() -> String) -> Result<u64> {
+    match block {
+        Some(block) => Ok(block),
+        None => fallback(StrictViolation::MissingBlockNumber { what: what() }).map(|()| 0),
+    }
+}
+
+/// Block number and log index of an event log, for ordering.
+pub fn log_position(log: &alloy::rpc::types::Log) -> Result<(u64, u64)> {
+    let what = || match log.transaction_hash {
+        Some(tx) => format!("log in tx {tx}"),
+        None => "log".to_string(),
+    };
+    let block = block_number(log.block_number, what)?;
+    let index = match log.log_index {
+        Some(index) => index,
+        None => fallback(StrictViolation::MissingLogIndex { what: what() }).map(|()| 0)?,
+    };
+    Ok((block, index))
+}
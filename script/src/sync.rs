@@ -0,0 +1,1146 @@
+//! Merkle tree sync verification.
+//!
+//! The host rebuilds the pool's tree by replaying events. If the RPC truncates
+//! log history (or DEPLOY_BLOCK is wrong) the local tree silently diverges and
+//! every proof built from it is rejected on-chain. Instead of hoping, this
+//! module works out which leaves are actually verified:
+//!
+//!   - Full sync: local leaf count and root match the pool's current state.
+//!   - Partial sync: the pool keeps its last ROOT_HISTORY_SIZE roots, one per
+//!     insertion. If the root over the first `k` local leaves is one of them,
+//!     leaves `[0, k)` are verified and notes there can be proven against it.
+//!   - Otherwise nothing is verified and no proofs should be generated.
+//!
+//! Deposit events carry their leaf index, which pins down where the local
+//! replay first diverges.
+//!
+//! `sync_pool_tree` is the whole sync used by the exit and sweep binaries:
+//! start from a checkpoint or the tree store, replay newer events (rolling
+//! back and re-reading on a reorg), verify, and save the result.
+//!
+//! The pool's root buffer is mirrored in `RootHistory`, so asking whether a
+//! proof's root is still accepted is answered locally and only goes to the
+//! RPC once the copy is stale.
+//!
+//! Spent nullifiers are read from `PrivateTransfer` and `Withdrawal` events
+//! into a `NullifierSet` by `replay_nullifiers`, so spent checks need no
+//! `isSpent` call per note.
+//!
+//! `pool_snapshot` rebuilds the tree and nullifier set as of a past block,
+//! for proving a note was held then (`shielded_pool_lib::claim`).
+//!
+//! `scan_outputs` lists the commitments a block range inserted with their
+//! leaf indices and encrypted payloads, for finding incoming notes. The
+//! wallet's scan cursor keeps the hash of the last block scanned; if the
+//! chain no longer has that block, `check_scan_cursor` rolls the wallet back
+//! to before the reorg (`roll_back_wallet`) so the scan reads the new chain.
+
+use alloy::{
+    consensus::Transaction as _,
+    eips::{BlockId, BlockNumberOrTag},
+    primitives::Address,
+    providers::Provider,
+    sol,
+};
+use anyhow::{bail, ensure, Context, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use shielded_pool_lib::{
+    nullifier_set::NullifierSet, tree_store::PersistentMerkleTree, IncrementalMerkleTree, ROOT_HISTORY_SIZE,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+use crate::{
+    chains::FinalityPolicy,
+    decode_hex_fixed,
+    multicall::PoolViews,
+    strict::{self, StrictViolation},
+    wallet::{SyncCursor, WalletState},
+};
+
+sol! {
+    #[sol(rpc)]
+    interface IPoolEvents {
+        function getLastRoot() external view returns (bytes32);
+        function getLeafCount() external view returns (uint32);
+        function getEncryptedNote(uint256 leafIndex) external view returns (bytes memory);
+
+        event Deposit(bytes32 indexed commitment, uint256 amount, uint32 leafIndex, uint256 timestamp);
+        event PrivateTransfer(bytes32 indexed nullifier1, bytes32 indexed nullifier2, bytes32 newCommitment1, bytes32 newCommitment2, uint256 timestamp);
+        event Withdrawal(bytes32 indexed nullifier, address indexed recipient, uint256 amount, uint256 timestamp);
+        event EncryptedNote(bytes32 indexed commitment, bytes encryptedData);
+    }
+}
+
+/// The encrypted payload posted with the note at `leaf_index` (empty if none was).
+pub async fn encrypted_note<P: Provider>(provider: &P, pool_addr: Address, leaf_index: u32) -> Result<Vec<u8>> {
+    let pool = IPoolEvents::new(pool_addr, provider);
+    Ok(pool.getEncryptedNote(alloy::primitives::U256::from(leaf_index)).call().await?.to_vec())
+}
+
+/// Re-read events this many times when a reorg is detected during sync.
+pub const MAX_SYNC_ATTEMPTS: u32 = 3;
+
+/// What the local replay can be trusted for.
+#[derive(Clone, Debug)]
+pub struct SyncStatus {
+    pub on_chain_leaves: u32,
+    pub local_leaves: u32,
+    /// Leaves `[0, verified_leaves)` hash to `verified_root`, a root the pool accepts.
+    pub verified_leaves: u32,
+    pub verified_root: Option<[u8; 32]>,
+    /// First leaf whose local value contradicts a Deposit event, if any.
+    pub first_divergence: Option<u32>,
+}
+
+impl SyncStatus {
+    pub fn is_complete(&self) -> bool {
+        self.verified_root.is_some()
+            && self.verified_leaves == self.on_chain_leaves
+            && self.local_leaves == self.on_chain_leaves
+    }
+
+    /// Whether a note at `leaf_index` can be proven against `verified_root`.
+    pub fn covers(&self, leaf_index: u32) -> bool {
+        self.verified_root.is_some() && leaf_index < self.verified_leaves
+    }
+
+    /// Why a note at `leaf_index` cannot be proven (None if it can).
+    pub fn refusal(&self, leaf_index: u32) -> Option<String> {
+        if self.covers(leaf_index) {
+            return None;
+        }
+        Some(if self.verified_root.is_none() {
+            "no part of the local tree matches a known on-chain root".to_string()
+        } else {
+            format!(
+                "leaf {leaf_index} is outside the verified range [0, {})",
+                self.verified_leaves
+            )
+        })
+    }
+
+    pub fn print(&self) {
+        println!(
+            "    On-chain leaves: {}, local leaves: {}",
+            self.on_chain_leaves, self.local_leaves
+        );
+        if self.is_complete() {
+            println!("    Root verified ✓ (full sync)");
+            return;
+        }
+        match self.verified_root {
+            Some(root) => println!(
+                "    ⚠ Partial sync: leaves [0, {}) verified against known root 0x{}",
+                self.verified_leaves,
+                hex::encode(root)
+            ),
+            None => println!("    ⚠ Local tree does not match any known on-chain root"),
+        }
+        if let Some(leaf) = self.first_divergence {
+            println!("    Local replay first diverges from Deposit events at leaf {leaf}");
+        }
+        if self.local_leaves < self.on_chain_leaves {
+            println!(
+                "    Missing {} leaf/leaves — the RPC likely truncated log history",
+                self.on_chain_leaves - self.local_leaves
+            );
+        }
+        println!("    To recover the full tree, try one of:");
+        println!("      - an RPC with complete log history (archive node) via RPC_URL");
+        println!("      - the correct DEPLOY_BLOCK (too late a block drops early leaves)");
+        println!("      - TREE_CHECKPOINT pointing at a leaf list exported by a fully synced run");
+    }
+}
+
+/// Commitments inserted by events in a block range, in tree order.
+pub struct Replay {
+    pub leaves: Vec<[u8; 32]>,
+    /// Block each of `leaves` was inserted in
+    pub leaf_blocks: Vec<u64>,
+    /// `(leafIndex, commitment)` from Deposit events
+    pub deposit_anchors: Vec<(u32, [u8; 32])>,
+    /// Logs the node flagged as removed by a reorg
+    pub removed_logs: usize,
+}
+
+/// How `replay_events` splits and parallelizes the work.
+#[derive(Clone, Copy, Debug)]
+pub struct SyncPipeline {
+    /// Blocks per log query (SYNC_CHUNK_BLOCKS)
+    pub chunk_blocks: u64,
+    /// RPC requests in flight at once (SYNC_CONCURRENCY)
+    pub concurrency: usize,
+}
+
+impl Default for SyncPipeline {
+    fn default() -> Self {
+        SyncPipeline { chunk_blocks: 10_000, concurrency: 8 }
+    }
+}
+
+impl SyncPipeline {
+    /// Defaults, overridden by SYNC_CHUNK_BLOCKS and SYNC_CONCURRENCY.
+    pub fn from_env() -> Result<Self> {
+        let mut config = SyncPipeline::default();
+        if let Ok(blocks) = std::env::var("SYNC_CHUNK_BLOCKS") {
+            config.chunk_blocks = blocks.parse().context("SYNC_CHUNK_BLOCKS must be a number")?;
+        }
+        if let Ok(n) = std::env::var("SYNC_CONCURRENCY") {
+            config.concurrency = n.parse().context("SYNC_CONCURRENCY must be a number")?;
+        }
+        ensure!(config.chunk_blocks > 0, "SYNC_CHUNK_BLOCKS must be at least 1");
+        ensure!(config.concurrency > 0, "SYNC_CONCURRENCY must be at least 1");
+        Ok(config)
+    }
+}
+
+struct Insertion {
+    block: u64,
+    log_index: u64,
+    commitments: Vec<[u8; 32]>,
+}
+
+/// Decoded events of one block range.
+#[derive(Default)]
+struct ChunkReplay {
+    insertions: Vec<Insertion>,
+    deposit_anchors: Vec<(u32, [u8; 32])>,
+    removed_logs: usize,
+    deposits: usize,
+    transfers: usize,
+    withdrawals: usize,
+}
+
+/// Read every commitment insertion between `from_block` and `to_block`.
+///
+/// The range is split into `SyncPipeline::chunk_blocks` chunks that are
+/// fetched and decoded concurrently (including the per-withdrawal calldata
+/// lookups), with at most `concurrency` RPC requests in flight. Chunks are
+/// consumed in block order, so at most `concurrency` decoded chunks wait
+/// ahead of the ordered merge.
+pub async fn replay_events<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Replay> {
+    let pipeline = SyncPipeline::from_env()?;
+    let permits = Semaphore::new(pipeline.concurrency);
+    let ranges: Vec<(u64, u64)> = (from_block..=to_block)
+        .step_by(pipeline.chunk_blocks as usize)
+        .map(|start| (start, start.saturating_add(pipeline.chunk_blocks - 1).min(to_block)))
+        .collect();
+    let total_chunks = ranges.len();
+
+    let mut chunks = stream::iter(ranges)
+        .map(|(from, to)| replay_chunk(provider, pool_addr, from, to, &permits, pipeline.concurrency))
+        .buffered(pipeline.concurrency);
+
+    let mut replay =
+        Replay { leaves: Vec::new(), leaf_blocks: Vec::new(), deposit_anchors: Vec::new(), removed_logs: 0 };
+    let (mut deposits, mut transfers, mut withdrawals) = (0, 0, 0);
+    let mut done = 0;
+    let mut reported = 0;
+    while let Some(mut chunk) = chunks.try_next().await? {
+        // Chunks arrive in block order; only order within the chunk is left
+        chunk.insertions.sort_by_key(|i| (i.block, i.log_index));
+        for insertion in chunk.insertions {
+            replay.leaf_blocks.extend(insertion.commitments.iter().map(|_| insertion.block));
+            replay.leaves.extend(insertion.commitments);
+        }
+        replay.deposit_anchors.extend(chunk.deposit_anchors);
+        replay.removed_logs += chunk.removed_logs;
+        deposits += chunk.deposits;
+        transfers += chunk.transfers;
+        withdrawals += chunk.withdrawals;
+
+        done += 1;
+        let percent = done * 100 / total_chunks;
+        if total_chunks > 1 && percent / 10 > reported {
+            reported = percent / 10;
+            println!("    {percent:>3}% of blocks read ({} commitments)", replay.leaves.len());
+        }
+    }
+
+    println!("    Deposits: {deposits}");
+    println!("    Transfers: {transfers}");
+    println!("    Withdrawals: {withdrawals}");
+    println!("    Total commitments to insert: {}", replay.leaves.len());
+    Ok(replay)
+}
+
+/// Fetch and decode the events of one block range.
+async fn replay_chunk<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    to_block: u64,
+    permits: &Semaphore,
+    concurrency: usize,
+) -> Result<ChunkReplay> {
+    // Replay ALL commitment insertions in order:
+    //   Deposit:         1 commitment  (from event)
+    //   PrivateTransfer: 2 commitments (from event)
+    //   Withdrawal:      0 or 1 commitment (change, from tx calldata)
+
+    let pool = IPoolEvents::new(pool_addr, provider);
+    let (deposit_logs, transfer_logs, withdrawal_logs) = futures::try_join!(
+        async {
+            let _permit = permits.acquire().await?;
+            Ok::<_, anyhow::Error>(pool.Deposit_filter().from_block(from_block).to_block(to_block).query().await?)
+        },
+        async {
+            let _permit = permits.acquire().await?;
+            Ok(pool.PrivateTransfer_filter().from_block(from_block).to_block(to_block).query().await?)
+        },
+        async {
+            let _permit = permits.acquire().await?;
+            Ok(pool.Withdrawal_filter().from_block(from_block).to_block(to_block).query().await?)
+        },
+    )?;
+
+    let mut chunk = ChunkReplay {
+        deposits: deposit_logs.len(),
+        transfers: transfer_logs.len(),
+        withdrawals: withdrawal_logs.len(),
+        ..Default::default()
+    };
+
+    // 1. Deposits
+    chunk.removed_logs += deposit_logs.iter().filter(|(_, log)| log.removed).count();
+    for (event, log) in &deposit_logs {
+        let (block, log_index) = strict::log_position(log)?;
+        chunk.deposit_anchors.push((event.leafIndex, event.commitment.0));
+        chunk.insertions.push(Insertion {
+            block,
+            log_index,
+            commitments: vec![event.commitment.0],
+        });
+    }
+
+    // 2. Private transfers (2 commitments each)
+    chunk.removed_logs += transfer_logs.iter().filter(|(_, log)| log.removed).count();
+    for (event, log) in &transfer_logs {
+        let (block, log_index) = strict::log_position(log)?;
+        chunk.insertions.push(Insertion {
+            block,
+            log_index,
+            commitments: vec![event.newCommitment1.0, event.newCommitment2.0],
+        });
+    }
+
+    // 3. Withdrawals — decode changeCommitment from tx calldata, one lookup each
+    chunk.removed_logs += withdrawal_logs.iter().filter(|(_, log)| log.removed).count();
+    let changes: Vec<Option<Insertion>> = stream::iter(&withdrawal_logs)
+        .map(|(_event, log)| async move {
+            let (block, log_index) = strict::log_position(log)?;
+            let tx = match log.transaction_hash {
+                Some(tx_hash) => {
+                    let _permit = permits.acquire().await?;
+                    provider.get_transaction_by_hash(tx_hash).await?
+                }
+                None => None,
+            };
+            let Some(tx) = tx else {
+                // Without the calldata the change leaf (if any) is lost
+                let tx_hash = log.transaction_hash.map_or_else(|| "(unknown)".to_string(), |h| h.to_string());
+                strict::fallback(StrictViolation::UnreadableWithdrawal { tx_hash })?;
+                return Ok(None);
+            };
+            Ok::<_, anyhow::Error>(withdraw_change_commitment(tx.input()).map(|change| Insertion {
+                block,
+                log_index,
+                commitments: vec![change],
+            }))
+        })
+        .buffer_unordered(concurrency)
+        .try_collect()
+        .await?;
+    chunk.insertions.extend(changes.into_iter().flatten());
+    Ok(chunk)
+}
+
+/// Non-zero change commitment of a `withdraw` call, from its calldata.
+fn withdraw_change_commitment(input: &[u8]) -> Option<[u8; 32]> {
+    // withdraw(bytes proof, bytes publicValues, bytes encryptedChange)
+    // Calldata: 4-byte selector + ABI-encoded (bytes, bytes, bytes)
+    // Word 0: offset to proof, Word 1: offset to publicValues, Word 2: offset to encryptedChange
+    // At each offset: first 32 bytes = length, then data
+    if input.len() <= 4 + 32 * 3 {
+        return None;
+    }
+    let data = &input[4..];
+    let pv_offset = u64::from_be_bytes(data[32 + 24..32 + 32].try_into().unwrap()) as usize;
+    if pv_offset.checked_add(32)? > data.len() {
+        return None;
+    }
+    let pv_len = u64::from_be_bytes(data[pv_offset + 24..pv_offset + 32].try_into().unwrap()) as usize;
+    let pv_start = pv_offset + 32;
+    if pv_len < 160 || pv_start + 160 > data.len() {
+        return None;
+    }
+    let mut change_comm = [0u8; 32];
+    change_comm.copy_from_slice(&data[pv_start + 128..pv_start + 160]);
+    (change_comm != [0u8; 32]).then_some(change_comm)
+}
+
+/// Every nullifier spent between `from_block` and `to_block`.
+///
+/// Both events carry their nullifiers as topics, so this is two log queries
+/// and no calldata. Like the tree, the set is only as complete as the RPC's
+/// log history: use it when the tree sync over the same range is complete.
+pub async fn replay_nullifiers<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<NullifierSet> {
+    let pool = IPoolEvents::new(pool_addr, provider);
+    let mut set = NullifierSet::new();
+
+    let transfer_logs = pool.PrivateTransfer_filter().from_block(from_block).to_block(to_block).query().await?;
+    let withdrawal_logs = pool.Withdrawal_filter().from_block(from_block).to_block(to_block).query().await?;
+    let removed = transfer_logs.iter().filter(|(_, log)| log.removed).count()
+        + withdrawal_logs.iter().filter(|(_, log)| log.removed).count();
+    ensure!(removed == 0, "{removed} nullifier log(s) were removed by a reorg; re-run");
+
+    for (event, _) in &transfer_logs {
+        set.extend([event.nullifier1.0, event.nullifier2.0]);
+    }
+    for (event, _) in &withdrawal_logs {
+        set.insert(event.nullifier.0);
+    }
+    println!("    Spent nullifiers: {} (root 0x{})", set.len(), hex::encode(&set.root()[..8]));
+    Ok(set)
+}
+
+/// A commitment inserted in a scanned block range.
+#[derive(Clone, Debug)]
+pub struct ScannedOutput {
+    pub leaf_index: u32,
+    pub commitment: [u8; 32],
+    pub block: u64,
+    /// Payload of the commitment's `EncryptedNote` event (empty if none was posted)
+    pub encrypted: Vec<u8>,
+}
+
+/// Every commitment inserted between `from_block` and `to_block`, with its
+/// leaf index and encrypted payload, for trial decryption.
+///
+/// Leaf indices count on from the pool's leaf count just before
+/// `from_block`, so the RPC must serve state that far back; Deposit events in
+/// the range are checked against them.
+pub async fn scan_outputs<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    from_block: u64,
+    to_block: u64,
+) -> Result<Vec<ScannedOutput>> {
+    ensure!(from_block <= to_block, "nothing to scan: block {from_block} is past block {to_block}");
+    let replay = replay_events(provider, pool_addr, from_block, to_block).await?;
+    ensure!(replay.removed_logs == 0, "{} log(s) were removed by a reorg; re-run", replay.removed_logs);
+
+    let pool = IPoolEvents::new(pool_addr, provider);
+    let first_leaf = match from_block.checked_sub(1) {
+        Some(before) => pool
+            .getLeafCount()
+            .block(BlockId::number(before))
+            .call()
+            .await
+            .with_context(|| {
+                format!("Failed to read the pool's leaf count at block {before} (is RPC_URL an archive node?)")
+            })?,
+        None => 0,
+    };
+    for &(leaf, commitment) in &replay.deposit_anchors {
+        let local = leaf.checked_sub(first_leaf).and_then(|i| replay.leaves.get(i as usize));
+        ensure!(local == Some(&commitment), "Deposit event for leaf {leaf} does not match the replayed events");
+    }
+
+    let payload_logs = pool.EncryptedNote_filter().from_block(from_block).to_block(to_block).query().await?;
+    ensure!(payload_logs.iter().all(|(_, log)| !log.removed), "encrypted note logs were removed by a reorg; re-run");
+    // The same commitment may be inserted twice; its payloads are taken in order
+    let mut payloads: HashMap<[u8; 32], VecDeque<Vec<u8>>> = HashMap::new();
+    for (event, _) in payload_logs {
+        payloads.entry(event.commitment.0).or_default().push_back(event.encryptedData.to_vec());
+    }
+
+    Ok(replay
+        .leaves
+        .iter()
+        .zip(&replay.leaf_blocks)
+        .enumerate()
+        .map(|(i, (&commitment, &block))| {
+            let encrypted = payloads.get_mut(&commitment).and_then(VecDeque::pop_front).unwrap_or_default();
+            ScannedOutput { leaf_index: first_leaf + i as u32, commitment, block, encrypted }
+        })
+        .collect())
+}
+
+/// 0x-prefixed hash of `block` as the chain has it now.
+pub async fn block_hash<P: Provider>(provider: &P, block: u64) -> Result<String> {
+    let block = provider
+        .get_block_by_number(BlockNumberOrTag::Number(block))
+        .await?
+        .with_context(|| format!("block {block} not found"))?;
+    Ok(block.header.hash.to_string())
+}
+
+/// What `roll_back_wallet` took out of a wallet.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WalletRollback {
+    /// Labels of the notes at leaves inserted after the fork
+    pub removed_notes: Vec<String>,
+    /// Labels of the notes spent only by transactions mined after the fork
+    pub unspent_notes: Vec<String>,
+    /// Hashes of the wallet's transactions mined after the fork
+    pub dropped_txs: Vec<String>,
+}
+
+/// Undo what the wallet took from blocks after `fork_block`, once a reorg
+/// replaced them. `leaves_at_fork` is the pool's leaf count at `fork_block`.
+///
+/// Notes at later leaves are removed, the wallet's transactions mined after
+/// the fork leave the history and the notes they spent are unspent again,
+/// and the scan cursor moves back to the block after the fork. Scanning
+/// from there picks up whatever the new chain holds.
+pub fn roll_back_wallet(state: &mut WalletState, fork_block: u64, leaves_at_fork: u32) -> WalletRollback {
+    let mut rollback = WalletRollback::default();
+    let (kept, dropped): (Vec<_>, Vec<_>) =
+        std::mem::take(&mut state.transactions).into_iter().partition(|t| t.block_number <= fork_block);
+    state.transactions = kept;
+    state.notes.retain(|n| {
+        if n.leaf_index >= leaves_at_fork {
+            rollback.removed_notes.push(n.label.clone());
+        }
+        n.leaf_index < leaves_at_fork
+    });
+    let respent: Vec<&String> = dropped.iter().flat_map(|t| &t.spent).collect();
+    for note in state.notes.iter_mut().filter(|n| n.spent && respent.contains(&&n.commitment)) {
+        note.spent = false;
+        rollback.unspent_notes.push(note.label.clone());
+    }
+    rollback.dropped_txs = dropped.into_iter().map(|t| t.tx_hash).collect();
+    if let Some(cursor) = state.sync_cursor.as_mut().filter(|c| c.next_block > fork_block + 1) {
+        cursor.next_block = fork_block + 1;
+        cursor.block_hash = None;
+    }
+    rollback
+}
+
+/// Check that the chain still has the last block the wallet scanned on
+/// `pool_addr`. If it does not, the chain reorged below the cursor: the
+/// fork is taken to be at most `reorg_depth` blocks back, and the wallet is
+/// rolled back to it. Returns the rollback, if there was one.
+pub async fn check_scan_cursor<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    chain_id: u64,
+    finality: &FinalityPolicy,
+    state: &mut WalletState,
+) -> Result<Option<WalletRollback>> {
+    if state.scan_cursor(chain_id, &pool_addr.to_string()).is_none() {
+        return Ok(None);
+    }
+    let Some(SyncCursor { next_block, block_hash: Some(hash), .. }) = state.sync_cursor.clone() else {
+        return Ok(None);
+    };
+    let last = next_block - 1;
+    if block_hash(provider, last).await?.eq_ignore_ascii_case(&hash) {
+        return Ok(None);
+    }
+    let fork_block = last.saturating_sub(finality.reorg_depth);
+    let pool = IPoolEvents::new(pool_addr, provider);
+    let leaves_at_fork = pool
+        .getLeafCount()
+        .block(BlockId::number(fork_block))
+        .call()
+        .await
+        .with_context(|| format!("Failed to read the pool's leaf count at block {fork_block}"))?;
+    Ok(Some(roll_back_wallet(state, fork_block, leaves_at_fork)))
+}
+
+/// Local copy of the pool's `roots` buffer and `currentRootIndex`.
+///
+/// `isKnownRoot` scans that buffer, so the copy answers it without a call.
+/// A positive answer is trusted while the copy is younger than `max_age`;
+/// after that, or when the root is not in the copy (it may be newer), the
+/// buffer is re-read and the answer taken from the fresh copy.
+#[derive(Clone, Debug)]
+pub struct RootHistory {
+    pub current_root_index: u32,
+    pub roots: Vec<[u8; 32]>,
+    fetched_at: Instant,
+    max_age: Duration,
+}
+
+/// Trust a root history copy for this long unless `ROOT_HISTORY_MAX_AGE_SECS` says otherwise.
+pub const DEFAULT_ROOT_HISTORY_MAX_AGE: Duration = Duration::from_secs(30);
+
+impl RootHistory {
+    /// Read the pool's root buffer (one multicall plus two calls).
+    pub async fn fetch<P: Provider>(views: &PoolViews<P>, max_age: Duration) -> Result<Self> {
+        let (current_root_index, roots) = views.root_history().await?;
+        Ok(RootHistory { current_root_index, roots, fetched_at: Instant::now(), max_age })
+    }
+
+    /// `ROOT_HISTORY_MAX_AGE_SECS`, or `DEFAULT_ROOT_HISTORY_MAX_AGE`.
+    pub fn max_age_from_env() -> Result<Duration> {
+        match std::env::var("ROOT_HISTORY_MAX_AGE_SECS") {
+            Ok(secs) => Ok(Duration::from_secs(
+                secs.parse().context("ROOT_HISTORY_MAX_AGE_SECS must be a number")?,
+            )),
+            Err(_) => Ok(DEFAULT_ROOT_HISTORY_MAX_AGE),
+        }
+    }
+
+    /// Whether the copy contains `root`. Matches MerkleTree.sol isKnownRoot().
+    pub fn contains(&self, root: &[u8; 32]) -> bool {
+        *root != [0u8; 32] && self.roots.contains(root)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.fetched_at.elapsed() > self.max_age
+    }
+
+    /// Whether `tree` tracked the pool's root history itself, slot for slot.
+    pub fn matches(&self, tree: &IncrementalMerkleTree) -> bool {
+        tree.current_root_index as u32 == self.current_root_index && tree.roots == self.roots
+    }
+
+    /// `isKnownRoot(root)`, from the copy when it can be trusted.
+    pub async fn is_known_root<P: Provider>(&mut self, views: &PoolViews<P>, root: [u8; 32]) -> Result<bool> {
+        if !self.is_stale() && self.contains(&root) {
+            return Ok(true);
+        }
+        *self = RootHistory::fetch(views, self.max_age).await?;
+        Ok(self.contains(&root))
+    }
+}
+
+/// Compare the local replay against the pool and find the verified prefix.
+///
+/// `deposit_anchors` are `(leafIndex, commitment)` pairs from Deposit events;
+/// candidate roots are looked up in `history`.
+pub fn verify_sync(
+    history: &RootHistory,
+    levels: usize,
+    leaves: &[[u8; 32]],
+    on_chain_leaves: u32,
+    deposit_anchors: &[(u32, [u8; 32])],
+) -> SyncStatus {
+    let local_leaves = leaves.len() as u32;
+    let first_divergence = deposit_anchors
+        .iter()
+        .filter(|(idx, comm)| leaves.get(*idx as usize) != Some(comm))
+        .map(|(idx, _)| *idx)
+        .min();
+
+    // The pool remembers the roots after its last ROOT_HISTORY_SIZE insertions,
+    // so only prefixes of those lengths can be checked.
+    let hi = local_leaves.min(on_chain_leaves);
+    let lo = on_chain_leaves.saturating_sub(ROOT_HISTORY_SIZE as u32 - 1).max(1);
+    let mut candidates: Vec<(u32, [u8; 32])> = Vec::new();
+    // Leaves below the oldest checkable prefix are rebuilt in bulk
+    let bulk = (lo - 1).min(hi) as usize;
+    let mut tree = IncrementalMerkleTree::from_leaves(levels, leaves[..bulk].iter().copied()).unwrap_or_else(|e| panic!("{e}"));
+    for (i, leaf) in leaves.iter().enumerate().take(hi as usize).skip(bulk) {
+        tree.insert(*leaf);
+        let k = i as u32 + 1;
+        if k >= lo {
+            candidates.push((k, tree.get_root()));
+        }
+    }
+
+    let verified = candidates.iter().rev().find(|(_, root)| history.contains(root)).copied();
+
+    SyncStatus {
+        on_chain_leaves,
+        local_leaves,
+        verified_leaves: verified.map_or(0, |(k, _)| k),
+        verified_root: verified.map(|(_, r)| r),
+        first_divergence,
+    }
+}
+
+/// Tree over the first `count` leaves — the tree proofs must be built from
+/// when only a prefix is verified.
+pub fn prefix_tree(levels: usize, leaves: &[[u8; 32]], count: u32) -> IncrementalMerkleTree {
+    IncrementalMerkleTree::from_leaves(levels, leaves[..count as usize].iter().copied()).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Where the pool's tree is rebuilt from.
+#[derive(Clone, Debug)]
+pub struct TreeSource {
+    pub levels: usize,
+    /// Block the pool was deployed at; a full replay starts here
+    pub deploy_block: u64,
+    /// Leaf list from an earlier fully synced run, replayed on top of
+    pub checkpoint: Option<PathBuf>,
+    /// Persistent tree, resumed from when there is no checkpoint
+    pub store: PathBuf,
+    /// The chain's entry in the chain registry
+    pub finality: FinalityPolicy,
+}
+
+impl TreeSource {
+    /// From `TREE_LEVELS`, `DEPLOY_BLOCK`, `TREE_CHECKPOINT`, `TREE_STORE` and
+    /// the chain registry.
+    pub fn from_env(chain_id: u64, pool: Address) -> Result<Self> {
+        Ok(TreeSource {
+            levels: std::env::var("TREE_LEVELS").unwrap_or_else(|_| "20".to_string()).parse()?,
+            deploy_block: std::env::var("DEPLOY_BLOCK")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .context("DEPLOY_BLOCK must be a number")?,
+            checkpoint: std::env::var("TREE_CHECKPOINT").ok().map(PathBuf::from),
+            store: std::env::var("TREE_STORE")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| default_tree_store_path(chain_id, &pool.to_string())),
+            finality: FinalityPolicy::for_chain(chain_id)?,
+        })
+    }
+}
+
+/// The local tree after `sync_pool_tree`.
+pub struct PoolSync {
+    /// Tree over the verified leaves only; build proofs from this
+    pub tree: IncrementalMerkleTree,
+    pub status: SyncStatus,
+    /// Block the events were read up to (`confirmations` behind the head)
+    pub block: u64,
+    /// The pool's root buffer, read right after the sync
+    pub roots: RootHistory,
+}
+
+/// Rebuild the pool's tree and work out which part of it is verified.
+///
+/// Starts from the checkpoint if one is given, else from the tree store, and
+/// replays only newer events. If the chain reorgs while we read it, logs come
+/// back marked removed or the replayed root disagrees with the pool's root at
+/// the sync block: the tree is rolled back to the starting leaves and the
+/// events re-read, up to `MAX_SYNC_ATTEMPTS` times. Events are read up to
+/// the chain's `confirmations` behind the head. After a fully verified sync,
+/// the leaves at least `reorg_depth` deep are saved to the store and to
+/// `TreeCheckpoint::default_path()`.
+pub async fn sync_pool_tree<P: Provider + Clone>(
+    provider: P,
+    views: &PoolViews<P>,
+    pool_addr: Address,
+    chain_id: u64,
+    source: &TreeSource,
+) -> Result<PoolSync> {
+    let levels = source.levels;
+    let mut store = PersistentMerkleTree::open(&source.store, levels)
+        .with_context(|| format!("Failed to open tree store {}", source.store.display()))?;
+
+    let mut tree = IncrementalMerkleTree::new(levels);
+    let mut from_block = source.deploy_block;
+    let mut resumed_from_store = false;
+    if let Some(ref path) = source.checkpoint {
+        let checkpoint = TreeCheckpoint::load(path)?;
+        let leaves = checkpoint.leaves_for(chain_id, &pool_addr.to_string(), levels)?;
+        tree = IncrementalMerkleTree::from_leaves(levels, leaves)
+            .map_err(anyhow::Error::msg)
+            .context("checkpoint holds too many leaves")?;
+        from_block = checkpoint.block + 1;
+        println!(
+            "    Checkpoint: {} leaves up to block {} ({})",
+            tree.leaves.len(),
+            checkpoint.block,
+            path.display()
+        );
+    } else if let Some(block) = store.synced_block() {
+        tree = store.tree().clone();
+        from_block = block + 1;
+        resumed_from_store = true;
+        println!(
+            "    Tree store: {} leaves up to block {} ({})",
+            tree.leaves.len(),
+            block,
+            source.store.display()
+        );
+    }
+
+    let pool = IPoolEvents::new(pool_addr, provider.clone());
+    let base = tree.checkpoint();
+    let base_leaves = tree.leaves.len();
+    let mut attempt = 1;
+    let (head, block, replay) = loop {
+        tree.rollback(base).map_err(anyhow::Error::msg)?;
+        let head = provider.get_block_number().await?;
+        let block = source.finality.final_block(head).max(from_block.saturating_sub(1));
+        let replay = replay_events(&provider, pool_addr, from_block, block).await?;
+        for leaf in &replay.leaves {
+            tree.try_insert(*leaf)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("pool has more leaves than a {levels}-level tree holds (check TREE_LEVELS)"))?;
+        }
+        let reorged = if replay.removed_logs > 0 {
+            Some(format!("{} log(s) were removed", replay.removed_logs))
+        } else {
+            let on_chain_root = pool.getLastRoot().block(BlockId::number(block)).call().await?;
+            let on_chain_leaves = pool.getLeafCount().block(BlockId::number(block)).call().await?;
+            let audit = tree.audit(on_chain_root.0, on_chain_leaves);
+            (!audit.is_in_sync()).then(|| format!("at block {block}, {audit}"))
+        };
+        match reorged {
+            Some(why) if attempt < MAX_SYNC_ATTEMPTS => {
+                println!("    ⚠ {why} (possible reorg); rolling back and re-reading events");
+                attempt += 1;
+            }
+            _ => break (head, block, replay),
+        }
+    };
+    let leaves = &tree.leaves;
+
+    // Work out which leaves are verified against a root the pool accepts.
+    // Proofs are only ever built from that verified prefix.
+    let on_chain_leaves: u32 = pool.getLeafCount().block(BlockId::number(block)).call().await?;
+    let roots = RootHistory::fetch(views, RootHistory::max_age_from_env()?).await?;
+    let status = verify_sync(&roots, levels, leaves, on_chain_leaves, &replay.deposit_anchors);
+    status.print();
+    if status.is_complete() && !roots.matches(&tree) {
+        // The pool moved on between the sync block and the read; harmless
+        println!("    Pool's root buffer has advanced past the sync block");
+    }
+    if resumed_from_store && !status.is_complete() {
+        println!(
+            "      - deleting {} (e.g. after a reorg) to replay from DEPLOY_BLOCK",
+            source.store.display()
+        );
+    }
+    if !status.is_complete() {
+        strict::fallback(StrictViolation::PartialSync {
+            verified_leaves: status.verified_leaves,
+            on_chain_leaves: status.on_chain_leaves,
+        })?;
+    }
+
+    // Persist only what no reorg within `reorg_depth` can take back
+    let settled = source.finality.settled_block(head).min(block);
+    if status.is_complete() && settled + 1 >= from_block {
+        let settled_leaves = base_leaves + replay.leaf_blocks.iter().take_while(|b| **b <= settled).count();
+        let leaves = &leaves[..settled_leaves];
+        save_tree_store(&mut store, levels, leaves, settled)?;
+        println!("    Tree store updated: {} ({} leaves up to block {settled})", source.store.display(), leaves.len());
+
+        let checkpoint = TreeCheckpoint {
+            chain_id,
+            pool: pool_addr.to_string(),
+            levels,
+            block: settled,
+            leaves: leaves.iter().map(hex::encode).collect(),
+        };
+        let path = TreeCheckpoint::default_path();
+        checkpoint.save(&path)?;
+        println!("    Checkpoint saved to {}", path.display());
+    }
+
+    Ok(PoolSync { tree: prefix_tree(levels, leaves, status.verified_leaves), status, block, roots })
+}
+
+/// The pool as of a past block, e.g. an airdrop snapshot.
+pub struct PoolSnapshot {
+    pub block: u64,
+    pub tree: IncrementalMerkleTree,
+    /// Nullifiers spent up to and including `block`
+    pub nullifiers: NullifierSet,
+}
+
+/// Rebuild the pool as of `block` from every event since the deploy block,
+/// and check the tree against the pool's root and leaf count at that block.
+/// Needs an RPC with state and logs that far back (an archive node).
+pub async fn pool_snapshot<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    source: &TreeSource,
+    block: u64,
+) -> Result<PoolSnapshot> {
+    let replay = replay_events(provider, pool_addr, source.deploy_block, block).await?;
+    ensure!(replay.removed_logs == 0, "{} log(s) were removed by a reorg; re-run", replay.removed_logs);
+    let tree = IncrementalMerkleTree::from_leaves(source.levels, replay.leaves)
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("pool has more leaves than a {}-level tree holds (check TREE_LEVELS)", source.levels))?;
+
+    let pool = IPoolEvents::new(pool_addr, provider);
+    let at = BlockId::number(block);
+    let root = pool
+        .getLastRoot()
+        .block(at)
+        .call()
+        .await
+        .with_context(|| format!("Failed to read the pool's root at block {block} (is RPC_URL an archive node?)"))?;
+    let leaves = pool.getLeafCount().block(at).call().await?;
+    let audit = tree.audit(root.0, leaves);
+    ensure!(audit.is_in_sync(), "cannot rebuild the pool at block {block}: {audit}");
+
+    let nullifiers = replay_nullifiers(provider, pool_addr, source.deploy_block, block).await?;
+    Ok(PoolSnapshot { block, tree, nullifiers })
+}
+
+/// The block at whose end `root` was the pool's latest root, found by
+/// replaying events up to `to_block`. Fails if `root` never was one, or was
+/// replaced within the block it appeared in.
+pub async fn find_root_block<P: Provider>(
+    provider: &P,
+    pool_addr: Address,
+    source: &TreeSource,
+    root: [u8; 32],
+    to_block: u64,
+) -> Result<u64> {
+    let replay = replay_events(provider, pool_addr, source.deploy_block, to_block).await?;
+    let mut tree = IncrementalMerkleTree::new(source.levels);
+    for (i, leaf) in replay.leaves.iter().enumerate() {
+        tree.try_insert(*leaf).map_err(anyhow::Error::msg)?;
+        if tree.get_root() != root {
+            continue;
+        }
+        let block = replay.leaf_blocks[i];
+        ensure!(
+            replay.leaf_blocks.get(i + 1).is_none_or(|next| *next > block),
+            "root 0x{} was replaced within block {block}; it was never the pool's root at the end of a block",
+            hex::encode(root)
+        );
+        return Ok(block);
+    }
+    bail!("root 0x{} is not a root of the pool up to block {to_block}", hex::encode(root))
+}
+
+/// Default tree store for a pool: fixtures/tree-store/<chain_id>-<pool> at the repo root.
+pub fn default_tree_store_path(chain_id: u64, pool: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("fixtures/tree-store")
+        .join(format!("{chain_id}-{}", pool.to_lowercase()))
+}
+
+/// Persist a fully verified leaf list synced up to `block`.
+///
+/// Only the leaves past the stored ones are appended. If the store holds
+/// leaves that are not a prefix of `leaves` (e.g. it was written before a
+/// reorg), it is rebuilt from scratch.
+pub fn save_tree_store(
+    store: &mut PersistentMerkleTree,
+    levels: usize,
+    leaves: &[[u8; 32]],
+    block: u64,
+) -> Result<()> {
+    if !leaves.starts_with(&store.tree().leaves) {
+        let dir = store.path().to_path_buf();
+        std::fs::remove_dir_all(&dir)?;
+        *store = PersistentMerkleTree::open(&dir, levels)?;
+    }
+    for leaf in &leaves[store.tree().leaves.len()..] {
+        store.append(*leaf)?;
+    }
+    store.set_synced_block(block);
+    store.flush()?;
+    Ok(())
+}
+
+/// Leaf list saved after a fully verified sync, so later runs do not depend
+/// on the RPC's log history.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TreeCheckpoint {
+    pub chain_id: u64,
+    /// 0x-prefixed ShieldedPool address
+    pub pool: String,
+    pub levels: usize,
+    /// Block the leaves were read up to
+    pub block: u64,
+    /// Hex-encoded commitments in leaf order
+    pub leaves: Vec<String>,
+}
+
+impl TreeCheckpoint {
+    /// fixtures/tree-checkpoint.json at the repo root.
+    pub fn default_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("fixtures/tree-checkpoint.json")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tree checkpoint: {}", path.display()))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Decoded leaves, after checking the checkpoint is for this pool.
+    pub fn leaves_for(&self, chain_id: u64, pool: &str, levels: usize) -> Result<Vec<[u8; 32]>> {
+        ensure!(self.chain_id == chain_id, "checkpoint is for chain {}", self.chain_id);
+        ensure!(self.pool.eq_ignore_ascii_case(pool), "checkpoint is for pool {}", self.pool);
+        ensure!(self.levels == levels, "checkpoint is for a {}-level tree", self.levels);
+        self.leaves.iter().map(|l| decode_hex_fixed::<32>(l)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit::{AuditAction, AuditEntry, AuditNote},
+        wallet::WalletNote,
+    };
+    use alloy::{
+        primitives::{Bytes, B256, U256},
+        providers::ProviderBuilder,
+        rpc::types::Block,
+        transports::mock::Asserter,
+    };
+    use shielded_pool_lib::{keccak256, Note};
+
+    const LEVELS: usize = 20;
+    const POOL: Address = Address::repeat_byte(0xaa);
+    const SCANNED_HASH: B256 = B256::repeat_byte(0x13);
+
+    /// Block `eth_getBlockByNumber` answers with, with only its hash set.
+    fn block(hash: B256) -> Block {
+        let mut block: Block = Block::default();
+        block.header.hash = hash;
+        block
+    }
+
+    fn note(blinding: u8) -> Note {
+        Note { amount: 1_000 * blinding as u64, pubkey: [7u8; 32], blinding: [blinding; 32] }
+    }
+
+    fn other_leaf(i: u8) -> [u8; 32] {
+        keccak256(&[i])
+    }
+
+    /// The wallet's transfer at `block`: spends `spent`, creates `created`.
+    fn transfer(state: &mut WalletState, block: u64, spent: &Note, created: &[(&Note, u32)]) {
+        let mut entry = AuditEntry::new(AuditAction::Transfer, "test", 1, "0xpool", &format!("0x{block:x}"), block)
+            .with_spent(AuditNote::new("spent", spent, None));
+        for (note, leaf) in created {
+            entry = entry.with_created(AuditNote::new("created", note, Some(*leaf)));
+        }
+        state.record_tx(&entry);
+    }
+
+    /// Blocks 10..=13 of one chain: a deposit of ours, someone else's
+    /// deposit, our transfer spending the first note into a payment and our
+    /// change, and a note received. Returns the leaves with their blocks,
+    /// and the wallet scanned up to block 13.
+    fn synced() -> (Vec<([u8; 32], u64)>, WalletState) {
+        let (payment, change, received) = (note(3), note(4), note(5));
+        let leaves = vec![
+            (note(1).commitment(), 10),
+            (other_leaf(1), 11),
+            (payment.commitment(), 12),
+            (change.commitment(), 12),
+            (received.commitment(), 13),
+        ];
+        let mut state = WalletState {
+            notes: vec![
+                WalletNote::new("deposit_0", &note(1), 0),
+                WalletNote::new("change_3", &change, 3),
+                WalletNote::new("received_4", &received, 4),
+            ],
+            sync_cursor: Some(SyncCursor {
+                chain_id: 1,
+                pool: POOL.to_string(),
+                next_block: 14,
+                block_hash: Some(SCANNED_HASH.to_string()),
+            }),
+            ..WalletState::default()
+        };
+        transfer(&mut state, 12, &note(1), &[(&payment, 2), (&change, 3)]);
+        (leaves, state)
+    }
+
+    #[tokio::test]
+    async fn test_reorg_below_the_cursor_removes_what_came_after_the_fork() {
+        let asserter = Asserter::new();
+        let provider = ProviderBuilder::new().connect_mocked_client(asserter.clone());
+        let finality = FinalityPolicy { confirmations: 0, reorg_depth: 2, poll_interval_ms: 0 };
+        let (leaves, mut state) = synced();
+        assert!(state.notes[0].spent);
+
+        // The chain still has the last block scanned
+        asserter.push_success(&block(SCANNED_HASH));
+        assert_eq!(check_scan_cursor(&provider, POOL, 1, &finality, &mut state).await.unwrap(), None);
+        assert_eq!(state.notes.len(), 3);
+
+        // It replaced block 13, so the fork is taken to be at block 11, where the pool held 2 leaves
+        asserter.push_success(&block(B256::repeat_byte(0xee)));
+        asserter.push_success(&Bytes::from(U256::from(2).to_be_bytes::<32>()));
+        let rollback = check_scan_cursor(&provider, POOL, 1, &finality, &mut state).await.unwrap().unwrap();
+        assert!(asserter.read_q().is_empty());
+        assert_eq!(rollback.removed_notes, ["change_3", "received_4"]);
+        assert_eq!(rollback.unspent_notes, ["deposit_0"]);
+        assert_eq!(rollback.dropped_txs, [format!("0x{:x}", 12)]);
+
+        let notes: Vec<(&str, u32, bool)> =
+            state.notes.iter().map(|n| (n.label.as_str(), n.leaf_index, n.spent)).collect();
+        assert_eq!(notes, [("deposit_0", 0, false)]);
+        assert!(state.transactions.is_empty());
+        let cursor = state.sync_cursor.as_ref().unwrap();
+        assert_eq!((cursor.next_block, &cursor.block_hash), (12, &None));
+        assert_eq!(leaves.iter().filter(|(_, block)| *block <= 11).count(), 2);
+
+        // Without a hash to compare there is nothing to check
+        assert_eq!(check_scan_cursor(&provider, POOL, 1, &finality, &mut state).await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_reorg_keeps_what_came_before_the_fork() {
+        let (_, mut state) = synced();
+        // Only block 13 was replaced: the transfer at block 12 stands
+        let rollback = roll_back_wallet(&mut state, 12, 4);
+        assert_eq!(rollback.removed_notes, ["received_4"]);
+        assert!(rollback.unspent_notes.is_empty() && rollback.dropped_txs.is_empty());
+        assert!(state.notes[0].spent);
+        assert_eq!(state.transactions.len(), 1);
+        assert_eq!(state.sync_cursor.as_ref().unwrap().next_block, 13);
+
+        // A fork past the cursor changes nothing
+        let (_, mut state) = synced();
+        assert_eq!(roll_back_wallet(&mut state, 13, 5), WalletRollback::default());
+        assert_eq!(state.sync_cursor, synced().1.sync_cursor);
+        assert_eq!(state.notes.len(), 3);
+    }
+
+    #[test]
+    fn test_rolled_back_tree_matches_a_rebuild() {
+        let (leaves, _) = synced();
+        let fork_block = 11;
+        let mut tree = IncrementalMerkleTree::new(LEVELS);
+        let before: Vec<[u8; 32]> = leaves.iter().filter(|(_, b)| *b <= fork_block).map(|(leaf, _)| *leaf).collect();
+        for leaf in &before {
+            tree.insert(*leaf);
+        }
+        let fork = tree.checkpoint();
+        for (leaf, _) in &leaves[before.len()..] {
+            tree.insert(*leaf);
+        }
+
+        // The new chain has other blocks after the fork
+        tree.rollback(fork).unwrap();
+        assert_eq!(tree.leaves, before);
+        let replacement = [other_leaf(2), other_leaf(3)];
+        for leaf in replacement {
+            tree.insert(leaf);
+        }
+        let new_chain: Vec<[u8; 32]> = before.iter().copied().chain(replacement).collect();
+        let rebuilt = IncrementalMerkleTree::from_leaves(LEVELS, new_chain.clone()).unwrap();
+        assert_eq!(tree.get_root(), rebuilt.get_root());
+        assert_eq!(tree.get_proof(3), rebuilt.get_proof(3));
+        assert_eq!(prefix_tree(LEVELS, &new_chain, 2).get_root(), prefix_tree(LEVELS, &before, 2).get_root());
+    }
+}
@@ -0,0 +1,809 @@
+//! Wallet state shared by the e2e, exit and proof CLI binaries.
+//!
+//! The e2e script writes fixtures/wallet.json; exit and `note import`/`note export`
+//! read (and extend) it.
+//!
+//! Notes carry an open metadata map so integrators can attach their own data
+//! (order ids, ticket refs, ...) without changing the schema. Keys are
+//! namespaced as `namespace:name`; values are arbitrary JSON.
+//!
+//! Cancellable notes also store their lock, so either the recipient (claim)
+//! or the sender (reclaim) can spend them from whichever wallet holds the key.
+//!
+//! Notes record when they were created or imported, for idle-note reminders
+//! (see `crate::idle`).
+//!
+//! Messages sent and received over encrypted outputs (see `crate::messaging`)
+//! are kept alongside the notes and threaded by counterparty viewing pubkey.
+//!
+//! Wallets written by older script versions load as well: camelCase field
+//! names, amounts as strings, 0x-prefixed or uppercase hex and missing
+//! derivable fields are accepted, and `load` canonicalizes them in memory.
+//! `shielded-pool wallet fix` verifies the result and rewrites the file.
+//!
+//! The wallet also remembers which of its notes are spent, how far `scan`
+//! has read the pool, and the pool transactions it sent. A wallet path
+//! ending in `.db` (the default, fixtures/wallet.db) is a SQLite database
+//! (see `crate::wallet_store`); any other path is a JSON file.
+//! `shielded-pool wallet migrate` imports a JSON wallet into a database.
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use shielded_pool_lib::{
+    cancellable::{authorize_spender, SpendAuthorization, SpendPath},
+    derive_pubkey,
+    keystore::Keystore,
+    CancellableLock, Note, SpenderKey, SpendingKey,
+};
+use crypto_box::PublicKey;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    audit::{AuditAction, AuditEntry},
+    decode_hex_fixed, derive_viewing_keypair,
+    idle::{unix_now, IdlePolicy},
+    messaging::{decrypt_message, encrypt_note_with_message, Message, MessageBody},
+    read_passphrase,
+    strict::{self, StrictViolation},
+    wallet_store,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WalletNote {
+    /// Human label (e.g. "deposit_a", "transfer_change")
+    pub label: String,
+    /// Note amount (raw, 6 decimals)
+    #[serde(deserialize_with = "amount_from_number_or_string")]
+    pub amount: u64,
+    /// Hex-encoded 32-byte public key
+    pub pubkey: String,
+    /// Hex-encoded 32-byte blinding factor
+    pub blinding: String,
+    /// Hex-encoded 32-byte commitment
+    #[serde(default)]
+    pub commitment: String,
+    /// Leaf index in the Merkle tree
+    #[serde(alias = "leafIndex")]
+    pub leaf_index: u32,
+    /// Integrator metadata (`namespace:name` → JSON value)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: NoteMetadata,
+    /// Set for cancellable notes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<WalletLock>,
+    /// Unix time the note was created or imported (None in older wallets)
+    #[serde(default, alias = "touchedAt", skip_serializing_if = "Option::is_none")]
+    pub touched_at: Option<u64>,
+    /// Set once the note's nullifier is known to be spent on-chain
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub spent: bool,
+}
+
+/// Lock of a cancellable note (see `shielded_pool_lib::cancellable`).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WalletLock {
+    /// Hex-encoded 32-byte pubkey that can claim the note
+    #[serde(alias = "recipientPubkey")]
+    pub recipient_pubkey: String,
+    /// Hex-encoded 32-byte pubkey that can reclaim the note until the deadline
+    #[serde(alias = "senderPubkey")]
+    pub sender_pubkey: String,
+    /// Unix timestamp after which the sender can no longer reclaim
+    #[serde(alias = "reclaimDeadline")]
+    pub reclaim_deadline: u64,
+}
+
+impl WalletLock {
+    pub fn new(lock: &CancellableLock) -> Self {
+        WalletLock {
+            recipient_pubkey: hex::encode(lock.recipient_pubkey),
+            sender_pubkey: hex::encode(lock.sender_pubkey),
+            reclaim_deadline: lock.reclaim_deadline,
+        }
+    }
+
+    pub fn to_lock(&self) -> Result<CancellableLock> {
+        Ok(CancellableLock {
+            recipient_pubkey: decode_hex_fixed(&self.recipient_pubkey).context("invalid lock recipient pubkey")?,
+            sender_pubkey: decode_hex_fixed(&self.sender_pubkey).context("invalid lock sender pubkey")?,
+            reclaim_deadline: self.reclaim_deadline,
+        })
+    }
+}
+
+/// Namespaced note metadata, ordered by key so exports are stable.
+pub type NoteMetadata = BTreeMap<String, serde_json::Value>;
+
+/// Metadata of a change note split into several (see
+/// `shielded_pool_lib::coin_selection::split_change`):
+/// `{"group": <hex commitment of the payment>, "part": i, "of": n}`.
+pub const CHANGE_SPLIT_KEY: &str = "change:split";
+
+/// Check a metadata key has the form `namespace:name`, where the namespace is
+/// lowercase ASCII alphanumerics plus `_.-` and the name is non-empty with no
+/// whitespace.
+pub fn validate_metadata_key(key: &str) -> Result<()> {
+    let (namespace, name) = key
+        .split_once(':')
+        .with_context(|| format!("metadata key '{key}' must be namespaced as 'namespace:name'"))?;
+    ensure!(
+        !namespace.is_empty()
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "_.-".contains(c)),
+        "metadata namespace '{namespace}' must be lowercase [a-z0-9_.-]"
+    );
+    ensure!(
+        !name.is_empty() && !name.chars().any(char::is_whitespace),
+        "metadata name in '{key}' must be non-empty without whitespace"
+    );
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WalletState {
+    /// Spending keys (sender, recipient)
+    #[serde(default, alias = "spendingKeys")]
+    pub spending_keys: Vec<WalletSpendingKey>,
+    /// All notes created during this session
+    #[serde(default)]
+    pub notes: Vec<WalletNote>,
+    /// Idle-note reminder settings (defaults apply when unset)
+    #[serde(default, alias = "idlePolicy", skip_serializing_if = "Option::is_none")]
+    pub idle_policy: Option<IdlePolicy>,
+    /// Messages sent and received, in the order they were recorded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<WalletMessage>,
+    /// Where `scan` continues from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_cursor: Option<SyncCursor>,
+    /// Pool transactions sent from this wallet, in the order they were recorded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<WalletTx>,
+}
+
+/// How far `scan` has read one pool's encrypted outputs.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncCursor {
+    pub chain_id: u64,
+    /// 0x-prefixed ShieldedPool address
+    pub pool: String,
+    /// First block not scanned yet
+    pub next_block: u64,
+    /// 0x-prefixed hash of the last block scanned, to notice a reorg below it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<String>,
+}
+
+/// A pool transaction in the wallet's history (see `WalletState::record_tx`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletTx {
+    pub action: AuditAction,
+    /// 0x-prefixed transaction hash
+    pub tx_hash: String,
+    pub block_number: u64,
+    /// Amount that entered or left the pool publicly (deposits and withdrawals)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_amount: Option<u64>,
+    /// Hex-encoded commitments of the wallet notes spent
+    #[serde(default)]
+    pub spent: Vec<String>,
+    /// Hex-encoded commitments of the notes created, in tree order
+    #[serde(default)]
+    pub created: Vec<String>,
+    /// Unix time the transaction was recorded
+    pub recorded_at: u64,
+}
+
+/// Whether a wallet message was sent or received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageDirection {
+    Sent,
+    Received,
+}
+
+/// A message in the wallet's history.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WalletMessage {
+    pub direction: MessageDirection,
+    /// Hex-encoded viewing pubkey of the other party (the thread key)
+    pub counterparty: String,
+    /// Hex-encoded commitment of the note that carried the message
+    pub commitment: String,
+    pub message: Message,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WalletSpendingKey {
+    pub label: String,
+    /// Hex-encoded 32-byte spending key (plaintext; only in wallets from older e2e runs)
+    #[serde(default, alias = "spendingKey", skip_serializing_if = "String::is_empty")]
+    pub spending_key: String,
+    /// Spending key encrypted under the wallet passphrase
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keystore: Option<Keystore>,
+    /// Hex-encoded 32-byte derived shielded pubkey
+    #[serde(default)]
+    pub pubkey: String,
+    /// Hex-encoded 32-byte viewing public key (x25519)
+    #[serde(default, alias = "viewingPubkey")]
+    pub viewing_pubkey: String,
+}
+
+impl WalletNote {
+    pub fn new(label: &str, note: &Note, leaf_index: u32) -> Self {
+        WalletNote {
+            label: label.to_string(),
+            amount: note.amount,
+            pubkey: hex::encode(note.pubkey),
+            blinding: hex::encode(note.blinding),
+            commitment: hex::encode(note.commitment()),
+            leaf_index,
+            metadata: NoteMetadata::new(),
+            lock: None,
+            touched_at: Some(unix_now()),
+            spent: false,
+        }
+    }
+
+    /// Record the lock of a cancellable note.
+    pub fn with_lock(mut self, lock: &CancellableLock) -> Self {
+        self.lock = Some(WalletLock::new(lock));
+        self
+    }
+
+    /// The note's lock, if it is cancellable.
+    pub fn to_lock(&self) -> Result<Option<CancellableLock>> {
+        self.lock.as_ref().map(WalletLock::to_lock).transpose()
+    }
+
+    /// Pick a key from `keys` that may spend this note, with its nullifier.
+    ///
+    /// For a cancellable note held by both parties' keys, claiming is
+    /// preferred over reclaiming. Returns `None` if no key can spend it.
+    pub fn authorize(&self, keys: &[SpendingKey]) -> Result<Option<(SpendingKey, SpendAuthorization)>> {
+        Ok(self.authorize_spender(keys)?.map(|(key, _, auth)| (key, auth)))
+    }
+
+    /// `authorize`, with the key the transfer and withdraw circuits take:
+    /// the spending key's proving key, or its legacy key for a note from
+    /// before proving keys (only those two circuits spend such notes).
+    pub fn authorize_spender(
+        &self,
+        keys: &[SpendingKey],
+    ) -> Result<Option<(SpendingKey, SpenderKey, SpendAuthorization)>> {
+        let note = self.to_note()?;
+        let lock = self.to_lock()?;
+        ensure!(
+            lock.is_none_or(|l| l.locks(&note)),
+            "lock of note '{}' does not match its pubkey",
+            self.label
+        );
+        Ok(keys
+            .iter()
+            .filter_map(|k| authorize_spender(&note, k.as_bytes(), lock.as_ref()).ok().map(|(s, a)| (*k, s, a)))
+            .min_by_key(|(_, _, a)| a.path == SpendPath::Reclaim))
+    }
+
+    /// Set (or with `None`, remove) a metadata entry.
+    pub fn set_metadata(&mut self, key: &str, value: Option<serde_json::Value>) -> Result<()> {
+        validate_metadata_key(key)?;
+        match value {
+            Some(v) => self.metadata.insert(key.to_string(), v),
+            None => self.metadata.remove(key),
+        };
+        Ok(())
+    }
+
+    /// Whether the note has `key`, and (if given) the value equals `value`.
+    pub fn metadata_matches(&self, key: &str, value: Option<&serde_json::Value>) -> bool {
+        match (self.metadata.get(key), value) {
+            (Some(v), Some(want)) => v == want,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Reconstruct the Note from its hex fields.
+    pub fn to_note(&self) -> Result<Note> {
+        Ok(Note {
+            amount: self.amount,
+            pubkey: decode_hex_fixed(&self.pubkey).context("invalid note pubkey")?,
+            blinding: decode_hex_fixed(&self.blinding).context("invalid note blinding")?,
+        })
+    }
+}
+
+impl WalletState {
+    /// Index of the one note whose label or hex commitment is `selector`.
+    pub fn find_note(&self, selector: &str) -> Result<usize> {
+        let commitment = selector.strip_prefix("0x").unwrap_or(selector).to_lowercase();
+        let matches: Vec<usize> = self
+            .notes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.label == selector || n.commitment.to_lowercase() == commitment)
+            .map(|(i, _)| i)
+            .collect();
+        match matches.as_slice() {
+            [i] => Ok(*i),
+            [] => anyhow::bail!("no note with label or commitment '{selector}'"),
+            _ => anyhow::bail!("'{selector}' matches {} notes; use the commitment instead", matches.len()),
+        }
+    }
+
+    /// Track the change notes of the payment whose output to the recipient is
+    /// `payment`, each with its leaf. One note is labelled `label`; a split is
+    /// labelled `label_1`..`label_n` and tagged with `CHANGE_SPLIT_KEY`, so
+    /// the wallet knows which notes are linked again if spent together.
+    pub fn push_change(&mut self, label: &str, payment: &[u8; 32], notes: &[(Note, u32)]) -> Result<()> {
+        if let [(note, leaf)] = notes {
+            self.notes.push(WalletNote::new(label, note, *leaf));
+            return Ok(());
+        }
+        for (i, (note, leaf)) in notes.iter().enumerate() {
+            let mut wallet_note = WalletNote::new(&format!("{label}_{}", i + 1), note, *leaf);
+            let split = serde_json::json!({ "group": hex::encode(payment), "part": i + 1, "of": notes.len() });
+            wallet_note.set_metadata(CHANGE_SPLIT_KEY, Some(split))?;
+            self.notes.push(wallet_note);
+        }
+        Ok(())
+    }
+
+    /// Indices of the other notes split from the same change as `self.notes[index]`.
+    pub fn split_siblings(&self, index: usize) -> Vec<usize> {
+        let group = |n: &WalletNote| n.metadata.get(CHANGE_SPLIT_KEY).and_then(|v| v.get("group")).cloned();
+        let Some(wanted) = group(&self.notes[index]) else {
+            return Vec::new();
+        };
+        (0..self.notes.len()).filter(|&i| i != index && group(&self.notes[i]).as_ref() == Some(&wanted)).collect()
+    }
+
+    /// Add the transaction behind an audit entry to the history, once, and
+    /// mark the notes it spent as spent.
+    pub fn record_tx(&mut self, entry: &AuditEntry) {
+        let spent: Vec<String> = entry.spent.iter().map(|n| n.commitment.clone()).collect();
+        for note in self.notes.iter_mut().filter(|n| spent.contains(&n.commitment)) {
+            note.spent = true;
+        }
+        if self.transactions.iter().any(|t| t.tx_hash == entry.tx_hash) {
+            return;
+        }
+        self.transactions.push(WalletTx {
+            action: entry.action,
+            tx_hash: entry.tx_hash.clone(),
+            block_number: entry.block_number,
+            public_amount: entry.public_amount,
+            spent,
+            created: entry.created.iter().map(|n| n.commitment.clone()).collect(),
+            recorded_at: unix_now(),
+        });
+    }
+
+    /// The block `scan` continues from on `pool`, if it has scanned it before.
+    pub fn scan_cursor(&self, chain_id: u64, pool: &str) -> Option<u64> {
+        self.sync_cursor
+            .as_ref()
+            .filter(|c| c.chain_id == chain_id && c.pool.eq_ignore_ascii_case(pool))
+            .map(|c| c.next_block)
+    }
+
+    /// The wallet's idle-note policy, or the default one.
+    pub fn idle_policy(&self) -> IdlePolicy {
+        self.idle_policy.clone().unwrap_or_default()
+    }
+
+    /// Attach `body` to `note` for the owner of `recipient_viewing_pubkey`,
+    /// signed with `sender`'s viewing key, and record it as sent. Returns the
+    /// encrypted payload to submit as the note's encrypted output.
+    pub fn send_message(
+        &mut self,
+        sender: &SpendingKey,
+        recipient_viewing_pubkey: &PublicKey,
+        note: &Note,
+        body: MessageBody,
+    ) -> Result<Vec<u8>> {
+        let (viewing_secret, _) = derive_viewing_keypair(sender.as_bytes());
+        let message = Message::new(&viewing_secret, recipient_viewing_pubkey, note, body, unix_now())?;
+        let encrypted = encrypt_note_with_message(note, &message, recipient_viewing_pubkey)?;
+        self.messages.push(WalletMessage {
+            direction: MessageDirection::Sent,
+            counterparty: hex::encode(recipient_viewing_pubkey.as_bytes()),
+            commitment: hex::encode(note.commitment()),
+            message,
+        });
+        Ok(encrypted)
+    }
+
+    /// Trial-decrypt an encrypted output with each of `keys` and record the
+    /// verified message it carries. Returns `None` if the payload is not for
+    /// this wallet, carries no message, or was recorded already.
+    pub fn receive_message(&mut self, encrypted: &[u8], keys: &[SpendingKey]) -> Result<Option<&WalletMessage>> {
+        for key in keys {
+            let (viewing_secret, _) = derive_viewing_keypair(key.as_bytes());
+            let Some(received) = decrypt_message(encrypted, &viewing_secret)? else {
+                continue;
+            };
+            let commitment = hex::encode(received.note.commitment());
+            if self.messages.iter().any(|m| m.direction == MessageDirection::Received && m.commitment == commitment) {
+                return Ok(None);
+            }
+            self.messages.push(WalletMessage {
+                direction: MessageDirection::Received,
+                counterparty: received.message.sender.clone(),
+                commitment,
+                message: received.message,
+            });
+            return Ok(self.messages.last());
+        }
+        Ok(None)
+    }
+
+    /// Messages exchanged with `counterparty` (hex viewing pubkey), oldest first.
+    pub fn thread(&self, counterparty: &str) -> Vec<&WalletMessage> {
+        let counterparty = counterparty.strip_prefix("0x").unwrap_or(counterparty).to_lowercase();
+        let mut thread: Vec<&WalletMessage> = self.messages.iter().filter(|m| m.counterparty == counterparty).collect();
+        thread.sort_by_key(|m| m.message.sent_at);
+        thread
+    }
+
+    /// Every thread, keyed by counterparty viewing pubkey, each oldest first.
+    pub fn threads(&self) -> BTreeMap<&str, Vec<&WalletMessage>> {
+        let mut threads: BTreeMap<&str, Vec<&WalletMessage>> = BTreeMap::new();
+        for m in &self.messages {
+            threads.entry(&m.counterparty).or_default().push(m);
+        }
+        for thread in threads.values_mut() {
+            thread.sort_by_key(|m| m.message.sent_at);
+        }
+        threads
+    }
+
+    /// Unlock every spending key in the wallet.
+    ///
+    /// Encrypted keys are decrypted with a single passphrase (prompted once);
+    /// plaintext keys from older wallets are accepted as-is with a warning.
+    pub fn unlock_spending_keys(&self) -> Result<Vec<SpendingKey>> {
+        let mut passphrase: Option<String> = None;
+        let mut keys = Vec::with_capacity(self.spending_keys.len());
+        for entry in &self.spending_keys {
+            let sk = if let Some(ref ks) = entry.keystore {
+                if passphrase.is_none() {
+                    passphrase = Some(read_passphrase("Wallet passphrase: ", false)?);
+                }
+                ks.decrypt(passphrase.as_deref().unwrap())
+                    .with_context(|| format!("Failed to unlock key '{}'", entry.label))?
+            } else {
+                ensure!(
+                    !entry.spending_key.is_empty(),
+                    "Key '{}' has neither a keystore nor a plaintext spending key",
+                    entry.label
+                );
+                strict::fallback(StrictViolation::PlaintextKey { label: entry.label.clone() })?;
+                println!("    ⚠ Key '{}' is stored in plaintext", entry.label);
+                SpendingKey::from(decode_hex_fixed::<32>(&entry.spending_key)?)
+            };
+            keys.push(sk);
+        }
+        Ok(keys)
+    }
+
+    /// Bring a wallet written by an older version into the current form.
+    ///
+    /// Hex fields lose their 0x prefix and are lowercased, empty and duplicate
+    /// labels are renamed, and missing derivable fields are filled in: note
+    /// commitments, key pubkeys, and viewing pubkeys of plaintext keys. Stored
+    /// values that disagree with what they derive from are left for `verify`
+    /// to report. Returns one line per change.
+    pub fn canonicalize(&mut self) -> Result<Vec<String>> {
+        let mut fixes = Vec::new();
+
+        let mut labels = Vec::new();
+        for (i, k) in self.spending_keys.iter_mut().enumerate() {
+            let what = format!("key {i}");
+            canonical_label(&mut k.label, &format!("key_{i}"), &mut labels, &what, &mut fixes);
+            for (field, value) in [("spending_key", &mut k.spending_key), ("pubkey", &mut k.pubkey), ("viewing_pubkey", &mut k.viewing_pubkey)] {
+                canonical_hex(value, &format!("{} of key '{}'", field, k.label), &mut fixes)?;
+            }
+            if k.pubkey.is_empty() {
+                let derived = match (&k.keystore, k.spending_key.is_empty()) {
+                    (_, false) => Some(hex::encode(derive_pubkey(&decode_hex_fixed(&k.spending_key)?))),
+                    (Some(ks), true) => Some(ks.pubkey.trim_start_matches("0x").to_lowercase()),
+                    (None, true) => None,
+                };
+                if let Some(pubkey) = derived {
+                    fixes.push(format!("filled in pubkey of key '{}'", k.label));
+                    k.pubkey = pubkey;
+                }
+            }
+            if k.viewing_pubkey.is_empty() && !k.spending_key.is_empty() {
+                let (_, viewing) = derive_viewing_keypair(&decode_hex_fixed(&k.spending_key)?);
+                fixes.push(format!("filled in viewing_pubkey of key '{}'", k.label));
+                k.viewing_pubkey = hex::encode(viewing.as_bytes());
+            }
+        }
+
+        let mut labels = Vec::new();
+        for n in &mut self.notes {
+            let what = format!("note at leaf {}", n.leaf_index);
+            canonical_label(&mut n.label, &format!("note_{}", n.leaf_index), &mut labels, &what, &mut fixes);
+            for (field, value) in [("pubkey", &mut n.pubkey), ("blinding", &mut n.blinding), ("commitment", &mut n.commitment)] {
+                canonical_hex(value, &format!("{} of note '{}'", field, n.label), &mut fixes)?;
+            }
+            if let Some(lock) = &mut n.lock {
+                for (field, value) in [("lock recipient_pubkey", &mut lock.recipient_pubkey), ("lock sender_pubkey", &mut lock.sender_pubkey)] {
+                    canonical_hex(value, &format!("{} of note '{}'", field, n.label), &mut fixes)?;
+                }
+            }
+            if n.commitment.is_empty() {
+                n.commitment = hex::encode(n.to_note()?.commitment());
+                fixes.push(format!("recomputed missing commitment of note '{}'", n.label));
+            }
+        }
+        Ok(fixes)
+    }
+
+    /// Check every derived field against what it derives from: note
+    /// commitments, cancellable locks, and pubkeys of plaintext keys. Also
+    /// rejects notes stored twice. Lists every problem found.
+    pub fn verify(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        for k in &self.spending_keys {
+            if k.spending_key.is_empty() && k.keystore.is_none() {
+                problems.push(format!("key '{}' has neither a keystore nor a plaintext spending key", k.label));
+            }
+            if !k.spending_key.is_empty() {
+                let derived = hex::encode(derive_pubkey(&decode_hex_fixed(&k.spending_key)?));
+                if k.pubkey != derived {
+                    problems.push(format!("pubkey of key '{}' is {} but its spending key derives {}", k.label, k.pubkey, derived));
+                }
+            }
+            if let Some(ks) = &k.keystore {
+                if !ks.pubkey.trim_start_matches("0x").eq_ignore_ascii_case(&k.pubkey) {
+                    problems.push(format!("pubkey of key '{}' differs from its keystore's", k.label));
+                }
+            }
+        }
+        let mut seen = Vec::new();
+        for n in &self.notes {
+            let note = n.to_note()?;
+            let computed = hex::encode(note.commitment());
+            if n.commitment != computed {
+                problems.push(format!("commitment of note '{}' is {} but its fields hash to {}", n.label, n.commitment, computed));
+            }
+            if let Some(lock) = n.to_lock()? {
+                if !lock.locks(&note) {
+                    problems.push(format!("lock of note '{}' does not match its pubkey", n.label));
+                }
+            }
+            if seen.contains(&&n.commitment) {
+                problems.push(format!("note '{}' is stored more than once", n.label));
+            }
+            seen.push(&n.commitment);
+        }
+        ensure!(problems.is_empty(), "wallet does not verify:\n  - {}", problems.join("\n  - "));
+        Ok(())
+    }
+
+    /// Read a wallet file or database, canonicalizing older layouts in memory.
+    pub fn load(path: &Path) -> Result<Self> {
+        Ok(Self::load_raw(path)?.0)
+    }
+
+    /// `load`, or an empty wallet if there is nothing at `path` (and no JSON
+    /// wallet beside it waiting to be migrated).
+    pub fn load_or_default(path: &Path) -> Result<Self> {
+        let legacy = wallet_store::is_store_path(path) && path.with_extension("json").exists();
+        if path.exists() || legacy {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// Read a wallet file or database and canonicalize it, returning the
+    /// changes made.
+    pub fn load_raw(path: &Path) -> Result<(Self, Vec<String>)> {
+        let mut state: Self = if wallet_store::is_store_path(path) {
+            let legacy = path.with_extension("json");
+            ensure!(
+                path.exists() || !legacy.exists(),
+                "{} does not exist; import {} with `wallet migrate`",
+                path.display(),
+                legacy.display()
+            );
+            wallet_store::load(path)?
+        } else {
+            let json = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read wallet file: {}", path.display()))?;
+            serde_json::from_str(&json).with_context(|| format!("Failed to parse wallet file: {}", path.display()))?
+        };
+        let fixes = state.canonicalize()?;
+        Ok((state, fixes))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if wallet_store::is_store_path(path) {
+            return wallet_store::save(self, path);
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Strip a 0x prefix and lowercase `value`, which must be empty or 32 bytes of hex.
+fn canonical_hex(value: &mut String, what: &str, fixes: &mut Vec<String>) -> Result<()> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    let canonical = value.strip_prefix("0x").unwrap_or(value).to_lowercase();
+    decode_hex_fixed::<32>(&canonical).with_context(|| format!("invalid {what}"))?;
+    if *value != canonical {
+        fixes.push(format!("normalized hex of {what}"));
+        *value = canonical;
+    }
+    Ok(())
+}
+
+/// Give an empty label `fallback`, and a label already in `taken` a numeric suffix.
+fn canonical_label(label: &mut String, fallback: &str, taken: &mut Vec<String>, what: &str, fixes: &mut Vec<String>) {
+    let trimmed = label.trim();
+    let base = if trimmed.is_empty() { fallback } else { trimmed };
+    let mut candidate = base.to_string();
+    let mut n = 2;
+    while taken.contains(&candidate) {
+        candidate = format!("{base}_{n}");
+        n += 1;
+    }
+    if *label != candidate {
+        fixes.push(format!("relabeled {what} '{label}' as '{candidate}'"));
+        *label = candidate.clone();
+    }
+    taken.push(candidate);
+}
+
+/// Accept an amount as a JSON number or a decimal string (older wallets and
+/// the TS SDK write strings).
+fn amount_from_number_or_string<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Number(u64),
+        String(String),
+    }
+    match Amount::deserialize(d)? {
+        Amount::Number(n) => Ok(n),
+        Amount::String(s) => s.trim().parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// Parse a `KEY` or `KEY=VALUE` metadata argument. VALUE is JSON; anything
+/// that does not parse as JSON is taken as a plain string.
+pub fn parse_metadata_arg(arg: &str) -> Result<(String, Option<serde_json::Value>)> {
+    let (key, value) = match arg.split_once('=') {
+        Some((k, v)) => (k, Some(serde_json::from_str(v).unwrap_or_else(|_| v.into()))),
+        None => (arg, None),
+    };
+    validate_metadata_key(key)?;
+    Ok((key.to_string(), value))
+}
+
+/// fixtures/wallet.db at the repo root (where e2e writes its wallet).
+pub fn default_wallet_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("fixtures/wallet.db")
+}
+
+/// `WALLET_FILE` if set, otherwise the default wallet path.
+pub fn wallet_path() -> PathBuf {
+    std::env::var("WALLET_FILE").map(PathBuf::from).unwrap_or_else(|_| default_wallet_path())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [0xab; 32];
+
+    fn note(amount: u64, blinding: u8) -> Note {
+        Note { amount, pubkey: derive_pubkey(&KEY), blinding: [blinding; 32] }
+    }
+
+    /// The wallet the legacy file below canonicalizes to.
+    fn canonical() -> WalletState {
+        let (_, viewing) = derive_viewing_keypair(&KEY);
+        let mut first = WalletNote::new("deposit", &note(1_000_000, 0xc1), 0);
+        first.touched_at = None;
+        let mut second = WalletNote::new("deposit_2", &note(400_000, 0xc2), 1);
+        second.touched_at = Some(1_700_000_000);
+        WalletState {
+            spending_keys: vec![WalletSpendingKey {
+                label: "key_0".into(),
+                spending_key: hex::encode(KEY),
+                keystore: None,
+                pubkey: hex::encode(derive_pubkey(&KEY)),
+                viewing_pubkey: hex::encode(viewing.as_bytes()),
+            }],
+            notes: vec![first, second],
+            ..WalletState::default()
+        }
+    }
+
+    /// `canonical()` as an older e2e wrote it: camelCase names, an amount as
+    /// a string, 0x-prefixed uppercase hex, an empty and a duplicate label,
+    /// and no pubkeys or first commitment.
+    fn legacy() -> String {
+        let hex = |bytes: [u8; 32]| format!("0x{}", hex::encode_upper(bytes));
+        serde_json::json!({
+            "spendingKeys": [{ "label": " ", "spendingKey": hex(KEY) }],
+            "notes": [
+                { "label": "deposit", "amount": "1000000", "pubkey": hex(derive_pubkey(&KEY)),
+                  "blinding": hex([0xc1; 32]), "leafIndex": 0 },
+                { "label": "deposit", "amount": 400000, "pubkey": hex(derive_pubkey(&KEY)),
+                  "blinding": hex([0xc2; 32]), "commitment": hex(note(400_000, 0xc2).commitment()),
+                  "leafIndex": 1, "touchedAt": 1700000000 },
+            ],
+        })
+        .to_string()
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wallet-{name}-{}.json", std::process::id()))
+    }
+
+    #[test]
+    fn test_serializing_a_wallet_twice_gives_the_same_bytes() {
+        let mut state = canonical();
+        state.notes[0].set_metadata("shop:order", Some("A-17".into())).unwrap();
+        state.notes[0].set_metadata("crm:customer", Some(42.into())).unwrap();
+        let bytes = serde_json::to_string_pretty(&state).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&state).unwrap(), bytes);
+
+        // Metadata set in another order serializes the same
+        let mut reordered = canonical();
+        reordered.notes[0].set_metadata("crm:customer", Some(42.into())).unwrap();
+        reordered.notes[0].set_metadata("shop:order", Some("A-17".into())).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&reordered).unwrap(), bytes);
+
+        // Saving, loading and saving again writes the same file
+        let path = temp_file("twice");
+        state.save(&path).unwrap();
+        let (loaded, fixes) = WalletState::load_raw(&path).unwrap();
+        assert!(fixes.is_empty(), "{fixes:?}");
+        loaded.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), bytes);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_wallet_loads_in_canonical_form() {
+        let path = temp_file("legacy");
+        std::fs::write(&path, legacy()).unwrap();
+        let (state, fixes) = WalletState::load_raw(&path).unwrap();
+        state.verify().unwrap();
+        let expected = serde_json::to_string_pretty(&canonical()).unwrap();
+        assert_eq!(serde_json::to_string_pretty(&state).unwrap(), expected);
+        for fix in [
+            "relabeled key 0 ' ' as 'key_0'",
+            "normalized hex of spending_key of key 'key_0'",
+            "filled in pubkey of key 'key_0'",
+            "filled in viewing_pubkey of key 'key_0'",
+            "relabeled note at leaf 1 'deposit' as 'deposit_2'",
+            "normalized hex of blinding of note 'deposit'",
+            "normalized hex of commitment of note 'deposit_2'",
+            "recomputed missing commitment of note 'deposit'",
+        ] {
+            assert!(fixes.iter().any(|f| f == fix), "{fix:?} not in {fixes:?}");
+        }
+
+        // Once rewritten, the file is canonical and loads without fixes
+        state.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        assert!(WalletState::load_raw(&path).unwrap().1.is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+}
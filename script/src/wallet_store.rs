@@ -0,0 +1,432 @@
+//! SQLite storage of the wallet.
+//!
+//! A wallet path ending in `.db`, `.sqlite` or `.sqlite3` is a database with
+//! one table per part of `WalletState`: spending keys, notes (amount, leaf
+//! index, label, commitment and whether the nullifier is spent in columns of
+//! their own), messages, the transaction history, and a settings table for
+//! the idle policy and the scan cursor. Nested values (keystores, metadata,
+//! locks, message bodies) are stored as JSON text.
+//!
+//! `save` rewrites every table in one transaction, so an interrupted save
+//! leaves the previous wallet intact. The schema version is kept in
+//! `PRAGMA user_version`; a database of another version is refused rather
+//! than misread.
+//!
+//! `migrate` imports a JSON wallet. It only reads the JSON file, and counts
+//! the import done once the database has committed and reads back as the
+//! same wallet.
+
+use anyhow::{bail, ensure, Context, Result};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+use crate::wallet::{WalletNote, WalletSpendingKey, WalletState, WalletTx};
+
+/// Version of the tables below, in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS spending_keys (
+    position INTEGER PRIMARY KEY,
+    label TEXT NOT NULL,
+    spending_key TEXT NOT NULL,
+    keystore TEXT,
+    pubkey TEXT NOT NULL,
+    viewing_pubkey TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS notes (
+    position INTEGER PRIMARY KEY,
+    label TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    pubkey TEXT NOT NULL,
+    blinding TEXT NOT NULL,
+    commitment TEXT NOT NULL,
+    leaf_index INTEGER NOT NULL,
+    spent INTEGER NOT NULL,
+    metadata TEXT NOT NULL,
+    lock TEXT,
+    touched_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS notes_commitment ON notes (commitment);
+CREATE TABLE IF NOT EXISTS messages (
+    position INTEGER PRIMARY KEY,
+    message TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS transactions (
+    position INTEGER PRIMARY KEY,
+    action TEXT NOT NULL,
+    tx_hash TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    public_amount INTEGER,
+    spent TEXT NOT NULL,
+    created TEXT NOT NULL,
+    recorded_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// Whether `path` names a wallet database rather than a JSON file.
+pub fn is_store_path(path: &Path) -> bool {
+    path.extension().and_then(|e| e.to_str()).is_some_and(|e| ["db", "sqlite", "sqlite3"].contains(&e))
+}
+
+/// Read the wallet in the database at `path`.
+pub fn load(path: &Path) -> Result<WalletState> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open wallet database: {}", path.display()))?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    ensure!(
+        version == SCHEMA_VERSION,
+        "{} has wallet schema version {}, this build reads version {}",
+        path.display(),
+        version,
+        SCHEMA_VERSION
+    );
+    let mut state = WalletState::default();
+
+    let mut stmt = conn.prepare(
+        "SELECT label, spending_key, keystore, pubkey, viewing_pubkey FROM spending_keys ORDER BY position",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        state.spending_keys.push(WalletSpendingKey {
+            label: row.get(0)?,
+            spending_key: row.get(1)?,
+            keystore: row.get::<_, Option<String>>(2)?.map(|ks| from_json(&ks, "keystore")).transpose()?,
+            pubkey: row.get(3)?,
+            viewing_pubkey: row.get(4)?,
+        });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT label, amount, pubkey, blinding, commitment, leaf_index, spent, metadata, lock, touched_at
+         FROM notes ORDER BY position",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        state.notes.push(WalletNote {
+            label: row.get(0)?,
+            amount: row.get(1)?,
+            pubkey: row.get(2)?,
+            blinding: row.get(3)?,
+            commitment: row.get(4)?,
+            leaf_index: row.get(5)?,
+            spent: row.get(6)?,
+            metadata: from_json(&row.get::<_, String>(7)?, "note metadata")?,
+            lock: row.get::<_, Option<String>>(8)?.map(|lock| from_json(&lock, "note lock")).transpose()?,
+            touched_at: row.get(9)?,
+        });
+    }
+
+    let mut stmt = conn.prepare("SELECT message FROM messages ORDER BY position")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        state.messages.push(from_json(&row.get::<_, String>(0)?, "message")?);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT action, tx_hash, block_number, public_amount, spent, created, recorded_at
+         FROM transactions ORDER BY position",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        state.transactions.push(WalletTx {
+            action: from_json(&row.get::<_, String>(0)?, "transaction action")?,
+            tx_hash: row.get(1)?,
+            block_number: row.get(2)?,
+            public_amount: row.get(3)?,
+            spent: from_json(&row.get::<_, String>(4)?, "spent notes")?,
+            created: from_json(&row.get::<_, String>(5)?, "created notes")?,
+            recorded_at: row.get(6)?,
+        });
+    }
+
+    let mut stmt = conn.prepare("SELECT name, value FROM settings")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let (name, value): (String, String) = (row.get(0)?, row.get(1)?);
+        match name.as_str() {
+            "idle_policy" => state.idle_policy = Some(from_json(&value, "idle policy")?),
+            "sync_cursor" => state.sync_cursor = Some(from_json(&value, "sync cursor")?),
+            _ => {}
+        }
+    }
+    Ok(state)
+}
+
+/// Write `state` to the database at `path`, creating it if missing.
+pub fn save(state: &WalletState, path: &Path) -> Result<()> {
+    let mut conn =
+        Connection::open(path).with_context(|| format!("Failed to open wallet database: {}", path.display()))?;
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    ensure!(
+        version == 0 || version == SCHEMA_VERSION,
+        "{} has wallet schema version {}, this build writes version {}",
+        path.display(),
+        version,
+        SCHEMA_VERSION
+    );
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    tx.execute_batch(
+        "DELETE FROM spending_keys; DELETE FROM notes; DELETE FROM messages;
+         DELETE FROM transactions; DELETE FROM settings;",
+    )?;
+
+    for (i, k) in state.spending_keys.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO spending_keys (position, label, spending_key, keystore, pubkey, viewing_pubkey)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                i,
+                k.label,
+                k.spending_key,
+                k.keystore.as_ref().map(serde_json::to_string).transpose()?,
+                k.pubkey,
+                k.viewing_pubkey
+            ],
+        )?;
+    }
+    for (i, n) in state.notes.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO notes (position, label, amount, pubkey, blinding, commitment, leaf_index, spent, metadata,
+                                lock, touched_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                i,
+                n.label,
+                n.amount,
+                n.pubkey,
+                n.blinding,
+                n.commitment,
+                n.leaf_index,
+                n.spent,
+                serde_json::to_string(&n.metadata)?,
+                n.lock.as_ref().map(serde_json::to_string).transpose()?,
+                n.touched_at
+            ],
+        )?;
+    }
+    for (i, m) in state.messages.iter().enumerate() {
+        tx.execute("INSERT INTO messages (position, message) VALUES (?1, ?2)", params![i, serde_json::to_string(m)?])?;
+    }
+    for (i, t) in state.transactions.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO transactions (position, action, tx_hash, block_number, public_amount, spent, created,
+                                       recorded_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                i,
+                serde_json::to_string(&t.action)?,
+                t.tx_hash,
+                t.block_number,
+                t.public_amount,
+                serde_json::to_string(&t.spent)?,
+                serde_json::to_string(&t.created)?,
+                t.recorded_at
+            ],
+        )?;
+    }
+    let settings = [
+        ("idle_policy", state.idle_policy.as_ref().map(serde_json::to_string).transpose()?),
+        ("sync_cursor", state.sync_cursor.as_ref().map(serde_json::to_string).transpose()?),
+    ];
+    for (name, value) in settings {
+        if let Some(value) = value {
+            tx.execute("INSERT INTO settings (name, value) VALUES (?1, ?2)", params![name, value])?;
+        }
+    }
+
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// What `migrate` did.
+#[derive(Debug)]
+pub enum Migration {
+    /// The JSON wallet was imported, after the listed canonicalization fixes
+    Imported { state: WalletState, fixes: Vec<String> },
+    /// The database already holds the JSON wallet from an earlier run
+    AlreadyDone,
+}
+
+/// Import the JSON wallet at `from` into a new database at `to`.
+///
+/// A database that does not read back as imported is removed again. A
+/// database left by an import that never committed is overwritten, and one
+/// that already holds the same wallet is left alone, so an interrupted import
+/// can simply be run again.
+pub fn migrate(from: &Path, to: &Path) -> Result<Migration> {
+    ensure!(is_store_path(to), "{} is not a wallet database path (.db)", to.display());
+    ensure!(!is_store_path(from), "{} is not a JSON wallet", from.display());
+    let (state, fixes) = WalletState::load_raw(from)?;
+    state.verify()?;
+
+    if to.exists() && schema_version(to)? != 0 {
+        ensure!(
+            same_wallet(&WalletState::load(to)?, &state)?,
+            "{} already exists and holds another wallet",
+            to.display()
+        );
+        return Ok(Migration::AlreadyDone);
+    }
+    state.save(to)?;
+    if !same_wallet(&WalletState::load(to)?, &state)? {
+        std::fs::remove_file(to)?;
+        bail!("{} did not read back as imported; removed it", to.display());
+    }
+    Ok(Migration::Imported { state, fixes })
+}
+
+/// `PRAGMA user_version` of the database at `path`; 0 until a save commits.
+fn schema_version(path: &Path) -> Result<i64> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open wallet database: {}", path.display()))?;
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+fn same_wallet(a: &WalletState, b: &WalletState) -> Result<bool> {
+    Ok(serde_json::to_value(a)? == serde_json::to_value(b)?)
+}
+
+fn from_json<T: DeserializeOwned>(json: &str, what: &str) -> Result<T> {
+    serde_json::from_str(json).with_context(|| format!("invalid {what} in wallet database"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        derive_viewing_keypair,
+        wallet::{SyncCursor, WalletNote},
+    };
+    use shielded_pool_lib::{derive_pubkey, Note};
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wallet-store-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A key, one spent and one unspent note, a scan cursor and a transaction.
+    fn wallet() -> WalletState {
+        let spending_key = [7u8; 32];
+        let pubkey = derive_pubkey(&spending_key);
+        let (_, viewing) = derive_viewing_keypair(&spending_key);
+        let note = |amount, blinding| Note { amount, pubkey, blinding: [blinding; 32] };
+        let mut spent = WalletNote::new("deposit_0", &note(1_000_000, 1), 0);
+        spent.spent = true;
+        WalletState {
+            spending_keys: vec![WalletSpendingKey {
+                label: "alice".into(),
+                spending_key: hex::encode(spending_key),
+                keystore: None,
+                pubkey: hex::encode(pubkey),
+                viewing_pubkey: hex::encode(viewing.as_bytes()),
+            }],
+            notes: vec![spent, WalletNote::new("change_1", &note(400_000, 2), 1)],
+            sync_cursor: Some(SyncCursor {
+                chain_id: 9746,
+                pool: format!("0x{}", "aa".repeat(20)),
+                next_block: 1_234,
+                block_hash: Some(format!("0x{}", "cc".repeat(32))),
+            }),
+            transactions: vec![WalletTx {
+                action: crate::audit::AuditAction::Withdraw,
+                tx_hash: "0x01".into(),
+                block_number: 1_200,
+                public_amount: Some(600_000),
+                spent: vec!["deposit_0".into()],
+                created: vec!["change_1".into()],
+                recorded_at: 1_700_000_000,
+            }],
+            ..WalletState::default()
+        }
+    }
+
+    /// `wallet()` as a JSON wallet in `dir`, and the database path beside it.
+    fn legacy(dir: &Path) -> (PathBuf, PathBuf) {
+        let json = dir.join("wallet.json");
+        wallet().save(&json).unwrap();
+        (json, dir.join("wallet.db"))
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let dir = temp_dir("round-trip");
+        let path = dir.join("wallet.db");
+        let mut state = wallet();
+        save(&state, &path).unwrap();
+        assert!(same_wallet(&load(&path).unwrap(), &state).unwrap());
+        // A save replaces every table, leaving no rows of the previous wallet
+        state.notes.pop();
+        state.sync_cursor = None;
+        save(&state, &path).unwrap();
+        assert!(same_wallet(&load(&path).unwrap(), &state).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_keeps_notes_spent_flags_and_sync_height() {
+        let dir = temp_dir("migrate");
+        let (json, db) = legacy(&dir);
+        let Migration::Imported { state, fixes } = migrate(&json, &db).unwrap() else { panic!("not imported") };
+        assert!(fixes.is_empty(), "{fixes:?}");
+        let imported = WalletState::load(&db).unwrap();
+        assert!(same_wallet(&imported, &state).unwrap());
+        let notes: Vec<(&str, u64, u32, bool)> =
+            imported.notes.iter().map(|n| (n.label.as_str(), n.amount, n.leaf_index, n.spent)).collect();
+        assert_eq!(notes, [("deposit_0", 1_000_000, 0, true), ("change_1", 400_000, 1, false)]);
+        assert_eq!(imported.sync_cursor, wallet().sync_cursor);
+        assert_eq!(imported.transactions, wallet().transactions);
+        assert_eq!(imported.spending_keys[0].spending_key, wallet().spending_keys[0].spending_key);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_leaves_the_json_wallet() {
+        let dir = temp_dir("keep-json");
+        let (json, db) = legacy(&dir);
+        let original = std::fs::read(&json).unwrap();
+
+        // A failed import: the database cannot be created
+        let blocked = json.join("wallet.db");
+        assert!(migrate(&json, &blocked).is_err());
+        assert_eq!(std::fs::read(&json).unwrap(), original);
+
+        // An import interrupted before it committed leaves an empty database
+        std::fs::write(&db, b"").unwrap();
+        let Migration::Imported { state, .. } = migrate(&json, &db).unwrap() else { panic!("not imported") };
+        assert!(same_wallet(&WalletState::load(&db).unwrap(), &state).unwrap());
+
+        // Nor is the JSON removed once the import committed
+        assert_eq!(std::fs::read(&json).unwrap(), original);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_twice_changes_nothing() {
+        let dir = temp_dir("twice");
+        let (json, db) = legacy(&dir);
+        assert!(matches!(migrate(&json, &db).unwrap(), Migration::Imported { .. }));
+        let imported = std::fs::read(&db).unwrap();
+        assert!(matches!(migrate(&json, &db).unwrap(), Migration::AlreadyDone));
+        assert_eq!(std::fs::read(&db).unwrap(), imported);
+
+        // A database holding another wallet is refused, not overwritten
+        let mut other = wallet();
+        other.notes[1].spent = true;
+        other.save(&json).unwrap();
+        let error = format!("{:#}", migrate(&json, &db).unwrap_err());
+        assert!(error.contains("holds another wallet"), "{error}");
+        assert_eq!(std::fs::read(&db).unwrap(), imported);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,442 @@
+//! JSON-RPC 2.0 interface of the wallet, for `walletd`.
+//!
+//! `walletd` keeps the wallet unlocked in one long-running process and
+//! serves it on localhost, so GUIs and scripts in other languages can drive
+//! it without shelling out to the CLI and parsing its output. Each call is a
+//! POST of one JSON-RPC request to `/`:
+//!   - `getBalance`     — each note's status and the unspent total, as `balance`
+//!   - `listNotes`      — the wallet's notes, without syncing (`{includeSpent}`)
+//!   - `createTransfer` — `{to, amount, viewingPubkey?, from?}`, as `send`
+//!   - `createWithdraw` — `{amount?, note?, to?}`, as `withdraw --submit`
+//!   - `getSyncStatus`  — sync the pool tree and report how far it is verified
+//!
+//! Amounts are USDT strings in requests ("0.5") and base units in results.
+//! Blinding factors and keys never leave the daemon.
+//!
+//! The daemon holds unlocked spending keys, so every request must carry
+//! `Authorization: Bearer <token>`. The token is `WALLETD_TOKEN`, or else a
+//! random one generated at startup and written to a file only its owner can
+//! read (`walletd.token` next to the wallet), which local clients read it
+//! from. The server only listens on 127.0.0.1 and refuses any `Host` but
+//! `127.0.0.1:<port>` or `localhost:<port>`, so a web page that rebinds its
+//! own domain to 127.0.0.1 cannot reach it, and it only takes
+//! `application/json` bodies. Tokens are compared in constant time.
+
+use anyhow::{Context, Result};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use shielded_pool_lib::ct_eq_32;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+use crate::entropy;
+use crate::wallet::{NoteMetadata, WalletNote};
+
+/// The methods `walletd` serves.
+pub const METHODS: &[&str] = &["getBalance", "listNotes", "createTransfer", "createWithdraw", "getSyncStatus"];
+
+/// JSON-RPC error codes.
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+/// A call that was understood but failed (sync, proving, the transaction)
+pub const CALL_FAILED: i64 = -32000;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        RpcError { code: CALL_FAILED, message: format!("{e:#}") }
+    }
+}
+
+/// Answers one call: the method name and its params (`null` when omitted).
+pub type Handler = dyn Fn(&str, Value) -> Result<Value, RpcError> + Send + Sync;
+
+/// Decode a method's params; omitted params read as `{}`.
+pub fn params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = if params.is_null() { Value::Object(Default::default()) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError { code: INVALID_PARAMS, message: e.to_string() })
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ListNotesParams {
+    #[serde(default)]
+    pub include_spent: bool,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TransferParams {
+    pub to: String,
+    pub amount: String,
+    pub viewing_pubkey: Option<String>,
+    pub from: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct WithdrawParams {
+    pub amount: Option<String>,
+    pub note: Option<String>,
+    pub to: Option<String>,
+}
+
+/// A wallet note as `listNotes` returns it: everything but its blinding.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteView {
+    pub label: String,
+    pub amount: u64,
+    pub pubkey: String,
+    pub commitment: String,
+    pub leaf_index: u32,
+    pub spent: bool,
+    /// Deadline until which the sender may reclaim a cancellable note
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reclaim_deadline: Option<u64>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: NoteMetadata,
+}
+
+impl From<&WalletNote> for NoteView {
+    fn from(note: &WalletNote) -> Self {
+        NoteView {
+            label: note.label.clone(),
+            amount: note.amount,
+            pubkey: note.pubkey.clone(),
+            commitment: note.commitment.clone(),
+            leaf_index: note.leaf_index,
+            spent: note.spent,
+            reclaim_deadline: note.lock.as_ref().map(|lock| lock.reclaim_deadline),
+            metadata: note.metadata.clone(),
+        }
+    }
+}
+
+/// The result of `getBalance` (and what `balance` prints).
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Balance {
+    /// Block the pool was synced to
+    pub block: u64,
+    pub notes: Vec<NoteBalance>,
+    pub unspent: u64,
+    pub unspent_notes: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteBalance {
+    pub label: String,
+    pub amount: u64,
+    pub leaf_index: u32,
+    /// "unspent", "spent", or why the note cannot be counted or spent yet
+    pub status: String,
+}
+
+/// The result of `createTransfer`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Transferred {
+    pub tx_hash: String,
+    pub amount: u64,
+    pub payment_leaf: u32,
+    pub change: u64,
+    pub change_leaf: u32,
+}
+
+/// The result of `createWithdraw`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Withdrawn {
+    pub tx_hash: String,
+    pub amount: u64,
+    pub recipient: String,
+    pub change: u64,
+    /// Label of the change note added to the wallet, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_note: Option<String>,
+}
+
+/// The result of `getSyncStatus`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncReport {
+    pub block: u64,
+    pub on_chain_leaves: u32,
+    pub local_leaves: u32,
+    /// Leaves proofs can be built over: they hash to `verifiedRoot`
+    pub verified_leaves: u32,
+    pub verified_root: Option<String>,
+    pub first_divergence: Option<u32>,
+    pub complete: bool,
+}
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct Daemon {
+    port: u16,
+    token: String,
+    handler: Arc<Handler>,
+}
+
+impl Daemon {
+    /// Only names of the loopback address reach the daemon; any other `Host`
+    /// is a page that resolved its own domain to 127.0.0.1.
+    fn host_allowed(&self, headers: &HeaderMap) -> bool {
+        let host = headers.get(header::HOST).and_then(|v| v.to_str().ok());
+        host.is_some_and(|host| {
+            host == format!("127.0.0.1:{}", self.port) || host == format!("localhost:{}", self.port)
+        })
+    }
+
+    fn authorized(&self, headers: &HeaderMap) -> bool {
+        let bearer = headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok());
+        let Some(token) = bearer.and_then(|v| v.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        // Hashed first, so the comparison takes as long whatever the lengths
+        let digest = |token: &str| -> [u8; 32] { Sha256::digest(token.as_bytes()).into() };
+        ct_eq_32(&digest(token), &digest(&self.token))
+    }
+}
+
+/// The token requests must carry: `WALLETD_TOKEN`, or else a fresh random
+/// one, written to `token_file` readable by its owner only (on Unix).
+pub fn token(token_file: &Path) -> Result<String> {
+    if let Some(token) = std::env::var("WALLETD_TOKEN").ok().filter(|token| !token.is_empty()) {
+        return Ok(token);
+    }
+    let token = hex::encode(entropy::random_bytes::<32>()?);
+    write_token(token_file, &token).with_context(|| format!("could not write {}", token_file.display()))?;
+    println!("Token written to {} (clients send it as a bearer token)", token_file.display());
+    Ok(token)
+}
+
+fn write_token(path: &Path, token: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    // A file left by an earlier run may be readable by others; never reuse it
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())
+}
+
+fn reply(id: Value, outcome: Result<Value, RpcError>) -> Json<Value> {
+    Json(match outcome {
+        Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    })
+}
+
+/// Serve `handler` on 127.0.0.1:`port` until the process ends, to requests
+/// carrying `token`.
+pub async fn serve(port: u16, token: String, handler: Arc<Handler>) -> Result<()> {
+    let app = Router::new().route("/", post(call)).with_state(Arc::new(Daemon { port, token, handler }));
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!("Serving JSON-RPC on http://127.0.0.1:{port}");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn call(State(daemon): State<Arc<Daemon>>, headers: HeaderMap, body: String) -> Response {
+    if !daemon.host_allowed(&headers) {
+        return (StatusCode::FORBIDDEN, "walletd only answers 127.0.0.1 and localhost").into_response();
+    }
+    if !daemon.authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let json = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if !json.is_some_and(|v| v.starts_with("application/json")) {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "walletd takes application/json").into_response();
+    }
+
+    let request: RpcRequest = match serde_json::from_str(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let code = if serde_json::from_str::<Value>(&body).is_ok() { INVALID_REQUEST } else { PARSE_ERROR };
+            return reply(Value::Null, Err(RpcError { code, message: e.to_string() })).into_response();
+        }
+    };
+    if request.jsonrpc != "2.0" {
+        let error = RpcError { code: INVALID_REQUEST, message: "jsonrpc must be \"2.0\"".into() };
+        return reply(request.id, Err(error)).into_response();
+    }
+    if !METHODS.contains(&request.method.as_str()) {
+        let error = RpcError { code: METHOD_NOT_FOUND, message: format!("no method {}", request.method) };
+        return reply(request.id, Err(error)).into_response();
+    }
+
+    // Calls sync, prove and send like the CLI does, on their own runtime
+    let handler = daemon.handler.clone();
+    let RpcRequest { id, method, params, .. } = request;
+    let outcome = tokio::task::spawn_blocking(move || handler(&method, params))
+        .await
+        .unwrap_or_else(|e| Err(RpcError { code: CALL_FAILED, message: format!("the call panicked: {e}") }));
+    reply(id, outcome).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    const TOKEN: &str = "secret-token";
+
+    /// Answers `listNotes` with its decoded params and fails everything else.
+    fn daemon() -> Arc<Daemon> {
+        let handler = |method: &str, params: Value| -> Result<Value, RpcError> {
+            match method {
+                "listNotes" => {
+                    let ListNotesParams { include_spent } = super::params(params)?;
+                    Ok(serde_json::json!({ "includeSpent": include_spent }))
+                }
+                _ => Err(anyhow::anyhow!("{method} failed").into()),
+            }
+        };
+        Arc::new(Daemon { port: 3004, token: TOKEN.into(), handler: Arc::new(handler) })
+    }
+
+    fn headers(host: &str, token: Option<&str>, content_type: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_str(host).unwrap());
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+        if let Some(token) = token {
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {token}")).unwrap());
+        }
+        headers
+    }
+
+    async fn post(headers: HeaderMap, body: &str) -> (StatusCode, Option<Value>) {
+        let response = call(State(daemon()), headers, body.into()).await;
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).ok())
+    }
+
+    async fn rpc(body: &str) -> Value {
+        let (status, reply) = post(headers("127.0.0.1:3004", Some(TOKEN), "application/json"), body).await;
+        assert_eq!(status, StatusCode::OK);
+        reply.unwrap()
+    }
+
+    const LIST: &str = r#"{"jsonrpc":"2.0","id":1,"method":"listNotes"}"#;
+
+    #[tokio::test]
+    async fn test_call_requires_the_token() {
+        assert_eq!(rpc(LIST).await["result"], serde_json::json!({ "includeSpent": false }));
+        let json = "application/json";
+        assert_eq!(post(headers("127.0.0.1:3004", None, json), LIST).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(post(headers("127.0.0.1:3004", Some("secret"), json), LIST).await.0, StatusCode::UNAUTHORIZED);
+        let mut basic = headers("127.0.0.1:3004", None, json);
+        basic.insert(header::AUTHORIZATION, HeaderValue::from_static("Basic c2VjcmV0LXRva2Vu"));
+        assert_eq!(post(basic, LIST).await.0, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_call_refuses_other_hosts() {
+        let json = "application/json";
+        assert_eq!(post(headers("localhost:3004", Some(TOKEN), json), LIST).await.0, StatusCode::OK);
+        // A rebound domain, the wrong port, and no port at all
+        for host in ["evil.example:3004", "127.0.0.1:3005", "127.0.0.1", "localhost"] {
+            assert_eq!(post(headers(host, Some(TOKEN), json), LIST).await.0, StatusCode::FORBIDDEN, "{host}");
+        }
+        let mut no_host = headers("127.0.0.1:3004", Some(TOKEN), json);
+        no_host.remove(header::HOST);
+        assert_eq!(post(no_host, LIST).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_call_takes_only_json() {
+        for content_type in ["text/plain", "application/x-www-form-urlencoded", "multipart/form-data"] {
+            let (status, _) = post(headers("127.0.0.1:3004", Some(TOKEN), content_type), LIST).await;
+            assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{content_type}");
+        }
+        let charset = headers("127.0.0.1:3004", Some(TOKEN), "application/json; charset=utf-8");
+        assert_eq!(post(charset, LIST).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_call_reports_json_rpc_errors() {
+        let error = |reply: Value| (reply["error"]["code"].as_i64().unwrap(), reply["id"].clone());
+        assert_eq!(error(rpc("{not json").await), (PARSE_ERROR, Value::Null));
+        assert_eq!(error(rpc(r#"{"id":1,"method":"listNotes"}"#).await), (INVALID_REQUEST, Value::Null));
+        let old = r#"{"jsonrpc":"1.0","id":2,"method":"listNotes"}"#;
+        assert_eq!(error(rpc(old).await), (INVALID_REQUEST, 2.into()));
+        let unknown = r#"{"jsonrpc":"2.0","id":3,"method":"dumpKeys"}"#;
+        assert_eq!(error(rpc(unknown).await), (METHOD_NOT_FOUND, 3.into()));
+        let failing = r#"{"jsonrpc":"2.0","id":4,"method":"getBalance"}"#;
+        let reply = rpc(failing).await;
+        assert_eq!(error(reply.clone()), (CALL_FAILED, 4.into()));
+        assert_eq!(reply["error"]["message"], "getBalance failed");
+    }
+
+    #[tokio::test]
+    async fn test_call_decodes_params() {
+        let with = |params: &str| format!(r#"{{"jsonrpc":"2.0","id":"a","method":"listNotes","params":{params}}}"#);
+        assert_eq!(rpc(&with(r#"{"includeSpent":true}"#)).await["result"]["includeSpent"], true);
+        assert_eq!(rpc(&with("{}")).await["result"]["includeSpent"], false);
+        assert_eq!(rpc(&with("null")).await["result"]["includeSpent"], false);
+        for bad in [r#"{"include_spent":true}"#, r#"{"includeSpent":"yes"}"#, "3"] {
+            let reply = rpc(&with(bad)).await;
+            assert_eq!(reply["error"]["code"], INVALID_PARAMS, "{bad}");
+            assert_eq!(reply["id"], "a");
+        }
+    }
+
+    #[test]
+    fn test_transfer_params_are_camel_case_and_strict() {
+        let params: TransferParams =
+            super::params(serde_json::json!({ "to": "bob", "amount": "0.5", "viewingPubkey": "0x01" })).unwrap();
+        assert_eq!((params.to.as_str(), params.amount.as_str()), ("bob", "0.5"));
+        assert_eq!((params.viewing_pubkey.as_deref(), params.from), (Some("0x01"), None));
+        let missing = super::params::<TransferParams>(serde_json::json!({ "to": "bob" })).unwrap_err();
+        assert_eq!(missing.code, INVALID_PARAMS);
+        let extra = super::params::<WithdrawParams>(serde_json::json!({ "recipient": "0x00" })).unwrap_err();
+        assert_eq!(extra.code, INVALID_PARAMS);
+        assert!(super::params::<WithdrawParams>(Value::Null).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generated_token_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("walletd-token-{}", std::process::id()));
+        std::fs::write(&path, "stale").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_token(&path, "fresh").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fresh");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
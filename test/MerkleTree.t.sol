@@ -12,6 +12,10 @@ contract MerkleTreeHarness is MerkleTree {
         return _insert(leaf);
     }
 
+    function insertPair(bytes32 left, bytes32 right) external returns (uint32) {
+        return _insertPair(left, right);
+    }
+
     function hashPair(bytes32 left, bytes32 right) external pure returns (bytes32) {
         return _hashPair(left, right);
     }
@@ -236,6 +240,41 @@ contract MerkleTreeTest is Test {
         assertEq(tree.getLastRoot(), expectedRoot);
     }
 
+    // =========================================================================
+    //  Pair insertion
+    // =========================================================================
+
+    function test_insertPair_matchesTwoInserts() public {
+        MerkleTreeHarness single = new MerkleTreeHarness(4);
+        // Even start, then odd start after a lone insert
+        tree.insertPair(keccak256("a"), keccak256("b"));
+        single.insert(keccak256("a"));
+        single.insert(keccak256("b"));
+        assertEq(tree.getLastRoot(), single.getLastRoot());
+
+        tree.insert(keccak256("c"));
+        single.insert(keccak256("c"));
+        uint32 index = tree.insertPair(keccak256("d"), keccak256("e"));
+        single.insert(keccak256("d"));
+        single.insert(keccak256("e"));
+        assertEq(index, 3);
+        assertEq(tree.getLastRoot(), single.getLastRoot());
+        assertEq(tree.getLeafCount(), 5);
+    }
+
+    function test_insertPair_recordsOneRoot() public {
+        tree.insertPair(keccak256("a"), keccak256("b"));
+        assertEq(tree.currentRootIndex(), 1);
+    }
+
+    function test_insertPair_revertsWhenOneSlotLeft() public {
+        for (uint256 i = 0; i < 15; i++) {
+            tree.insert(keccak256(abi.encodePacked(i)));
+        }
+        vm.expectRevert(MerkleTree.MerkleTreeFull.selector);
+        tree.insertPair(keccak256("a"), keccak256("b"));
+    }
+
     // =========================================================================
     //  Leaf count
     // =========================================================================
@@ -0,0 +1,17 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+/// @notice Mock withdraw-and-call target (a bridge adapter). For testing only.
+contract MockCallTarget {
+    address public lastCaller;
+    uint256 public lastAmount;
+
+    function bridge(uint256 amount) external {
+        lastCaller = msg.sender;
+        lastAmount = amount;
+    }
+
+    function fail() external pure {
+        revert("MockCallTarget: call failed");
+    }
+}
@@ -3,8 +3,10 @@ pragma solidity ^0.8.20;
 
 import "forge-std/Test.sol";
 import {ShieldedPool} from "../contracts/ShieldedPool.sol";
+import {CounterTree} from "../contracts/CounterTree.sol";
 import {MockERC20} from "./MockERC20.sol";
 import {MockSP1Verifier} from "./MockSP1Verifier.sol";
+import {MockCallTarget} from "./MockCallTarget.sol";
 
 contract ShieldedPoolTest is Test {
     ShieldedPool pool;
@@ -13,6 +15,12 @@ contract ShieldedPoolTest is Test {
 
     bytes32 constant TRANSFER_VKEY = keccak256("transfer_vkey");
     bytes32 constant WITHDRAW_VKEY = keccak256("withdraw_vkey");
+    bytes32 constant DEPOSIT_VKEY = keccak256("deposit_vkey");
+    bytes32 constant JOIN_SPLIT_VKEY = keccak256("join_split_vkey");
+    bytes32 constant BATCH_WITHDRAW_VKEY = keccak256("batch_withdraw_vkey");
+    bytes32 constant CONSOLIDATE_VKEY = keccak256("consolidate_vkey");
+    bytes32 constant PAYOUT_VKEY = keccak256("payout_vkey");
+    bytes32 constant POLICY_SPEND_VKEY = keccak256("policy_spend_vkey");
     uint32 constant TREE_LEVELS = 4;
 
     address alice = makeAddr("alice");
@@ -26,13 +34,39 @@ contract ShieldedPoolTest is Test {
             address(verifier),
             TRANSFER_VKEY,
             WITHDRAW_VKEY,
-            TREE_LEVELS
+            bytes32(0),
+            TREE_LEVELS,
+            _spendVkeys()
         );
 
         // Fund alice
         token.mint(alice, 10_000_000); // 10 USDT (6 decimals)
     }
 
+    /// @dev Verification keys enabling every wider spend circuit
+    function _spendVkeys() internal pure returns (ShieldedPool.SpendVkeys memory) {
+        return ShieldedPool.SpendVkeys(
+            JOIN_SPLIT_VKEY,
+            BATCH_WITHDRAW_VKEY,
+            CONSOLIDATE_VKEY,
+            PAYOUT_VKEY,
+            POLICY_SPEND_VKEY
+        );
+    }
+
+    /// @dev A second deployment of the pool, on the same token and verifier
+    function _secondPool() internal returns (ShieldedPool) {
+        return new ShieldedPool(
+            address(token),
+            address(verifier),
+            TRANSFER_VKEY,
+            WITHDRAW_VKEY,
+            bytes32(0),
+            TREE_LEVELS,
+            _spendVkeys()
+        );
+    }
+
     // =========================================================================
     //  Constructor
     // =========================================================================
@@ -42,9 +76,22 @@ contract ShieldedPoolTest is Test {
         assertEq(address(pool.VERIFIER()), address(verifier));
         assertEq(pool.TRANSFER_VKEY(), TRANSFER_VKEY);
         assertEq(pool.WITHDRAW_VKEY(), WITHDRAW_VKEY);
+        assertEq(pool.DEPOSIT_VKEY(), bytes32(0));
+        assertEq(pool.JOIN_SPLIT_VKEY(), JOIN_SPLIT_VKEY);
+        assertEq(pool.BATCH_WITHDRAW_VKEY(), BATCH_WITHDRAW_VKEY);
+        assertEq(pool.CONSOLIDATE_VKEY(), CONSOLIDATE_VKEY);
+        assertEq(pool.PAYOUT_VKEY(), PAYOUT_VKEY);
+        assertEq(pool.POLICY_SPEND_VKEY(), POLICY_SPEND_VKEY);
         assertEq(pool.levels(), TREE_LEVELS);
     }
 
+    function test_constructor_deploysCounterTree() public view {
+        CounterTree counters = pool.COUNTER_TREE();
+        assertEq(counters.POOL(), address(pool));
+        assertEq(counters.levels(), TREE_LEVELS);
+        assertEq(counters.nextIndex(), 0);
+    }
+
     function test_constructor_revertsZeroToken() public {
         vm.expectRevert(ShieldedPool.ZeroAddress.selector);
         new ShieldedPool(
@@ -52,7 +99,9 @@ contract ShieldedPoolTest is Test {
             address(verifier),
             TRANSFER_VKEY,
             WITHDRAW_VKEY,
-            TREE_LEVELS
+            bytes32(0),
+            TREE_LEVELS,
+            _spendVkeys()
         );
     }
 
@@ -63,7 +112,9 @@ contract ShieldedPoolTest is Test {
             address(0),
             TRANSFER_VKEY,
             WITHDRAW_VKEY,
-            TREE_LEVELS
+            bytes32(0),
+            TREE_LEVELS,
+            _spendVkeys()
         );
     }
 
@@ -181,6 +232,60 @@ contract ShieldedPoolTest is Test {
         assertTrue(pool.getLastRoot() != rootBefore);
     }
 
+    // =========================================================================
+    //  Proven Deposit
+    // =========================================================================
+
+    /// @dev A pool that requires deposit proofs
+    function _provenPool() internal returns (ShieldedPool) {
+        return new ShieldedPool(
+            address(token),
+            address(verifier),
+            TRANSFER_VKEY,
+            WITHDRAW_VKEY,
+            DEPOSIT_VKEY,
+            TREE_LEVELS,
+            _spendVkeys()
+        );
+    }
+
+    function test_depositWithProof_succeeds() public {
+        ShieldedPool proven = _provenPool();
+        bytes32 commitment = keccak256("note1");
+
+        vm.startPrank(alice);
+        token.approve(address(proven), 1_000_000);
+        vm.expectRevert(ShieldedPool.DepositProofRequired.selector);
+        proven.deposit(commitment, 1_000_000, "");
+
+        vm.expectEmit(true, false, false, true);
+        emit ShieldedPool.Deposit(commitment, 1_000_000, 0, block.timestamp);
+        proven.depositWithProof(hex"", abi.encode(commitment, uint256(1_000_000)), "");
+        vm.stopPrank();
+
+        assertEq(proven.nextIndex(), 1);
+        assertEq(token.balanceOf(address(proven)), 1_000_000);
+    }
+
+    function test_depositWithProof_revertsInvalidProof() public {
+        ShieldedPool proven = _provenPool();
+        verifier.setShouldRevert(true);
+
+        vm.startPrank(alice);
+        token.approve(address(proven), 1_000_000);
+        vm.expectRevert("MockSP1Verifier: proof invalid");
+        proven.depositWithProof(hex"", abi.encode(keccak256("note1"), uint256(1_000_000)), "");
+        vm.stopPrank();
+    }
+
+    function test_depositWithProof_revertsWhenDisabled() public {
+        vm.startPrank(alice);
+        token.approve(address(pool), 1_000_000);
+        vm.expectRevert(ShieldedPool.DepositProofsDisabled.selector);
+        pool.depositWithProof(hex"", abi.encode(keccak256("note1"), uint256(1_000_000)), "");
+        vm.stopPrank();
+    }
+
     // =========================================================================
     //  Private Transfer
     // =========================================================================
@@ -201,8 +306,52 @@ contract ShieldedPoolTest is Test {
         bytes32 null2,
         bytes32 outComm1,
         bytes32 outComm2
-    ) internal pure returns (bytes memory) {
-        bytes32[5] memory v = [root, null1, null2, outComm1, outComm2];
+    ) internal view returns (bytes memory) {
+        return _buildTransferPublicValues(root, null1, null2, outComm1, outComm2, 0);
+    }
+
+    /// @dev Same, with a reclaim deadline (non-zero when an input is a reclaimed cancellable note)
+    function _buildTransferPublicValues(
+        bytes32 root,
+        bytes32 null1,
+        bytes32 null2,
+        bytes32 outComm1,
+        bytes32 outComm2,
+        uint256 reclaimDeadline
+    ) internal view returns (bytes memory) {
+        bytes32[10] memory v = [
+            root,
+            null1,
+            null2,
+            outComm1,
+            outComm2,
+            bytes32(reclaimDeadline),
+            bytes32(0),
+            pool.poolBinding(),
+            bytes32(0),
+            bytes32(uint256(TREE_LEVELS))
+        ];
+        return abi.encode(v);
+    }
+
+    /// @dev Transfer public values whose outputs' payloads are proven to be enc1 and enc2
+    function _buildProvenTransferPublicValues(bytes memory enc1, bytes memory enc2)
+        internal
+        view
+        returns (bytes memory)
+    {
+        bytes32[10] memory v = [
+            pool.getLastRoot(),
+            keccak256("n1"),
+            keccak256("n2"),
+            keccak256("o1"),
+            keccak256("o2"),
+            bytes32(0),
+            bytes32(0),
+            pool.poolBinding(),
+            keccak256(abi.encode(keccak256(enc1), keccak256(enc2))),
+            bytes32(uint256(TREE_LEVELS))
+        ];
         return abi.encode(v);
     }
 
@@ -249,6 +398,53 @@ contract ShieldedPoolTest is Test {
         pool.privateTransfer(hex"", publicValues, "", "");
     }
 
+    function test_transfer_associated_emitsAssociatedSpend() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes32 aspRoot = keccak256("asp");
+        bytes32[10] memory v = [
+            pool.getLastRoot(),
+            keccak256("n1"),
+            keccak256("n2"),
+            keccak256("o1"),
+            keccak256("o2"),
+            bytes32(0),
+            aspRoot,
+            pool.poolBinding(),
+            bytes32(0),
+            bytes32(uint256(TREE_LEVELS))
+        ];
+
+        vm.expectEmit(true, false, false, true);
+        emit ShieldedPool.AssociatedSpend(aspRoot, keccak256("o1"), keccak256("o2"));
+
+        pool.privateTransfer(hex"", abi.encode(v), "", "");
+    }
+
+    function test_transfer_revertsForAnotherPool() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes32 root = pool.getLastRoot();
+        bytes memory pv =
+            _buildTransferPublicValues(root, keccak256("n1"), keccak256("n2"), keccak256("o1"), keccak256("o2"));
+
+        // The same proof submitted to a second deployment of the pool
+        ShieldedPool other = _secondPool();
+        assertTrue(other.poolBinding() != pool.poolBinding());
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        other.privateTransfer(hex"", pv, "", "");
+    }
+
+    function test_transfer_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildTransferPublicValues(
+            pool.getLastRoot(), keccak256("n1"), keccak256("n2"), keccak256("o1"), keccak256("o2")
+        );
+
+        // Proofs a level short of this tree's depth
+        pv[319] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.privateTransfer(hex"", pv, "", "");
+    }
+
     function test_transfer_storesEncryptedOutputs() public {
         bytes32 comm1 = keccak256("note1");
         _depositNote(alice, comm1, 500_000);
@@ -272,6 +468,28 @@ contract ShieldedPoolTest is Test {
         assertEq(pool.getEncryptedNote(2), enc2);
     }
 
+    function test_transfer_provenPayloads_succeeds() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildProvenTransferPublicValues(hex"aabb", hex"ccdd");
+
+        pool.privateTransfer(hex"", pv, hex"aabb", hex"ccdd");
+        assertEq(pool.getEncryptedNote(1), hex"aabb");
+        assertEq(pool.getEncryptedNote(2), hex"ccdd");
+    }
+
+    function test_transfer_provenPayloads_revertsOnOtherPayloads() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildProvenTransferPublicValues(hex"aabb", hex"ccdd");
+
+        // Garbage, swapped or missing payloads are not the proven ones
+        vm.expectRevert(ShieldedPool.EncryptedOutputMismatch.selector);
+        pool.privateTransfer(hex"", pv, hex"aabb", hex"0000");
+        vm.expectRevert(ShieldedPool.EncryptedOutputMismatch.selector);
+        pool.privateTransfer(hex"", pv, hex"ccdd", hex"aabb");
+        vm.expectRevert(ShieldedPool.EncryptedOutputMismatch.selector);
+        pool.privateTransfer(hex"", pv, "", "");
+    }
+
     function test_transfer_revertsUnknownRoot() public {
         bytes memory publicValues = _buildTransferPublicValues(
             keccak256("fake_root"),
@@ -320,6 +538,18 @@ contract ShieldedPoolTest is Test {
         pool.privateTransfer(hex"", pv2, "", "");
     }
 
+    function test_transfer_revertsDuplicateNullifier() public {
+        bytes32 comm1 = keccak256("note1");
+        _depositNote(alice, comm1, 500_000);
+        bytes32 root = pool.getLastRoot();
+
+        // The same note as both inputs
+        bytes32 null1 = keccak256("nullifier1");
+        bytes memory pv = _buildTransferPublicValues(root, null1, null1, keccak256("o1"), keccak256("o2"));
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.privateTransfer(hex"", pv, "", "");
+    }
+
     function test_transfer_revertsInvalidProof() public {
         bytes32 comm1 = keccak256("note1");
         _depositNote(alice, comm1, 500_000);
@@ -344,6 +574,28 @@ contract ShieldedPoolTest is Test {
         assertEq(token.balanceOf(address(pool)), poolBalanceBefore);
     }
 
+    function test_transfer_reclaimBeforeDeadline() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes32 root = pool.getLastRoot();
+        bytes memory pv = _buildTransferPublicValues(
+            root, keccak256("n1"), keccak256("n2"), keccak256("o1"), keccak256("o2"), block.timestamp + 1 hours
+        );
+        pool.privateTransfer(hex"", pv, "", "");
+        assertTrue(pool.nullifiers(keccak256("n1")));
+    }
+
+    function test_transfer_revertsReclaimAfterDeadline() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes32 root = pool.getLastRoot();
+        uint256 deadline = block.timestamp + 1 hours;
+        bytes memory pv = _buildTransferPublicValues(
+            root, keccak256("n1"), keccak256("n2"), keccak256("o1"), keccak256("o2"), deadline
+        );
+        vm.warp(deadline + 1);
+        vm.expectRevert(ShieldedPool.ReclaimWindowClosed.selector);
+        pool.privateTransfer(hex"", pv, "", "");
+    }
+
     // =========================================================================
     //  Withdraw
     // =========================================================================
@@ -354,8 +606,71 @@ contract ShieldedPoolTest is Test {
         address recipient,
         uint256 amount,
         bytes32 changeComm
-    ) internal pure returns (bytes memory) {
-        return abi.encode(root, nullifier, recipient, amount, changeComm);
+    ) internal view returns (bytes memory) {
+        return _buildWithdrawPublicValues(root, nullifier, recipient, amount, changeComm, 0);
+    }
+
+    function _buildWithdrawPublicValues(
+        bytes32 root,
+        bytes32 nullifier,
+        address recipient,
+        uint256 amount,
+        bytes32 changeComm,
+        uint256 reclaimDeadline
+    ) internal view returns (bytes memory) {
+        // Encoded in two halves to stay clear of stack-too-deep
+        return bytes.concat(
+            abi.encode(root, nullifier, recipient, amount, changeComm, reclaimDeadline),
+            abi.encode(
+                address(0), uint256(0), bytes32(0), pool.poolBinding(), uint256(0), bytes32(0), uint256(TREE_LEVELS)
+            )
+        );
+    }
+
+    function _buildRelayedWithdrawPublicValues(
+        bytes32 root,
+        bytes32 nullifier,
+        address recipient,
+        uint256 amount,
+        address relayer,
+        uint256 fee
+    ) internal view returns (bytes memory) {
+        return bytes.concat(
+            abi.encode(root, nullifier, recipient, amount, bytes32(0), uint256(0)),
+            abi.encode(relayer, fee, bytes32(0), pool.poolBinding(), uint256(0), bytes32(0), uint256(TREE_LEVELS))
+        );
+    }
+
+    function _buildExpiringWithdrawPublicValues(
+        bytes32 root,
+        bytes32 nullifier,
+        address recipient,
+        uint256 amount,
+        uint256 deadline
+    ) internal view returns (bytes memory) {
+        return bytes.concat(
+            abi.encode(root, nullifier, recipient, amount, bytes32(0), uint256(0)),
+            abi.encode(
+                address(0), uint256(0), bytes32(0), pool.poolBinding(), deadline, bytes32(0), uint256(TREE_LEVELS)
+            )
+        );
+    }
+
+    function _buildCallWithdrawPublicValues(
+        bytes32 root,
+        bytes32 nullifier,
+        address recipient,
+        uint256 amount,
+        address target,
+        bytes memory data
+    ) internal view returns (bytes memory) {
+        bytes32 callBinding = keccak256(abi.encode(target, keccak256(data)));
+        return bytes.concat(
+            abi.encode(root, nullifier, recipient, amount, bytes32(0), uint256(0)),
+            abi.encode(
+                address(0), uint256(0), bytes32(0), pool.poolBinding(), uint256(0), callBinding, uint256(TREE_LEVELS)
+            )
+        );
     }
 
     function test_withdraw_succeeds() public {
@@ -384,6 +699,132 @@ contract ShieldedPoolTest is Test {
         pool.withdraw(hex"", pv, "");
     }
 
+    function test_withdraw_associated_emitsAssociatedSpend() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes32 aspRoot = keccak256("asp");
+        bytes32 changeComm = keccak256("change");
+        bytes memory pv = bytes.concat(
+            abi.encode(pool.getLastRoot(), keccak256("n"), bob, uint256(600_000), changeComm, uint256(0)),
+            abi.encode(
+                address(0), uint256(0), aspRoot, pool.poolBinding(), uint256(0), bytes32(0), uint256(TREE_LEVELS)
+            )
+        );
+
+        vm.expectEmit(true, false, false, true);
+        emit ShieldedPool.AssociatedSpend(aspRoot, changeComm, bytes32(0));
+
+        pool.withdraw(hex"", pv, "");
+        assertEq(token.balanceOf(bob), 600_000);
+    }
+
+    function test_withdraw_revertsOnAnotherChain() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes memory pv = _buildWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), bob, 600_000, bytes32(0));
+
+        // A fork keeps the pool's address and tree but not its chain id
+        vm.chainId(block.chainid + 1);
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        pool.withdraw(hex"", pv, "");
+    }
+
+    function test_withdraw_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes memory pv = _buildWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), bob, 600_000, bytes32(0));
+
+        // A proof a level short of this tree's depth
+        pv[415] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.withdraw(hex"", pv, "");
+    }
+
+    function test_withdrawAndCall_paysOutThenCalls() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        MockCallTarget target = new MockCallTarget();
+        bytes memory data = abi.encodeCall(MockCallTarget.bridge, (600_000));
+        bytes32 nullifier = keccak256("n");
+        bytes memory pv =
+            _buildCallWithdrawPublicValues(pool.getLastRoot(), nullifier, address(target), 600_000, address(target), data);
+
+        pool.withdrawAndCall(hex"", pv, "", address(target), data);
+        assertEq(token.balanceOf(address(target)), 600_000);
+        assertEq(target.lastAmount(), 600_000);
+        assertEq(target.lastCaller(), address(pool));
+        assertTrue(pool.isSpent(nullifier));
+    }
+
+    function test_withdrawAndCall_revertsOnOtherCall() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        MockCallTarget target = new MockCallTarget();
+        bytes memory data = abi.encodeCall(MockCallTarget.bridge, (600_000));
+        bytes memory pv =
+            _buildCallWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), address(target), 600_000, address(target), data);
+
+        vm.expectRevert(ShieldedPool.WrongCall.selector);
+        pool.withdrawAndCall(hex"", pv, "", address(target), abi.encodeCall(MockCallTarget.bridge, (1)));
+        vm.expectRevert(ShieldedPool.WrongCall.selector);
+        pool.withdrawAndCall(hex"", pv, "", bob, data);
+    }
+
+    function test_withdraw_revertsOnCallProof() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        MockCallTarget target = new MockCallTarget();
+        bytes memory data = abi.encodeCall(MockCallTarget.bridge, (600_000));
+        bytes memory pv =
+            _buildCallWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), address(target), 600_000, address(target), data);
+
+        // Skipping the committed call would strand the funds at the target
+        vm.expectRevert(ShieldedPool.WrongCall.selector);
+        pool.withdraw(hex"", pv, "");
+    }
+
+    function test_withdrawAndCall_revertsWhenCallFails() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        MockCallTarget target = new MockCallTarget();
+        bytes memory data = abi.encodeCall(MockCallTarget.fail, ());
+        bytes32 nullifier = keccak256("n");
+        bytes memory pv =
+            _buildCallWithdrawPublicValues(pool.getLastRoot(), nullifier, address(target), 600_000, address(target), data);
+
+        vm.expectRevert(ShieldedPool.CallFailed.selector);
+        pool.withdrawAndCall(hex"", pv, "", address(target), data);
+        assertFalse(pool.isSpent(nullifier));
+        assertEq(token.balanceOf(address(target)), 0);
+    }
+
+    function test_withdrawAndCall_revertsOnTokenTarget() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        // From the pool, a token call could move every depositor's funds
+        bytes memory data = abi.encodeCall(token.transfer, (bob, 1_000_000));
+        bytes memory pv =
+            _buildCallWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), bob, 1, address(token), data);
+
+        vm.expectRevert(ShieldedPool.WrongCall.selector);
+        pool.withdrawAndCall(hex"", pv, "", address(token), data);
+    }
+
+    function test_withdraw_succeedsAtDeadline() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        uint256 deadline = block.timestamp + 1 hours;
+        bytes memory pv =
+            _buildExpiringWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), bob, 600_000, deadline);
+
+        vm.warp(deadline);
+        pool.withdraw(hex"", pv, "");
+        assertEq(token.balanceOf(bob), 600_000);
+    }
+
+    function test_withdraw_revertsAfterDeadline() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        uint256 deadline = block.timestamp + 1 hours;
+        bytes memory pv =
+            _buildExpiringWithdrawPublicValues(pool.getLastRoot(), keccak256("n"), bob, 600_000, deadline);
+
+        // A relayer sitting on the proof past the owner's deadline can no longer submit it
+        vm.warp(deadline + 1);
+        vm.expectRevert(ShieldedPool.WithdrawalExpired.selector);
+        pool.withdraw(hex"", pv, "");
+    }
+
     function test_withdraw_withChangeCommitment() public {
         _depositNote(alice, keccak256("note1"), 1_000_000);
         bytes32 root = pool.getLastRoot();
@@ -461,6 +902,608 @@ contract ShieldedPoolTest is Test {
         pool.withdraw(hex"", pv, "");
     }
 
+    function test_withdraw_reclaimAtDeadline() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes32 root = pool.getLastRoot();
+        uint256 deadline = block.timestamp + 1 days;
+        bytes memory pv = _buildWithdrawPublicValues(root, keccak256("n"), alice, 1_000_000, bytes32(0), deadline);
+        vm.warp(deadline);
+        pool.withdraw(hex"", pv, "");
+        assertEq(token.balanceOf(alice), 1_000_000);
+    }
+
+    function test_withdraw_paysRelayerFee() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes32 root = pool.getLastRoot();
+        address relayer = makeAddr("relayer");
+
+        bytes memory pv = _buildRelayedWithdrawPublicValues(root, keccak256("n"), bob, 980_000, relayer, 20_000);
+        // Anyone may submit it; the fee goes to the committed relayer
+        vm.prank(makeAddr("frontrunner"));
+        pool.withdraw(hex"", pv, "");
+
+        assertEq(token.balanceOf(bob), 980_000);
+        assertEq(token.balanceOf(relayer), 20_000);
+        assertEq(token.balanceOf(address(pool)), 0);
+    }
+
+    function test_withdraw_revertsFeeToZeroRelayer() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes32 root = pool.getLastRoot();
+
+        bytes memory pv = _buildRelayedWithdrawPublicValues(root, keccak256("n"), bob, 980_000, address(0), 20_000);
+        vm.expectRevert(ShieldedPool.ZeroAddress.selector);
+        pool.withdraw(hex"", pv, "");
+    }
+
+    function test_withdraw_revertsReclaimAfterDeadline() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes32 root = pool.getLastRoot();
+        uint256 deadline = block.timestamp + 1 days;
+        bytes memory pv = _buildWithdrawPublicValues(root, keccak256("n"), alice, 1_000_000, bytes32(0), deadline);
+        vm.warp(deadline + 1);
+        vm.expectRevert(ShieldedPool.ReclaimWindowClosed.selector);
+        pool.withdraw(hex"", pv, "");
+    }
+
+    // =========================================================================
+    //  Join-Split
+    // =========================================================================
+
+    /// @dev Join-split public values spending n1..n4 into o1..o4 at the current root
+    function _buildJoinSplitPublicValues(uint256 reclaimDeadline) internal view returns (bytes memory) {
+        bytes32[12] memory v = [
+            pool.getLastRoot(),
+            keccak256("n1"),
+            keccak256("n2"),
+            keccak256("n3"),
+            keccak256("n4"),
+            keccak256("o1"),
+            keccak256("o2"),
+            keccak256("o3"),
+            keccak256("o4"),
+            bytes32(reclaimDeadline),
+            bytes32(uint256(TREE_LEVELS)),
+            pool.poolBinding()
+        ];
+        return abi.encode(v);
+    }
+
+    function test_joinSplit_succeeds() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes[4] memory enc = [bytes(hex"aa"), bytes(hex"bb"), bytes(""), bytes(hex"dd")];
+
+        pool.joinSplit(hex"", _buildJoinSplitPublicValues(0), enc);
+
+        // Every nullifier is spent, dummies included
+        assertTrue(pool.isSpent(keccak256("n1")));
+        assertTrue(pool.isSpent(keccak256("n4")));
+        // 1 deposit + 4 outputs
+        assertEq(pool.nextIndex(), 5);
+        assertEq(pool.getEncryptedNote(1), hex"aa");
+        assertEq(pool.getEncryptedNote(3).length, 0);
+        assertEq(pool.getEncryptedNote(4), hex"dd");
+    }
+
+    function test_joinSplit_emitsEvent() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes[4] memory none;
+
+        vm.expectEmit(false, false, false, true);
+        emit ShieldedPool.JoinSplit(
+            [keccak256("n1"), keccak256("n2"), keccak256("n3"), keccak256("n4")],
+            [keccak256("o1"), keccak256("o2"), keccak256("o3"), keccak256("o4")],
+            block.timestamp
+        );
+
+        pool.joinSplit(hex"", _buildJoinSplitPublicValues(0), none);
+    }
+
+    function test_joinSplit_revertsForAnotherPool() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildJoinSplitPublicValues(0);
+        bytes[4] memory none;
+
+        ShieldedPool other = _secondPool();
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        other.joinSplit(hex"", pv, none);
+    }
+
+    function test_joinSplit_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildJoinSplitPublicValues(0);
+        bytes[4] memory none;
+
+        // Proofs a level short of this tree's depth
+        pv[351] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.joinSplit(hex"", pv, none);
+    }
+
+    function test_joinSplit_revertsUnknownRoot() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildJoinSplitPublicValues(0);
+        bytes[4] memory none;
+
+        pv[0] = bytes1(uint8(pv[0]) ^ 0xff);
+        vm.expectRevert(ShieldedPool.InvalidMerkleRoot.selector);
+        pool.joinSplit(hex"", pv, none);
+    }
+
+    function test_joinSplit_revertsDoubleSpend() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes[4] memory none;
+        pool.joinSplit(hex"", _buildJoinSplitPublicValues(0), none);
+
+        // The same inputs again, at the new root
+        bytes memory pv = _buildJoinSplitPublicValues(0);
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.joinSplit(hex"", pv, none);
+    }
+
+    function test_joinSplit_revertsRepeatedNullifier() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildJoinSplitPublicValues(0);
+        bytes[4] memory none;
+
+        // The first input's nullifier in the second slot too
+        for (uint256 i = 0; i < 32; i++) {
+            pv[64 + i] = pv[32 + i];
+        }
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.joinSplit(hex"", pv, none);
+    }
+
+    function test_joinSplit_revertsInvalidProof() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildJoinSplitPublicValues(0);
+        bytes[4] memory none;
+
+        verifier.setShouldRevert(true);
+        vm.expectRevert("MockSP1Verifier: proof invalid");
+        pool.joinSplit(hex"", pv, none);
+    }
+
+    function test_joinSplit_revertsReclaimAfterDeadline() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        uint256 deadline = block.timestamp + 1 hours;
+        bytes memory pv = _buildJoinSplitPublicValues(deadline);
+        bytes[4] memory none;
+
+        vm.warp(deadline + 1);
+        vm.expectRevert(ShieldedPool.ReclaimWindowClosed.selector);
+        pool.joinSplit(hex"", pv, none);
+    }
+
+    // =========================================================================
+    //  Batch Withdraw
+    // =========================================================================
+
+    /// @dev Batch-withdraw public values spending `spent` notes to `recipient`;
+    ///      the remaining nullifier slots are zero
+    function _buildBatchWithdrawPublicValues(uint256 spent, address recipient, uint256 amount)
+        internal
+        view
+        returns (bytes memory)
+    {
+        bytes32[14] memory v;
+        v[0] = pool.getLastRoot();
+        for (uint256 i = 0; i < spent; i++) {
+            v[1 + i] = keccak256(abi.encode("batch_null", i));
+        }
+        v[9] = bytes32(uint256(uint160(recipient)));
+        v[10] = bytes32(amount);
+        v[12] = bytes32(uint256(TREE_LEVELS));
+        v[13] = pool.poolBinding();
+        return abi.encode(v);
+    }
+
+    function test_batchWithdraw_succeeds() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+
+        pool.batchWithdraw(hex"", _buildBatchWithdrawPublicValues(3, bob, 800_000));
+
+        assertEq(token.balanceOf(bob), 800_000);
+        assertEq(token.balanceOf(address(pool)), 200_000);
+        assertTrue(pool.isSpent(keccak256(abi.encode("batch_null", uint256(0)))));
+        assertTrue(pool.isSpent(keccak256(abi.encode("batch_null", uint256(2)))));
+        // Unused slots are skipped, and there is no change note
+        assertFalse(pool.isSpent(bytes32(0)));
+        assertEq(pool.nextIndex(), 1);
+    }
+
+    function test_batchWithdraw_emitsEvent() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes32[8] memory spent;
+        spent[0] = keccak256(abi.encode("batch_null", uint256(0)));
+
+        vm.expectEmit(true, false, false, true);
+        emit ShieldedPool.BatchWithdrawal(spent, bob, 500_000, block.timestamp);
+
+        pool.batchWithdraw(hex"", _buildBatchWithdrawPublicValues(1, bob, 500_000));
+    }
+
+    function test_batchWithdraw_revertsForAnotherPool() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes memory pv = _buildBatchWithdrawPublicValues(2, bob, 500_000);
+
+        ShieldedPool other = _secondPool();
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        other.batchWithdraw(hex"", pv);
+    }
+
+    function test_batchWithdraw_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes memory pv = _buildBatchWithdrawPublicValues(2, bob, 500_000);
+
+        pv[415] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.batchWithdraw(hex"", pv);
+    }
+
+    function test_batchWithdraw_revertsZeroRecipient() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes memory pv = _buildBatchWithdrawPublicValues(2, address(0), 500_000);
+
+        vm.expectRevert(ShieldedPool.ZeroAddress.selector);
+        pool.batchWithdraw(hex"", pv);
+    }
+
+    function test_batchWithdraw_revertsDoubleSpend() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        pool.batchWithdraw(hex"", _buildBatchWithdrawPublicValues(2, bob, 400_000));
+
+        bytes memory pv = _buildBatchWithdrawPublicValues(2, bob, 400_000);
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.batchWithdraw(hex"", pv);
+        assertEq(token.balanceOf(bob), 400_000);
+    }
+
+    function test_batchWithdraw_revertsInvalidProof() public {
+        _depositNote(alice, keccak256("note1"), 1_000_000);
+        bytes memory pv = _buildBatchWithdrawPublicValues(2, bob, 500_000);
+
+        verifier.setShouldRevert(true);
+        vm.expectRevert("MockSP1Verifier: proof invalid");
+        pool.batchWithdraw(hex"", pv);
+    }
+
+    // =========================================================================
+    //  Consolidate
+    // =========================================================================
+
+    /// @dev Consolidation public values merging `merged` notes into one;
+    ///      the remaining nullifier slots are zero
+    function _buildConsolidatePublicValues(uint256 merged) internal view returns (bytes memory) {
+        bytes32[12] memory v;
+        v[0] = pool.getLastRoot();
+        for (uint256 i = 0; i < merged; i++) {
+            v[1 + i] = keccak256(abi.encode("merge_null", i));
+        }
+        v[9] = keccak256("merged");
+        v[10] = bytes32(uint256(TREE_LEVELS));
+        v[11] = pool.poolBinding();
+        return abi.encode(v);
+    }
+
+    function test_consolidate_succeeds() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        uint256 poolBalanceBefore = token.balanceOf(address(pool));
+
+        pool.consolidate(hex"", _buildConsolidatePublicValues(5), hex"ee");
+
+        assertTrue(pool.isSpent(keccak256(abi.encode("merge_null", uint256(4)))));
+        assertFalse(pool.isSpent(bytes32(0)));
+        assertEq(pool.nextIndex(), 2);
+        assertEq(pool.getEncryptedNote(1), hex"ee");
+        assertEq(token.balanceOf(address(pool)), poolBalanceBefore);
+    }
+
+    function test_consolidate_emitsEvent() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes32[8] memory spent;
+        spent[0] = keccak256(abi.encode("merge_null", uint256(0)));
+        spent[1] = keccak256(abi.encode("merge_null", uint256(1)));
+
+        vm.expectEmit(true, false, false, true);
+        emit ShieldedPool.Consolidation(spent, keccak256("merged"), block.timestamp);
+
+        pool.consolidate(hex"", _buildConsolidatePublicValues(2), "");
+    }
+
+    function test_consolidate_revertsForAnotherPool() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildConsolidatePublicValues(2);
+
+        ShieldedPool other = _secondPool();
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        other.consolidate(hex"", pv, "");
+    }
+
+    function test_consolidate_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildConsolidatePublicValues(2);
+
+        pv[351] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.consolidate(hex"", pv, "");
+    }
+
+    function test_consolidate_revertsDoubleSpend() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        pool.consolidate(hex"", _buildConsolidatePublicValues(2), "");
+
+        // A later merge re-using the first two notes
+        bytes memory pv = _buildConsolidatePublicValues(3);
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.consolidate(hex"", pv, "");
+    }
+
+    function test_consolidate_revertsInvalidProof() public {
+        _depositNote(alice, keccak256("note1"), 500_000);
+        bytes memory pv = _buildConsolidatePublicValues(2);
+
+        verifier.setShouldRevert(true);
+        vm.expectRevert("MockSP1Verifier: proof invalid");
+        pool.consolidate(hex"", pv, "");
+    }
+
+    // =========================================================================
+    //  Payout
+    // =========================================================================
+
+    /// @dev Payout public values spending two notes into eight outputs
+    function _buildPayoutPublicValues() internal view returns (bytes memory) {
+        bytes32[14] memory v;
+        v[0] = pool.getLastRoot();
+        v[1] = keccak256("payer_null_1");
+        v[2] = keccak256("payer_null_2");
+        for (uint256 i = 0; i < 8; i++) {
+            v[3 + i] = keccak256(abi.encode("payee", i));
+        }
+        v[12] = bytes32(uint256(TREE_LEVELS));
+        v[13] = pool.poolBinding();
+        return abi.encode(v);
+    }
+
+    function test_payout_succeeds() public {
+        _depositNote(alice, keccak256("payroll"), 1_000_000);
+        bytes[8] memory enc;
+        enc[7] = hex"77";
+
+        pool.payout(hex"", _buildPayoutPublicValues(), enc);
+
+        assertTrue(pool.isSpent(keccak256("payer_null_1")));
+        assertTrue(pool.isSpent(keccak256("payer_null_2")));
+        // 1 deposit + 8 outputs, dummies included
+        assertEq(pool.nextIndex(), 9);
+        assertEq(pool.getEncryptedNote(8), hex"77");
+    }
+
+    function test_payout_revertsForAnotherPool() public {
+        _depositNote(alice, keccak256("payroll"), 1_000_000);
+        bytes memory pv = _buildPayoutPublicValues();
+        bytes[8] memory none;
+
+        ShieldedPool other = _secondPool();
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        other.payout(hex"", pv, none);
+    }
+
+    function test_payout_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("payroll"), 1_000_000);
+        bytes memory pv = _buildPayoutPublicValues();
+        bytes[8] memory none;
+
+        pv[415] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.payout(hex"", pv, none);
+    }
+
+    function test_payout_revertsRepeatedNullifier() public {
+        _depositNote(alice, keccak256("payroll"), 1_000_000);
+        bytes memory pv = _buildPayoutPublicValues();
+        bytes[8] memory none;
+
+        for (uint256 i = 0; i < 32; i++) {
+            pv[64 + i] = pv[32 + i];
+        }
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.payout(hex"", pv, none);
+    }
+
+    function test_payout_revertsInvalidProof() public {
+        _depositNote(alice, keccak256("payroll"), 1_000_000);
+        bytes memory pv = _buildPayoutPublicValues();
+        bytes[8] memory none;
+
+        verifier.setShouldRevert(true);
+        vm.expectRevert("MockSP1Verifier: proof invalid");
+        pool.payout(hex"", pv, none);
+    }
+
+    // =========================================================================
+    //  Policy Spend
+    // =========================================================================
+
+    /// @dev Policy-spend public values for today's epoch. `counterLevels` is
+    ///      zero on the epoch's first spend, when `counterNullifier` is the
+    ///      epoch nullifier.
+    function _buildPolicySpendPublicValues(
+        bytes32 nullifier,
+        bytes32 counterNullifier,
+        bytes32 counterRoot,
+        uint256 counterLevels
+    ) internal view returns (bytes memory) {
+        bytes32[11] memory v = [
+            pool.getLastRoot(),
+            nullifier,
+            keccak256(abi.encode("payment", nullifier)),
+            keccak256(abi.encode("change", nullifier)),
+            counterRoot,
+            counterNullifier,
+            keccak256(abi.encode("counter", nullifier)),
+            bytes32(block.timestamp / 1 days),
+            bytes32(uint256(TREE_LEVELS)),
+            bytes32(counterLevels),
+            pool.poolBinding()
+        ];
+        return abi.encode(v);
+    }
+
+    /// @dev Public values of the epoch's first spend
+    function _buildFirstPolicySpendPublicValues() internal view returns (bytes memory) {
+        return _buildPolicySpendPublicValues(keccak256("policy_null_1"), keccak256("epoch_null"), bytes32(0), 0);
+    }
+
+    function test_policySpend_firstOfEpoch_succeeds() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        CounterTree counters = pool.COUNTER_TREE();
+        bytes32 counterRootBefore = counters.getLastRoot();
+
+        pool.policySpend(hex"", _buildFirstPolicySpendPublicValues(), hex"aa", hex"bb");
+
+        assertTrue(pool.isSpent(keccak256("policy_null_1")));
+        assertTrue(pool.isSpent(keccak256("epoch_null")));
+        // Payment and change go into the note tree, the counter into its own
+        assertEq(pool.nextIndex(), 3);
+        assertEq(pool.getEncryptedNote(1), hex"aa");
+        assertEq(pool.getEncryptedNote(2), hex"bb");
+        assertEq(counters.nextIndex(), 1);
+        assertTrue(counters.getLastRoot() != counterRootBefore);
+    }
+
+    function test_policySpend_replacesTheEpochCounter() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        pool.policySpend(hex"", _buildFirstPolicySpendPublicValues(), "", "");
+        CounterTree counters = pool.COUNTER_TREE();
+
+        bytes32 second = keccak256("policy_null_2");
+        bytes memory pv =
+            _buildPolicySpendPublicValues(second, keccak256("counter_null_1"), counters.getLastRoot(), TREE_LEVELS);
+
+        vm.expectEmit(true, true, false, true);
+        emit ShieldedPool.PolicySpend(
+            second,
+            keccak256("counter_null_1"),
+            keccak256(abi.encode("payment", second)),
+            keccak256(abi.encode("change", second)),
+            keccak256(abi.encode("counter", second)),
+            1,
+            block.timestamp
+        );
+
+        pool.policySpend(hex"", pv, "", "");
+        assertEq(counters.nextIndex(), 2);
+    }
+
+    function test_policySpend_revertsSecondFirstOfEpoch() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        pool.policySpend(hex"", _buildFirstPolicySpendPublicValues(), "", "");
+
+        // Another note of the policy revealing the same epoch nullifier
+        bytes memory pv =
+            _buildPolicySpendPublicValues(keccak256("policy_null_2"), keccak256("epoch_null"), bytes32(0), 0);
+        vm.expectRevert(ShieldedPool.NullifierAlreadySpent.selector);
+        pool.policySpend(hex"", pv, "", "");
+    }
+
+    function test_policySpend_revertsUnknownCounterRoot() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        bytes memory pv = _buildPolicySpendPublicValues(
+            keccak256("policy_null_1"), keccak256("counter_null_1"), keccak256("fake_counter_root"), TREE_LEVELS
+        );
+
+        vm.expectRevert(ShieldedPool.InvalidMerkleRoot.selector);
+        pool.policySpend(hex"", pv, "", "");
+    }
+
+    function test_policySpend_revertsOnAnotherCounterDepth() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        pool.policySpend(hex"", _buildFirstPolicySpendPublicValues(), "", "");
+        bytes32 counterRoot = pool.COUNTER_TREE().getLastRoot();
+
+        bytes memory pv = _buildPolicySpendPublicValues(
+            keccak256("policy_null_2"), keccak256("counter_null_1"), counterRoot, TREE_LEVELS - 1
+        );
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.policySpend(hex"", pv, "", "");
+    }
+
+    function test_policySpend_revertsOnAnotherEpoch() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        bytes memory pv = _buildFirstPolicySpendPublicValues();
+
+        // Submitted the next day, it would count against a finished epoch
+        vm.warp(block.timestamp + 1 days);
+        vm.expectRevert(ShieldedPool.WrongEpoch.selector);
+        pool.policySpend(hex"", pv, "", "");
+    }
+
+    function test_policySpend_revertsForAnotherPool() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        bytes memory pv = _buildFirstPolicySpendPublicValues();
+
+        ShieldedPool other = _secondPool();
+        vm.expectRevert(ShieldedPool.WrongPool.selector);
+        other.policySpend(hex"", pv, "", "");
+    }
+
+    function test_policySpend_revertsOnAnotherTreeDepth() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        bytes memory pv = _buildFirstPolicySpendPublicValues();
+
+        pv[287] = bytes1(uint8(TREE_LEVELS - 1));
+        vm.expectRevert(ShieldedPool.WrongTreeDepth.selector);
+        pool.policySpend(hex"", pv, "", "");
+    }
+
+    function test_policySpend_revertsInvalidProof() public {
+        _depositNote(alice, keccak256("policy_note"), 1_000_000);
+        bytes memory pv = _buildFirstPolicySpendPublicValues();
+
+        verifier.setShouldRevert(true);
+        vm.expectRevert("MockSP1Verifier: proof invalid");
+        pool.policySpend(hex"", pv, "", "");
+    }
+
+    function test_counterTree_onlyPoolAppends() public {
+        CounterTree counters = pool.COUNTER_TREE();
+
+        vm.expectRevert(CounterTree.NotPool.selector);
+        counters.insert(keccak256("forged_counter"));
+    }
+
+    // =========================================================================
+    //  Disabled circuits
+    // =========================================================================
+
+    function test_spendCircuits_revertWhenDisabled() public {
+        ShieldedPool.SpendVkeys memory disabled;
+        ShieldedPool bare = new ShieldedPool(
+            address(token),
+            address(verifier),
+            TRANSFER_VKEY,
+            WITHDRAW_VKEY,
+            bytes32(0),
+            TREE_LEVELS,
+            disabled
+        );
+        bytes[4] memory four;
+        bytes[8] memory eight;
+
+        vm.expectRevert(ShieldedPool.CircuitDisabled.selector);
+        bare.joinSplit(hex"", new bytes(384), four);
+        vm.expectRevert(ShieldedPool.CircuitDisabled.selector);
+        bare.batchWithdraw(hex"", new bytes(448));
+        vm.expectRevert(ShieldedPool.CircuitDisabled.selector);
+        bare.consolidate(hex"", new bytes(384), "");
+        vm.expectRevert(ShieldedPool.CircuitDisabled.selector);
+        bare.payout(hex"", new bytes(448), eight);
+        vm.expectRevert(ShieldedPool.CircuitDisabled.selector);
+        bare.policySpend(hex"", new bytes(352), "", "");
+    }
+
     // =========================================================================
     //  View functions
     // =========================================================================
@@ -42,13 +42,21 @@ mod tests {
             blinding: [0x04u8; 32],
         };
 
-        TransferPrivateInputs {
+        let mut inputs = TransferPrivateInputs {
             input_notes: [note0, note1],
-            spending_keys: [spending_key, spending_key],
+            spender_keys: [SpenderKey::derive(&spending_key); 2],
             merkle_proofs: [proof0, proof1],
             output_notes: [out_note0, out_note1],
             root,
-        }
+            locks: [None, None],
+            association: None,
+            pool_binding: [0u8; 32],
+            output_encryption: None,
+            multisig: [None, None],
+            spend_signatures: vec![],
+        };
+        inputs.sign(&spending_key).unwrap();
+        inputs
     }
 
     fn build_withdraw_test_inputs() -> WithdrawPrivateInputs {
@@ -74,15 +82,27 @@ mod tests {
             blinding: [0x05u8; 32],
         };
 
-        WithdrawPrivateInputs {
+        let mut inputs = WithdrawPrivateInputs {
             input_note: note,
-            spending_key,
+            spender_key: SpenderKey::derive(&spending_key),
             merkle_proof: proof,
             root,
             recipient: [0xDE; 20],
             withdraw_amount: 600_000,
             change_note: Some(change_note),
-        }
+            lock: None,
+            fee: 0,
+            relayer: [0u8; 20],
+            association: None,
+            pool_binding: [0u8; 32],
+            deadline: 0,
+            call_target: [0u8; 20],
+            calldata_hash: [0u8; 32],
+            multisig: None,
+            spend_signatures: vec![],
+        };
+        inputs.sign(&spending_key).unwrap();
+        inputs
     }
 
     fn fixtures_dir() -> std::path::PathBuf {
@@ -124,7 +144,7 @@ mod tests {
     fn test_withdraw_conservation() {
         let inputs = build_withdraw_test_inputs();
         let change_amount = inputs.change_note.as_ref().map(|n| n.amount).unwrap_or(0);
-        assert_eq!(inputs.input_note.amount, inputs.withdraw_amount + change_amount);
+        assert_eq!(inputs.input_note.amount, inputs.withdraw_amount + change_amount + inputs.fee);
     }
 
     #[test]
@@ -137,19 +157,29 @@ mod tests {
     }
 
     #[test]
-    fn test_spending_key_matches_pubkey() {
+    fn test_spender_key_matches_pubkey() {
         let inputs = build_transfer_test_inputs();
         for i in 0..2 {
-            let derived = derive_pubkey(&inputs.spending_keys[i]);
-            assert_eq!(derived, inputs.input_notes[i].pubkey);
+            assert_eq!(inputs.spender_keys[i].pubkey(), inputs.input_notes[i].pubkey);
         }
     }
 
+    #[test]
+    fn test_inputs_are_signed_by_their_owner() {
+        let transfer = build_transfer_test_inputs();
+        assert!(transfer.check().is_ok());
+        let withdraw = build_withdraw_test_inputs();
+        assert!(withdraw.check().is_ok());
+        let mut unsigned = withdraw;
+        unsigned.spend_signatures.clear();
+        assert_eq!(unsigned.check().unwrap_err(), "missing spend authorization signature");
+    }
+
     #[test]
     fn test_public_values_size_transfer() {
         let inputs = build_transfer_test_inputs();
-        let null0 = compute_nullifier(&inputs.input_notes[0].commitment(), &inputs.spending_keys[0]);
-        let null1 = compute_nullifier(&inputs.input_notes[1].commitment(), &inputs.spending_keys[1]);
+        let null0 = nullifier_from_key(&inputs.input_notes[0].commitment(), inputs.spender_keys[0].nullifier_key());
+        let null1 = nullifier_from_key(&inputs.input_notes[1].commitment(), inputs.spender_keys[1].nullifier_key());
         let out0 = inputs.output_notes[0].commitment();
         let out1 = inputs.output_notes[1].commitment();
 
@@ -159,14 +189,21 @@ mod tests {
         pv.extend_from_slice(&null1);
         pv.extend_from_slice(&out0);
         pv.extend_from_slice(&out1);
-        assert_eq!(pv.len(), 160);
+        pv.extend_from_slice(&[0u8; 32]); // reclaim deadline: no reclaimed inputs
+        pv.extend_from_slice(&[0u8; 32]); // association root: not associated
+        pv.extend_from_slice(&inputs.pool_binding);
+        pv.extend_from_slice(&[0u8; 32]); // ciphertexts hash: payloads not proven
+        let mut levels_be = [0u8; 32];
+        levels_be[24..].copy_from_slice(&(inputs.merkle_proofs[0].len() as u64).to_be_bytes());
+        pv.extend_from_slice(&levels_be);
+        assert_eq!(pv.len(), TransferPublicValues::LEN);
     }
 
     #[test]
     fn test_public_values_size_withdraw() {
         let inputs = build_withdraw_test_inputs();
         let commitment = inputs.input_note.commitment();
-        let nullifier = compute_nullifier(&commitment, &inputs.spending_key);
+        let nullifier = nullifier_from_key(&commitment, inputs.spender_key.nullifier_key());
         let change_comm = inputs.change_note.as_ref().map(|n| n.commitment()).unwrap_or([0u8; 32]);
 
         let mut pv = Vec::new();
@@ -179,6 +216,49 @@ mod tests {
         amount_be[24..].copy_from_slice(&inputs.withdraw_amount.to_be_bytes());
         pv.extend_from_slice(&amount_be);
         pv.extend_from_slice(&change_comm);
-        assert_eq!(pv.len(), 160);
+        pv.extend_from_slice(&[0u8; 32]); // reclaim deadline: plain note
+        pv.extend_from_slice(&[0u8; 64]); // relayer and fee: submitted by the recipient
+        pv.extend_from_slice(&[0u8; 32]); // association root: not associated
+        pv.extend_from_slice(&inputs.pool_binding);
+        let mut deadline_be = [0u8; 32];
+        deadline_be[24..].copy_from_slice(&inputs.deadline.to_be_bytes());
+        pv.extend_from_slice(&deadline_be);
+        pv.extend_from_slice(&[0u8; 32]); // call binding: plain withdrawal
+        let mut levels_be = [0u8; 32];
+        levels_be[24..].copy_from_slice(&(inputs.merkle_proof.len() as u64).to_be_bytes());
+        pv.extend_from_slice(&levels_be);
+        assert_eq!(pv.len(), WithdrawPublicValues::LEN);
+    }
+
+    #[test]
+    fn test_tree_fixture_files() {
+        // Reference tree state for the Foundry suites (see shielded_pool_lib::fixtures)
+        let vectors = test_vectors::generate();
+        let leaves: Vec<[u8; 32]> = vectors.trees[0]
+            .leaves
+            .iter()
+            .map(|l| hex::decode(&l[2..]).unwrap().try_into().unwrap())
+            .collect();
+        let tree = IncrementalMerkleTree::from_leaves(vectors.trees[0].levels, leaves).unwrap();
+        let indices: Vec<u32> = (0..tree.next_index).collect();
+        let fixture = fixtures::TreeFixture::new(&tree, &indices).unwrap();
+        assert_eq!(&fixture.root, vectors.trees[0].roots.last().unwrap());
+        std::fs::write(fixtures_dir().join("tree-fixture.json"), fixture.to_json()).unwrap();
+        std::fs::write(fixtures_dir().join("TreeFixture.sol"), fixture.to_solidity("TreeFixture")).unwrap();
+    }
+
+    #[test]
+    fn test_vectors_json() {
+        // Canonical vectors for the Solidity and TS suites (see shielded_pool_lib::test_vectors)
+        let json = test_vectors::to_json();
+        std::fs::write(fixtures_dir().join("test-vectors.json"), &json).unwrap();
+        let parsed: test_vectors::TestVectors = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.zeros.empty_root, format!("0x{}", hex::encode(compute_empty_root(20))));
+        for pv in &parsed.public_values.withdraw {
+            let encoded = hex::decode(&pv.encoded[2..]).unwrap();
+            let decoded = WithdrawPublicValues::decode(&encoded).unwrap();
+            assert_eq!(decoded.amount.to_string(), pv.amount);
+            assert_eq!(decoded.fee.to_string(), pv.fee);
+        }
     }
 }